@@ -0,0 +1,505 @@
+//! Direct play of media files over HTTP
+//!
+//! Files are served as stored, with byte ranges so players can seek and
+//! with validators so they can revalidate what they cached. Bodies are read
+//! from the storage of the library in chunks as the client consumes them.
+
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use rustflix_media_library::LibraryStorage;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::error;
+use uuid::Uuid;
+
+/// Bytes read from a file at a time, large enough that object storage is
+/// not sent a request per few packets
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Most ranges a request may ask for; requests for more get the whole file
+const MAX_RANGES: usize = 16;
+
+/// Byte ranges of a `Range` header
+#[derive(Debug, Clone, PartialEq)]
+pub enum ByteRanges {
+    /// No usable range, so the whole file is served
+    Full,
+    /// Inclusive ranges in file order, overlapping and adjacent ones merged
+    Partial(Vec<(u64, u64)>),
+    /// No range overlaps the file
+    Unsatisfiable,
+}
+
+/// Validators of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validators {
+    /// Strong entity tag of the size and modification time
+    pub etag: String,
+    /// Modification time in whole seconds, as HTTP dates have it
+    pub last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// Validators of a file of a size modified at a time
+    pub fn new(len: u64, modified: SystemTime) -> Self {
+        let modified = DateTime::<Utc>::from(modified);
+        Self {
+            etag: format!("\"{:x}-{:x}\"", len, modified.timestamp_nanos_opt().unwrap_or_default()),
+            last_modified: DateTime::from_timestamp(modified.timestamp(), 0).unwrap_or(modified),
+        }
+    }
+
+    /// Check whether the `If-None-Match` or `If-Modified-Since` header of a
+    /// request names the current file
+    pub fn is_not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(value) = header_value(headers, header::IF_NONE_MATCH) {
+            // Weak comparison
+            return value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
+        }
+        header_value(headers, header::IF_MODIFIED_SINCE)
+            .and_then(parse_http_date)
+            .is_some_and(|since| self.last_modified <= since)
+    }
+
+    /// Check whether an `If-Range` value names the current file, so the
+    /// ranges of the request apply
+    pub fn matches_if_range(&self, value: &str) -> bool {
+        let value = value.trim();
+        if value.starts_with('"') || value.starts_with("W/") {
+            // Strong comparison, which weak tags never pass
+            value == self.etag
+        } else {
+            parse_http_date(value) == Some(self.last_modified)
+        }
+    }
+}
+
+/// Parse a `Range` header against a file length
+///
+/// Headers that are malformed, use another unit or ask for too many ranges
+/// are ignored.
+pub fn parse_range(value: &str, len: u64) -> ByteRanges {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return ByteRanges::Full;
+    };
+    let specs = specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()).collect::<Vec<_>>();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return ByteRanges::Full;
+    }
+
+    let number = |value: &str| {
+        (!value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())).then(|| value.parse::<u64>().ok()).flatten()
+    };
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let Some((first, last)) = spec.split_once('-') else {
+            return ByteRanges::Full;
+        };
+        let range = match (first.trim(), last.trim()) {
+            ("", suffix) => match number(suffix) {
+                Some(suffix) => (suffix > 0 && len > 0).then(|| (len - suffix.min(len), len - 1)),
+                None => return ByteRanges::Full,
+            },
+            (first, "") => match number(first) {
+                Some(first) => (first < len).then(|| (first, len - 1)),
+                None => return ByteRanges::Full,
+            },
+            (first, last) => match (number(first), number(last)) {
+                (Some(first), Some(last)) if first <= last => (first < len).then(|| (first, last.min(len - 1))),
+                _ => return ByteRanges::Full,
+            },
+        };
+        ranges.extend(range);
+    }
+    if ranges.is_empty() {
+        return ByteRanges::Unsatisfiable;
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last)) if start <= last.saturating_add(1) => *last = (*last).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    ByteRanges::Partial(merged)
+}
+
+/// IMF-fixdate of a time, as in `Last-Modified`
+pub fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Parse an HTTP date
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim()).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// Serve a file for a GET or HEAD request
+///
+/// Answers `304 Not Modified` to requests whose validators match,
+/// `206 Partial Content` to range requests, with a `multipart/byteranges`
+/// body for several ranges, and `416 Range Not Satisfiable` to ranges past
+/// the end of the file. `If-Range` requests for a changed file get the
+/// whole file.
+pub async fn serve_file(
+    storage: Arc<dyn LibraryStorage>,
+    key: &str,
+    content_type: &str,
+    method: &Method,
+    headers: &HeaderMap,
+) -> std::result::Result<Response, StatusCode> {
+    let entry = storage
+        .stat(key)
+        .await
+        .map_err(|e| {
+            error!("Failed to look up {} in {}: {}", key, storage.location(), e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let len = entry.size;
+    let validators = Validators::new(len, entry.modified.into());
+
+    let response = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &validators.etag)
+        .header(header::LAST_MODIFIED, http_date(validators.last_modified));
+    if validators.is_not_modified(headers) {
+        return build(response.status(StatusCode::NOT_MODIFIED), Body::empty());
+    }
+
+    let if_range = header_value(headers, header::IF_RANGE);
+    let ranges = match header_value(headers, header::RANGE) {
+        Some(range) if if_range.is_none_or(|value| validators.matches_if_range(value)) => parse_range(range, len),
+        _ => ByteRanges::Full,
+    };
+    let head = method == Method::HEAD;
+    let key = entry.key;
+
+    match ranges {
+        ByteRanges::Full => {
+            let response = response
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_LENGTH, len);
+            build(response, body(head, || read_range(storage, key, 0, len)))
+        }
+        ByteRanges::Unsatisfiable => {
+            let response = response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len));
+            build(response, Body::empty())
+        }
+        ByteRanges::Partial(ranges) if ranges.len() == 1 => {
+            let (start, end) = ranges[0];
+            let response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len))
+                .header(header::CONTENT_LENGTH, end - start + 1);
+            build(response, body(head, || read_range(storage, key, start, end - start + 1)))
+        }
+        ByteRanges::Partial(ranges) => {
+            let boundary = Uuid::new_v4().simple().to_string();
+            let parts = ranges
+                .iter()
+                .enumerate()
+                .map(|(index, (start, end))| {
+                    let separator = if index == 0 { "" } else { "\r\n" };
+                    let part_header = format!(
+                        "{}--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        separator, boundary, content_type, start, end, len
+                    );
+                    (part_header, *start, end - start + 1)
+                })
+                .collect::<Vec<_>>();
+            let closing = format!("\r\n--{}--\r\n", boundary);
+            let content_length = parts.iter().map(|(part_header, _, len)| part_header.len() as u64 + len).sum::<u64>()
+                + closing.len() as u64;
+
+            let response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, format!("multipart/byteranges; boundary={}", boundary))
+                .header(header::CONTENT_LENGTH, content_length);
+            build(
+                response,
+                body(head, || {
+                    stream::iter(parts)
+                        .flat_map(move |(part_header, start, len)| {
+                            stream::once(async move { Ok(Bytes::from(part_header)) })
+                                .chain(read_range(storage.clone(), key.clone(), start, len))
+                        })
+                        .chain(stream::once(async move { Ok(Bytes::from(closing)) }))
+                }),
+            )
+        }
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Body of a response, empty for HEAD requests
+fn body<S>(head: bool, stream: impl FnOnce() -> S) -> Body
+where
+    S: Stream<Item = std::io::Result<Bytes>> + Send + 'static,
+{
+    if head {
+        Body::empty()
+    } else {
+        Body::from_stream(stream())
+    }
+}
+
+/// Stream `len` bytes of a file from an offset, in chunks
+///
+/// Files that shrank while being served end the stream with an error, so
+/// the response is cut short rather than padded.
+fn read_range(
+    storage: Arc<dyn LibraryStorage>,
+    key: String,
+    start: u64,
+    len: u64,
+) -> impl Stream<Item = std::io::Result<Bytes>> + Send + 'static {
+    let end = start + len;
+    stream::try_unfold(start, move |position| {
+        let storage = storage.clone();
+        let key = key.clone();
+        async move {
+            if position >= end {
+                return Ok(None);
+            }
+            let chunk = storage
+                .read_range(&key, position..end.min(position + CHUNK_SIZE as u64))
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            if chunk.is_empty() {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            let next = position + chunk.len() as u64;
+            Ok(Some((chunk, next)))
+        }
+    })
+}
+
+fn build(response: axum::http::response::Builder, body: Body) -> std::result::Result<Response, StatusCode> {
+    response.body(body).map_err(|e| {
+        error!("Failed to build response: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use rustflix_core::config::ObjectStorageConfig;
+    use rustflix_media_library::storage::{LocalStorage, S3Storage};
+    use std::time::UNIX_EPOCH;
+    use tempfile::TempDir;
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    const CONTENT: &[u8] = b"0123456789abcdefghij";
+
+    fn sample_file() -> (TempDir, Arc<dyn LibraryStorage>) {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("movie.mp4"), CONTENT).unwrap();
+        let storage = Arc::new(LocalStorage::new(temp_dir.path().to_str().unwrap()));
+        (temp_dir, storage)
+    }
+
+    /// Bucket `media` holding `movies/movie.mp4`, answering HEAD and ranged
+    /// GET requests without checking signatures
+    struct FakeBucket;
+
+    impl Respond for FakeBucket {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            if request.url.path() != "/media/movies/movie.mp4" {
+                return ResponseTemplate::new(404);
+            }
+            let range = request
+                .headers
+                .get(&"range".parse::<wiremock::http::HeaderName>().unwrap())
+                .and_then(|values| {
+                    let (start, end) = values.last().as_str().strip_prefix("bytes=")?.split_once('-')?;
+                    Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+                });
+            let response = match range {
+                Some((start, end)) => ResponseTemplate::new(206).set_body_bytes(&CONTENT[start..=end.min(CONTENT.len() - 1)]),
+                None => ResponseTemplate::new(200).set_body_bytes(CONTENT),
+            };
+            response.insert_header("Last-Modified", "Tue, 02 Jan 2024 03:04:05 GMT")
+        }
+    }
+
+    async fn fake_bucket() -> (MockServer, Arc<dyn LibraryStorage>) {
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any()).respond_with(FakeBucket).mount(&server).await;
+        let config = ObjectStorageConfig {
+            endpoint: server.uri(),
+            region: "us-east-1".to_string(),
+            access_key_id: "minio".to_string(),
+            secret_access_key: "minio-secret".to_string(),
+            path_style: true,
+            poll_interval: 300,
+        };
+        let storage = Arc::new(S3Storage::from_location("s3://media/movies", &config).unwrap());
+        (server, storage)
+    }
+
+    fn request(headers: &[(header::HeaderName, &str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    async fn get(storage: &Arc<dyn LibraryStorage>, headers: &[(header::HeaderName, &str)]) -> (Response, Vec<u8>) {
+        let response = serve_file(storage.clone(), "movie.mp4", "video/mp4", &Method::GET, &request(headers))
+            .await
+            .unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap().to_vec();
+        (Response::from_parts(parts, Body::empty()), body)
+    }
+
+    fn header_of(response: &Response, name: header::HeaderName) -> &str {
+        response.headers().get(name).unwrap().to_str().unwrap()
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-4", 20), ByteRanges::Partial(vec![(0, 4)]));
+        assert_eq!(parse_range("bytes=15-", 20), ByteRanges::Partial(vec![(15, 19)]));
+        assert_eq!(parse_range("bytes=-5", 20), ByteRanges::Partial(vec![(15, 19)]));
+        assert_eq!(parse_range("bytes=-50", 20), ByteRanges::Partial(vec![(0, 19)]));
+        assert_eq!(parse_range("bytes=10-100", 20), ByteRanges::Partial(vec![(10, 19)]));
+        assert_eq!(parse_range("bytes=10-12, 0-1, 2-3, 11-14", 20), ByteRanges::Partial(vec![(0, 3), (10, 14)]));
+        assert_eq!(parse_range("bytes=20-, -0", 20), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=30-40, 5-6", 20), ByteRanges::Partial(vec![(5, 6)]));
+
+        assert_eq!(parse_range("items=0-4", 20), ByteRanges::Full);
+        assert_eq!(parse_range("bytes=5-4", 20), ByteRanges::Full);
+        assert_eq!(parse_range("bytes=a-4", 20), ByteRanges::Full);
+        assert_eq!(parse_range("bytes=", 20), ByteRanges::Full);
+        assert_eq!(parse_range(&format!("bytes={}", vec!["0-0"; 17].join(",")), 20), ByteRanges::Full);
+    }
+
+    #[test]
+    fn test_validators() {
+        let modified = UNIX_EPOCH + std::time::Duration::from_millis(784_111_777_250);
+        let validators = Validators::new(20, modified);
+        assert_eq!(http_date(validators.last_modified), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(validators.last_modified));
+
+        assert!(validators.matches_if_range(&validators.etag));
+        assert!(!validators.matches_if_range(&format!("W/{}", validators.etag)));
+        assert!(validators.matches_if_range("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert!(!validators.matches_if_range("Sun, 06 Nov 1994 08:49:36 GMT"));
+
+        let weak = format!("\"other\", W/{}", validators.etag);
+        assert!(validators.is_not_modified(&request(&[(header::IF_NONE_MATCH, &weak)])));
+        assert!(!validators.is_not_modified(&request(&[(header::IF_NONE_MATCH, "\"other\"")])));
+        assert!(validators.is_not_modified(&request(&[(header::IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT")])));
+        assert!(!validators.is_not_modified(&request(&[(header::IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:00:00 GMT")])));
+    }
+
+    #[tokio::test]
+    async fn test_serve_whole_file() {
+        let (_temp_dir, storage) = sample_file();
+
+        let (response, body) = get(&storage, &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header_of(&response, header::CONTENT_TYPE), "video/mp4");
+        assert_eq!(header_of(&response, header::CONTENT_LENGTH), "20");
+        assert_eq!(header_of(&response, header::ACCEPT_RANGES), "bytes");
+        assert_eq!(body, CONTENT);
+
+        let etag = header_of(&response, header::ETAG).to_string();
+        let (response, body) = get(&storage, &[(header::IF_NONE_MATCH, &etag)]).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+
+        let result = serve_file(storage, "missing.mp4", "video/mp4", &Method::GET, &HeaderMap::new()).await;
+        assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_serve_ranges() {
+        let (_temp_dir, storage) = sample_file();
+
+        let (response, body) = get(&storage, &[(header::RANGE, "bytes=5-9")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header_of(&response, header::CONTENT_RANGE), "bytes 5-9/20");
+        assert_eq!(header_of(&response, header::CONTENT_LENGTH), "5");
+        assert_eq!(body, b"56789");
+
+        let (response, _) = get(&storage, &[(header::RANGE, "bytes=25-")]).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(header_of(&response, header::CONTENT_RANGE), "bytes */20");
+
+        let (response, body) = get(&storage, &[(header::RANGE, "bytes=0-1,-3")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = header_of(&response, header::CONTENT_TYPE);
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let expected = format!(
+            "--{b}\r\nContent-Type: video/mp4\r\nContent-Range: bytes 0-1/20\r\n\r\n01\r\n\
+             --{b}\r\nContent-Type: video/mp4\r\nContent-Range: bytes 17-19/20\r\n\r\nhij\r\n--{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+        assert_eq!(header_of(&response, header::CONTENT_LENGTH), expected.len().to_string());
+    }
+
+    #[tokio::test]
+    async fn test_if_range_and_head() {
+        let (_temp_dir, storage) = sample_file();
+        let (response, _) = get(&storage, &[]).await;
+        let etag = header_of(&response, header::ETAG).to_string();
+        let last_modified = header_of(&response, header::LAST_MODIFIED).to_string();
+
+        let (response, body) = get(&storage, &[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, &etag)]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, b"01");
+        let (response, _) = get(&storage, &[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, &last_modified)]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let (response, body) = get(&storage, &[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, "\"stale\"")]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body, CONTENT);
+
+        let headers = request(&[(header::RANGE, "bytes=10-")]);
+        let response = serve_file(storage, "movie.mp4", "video/mp4", &Method::HEAD, &headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header_of(&response, header::CONTENT_LENGTH), "10");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_serve_from_bucket() {
+        let (_server, storage) = fake_bucket().await;
+
+        let (response, body) = get(&storage, &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header_of(&response, header::CONTENT_LENGTH), "20");
+        assert_eq!(header_of(&response, header::LAST_MODIFIED), "Tue, 02 Jan 2024 03:04:05 GMT");
+        assert_eq!(body, CONTENT);
+
+        let (response, body) = get(&storage, &[(header::RANGE, "bytes=5-9")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header_of(&response, header::CONTENT_RANGE), "bytes 5-9/20");
+        assert_eq!(body, b"56789");
+
+        let result = serve_file(storage, "missing.mp4", "video/mp4", &Method::GET, &HeaderMap::new()).await;
+        assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,13 +1,26 @@
 //! API request handlers
 
-use rustflix_core::{Result, RustFlixError};
+use rustflix_core::streaming::TranscodingStatus;
+use rustflix_core::{ExtraType, MediaFormat, Result, RustFlixError, StackTimeline};
+use rustflix_database::{
+    AudiobookModel, AuditIssueModel, BookModel, ImageVariantModel, LibraryAuditModel, MediaChapterModel, MediaImageModel, MediaItemModel, MediaRepository, PhotoModel, PlaybackStateModel,
+    ReadingProgressModel, UserRepository,
+};
+use rustflix_media_library::{
+    books, images, storage, AuditCleanup, IssueKind, LibraryAuditor, LibraryStorages, MediaLibraryService, VariantFormat,
+};
+use rustflix_streaming::StreamingService;
 use axum::{
-    extract::{Json, Path, Query},
-    http::StatusCode,
-    response::{IntoResponse, Json as ResponseJson},
+    extract::{Extension, Json, Path, Query},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Json as ResponseJson, Response},
 };
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tracing::error;
 use uuid::Uuid;
+use crate::direct_play;
 
 /// Media-related API handlers
 pub struct MediaHandler;
@@ -56,6 +69,7 @@ impl MediaHandler {
                 },
                 created_at: "2023-01-01T00:00:00Z".to_string(),
                 updated_at: "2023-01-01T00:00:00Z".to_string(),
+                chapters: Vec::new(),
             },
             MediaItem {
                 id: Uuid::new_v4(),
@@ -89,6 +103,7 @@ impl MediaHandler {
                 },
                 created_at: "2023-01-01T00:00:00Z".to_string(),
                 updated_at: "2023-01-01T00:00:00Z".to_string(),
+                chapters: Vec::new(),
             },
             MediaItem {
                 id: Uuid::new_v4(),
@@ -122,6 +137,7 @@ impl MediaHandler {
                 },
                 created_at: "2023-01-01T00:00:00Z".to_string(),
                 updated_at: "2023-01-01T00:00:00Z".to_string(),
+                chapters: Vec::new(),
             },
         ];
 
@@ -145,42 +161,71 @@ impl MediaHandler {
         })
     }
 
-    /// Get specific media item
-    pub async fn get_media(Path(id): Path<Uuid>) -> impl IntoResponse {
-        // Mock implementation - return a sample media item
-        let media_item = MediaItem {
-            id,
-            title: "Sample Media Item".to_string(),
-            description: Some("This is a sample media item for testing purposes.".to_string()),
-            media_type: "movie".to_string(),
-            file_path: "/media/movies/sample.mp4".to_string(),
-            file_size: 2_000_000_000, // 2 GB
-            duration: Some(120),
-            metadata: MediaMetadata {
-                title: "Sample Media Item".to_string(),
-                description: Some("This is a sample media item for testing purposes.".to_string()),
-                release_date: Some("2023-01-01".to_string()),
-                genres: vec!["Action".to_string(), "Drama".to_string()],
-                cast: vec![],
-                crew: vec![],
-                rating: Some(8.5),
-                poster: Some("https://via.placeholder.com/300x450".to_string()),
-                backdrop: Some("https://via.placeholder.com/1920x1080".to_string()),
-                trailer: None,
-                imdb_id: None,
-                tmdb_id: None,
-            },
-            created_at: "2023-01-01T00:00:00Z".to_string(),
-            updated_at: "2023-01-01T00:00:00Z".to_string(),
+    /// Get specific media item with its chapters
+    pub async fn get_media(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<MediaItem>>, StatusCode> {
+        let failed = |e: RustFlixError| {
+            error!("Failed to load media item {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
         };
 
-        (StatusCode::OK, ResponseJson(ApiResponse {
-            data: media_item,
+        let item = repository
+            .get_media_item(id)
+            .await
+            .map_err(failed)?
+            .filter(|item| item.removed_at.is_none())
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let chapters = repository.get_chapters(id).await.map_err(failed)?;
+        let images = repository.get_media_images(id).await.map_err(failed)?;
+
+        let mut media = media_item(item, chapters);
+        for image in images {
+            let url = Some(format!("/api/v1/media/{}/images/{}", id, image.kind));
+            match image.kind.as_str() {
+                "poster" => media.metadata.poster = url,
+                "backdrop" => media.metadata.backdrop = url,
+                _ => {}
+            }
+        }
+
+        Ok(ResponseJson(ApiResponse {
+            data: media,
             success: true,
-            message: Some("Media item retrieved successfully".to_string()),
+            message: None,
         }))
     }
 
+    /// List the poster, backdrop and thumbnail images of a media item with
+    /// their blurhash and sizes
+    pub async fn get_images(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<Vec<MediaImage>>>, StatusCode> {
+        let images = load_images(&repository, id).await.map_err(|e| {
+            error!("Failed to load images of {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: images,
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Serve the smallest variant of an image at least as wide as requested,
+    /// as WebP when the client accepts it
+    pub async fn get_image(
+        Extension(repository): Extension<MediaRepository>,
+        Path((id, kind)): Path<(Uuid, String)>,
+        Query(params): Query<ThumbnailParams>,
+        headers: HeaderMap,
+    ) -> std::result::Result<impl IntoResponse, StatusCode> {
+        serve_image(&repository, id, &kind, params.width.unwrap_or(0), &headers).await
+    }
+
     /// Update media item
     pub async fn update_media(
         Path(id): Path<Uuid>,
@@ -224,6 +269,7 @@ impl MediaHandler {
                 },
                 created_at: "2023-01-01T00:00:00Z".to_string(),
                 updated_at: "2023-01-01T00:00:00Z".to_string(),
+                chapters: Vec::new(),
             },
             MediaItem {
                 id: Uuid::new_v4(),
@@ -249,6 +295,7 @@ impl MediaHandler {
                 },
                 created_at: "2023-01-01T00:00:00Z".to_string(),
                 updated_at: "2023-01-01T00:00:00Z".to_string(),
+                chapters: Vec::new(),
             },
         ];
 
@@ -283,10 +330,159 @@ impl MediaHandler {
         StatusCode::NOT_IMPLEMENTED
     }
 
-    /// Scan library
-    pub async fn scan_library(Path(id): Path<Uuid>) -> StatusCode {
-        // Placeholder implementation
-        StatusCode::NOT_IMPLEMENTED
+    /// Start a scan of a library in the background
+    ///
+    /// Answers `409 Conflict` while the library is already being scanned.
+    pub async fn scan_library(
+        Extension(media_library): Extension<MediaLibraryService>,
+        Path(id): Path<Uuid>,
+    ) -> StatusCode {
+        match media_library.start_scan(id).await {
+            Ok(()) => StatusCode::ACCEPTED,
+            Err(RustFlixError::NotFound { .. }) => StatusCode::NOT_FOUND,
+            Err(RustFlixError::ServiceUnavailable { .. }) => StatusCode::CONFLICT,
+            Err(e) => {
+                error!("Failed to start scan of library {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Cancel the running scan of a library
+    pub async fn cancel_scan(
+        Extension(media_library): Extension<MediaLibraryService>,
+        Path(id): Path<Uuid>,
+    ) -> StatusCode {
+        if media_library.cancel_scan(id) {
+            StatusCode::NO_CONTENT
+        } else {
+            StatusCode::NOT_FOUND
+        }
+    }
+
+    /// List groups of media items with identical content
+    pub async fn list_duplicates(
+        Extension(repository): Extension<MediaRepository>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<Vec<DuplicateGroup>>>, StatusCode> {
+        let items = repository.get_duplicate_media_items().await.map_err(|e| {
+            error!("Failed to load duplicate media items: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: group_duplicates(items),
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Get the versions and parts of the title a media item belongs to
+    pub async fn get_stack(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<MediaStack>>, StatusCode> {
+        let (item, members) = load_title(&repository, id).await?;
+        let stack = match item.stack_id {
+            Some(stack_id) => repository.get_stack(stack_id).await.map_err(|e| {
+                error!("Failed to load media stack {}: {}", stack_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?,
+            None => None,
+        };
+
+        Ok(ResponseJson(ApiResponse {
+            data: MediaStack {
+                id: stack.as_ref().map(|stack| stack.id),
+                title: stack.as_ref().map(|stack| stack.title.clone()),
+                year: stack.and_then(|stack| stack.year),
+                versions: stack_versions(&members),
+            },
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Get a user's position in the title a media item belongs to
+    ///
+    /// Positions are shared by every part and version of a stack, and by
+    /// the files of an audiobook; the response names the part of this
+    /// item's version to resume.
+    pub async fn get_playback(
+        Extension(repository): Extension<MediaRepository>,
+        Extension(users): Extension<UserRepository>,
+        Path(id): Path<Uuid>,
+        Query(params): Query<PlaybackParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<PlaybackPosition>>, StatusCode> {
+        let (item, members) = load_title(&repository, id).await?;
+        let state = users
+            .get_playback_state(params.user_id, members[0].id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load playback state of {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        let timeline = version_timeline(&item, &members);
+        let position = playback_position(&timeline, state.position_seconds, state.updated_at)
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: position,
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Store a user's position within a media item as a position in its title
+    pub async fn update_playback(
+        Extension(repository): Extension<MediaRepository>,
+        Extension(users): Extension<UserRepository>,
+        Path(id): Path<Uuid>,
+        Json(payload): Json<UpdatePlaybackRequest>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<PlaybackPosition>>, StatusCode> {
+        let (item, members) = load_title(&repository, id).await?;
+        let lead = members[0].id;
+        let timeline = version_timeline(&item, &members);
+        let position = timeline.position(item.id, payload.position).ok_or(StatusCode::NOT_FOUND)?;
+
+        let state = store_playback(&users, payload.user_id, lead, &timeline, position, None)
+            .await
+            .map_err(|e| {
+                error!("Failed to store playback state of {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: playback_position(&timeline, position, state.updated_at).ok_or(StatusCode::NOT_FOUND)?,
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// List the trailers, featurettes and other extras of a movie or show
+    pub async fn get_extras(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<Vec<MediaExtra>>>, StatusCode> {
+        let failed = |e: RustFlixError| {
+            error!("Failed to load extras of {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        let item = repository
+            .get_media_item(id)
+            .await
+            .map_err(failed)?
+            .filter(|item| item.removed_at.is_none())
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let extras = repository.get_extras(&extra_owners(&item)).await.map_err(failed)?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: extras.into_iter().filter_map(media_extra).collect(),
+            success: true,
+            message: None,
+        }))
     }
 
     /// Get available genres
@@ -316,6 +512,537 @@ impl MediaHandler {
     }
 }
 
+/// Photo library API handlers
+pub struct PhotoHandler;
+
+impl PhotoHandler {
+    /// List the photos taken in a year, month or day, newest first
+    pub async fn list_photos(
+        Extension(repository): Extension<MediaRepository>,
+        Query(params): Query<PhotoDateParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<Vec<Photo>>>, StatusCode> {
+        let (from, to) = date_range(params.year, params.month, params.day).ok_or(StatusCode::BAD_REQUEST)?;
+        let limit = params.limit.unwrap_or(100).min(1000) as i64;
+        let offset = params.offset.unwrap_or(0) as i64;
+
+        let photos = repository
+            .get_photos_taken_between(from, to, limit, offset)
+            .await
+            .map_err(|e| {
+                error!("Failed to load photos: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: photos.into_iter().map(|(path, photo)| photo_entry(path, photo)).collect(),
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Count photos per year, per month of a year or per day of a month
+    pub async fn get_timeline(
+        Extension(repository): Extension<MediaRepository>,
+        Query(params): Query<PhotoDateParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<Vec<PhotoDate>>>, StatusCode> {
+        let unit = timeline_unit(params.year, params.month).ok_or(StatusCode::BAD_REQUEST)?;
+        let (from, to) = match params.year {
+            Some(year) => date_range(Some(year), params.month, None)
+                .map(|(from, to)| (Some(from), Some(to)))
+                .ok_or(StatusCode::BAD_REQUEST)?,
+            None => (None, None),
+        };
+
+        let periods = repository.get_photo_timeline(unit, from, to).await.map_err(|e| {
+            error!("Failed to load photo timeline: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: periods
+                .into_iter()
+                .map(|(period, count)| photo_date(period, unit, count))
+                .collect(),
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// List the subfolders and photos of a folder, or the photo libraries
+    /// when no folder is given
+    pub async fn browse_folder(
+        Extension(repository): Extension<MediaRepository>,
+        Query(params): Query<PhotoFolderParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<PhotoFolderListing>>, StatusCode> {
+        let failed = |e: RustFlixError| {
+            error!("Failed to browse photo folders: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        let listing = match params.path {
+            Some(path) => {
+                let folder = path.trim_end_matches(std::path::MAIN_SEPARATOR);
+                let prefix = format!("{}{}", folder, std::path::MAIN_SEPARATOR);
+                let photos = repository.get_photos_by_prefix(&prefix).await.map_err(failed)?;
+                folder_listing(folder, photos)
+            }
+            None => {
+                let mut folders = Vec::new();
+                for library in repository.list_libraries().await.map_err(failed)? {
+                    if library.library_type != "photos" || !library.is_enabled {
+                        continue;
+                    }
+                    let folder = library.path.trim_end_matches(std::path::MAIN_SEPARATOR).to_string();
+                    let prefix = format!("{}{}", folder, std::path::MAIN_SEPARATOR);
+                    folders.push(PhotoFolder {
+                        name: library.name,
+                        path: folder,
+                        photo_count: repository.count_photos_by_prefix(&prefix).await.map_err(failed)?,
+                    });
+                }
+                PhotoFolderListing { path: None, folders, photos: Vec::new() }
+            }
+        };
+
+        Ok(ResponseJson(ApiResponse {
+            data: listing,
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Get a photo with its EXIF details and thumbnail sizes
+    pub async fn get_photo(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<PhotoDetails>>, StatusCode> {
+        let failed = |e: RustFlixError| {
+            error!("Failed to load photo {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        let item = repository
+            .get_media_item(id)
+            .await
+            .map_err(failed)?
+            .filter(|item| item.removed_at.is_none())
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let photo = repository.get_photo(id).await.map_err(failed)?.ok_or(StatusCode::NOT_FOUND)?;
+        let image = load_images(&repository, id)
+            .await
+            .map_err(failed)?
+            .into_iter()
+            .find(|image| image.kind == "photo");
+
+        Ok(ResponseJson(ApiResponse {
+            data: PhotoDetails {
+                photo: photo_entry(item.path, photo),
+                blurhash: image.as_ref().map(|image| image.blurhash.clone()),
+                thumbnails: image
+                    .map(|image| {
+                        image
+                            .sizes
+                            .into_iter()
+                            .map(|size| PhotoThumbnail { width: size.width, height: size.height })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            },
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Serve the smallest thumbnail of a photo at least as wide as requested,
+    /// as WebP when the client accepts it
+    pub async fn get_thumbnail(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+        Query(params): Query<ThumbnailParams>,
+        headers: HeaderMap,
+    ) -> std::result::Result<impl IntoResponse, StatusCode> {
+        serve_image(&repository, id, "photo", params.width.unwrap_or(0), &headers).await
+    }
+}
+
+/// Slowest and fastest playback speeds accepted for audiobooks
+const PLAYBACK_RATES: std::ops::RangeInclusive<f32> = 0.25..=4.0;
+
+/// Audiobook library API handlers
+pub struct AudiobookHandler;
+
+impl AudiobookHandler {
+    /// List the books of the audiobook libraries by title
+    pub async fn list_audiobooks(
+        Extension(repository): Extension<MediaRepository>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<Vec<Audiobook>>>, StatusCode> {
+        let books = repository.get_audiobooks().await.map_err(|e| {
+            error!("Failed to load audiobooks: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: books.into_iter().map(audiobook).collect(),
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Get a book with its files and its chapters on the timeline of the
+    /// whole book
+    pub async fn get_audiobook(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<AudiobookDetails>>, StatusCode> {
+        let (book, parts) = load_book(&repository, id).await?;
+        let mut chapters = HashMap::new();
+        for part in &parts {
+            let part_chapters = repository.get_chapters(part.id).await.map_err(|e| {
+                error!("Failed to load chapters of {}: {}", part.id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            chapters.insert(part.id, part_chapters);
+        }
+
+        let timeline = version_timeline(&parts[0], &parts);
+        Ok(ResponseJson(ApiResponse {
+            data: AudiobookDetails {
+                book: audiobook(book),
+                duration: timeline.duration(),
+                chapters: book_chapters(&timeline, &parts, &chapters),
+                parts: timeline
+                    .parts()
+                    .filter_map(|(id, start, duration)| {
+                        let part = parts.iter().find(|part| part.id == id)?;
+                        Some(StackPart { id, path: part.path.clone(), start, duration })
+                    })
+                    .collect(),
+            },
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Get a user's position in a book and their playback speed
+    pub async fn get_playback(
+        Extension(repository): Extension<MediaRepository>,
+        Extension(users): Extension<UserRepository>,
+        Path(id): Path<Uuid>,
+        Query(params): Query<PlaybackParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<AudiobookPlayback>>, StatusCode> {
+        let (_, parts) = load_book(&repository, id).await?;
+        let state = users
+            .get_playback_state(params.user_id, parts[0].id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load playback state of audiobook {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        let timeline = version_timeline(&parts[0], &parts);
+        let position = playback_position(&timeline, state.position_seconds, state.updated_at)
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: AudiobookPlayback { position, playback_rate: state.playback_rate },
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Store a user's position in a book, given within one of its files or
+    /// in the whole book, and optionally their playback speed
+    pub async fn update_playback(
+        Extension(repository): Extension<MediaRepository>,
+        Extension(users): Extension<UserRepository>,
+        Path(id): Path<Uuid>,
+        Json(payload): Json<UpdateAudiobookPlaybackRequest>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<AudiobookPlayback>>, StatusCode> {
+        if payload.playback_rate.is_some_and(|rate| !PLAYBACK_RATES.contains(&rate)) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let (_, parts) = load_book(&repository, id).await?;
+        let timeline = version_timeline(&parts[0], &parts);
+        let position =
+            book_position(&timeline, payload.media_id, payload.position).ok_or(StatusCode::NOT_FOUND)?;
+
+        let state = store_playback(&users, payload.user_id, parts[0].id, &timeline, position, payload.playback_rate)
+            .await
+            .map_err(|e| {
+                error!("Failed to store playback state of audiobook {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: AudiobookPlayback {
+                position: playback_position(&timeline, position, state.updated_at).ok_or(StatusCode::NOT_FOUND)?,
+                playback_rate: state.playback_rate,
+            },
+            success: true,
+            message: None,
+        }))
+    }
+}
+
+/// Comic and e-book library API handlers
+pub struct BookHandler;
+
+impl BookHandler {
+    /// List the comics and e-books of the book libraries by series and title
+    pub async fn list_books(
+        Extension(repository): Extension<MediaRepository>,
+        Query(params): Query<ListParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<Vec<Book>>>, StatusCode> {
+        let limit = params.limit.unwrap_or(100).min(1000) as i64;
+        let offset = params.offset.unwrap_or(0) as i64;
+        let books = repository.get_books(limit, offset).await.map_err(|e| {
+            error!("Failed to load books: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: books.into_iter().map(|(path, model)| book(&path, model)).collect(),
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Get a comic or e-book with its description and cover sizes
+    pub async fn get_book(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<BookDetails>>, StatusCode> {
+        let (item, model) = load_book_item(&repository, id).await?;
+        let images = load_images(&repository, id).await.map_err(|e| {
+            error!("Failed to load images of book {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: BookDetails {
+                publisher: model.publisher.clone(),
+                language: model.language.clone(),
+                description: model.description.clone(),
+                book: book(&item.path, model),
+                format: item.format,
+                images,
+            },
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Serve a page of a comic, counting from 1, scaled down to the
+    /// requested width as WebP when the client accepts it, otherwise as JPEG
+    ///
+    /// Without a width the page is sent as stored in the archive. E-book
+    /// pages are laid out by the reader and are not served.
+    pub async fn get_page(
+        Extension(repository): Extension<MediaRepository>,
+        Path((id, page)): Path<(Uuid, u32)>,
+        Query(params): Query<ThumbnailParams>,
+        headers: HeaderMap,
+    ) -> std::result::Result<impl IntoResponse, StatusCode> {
+        let (item, _) = load_book_item(&repository, id).await?;
+        if !MediaFormat::from_extension(&item.format).is_comic() || page == 0 {
+            return Err(StatusCode::NOT_FOUND);
+        }
+
+        let accept = headers.get(header::ACCEPT).and_then(|accept| accept.to_str().ok());
+        let format = VariantFormat::from_name(preferred_format(accept)).unwrap_or(VariantFormat::Jpeg);
+        let width = params.width;
+        let path = std::path::PathBuf::from(&item.path);
+        let result = tokio::task::spawn_blocking(move || -> Result<(&'static str, Vec<u8>)> {
+            let page = books::read_page(&path, page as usize - 1)?;
+            match width {
+                Some(width) => Ok((format.mime_type(), images::resize_image(&page.data, width.max(1), format)?)),
+                None => Ok((page.mime_type, page.data)),
+            }
+        })
+        .await
+        .map_err(|e| RustFlixError::internal(format!("Page task failed: {}", e)))
+        .and_then(|result| result);
+        let (content_type, data) = result.map_err(|e| match e {
+            RustFlixError::NotFound { .. } => StatusCode::NOT_FOUND,
+            e => {
+                error!("Failed to read page {} of book {}: {}", page, id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+        Ok((
+            [
+                (header::CONTENT_TYPE, content_type),
+                (header::CACHE_CONTROL, "public, max-age=3600"),
+                (header::VARY, "Accept"),
+            ],
+            data,
+        ))
+    }
+
+    /// Get a user's reading progress in a book
+    pub async fn get_progress(
+        Extension(repository): Extension<MediaRepository>,
+        Extension(users): Extension<UserRepository>,
+        Path(id): Path<Uuid>,
+        Query(params): Query<PlaybackParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<ReadingProgress>>, StatusCode> {
+        load_book_item(&repository, id).await?;
+        let progress = users
+            .get_reading_progress(params.user_id, id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load reading progress of book {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: reading_progress(progress),
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Store a user's reading progress in a book, given as a page of a
+    /// comic or PDF, or as a location and fraction read of an EPUB
+    pub async fn update_progress(
+        Extension(repository): Extension<MediaRepository>,
+        Extension(users): Extension<UserRepository>,
+        Path(id): Path<Uuid>,
+        Json(payload): Json<UpdateReadingProgressRequest>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<ReadingProgress>>, StatusCode> {
+        let (_, model) = load_book_item(&repository, id).await?;
+        let (progress, is_finished) = resolve_progress(model.page_count, &payload).ok_or(StatusCode::BAD_REQUEST)?;
+
+        let progress = ReadingProgressModel {
+            user_id: payload.user_id,
+            media_id: id,
+            page: payload.page.map(|page| page as i32),
+            location: payload.location,
+            progress,
+            is_finished,
+            updated_at: Utc::now(),
+        };
+        users.upsert_reading_progress(&progress).await.map_err(|e| {
+            error!("Failed to store reading progress of book {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: reading_progress(progress),
+            success: true,
+            message: None,
+        }))
+    }
+}
+
+/// Library audit API handlers
+pub struct AuditHandler;
+
+impl AuditHandler {
+    /// Start an audit of every library in the background
+    ///
+    /// With `?cleanup=true` the cleanup actions are applied once it
+    /// completes. Only one audit runs at a time.
+    pub async fn start_audit(
+        Extension(auditor): Extension<LibraryAuditor>,
+        Query(params): Query<StartAuditParams>,
+    ) -> std::result::Result<(StatusCode, ResponseJson<ApiResponse<LibraryAudit>>), StatusCode> {
+        let audit = auditor.start(params.cleanup.unwrap_or(false)).await.map_err(|e| match e {
+            RustFlixError::ServiceUnavailable { .. } => StatusCode::CONFLICT,
+            e => {
+                error!("Failed to start library audit: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+        Ok((
+            StatusCode::ACCEPTED,
+            ResponseJson(ApiResponse {
+                data: library_audit(audit),
+                success: true,
+                message: None,
+            }),
+        ))
+    }
+
+    /// List library audits, latest first
+    pub async fn list_audits(
+        Extension(repository): Extension<MediaRepository>,
+        Query(params): Query<ListParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<Vec<LibraryAudit>>>, StatusCode> {
+        let limit = params.limit.unwrap_or(20).min(100) as i64;
+        let audits = repository.list_audits(limit).await.map_err(|e| {
+            error!("Failed to load library audits: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: audits.into_iter().map(library_audit).collect(),
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Get an audit with its issues, optionally of one kind
+    pub async fn get_audit(
+        Extension(repository): Extension<MediaRepository>,
+        Path(id): Path<Uuid>,
+        Query(params): Query<AuditIssueParams>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<LibraryAuditDetails>>, StatusCode> {
+        let kind = match params.kind.as_deref() {
+            Some(name) => Some(IssueKind::from_name(name).ok_or(StatusCode::BAD_REQUEST)?),
+            None => None,
+        };
+        let audit = repository
+            .get_audit(id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load library audit {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let issues = repository.get_audit_issues(id, kind.map(|kind| kind.as_str())).await.map_err(|e| {
+            error!("Failed to load issues of library audit {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: LibraryAuditDetails {
+                audit: library_audit(audit),
+                counts: issue_counts(&issues),
+                issues: issues.into_iter().map(audit_issue).collect(),
+            },
+            success: true,
+            message: None,
+        }))
+    }
+
+    /// Apply the cleanup actions to the unresolved issues of a completed
+    /// audit
+    pub async fn clean_up(
+        Extension(auditor): Extension<LibraryAuditor>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<AuditCleanupSummary>>, StatusCode> {
+        let cleanup = auditor.clean_up(id).await.map_err(|e| match e {
+            RustFlixError::NotFound { .. } => StatusCode::NOT_FOUND,
+            RustFlixError::Validation { .. } => StatusCode::CONFLICT,
+            e => {
+                error!("Failed to clean up library audit {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+        Ok(ResponseJson(ApiResponse {
+            data: cleanup_summary(cleanup),
+            success: true,
+            message: None,
+        }))
+    }
+}
+
 /// User-related API handlers
 pub struct UserHandler;
 
@@ -399,6 +1126,7 @@ impl UserHandler {
                 },
                 created_at: "2023-01-01T00:00:00Z".to_string(),
                 updated_at: "2023-01-01T00:00:00Z".to_string(),
+                chapters: Vec::new(),
             },
         ];
 
@@ -571,6 +1299,37 @@ impl StreamHandler {
         StatusCode::OK
     }
 
+    /// Serve the original file of a media item, with byte ranges for seeking
+    pub async fn direct_play(
+        Extension(repository): Extension<MediaRepository>,
+        Extension(storages): Extension<LibraryStorages>,
+        Path(id): Path<Uuid>,
+        method: Method,
+        headers: HeaderMap,
+    ) -> std::result::Result<Response, StatusCode> {
+        let item = repository
+            .get_media_item(id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load media item {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .filter(|item| item.removed_at.is_none())
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        let (storage, key) = storages
+            .locate(&item.path)
+            .await
+            .map_err(|e| {
+                error!("Failed to open the storage of {}: {}", item.path, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        let content_type = MediaFormat::from_extension(&item.format).mime_type();
+        direct_play::serve_file(storage, &key, content_type, &method, &headers).await
+    }
+
     /// Serve HLS files
     pub async fn serve_hls(Path((id, file)): Path<(Uuid, String)>) -> (StatusCode, Vec<u8>) {
         // Placeholder implementation
@@ -583,22 +1342,77 @@ impl StreamHandler {
         (StatusCode::NOT_FOUND, vec![])
     }
 
-    /// Start transcoding job
-    pub async fn start_transcode(Json(payload): Json<TranscodeRequest>) -> StatusCode {
-        // Placeholder implementation
-        StatusCode::NOT_IMPLEMENTED
+    /// Start transcoding a media item with a quality profile
+    pub async fn start_transcode(
+        Extension(repository): Extension<MediaRepository>,
+        Extension(streaming): Extension<StreamingService>,
+        Json(payload): Json<TranscodeRequest>,
+    ) -> std::result::Result<(StatusCode, ResponseJson<ApiResponse<TranscodeJob>>), StatusCode> {
+        let item = repository
+            .get_media_item(payload.media_id)
+            .await
+            .map_err(|e| {
+                error!("Failed to load media item {}: {}", payload.media_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .filter(|item| item.removed_at.is_none())
+            .ok_or(StatusCode::NOT_FOUND)?;
+        // ffmpeg reads local files only
+        if storage::is_remote(&item.path) {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let job = streaming
+            .start_transcode(item.id, std::path::PathBuf::from(&item.path), &payload.profile)
+            .await
+            .map_err(|e| match e {
+                RustFlixError::Validation { .. } => StatusCode::BAD_REQUEST,
+                e => {
+                    error!("Failed to start transcoding media item {}: {}", item.id, e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            })?;
+
+        Ok((
+            StatusCode::ACCEPTED,
+            ResponseJson(ApiResponse {
+                data: TranscodeJob {
+                    id: job.id,
+                    status: transcode_status_name(job.status).to_string(),
+                },
+                success: true,
+                message: None,
+            }),
+        ))
     }
 
-    /// Get transcoding status
-    pub async fn transcode_status(Path(id): Path<Uuid>) -> StatusCode {
-        // Placeholder implementation
-        StatusCode::NOT_FOUND
+    /// Get the status and progress of a transcoding job
+    pub async fn transcode_status(
+        Extension(streaming): Extension<StreamingService>,
+        Path(id): Path<Uuid>,
+    ) -> std::result::Result<ResponseJson<ApiResponse<TranscodeStatus>>, StatusCode> {
+        let job = streaming.transcode_job(id).ok_or(StatusCode::NOT_FOUND)?;
+        Ok(ResponseJson(ApiResponse {
+            data: TranscodeStatus {
+                id: job.id,
+                status: transcode_status_name(job.status).to_string(),
+                progress: job.progress,
+            },
+            success: true,
+            message: job.error_message,
+        }))
     }
 
-    /// Cancel transcoding job
-    pub async fn cancel_transcode(Path(id): Path<Uuid>) -> StatusCode {
-        // Placeholder implementation
-        StatusCode::OK
+    /// Cancel a running transcoding job
+    pub async fn cancel_transcode(
+        Extension(streaming): Extension<StreamingService>,
+        Path(id): Path<Uuid>,
+    ) -> StatusCode {
+        if streaming.cancel_transcode(id) {
+            StatusCode::NO_CONTENT
+        } else {
+            StatusCode::NOT_FOUND
+        }
     }
 
     /// Get stream URL for media
@@ -632,6 +1446,21 @@ pub struct MediaItem {
     pub created_at: String,
     #[serde(rename = "updatedAt")]
     pub updated_at: String,
+    /// Chapter markers in playback order
+    pub chapters: Vec<MediaChapter>,
+}
+
+/// Chapter marker of a media item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaChapter {
+    /// Position of the chapter, starting at 1
+    pub index: i32,
+    pub title: String,
+    /// Seconds from the start of the file
+    #[serde(rename = "startTime")]
+    pub start_time: f64,
+    #[serde(rename = "endTime")]
+    pub end_time: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -663,78 +1492,1078 @@ pub struct Person {
     pub profile_image: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PaginatedResponse<T> {
-    pub data: Vec<T>,
-    pub pagination: PaginationInfo,
-}
+/// Group media items ordered by file hash into duplicate groups
+pub fn group_duplicates(items: Vec<MediaItemModel>) -> Vec<DuplicateGroup> {
+    let mut groups: Vec<(DuplicateGroup, Vec<Option<String>>)> = Vec::new();
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ApiResponse<T> {
-    pub data: T,
-    pub success: bool,
-    pub message: Option<String>,
-}
+    for item in items {
+        let Some(hash) = item.file_hash.clone() else {
+            continue;
+        };
+        let duplicate = DuplicateItem {
+            id: item.id,
+            path: item.path,
+            file_size: item.file_size,
+            created_at: item.created_at,
+        };
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PaginationInfo {
-    pub page: u32,
-    pub limit: u32,
-    pub total: u32,
-    pub total_pages: u32,
+        match groups.last_mut() {
+            Some((group, full_hashes)) if group.hash == hash => {
+                group.items.push(duplicate);
+                full_hashes.push(item.full_hash);
+            }
+            _ => groups.push((
+                DuplicateGroup { hash, confirmed: false, items: vec![duplicate] },
+                vec![item.full_hash],
+            )),
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|(group, _)| group.items.len() > 1)
+        .map(|(mut group, full_hashes)| {
+            // Only a matching hash of the whole file confirms the duplicate
+            group.confirmed = full_hashes[0].is_some() && full_hashes.iter().all(|hash| *hash == full_hashes[0]);
+            group
+        })
+        .collect()
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Library {
-    pub id: Uuid,
-    pub name: String,
-    pub path: String,
+/// Load a media item with the members of its stack, ordered by version and
+/// part; the files of an audiobook are the parts of their book, and any
+/// other item outside a stack is its own only member
+async fn load_title(
+    repository: &MediaRepository,
+    id: Uuid,
+) -> std::result::Result<(MediaItemModel, Vec<MediaItemModel>), StatusCode> {
+    let failed = |e: RustFlixError| {
+        error!("Failed to load media item {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let item = repository
+        .get_media_item(id)
+        .await
+        .map_err(failed)?
+        .filter(|item| item.removed_at.is_none())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let book_id = match item.media_type.as_str() {
+        "audiobook" => repository.get_audiobook_file(item.id).await.map_err(failed)?.and_then(|file| file.book_id),
+        _ => None,
+    };
+    let members = match (item.stack_id, book_id) {
+        (Some(stack_id), _) => repository.get_stack_items(stack_id).await.map_err(failed)?,
+        (None, Some(book_id)) => repository.get_audiobook_parts(book_id).await.map_err(failed)?,
+        (None, None) => Vec::new(),
+    };
+
+    if members.iter().any(|member| member.id == item.id) {
+        Ok((item, members))
+    } else {
+        let members = vec![item.clone()];
+        Ok((item, members))
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct User {
-    pub id: Uuid,
-    pub username: String,
-    pub email: String,
+/// Timeline of the version a stack member belongs to
+pub fn version_timeline(item: &MediaItemModel, members: &[MediaItemModel]) -> StackTimeline {
+    StackTimeline::new(
+        members
+            .iter()
+            .filter(|member| member.stack_version == item.stack_version)
+            .map(|member| (member.id, member.duration))
+            .collect(),
+    )
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AuthUser {
-    pub id: Uuid,
-    pub username: String,
-    pub email: String,
-    pub role: String,
+/// Group stack members ordered by version and part into versions
+pub fn stack_versions(members: &[MediaItemModel]) -> Vec<StackVersion> {
+    let mut versions: Vec<StackVersion> = Vec::new();
+
+    for member in members {
+        let name = member.stack_version.clone().unwrap_or_default();
+        if versions.last().is_none_or(|version| version.name != name) {
+            let timeline = version_timeline(member, members);
+            versions.push(StackVersion {
+                name,
+                duration: timeline.duration(),
+                parts: timeline
+                    .parts()
+                    .filter_map(|(id, start, duration)| {
+                        let part = members.iter().find(|member| member.id == id)?;
+                        Some(StackPart { id, path: part.path.clone(), start, duration })
+                    })
+                    .collect(),
+            });
+        }
+    }
+    versions
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LoginRequest {
-    pub username: String,
-    pub password: String,
+/// Store a user's position on the timeline of a title, keeping their other
+/// playback settings unless a playback speed is given
+///
+/// The state of a title is kept on its lead item, the first part.
+async fn store_playback(
+    users: &UserRepository,
+    user_id: Uuid,
+    lead: Uuid,
+    timeline: &StackTimeline,
+    position: f64,
+    playback_rate: Option<f32>,
+) -> Result<PlaybackStateModel> {
+    let now = Utc::now();
+    let duration = timeline.duration();
+    let mut state = match users.get_playback_state(user_id, lead).await? {
+        Some(state) => PlaybackStateModel {
+            position_seconds: position,
+            duration_seconds: (duration > 0.0).then_some(duration),
+            updated_at: now,
+            ..state
+        },
+        None => PlaybackStateModel {
+            user_id,
+            media_id: lead,
+            position_seconds: position,
+            duration_seconds: (duration > 0.0).then_some(duration),
+            playback_rate: 1.0,
+            volume: 1.0,
+            is_muted: false,
+            subtitle_track: None,
+            audio_track: None,
+            updated_at: now,
+        },
+    };
+    if let Some(playback_rate) = playback_rate {
+        state.playback_rate = playback_rate;
+    }
+    users.upsert_playback_state(&state).await?;
+    Ok(state)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RegisterRequest {
-    pub username: String,
-    pub email: String,
-    pub password: String,
+/// Load a book with its files in reading order; books whose files are all
+/// gone are not found
+async fn load_book(
+    repository: &MediaRepository,
+    id: Uuid,
+) -> std::result::Result<(AudiobookModel, Vec<MediaItemModel>), StatusCode> {
+    let failed = |e: RustFlixError| {
+        error!("Failed to load audiobook {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let book = repository.get_audiobook(id).await.map_err(failed)?.ok_or(StatusCode::NOT_FOUND)?;
+    let parts = repository.get_audiobook_parts(id).await.map_err(failed)?;
+    if parts.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok((book, parts))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LoginResponse {
-    pub token: String,
-    pub user: AuthUser,
+/// Chapters of a book on the timeline of the whole book
+///
+/// Files with embedded chapters contribute those, offset by the start of
+/// the file; any other file is one chapter named after it.
+pub fn book_chapters(
+    timeline: &StackTimeline,
+    parts: &[MediaItemModel],
+    chapters: &HashMap<Uuid, Vec<MediaChapterModel>>,
+) -> Vec<MediaChapter> {
+    let mut book_chapters = Vec::new();
+    for (id, start, duration) in timeline.parts() {
+        match chapters.get(&id).filter(|chapters| !chapters.is_empty()) {
+            Some(chapters) => book_chapters.extend(chapters.iter().map(|chapter| MediaChapter {
+                index: 0,
+                title: chapter.title.clone(),
+                start_time: start + chapter.start_time,
+                end_time: start + chapter.end_time,
+            })),
+            None => book_chapters.push(MediaChapter {
+                index: 0,
+                title: parts
+                    .iter()
+                    .find(|part| part.id == id)
+                    .and_then(|part| std::path::Path::new(&part.path).file_stem())
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                start_time: start,
+                end_time: start + duration,
+            }),
+        }
+    }
+    for (index, chapter) in book_chapters.iter_mut().enumerate() {
+        chapter.index = index as i32 + 1;
+    }
+    book_chapters
 }
 
-#[derive(Debug, Deserialize)]
-pub struct SearchParams {
-    pub q: Option<String>,
-    pub limit: Option<u32>,
-    pub offset: Option<u32>,
+fn audiobook(book: AudiobookModel) -> Audiobook {
+    Audiobook {
+        id: book.id,
+        title: book.title,
+        author: book.author,
+        narrator: book.narrator,
+        series: book.series,
+        series_part: book.series_part,
+        year: book.year,
+        genre: book.genre,
+        part_count: book.part_count,
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct UpdateMediaRequest {
-    pub title: Option<String>,
+/// Load the media item and details of a comic or e-book; removed books are
+/// not found
+async fn load_book_item(
+    repository: &MediaRepository,
+    id: Uuid,
+) -> std::result::Result<(MediaItemModel, BookModel), StatusCode> {
+    let failed = |e: RustFlixError| {
+        error!("Failed to load book {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let item = repository
+        .get_media_item(id)
+        .await
+        .map_err(failed)?
+        .filter(|item| item.removed_at.is_none())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let book = repository.get_book(id).await.map_err(failed)?.ok_or(StatusCode::NOT_FOUND)?;
+    Ok((item, book))
+}
+
+/// Book entry, titled after its file when it has no title of its own
+fn book(path: &str, book: BookModel) -> Book {
+    let title = book.title.unwrap_or_else(|| {
+        std::path::Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    Book {
+        id: book.media_id,
+        title,
+        author: book.author,
+        series: book.series,
+        series_index: book.series_index,
+        year: book.year,
+        page_count: book.page_count,
+    }
+}
+
+fn reading_progress(progress: ReadingProgressModel) -> ReadingProgress {
+    ReadingProgress {
+        page: progress.page,
+        location: progress.location,
+        progress: progress.progress,
+        is_finished: progress.is_finished,
+        updated_at: progress.updated_at,
+    }
+}
+
+/// Fraction read and whether the book is finished, from the reported
+/// progress; `None` when it is out of range
+///
+/// The fraction of a paged book follows from its page when not given, and
+/// reaching the last page or the end finishes the book unless the reader
+/// says otherwise.
+pub fn resolve_progress(page_count: Option<i32>, payload: &UpdateReadingProgressRequest) -> Option<(f32, bool)> {
+    let page_count = page_count.filter(|count| *count > 0);
+    if payload.page.is_some_and(|page| page == 0 || page_count.is_some_and(|count| page as i64 > count as i64)) {
+        return None;
+    }
+    if payload.progress.is_some_and(|progress| !(0.0..=1.0).contains(&progress)) {
+        return None;
+    }
+
+    let from_page = payload.page.zip(page_count).map(|(page, count)| page as f32 / count as f32);
+    let progress = payload.progress.or(from_page).unwrap_or(0.0);
+    Some((progress, payload.is_finished.unwrap_or(progress >= 1.0)))
+}
+
+/// Resolve a position in a title to the part to resume
+fn library_audit(audit: LibraryAuditModel) -> LibraryAudit {
+    LibraryAudit {
+        id: audit.id,
+        status: audit.status,
+        items_checked: audit.items_checked,
+        issue_count: audit.issue_count,
+        error_message: audit.error_message,
+        started_at: audit.started_at,
+        finished_at: audit.finished_at,
+        cleaned_at: audit.cleaned_at,
+    }
+}
+
+fn audit_issue(issue: AuditIssueModel) -> AuditIssue {
+    let cleanable = IssueKind::from_name(&issue.kind).is_some_and(|kind| kind.can_clean_up());
+    AuditIssue {
+        id: issue.id,
+        kind: issue.kind,
+        library_id: issue.library_id,
+        media_id: issue.media_id,
+        path: issue.path,
+        detail: issue.detail,
+        cleanable,
+        resolved_at: issue.resolved_at,
+    }
+}
+
+fn cleanup_summary(cleanup: AuditCleanup) -> AuditCleanupSummary {
+    AuditCleanupSummary {
+        items_removed: cleanup.items_removed,
+        stacks_deleted: cleanup.stacks_deleted,
+        audiobooks_deleted: cleanup.audiobooks_deleted,
+        image_files_removed: cleanup.image_files_removed,
+        issues_resolved: cleanup.issues_resolved,
+    }
+}
+
+/// Number of issues of each kind, leaving out resolved ones
+pub fn issue_counts(issues: &[AuditIssueModel]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for issue in issues.iter().filter(|issue| issue.resolved_at.is_none()) {
+        *counts.entry(issue.kind.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Position on a book's timeline of a position given within one of its
+/// files, or in the whole book; whole-book positions are kept within its length
+fn book_position(timeline: &StackTimeline, media_id: Option<Uuid>, position: f64) -> Option<f64> {
+    match media_id {
+        Some(media_id) => timeline.position(media_id, position),
+        None => {
+            let duration = timeline.duration();
+            Some(if duration > 0.0 { position.clamp(0.0, duration) } else { position.max(0.0) })
+        }
+    }
+}
+
+fn playback_position(timeline: &StackTimeline, position: f64, updated_at: DateTime<Utc>) -> Option<PlaybackPosition> {
+    let duration = timeline.duration();
+    let position = if duration > 0.0 { position.min(duration) } else { position };
+    let (media_id, offset) = timeline.locate(position)?;
+
+    Some(PlaybackPosition { media_id, offset, position, duration, updated_at })
+}
+
+/// Folders whose extras belong to a media item: its own folder, and for
+/// episodes the show folder above the season folder as well
+pub fn extra_owners(item: &MediaItemModel) -> Vec<String> {
+    let path = std::path::Path::new(&item.path);
+    let depth = if item.media_type == "episode" { 2 } else { 1 };
+
+    path.ancestors()
+        .skip(1)
+        .take(depth)
+        .filter(|dir| dir.parent().is_some())
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Media item response for a stored item; it is titled after its file
+/// until metadata is matched
+pub fn media_item(item: MediaItemModel, chapters: Vec<MediaChapterModel>) -> MediaItem {
+    let title = std::path::Path::new(&item.path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    MediaItem {
+        id: item.id,
+        title: title.clone(),
+        description: None,
+        media_type: item.media_type,
+        file_path: item.path,
+        file_size: item.file_size.max(0) as u64,
+        duration: item.duration.map(|duration| duration.round() as i32),
+        metadata: MediaMetadata {
+            title,
+            description: None,
+            release_date: None,
+            genres: Vec::new(),
+            cast: Vec::new(),
+            crew: Vec::new(),
+            rating: None,
+            poster: None,
+            backdrop: None,
+            trailer: None,
+            imdb_id: None,
+            tmdb_id: None,
+        },
+        created_at: item.created_at.to_rfc3339(),
+        updated_at: item.updated_at.to_rfc3339(),
+        chapters: chapters
+            .into_iter()
+            .map(|chapter| MediaChapter {
+                index: chapter.chapter_index,
+                title: chapter.title,
+                start_time: chapter.start_time,
+                end_time: chapter.end_time,
+            })
+            .collect(),
+    }
+}
+
+fn media_extra(item: MediaItemModel) -> Option<MediaExtra> {
+    let extra_type = ExtraType::from_name(item.extra_type.as_deref()?)?;
+    let title = std::path::Path::new(&item.path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Some(MediaExtra {
+        id: item.id,
+        title,
+        extra_type,
+        path: item.path,
+        duration: item.duration,
+    })
+}
+
+/// Start and end of a year, month or day; a month needs a year and a day
+/// needs a month
+pub fn date_range(year: Option<i32>, month: Option<u32>, day: Option<u32>) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let (from, to) = match (year, month, day) {
+        (Some(year), None, None) => (NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?),
+        (Some(year), Some(month), None) => {
+            let from = NaiveDate::from_ymd_opt(year, month, 1)?;
+            (from, from.checked_add_months(chrono::Months::new(1))?)
+        }
+        (Some(year), Some(month), Some(day)) => {
+            let from = NaiveDate::from_ymd_opt(year, month, day)?;
+            (from, from.succ_opt()?)
+        }
+        _ => return None,
+    };
+    Some((from.and_hms_opt(0, 0, 0)?, to.and_hms_opt(0, 0, 0)?))
+}
+
+/// Unit the timeline is counted in below the given year and month
+pub fn timeline_unit(year: Option<i32>, month: Option<u32>) -> Option<&'static str> {
+    match (year, month) {
+        (None, None) => Some("year"),
+        (Some(_), None) => Some("month"),
+        (Some(_), Some(_)) => Some("day"),
+        (None, Some(_)) => None,
+    }
+}
+
+fn photo_date(period: NaiveDateTime, unit: &str, count: i64) -> PhotoDate {
+    PhotoDate {
+        year: period.year(),
+        month: (unit != "year").then(|| period.month()),
+        day: (unit == "day").then(|| period.day()),
+        count,
+    }
+}
+
+fn photo_entry(path: String, photo: PhotoModel) -> Photo {
+    let name = std::path::Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Photo {
+        id: photo.media_id,
+        name,
+        path,
+        taken_at: photo.taken_at,
+        has_exif_date: photo.has_exif_date,
+        width: photo.width,
+        height: photo.height,
+        camera_make: photo.camera_make,
+        camera_model: photo.camera_model,
+        lens_model: photo.lens_model,
+        orientation: photo.orientation,
+        latitude: photo.latitude,
+        longitude: photo.longitude,
+        altitude: photo.altitude,
+    }
+}
+
+/// Split the photos below a folder into its own photos and subfolders
+pub fn folder_listing(folder: &str, photos: Vec<(String, PhotoModel)>) -> PhotoFolderListing {
+    let folder_path = std::path::Path::new(folder);
+    let mut folders: Vec<PhotoFolder> = Vec::new();
+    let mut entries = Vec::new();
+
+    for (path, photo) in photos {
+        let Ok(relative) = std::path::Path::new(&path).strip_prefix(folder_path) else {
+            continue;
+        };
+        let mut components = relative.components();
+        let first = components.next();
+        match (first, components.next()) {
+            (Some(_), None) => entries.push(photo_entry(path, photo)),
+            (Some(subfolder), Some(_)) => {
+                let name = subfolder.as_os_str().to_string_lossy().into_owned();
+                match folders.iter_mut().find(|folder| folder.name == name) {
+                    Some(folder) => folder.photo_count += 1,
+                    None => folders.push(PhotoFolder {
+                        path: folder_path.join(&name).to_string_lossy().into_owned(),
+                        name,
+                        photo_count: 1,
+                    }),
+                }
+            }
+            _ => {}
+        }
+    }
+    folders.sort_by(|a, b| a.name.cmp(&b.name));
+
+    PhotoFolderListing {
+        path: Some(folder.to_string()),
+        folders,
+        photos: entries,
+    }
+}
+
+/// Load the images of a media item with their sizes
+async fn load_images(repository: &MediaRepository, id: Uuid) -> Result<Vec<MediaImage>> {
+    let mut images = Vec::new();
+    for MediaImageModel { kind, hash, .. } in repository.get_media_images(id).await? {
+        let Some(image) = repository.get_image(&hash).await? else {
+            continue;
+        };
+        let variants = repository.get_image_variants(&hash).await?;
+        images.push(MediaImage {
+            kind,
+            width: image.width,
+            height: image.height,
+            blurhash: image.blurhash,
+            sizes: image_sizes(&variants),
+        });
+    }
+    Ok(images)
+}
+
+/// Send the variant of a media item's image best matching a width and the
+/// formats accepted by the client
+async fn serve_image(
+    repository: &MediaRepository,
+    id: Uuid,
+    kind: &str,
+    width: u32,
+    headers: &HeaderMap,
+) -> std::result::Result<impl IntoResponse, StatusCode> {
+    let failed = |e: RustFlixError| {
+        error!("Failed to load {} image of {}: {}", kind, id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let image = repository
+        .get_media_images(id)
+        .await
+        .map_err(failed)?
+        .into_iter()
+        .find(|image| image.kind == kind)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let variants = repository.get_image_variants(&image.hash).await.map_err(failed)?;
+    let accept = headers.get(header::ACCEPT).and_then(|accept| accept.to_str().ok());
+    let variant = pick_variant(&variants, width, preferred_format(accept)).ok_or(StatusCode::NOT_FOUND)?;
+
+    let data = tokio::fs::read(&variant.path).await.map_err(|e| {
+        error!("Failed to read image {}: {}", variant.path, e);
+        StatusCode::NOT_FOUND
+    })?;
+    let content_type = if variant.format == "webp" { "image/webp" } else { "image/jpeg" };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            // Variants are content-addressed, so a URL's image only changes
+            // when the artwork is replaced
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+            (header::VARY, "Accept"),
+        ],
+        data,
+    ))
+}
+
+/// Variant format to serve for an `Accept` header
+pub fn preferred_format(accept: Option<&str>) -> &'static str {
+    if accept.is_some_and(|accept| accept.contains("image/webp")) {
+        "webp"
+    } else {
+        "jpeg"
+    }
+}
+
+/// Smallest variant at least `width` wide, or the largest one, preferring
+/// the given format
+pub fn pick_variant<'a>(variants: &'a [ImageVariantModel], width: u32, format: &str) -> Option<&'a ImageVariantModel> {
+    let available = if variants.iter().any(|variant| variant.format == format) {
+        format
+    } else {
+        "jpeg"
+    };
+    let candidates = variants.iter().filter(|variant| variant.format == available);
+    candidates
+        .clone()
+        .filter(|variant| variant.width as u32 >= width)
+        .min_by_key(|variant| variant.width)
+        .or_else(|| candidates.max_by_key(|variant| variant.width))
+}
+
+/// Distinct sizes of an image's variants, smallest first
+pub fn image_sizes(variants: &[ImageVariantModel]) -> Vec<ImageSize> {
+    let mut sizes = variants
+        .iter()
+        .map(|variant| (variant.width, variant.height))
+        .collect::<Vec<_>>();
+    sizes.sort();
+    sizes.dedup();
+    sizes.into_iter().map(|(width, height)| ImageSize { width, height }).collect()
+}
+
+/// Photo with its EXIF details
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Photo {
+    pub id: Uuid,
+    pub name: String,
+    pub path: String,
+    /// Camera's local time, or the file modification time without EXIF date
+    #[serde(rename = "takenAt")]
+    pub taken_at: NaiveDateTime,
+    #[serde(rename = "hasExifDate")]
+    pub has_exif_date: bool,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    #[serde(rename = "cameraMake")]
+    pub camera_make: Option<String>,
+    #[serde(rename = "cameraModel")]
+    pub camera_model: Option<String>,
+    #[serde(rename = "lensModel")]
+    pub lens_model: Option<String>,
+    pub orientation: Option<i32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PhotoDetails {
+    #[serde(flatten)]
+    pub photo: Photo,
+    /// Placeholder to show while a thumbnail loads
+    pub blurhash: Option<String>,
+    pub thumbnails: Vec<PhotoThumbnail>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PhotoThumbnail {
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Number of photos taken in a year, month or day
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PhotoDate {
+    pub year: i32,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PhotoFolder {
+    pub name: String,
+    pub path: String,
+    /// Photos in the folder and its subfolders
+    #[serde(rename = "photoCount")]
+    pub photo_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PhotoFolderListing {
+    /// Folder listed, absent for the list of photo libraries
+    pub path: Option<String>,
+    pub folders: Vec<PhotoFolder>,
+    pub photos: Vec<Photo>,
+}
+
+/// Poster, backdrop, thumbnail or photo image of a media item
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MediaImage {
+    pub kind: String,
+    pub width: i32,
+    pub height: i32,
+    /// Placeholder to show while a variant loads
+    pub blurhash: String,
+    /// Sizes variants are available at, each as JPEG and WebP
+    pub sizes: Vec<ImageSize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageSize {
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Extra video attached to a movie or show
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MediaExtra {
+    pub id: Uuid,
+    pub title: String,
+    #[serde(rename = "extraType")]
+    pub extra_type: ExtraType,
+    pub path: String,
+    pub duration: Option<f64>,
+}
+
+/// Title grouping the parts and versions of a movie
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MediaStack {
+    /// Stack id, absent for a single file
+    pub id: Option<Uuid>,
+    pub title: Option<String>,
+    pub year: Option<i32>,
+    pub versions: Vec<StackVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StackVersion {
+    pub name: String,
+    pub duration: f64,
+    pub parts: Vec<StackPart>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StackPart {
+    pub id: Uuid,
+    pub path: String,
+    /// Start of the part on the version's timeline, in seconds
+    pub start: f64,
+    pub duration: f64,
+}
+
+/// Book of an audiobook library
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Audiobook {
+    pub id: Uuid,
+    pub title: String,
+    pub author: Option<String>,
+    pub narrator: Option<String>,
+    pub series: Option<String>,
+    /// Position in the series, e.g. "3" or "2.5"
+    #[serde(rename = "seriesPart")]
+    pub series_part: Option<String>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    /// Number of files the book is made of
+    #[serde(rename = "partCount")]
+    pub part_count: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AudiobookDetails {
+    #[serde(flatten)]
+    pub book: Audiobook,
+    pub duration: f64,
+    /// Files in reading order, placed on the timeline of the whole book
+    pub parts: Vec<StackPart>,
+    /// Chapters with times on the timeline of the whole book
+    pub chapters: Vec<MediaChapter>,
+}
+
+/// Position in a book with the user's playback speed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AudiobookPlayback {
+    #[serde(flatten)]
+    pub position: PlaybackPosition,
+    #[serde(rename = "playbackRate")]
+    pub playback_rate: f32,
+}
+
+/// Comic or e-book of a book library
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Book {
+    pub id: Uuid,
+    pub title: String,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    /// Position in the series, e.g. "3" or "2.5"
+    #[serde(rename = "seriesIndex")]
+    pub series_index: Option<String>,
+    pub year: Option<i32>,
+    /// Pages of comics and PDF documents
+    #[serde(rename = "pageCount")]
+    pub page_count: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookDetails {
+    #[serde(flatten)]
+    pub book: Book,
+    /// File format, e.g. "cbz" or "epub"
+    pub format: String,
+    pub publisher: Option<String>,
+    pub language: Option<String>,
+    pub description: Option<String>,
+    /// Cover, as the poster image
+    pub images: Vec<MediaImage>,
+}
+
+/// Position of a user in a book
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadingProgress {
+    /// Page of comics and PDF documents, from 1
+    pub page: Option<i32>,
+    /// Position within an EPUB as recorded by the reader, such as a CFI
+    pub location: Option<String>,
+    /// Fraction of the book read, from 0 to 1
+    pub progress: f32,
+    #[serde(rename = "isFinished")]
+    pub is_finished: bool,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Position in a title with the part and offset to resume at
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaybackPosition {
+    #[serde(rename = "mediaId")]
+    pub media_id: Uuid,
+    /// Offset within the part, in seconds
+    pub offset: f64,
+    /// Position in the whole title, in seconds
+    pub position: f64,
+    pub duration: f64,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Run of the library audit
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryAudit {
+    pub id: Uuid,
+    /// "running", "completed" or "failed"
+    pub status: String,
+    #[serde(rename = "itemsChecked")]
+    pub items_checked: i32,
+    #[serde(rename = "issueCount")]
+    pub issue_count: i32,
+    #[serde(rename = "errorMessage")]
+    pub error_message: Option<String>,
+    #[serde(rename = "startedAt")]
+    pub started_at: DateTime<Utc>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(rename = "cleanedAt")]
+    pub cleaned_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryAuditDetails {
+    #[serde(flatten)]
+    pub audit: LibraryAudit,
+    /// Unresolved issues by kind
+    pub counts: BTreeMap<String, usize>,
+    pub issues: Vec<AuditIssue>,
+}
+
+/// Problem found by a library audit
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditIssue {
+    pub id: Uuid,
+    /// e.g. "missing_file", "probe_failed" or "orphaned_stack"
+    pub kind: String,
+    #[serde(rename = "libraryId")]
+    pub library_id: Option<Uuid>,
+    #[serde(rename = "mediaId")]
+    pub media_id: Option<Uuid>,
+    pub path: Option<String>,
+    pub detail: Option<String>,
+    /// Whether cleanup acts on the issue
+    pub cleanable: bool,
+    #[serde(rename = "resolvedAt")]
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditCleanupSummary {
+    #[serde(rename = "itemsRemoved")]
+    pub items_removed: u64,
+    #[serde(rename = "stacksDeleted")]
+    pub stacks_deleted: usize,
+    #[serde(rename = "audiobooksDeleted")]
+    pub audiobooks_deleted: u64,
+    #[serde(rename = "imageFilesRemoved")]
+    pub image_files_removed: usize,
+    #[serde(rename = "issuesResolved")]
+    pub issues_resolved: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    pub confirmed: bool,
+    pub items: Vec<DuplicateItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateItem {
+    pub id: Uuid,
+    pub path: String,
+    #[serde(rename = "fileSize")]
+    pub file_size: i64,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaginatedResponse<T> {
+    pub data: Vec<T>,
+    pub pagination: PaginationInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiResponse<T> {
+    pub data: T,
+    pub success: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaginationInfo {
+    pub page: u32,
+    pub limit: u32,
+    pub total: u32,
+    pub total_pages: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Library {
+    pub id: Uuid,
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthUser {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub role: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterRequest {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub user: AuthUser,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    pub q: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaybackParams {
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListParams {
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartAuditParams {
+    /// Apply the cleanup actions once the audit completes
+    pub cleanup: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditIssueParams {
+    pub kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PhotoDateParams {
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PhotoFolderParams {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThumbnailParams {
+    pub width: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePlaybackRequest {
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+    /// Offset within the media item, in seconds
+    pub position: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAudiobookPlaybackRequest {
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+    /// File the position is given in, or none for a position in the whole book
+    #[serde(rename = "mediaId")]
+    pub media_id: Option<Uuid>,
+    /// Position in seconds
+    pub position: f64,
+    /// Playback speed, kept as it was when not given
+    #[serde(rename = "playbackRate")]
+    pub playback_rate: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateReadingProgressRequest {
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+    /// Page of a comic or PDF document, from 1
+    pub page: Option<u32>,
+    /// Position within an EPUB, such as a CFI
+    pub location: Option<String>,
+    /// Fraction of the book read, from 0 to 1; derived from the page when
+    /// not given
+    pub progress: Option<f32>,
+    #[serde(rename = "isFinished")]
+    pub is_finished: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMediaRequest {
+    pub title: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -799,6 +2628,17 @@ pub struct TranscodeStatus {
     pub progress: f32,
 }
 
+fn transcode_status_name(status: TranscodingStatus) -> &'static str {
+    match status {
+        TranscodingStatus::Queued => "queued",
+        TranscodingStatus::Starting => "starting",
+        TranscodingStatus::Running => "running",
+        TranscodingStatus::Completed => "completed",
+        TranscodingStatus::Failed => "failed",
+        TranscodingStatus::Cancelled => "cancelled",
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PlaybackState {
     pub id: Uuid,
@@ -821,3 +2661,408 @@ pub struct UserPreferences {
     pub language: Option<String>,
     pub autoplay: Option<bool>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(path: &str, file_hash: Option<&str>, full_hash: Option<&str>) -> MediaItemModel {
+        let now = Utc::now();
+        MediaItemModel {
+            id: Uuid::new_v4(),
+            path: path.to_string(),
+            file_size: 100,
+            file_hash: file_hash.map(str::to_string),
+            media_type: "movie".to_string(),
+            format: "mkv".to_string(),
+            duration: None,
+            width: None,
+            height: None,
+            bitrate: None,
+            file_modified: None,
+            removed_at: None,
+            full_hash: full_hash.map(str::to_string),
+            created_at: now,
+            updated_at: now,
+            stack_id: None,
+            stack_version: None,
+            stack_part: None,
+            extra_type: None,
+            extra_owner: None,
+        }
+    }
+
+    fn part(path: &str, version: &str, part: i32, duration: f64) -> MediaItemModel {
+        MediaItemModel {
+            stack_id: Some(Uuid::nil()),
+            stack_version: Some(version.to_string()),
+            stack_part: Some(part),
+            duration: Some(duration),
+            ..item(path, None, None)
+        }
+    }
+
+    #[test]
+    fn test_extra_owners() {
+        let movie = item("/movies/Movie (2001)/Movie (2001).mkv", None, None);
+        assert_eq!(extra_owners(&movie), vec!["/movies/Movie (2001)"]);
+
+        let episode = MediaItemModel {
+            media_type: "episode".to_string(),
+            ..item("/tv/Show/Season 1/Show S01E01.mkv", None, None)
+        };
+        assert_eq!(extra_owners(&episode), vec!["/tv/Show/Season 1", "/tv/Show"]);
+    }
+
+    #[test]
+    fn test_media_extra() {
+        let trailer = MediaItemModel {
+            extra_type: Some("trailer".to_string()),
+            extra_owner: Some("/movies/Movie (2001)".to_string()),
+            ..item("/movies/Movie (2001)/Trailers/Teaser.mkv", None, None)
+        };
+        let extra = media_extra(trailer).unwrap();
+        assert_eq!(extra.title, "Teaser");
+        assert_eq!(extra.extra_type, ExtraType::Trailer);
+
+        assert!(media_extra(item("/movies/Movie (2001)/Movie (2001).mkv", None, None)).is_none());
+    }
+
+    #[test]
+    fn test_media_item_chapters() {
+        let movie = MediaItemModel {
+            duration: Some(5399.6),
+            ..item("/movies/Movie (2001)/Movie (2001).mkv", None, None)
+        };
+        let chapter = |index: i32, start_time: f64, end_time: f64, title: &str| MediaChapterModel {
+            media_id: movie.id,
+            chapter_index: index,
+            start_time,
+            end_time,
+            title: title.to_string(),
+        };
+        let chapters = vec![chapter(1, 0.0, 600.0, "Opening"), chapter(2, 600.0, 5399.6, "Chapter 2")];
+
+        let response = media_item(movie, chapters);
+        assert_eq!(response.title, "Movie (2001)");
+        assert_eq!(response.duration, Some(5400));
+        assert_eq!(response.chapters.len(), 2);
+        assert_eq!(
+            response.chapters[0],
+            MediaChapter { index: 1, title: "Opening".to_string(), start_time: 0.0, end_time: 600.0 }
+        );
+
+        let json = serde_json::to_value(&response.chapters[1]).unwrap();
+        assert_eq!(json["startTime"], 600.0);
+        assert_eq!(json["endTime"], 5399.6);
+    }
+
+    #[test]
+    fn test_stack_versions() {
+        let members = vec![
+            part("/m/movie - 1080p.mkv", "1080p", 1, 7200.0),
+            part("/m/movie - cut - cd1.mkv", "Director's Cut", 1, 3600.0),
+            part("/m/movie - cut - cd2.mkv", "Director's Cut", 2, 4000.0),
+        ];
+
+        let versions = stack_versions(&members);
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].name, "1080p");
+        assert_eq!(versions[0].duration, 7200.0);
+        assert_eq!(versions[1].duration, 7600.0);
+        let starts = versions[1].parts.iter().map(|part| part.start).collect::<Vec<_>>();
+        assert_eq!(starts, vec![0.0, 3600.0]);
+    }
+
+    #[test]
+    fn test_playback_position_spans_parts() {
+        let members = vec![
+            part("/m/movie - cd1.mkv", "", 1, 3600.0),
+            part("/m/movie - cd2.mkv", "", 2, 3000.0),
+        ];
+        let timeline = version_timeline(&members[1], &members);
+
+        // 10 minutes into the second part is 70 minutes into the title
+        let position = timeline.position(members[1].id, 600.0).unwrap();
+        assert_eq!(position, 4200.0);
+
+        let resume = playback_position(&timeline, position, Utc::now()).unwrap();
+        assert_eq!(resume.media_id, members[1].id);
+        assert_eq!(resume.offset, 600.0);
+        assert_eq!(resume.duration, 6600.0);
+
+        let end = playback_position(&timeline, 9000.0, Utc::now()).unwrap();
+        assert_eq!((end.media_id, end.offset, end.position), (members[1].id, 3000.0, 6600.0));
+    }
+
+    #[test]
+    fn test_book_chapters() {
+        let parts = vec![
+            MediaItemModel { duration: Some(1800.0), ..item("/books/Book/Chapter 1.mp3", None, None) },
+            MediaItemModel { duration: Some(1200.0), ..item("/books/Book/Chapter 2.m4b", None, None) },
+        ];
+        let timeline = version_timeline(&parts[0], &parts);
+        let chapter = |start_time: f64, end_time: f64, title: &str| MediaChapterModel {
+            media_id: parts[1].id,
+            chapter_index: 1,
+            start_time,
+            end_time,
+            title: title.to_string(),
+        };
+        let chapters = HashMap::from([
+            (parts[0].id, Vec::new()),
+            (parts[1].id, vec![chapter(0.0, 600.0, "Part A"), chapter(600.0, 1200.0, "Part B")]),
+        ]);
+
+        let book = book_chapters(&timeline, &parts, &chapters);
+        let summary = book
+            .iter()
+            .map(|chapter| (chapter.index, chapter.title.as_str(), chapter.start_time, chapter.end_time))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            (1, "Chapter 1", 0.0, 1800.0),
+            (2, "Part A", 1800.0, 2400.0),
+            (3, "Part B", 2400.0, 3000.0),
+        ]);
+
+        // Resuming 5 minutes into the second chapter of the second file
+        let resume = playback_position(&timeline, 2700.0, Utc::now()).unwrap();
+        assert_eq!((resume.media_id, resume.offset, resume.duration), (parts[1].id, 900.0, 3000.0));
+        let playback = serde_json::to_value(AudiobookPlayback { position: resume, playback_rate: 1.5 }).unwrap();
+        assert_eq!(playback["playbackRate"], 1.5);
+        assert_eq!(playback["position"], 2700.0);
+    }
+
+    #[tokio::test]
+    async fn test_resume_mp3_book() {
+        // Constant bitrate MPEG-1 layer III frames: 128 kbit/s at 44.1 kHz
+        let mp3 = |frames: usize| {
+            let mut data = Vec::new();
+            for _ in 0..frames {
+                data.extend([0xff, 0xfb, 0x90, 0x40]);
+                data.extend([0u8; 413]);
+            }
+            data
+        };
+        let dir = tempfile::TempDir::new().unwrap();
+        let analyzer = rustflix_media_library::MediaAnalyzer::new().unwrap();
+        let mut parts = Vec::new();
+        for (name, frames) in [("Part 1.mp3", 1200), ("Part 2.mp3", 2400)] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, mp3(frames)).unwrap();
+            let info = analyzer.analyze_file(&path).await.unwrap();
+            parts.push(MediaItemModel { duration: info.duration, ..item(&path.to_string_lossy(), None, None) });
+        }
+        let first = parts[0].duration.unwrap();
+        assert!((first - 1200.0 * 417.0 * 8.0 / 128_000.0).abs() < 1e-6);
+
+        // Half a minute into the second file
+        let timeline = version_timeline(&parts[0], &parts);
+        let position = book_position(&timeline, None, first + 30.0).unwrap();
+        let resume = playback_position(&timeline, position, Utc::now()).unwrap();
+        assert_eq!(resume.media_id, parts[1].id);
+        assert!((resume.offset - 30.0).abs() < 1e-6);
+        assert_eq!(book_position(&timeline, Some(parts[1].id), 30.0), Some(position));
+
+        // Whole-book positions past the end stop at the end of the last file
+        assert_eq!(book_position(&timeline, None, 1e9), Some(timeline.duration()));
+        assert_eq!(book_position(&timeline, None, -5.0), Some(0.0));
+        assert_eq!(book_position(&timeline, Some(Uuid::new_v4()), 30.0), None);
+    }
+
+    #[test]
+    fn test_resolve_progress() {
+        let request = |page: Option<u32>, progress: Option<f32>, is_finished: Option<bool>| UpdateReadingProgressRequest {
+            user_id: Uuid::new_v4(),
+            page,
+            location: None,
+            progress,
+            is_finished,
+        };
+
+        assert_eq!(resolve_progress(Some(20), &request(Some(5), None, None)), Some((0.25, false)));
+        assert_eq!(resolve_progress(Some(20), &request(Some(20), None, None)), Some((1.0, true)));
+        assert_eq!(resolve_progress(Some(20), &request(Some(20), None, Some(false))), Some((1.0, false)));
+        assert_eq!(resolve_progress(Some(20), &request(Some(21), None, None)), None);
+        assert_eq!(resolve_progress(Some(20), &request(Some(0), None, None)), None);
+        // EPUBs have no pages, their readers report the fraction read
+        assert_eq!(resolve_progress(None, &request(None, Some(0.4), None)), Some((0.4, false)));
+        assert_eq!(resolve_progress(None, &request(None, Some(1.5), None)), None);
+
+        let book = book("/books/Comics/Issue 12.cbz", BookModel {
+            media_id: Uuid::new_v4(),
+            title: None,
+            author: None,
+            series: Some("Series".to_string()),
+            series_index: Some("12".to_string()),
+            publisher: None,
+            language: None,
+            description: None,
+            year: None,
+            page_count: Some(24),
+            updated_at: Utc::now(),
+        });
+        let value = serde_json::to_value(&book).unwrap();
+        assert_eq!(value["title"], "Issue 12");
+        assert_eq!(value["seriesIndex"], "12");
+        assert_eq!(value["pageCount"], 24);
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let items = vec![
+            item("/a/one.mkv", Some("aaa"), Some("full")),
+            item("/b/one.mkv", Some("aaa"), Some("full")),
+            item("/a/two.mkv", Some("bbb"), Some("full")),
+            item("/b/two.mkv", Some("bbb"), None),
+            item("/a/single.mkv", Some("ccc"), None),
+            item("/a/unhashed.mkv", None, None),
+        ];
+
+        let groups = group_duplicates(items);
+        assert_eq!(groups.len(), 2);
+
+        assert_eq!(groups[0].hash, "aaa");
+        assert!(groups[0].confirmed);
+        let paths = groups[0].items.iter().map(|item| item.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["/a/one.mkv", "/b/one.mkv"]);
+
+        assert_eq!(groups[1].hash, "bbb");
+        assert!(!groups[1].confirmed);
+    }
+
+    fn photo(path: &str) -> (String, PhotoModel) {
+        let photo = PhotoModel {
+            media_id: Uuid::new_v4(),
+            taken_at: NaiveDate::from_ymd_opt(2021, 7, 14).unwrap().and_hms_opt(18, 30, 0).unwrap(),
+            has_exif_date: true,
+            width: Some(4000),
+            height: Some(3000),
+            camera_make: None,
+            camera_model: None,
+            lens_model: None,
+            orientation: None,
+            latitude: None,
+            longitude: None,
+            altitude: None,
+            updated_at: Utc::now(),
+        };
+        (path.to_string(), photo)
+    }
+
+    #[test]
+    fn test_date_range() {
+        let at = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(0, 0, 0).unwrap();
+
+        assert_eq!(date_range(Some(2021), None, None), Some((at(2021, 1, 1), at(2022, 1, 1))));
+        assert_eq!(date_range(Some(2021), Some(12), None), Some((at(2021, 12, 1), at(2022, 1, 1))));
+        assert_eq!(date_range(Some(2024), Some(2), Some(29)), Some((at(2024, 2, 29), at(2024, 3, 1))));
+        assert_eq!(date_range(Some(2021), Some(2), Some(29)), None);
+        assert_eq!(date_range(None, Some(2), None), None);
+        assert_eq!(date_range(Some(2021), None, Some(3)), None);
+
+        assert_eq!(timeline_unit(None, None), Some("year"));
+        assert_eq!(timeline_unit(Some(2021), None), Some("month"));
+        assert_eq!(timeline_unit(Some(2021), Some(7)), Some("day"));
+        assert_eq!(timeline_unit(None, Some(7)), None);
+
+        let date = photo_date(at(2021, 7, 1), "month", 12);
+        assert_eq!((date.year, date.month, date.day, date.count), (2021, Some(7), None, 12));
+    }
+
+    #[test]
+    fn test_folder_listing() {
+        let photos = vec![
+            photo("/photos/2021/beach.jpg"),
+            photo("/photos/2021/Summer/one.jpg"),
+            photo("/photos/2021/Summer/Day 2/two.jpg"),
+            photo("/photos/2021/Autumn/three.jpg"),
+            photo("/photos/2021-other/four.jpg"),
+        ];
+
+        let listing = folder_listing("/photos/2021", photos);
+        assert_eq!(listing.path.as_deref(), Some("/photos/2021"));
+        assert_eq!(listing.photos.len(), 1);
+        assert_eq!(listing.photos[0].name, "beach.jpg");
+        let folders = listing
+            .folders
+            .iter()
+            .map(|folder| (folder.name.as_str(), folder.path.as_str(), folder.photo_count))
+            .collect::<Vec<_>>();
+        assert_eq!(folders, vec![
+            ("Autumn", "/photos/2021/Autumn", 1),
+            ("Summer", "/photos/2021/Summer", 2),
+        ]);
+    }
+
+    fn variant(width: i32, height: i32, format: &str) -> ImageVariantModel {
+        ImageVariantModel {
+            hash: "ab12".to_string(),
+            width,
+            height,
+            format: format.to_string(),
+            path: format!("{}x{}.{}", width, height, format),
+        }
+    }
+
+    #[test]
+    fn test_pick_variant() {
+        let variants = [(320, 240), (640, 480), (1280, 960)]
+            .into_iter()
+            .flat_map(|(width, height)| [variant(width, height, "jpeg"), variant(width, height, "webp")])
+            .collect::<Vec<_>>();
+
+        assert_eq!(pick_variant(&variants, 0, "jpeg").unwrap().path, "320x240.jpeg");
+        assert_eq!(pick_variant(&variants, 500, "webp").unwrap().path, "640x480.webp");
+        assert_eq!(pick_variant(&variants, 4000, "jpeg").unwrap().width, 1280);
+        assert!(pick_variant(&[], 100, "jpeg").is_none());
+
+        // JPEG is served when no WebP variant exists
+        let jpeg_only = [variant(320, 240, "jpeg")];
+        assert_eq!(pick_variant(&jpeg_only, 0, "webp").unwrap().format, "jpeg");
+    }
+
+    #[test]
+    fn test_issue_counts() {
+        let issue = |kind: &str, resolved: bool| AuditIssueModel {
+            id: Uuid::new_v4(),
+            audit_id: Uuid::nil(),
+            kind: kind.to_string(),
+            library_id: None,
+            media_id: None,
+            row_id: None,
+            path: None,
+            detail: None,
+            resolved_at: resolved.then(Utc::now),
+        };
+        let issues = [
+            issue("missing_file", false),
+            issue("missing_file", false),
+            issue("missing_file", true),
+            issue("probe_failed", false),
+        ];
+        let counts = issue_counts(&issues);
+
+        assert_eq!(counts.get("missing_file"), Some(&2));
+        assert_eq!(counts.get("probe_failed"), Some(&1));
+        assert_eq!(counts.len(), 2);
+        assert!(audit_issue(issues[0].clone()).cleanable);
+        assert!(!audit_issue(issues[3].clone()).cleanable);
+    }
+
+    #[test]
+    fn test_preferred_format() {
+        assert_eq!(preferred_format(Some("image/avif,image/webp,*/*")), "webp");
+        assert_eq!(preferred_format(Some("image/*")), "jpeg");
+        assert_eq!(preferred_format(None), "jpeg");
+    }
+
+    #[test]
+    fn test_image_sizes() {
+        let variants = [variant(640, 480, "webp"), variant(320, 240, "jpeg"), variant(640, 480, "jpeg")];
+        assert_eq!(
+            image_sizes(&variants),
+            vec![ImageSize { width: 320, height: 240 }, ImageSize { width: 640, height: 480 }]
+        );
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! REST and WebSocket API layer for the RustFlix media server.
 
+pub mod direct_play;
 pub mod handlers;
 pub mod middleware;
 pub mod websocket;
@@ -10,11 +11,14 @@ pub mod auth;
 // Re-export commonly used types
 pub mod routes;
 pub use routes::create_router;
-pub use handlers::{MediaHandler, UserHandler, StreamHandler, AuthHandler};
+pub use handlers::{MediaHandler, PhotoHandler, UserHandler, StreamHandler, AuthHandler};
 pub use websocket::WebSocketHandler;
 
 use rustflix_core::{Result, RustFlixError};
-use axum::Router;
+use rustflix_database::DatabaseService;
+use rustflix_media_library::{LibraryAuditor, LibraryStorages, MediaLibraryService};
+use rustflix_streaming::StreamingService;
+use axum::{Extension, Router};
 
 /// API service for handling HTTP requests
 #[derive(Debug, Clone)]
@@ -30,6 +34,43 @@ impl ApiService {
         Ok(Self { router })
     }
 
+    /// Create an API service whose handlers can reach the database
+    pub fn with_database(database: &DatabaseService) -> Result<Self> {
+        let router = create_router()?
+            .layer(Extension(database.media_repo.clone()))
+            .layer(Extension(database.user_repo.clone()));
+
+        Ok(Self { router })
+    }
+
+    /// Let the admin handlers run library audits
+    pub fn with_auditor(self, auditor: LibraryAuditor) -> Self {
+        Self {
+            router: self.router.layer(Extension(auditor)),
+        }
+    }
+
+    /// Let the library handlers start and cancel scans
+    pub fn with_media_library(self, media_library: MediaLibraryService) -> Self {
+        Self {
+            router: self.router.layer(Extension(media_library)),
+        }
+    }
+
+    /// Let direct play read files from the storage of their library
+    pub fn with_storages(self, storages: LibraryStorages) -> Self {
+        Self {
+            router: self.router.layer(Extension(storages)),
+        }
+    }
+
+    /// Let the stream handlers run transcoding jobs
+    pub fn with_streaming(self, streaming: StreamingService) -> Self {
+        Self {
+            router: self.router.layer(Extension(streaming)),
+        }
+    }
+
     /// Get the router
     pub fn router(&self) -> Router {
         self.router.clone()
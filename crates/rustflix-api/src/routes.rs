@@ -4,17 +4,19 @@ use rustflix_core::Result;
 use axum::{
     routing::{get, post, put, delete, patch},
     Router,
-    http::{HeaderValue, Method},
+    http::{header, HeaderValue, Method},
 };
 use tower_http::cors::{CorsLayer, Any};
-use crate::handlers::{MediaHandler, UserHandler, StreamHandler, AuthHandler};
+use crate::handlers::{AudiobookHandler, AuditHandler, BookHandler, MediaHandler, PhotoHandler, UserHandler, StreamHandler, AuthHandler};
 
 /// Create the main API router
 pub fn create_router() -> Result<Router> {
     let cors = CorsLayer::new()
         .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
-        .allow_headers(Any);
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::HEAD, Method::OPTIONS])
+        .allow_headers(Any)
+        // Players reading ranges need to see how they were answered
+        .expose_headers([header::ACCEPT_RANGES, header::CONTENT_RANGE, header::CONTENT_LENGTH, header::ETAG]);
 
     let router = Router::new()
         // Health check
@@ -27,11 +29,45 @@ pub fn create_router() -> Result<Router> {
         .route("/api/v1/media/:id", delete(MediaHandler::delete_media))
         .route("/api/v1/media/search", get(MediaHandler::search_media))
         .route("/api/v1/media/genres", get(MediaHandler::get_genres))
+        .route("/api/v1/media/:id/stack", get(MediaHandler::get_stack))
+        .route("/api/v1/media/:id/extras", get(MediaHandler::get_extras))
+        .route("/api/v1/media/:id/playback", get(MediaHandler::get_playback))
+        .route("/api/v1/media/:id/playback", put(MediaHandler::update_playback))
+        .route("/api/v1/media/:id/images", get(MediaHandler::get_images))
+        .route("/api/v1/media/:id/images/:kind", get(MediaHandler::get_image))
         
+        // Photo routes
+        .route("/api/v1/photos", get(PhotoHandler::list_photos))
+        .route("/api/v1/photos/timeline", get(PhotoHandler::get_timeline))
+        .route("/api/v1/photos/folders", get(PhotoHandler::browse_folder))
+        .route("/api/v1/photos/:id", get(PhotoHandler::get_photo))
+        .route("/api/v1/photos/:id/thumbnail", get(PhotoHandler::get_thumbnail))
+
+        // Audiobook routes
+        .route("/api/v1/audiobooks", get(AudiobookHandler::list_audiobooks))
+        .route("/api/v1/audiobooks/:id", get(AudiobookHandler::get_audiobook))
+        .route("/api/v1/audiobooks/:id/playback", get(AudiobookHandler::get_playback))
+        .route("/api/v1/audiobooks/:id/playback", put(AudiobookHandler::update_playback))
+
+        // Book routes
+        .route("/api/v1/books", get(BookHandler::list_books))
+        .route("/api/v1/books/:id", get(BookHandler::get_book))
+        .route("/api/v1/books/:id/pages/:page", get(BookHandler::get_page))
+        .route("/api/v1/books/:id/progress", get(BookHandler::get_progress))
+        .route("/api/v1/books/:id/progress", put(BookHandler::update_progress))
+
         // Library routes
         .route("/api/v1/libraries", get(MediaHandler::list_libraries))
         .route("/api/v1/libraries", post(MediaHandler::create_library))
         .route("/api/v1/libraries/:id/scan", post(MediaHandler::scan_library))
+        .route("/api/v1/libraries/:id/scan", delete(MediaHandler::cancel_scan))
+
+        // Admin routes
+        .route("/api/v1/admin/duplicates", get(MediaHandler::list_duplicates))
+        .route("/api/v1/admin/audits", get(AuditHandler::list_audits))
+        .route("/api/v1/admin/audits", post(AuditHandler::start_audit))
+        .route("/api/v1/admin/audits/:id", get(AuditHandler::get_audit))
+        .route("/api/v1/admin/audits/:id/cleanup", post(AuditHandler::clean_up))
         
         // User routes
         .route("/api/v1/users", get(UserHandler::list_users))
@@ -55,6 +91,7 @@ pub fn create_router() -> Result<Router> {
         .route("/api/v1/stream/:id/info", get(StreamHandler::get_stream_info))
         .route("/api/v1/stream/:id/start", post(StreamHandler::start_stream))
         .route("/api/v1/stream/:id/stop", post(StreamHandler::stop_stream))
+        .route("/api/v1/stream/:id/direct", get(StreamHandler::direct_play))
         .route("/api/v1/stream/:media_id/:format", get(StreamHandler::get_stream_url))
         .route("/api/v1/stream/:id/hls/:file", get(StreamHandler::serve_hls))
         .route("/api/v1/stream/:id/dash/:file", get(StreamHandler::serve_dash))
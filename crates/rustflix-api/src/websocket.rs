@@ -193,7 +193,7 @@ mod tests {
     #[test]
     fn test_client_message_deserialization() {
         let json = r#"{"type": "Subscribe", "events": ["media", "stream"]}"#;
-        let message: Result<ClientMessage, _> = serde_json::from_str(json);
+        let message: std::result::Result<ClientMessage, _> = serde_json::from_str(json);
         assert!(message.is_ok());
     }
 }
@@ -136,6 +136,60 @@ pub struct MediaConfig {
     pub thumbnail_sizes: Vec<(u32, u32)>,
     pub extract_chapters: bool,
     pub generate_previews: bool,
+    /// Hash whole files in addition to the partial fingerprint
+    #[serde(default)]
+    pub full_hash: bool,
+    /// Gitignore-style patterns excluded from every library
+    #[serde(default = "default_ignore_patterns")]
+    pub ignore_patterns: Vec<String>,
+    /// Files smaller than this many bytes are not added to libraries
+    #[serde(default)]
+    pub min_file_size: u64,
+    /// S3-compatible storage used by libraries whose path is an `s3://` URL
+    #[serde(default)]
+    pub object_storage: Option<ObjectStorageConfig>,
+}
+
+/// S3-compatible object storage configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectStorageConfig {
+    /// Base URL of the service, such as `https://s3.eu-west-1.amazonaws.com`
+    pub endpoint: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Address buckets in the path rather than the host name, as MinIO and
+    /// most self-hosted services expect
+    #[serde(default)]
+    pub path_style: bool,
+    /// How often bucket libraries are listed for changes
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64, // seconds
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_poll_interval() -> u64 {
+    300
+}
+
+fn default_transcode_path() -> PathBuf {
+    PathBuf::from("transcodes")
+}
+
+/// Patterns for NAS metadata folders, trash folders, samples and partial downloads
+fn default_ignore_patterns() -> Vec<String> {
+    [
+        "@eaDir", "#recycle", ".trash*", ".Trash-*", "$RECYCLE.BIN",
+        "sample", "sample.*", "*-sample.*", "*.sample.*",
+        "*.part", "*.partial", "*.crdownload", "*.!qB", "*.tmp",
+    ]
+    .iter()
+    .map(|pattern| pattern.to_string())
+    .collect()
 }
 
 /// Streaming configuration
@@ -147,6 +201,9 @@ pub struct StreamingConfig {
     pub hardware_acceleration: HardwareAcceleration,
     pub quality_profiles: Vec<QualityProfile>,
     pub max_concurrent_streams: Option<u32>,
+    /// Folder transcoded files are written to
+    #[serde(default = "default_transcode_path")]
+    pub transcode_path: PathBuf,
 }
 
 /// Hardware acceleration settings
@@ -262,12 +319,16 @@ impl Default for MediaConfig {
             supported_extensions: vec![
                 "mp4".to_string(), "mkv".to_string(), "avi".to_string(),
                 "mov".to_string(), "wmv".to_string(), "flv".to_string(),
-                "webm".to_string(), "m4v".to_string(),
+                "webm".to_string(), "m4v".to_string(), "iso".to_string(),
             ],
             thumbnail_path: PathBuf::from("thumbnails"),
             thumbnail_sizes: vec![(320, 180), (640, 360), (1280, 720)],
             extract_chapters: true,
             generate_previews: true,
+            full_hash: false,
+            ignore_patterns: default_ignore_patterns(),
+            min_file_size: 0,
+            object_storage: None,
         }
     }
 }
@@ -300,6 +361,7 @@ impl Default for StreamingConfig {
                 },
             ],
             max_concurrent_streams: Some(10),
+            transcode_path: default_transcode_path(),
         }
     }
 }
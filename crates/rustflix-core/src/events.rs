@@ -25,6 +25,7 @@ pub enum EventType {
     LibraryScanStarted { library_id: Uuid },
     LibraryScanProgress { library_id: Uuid, progress: f32, current_path: String },
     LibraryScanCompleted { library_id: Uuid, items_added: u32, items_updated: u32 },
+    LibraryScanFailed { library_id: Uuid, error: String },
     MediaItemAdded { media_id: Uuid, path: String },
     MediaItemUpdated { media_id: Uuid },
     MediaItemRemoved { media_id: Uuid },
@@ -106,6 +107,7 @@ impl Event {
             EventType::LibraryScanStarted { .. } => "library_scan_started",
             EventType::LibraryScanProgress { .. } => "library_scan_progress",
             EventType::LibraryScanCompleted { .. } => "library_scan_completed",
+            EventType::LibraryScanFailed { .. } => "library_scan_failed",
             EventType::MediaItemAdded { .. } => "media_item_added",
             EventType::MediaItemUpdated { .. } => "media_item_updated",
             EventType::MediaItemRemoved { .. } => "media_item_removed",
@@ -13,10 +13,10 @@ pub mod events;
 
 // Re-export commonly used types
 pub use error::{RustFlixError, Result};
-pub use media::{MediaItem, MediaType, MediaFormat, MediaId};
-pub use metadata::MediaMetadata;
+pub use media::{MediaItem, MediaType, MediaFormat, MediaId, ExtraType, StackTimeline};
+pub use metadata::{AlbumType, MediaMetadata};
 pub use user::{User, UserRole, UserId};
-pub use streaming::{StreamInfo, StreamingProtocol, StreamId};
+pub use streaming::{SegmentFormat, StreamInfo, StreamingProtocol, StreamId};
 pub use config::RustFlixConfig;
 pub use events::{Event, EventType};
 
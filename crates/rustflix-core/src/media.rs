@@ -48,12 +48,30 @@ pub enum MediaType {
     Music,
     /// Photo/image
     Photo,
+    /// Audiobook file, a whole book or one of its parts
+    Audiobook,
+    /// Comic or e-book
+    Book,
     /// Person (cast/crew)
     Person,
     /// Other/unknown media type
     Other,
 }
 
+/// Kind of extra attached to a movie or show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExtraType {
+    Trailer,
+    Featurette,
+    BehindTheScenes,
+    DeletedScene,
+    Interview,
+    Scene,
+    Short,
+    /// Extra of no specific kind
+    Other,
+}
+
 /// Media container format
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MediaFormat {
@@ -67,6 +85,11 @@ pub enum MediaFormat {
     Webm,
     M4v,
     
+    // Disc formats
+    Bluray,
+    Dvd,
+    Iso,
+    
     // Audio formats
     Mp3,
     Flac,
@@ -74,6 +97,7 @@ pub enum MediaFormat {
     Ogg,
     Wav,
     M4a,
+    M4b,
     
     // Image formats
     Jpeg,
@@ -83,6 +107,12 @@ pub enum MediaFormat {
     Webp,
     Svg,
     
+    // Book formats
+    Cbz,
+    Cbr,
+    Epub,
+    Pdf,
+    
     // Unknown format
     Unknown,
 }
@@ -120,10 +150,13 @@ pub struct SubtitleTrack {
     pub codec: String,
     pub forced: bool,
     pub default: bool,
+    /// External subtitle file; `None` for tracks embedded in the container
+    #[serde(default)]
+    pub path: Option<PathBuf>,
 }
 
 /// Complete media stream information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MediaStreams {
     pub video: Vec<VideoCodec>,
     pub audio: Vec<AudioCodec>,
@@ -141,18 +174,24 @@ impl MediaFormat {
             "wmv" => Self::Wmv,
             "flv" => Self::Flv,
             "webm" => Self::Webm,
+            "iso" => Self::Iso,
             "mp3" => Self::Mp3,
             "flac" => Self::Flac,
             "aac" => Self::Aac,
             "ogg" => Self::Ogg,
             "wav" => Self::Wav,
             "m4a" => Self::M4a,
+            "m4b" => Self::M4b,
             "jpg" | "jpeg" => Self::Jpeg,
             "png" => Self::Png,
             "gif" => Self::Gif,
             "bmp" => Self::Bmp,
             "webp" => Self::Webp,
             "svg" => Self::Svg,
+            "cbz" => Self::Cbz,
+            "cbr" => Self::Cbr,
+            "epub" => Self::Epub,
+            "pdf" => Self::Pdf,
             _ => Self::Unknown,
         }
     }
@@ -162,14 +201,20 @@ impl MediaFormat {
         matches!(
             self,
             Self::Mp4 | Self::Mkv | Self::Avi | Self::Mov | Self::Wmv | Self::Flv | Self::Webm | Self::M4v
+                | Self::Bluray | Self::Dvd | Self::Iso
         )
     }
 
+    /// Check if format is a Blu-ray or DVD disc, as a folder or an image
+    pub fn is_disc(&self) -> bool {
+        matches!(self, Self::Bluray | Self::Dvd | Self::Iso)
+    }
+
     /// Check if format is an audio format
     pub fn is_audio(&self) -> bool {
         matches!(
             self,
-            Self::Mp3 | Self::Flac | Self::Aac | Self::Ogg | Self::Wav | Self::M4a
+            Self::Mp3 | Self::Flac | Self::Aac | Self::Ogg | Self::Wav | Self::M4a | Self::M4b
         )
     }
 
@@ -181,6 +226,51 @@ impl MediaFormat {
         )
     }
 
+    /// Check if format is a comic archive or e-book
+    pub fn is_book(&self) -> bool {
+        matches!(self, Self::Cbz | Self::Cbr | Self::Epub | Self::Pdf)
+    }
+
+    /// Check if format is a comic archive of page images
+    pub fn is_comic(&self) -> bool {
+        matches!(self, Self::Cbz | Self::Cbr)
+    }
+
+    /// Get the name used in the database and API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Mkv => "mkv",
+            Self::Avi => "avi",
+            Self::Mov => "mov",
+            Self::Wmv => "wmv",
+            Self::Flv => "flv",
+            Self::Webm => "webm",
+            Self::M4v => "m4v",
+            Self::Bluray => "bluray",
+            Self::Dvd => "dvd",
+            Self::Iso => "iso",
+            Self::Mp3 => "mp3",
+            Self::Flac => "flac",
+            Self::Aac => "aac",
+            Self::Ogg => "ogg",
+            Self::Wav => "wav",
+            Self::M4a => "m4a",
+            Self::M4b => "m4b",
+            Self::Jpeg => "jpeg",
+            Self::Png => "png",
+            Self::Gif => "gif",
+            Self::Bmp => "bmp",
+            Self::Webp => "webp",
+            Self::Svg => "svg",
+            Self::Cbz => "cbz",
+            Self::Cbr => "cbr",
+            Self::Epub => "epub",
+            Self::Pdf => "pdf",
+            Self::Unknown => "unknown",
+        }
+    }
+
     /// Get MIME type for the format
     pub fn mime_type(&self) -> &'static str {
         match self {
@@ -191,18 +281,25 @@ impl MediaFormat {
             Self::Wmv => "video/x-ms-wmv",
             Self::Flv => "video/x-flv",
             Self::Webm => "video/webm",
+            Self::Bluray => "video/mp2t",
+            Self::Dvd => "video/mpeg",
+            Self::Iso => "application/x-iso9660-image",
             Self::Mp3 => "audio/mpeg",
             Self::Flac => "audio/flac",
             Self::Aac => "audio/aac",
             Self::Ogg => "audio/ogg",
             Self::Wav => "audio/wav",
-            Self::M4a => "audio/mp4",
+            Self::M4a | Self::M4b => "audio/mp4",
             Self::Jpeg => "image/jpeg",
             Self::Png => "image/png",
             Self::Gif => "image/gif",
             Self::Bmp => "image/bmp",
             Self::Webp => "image/webp",
             Self::Svg => "image/svg+xml",
+            Self::Cbz => "application/vnd.comicbook+zip",
+            Self::Cbr => "application/vnd.comicbook-rar",
+            Self::Epub => "application/epub+zip",
+            Self::Pdf => "application/pdf",
             Self::Unknown => "application/octet-stream",
         }
     }
@@ -217,10 +314,59 @@ impl MediaType {
             Self::Music
         } else if format.is_image() {
             Self::Photo
+        } else if format.is_book() {
+            Self::Book
         } else {
             Self::Other
         }
     }
+
+    /// Get the name used in the database and API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Movie => "movie",
+            Self::Episode => "episode",
+            Self::TvShow => "tv_show",
+            Self::Music => "music",
+            Self::Photo => "photo",
+            Self::Audiobook => "audiobook",
+            Self::Book => "book",
+            Self::Person => "person",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl ExtraType {
+    /// Get the name used in the database and API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trailer => "trailer",
+            Self::Featurette => "featurette",
+            Self::BehindTheScenes => "behind_the_scenes",
+            Self::DeletedScene => "deleted_scene",
+            Self::Interview => "interview",
+            Self::Scene => "scene",
+            Self::Short => "short",
+            Self::Other => "other",
+        }
+    }
+
+    /// Parse a name produced by `as_str`
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::Trailer,
+            Self::Featurette,
+            Self::BehindTheScenes,
+            Self::DeletedScene,
+            Self::Interview,
+            Self::Scene,
+            Self::Short,
+            Self::Other,
+        ]
+        .into_iter()
+        .find(|extra| extra.as_str() == name)
+    }
 }
 
 impl MediaItem {
@@ -268,11 +414,89 @@ impl MediaItem {
     }
 }
 
+/// Continuous timeline of a multi-part title played back-to-back
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StackTimeline {
+    /// Parts in playback order with their durations in seconds
+    parts: Vec<(MediaId, f64)>,
+}
+
+impl StackTimeline {
+    /// Create a timeline from parts in playback order; unknown durations count as zero
+    pub fn new(parts: Vec<(MediaId, Option<f64>)>) -> Self {
+        Self {
+            parts: parts
+                .into_iter()
+                .map(|(id, duration)| (id, duration.unwrap_or(0.0).max(0.0)))
+                .collect(),
+        }
+    }
+
+    /// Total duration in seconds
+    pub fn duration(&self) -> f64 {
+        self.parts.iter().map(|(_, duration)| duration).sum()
+    }
+
+    /// Parts with their start on the timeline and their duration
+    pub fn parts(&self) -> impl Iterator<Item = (MediaId, f64, f64)> + '_ {
+        self.parts.iter().scan(0.0, |start, &(id, duration)| {
+            let part = (id, *start, duration);
+            *start += duration;
+            Some(part)
+        })
+    }
+
+    /// Check whether a media item is one of the parts
+    pub fn contains(&self, id: MediaId) -> bool {
+        self.parts.iter().any(|(part, _)| *part == id)
+    }
+
+    /// Convert a position on the timeline into a part and the offset within it
+    ///
+    /// Positions past the end resolve to the end of the last part.
+    pub fn locate(&self, position: f64) -> Option<(MediaId, f64)> {
+        let position = position.max(0.0);
+        let mut last = None;
+
+        for (id, start, duration) in self.parts() {
+            if position < start + duration {
+                return Some((id, position - start));
+            }
+            last = Some((id, duration));
+        }
+        last
+    }
+
+    /// Convert an offset within a part into a position on the timeline
+    pub fn position(&self, id: MediaId, offset: f64) -> Option<f64> {
+        self.parts()
+            .find(|(part, _, _)| *part == id)
+            .map(|(_, start, duration)| start + offset.clamp(0.0, duration))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::Path;
 
+    #[test]
+    fn test_stack_timeline() {
+        let (cd1, cd2) = (Uuid::new_v4(), Uuid::new_v4());
+        let timeline = StackTimeline::new(vec![(cd1, Some(3000.0)), (cd2, Some(2400.0))]);
+
+        assert_eq!(timeline.duration(), 5400.0);
+        assert_eq!(timeline.locate(0.0), Some((cd1, 0.0)));
+        assert_eq!(timeline.locate(3000.0), Some((cd2, 0.0)));
+        assert_eq!(timeline.locate(3600.0), Some((cd2, 600.0)));
+        assert_eq!(timeline.locate(9000.0), Some((cd2, 2400.0)));
+        assert_eq!(timeline.position(cd2, 600.0), Some(3600.0));
+        assert_eq!(timeline.position(cd1, 5000.0), Some(3000.0));
+        assert_eq!(timeline.position(Uuid::new_v4(), 1.0), None);
+        assert!(timeline.contains(cd1));
+        assert_eq!(StackTimeline::default().locate(10.0), None);
+    }
+
     #[test]
     fn test_format_from_extension() {
         assert_eq!(MediaFormat::from_extension("mp4"), MediaFormat::Mp4);
@@ -286,6 +510,17 @@ mod tests {
         assert!(MediaFormat::Mp3.is_audio());
         assert!(MediaFormat::Jpeg.is_image());
         assert!(!MediaFormat::Mp4.is_audio());
+        assert!(MediaFormat::Cbz.is_comic());
+        assert!(MediaFormat::Epub.is_book() && !MediaFormat::Epub.is_comic());
+        assert_eq!(MediaType::from_format(MediaFormat::Pdf), MediaType::Book);
+    }
+
+    #[test]
+    fn test_database_names() {
+        assert_eq!(MediaType::Episode.as_str(), "episode");
+        assert_eq!(MediaFormat::Mkv.as_str(), "mkv");
+        assert_eq!(MediaFormat::from_extension(MediaFormat::M4a.as_str()), MediaFormat::M4a);
+        assert_eq!(MediaFormat::from_extension(MediaFormat::M4b.as_str()), MediaFormat::M4b);
     }
 
     #[test]
@@ -159,7 +159,7 @@ pub struct TrackMetadata {
 }
 
 /// Album type classification
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AlbumType {
     Album,
     Single,
@@ -170,6 +170,47 @@ pub enum AlbumType {
     Other,
 }
 
+impl AlbumType {
+    /// Get the name used in the database and API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Album => "album",
+            Self::Single => "single",
+            Self::Compilation => "compilation",
+            Self::Soundtrack => "soundtrack",
+            Self::Live => "live",
+            Self::Remix => "remix",
+            Self::Other => "other",
+        }
+    }
+
+    /// Parse a MusicBrainz release type such as "album", "ep" or
+    /// "album; soundtrack"; secondary types take precedence
+    pub fn from_release_type(release_type: &str) -> Self {
+        let types = release_type
+            .split([';', '/', ','])
+            .map(|kind| kind.trim().to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        let has = |kind: &str| types.iter().any(|t| t == kind);
+
+        if has("compilation") {
+            Self::Compilation
+        } else if has("soundtrack") {
+            Self::Soundtrack
+        } else if has("live") {
+            Self::Live
+        } else if has("remix") {
+            Self::Remix
+        } else if has("album") {
+            Self::Album
+        } else if has("single") || has("ep") {
+            Self::Single
+        } else {
+            Self::Other
+        }
+    }
+}
+
 /// Metadata provider information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetadataProvider {
@@ -309,4 +350,13 @@ mod tests {
         assert_eq!(metadata.get_external_id("tmdb"), Some(&"12345".to_string()));
         assert_eq!(metadata.get_external_id("imdb"), None);
     }
+
+    #[test]
+    fn test_album_type_from_release_type() {
+        assert_eq!(AlbumType::from_release_type("Album"), AlbumType::Album);
+        assert_eq!(AlbumType::from_release_type("ep"), AlbumType::Single);
+        assert_eq!(AlbumType::from_release_type("album; soundtrack"), AlbumType::Soundtrack);
+        assert_eq!(AlbumType::from_release_type("album/live"), AlbumType::Live);
+        assert_eq!(AlbumType::from_release_type("broadcast"), AlbumType::Other);
+    }
 }
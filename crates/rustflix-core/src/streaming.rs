@@ -21,6 +21,16 @@ pub enum StreamingProtocol {
     Progressive,
 }
 
+/// Container of HLS and DASH media segments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SegmentFormat {
+    /// MPEG-2 transport stream segments, for HLS clients without fMP4 support
+    MpegTs,
+    /// Fragmented MP4 (CMAF) segments behind an init segment, shared by HLS
+    /// and DASH and required for HEVC, AV1 and HDR video
+    Fmp4,
+}
+
 /// Video quality levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Quality {
@@ -236,6 +246,46 @@ impl StreamingProtocol {
     }
 }
 
+impl SegmentFormat {
+    /// Parse the container name of a quality profile
+    pub fn from_container(container: &str) -> Option<Self> {
+        match container.to_lowercase().as_str() {
+            "ts" | "mpegts" => Some(Self::MpegTs),
+            "mp4" | "fmp4" | "cmaf" => Some(Self::Fmp4),
+            _ => None,
+        }
+    }
+
+    /// Container name, as stored in `StreamInfo::container`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MpegTs => "ts",
+            Self::Fmp4 => "mp4",
+        }
+    }
+
+    /// File extension of media segments
+    pub fn segment_extension(&self) -> &'static str {
+        match self {
+            Self::MpegTs => "ts",
+            Self::Fmp4 => "m4s",
+        }
+    }
+
+    /// MIME type of media segments
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::MpegTs => "video/mp2t",
+            Self::Fmp4 => "video/mp4",
+        }
+    }
+
+    /// Check whether segments depend on an init segment (`EXT-X-MAP`)
+    pub fn has_init_segment(&self) -> bool {
+        *self == Self::Fmp4
+    }
+}
+
 impl StreamInfo {
     /// Create new stream info
     pub fn new(
@@ -264,8 +314,7 @@ impl StreamInfo {
             },
             container: match protocol {
                 StreamingProtocol::DirectPlay => "original".to_string(),
-                StreamingProtocol::Hls => "ts".to_string(),
-                StreamingProtocol::Dash => "mp4".to_string(),
+                StreamingProtocol::Hls | StreamingProtocol::Dash => SegmentFormat::Fmp4.as_str().to_string(),
                 StreamingProtocol::Progressive => "mp4".to_string(),
             },
             duration: None,
@@ -275,6 +324,14 @@ impl StreamInfo {
         }
     }
 
+    /// Format of the media segments of an HLS or DASH stream
+    pub fn segment_format(&self) -> Option<SegmentFormat> {
+        match self.protocol {
+            StreamingProtocol::Hls | StreamingProtocol::Dash => SegmentFormat::from_container(&self.container),
+            StreamingProtocol::DirectPlay | StreamingProtocol::Progressive => None,
+        }
+    }
+
     /// Check if stream has expired
     pub fn is_expired(&self) -> bool {
         self.expires_at.map_or(false, |expires| Utc::now() > expires)
@@ -353,5 +410,15 @@ mod tests {
         assert_eq!(stream.quality, Quality::FullHD);
         assert_eq!(stream.resolution, Some((1920, 1080)));
         assert!(stream.supports_seeking);
+        assert_eq!(stream.segment_format(), Some(SegmentFormat::Fmp4));
+    }
+
+    #[test]
+    fn test_segment_format() {
+        assert_eq!(SegmentFormat::from_container("MPEGTS"), Some(SegmentFormat::MpegTs));
+        assert_eq!(SegmentFormat::from_container("cmaf"), Some(SegmentFormat::Fmp4));
+        assert_eq!(SegmentFormat::from_container("mkv"), None);
+        assert_eq!(SegmentFormat::Fmp4.segment_extension(), "m4s");
+        assert!(!SegmentFormat::MpegTs.has_init_segment());
     }
 }
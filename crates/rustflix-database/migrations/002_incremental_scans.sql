@@ -0,0 +1,10 @@
+-- Incremental library scan support
+-- Migration: 002_incremental_scans
+
+-- File modification time recorded at the last scan
+ALTER TABLE media_items ADD COLUMN file_modified TIMESTAMPTZ;
+
+-- Set when a file disappears from its library; cleared if it comes back
+ALTER TABLE media_items ADD COLUMN removed_at TIMESTAMPTZ;
+
+CREATE INDEX idx_media_items_removed_at ON media_items(removed_at) WHERE removed_at IS NOT NULL;
@@ -0,0 +1,5 @@
+-- Content hashes for duplicate and move detection
+-- Migration: 003_content_hashes
+
+-- Hash of the whole file; file_hash holds the partial content fingerprint
+ALTER TABLE media_items ADD COLUMN full_hash VARCHAR(64);
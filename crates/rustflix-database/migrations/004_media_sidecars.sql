@@ -0,0 +1,19 @@
+-- Sidecar files stored next to media items
+-- Migration: 004_media_sidecars
+
+-- External subtitles, local artwork and NFO files associated with a media item
+CREATE TABLE media_sidecars (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    media_id UUID NOT NULL REFERENCES media_items(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('subtitle', 'poster', 'backdrop', 'fanart', 'logo', 'thumbnail', 'nfo')),
+    language VARCHAR(10),
+    title VARCHAR(255),
+    codec VARCHAR(50),
+    forced BOOLEAN NOT NULL DEFAULT false,
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(media_id, path)
+);
+
+CREATE INDEX idx_media_sidecars_media_id ON media_sidecars(media_id);
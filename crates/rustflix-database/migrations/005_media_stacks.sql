@@ -0,0 +1,22 @@
+-- Multi-part and multi-version stacking
+-- Migration: 005_media_stacks
+
+-- Logical title grouping the parts and versions of one movie in a folder
+CREATE TABLE media_stacks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    directory TEXT NOT NULL,
+    title VARCHAR(500) NOT NULL,
+    year INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_media_stacks_directory ON media_stacks(directory);
+
+-- Stack membership: the version a file belongs to and its part number
+ALTER TABLE media_items
+    ADD COLUMN stack_id UUID REFERENCES media_stacks(id) ON DELETE SET NULL,
+    ADD COLUMN stack_version VARCHAR(255),
+    ADD COLUMN stack_part INTEGER;
+
+CREATE INDEX idx_media_items_stack_id ON media_items(stack_id);
@@ -0,0 +1,9 @@
+-- Extras such as trailers and featurettes
+-- Migration: 006_media_extras
+
+-- Extra type and the folder of the movie or show an extra belongs to
+ALTER TABLE media_items
+    ADD COLUMN extra_type VARCHAR(50),
+    ADD COLUMN extra_owner TEXT;
+
+CREATE INDEX idx_media_items_extra_owner ON media_items(extra_owner) WHERE extra_owner IS NOT NULL;
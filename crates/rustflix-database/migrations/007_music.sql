@@ -0,0 +1,71 @@
+-- Albums and artists built from embedded music tags
+-- Migration: 007_music
+
+-- Artists, keyed by MusicBrainz id or by name
+CREATE TABLE music_artists (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    group_key TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    sort_name TEXT,
+    musicbrainz_id VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Embedded cover art, stored once per distinct image
+CREATE TABLE music_cover_art (
+    hash VARCHAR(64) PRIMARY KEY,
+    mime_type VARCHAR(100) NOT NULL,
+    data BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Albums, keyed by MusicBrainz release id, album artist or folder
+CREATE TABLE music_albums (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    group_key TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    artist_id UUID REFERENCES music_artists(id) ON DELETE SET NULL,
+    album_type VARCHAR(50) NOT NULL,
+    is_compilation BOOLEAN NOT NULL DEFAULT FALSE,
+    year INTEGER,
+    genre TEXT,
+    disc_count INTEGER NOT NULL DEFAULT 1,
+    track_count INTEGER NOT NULL DEFAULT 0,
+    musicbrainz_id VARCHAR(255),
+    cover_hash VARCHAR(64) REFERENCES music_cover_art(hash) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Tags of each music file and the album and artist it was grouped into
+CREATE TABLE music_tracks (
+    media_id UUID PRIMARY KEY REFERENCES media_items(id) ON DELETE CASCADE,
+    title TEXT,
+    artist TEXT,
+    artist_sort TEXT,
+    album_artist TEXT,
+    album_artist_sort TEXT,
+    album TEXT,
+    track_number INTEGER,
+    track_total INTEGER,
+    disc_number INTEGER,
+    disc_total INTEGER,
+    year INTEGER,
+    genre TEXT,
+    compilation BOOLEAN NOT NULL DEFAULT FALSE,
+    release_type VARCHAR(255),
+    musicbrainz_recording_id VARCHAR(255),
+    musicbrainz_release_track_id VARCHAR(255),
+    musicbrainz_release_id VARCHAR(255),
+    musicbrainz_artist_id VARCHAR(255),
+    musicbrainz_album_artist_id VARCHAR(255),
+    cover_hash VARCHAR(64) REFERENCES music_cover_art(hash) ON DELETE SET NULL,
+    album_id UUID REFERENCES music_albums(id) ON DELETE SET NULL,
+    artist_id UUID REFERENCES music_artists(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_music_tracks_album_id ON music_tracks(album_id);
+CREATE INDEX idx_music_tracks_artist_id ON music_tracks(artist_id);
+CREATE INDEX idx_music_albums_artist_id ON music_albums(artist_id);
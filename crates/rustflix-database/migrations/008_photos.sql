@@ -0,0 +1,29 @@
+-- EXIF details and thumbnails of photos
+-- Migration: 008_photos
+
+CREATE TABLE photos (
+    media_id UUID PRIMARY KEY REFERENCES media_items(id) ON DELETE CASCADE,
+    -- Camera's local time from EXIF, or the file modification time (UTC)
+    taken_at TIMESTAMP NOT NULL,
+    has_exif_date BOOLEAN NOT NULL DEFAULT FALSE,
+    width INTEGER,
+    height INTEGER,
+    camera_make VARCHAR(255),
+    camera_model VARCHAR(255),
+    lens_model VARCHAR(255),
+    orientation INTEGER,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    altitude DOUBLE PRECISION,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_photos_taken_at ON photos(taken_at);
+
+CREATE TABLE photo_thumbnails (
+    media_id UUID NOT NULL REFERENCES photos(media_id) ON DELETE CASCADE,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    PRIMARY KEY (media_id, width, height)
+);
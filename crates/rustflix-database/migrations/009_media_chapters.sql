@@ -0,0 +1,12 @@
+-- Chapters read from video containers
+-- Migration: 009_media_chapters
+
+CREATE TABLE media_chapters (
+    media_id UUID NOT NULL REFERENCES media_items(id) ON DELETE CASCADE,
+    chapter_index INTEGER NOT NULL,
+    -- Seconds from the start of the file
+    start_time DOUBLE PRECISION NOT NULL,
+    end_time DOUBLE PRECISION NOT NULL,
+    title TEXT NOT NULL,
+    PRIMARY KEY (media_id, chapter_index)
+);
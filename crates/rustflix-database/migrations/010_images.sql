@@ -0,0 +1,33 @@
+-- Content-addressed images with their resized variants and blurhash
+-- Migration: 010_images
+
+CREATE TABLE images (
+    -- BLAKE3 hash of the source file
+    hash VARCHAR(64) PRIMARY KEY,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    blurhash VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE image_variants (
+    hash VARCHAR(64) NOT NULL REFERENCES images(hash) ON DELETE CASCADE,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    format VARCHAR(10) NOT NULL CHECK (format IN ('jpeg', 'webp')),
+    path TEXT NOT NULL,
+    PRIMARY KEY (hash, width, height, format)
+);
+
+-- Posters, backdrops and thumbnails of videos, and photos themselves
+CREATE TABLE media_images (
+    media_id UUID NOT NULL REFERENCES media_items(id) ON DELETE CASCADE,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('poster', 'backdrop', 'thumbnail', 'photo')),
+    hash VARCHAR(64) NOT NULL REFERENCES images(hash),
+    PRIMARY KEY (media_id, kind)
+);
+
+CREATE INDEX idx_media_images_hash ON media_images(hash);
+
+-- Photo thumbnails are now variants of the photo's image
+DROP TABLE photo_thumbnails;
@@ -0,0 +1,50 @@
+-- Audiobook libraries, with books built from the tags of their files
+-- Migration: 011_audiobooks
+
+ALTER TABLE libraries DROP CONSTRAINT libraries_library_type_check;
+ALTER TABLE libraries ADD CONSTRAINT libraries_library_type_check
+    CHECK (library_type IN ('movies', 'tv', 'music', 'photos', 'audiobooks'));
+
+ALTER TABLE media_items DROP CONSTRAINT media_items_media_type_check;
+ALTER TABLE media_items ADD CONSTRAINT media_items_media_type_check
+    CHECK (media_type IN ('movie', 'episode', 'music', 'photo', 'audiobook', 'other'));
+
+-- Books, keyed by the folder holding their files and their title
+CREATE TABLE audiobooks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    group_key TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    author TEXT,
+    narrator TEXT,
+    series TEXT,
+    -- Position in the series, e.g. "3" or "2.5"
+    series_part TEXT,
+    year INTEGER,
+    genre TEXT,
+    part_count INTEGER NOT NULL DEFAULT 0,
+    cover_hash VARCHAR(64) REFERENCES music_cover_art(hash) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Tags of each audiobook file and the book it was grouped into
+CREATE TABLE audiobook_files (
+    media_id UUID PRIMARY KEY REFERENCES media_items(id) ON DELETE CASCADE,
+    title TEXT,
+    author TEXT,
+    narrator TEXT,
+    book TEXT,
+    series TEXT,
+    series_part TEXT,
+    track_number INTEGER,
+    disc_number INTEGER,
+    year INTEGER,
+    genre TEXT,
+    cover_hash VARCHAR(64) REFERENCES music_cover_art(hash) ON DELETE SET NULL,
+    book_id UUID REFERENCES audiobooks(id) ON DELETE SET NULL,
+    -- Position of the file in its book, starting at 1
+    part_number INTEGER,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audiobook_files_book_id ON audiobook_files(book_id);
@@ -0,0 +1,42 @@
+-- Comic and e-book libraries with per-user reading progress
+-- Migration: 012_books
+
+ALTER TABLE libraries DROP CONSTRAINT libraries_library_type_check;
+ALTER TABLE libraries ADD CONSTRAINT libraries_library_type_check
+    CHECK (library_type IN ('movies', 'tv', 'music', 'photos', 'audiobooks', 'books'));
+
+ALTER TABLE media_items DROP CONSTRAINT media_items_media_type_check;
+ALTER TABLE media_items ADD CONSTRAINT media_items_media_type_check
+    CHECK (media_type IN ('movie', 'episode', 'music', 'photo', 'audiobook', 'book', 'other'));
+
+-- Details read from comic archives, EPUB packages and PDF documents
+CREATE TABLE books (
+    media_id UUID PRIMARY KEY REFERENCES media_items(id) ON DELETE CASCADE,
+    title TEXT,
+    author TEXT,
+    series TEXT,
+    -- Position in the series, e.g. "3" or "2.5"
+    series_index TEXT,
+    publisher TEXT,
+    language VARCHAR(35),
+    description TEXT,
+    year INTEGER,
+    -- Pages of comics and PDF documents
+    page_count INTEGER,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Reading position of each user in a book, the counterpart of playback_state
+CREATE TABLE reading_progress (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    media_id UUID NOT NULL REFERENCES media_items(id) ON DELETE CASCADE,
+    -- Page of comics and PDF documents, starting at 1
+    page INTEGER,
+    -- Position within an EPUB as the reader records it, such as a CFI
+    location TEXT,
+    -- Fraction of the book read, from 0 to 1
+    progress REAL NOT NULL DEFAULT 0,
+    is_finished BOOLEAN NOT NULL DEFAULT false,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, media_id)
+);
@@ -0,0 +1,36 @@
+-- Library health reports listing broken and inconsistent content
+-- Migration: 013_library_audits
+
+-- One run of the library audit job over every library
+CREATE TABLE library_audits (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    status VARCHAR(50) NOT NULL CHECK (status IN ('running', 'completed', 'failed')),
+    items_checked INTEGER NOT NULL DEFAULT 0,
+    issue_count INTEGER NOT NULL DEFAULT 0,
+    error_message TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ,
+    -- When the cleanup actions were last applied to the issues found
+    cleaned_at TIMESTAMPTZ
+);
+
+-- Problems found by an audit
+CREATE TABLE library_audit_issues (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    audit_id UUID NOT NULL REFERENCES library_audits(id) ON DELETE CASCADE,
+    kind VARCHAR(50) NOT NULL CHECK (kind IN (
+        'missing_file', 'empty_file', 'probe_failed', 'missing_metadata', 'duplicate_hash',
+        'unsupported_codec', 'orphaned_item', 'orphaned_stack', 'orphaned_audiobook', 'orphaned_image'
+    )),
+    -- Not references, as cleanup deletes the rows they point at
+    library_id UUID,
+    media_id UUID,
+    -- Stack or audiobook of orphaned_stack and orphaned_audiobook issues
+    row_id UUID,
+    path TEXT,
+    detail TEXT,
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_library_audits_started_at ON library_audits(started_at DESC);
+CREATE INDEX idx_library_audit_issues_audit_id ON library_audit_issues(audit_id, kind);
@@ -1,10 +1,9 @@
 //! Database model definitions
 
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
-use rustflix_core::{MediaId, StreamId, UserId};
 use ipnetwork::IpNetwork;
 
 /// Database model for media items
@@ -20,8 +19,279 @@ pub struct MediaItemModel {
     pub width: Option<i32>,
     pub height: Option<i32>,
     pub bitrate: Option<i64>,
+    pub file_modified: Option<DateTime<Utc>>,
+    pub removed_at: Option<DateTime<Utc>>,
+    pub full_hash: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub stack_id: Option<Uuid>,
+    pub stack_version: Option<String>,
+    pub stack_part: Option<i32>,
+    pub extra_type: Option<String>,
+    pub extra_owner: Option<String>,
+}
+
+/// Database model for stacks of multi-part and multi-version files
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MediaStackModel {
+    pub id: Uuid,
+    pub directory: String,
+    pub title: String,
+    pub year: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Place of a media item in a stack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackMemberModel {
+    pub media_id: Uuid,
+    pub version: String,
+    pub part: i32,
+}
+
+/// File state of a media item, used to reconcile library scans
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MediaFileStateModel {
+    pub id: Uuid,
+    pub path: String,
+    pub file_size: i64,
+    pub file_modified: Option<DateTime<Utc>>,
+    pub removed_at: Option<DateTime<Utc>>,
+}
+
+/// Database model for sidecar files (external subtitles, artwork, NFO)
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MediaSidecarModel {
+    pub id: Uuid,
+    pub media_id: Uuid,
+    pub path: String,
+    pub kind: String, // subtitle, poster, backdrop, fanart, logo, thumbnail, nfo
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub codec: Option<String>,
+    pub forced: bool,
+    pub is_default: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Database model for the tags of a music file and the album and artist it
+/// was grouped into
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MusicTrackModel {
+    pub media_id: Uuid,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub artist_sort: Option<String>,
+    pub album_artist: Option<String>,
+    pub album_artist_sort: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<i32>,
+    pub track_total: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub disc_total: Option<i32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub compilation: bool,
+    pub release_type: Option<String>,
+    pub musicbrainz_recording_id: Option<String>,
+    pub musicbrainz_release_track_id: Option<String>,
+    pub musicbrainz_release_id: Option<String>,
+    pub musicbrainz_artist_id: Option<String>,
+    pub musicbrainz_album_artist_id: Option<String>,
+    pub cover_hash: Option<String>,
+    pub album_id: Option<Uuid>,
+    pub artist_id: Option<Uuid>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Database model for music albums
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MusicAlbumModel {
+    pub id: Uuid,
+    pub group_key: String,
+    pub title: String,
+    pub artist_id: Option<Uuid>,
+    pub album_type: String,
+    pub is_compilation: bool,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub disc_count: i32,
+    pub track_count: i32,
+    pub musicbrainz_id: Option<String>,
+    pub cover_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Database model for music artists
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MusicArtistModel {
+    pub id: Uuid,
+    pub group_key: String,
+    pub name: String,
+    pub sort_name: Option<String>,
+    pub musicbrainz_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Album and artist a music track is grouped into
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MusicTrackLinkModel {
+    pub media_id: Uuid,
+    pub album_id: Option<Uuid>,
+    pub artist_id: Option<Uuid>,
+}
+
+/// Database model for the tags of an audiobook file and the book it was
+/// grouped into
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct AudiobookFileModel {
+    pub media_id: Uuid,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub narrator: Option<String>,
+    /// Title of the book the file belongs to
+    pub book: Option<String>,
+    pub series: Option<String>,
+    pub series_part: Option<String>,
+    pub track_number: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub cover_hash: Option<String>,
+    pub book_id: Option<Uuid>,
+    pub part_number: Option<i32>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Database model for audiobooks
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct AudiobookModel {
+    pub id: Uuid,
+    pub group_key: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub narrator: Option<String>,
+    pub series: Option<String>,
+    pub series_part: Option<String>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub part_count: i32,
+    pub cover_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Book an audiobook file is grouped into and its position in the book
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudiobookFileLinkModel {
+    pub media_id: Uuid,
+    pub book_id: Option<Uuid>,
+    pub part_number: Option<i32>,
+}
+
+/// Database model for the details of a comic or e-book
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct BookModel {
+    pub media_id: Uuid,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<String>,
+    pub publisher: Option<String>,
+    pub language: Option<String>,
+    pub description: Option<String>,
+    pub year: Option<i32>,
+    pub page_count: Option<i32>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Database model for the EXIF details of a photo
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct PhotoModel {
+    pub media_id: Uuid,
+    /// Camera's local time from EXIF, or the file modification time (UTC)
+    pub taken_at: NaiveDateTime,
+    pub has_exif_date: bool,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    pub orientation: Option<i32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Database model for a content-addressed source image
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize, Deserialize)]
+pub struct ImageModel {
+    pub hash: String,
+    pub width: i32,
+    pub height: i32,
+    pub blurhash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Database model for a resized variant of an image
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize, Deserialize)]
+pub struct ImageVariantModel {
+    pub hash: String,
+    pub width: i32,
+    pub height: i32,
+    pub format: String,
+    pub path: String,
+}
+
+/// Database model linking a media item to its poster, backdrop, thumbnail
+/// or photo image
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize, Deserialize)]
+pub struct MediaImageModel {
+    pub media_id: Uuid,
+    pub kind: String,
+    pub hash: String,
+}
+
+/// Database model for a chapter of a media item
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, Deserialize)]
+pub struct MediaChapterModel {
+    pub media_id: Uuid,
+    pub chapter_index: i32,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub title: String,
+}
+
+/// Database model for a run of the library audit
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct LibraryAuditModel {
+    pub id: Uuid,
+    pub status: String,
+    pub items_checked: i32,
+    pub issue_count: i32,
+    pub error_message: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub cleaned_at: Option<DateTime<Utc>>,
+}
+
+/// Database model for a problem found by a library audit
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize, Deserialize)]
+pub struct AuditIssueModel {
+    pub id: Uuid,
+    pub audit_id: Uuid,
+    pub kind: String,
+    pub library_id: Option<Uuid>,
+    pub media_id: Option<Uuid>,
+    /// Orphaned stack or audiobook
+    pub row_id: Option<Uuid>,
+    pub path: Option<String>,
+    pub detail: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
 }
 
 /// Database model for media metadata
@@ -140,6 +410,18 @@ pub struct PlaybackStateModel {
     pub updated_at: DateTime<Utc>,
 }
 
+/// Database model for reading progress
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct ReadingProgressModel {
+    pub user_id: Uuid,
+    pub media_id: Uuid,
+    pub page: Option<i32>,
+    pub location: Option<String>,
+    pub progress: f32,
+    pub is_finished: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
 /// Database model for user ratings
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct UserRatingModel {
@@ -1,10 +1,14 @@
 //! Media repository for database operations
 
 use rustflix_core::{Result, RustFlixError, MediaId};
-use crate::models::{MediaItemModel, LibraryModel};
-use sqlx::{PgPool, Row};
+use crate::models::{
+    ImageModel, ImageVariantModel, MediaItemModel, MediaChapterModel, MediaImageModel, MediaFileStateModel, MediaSidecarModel, MediaStackModel, StackMemberModel, LibraryModel,
+    MusicAlbumModel, MusicArtistModel, MusicTrackLinkModel, MusicTrackModel, PhotoModel,
+    AudiobookFileLinkModel, AudiobookFileModel, AudiobookModel, BookModel, LibraryAuditModel, AuditIssueModel,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
 use uuid::Uuid;
-use std::path::Path;
 
 /// Repository for media-related database operations
 #[derive(Debug, Clone)]
@@ -29,8 +33,9 @@ impl MediaRepository {
             r#"
             INSERT INTO media_items (
                 id, path, file_size, file_hash, media_type, format,
-                duration, width, height, bitrate, created_at, updated_at
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                duration, width, height, bitrate, file_modified, removed_at,
+                full_hash, created_at, updated_at, extra_type, extra_owner
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
             "#,
             item.id,
             item.path,
@@ -42,8 +47,13 @@ impl MediaRepository {
             item.width,
             item.height,
             item.bitrate,
+            item.file_modified,
+            item.removed_at,
+            item.full_hash,
             item.created_at,
-            item.updated_at
+            item.updated_at,
+            item.extra_type,
+            item.extra_owner
         )
         .execute(&self.pool)
         .await
@@ -87,7 +97,8 @@ impl MediaRepository {
             UPDATE media_items SET
                 path = $2, file_size = $3, file_hash = $4, media_type = $5,
                 format = $6, duration = $7, width = $8, height = $9,
-                bitrate = $10, updated_at = $11
+                bitrate = $10, file_modified = $11, removed_at = $12, full_hash = $13,
+                updated_at = $14, extra_type = $15, extra_owner = $16
             WHERE id = $1
             "#,
             item.id,
@@ -100,7 +111,12 @@ impl MediaRepository {
             item.width,
             item.height,
             item.bitrate,
-            item.updated_at
+            item.file_modified,
+            item.removed_at,
+            item.full_hash,
+            item.updated_at,
+            item.extra_type,
+            item.extra_owner
         )
         .execute(&self.pool)
         .await
@@ -120,6 +136,9 @@ impl MediaRepository {
     }
 
     /// List media items with pagination
+    ///
+    /// Stacked items are listed once, through the first part of their first
+    /// version. Extras are left out.
     pub async fn list_media_items(
         &self,
         limit: i64,
@@ -129,7 +148,16 @@ impl MediaRepository {
         let items = if let Some(media_type) = media_type {
             sqlx::query_as!(
                 MediaItemModel,
-                "SELECT * FROM media_items WHERE media_type = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+                r#"
+                SELECT * FROM media_items mi
+                WHERE media_type = $1 AND removed_at IS NULL AND extra_type IS NULL
+                    AND (stack_id IS NULL OR id = (
+                        SELECT lead.id FROM media_items lead
+                        WHERE lead.stack_id = mi.stack_id AND lead.removed_at IS NULL
+                        ORDER BY lead.stack_version, lead.stack_part LIMIT 1
+                    ))
+                ORDER BY created_at DESC LIMIT $2 OFFSET $3
+                "#,
                 media_type,
                 limit,
                 offset
@@ -140,7 +168,16 @@ impl MediaRepository {
         } else {
             sqlx::query_as!(
                 MediaItemModel,
-                "SELECT * FROM media_items ORDER BY created_at DESC LIMIT $1 OFFSET $2",
+                r#"
+                SELECT * FROM media_items mi
+                WHERE removed_at IS NULL AND extra_type IS NULL
+                    AND (stack_id IS NULL OR id = (
+                        SELECT lead.id FROM media_items lead
+                        WHERE lead.stack_id = mi.stack_id AND lead.removed_at IS NULL
+                        ORDER BY lead.stack_version, lead.stack_part LIMIT 1
+                    ))
+                ORDER BY created_at DESC LIMIT $1 OFFSET $2
+                "#,
                 limit,
                 offset
             )
@@ -153,6 +190,9 @@ impl MediaRepository {
     }
 
     /// Search media items by title or path
+    ///
+    /// Stacked items are returned once and extras are left out, like in
+    /// `list_media_items`.
     pub async fn search_media_items(&self, query: &str, limit: i64) -> Result<Vec<MediaItemModel>> {
         let search_pattern = format!("%{}%", query);
         
@@ -161,7 +201,12 @@ impl MediaRepository {
             r#"
             SELECT mi.* FROM media_items mi
             LEFT JOIN metadata m ON mi.id = m.media_id
-            WHERE mi.path ILIKE $1 OR m.title ILIKE $1
+            WHERE (mi.path ILIKE $1 OR m.title ILIKE $1) AND mi.removed_at IS NULL AND mi.extra_type IS NULL
+                AND (mi.stack_id IS NULL OR mi.id = (
+                    SELECT lead.id FROM media_items lead
+                    WHERE lead.stack_id = mi.stack_id AND lead.removed_at IS NULL
+                    ORDER BY lead.stack_version, lead.stack_part LIMIT 1
+                ))
             ORDER BY m.title, mi.path
             LIMIT $2
             "#,
@@ -182,7 +227,7 @@ impl MediaRepository {
             r#"
             SELECT mi.* FROM media_items mi
             JOIN libraries l ON mi.path LIKE l.path || '%'
-            WHERE l.id = $1
+            WHERE l.id = $1 AND mi.removed_at IS NULL
             ORDER BY mi.path
             "#,
             library_id
@@ -194,18 +239,1415 @@ impl MediaRepository {
         Ok(items)
     }
 
-    /// Count total media items
+    /// Get the file state of every media item below a path prefix
+    pub async fn get_file_states_by_prefix(&self, prefix: &str) -> Result<Vec<MediaFileStateModel>> {
+        let states = sqlx::query_as!(
+            MediaFileStateModel,
+            r#"
+            SELECT id, path, file_size, file_modified, removed_at
+            FROM media_items
+            WHERE starts_with(path, $1)
+            "#,
+            prefix
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(states)
+    }
+
+    /// Mark media items as removed from their library
+    pub async fn mark_media_items_removed(&self, ids: &[Uuid], removed_at: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query!(
+            "UPDATE media_items SET removed_at = $2 WHERE id = ANY($1) AND removed_at IS NULL",
+            ids,
+            removed_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Get the sidecar files of a media item
+    pub async fn get_sidecars(&self, media_id: MediaId) -> Result<Vec<MediaSidecarModel>> {
+        let sidecars = sqlx::query_as!(
+            MediaSidecarModel,
+            "SELECT * FROM media_sidecars WHERE media_id = $1 ORDER BY path",
+            media_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(sidecars)
+    }
+
+    /// Get the sidecar files of every media item below a path prefix
+    pub async fn get_sidecars_by_prefix(&self, prefix: &str) -> Result<Vec<MediaSidecarModel>> {
+        let sidecars = sqlx::query_as!(
+            MediaSidecarModel,
+            r#"
+            SELECT s.* FROM media_sidecars s
+            JOIN media_items m ON m.id = s.media_id
+            WHERE starts_with(m.path, $1)
+            ORDER BY s.path
+            "#,
+            prefix
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(sidecars)
+    }
+
+    /// Replace the sidecar files of a media item
+    pub async fn set_sidecars(&self, media_id: MediaId, sidecars: &[MediaSidecarModel]) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+
+        sqlx::query!("DELETE FROM media_sidecars WHERE media_id = $1", media_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        for sidecar in sidecars {
+            sqlx::query!(
+                r#"
+                INSERT INTO media_sidecars (
+                    id, media_id, path, kind, language, title, codec,
+                    forced, is_default, created_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                "#,
+                sidecar.id,
+                media_id,
+                sidecar.path,
+                sidecar.kind,
+                sidecar.language,
+                sidecar.title,
+                sidecar.codec,
+                sidecar.forced,
+                sidecar.is_default,
+                sidecar.created_at
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Get the stacked media items below a path prefix
+    pub async fn get_stacked_items_by_prefix(&self, prefix: &str) -> Result<Vec<MediaItemModel>> {
+        let items = sqlx::query_as!(
+            MediaItemModel,
+            "SELECT * FROM media_items WHERE starts_with(path, $1) AND stack_id IS NOT NULL",
+            prefix
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(items)
+    }
+
+    /// Get a stack by ID
+    pub async fn get_stack(&self, id: Uuid) -> Result<Option<MediaStackModel>> {
+        let stack = sqlx::query_as!(
+            MediaStackModel,
+            "SELECT * FROM media_stacks WHERE id = $1",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(stack)
+    }
+
+    /// Get the current items of a stack, ordered by version and part
+    pub async fn get_stack_items(&self, stack_id: Uuid) -> Result<Vec<MediaItemModel>> {
+        let items = sqlx::query_as!(
+            MediaItemModel,
+            r#"
+            SELECT * FROM media_items
+            WHERE stack_id = $1 AND removed_at IS NULL
+            ORDER BY stack_version, stack_part
+            "#,
+            stack_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(items)
+    }
+
+    /// Create or update a stack and replace its members
+    pub async fn save_stack(&self, stack: &MediaStackModel, members: &[StackMemberModel]) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO media_stacks (id, directory, title, year, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (id) DO UPDATE SET
+                directory = EXCLUDED.directory,
+                title = EXCLUDED.title,
+                year = EXCLUDED.year,
+                updated_at = EXCLUDED.updated_at
+            "#,
+            stack.id,
+            stack.directory,
+            stack.title,
+            stack.year,
+            stack.created_at,
+            stack.updated_at
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        let ids = members.iter().map(|member| member.media_id).collect::<Vec<_>>();
+        sqlx::query!(
+            r#"
+            UPDATE media_items SET stack_id = NULL, stack_version = NULL, stack_part = NULL
+            WHERE stack_id = $1 AND NOT (id = ANY($2))
+            "#,
+            stack.id,
+            &ids
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        for member in members {
+            sqlx::query!(
+                "UPDATE media_items SET stack_id = $2, stack_version = $3, stack_part = $4 WHERE id = $1",
+                member.media_id,
+                stack.id,
+                member.version,
+                member.part
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Delete stacks, releasing their members
+    pub async fn delete_stacks(&self, ids: &[Uuid]) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+
+        sqlx::query!(
+            "UPDATE media_items SET stack_id = NULL, stack_version = NULL, stack_part = NULL WHERE stack_id = ANY($1)",
+            ids
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        sqlx::query!("DELETE FROM media_stacks WHERE id = ANY($1)", ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Store an embedded picture under its content hash
+    pub async fn save_cover_art(&self, hash: &str, mime_type: &str, data: &[u8]) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO music_cover_art (hash, mime_type, data)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (hash) DO NOTHING
+            "#,
+            hash,
+            mime_type,
+            data
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Store the tags of a music file, keeping its current album and artist
+    pub async fn save_music_track(&self, track: &MusicTrackModel) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO music_tracks (
+                media_id, title, artist, artist_sort, album_artist, album_artist_sort, album,
+                track_number, track_total, disc_number, disc_total, year, genre, compilation,
+                release_type, musicbrainz_recording_id, musicbrainz_release_track_id,
+                musicbrainz_release_id, musicbrainz_artist_id, musicbrainz_album_artist_id,
+                cover_hash, album_id, artist_id, updated_at
+            ) VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18,
+                $19, $20, $21, $22, $23, $24
+            )
+            ON CONFLICT (media_id) DO UPDATE SET
+                title = EXCLUDED.title,
+                artist = EXCLUDED.artist,
+                artist_sort = EXCLUDED.artist_sort,
+                album_artist = EXCLUDED.album_artist,
+                album_artist_sort = EXCLUDED.album_artist_sort,
+                album = EXCLUDED.album,
+                track_number = EXCLUDED.track_number,
+                track_total = EXCLUDED.track_total,
+                disc_number = EXCLUDED.disc_number,
+                disc_total = EXCLUDED.disc_total,
+                year = EXCLUDED.year,
+                genre = EXCLUDED.genre,
+                compilation = EXCLUDED.compilation,
+                release_type = EXCLUDED.release_type,
+                musicbrainz_recording_id = EXCLUDED.musicbrainz_recording_id,
+                musicbrainz_release_track_id = EXCLUDED.musicbrainz_release_track_id,
+                musicbrainz_release_id = EXCLUDED.musicbrainz_release_id,
+                musicbrainz_artist_id = EXCLUDED.musicbrainz_artist_id,
+                musicbrainz_album_artist_id = EXCLUDED.musicbrainz_album_artist_id,
+                cover_hash = EXCLUDED.cover_hash,
+                updated_at = EXCLUDED.updated_at
+            "#,
+            track.media_id,
+            track.title,
+            track.artist,
+            track.artist_sort,
+            track.album_artist,
+            track.album_artist_sort,
+            track.album,
+            track.track_number,
+            track.track_total,
+            track.disc_number,
+            track.disc_total,
+            track.year,
+            track.genre,
+            track.compilation,
+            track.release_type,
+            track.musicbrainz_recording_id,
+            track.musicbrainz_release_track_id,
+            track.musicbrainz_release_id,
+            track.musicbrainz_artist_id,
+            track.musicbrainz_album_artist_id,
+            track.cover_hash,
+            track.album_id,
+            track.artist_id,
+            track.updated_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Delete the tags stored for a music file
+    pub async fn delete_music_track(&self, media_id: Uuid) -> Result<()> {
+        sqlx::query!("DELETE FROM music_tracks WHERE media_id = $1", media_id)
+            .execute(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Get the tags of every music file still in a library, with its path
+    pub async fn get_music_tracks(&self) -> Result<Vec<(String, MusicTrackModel)>> {
+        let tracks = sqlx::query_as!(
+            MusicTrackModel,
+            r#"
+            SELECT mt.* FROM music_tracks mt
+            JOIN media_items mi ON mi.id = mt.media_id
+            WHERE mi.removed_at IS NULL
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        let paths = sqlx::query!(
+            r#"
+            SELECT mi.id, mi.path FROM media_items mi
+            JOIN music_tracks mt ON mt.media_id = mi.id
+            WHERE mi.removed_at IS NULL
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?
+        .into_iter()
+        .map(|row| (row.id, row.path))
+        .collect::<std::collections::HashMap<_, _>>();
+
+        Ok(tracks
+            .into_iter()
+            .filter_map(|track| Some((paths.get(&track.media_id)?.clone(), track)))
+            .collect())
+    }
+
+    /// Get the tags of the music files at the given paths, including removed
+    /// ones, with the album and artist they are linked to
+    pub async fn get_music_tracks_by_paths(&self, paths: &[String]) -> Result<Vec<MusicTrackModel>> {
+        let tracks = sqlx::query_as!(
+            MusicTrackModel,
+            r#"
+            SELECT mt.* FROM music_tracks mt
+            JOIN media_items mi ON mi.id = mt.media_id
+            WHERE mi.path = ANY($1)
+            "#,
+            paths
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(tracks)
+    }
+
+    /// Get the tags of the music files still in a library that are linked to
+    /// one of the albums, or share one of the album titles or MusicBrainz
+    /// releases, with their paths
+    ///
+    /// Titles and releases are compared case-insensitively.
+    pub async fn get_music_tracks_by_albums(
+        &self,
+        album_ids: &[Uuid],
+        titles: &[String],
+        release_ids: &[String],
+    ) -> Result<Vec<(String, MusicTrackModel)>> {
+        let tracks = sqlx::query_as!(
+            MusicTrackModel,
+            r#"
+            SELECT mt.* FROM music_tracks mt
+            JOIN media_items mi ON mi.id = mt.media_id
+            WHERE mi.removed_at IS NULL
+              AND (
+                mt.album_id = ANY($1)
+                OR lower(btrim(mt.album)) IN (SELECT lower(title) FROM unnest($2::text[]) title)
+                OR lower(mt.musicbrainz_release_id) IN (SELECT lower(release) FROM unnest($3::text[]) release)
+              )
+            "#,
+            album_ids,
+            titles,
+            release_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        let ids = tracks.iter().map(|track| track.media_id).collect::<Vec<_>>();
+        let paths = self.media_paths(&ids).await?;
+        Ok(tracks
+            .into_iter()
+            .filter_map(|track| Some((paths.get(&track.media_id)?.clone(), track)))
+            .collect())
+    }
+
+    /// Get the paths of media items by id
+    async fn media_paths(&self, ids: &[Uuid]) -> Result<std::collections::HashMap<Uuid, String>> {
+        let paths = sqlx::query!("SELECT id, path FROM media_items WHERE id = ANY($1)", ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?
+            .into_iter()
+            .map(|row| (row.id, row.path))
+            .collect();
+
+        Ok(paths)
+    }
+
+    /// Get every music album
+    pub async fn get_music_albums(&self) -> Result<Vec<MusicAlbumModel>> {
+        let albums = sqlx::query_as!(MusicAlbumModel, "SELECT * FROM music_albums ORDER BY title")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(albums)
+    }
+
+    /// Get every music artist
+    pub async fn get_music_artists(&self) -> Result<Vec<MusicArtistModel>> {
+        let artists = sqlx::query_as!(MusicArtistModel, "SELECT * FROM music_artists ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(artists)
+    }
+
+    /// Replace the albums and artists of the music libraries
+    ///
+    /// Albums and artists missing from the lists are deleted, along with
+    /// cover art nothing refers to any more.
+    pub async fn save_music_library(
+        &self,
+        artists: &[MusicArtistModel],
+        albums: &[MusicAlbumModel],
+        links: &[MusicTrackLinkModel],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+        Self::upsert_music_library(&mut tx, artists, albums, links).await?;
+
+        let album_ids = albums.iter().map(|album| album.id).collect::<Vec<_>>();
+        let artist_ids = artists.iter().map(|artist| artist.id).collect::<Vec<_>>();
+        sqlx::query!("DELETE FROM music_albums WHERE id <> ALL($1)", &album_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        sqlx::query!("DELETE FROM music_artists WHERE id <> ALL($1)", &artist_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        Self::delete_unused_cover_art(&mut tx).await?;
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Replace some albums of the music libraries after their tracks were regrouped
+    ///
+    /// Albums in `replaced` missing from `albums` are deleted, as are artists
+    /// and cover art nothing refers to any more.
+    pub async fn save_music_albums(
+        &self,
+        artists: &[MusicArtistModel],
+        albums: &[MusicAlbumModel],
+        links: &[MusicTrackLinkModel],
+        replaced: &[Uuid],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+        Self::upsert_music_library(&mut tx, artists, albums, links).await?;
+
+        let album_ids = albums.iter().map(|album| album.id).collect::<Vec<_>>();
+        sqlx::query!("DELETE FROM music_albums WHERE id = ANY($1) AND id <> ALL($2)", replaced, &album_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        sqlx::query!(
+            r#"
+            DELETE FROM music_artists ar
+            WHERE NOT EXISTS (SELECT 1 FROM music_albums a WHERE a.artist_id = ar.id)
+              AND NOT EXISTS (SELECT 1 FROM music_tracks t WHERE t.artist_id = ar.id)
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RustFlixError::from)?;
+        Self::delete_unused_cover_art(&mut tx).await?;
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Insert or update artists and albums and link tracks to them
+    async fn upsert_music_library(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        artists: &[MusicArtistModel],
+        albums: &[MusicAlbumModel],
+        links: &[MusicTrackLinkModel],
+    ) -> Result<()> {
+        for artist in artists {
+            sqlx::query!(
+                r#"
+                INSERT INTO music_artists (id, group_key, name, sort_name, musicbrainz_id, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (id) DO UPDATE SET
+                    group_key = EXCLUDED.group_key,
+                    name = EXCLUDED.name,
+                    sort_name = EXCLUDED.sort_name,
+                    musicbrainz_id = EXCLUDED.musicbrainz_id,
+                    updated_at = EXCLUDED.updated_at
+                "#,
+                artist.id,
+                artist.group_key,
+                artist.name,
+                artist.sort_name,
+                artist.musicbrainz_id,
+                artist.created_at,
+                artist.updated_at
+            )
+            .execute(&mut **tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        for album in albums {
+            sqlx::query!(
+                r#"
+                INSERT INTO music_albums (
+                    id, group_key, title, artist_id, album_type, is_compilation, year, genre,
+                    disc_count, track_count, musicbrainz_id, cover_hash, created_at, updated_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+                ON CONFLICT (id) DO UPDATE SET
+                    group_key = EXCLUDED.group_key,
+                    title = EXCLUDED.title,
+                    artist_id = EXCLUDED.artist_id,
+                    album_type = EXCLUDED.album_type,
+                    is_compilation = EXCLUDED.is_compilation,
+                    year = EXCLUDED.year,
+                    genre = EXCLUDED.genre,
+                    disc_count = EXCLUDED.disc_count,
+                    track_count = EXCLUDED.track_count,
+                    musicbrainz_id = EXCLUDED.musicbrainz_id,
+                    cover_hash = EXCLUDED.cover_hash,
+                    updated_at = EXCLUDED.updated_at
+                "#,
+                album.id,
+                album.group_key,
+                album.title,
+                album.artist_id,
+                album.album_type,
+                album.is_compilation,
+                album.year,
+                album.genre,
+                album.disc_count,
+                album.track_count,
+                album.musicbrainz_id,
+                album.cover_hash,
+                album.created_at,
+                album.updated_at
+            )
+            .execute(&mut **tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        for link in links {
+            sqlx::query!(
+                "UPDATE music_tracks SET album_id = $2, artist_id = $3 WHERE media_id = $1",
+                link.media_id,
+                link.album_id,
+                link.artist_id
+            )
+            .execute(&mut **tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        Ok(())
+    }
+
+    /// Delete cover art no track, album, audiobook file or book refers to
+    async fn delete_unused_cover_art(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM music_cover_art c
+            WHERE NOT EXISTS (SELECT 1 FROM music_tracks t WHERE t.cover_hash = c.hash)
+              AND NOT EXISTS (SELECT 1 FROM music_albums a WHERE a.cover_hash = c.hash)
+              AND NOT EXISTS (SELECT 1 FROM audiobook_files f WHERE f.cover_hash = c.hash)
+              AND NOT EXISTS (SELECT 1 FROM audiobooks b WHERE b.cover_hash = c.hash)
+            "#
+        )
+        .execute(&mut **tx)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Store the tags of an audiobook file, keeping its current book
+    pub async fn save_audiobook_file(&self, file: &AudiobookFileModel) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO audiobook_files (
+                media_id, title, author, narrator, book, series, series_part, track_number,
+                disc_number, year, genre, cover_hash, book_id, part_number, updated_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+            ON CONFLICT (media_id) DO UPDATE SET
+                title = EXCLUDED.title,
+                author = EXCLUDED.author,
+                narrator = EXCLUDED.narrator,
+                book = EXCLUDED.book,
+                series = EXCLUDED.series,
+                series_part = EXCLUDED.series_part,
+                track_number = EXCLUDED.track_number,
+                disc_number = EXCLUDED.disc_number,
+                year = EXCLUDED.year,
+                genre = EXCLUDED.genre,
+                cover_hash = EXCLUDED.cover_hash,
+                updated_at = EXCLUDED.updated_at
+            "#,
+            file.media_id,
+            file.title,
+            file.author,
+            file.narrator,
+            file.book,
+            file.series,
+            file.series_part,
+            file.track_number,
+            file.disc_number,
+            file.year,
+            file.genre,
+            file.cover_hash,
+            file.book_id,
+            file.part_number,
+            file.updated_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Get the tags of every audiobook file still in a library, with its path
+    pub async fn get_audiobook_files(&self) -> Result<Vec<(String, AudiobookFileModel)>> {
+        let files = sqlx::query_as!(
+            AudiobookFileModel,
+            r#"
+            SELECT af.* FROM audiobook_files af
+            JOIN media_items mi ON mi.id = af.media_id
+            WHERE mi.removed_at IS NULL
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        let paths = sqlx::query!(
+            r#"
+            SELECT mi.id, mi.path FROM media_items mi
+            JOIN audiobook_files af ON af.media_id = mi.id
+            WHERE mi.removed_at IS NULL
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?
+        .into_iter()
+        .map(|row| (row.id, row.path))
+        .collect::<std::collections::HashMap<_, _>>();
+
+        Ok(files
+            .into_iter()
+            .filter_map(|file| Some((paths.get(&file.media_id)?.clone(), file)))
+            .collect())
+    }
+
+    /// Get the tags of the audiobook files at the given paths, including
+    /// removed ones, with the book they are linked to
+    pub async fn get_audiobook_files_by_paths(&self, paths: &[String]) -> Result<Vec<AudiobookFileModel>> {
+        let files = sqlx::query_as!(
+            AudiobookFileModel,
+            r#"
+            SELECT af.* FROM audiobook_files af
+            JOIN media_items mi ON mi.id = af.media_id
+            WHERE mi.path = ANY($1)
+            "#,
+            paths
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(files)
+    }
+
+    /// Get the tags of the audiobook files still in a library whose paths
+    /// start with one of the prefixes, with their paths
+    pub async fn get_audiobook_files_by_prefixes(&self, prefixes: &[String]) -> Result<Vec<(String, AudiobookFileModel)>> {
+        let files = sqlx::query_as!(
+            AudiobookFileModel,
+            r#"
+            SELECT af.* FROM audiobook_files af
+            JOIN media_items mi ON mi.id = af.media_id
+            WHERE mi.removed_at IS NULL
+              AND EXISTS (SELECT 1 FROM unnest($1::text[]) prefix WHERE starts_with(mi.path, prefix))
+            "#,
+            prefixes
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        let ids = files.iter().map(|file| file.media_id).collect::<Vec<_>>();
+        let paths = self.media_paths(&ids).await?;
+        Ok(files
+            .into_iter()
+            .filter_map(|file| Some((paths.get(&file.media_id)?.clone(), file)))
+            .collect())
+    }
+
+    /// Get the tags of an audiobook file and the book it was grouped into
+    pub async fn get_audiobook_file(&self, media_id: Uuid) -> Result<Option<AudiobookFileModel>> {
+        let file = sqlx::query_as!(AudiobookFileModel, "SELECT * FROM audiobook_files WHERE media_id = $1", media_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(file)
+    }
+
+    /// Get every audiobook
+    pub async fn get_audiobooks(&self) -> Result<Vec<AudiobookModel>> {
+        let books = sqlx::query_as!(AudiobookModel, "SELECT * FROM audiobooks ORDER BY title")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(books)
+    }
+
+    /// Get an audiobook by id
+    pub async fn get_audiobook(&self, id: Uuid) -> Result<Option<AudiobookModel>> {
+        let book = sqlx::query_as!(AudiobookModel, "SELECT * FROM audiobooks WHERE id = $1", id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(book)
+    }
+
+    /// Get the files of an audiobook still in a library, in reading order
+    pub async fn get_audiobook_parts(&self, book_id: Uuid) -> Result<Vec<MediaItemModel>> {
+        let items = sqlx::query_as!(
+            MediaItemModel,
+            r#"
+            SELECT mi.* FROM media_items mi
+            JOIN audiobook_files af ON af.media_id = mi.id
+            WHERE af.book_id = $1 AND mi.removed_at IS NULL
+            ORDER BY af.part_number
+            "#,
+            book_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(items)
+    }
+
+    /// Replace the books of the audiobook libraries
+    ///
+    /// Books missing from the list are deleted, along with cover art nothing
+    /// refers to any more.
+    pub async fn save_audiobook_library(
+        &self,
+        books: &[AudiobookModel],
+        links: &[AudiobookFileLinkModel],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+        Self::upsert_audiobook_library(&mut tx, books, links).await?;
+
+        let book_ids = books.iter().map(|book| book.id).collect::<Vec<_>>();
+        sqlx::query!("DELETE FROM audiobooks WHERE id <> ALL($1)", &book_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        Self::delete_unused_cover_art(&mut tx).await?;
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Replace some books of the audiobook libraries after their files were regrouped
+    ///
+    /// Books in `replaced` missing from `books` are deleted, along with cover
+    /// art nothing refers to any more.
+    pub async fn save_audiobooks(
+        &self,
+        books: &[AudiobookModel],
+        links: &[AudiobookFileLinkModel],
+        replaced: &[Uuid],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+        Self::upsert_audiobook_library(&mut tx, books, links).await?;
+
+        let book_ids = books.iter().map(|book| book.id).collect::<Vec<_>>();
+        sqlx::query!("DELETE FROM audiobooks WHERE id = ANY($1) AND id <> ALL($2)", replaced, &book_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        Self::delete_unused_cover_art(&mut tx).await?;
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Insert or update books and link files to them
+    async fn upsert_audiobook_library(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        books: &[AudiobookModel],
+        links: &[AudiobookFileLinkModel],
+    ) -> Result<()> {
+        for book in books {
+            sqlx::query!(
+                r#"
+                INSERT INTO audiobooks (
+                    id, group_key, title, author, narrator, series, series_part, year, genre,
+                    part_count, cover_hash, created_at, updated_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                ON CONFLICT (id) DO UPDATE SET
+                    group_key = EXCLUDED.group_key,
+                    title = EXCLUDED.title,
+                    author = EXCLUDED.author,
+                    narrator = EXCLUDED.narrator,
+                    series = EXCLUDED.series,
+                    series_part = EXCLUDED.series_part,
+                    year = EXCLUDED.year,
+                    genre = EXCLUDED.genre,
+                    part_count = EXCLUDED.part_count,
+                    cover_hash = EXCLUDED.cover_hash,
+                    updated_at = EXCLUDED.updated_at
+                "#,
+                book.id,
+                book.group_key,
+                book.title,
+                book.author,
+                book.narrator,
+                book.series,
+                book.series_part,
+                book.year,
+                book.genre,
+                book.part_count,
+                book.cover_hash,
+                book.created_at,
+                book.updated_at
+            )
+            .execute(&mut **tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        for link in links {
+            sqlx::query!(
+                "UPDATE audiobook_files SET book_id = $2, part_number = $3 WHERE media_id = $1",
+                link.media_id,
+                link.book_id,
+                link.part_number
+            )
+            .execute(&mut **tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        Ok(())
+    }
+
+    /// Store the details of a comic or e-book
+    pub async fn save_book(&self, book: &BookModel) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO books (
+                media_id, title, author, series, series_index, publisher, language, description,
+                year, page_count, updated_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (media_id) DO UPDATE SET
+                title = EXCLUDED.title,
+                author = EXCLUDED.author,
+                series = EXCLUDED.series,
+                series_index = EXCLUDED.series_index,
+                publisher = EXCLUDED.publisher,
+                language = EXCLUDED.language,
+                description = EXCLUDED.description,
+                year = EXCLUDED.year,
+                page_count = EXCLUDED.page_count,
+                updated_at = EXCLUDED.updated_at
+            "#,
+            book.media_id,
+            book.title,
+            book.author,
+            book.series,
+            book.series_index,
+            book.publisher,
+            book.language,
+            book.description,
+            book.year,
+            book.page_count,
+            book.updated_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Get the details of a comic or e-book
+    pub async fn get_book(&self, media_id: Uuid) -> Result<Option<BookModel>> {
+        let book = sqlx::query_as!(BookModel, "SELECT * FROM books WHERE media_id = $1", media_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(book)
+    }
+
+    /// Get the comics and e-books still in a library, with their paths, by
+    /// series and title
+    pub async fn get_books(&self, limit: i64, offset: i64) -> Result<Vec<(String, BookModel)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT mi.path, b.media_id, b.title, b.author, b.series, b.series_index, b.publisher,
+                   b.language, b.description, b.year, b.page_count, b.updated_at
+            FROM books b
+            JOIN media_items mi ON mi.id = b.media_id
+            WHERE mi.removed_at IS NULL
+            ORDER BY b.series NULLS LAST, b.series_index, COALESCE(b.title, mi.path)
+            LIMIT $1 OFFSET $2
+            "#,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let book = BookModel {
+                    media_id: row.media_id,
+                    title: row.title,
+                    author: row.author,
+                    series: row.series,
+                    series_index: row.series_index,
+                    publisher: row.publisher,
+                    language: row.language,
+                    description: row.description,
+                    year: row.year,
+                    page_count: row.page_count,
+                    updated_at: row.updated_at,
+                };
+                (row.path, book)
+            })
+            .collect())
+    }
+
+    /// Store the EXIF details of a photo
+    pub async fn save_photo(&self, photo: &PhotoModel) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO photos (
+                media_id, taken_at, has_exif_date, width, height, camera_make, camera_model,
+                lens_model, orientation, latitude, longitude, altitude, updated_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            ON CONFLICT (media_id) DO UPDATE SET
+                taken_at = EXCLUDED.taken_at,
+                has_exif_date = EXCLUDED.has_exif_date,
+                width = EXCLUDED.width,
+                height = EXCLUDED.height,
+                camera_make = EXCLUDED.camera_make,
+                camera_model = EXCLUDED.camera_model,
+                lens_model = EXCLUDED.lens_model,
+                orientation = EXCLUDED.orientation,
+                latitude = EXCLUDED.latitude,
+                longitude = EXCLUDED.longitude,
+                altitude = EXCLUDED.altitude,
+                updated_at = EXCLUDED.updated_at
+            "#,
+            photo.media_id,
+            photo.taken_at,
+            photo.has_exif_date,
+            photo.width,
+            photo.height,
+            photo.camera_make,
+            photo.camera_model,
+            photo.lens_model,
+            photo.orientation,
+            photo.latitude,
+            photo.longitude,
+            photo.altitude,
+            photo.updated_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Store a processed image with its variants, replacing earlier variants
+    pub async fn save_image(&self, image: &ImageModel, variants: &[ImageVariantModel]) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO images (hash, width, height, blurhash, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (hash) DO UPDATE SET
+                width = EXCLUDED.width,
+                height = EXCLUDED.height,
+                blurhash = EXCLUDED.blurhash
+            "#,
+            image.hash,
+            image.width,
+            image.height,
+            image.blurhash,
+            image.created_at
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        sqlx::query!("DELETE FROM image_variants WHERE hash = $1", image.hash)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        for variant in variants {
+            sqlx::query!(
+                "INSERT INTO image_variants (hash, width, height, format, path) VALUES ($1, $2, $3, $4, $5)",
+                variant.hash,
+                variant.width,
+                variant.height,
+                variant.format,
+                variant.path
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Get an image by content hash
+    pub async fn get_image(&self, hash: &str) -> Result<Option<ImageModel>> {
+        let image = sqlx::query_as!(ImageModel, "SELECT * FROM images WHERE hash = $1", hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(image)
+    }
+
+    /// Get the variants of an image, smallest first
+    pub async fn get_image_variants(&self, hash: &str) -> Result<Vec<ImageVariantModel>> {
+        let variants = sqlx::query_as!(
+            ImageVariantModel,
+            "SELECT * FROM image_variants WHERE hash = $1 ORDER BY width, height, format",
+            hash
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(variants)
+    }
+
+    /// Get the images of a media item
+    pub async fn get_media_images(&self, media_id: Uuid) -> Result<Vec<MediaImageModel>> {
+        let images = sqlx::query_as!(
+            MediaImageModel,
+            "SELECT * FROM media_images WHERE media_id = $1 ORDER BY kind",
+            media_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(images)
+    }
+
+    /// Get the images of every media item under a path prefix
+    pub async fn get_media_images_by_prefix(&self, prefix: &str) -> Result<Vec<MediaImageModel>> {
+        let images = sqlx::query_as!(
+            MediaImageModel,
+            r#"
+            SELECT i.* FROM media_images i
+            JOIN media_items m ON m.id = i.media_id
+            WHERE starts_with(m.path, $1)
+            "#,
+            prefix
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(images)
+    }
+
+    /// Replace the images of a media item
+    pub async fn set_media_images(&self, media_id: Uuid, images: &[MediaImageModel]) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+
+        sqlx::query!("DELETE FROM media_images WHERE media_id = $1", media_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        for image in images {
+            sqlx::query!(
+                "INSERT INTO media_images (media_id, kind, hash) VALUES ($1, $2, $3)",
+                media_id,
+                image.kind,
+                image.hash
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Unlink the images of media items, e.g. once they are removed
+    pub async fn delete_media_images(&self, media_ids: &[Uuid]) -> Result<()> {
+        sqlx::query!("DELETE FROM media_images WHERE media_id = ANY($1)", media_ids)
+            .execute(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Delete images no media item links to, returning their variants so
+    /// that the files can be removed
+    pub async fn delete_orphaned_images(&self) -> Result<Vec<ImageVariantModel>> {
+        // The select sees the variants as they were before the cascade
+        let variants = sqlx::query_as!(
+            ImageVariantModel,
+            r#"
+            WITH orphaned AS (
+                DELETE FROM images
+                WHERE NOT EXISTS (SELECT 1 FROM media_images i WHERE i.hash = images.hash)
+                RETURNING hash
+            )
+            SELECT v.hash AS "hash!", v.width AS "width!", v.height AS "height!",
+                v.format AS "format!", v.path AS "path!"
+            FROM image_variants v
+            JOIN orphaned o ON o.hash = v.hash
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(variants)
+    }
+
+    /// Replace the chapters of a media item
+    pub async fn set_chapters(&self, media_id: Uuid, chapters: &[MediaChapterModel]) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+
+        sqlx::query!("DELETE FROM media_chapters WHERE media_id = $1", media_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        for chapter in chapters {
+            sqlx::query!(
+                r#"
+                INSERT INTO media_chapters (media_id, chapter_index, start_time, end_time, title)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+                media_id,
+                chapter.chapter_index,
+                chapter.start_time,
+                chapter.end_time,
+                chapter.title
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Get the chapters of a media item in playback order
+    pub async fn get_chapters(&self, media_id: Uuid) -> Result<Vec<MediaChapterModel>> {
+        let chapters = sqlx::query_as!(
+            MediaChapterModel,
+            "SELECT * FROM media_chapters WHERE media_id = $1 ORDER BY chapter_index",
+            media_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(chapters)
+    }
+
+    /// Get the EXIF details of a photo
+    pub async fn get_photo(&self, media_id: Uuid) -> Result<Option<PhotoModel>> {
+        let photo = sqlx::query_as!(PhotoModel, "SELECT * FROM photos WHERE media_id = $1", media_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(photo)
+    }
+
+    /// Count photos per year, month or day (`unit`), newest first
+    ///
+    /// Only photos taken within `[from, to)` are counted when bounds are given.
+    pub async fn get_photo_timeline(
+        &self,
+        unit: &str,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+    ) -> Result<Vec<(NaiveDateTime, i64)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT date_trunc($1, p.taken_at) AS "period!", COUNT(*) AS "count!"
+            FROM photos p
+            JOIN media_items mi ON mi.id = p.media_id
+            WHERE mi.removed_at IS NULL
+              AND ($2::timestamp IS NULL OR p.taken_at >= $2)
+              AND ($3::timestamp IS NULL OR p.taken_at < $3)
+            GROUP BY 1
+            ORDER BY 1 DESC
+            "#,
+            unit,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(rows.into_iter().map(|row| (row.period, row.count)).collect())
+    }
+
+    /// Get the photos taken within `[from, to)`, newest first, with their paths
+    pub async fn get_photos_taken_between(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<(String, PhotoModel)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT mi.path, p.* FROM photos p
+            JOIN media_items mi ON mi.id = p.media_id
+            WHERE mi.removed_at IS NULL AND p.taken_at >= $1 AND p.taken_at < $2
+            ORDER BY p.taken_at DESC, mi.path
+            LIMIT $3 OFFSET $4
+            "#,
+            from,
+            to,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let photo = PhotoModel {
+                    media_id: row.media_id,
+                    taken_at: row.taken_at,
+                    has_exif_date: row.has_exif_date,
+                    width: row.width,
+                    height: row.height,
+                    camera_make: row.camera_make,
+                    camera_model: row.camera_model,
+                    lens_model: row.lens_model,
+                    orientation: row.orientation,
+                    latitude: row.latitude,
+                    longitude: row.longitude,
+                    altitude: row.altitude,
+                    updated_at: row.updated_at,
+                };
+                (row.path, photo)
+            })
+            .collect())
+    }
+
+    /// Count the photos below a folder
+    pub async fn count_photos_by_prefix(&self, prefix: &str) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM photos p
+            JOIN media_items mi ON mi.id = p.media_id
+            WHERE mi.removed_at IS NULL AND starts_with(mi.path, $1)
+            "#,
+            prefix
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(count)
+    }
+
+    /// Get the photos below a folder, with their paths
+    pub async fn get_photos_by_prefix(&self, prefix: &str) -> Result<Vec<(String, PhotoModel)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT mi.path, p.* FROM photos p
+            JOIN media_items mi ON mi.id = p.media_id
+            WHERE mi.removed_at IS NULL AND starts_with(mi.path, $1)
+            ORDER BY mi.path
+            "#,
+            prefix
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let photo = PhotoModel {
+                    media_id: row.media_id,
+                    taken_at: row.taken_at,
+                    has_exif_date: row.has_exif_date,
+                    width: row.width,
+                    height: row.height,
+                    camera_make: row.camera_make,
+                    camera_model: row.camera_model,
+                    lens_model: row.lens_model,
+                    orientation: row.orientation,
+                    latitude: row.latitude,
+                    longitude: row.longitude,
+                    altitude: row.altitude,
+                    updated_at: row.updated_at,
+                };
+                (row.path, photo)
+            })
+            .collect())
+    }
+
+    /// Get the extras belonging to the movie or show folders given
+    pub async fn get_extras(&self, owners: &[String]) -> Result<Vec<MediaItemModel>> {
+        let items = sqlx::query_as!(
+            MediaItemModel,
+            r#"
+            SELECT * FROM media_items
+            WHERE extra_owner = ANY($1) AND extra_type IS NOT NULL AND removed_at IS NULL
+            ORDER BY extra_type, path
+            "#,
+            owners
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(items)
+    }
+
+    /// Count media items, leaving out extras
     pub async fn count_media_items(&self, media_type: Option<&str>) -> Result<i64> {
         let count = if let Some(media_type) = media_type {
             sqlx::query_scalar!(
-                "SELECT COUNT(*) FROM media_items WHERE media_type = $1",
+                "SELECT COUNT(*) FROM media_items WHERE media_type = $1 AND removed_at IS NULL AND extra_type IS NULL",
                 media_type
             )
             .fetch_one(&self.pool)
             .await
             .map_err(RustFlixError::from)?
         } else {
-            sqlx::query_scalar!("SELECT COUNT(*) FROM media_items")
+            sqlx::query_scalar!("SELECT COUNT(*) FROM media_items WHERE removed_at IS NULL AND extra_type IS NULL")
                 .fetch_one(&self.pool)
                 .await
                 .map_err(RustFlixError::from)?
@@ -315,6 +1757,42 @@ impl MediaRepository {
         Ok(count.unwrap_or(0) > 0)
     }
 
+    /// Get removed media items matching any of the given file hashes
+    pub async fn get_removed_media_items_by_hashes(&self, hashes: &[String]) -> Result<Vec<MediaItemModel>> {
+        let items = sqlx::query_as!(
+            MediaItemModel,
+            "SELECT * FROM media_items WHERE file_hash = ANY($1) AND removed_at IS NOT NULL ORDER BY removed_at DESC",
+            hashes
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(items)
+    }
+
+    /// Get media items sharing their file hash with another item, ordered by hash
+    pub async fn get_duplicate_media_items(&self) -> Result<Vec<MediaItemModel>> {
+        let items = sqlx::query_as!(
+            MediaItemModel,
+            r#"
+            SELECT * FROM media_items
+            WHERE removed_at IS NULL AND file_hash IN (
+                SELECT file_hash FROM media_items
+                WHERE removed_at IS NULL AND file_hash IS NOT NULL
+                GROUP BY file_hash
+                HAVING COUNT(*) > 1
+            )
+            ORDER BY file_hash, created_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(items)
+    }
+
     /// Get media items that need metadata refresh
     pub async fn get_items_needing_metadata(&self, limit: i64) -> Result<Vec<MediaItemModel>> {
         let items = sqlx::query_as!(
@@ -322,7 +1800,8 @@ impl MediaRepository {
             r#"
             SELECT mi.* FROM media_items mi
             LEFT JOIN metadata m ON mi.id = m.media_id
-            WHERE m.id IS NULL OR m.updated_at < NOW() - INTERVAL '7 days'
+            WHERE (m.id IS NULL OR m.updated_at < NOW() - INTERVAL '7 days')
+              AND mi.removed_at IS NULL AND mi.extra_type IS NULL
             ORDER BY mi.created_at DESC
             LIMIT $1
             "#,
@@ -334,6 +1813,240 @@ impl MediaRepository {
 
         Ok(items)
     }
+
+    /// Get media items whose path lies in no library, e.g. after their
+    /// library was deleted
+    pub async fn get_media_items_outside_libraries(&self) -> Result<Vec<MediaItemModel>> {
+        let items = sqlx::query_as!(
+            MediaItemModel,
+            r#"
+            SELECT mi.* FROM media_items mi
+            WHERE mi.removed_at IS NULL
+              AND NOT EXISTS (SELECT 1 FROM libraries l WHERE starts_with(mi.path, l.path))
+            ORDER BY mi.path
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(items)
+    }
+
+    /// Get stacks no media item belongs to
+    pub async fn get_empty_stacks(&self) -> Result<Vec<MediaStackModel>> {
+        let stacks = sqlx::query_as!(
+            MediaStackModel,
+            r#"
+            SELECT s.* FROM media_stacks s
+            WHERE NOT EXISTS (SELECT 1 FROM media_items mi WHERE mi.stack_id = s.id)
+            ORDER BY s.directory
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(stacks)
+    }
+
+    /// Get audiobooks no file is grouped into
+    pub async fn get_empty_audiobooks(&self) -> Result<Vec<AudiobookModel>> {
+        let books = sqlx::query_as!(
+            AudiobookModel,
+            r#"
+            SELECT a.* FROM audiobooks a
+            WHERE NOT EXISTS (SELECT 1 FROM audiobook_files af WHERE af.book_id = a.id)
+            ORDER BY a.group_key
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(books)
+    }
+
+    /// Delete audiobooks, leaving their files ungrouped
+    pub async fn delete_audiobooks(&self, ids: &[Uuid]) -> Result<u64> {
+        let result = sqlx::query!("DELETE FROM audiobooks WHERE id = ANY($1)", ids)
+            .execute(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Get the hashes of images no media item links to
+    pub async fn get_orphaned_image_hashes(&self) -> Result<Vec<String>> {
+        let hashes = sqlx::query_scalar!(
+            r#"
+            SELECT hash FROM images
+            WHERE NOT EXISTS (SELECT 1 FROM media_images i WHERE i.hash = images.hash)
+            ORDER BY hash
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(hashes)
+    }
+
+    /// Record the start of a library audit
+    pub async fn create_audit(&self, audit: &LibraryAuditModel) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO library_audits (
+                id, status, items_checked, issue_count, error_message,
+                started_at, finished_at, cleaned_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            audit.id,
+            audit.status,
+            audit.items_checked,
+            audit.issue_count,
+            audit.error_message,
+            audit.started_at,
+            audit.finished_at,
+            audit.cleaned_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Record the outcome of a library audit with the issues it found
+    pub async fn finish_audit(&self, audit: &LibraryAuditModel, issues: &[AuditIssueModel]) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+
+        sqlx::query!(
+            r#"
+            UPDATE library_audits SET
+                status = $2, items_checked = $3, issue_count = $4,
+                error_message = $5, finished_at = $6
+            WHERE id = $1
+            "#,
+            audit.id,
+            audit.status,
+            audit.items_checked,
+            audit.issue_count,
+            audit.error_message,
+            audit.finished_at
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        for issue in issues {
+            sqlx::query!(
+                r#"
+                INSERT INTO library_audit_issues (
+                    id, audit_id, kind, library_id, media_id, row_id, path, detail, resolved_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                "#,
+                issue.id,
+                issue.audit_id,
+                issue.kind,
+                issue.library_id,
+                issue.media_id,
+                issue.row_id,
+                issue.path,
+                issue.detail,
+                issue.resolved_at
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+        }
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
+
+    /// Mark audits left running, e.g. by a restart, as failed
+    pub async fn fail_running_audits(&self, error_message: &str) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE library_audits SET status = 'failed', error_message = $1, finished_at = NOW()
+            WHERE status = 'running'
+            "#,
+            error_message
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Get a library audit by id
+    pub async fn get_audit(&self, id: Uuid) -> Result<Option<LibraryAuditModel>> {
+        let audit = sqlx::query_as!(LibraryAuditModel, "SELECT * FROM library_audits WHERE id = $1", id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        Ok(audit)
+    }
+
+    /// List library audits, latest first
+    pub async fn list_audits(&self, limit: i64) -> Result<Vec<LibraryAuditModel>> {
+        let audits = sqlx::query_as!(
+            LibraryAuditModel,
+            "SELECT * FROM library_audits ORDER BY started_at DESC LIMIT $1",
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(audits)
+    }
+
+    /// Get the issues of an audit, optionally of one kind
+    pub async fn get_audit_issues(&self, audit_id: Uuid, kind: Option<&str>) -> Result<Vec<AuditIssueModel>> {
+        let issues = sqlx::query_as!(
+            AuditIssueModel,
+            r#"
+            SELECT * FROM library_audit_issues
+            WHERE audit_id = $1 AND ($2::TEXT IS NULL OR kind = $2)
+            ORDER BY kind, path
+            "#,
+            audit_id,
+            kind
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(issues)
+    }
+
+    /// Mark audit issues as resolved by cleanup, recording when it ran
+    pub async fn resolve_audit_issues(&self, audit_id: Uuid, ids: &[Uuid], resolved_at: DateTime<Utc>) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(RustFlixError::from)?;
+
+        sqlx::query!(
+            "UPDATE library_audit_issues SET resolved_at = $3 WHERE audit_id = $1 AND id = ANY($2)",
+            audit_id,
+            ids,
+            resolved_at
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        sqlx::query!("UPDATE library_audits SET cleaned_at = $2 WHERE id = $1", audit_id, resolved_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(RustFlixError::from)?;
+
+        tx.commit().await.map_err(RustFlixError::from)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
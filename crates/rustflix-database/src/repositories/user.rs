@@ -1,7 +1,7 @@
 //! User repository for database operations
 
 use rustflix_core::{Result, RustFlixError, UserId};
-use crate::models::{UserModel, UserSessionModel, PlaybackStateModel, UserRatingModel, WatchHistoryModel};
+use crate::models::{UserModel, UserSessionModel, PlaybackStateModel, ReadingProgressModel, UserRatingModel, WatchHistoryModel};
 use sqlx::PgPool;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
@@ -262,6 +262,49 @@ impl UserRepository {
         Ok(state)
     }
 
+    /// Upsert reading progress
+    pub async fn upsert_reading_progress(&self, progress: &ReadingProgressModel) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO reading_progress (user_id, media_id, page, location, progress, is_finished, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (user_id, media_id) DO UPDATE SET
+                page = EXCLUDED.page,
+                location = EXCLUDED.location,
+                progress = EXCLUDED.progress,
+                is_finished = EXCLUDED.is_finished,
+                updated_at = EXCLUDED.updated_at
+            "#,
+            progress.user_id,
+            progress.media_id,
+            progress.page,
+            progress.location,
+            progress.progress,
+            progress.is_finished,
+            progress.updated_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(())
+    }
+
+    /// Get reading progress
+    pub async fn get_reading_progress(&self, user_id: UserId, media_id: Uuid) -> Result<Option<ReadingProgressModel>> {
+        let progress = sqlx::query_as!(
+            ReadingProgressModel,
+            "SELECT * FROM reading_progress WHERE user_id = $1 AND media_id = $2",
+            user_id,
+            media_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RustFlixError::from)?;
+
+        Ok(progress)
+    }
+
     /// Upsert user rating
     pub async fn upsert_rating(&self, rating: &UserRatingModel) -> Result<()> {
         sqlx::query!(
@@ -1,17 +1,36 @@
 //! Media file analysis functionality
+//!
+//! Files are parsed on the blocking thread pool; the private `analyze_*`
+//! helpers block and run there.
 
-use rustflix_core::{Result, RustFlixError, MediaFormat, MediaType};
+use crate::books::{self, BookInfo};
+use crate::disc;
+use crate::images;
+use crate::photo::{self, Exif};
+use crate::probe;
+use crate::storage::{LibraryStorage, StorageEntry, StorageReader};
+use crate::tags::{self, MusicTags};
+use rustflix_core::media::MediaStreams;
+use rustflix_core::{Result, RustFlixError, MediaFormat};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek};
 use std::path::Path;
-use tracing::{info, warn, debug};
+use std::sync::Arc;
+use tracing::{warn, debug};
+
+/// Box the thumbnails of `MediaAnalyzer::generate_thumbnail` fit in
+const THUMBNAIL_SIZE: (u32, u32) = (320, 180);
 
 /// Media analyzer for extracting metadata from files
 #[derive(Debug, Clone)]
 pub struct MediaAnalyzer {
-    // FFmpeg will be integrated here
+    // Container headers are parsed natively, see `crate::probe`
+    /// Whether chapters are read while analyzing video files
+    chapters: bool,
 }
 
 /// Media information extracted from files
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct MediaInfo {
     pub duration: Option<f64>,
     pub width: Option<u32>,
@@ -20,12 +39,28 @@ pub struct MediaInfo {
     pub video_codec: Option<String>,
     pub audio_codec: Option<String>,
     pub frame_rate: Option<f64>,
+    /// Every video, audio and subtitle stream found in the container
+    pub streams: MediaStreams,
+    /// Embedded tags of music files
+    pub tags: Option<MusicTags>,
+    /// EXIF block of photos
+    pub exif: Option<Exif>,
+    /// Details of comics and e-books
+    pub book: Option<BookInfo>,
+    /// Chapters of video files, `None` when they were not extracted
+    pub chapters: Option<Vec<Chapter>>,
 }
 
 impl MediaAnalyzer {
     /// Create a new media analyzer
     pub fn new() -> Result<Self> {
-        Ok(Self {})
+        Ok(Self { chapters: true })
+    }
+
+    /// Enable or disable chapter extraction during analysis
+    pub fn with_chapters(mut self, enabled: bool) -> Self {
+        self.chapters = enabled;
+        self
     }
 
     /// Analyze a media file and extract information
@@ -36,90 +71,166 @@ impl MediaAnalyzer {
             return Err(RustFlixError::not_found("file", &path.to_string_lossy()));
         }
 
-        // For now, return basic info - FFmpeg integration will be added
-        let format = MediaFormat::from_extension(
-            path.extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("")
-        );
-
-        let info = match format {
-            MediaFormat::Mp4 | MediaFormat::Mkv | MediaFormat::Avi => {
-                MediaInfo {
-                    duration: Some(3600.0), // Placeholder
-                    width: Some(1920),
-                    height: Some(1080),
-                    bitrate: Some(8_000_000),
-                    video_codec: Some("h264".to_string()),
-                    audio_codec: Some("aac".to_string()),
-                    frame_rate: Some(30.0),
-                }
+        let format = format_of(path);
+        let analyzer = self.clone();
+        let file_path = path.to_path_buf();
+        let info = tokio::task::spawn_blocking(move || {
+            if file_path.is_dir() {
+                return analyzer.analyze_disc(&file_path);
             }
-            MediaFormat::Mp3 | MediaFormat::Flac | MediaFormat::Aac => {
-                MediaInfo {
-                    duration: Some(240.0), // Placeholder
-                    width: None,
-                    height: None,
-                    bitrate: Some(320_000),
-                    video_codec: None,
-                    audio_codec: Some("mp3".to_string()),
-                    frame_rate: None,
-                }
+            let file = File::open(&file_path)?;
+            let file_size = file.metadata()?.len();
+            analyzer.analyze_from(&mut BufReader::new(file), file_size, format, &file_path.to_string_lossy())
+        })
+        .await
+        .map_err(|e| RustFlixError::internal(format!("Analysis task failed: {}", e)))??;
+
+        debug!("Analysis complete for: {}", path.display());
+        Ok(info)
+    }
+
+    /// Analyze a file kept in a storage backend through ranged reads
+    pub async fn analyze_entry(&self, storage: Arc<dyn LibraryStorage>, entry: &StorageEntry) -> Result<MediaInfo> {
+        let name = storage.path(&entry.key).to_string_lossy().into_owned();
+        debug!("Analyzing media file: {}", name);
+
+        let format = format_of(Path::new(&entry.key));
+        let mut reader = BufReader::new(StorageReader::new(storage, entry)?);
+        let (analyzer, file_size) = (self.clone(), entry.size);
+        let info = tokio::task::spawn_blocking(move || analyzer.analyze_from(&mut reader, file_size, format, &name))
+            .await
+            .map_err(|e| RustFlixError::internal(format!("Analysis task failed: {}", e)))??;
+
+        debug!("Analysis complete for: {}", entry.key);
+        Ok(info)
+    }
+
+    /// Analyze a file read through `reader`, `name` identifying it in logs
+    fn analyze_from<R: BufRead + Seek>(&self, reader: &mut R, file_size: u64, format: MediaFormat, name: &str) -> Result<MediaInfo> {
+        let mut info = if probe::supports_format(format) {
+            probe::probe_from(reader, file_size, format).inspect_err(|e| warn!("Failed to probe {}: {}", name, e))?
+        } else if format == MediaFormat::Iso {
+            let mut info = disc::probe_image(&mut *reader).inspect_err(|e| warn!("Failed to probe {}: {}", name, e))?;
+            if !self.chapters {
+                info.chapters = None;
             }
-            _ => {
-                MediaInfo {
-                    duration: None,
-                    width: None,
-                    height: None,
-                    bitrate: None,
-                    video_codec: None,
-                    audio_codec: None,
-                    frame_rate: None,
-                }
+            info
+        } else if photo::supports_format(format) {
+            photo::probe_from(reader, format).inspect_err(|e| warn!("Failed to analyze {}: {}", name, e))?
+        } else if books::supports_format(format) {
+            let book = books::read_from(reader, file_size, format).inspect_err(|e| warn!("Failed to read {}: {}", name, e))?;
+            MediaInfo {
+                book: Some(book),
+                ..MediaInfo::default()
             }
+        } else {
+            MediaInfo::default()
         };
 
-        debug!("Analysis complete for: {}", path.display());
+        if tags::supports_format(format) {
+            reader.rewind()?;
+            info.tags = match tags::read_from(reader, file_size, format) {
+                Ok(tags) => Some(tags).filter(|tags| !tags.is_empty()),
+                Err(e) => {
+                    warn!("Failed to read tags of {}: {}", name, e);
+                    None
+                }
+            };
+        }
+
+        // Audiobooks in one file are divided into chapters as well
+        if self.chapters && (format.is_video() || format == MediaFormat::M4b) && probe::supports_chapters(format) {
+            reader.rewind()?;
+            info.chapters = match probe::read_chapters_from(reader, file_size, format) {
+                Ok(chapters) => Some(chapters),
+                Err(e) => {
+                    warn!("Failed to read chapters of {}: {}", name, e);
+                    None
+                }
+            };
+        }
+
         Ok(info)
     }
 
-    /// Generate thumbnail for video file
+    /// Analyze the main title of a Blu-ray or DVD folder
+    fn analyze_disc(&self, root: &Path) -> Result<MediaInfo> {
+        let mut info = disc::probe_folder(root).inspect_err(|e| warn!("Failed to probe {}: {}", root.display(), e))?;
+        if !self.chapters {
+            info.chapters = None;
+        }
+        Ok(info)
+    }
+
+    /// Write a JPEG thumbnail of an image file
+    ///
+    /// Grabbing frames from videos needs FFmpeg and is not supported here;
+    /// video artwork comes from sidecar images, see `crate::images`.
     pub async fn generate_thumbnail(&self, path: &Path, output_path: &Path) -> Result<()> {
         debug!("Generating thumbnail: {} -> {}", path.display(), output_path.display());
-        
-        // Placeholder implementation - FFmpeg integration needed
-        tokio::fs::write(output_path, b"placeholder thumbnail").await
-            .map_err(RustFlixError::from)?;
-        
-        Ok(())
+
+        let format = MediaFormat::from_extension(
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("")
+        );
+        if !format.is_image() {
+            return Err(RustFlixError::media_processing(format!(
+                "Cannot generate thumbnails of {:?} files",
+                format
+            )));
+        }
+
+        let (source, output) = (path.to_path_buf(), output_path.to_path_buf());
+        tokio::task::spawn_blocking(move || images::generate_thumbnail(&source, &output, THUMBNAIL_SIZE))
+            .await
+            .map_err(|e| RustFlixError::internal(format!("Thumbnail task failed: {}", e)))?
     }
 
     /// Extract video chapters
     pub async fn extract_chapters(&self, path: &Path) -> Result<Vec<Chapter>> {
         debug!("Extracting chapters from: {}", path.display());
-        
-        // Placeholder implementation
-        Ok(vec![
-            Chapter {
-                id: 1,
-                start_time: 0.0,
-                end_time: 600.0,
-                title: "Chapter 1".to_string(),
-            },
-            Chapter {
-                id: 2,
-                start_time: 600.0,
-                end_time: 1200.0,
-                title: "Chapter 2".to_string(),
-            },
-        ])
+
+        let format = MediaFormat::from_extension(
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("")
+        );
+        if path.is_dir() || format == MediaFormat::Iso {
+            let file_path = path.to_path_buf();
+            let info = tokio::task::spawn_blocking(move || {
+                if file_path.is_dir() {
+                    disc::probe_folder(&file_path)
+                } else {
+                    disc::probe_image(BufReader::new(File::open(&file_path)?))
+                }
+            })
+            .await
+            .map_err(|e| RustFlixError::internal(format!("Chapter task failed: {}", e)))??;
+            return Ok(info.chapters.unwrap_or_default());
+        }
+        if !probe::supports_chapters(format) {
+            return Ok(Vec::new());
+        }
+
+        let file_path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || probe::read_chapters(&file_path, format))
+            .await
+            .map_err(|e| RustFlixError::internal(format!("Chapter task failed: {}", e)))?
     }
 }
 
+/// Media format of a file from its extension
+fn format_of(path: &Path) -> MediaFormat {
+    MediaFormat::from_extension(path.extension().and_then(|ext| ext.to_str()).unwrap_or(""))
+}
+
 /// Video chapter information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Chapter {
+    /// Position of the chapter, starting at 1
     pub id: u32,
+    /// Seconds from the start of the file
     pub start_time: f64,
     pub end_time: f64,
     pub title: String,
@@ -143,15 +254,61 @@ mod tests {
         
         // Create a temporary file
         let mut temp_file = NamedTempFile::with_suffix(".mp4").unwrap();
-        temp_file.write_all(b"fake video data").unwrap();
+        temp_file.write_all(&crate::probe::mp4::tests::sample_file()).unwrap();
         
         let result = analyzer.analyze_file(temp_file.path()).await;
         assert!(result.is_ok());
         
         let info = result.unwrap();
-        assert!(info.duration.is_some());
-        assert!(info.width.is_some());
-        assert!(info.height.is_some());
+        assert!((info.duration.unwrap() - 100.1).abs() < 1e-6);
+        assert_eq!(info.width, Some(1920));
+        assert_eq!(info.height, Some(800));
+        assert_eq!(info.video_codec.as_deref(), Some("h264"));
+        assert_eq!(info.audio_codec.as_deref(), Some("aac"));
+        assert_eq!(info.streams.subtitles.len(), 1);
+        assert!(info.bitrate.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_matroska_file() {
+        let analyzer = MediaAnalyzer::new().unwrap();
+
+        let mut temp_file = NamedTempFile::with_suffix(".mkv").unwrap();
+        temp_file.write_all(&crate::probe::matroska::tests::sample_file()).unwrap();
+
+        let info = analyzer.analyze_file(temp_file.path()).await.unwrap();
+        assert_eq!(info.duration, Some(5400.0));
+        assert_eq!(info.width, Some(3840));
+        assert_eq!(info.video_codec.as_deref(), Some("hevc"));
+        assert_eq!(info.streams.audio.len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_analyze_entry() {
+        let (_server, config) = crate::storage::s3::tests::fake_bucket(&[(
+            "movies/Movie.mkv",
+            crate::probe::matroska::tests::sample_file(),
+        )])
+        .await;
+        let storage: Arc<dyn LibraryStorage> =
+            Arc::new(crate::storage::S3Storage::from_location("s3://media/movies", &config).unwrap());
+        let entry = storage.stat("Movie.mkv").await.unwrap().unwrap();
+
+        let info = MediaAnalyzer::new().unwrap().analyze_entry(storage, &entry).await.unwrap();
+        assert_eq!(info.duration, Some(5400.0));
+        assert_eq!(info.video_codec.as_deref(), Some("hevc"));
+        assert_eq!(info.streams.audio.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_invalid_container() {
+        let analyzer = MediaAnalyzer::new().unwrap();
+
+        let mut temp_file = NamedTempFile::with_suffix(".mp4").unwrap();
+        temp_file.write_all(b"fake video data").unwrap();
+
+        let result = analyzer.analyze_file(temp_file.path()).await;
+        assert!(result.is_err());
     }
 
     #[tokio::test]
@@ -159,28 +316,50 @@ mod tests {
         let analyzer = MediaAnalyzer::new().unwrap();
         
         let mut temp_file = NamedTempFile::with_suffix(".mp3").unwrap();
-        temp_file.write_all(b"fake audio data").unwrap();
-        
-        let result = analyzer.analyze_file(temp_file.path()).await;
-        assert!(result.is_ok());
+        temp_file.write_all(&crate::probe::audio::tests::sample_mp3(400)).unwrap();
         
-        let info = result.unwrap();
-        assert!(info.duration.is_some());
+        let info = analyzer.analyze_file(temp_file.path()).await.unwrap();
+        assert!((info.duration.unwrap() - crate::probe::audio::tests::mp3_duration(400)).abs() < 1e-6);
+        assert_eq!(info.audio_codec.as_deref(), Some("mp3"));
+        assert_eq!(info.bitrate, Some(128_000));
         assert!(info.width.is_none());
         assert!(info.height.is_none());
+
+        let mut fake = NamedTempFile::with_suffix(".mp3").unwrap();
+        fake.write_all(b"fake audio data").unwrap();
+        assert!(analyzer.analyze_file(fake.path()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_music_tags() {
+        let analyzer = MediaAnalyzer::new().unwrap();
+
+        let mut temp_file = NamedTempFile::with_suffix(".flac").unwrap();
+        temp_file
+            .write_all(&crate::tags::vorbis::tests::sample_flac(&["ARTIST=Artist", "ALBUM=Album"]))
+            .unwrap();
+
+        let tags = analyzer.analyze_file(temp_file.path()).await.unwrap().tags.unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.album.as_deref(), Some("Album"));
     }
 
     #[tokio::test]
     async fn test_generate_thumbnail() {
         let analyzer = MediaAnalyzer::new().unwrap();
         
-        let mut temp_file = NamedTempFile::with_suffix(".mp4").unwrap();
-        temp_file.write_all(b"fake video data").unwrap();
-        
+        let source = NamedTempFile::with_suffix(".png").unwrap();
+        image::RgbImage::new(640, 480)
+            .save_with_format(source.path(), image::ImageFormat::Png)
+            .unwrap();
         let output_file = NamedTempFile::with_suffix(".jpg").unwrap();
-        
-        let result = analyzer.generate_thumbnail(temp_file.path(), output_file.path()).await;
-        assert!(result.is_ok());
+
+        analyzer.generate_thumbnail(source.path(), output_file.path()).await.unwrap();
+        assert_eq!(image::image_dimensions(output_file.path()).unwrap(), (240, 180));
+
+        let mut video = NamedTempFile::with_suffix(".mp4").unwrap();
+        video.write_all(b"fake video data").unwrap();
+        assert!(analyzer.generate_thumbnail(video.path(), output_file.path()).await.is_err());
     }
 
     #[tokio::test]
@@ -188,12 +367,19 @@ mod tests {
         let analyzer = MediaAnalyzer::new().unwrap();
         
         let mut temp_file = NamedTempFile::with_suffix(".mp4").unwrap();
-        temp_file.write_all(b"fake video data").unwrap();
-        
-        let result = analyzer.extract_chapters(temp_file.path()).await;
-        assert!(result.is_ok());
-        
-        let chapters = result.unwrap();
+        temp_file
+            .write_all(&crate::probe::mp4::tests::sample_file_with_chapters(&[(0, "Opening"), (60, "")]))
+            .unwrap();
+
+        let chapters = analyzer.extract_chapters(temp_file.path()).await.unwrap();
         assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0], Chapter { id: 1, start_time: 0.0, end_time: 60.0, title: "Opening".to_string() });
+        assert_eq!(chapters[1].title, "Chapter 2");
+        assert!((chapters[1].end_time - 100.1).abs() < 1e-6);
+
+        let info = analyzer.analyze_file(temp_file.path()).await.unwrap();
+        assert_eq!(info.chapters, Some(chapters));
+        let info = analyzer.with_chapters(false).analyze_file(temp_file.path()).await.unwrap();
+        assert!(info.chapters.is_none());
     }
 }
@@ -0,0 +1,291 @@
+//! Grouping of audiobook files into books
+//!
+//! A book is either a single file, typically an M4B divided by embedded
+//! chapters, or a folder of files with one file per chapter. Files are
+//! grouped by the folder holding them and their book title, read from the
+//! album tag; untagged files of a folder form one book named after it,
+//! except M4B files, which are each a book of their own. Disc folders of
+//! multi-disc books ("CD1", "Disc 2") count as one folder, as for music.
+
+use crate::music;
+use crate::tags::MusicTags;
+use chrono::Utc;
+use rustflix_database::{AudiobookFileLinkModel, AudiobookFileModel, AudiobookModel};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Books built from the files of the audiobook libraries
+#[derive(Debug, Clone, Default)]
+pub struct AudiobookLibrary {
+    pub books: Vec<AudiobookModel>,
+    /// Book and part number of every file
+    pub links: Vec<AudiobookFileLinkModel>,
+}
+
+/// Build the row of an audiobook file from its tags
+///
+/// The author is the album artist, falling back to the artist, as taggers
+/// disagree on which of the two names the author.
+pub fn file_model(media_id: Uuid, tags: &MusicTags, cover_hash: Option<String>) -> AudiobookFileModel {
+    AudiobookFileModel {
+        media_id,
+        title: tags.title.clone(),
+        author: tags.album_artist.clone().or_else(|| tags.artist.clone()),
+        narrator: tags.narrator.clone(),
+        book: tags.album.clone(),
+        series: tags.series.clone(),
+        series_part: tags.series_part.clone(),
+        track_number: tags.track_number.map(|number| number as i32),
+        disc_number: tags.disc_number.map(|number| number as i32),
+        year: tags.year,
+        genre: tags.genre.clone(),
+        cover_hash,
+        book_id: None,
+        part_number: None,
+        updated_at: Utc::now(),
+    }
+}
+
+/// Group files, given with their paths, into books
+///
+/// Books keep the ids of the existing ones with the same group key, so that
+/// regrouping a library does not change them.
+pub fn group(files: &[(PathBuf, AudiobookFileModel)], existing: &[AudiobookModel]) -> AudiobookLibrary {
+    let mut groups: HashMap<String, Vec<(&Path, &AudiobookFileModel)>> = HashMap::new();
+    let mut group_order = Vec::new();
+    for (path, file) in files {
+        let group_key = group_key(path, file);
+        groups
+            .entry(group_key.clone())
+            .or_insert_with(|| {
+                group_order.push(group_key);
+                Vec::new()
+            })
+            .push((path.as_path(), file));
+    }
+    group_order.sort();
+
+    let existing = existing
+        .iter()
+        .map(|book| (book.group_key.as_str(), book))
+        .collect::<HashMap<_, _>>();
+    let now = Utc::now();
+    let mut books = Vec::new();
+    let mut links = Vec::new();
+    for group_key in group_order {
+        let Some(mut parts) = groups.remove(&group_key) else {
+            continue;
+        };
+        parts.sort_by(|a, b| part_order(*a, *b));
+        let files = parts.iter().map(|(_, file)| *file).collect::<Vec<_>>();
+
+        let existing = existing.get(group_key.as_str());
+        let id = existing.map_or_else(Uuid::new_v4, |book| book.id);
+        for (index, file) in files.iter().enumerate() {
+            links.push(AudiobookFileLinkModel {
+                media_id: file.media_id,
+                book_id: Some(id),
+                part_number: Some(index as i32 + 1),
+            });
+        }
+
+        let common = |field: fn(&AudiobookFileModel) -> Option<&str>| {
+            music::most_common(files.iter().filter_map(|file| field(file)))
+        };
+        let title = common(book_title)
+            .or_else(|| (files.len() == 1).then(|| common(|file| file.title.as_deref())).flatten())
+            .unwrap_or_else(|| fallback_title(parts[0].0));
+        books.push(AudiobookModel {
+            id,
+            group_key,
+            title,
+            author: common(|file| file.author.as_deref()),
+            narrator: common(|file| file.narrator.as_deref()),
+            series: common(|file| file.series.as_deref()),
+            series_part: common(|file| file.series_part.as_deref()),
+            year: files.iter().filter_map(|file| file.year).min(),
+            genre: common(|file| file.genre.as_deref()),
+            part_count: files.len() as i32,
+            cover_hash: files.iter().find_map(|file| file.cover_hash.clone()),
+            created_at: existing.map_or(now, |book| book.created_at),
+            updated_at: now,
+        });
+    }
+
+    AudiobookLibrary { books, links }
+}
+
+/// Folder holding the files a file may be grouped with into a book
+///
+/// Every book lies within one such folder, so a change to a file only
+/// affects the books of the files under the folders of its old and new path.
+pub(crate) fn book_dir(path: &Path) -> &Path {
+    music::album_dir(path)
+}
+
+/// Key of the book a file belongs to
+fn group_key(path: &Path, file: &AudiobookFileModel) -> String {
+    let dir = music::album_dir(path).to_string_lossy();
+    match book_title(file) {
+        Some(title) => format!("book:{}\u{1f}{}", title.to_lowercase(), dir),
+        None if is_single_file(path) => format!("file:{}", path.to_string_lossy()),
+        None => format!("folder:{}", dir),
+    }
+}
+
+/// Book title of a file, if it has one
+fn book_title(file: &AudiobookFileModel) -> Option<&str> {
+    file.book.as_deref().map(str::trim).filter(|title| !title.is_empty())
+}
+
+/// Check whether a file holds a whole book by itself
+fn is_single_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("m4b"))
+}
+
+/// Title of an untagged book: the name of a single file, otherwise of the
+/// folder holding the book
+fn fallback_title(path: &Path) -> String {
+    let name = if is_single_file(path) { path.file_stem() } else { music::album_dir(path).file_name() };
+    name.map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Reading order of the files of a book: by disc and track number, then by
+/// path with numbers compared by value, so that "Chapter 2" precedes
+/// "Chapter 10"
+fn part_order(a: (&Path, &AudiobookFileModel), b: (&Path, &AudiobookFileModel)) -> Ordering {
+    let (a_path, a) = a;
+    let (b_path, b) = b;
+    (a.disc_number.unwrap_or(1), a.track_number.unwrap_or(0))
+        .cmp(&(b.disc_number.unwrap_or(1), b.track_number.unwrap_or(0)))
+        .then_with(|| natural_key(a_path).cmp(&natural_key(b_path)))
+}
+
+/// Runs of text and of digits of a path, for comparing numbers by value
+pub(crate) fn natural_key(path: &Path) -> Vec<(String, u64)> {
+    let path = path.to_string_lossy().to_lowercase();
+    let mut key = Vec::new();
+    let mut text = String::new();
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_ascii_digit() {
+            text.push(c);
+            continue;
+        }
+        let mut number = c.to_digit(10).unwrap_or(0) as u64;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            number = number.saturating_mul(10).saturating_add(digit as u64);
+            chars.next();
+        }
+        key.push((std::mem::take(&mut text), number));
+    }
+    key.push((text, 0));
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, book: Option<&str>, track: Option<u32>) -> (PathBuf, AudiobookFileModel) {
+        let tags = MusicTags {
+            album: book.map(str::to_string),
+            artist: Some("Author".to_string()),
+            track_number: track,
+            ..Default::default()
+        };
+        (PathBuf::from(path), file_model(Uuid::new_v4(), &tags, None))
+    }
+
+    fn book_of(library: &AudiobookLibrary, media_id: Uuid) -> (&AudiobookModel, i32) {
+        let link = library.links.iter().find(|link| link.media_id == media_id).unwrap();
+        let book = library.books.iter().find(|book| Some(book.id) == link.book_id).unwrap();
+        (book, link.part_number.unwrap())
+    }
+
+    #[test]
+    fn test_group_multi_file_book() {
+        let mut files = vec![
+            file("/books/Author/Book/Chapter 10.mp3", None, None),
+            file("/books/Author/Book/Chapter 2.mp3", None, None),
+            file("/books/Author/Book/Chapter 1.mp3", None, None),
+        ];
+        files[0].1.narrator = Some("Reader".to_string());
+        let library = group(&files, &[]);
+
+        assert_eq!(library.books.len(), 1);
+        let book = &library.books[0];
+        assert_eq!(book.title, "Book");
+        assert_eq!(book.author.as_deref(), Some("Author"));
+        assert_eq!(book.narrator.as_deref(), Some("Reader"));
+        assert_eq!(book.part_count, 3);
+        assert_eq!(book_of(&library, files[2].1.media_id).1, 1);
+        assert_eq!(book_of(&library, files[1].1.media_id).1, 2);
+        assert_eq!(book_of(&library, files[0].1.media_id).1, 3);
+    }
+
+    #[test]
+    fn test_group_tagged_discs() {
+        let mut files = vec![
+            file("/books/Saga/CD2/01.mp3", Some("Saga"), Some(1)),
+            file("/books/Saga/CD1/02.mp3", Some("Saga"), Some(2)),
+            file("/books/Saga/CD1/01.mp3", Some("Saga"), Some(1)),
+        ];
+        files[0].1.disc_number = Some(2);
+        files[1].1.disc_number = Some(1);
+        let library = group(&files, &[]);
+
+        assert_eq!(library.books.len(), 1);
+        assert_eq!(book_of(&library, files[2].1.media_id).1, 1);
+        assert_eq!(book_of(&library, files[1].1.media_id).1, 2);
+        assert_eq!(book_of(&library, files[0].1.media_id).1, 3);
+    }
+
+    #[test]
+    fn test_group_single_file_books() {
+        let mut files = vec![
+            file("/books/Author/First.m4b", None, None),
+            file("/books/Author/Second.m4b", None, None),
+            file("/books/Author/Third.m4b", Some("Third Book"), None),
+        ];
+        files[1].1.title = Some("The Second Book".to_string());
+        let first = group(&files, &[]);
+
+        assert_eq!(first.books.len(), 3);
+        assert_eq!(book_of(&first, files[0].1.media_id).0.title, "First");
+        assert_eq!(book_of(&first, files[1].1.media_id).0.title, "The Second Book");
+        assert_eq!(book_of(&first, files[2].1.media_id).0.title, "Third Book");
+
+        let second = group(&files, &first.books);
+        for (path, file) in &files {
+            assert_eq!(book_of(&second, file.media_id).0.id, book_of(&first, file.media_id).0.id, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_group_book_dir() {
+        let files = vec![
+            file("/books/Saga/CD1/01.mp3", Some("Saga"), Some(1)),
+            file("/books/Saga/CD2/01.mp3", Some("Saga"), Some(2)),
+            file("/books/Other/01.mp3", None, None),
+        ];
+        let full = group(&files, &[]);
+
+        // Regrouping the folder of a changed file keeps the ids of its books
+        let dir = book_dir(&files[1].0);
+        assert_eq!(dir, Path::new("/books/Saga"));
+        let folder = files.iter().filter(|(path, _)| path.starts_with(dir)).cloned().collect::<Vec<_>>();
+        let regrouped = group(&folder, &full.books);
+        assert_eq!(regrouped.books.len(), 1);
+        assert_eq!(regrouped.books[0].id, book_of(&full, files[0].1.media_id).0.id);
+        assert_eq!(regrouped.books[0].part_count, 2);
+    }
+
+    #[test]
+    fn test_natural_key() {
+        assert!(natural_key(Path::new("Part 2.mp3")) < natural_key(Path::new("Part 10.mp3")));
+        assert!(natural_key(Path::new("a/Part 9.mp3")) < natural_key(Path::new("b/Part 1.mp3")));
+    }
+}
@@ -0,0 +1,656 @@
+//! Library health audit
+//!
+//! An audit checks every item of every library: files that are gone or
+//! empty, files that fail probing and files a browser cannot play without
+//! transcoding. It also lists movies and episodes without metadata, items
+//! sharing a content hash, and rows left behind by deleted libraries, stacks
+//! and audiobooks. Issues are stored with the audit; cleanup marks missing
+//! items removed and deletes orphaned rows, while the other issues are left
+//! for an administrator to look at.
+
+use crate::analyzer::{MediaAnalyzer, MediaInfo};
+use crate::scanner::MediaScanner;
+use crate::storage::{self, LibraryStorage};
+use chrono::Utc;
+use rustflix_core::config::ObjectStorageConfig;
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use rustflix_database::{AuditIssueModel, LibraryAuditModel, LibraryModel, MediaItemModel, MediaRepository};
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Most movies and episodes listed for missing metadata
+const MAX_METADATA_ISSUES: i64 = 10_000;
+
+/// Containers browsers play directly
+const DIRECT_PLAY_FORMATS: &[MediaFormat] = &[
+    MediaFormat::Mp4,
+    MediaFormat::M4v,
+    MediaFormat::Webm,
+    MediaFormat::Mp3,
+    MediaFormat::Aac,
+    MediaFormat::M4a,
+    MediaFormat::M4b,
+    MediaFormat::Flac,
+    MediaFormat::Ogg,
+    MediaFormat::Wav,
+];
+
+/// Video codecs browsers decode
+const DIRECT_PLAY_VIDEO_CODECS: &[&str] = &["h264", "vp8", "vp9", "av1"];
+
+/// Audio codecs browsers decode
+const DIRECT_PLAY_AUDIO_CODECS: &[&str] = &["aac", "mp3", "opus", "vorbis", "flac", "pcm"];
+
+/// Kind of problem found by an audit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    MissingFile,
+    EmptyFile,
+    ProbeFailed,
+    MissingMetadata,
+    DuplicateHash,
+    UnsupportedCodec,
+    /// Media item in no library
+    OrphanedItem,
+    /// Stack without members
+    OrphanedStack,
+    /// Audiobook without files
+    OrphanedAudiobook,
+    /// Image no media item links to
+    OrphanedImage,
+}
+
+impl IssueKind {
+    pub const ALL: [IssueKind; 10] = [
+        Self::MissingFile,
+        Self::EmptyFile,
+        Self::ProbeFailed,
+        Self::MissingMetadata,
+        Self::DuplicateHash,
+        Self::UnsupportedCodec,
+        Self::OrphanedItem,
+        Self::OrphanedStack,
+        Self::OrphanedAudiobook,
+        Self::OrphanedImage,
+    ];
+
+    /// Get the name used in the database and API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MissingFile => "missing_file",
+            Self::EmptyFile => "empty_file",
+            Self::ProbeFailed => "probe_failed",
+            Self::MissingMetadata => "missing_metadata",
+            Self::DuplicateHash => "duplicate_hash",
+            Self::UnsupportedCodec => "unsupported_codec",
+            Self::OrphanedItem => "orphaned_item",
+            Self::OrphanedStack => "orphaned_stack",
+            Self::OrphanedAudiobook => "orphaned_audiobook",
+            Self::OrphanedImage => "orphaned_image",
+        }
+    }
+
+    /// Parse a name as returned by `as_str`
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+
+    /// Check whether cleanup acts on issues of this kind
+    pub fn can_clean_up(&self) -> bool {
+        matches!(
+            self,
+            Self::MissingFile | Self::OrphanedItem | Self::OrphanedStack | Self::OrphanedAudiobook | Self::OrphanedImage
+        )
+    }
+}
+
+/// What the cleanup of an audit did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditCleanup {
+    /// Media items marked removed, for missing files and items in no library
+    pub items_removed: u64,
+    pub stacks_deleted: usize,
+    pub audiobooks_deleted: u64,
+    /// Variant files of the orphaned images deleted
+    pub image_files_removed: usize,
+    pub issues_resolved: usize,
+}
+
+/// Runs library audits in the background, one at a time
+#[derive(Debug, Clone)]
+pub struct LibraryAuditor {
+    scanner: MediaScanner,
+    analyzer: MediaAnalyzer,
+    repository: MediaRepository,
+    /// Object storage of bucket libraries, when configured
+    object_storage: Option<ObjectStorageConfig>,
+    running: Arc<AtomicBool>,
+}
+
+impl LibraryAuditor {
+    /// Create an auditor probing files with `analyzer`
+    pub fn new(
+        repository: MediaRepository,
+        scanner: MediaScanner,
+        analyzer: MediaAnalyzer,
+        object_storage: Option<ObjectStorageConfig>,
+    ) -> Self {
+        Self {
+            scanner,
+            analyzer,
+            repository,
+            object_storage,
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Check whether an audit is running
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Start an audit in the background, returning its record
+    ///
+    /// With `cleanup`, the cleanup actions are applied once the audit
+    /// completes. Fails if an audit is already running.
+    pub async fn start(&self, cleanup: bool) -> Result<LibraryAuditModel> {
+        let guard = RunningAudit::acquire(&self.running).ok_or_else(|| {
+            RustFlixError::service_unavailable("media_library", "a library audit is already running")
+        })?;
+
+        let audit = LibraryAuditModel {
+            id: Uuid::new_v4(),
+            status: "running".to_string(),
+            items_checked: 0,
+            issue_count: 0,
+            error_message: None,
+            started_at: Utc::now(),
+            finished_at: None,
+            cleaned_at: None,
+        };
+        self.repository.create_audit(&audit).await?;
+
+        let auditor = self.clone();
+        let record = audit.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            auditor.run(record, cleanup).await;
+        });
+
+        Ok(audit)
+    }
+
+    /// Run an audit and store its outcome
+    async fn run(&self, mut audit: LibraryAuditModel, cleanup: bool) {
+        info!("Starting library audit {}", audit.id);
+
+        let issues = match self.check(audit.id).await {
+            Ok((checked, issues)) => {
+                audit.status = "completed".to_string();
+                audit.items_checked = checked as i32;
+                audit.issue_count = issues.len() as i32;
+                issues
+            }
+            Err(e) => {
+                error!("Library audit {} failed: {}", audit.id, e);
+                audit.status = "failed".to_string();
+                audit.error_message = Some(e.to_string());
+                Vec::new()
+            }
+        };
+        audit.finished_at = Some(Utc::now());
+
+        if let Err(e) = self.repository.finish_audit(&audit, &issues).await {
+            error!("Failed to record library audit {}: {}", audit.id, e);
+            return;
+        }
+        info!(
+            "Library audit {} checked {} items and found {} issues",
+            audit.id, audit.items_checked, audit.issue_count
+        );
+
+        if cleanup && audit.status == "completed" {
+            if let Err(e) = self.clean_up(audit.id).await {
+                error!("Cleanup of library audit {} failed: {}", audit.id, e);
+            }
+        }
+    }
+
+    /// Check every library, returning the number of items checked and the
+    /// issues found
+    pub async fn check(&self, audit_id: Uuid) -> Result<(usize, Vec<AuditIssueModel>)> {
+        let libraries = self.repository.list_libraries().await?;
+        let mut issues = Vec::new();
+        let mut checked = 0;
+
+        for library in &libraries {
+            let storage = match storage::open(&library.path, self.object_storage.as_ref()) {
+                Ok(storage) => storage,
+                Err(e) => {
+                    warn!("Not auditing library {}: {}", library.name, e);
+                    continue;
+                }
+            };
+            // An unmounted drive would otherwise report every file missing
+            if storage.local_root().is_some_and(|root| !root.is_dir()) {
+                let mut issue = new_issue(audit_id, IssueKind::MissingFile, Some(library.id), None, Some(&library.path));
+                issue.detail = Some("Library folder is missing".to_string());
+                issues.push(issue);
+                continue;
+            }
+
+            for item in self.repository.get_media_items_by_library(library.id).await? {
+                // Items of a nested library are checked with that library
+                if library_of(&libraries, &item.path) != Some(library.id) {
+                    continue;
+                }
+                checked += 1;
+                match self.check_item(&storage, &item).await {
+                    Ok(Some((kind, detail))) => {
+                        let mut issue = new_issue(audit_id, kind, Some(library.id), Some(item.id), Some(&item.path));
+                        issue.detail = detail;
+                        issues.push(issue);
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to check {}: {}", item.path, e),
+                }
+            }
+        }
+
+        // Metadata providers only know movies and shows; music, photos and
+        // books are described by their own tags
+        let needing_metadata = self.repository.get_items_needing_metadata(MAX_METADATA_ISSUES).await?;
+        for item in needing_metadata.iter().filter(|item| matches!(item.media_type.as_str(), "movie" | "episode")) {
+            let library_id = library_of(&libraries, &item.path);
+            issues.push(new_issue(audit_id, IssueKind::MissingMetadata, library_id, Some(item.id), Some(&item.path)));
+        }
+
+        let duplicates = self.repository.get_duplicate_media_items().await?;
+        for (item, original) in duplicate_pairs(&duplicates) {
+            let library_id = library_of(&libraries, &item.path);
+            let mut issue = new_issue(audit_id, IssueKind::DuplicateHash, library_id, Some(item.id), Some(&item.path));
+            issue.detail = Some(format!("Same content as {}", original.path));
+            issues.push(issue);
+        }
+
+        for item in self.repository.get_media_items_outside_libraries().await? {
+            issues.push(new_issue(audit_id, IssueKind::OrphanedItem, None, Some(item.id), Some(&item.path)));
+        }
+        for stack in self.repository.get_empty_stacks().await? {
+            let mut issue = new_issue(audit_id, IssueKind::OrphanedStack, None, None, Some(&stack.directory));
+            issue.row_id = Some(stack.id);
+            issue.detail = Some(stack.title);
+            issues.push(issue);
+        }
+        for book in self.repository.get_empty_audiobooks().await? {
+            let mut issue = new_issue(audit_id, IssueKind::OrphanedAudiobook, None, None, None);
+            issue.row_id = Some(book.id);
+            issue.detail = Some(book.title);
+            issues.push(issue);
+        }
+        for hash in self.repository.get_orphaned_image_hashes().await? {
+            let mut issue = new_issue(audit_id, IssueKind::OrphanedImage, None, None, None);
+            issue.detail = Some(hash);
+            issues.push(issue);
+        }
+
+        Ok((checked, issues))
+    }
+
+    /// Check the file of a media item, returning the issue found
+    async fn check_item(
+        &self,
+        storage: &Arc<dyn LibraryStorage>,
+        item: &MediaItemModel,
+    ) -> Result<Option<(IssueKind, Option<String>)>> {
+        let path = Path::new(&item.path);
+        let info = if storage.local_root().is_some() {
+            match tokio::fs::metadata(path).await {
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Some((IssueKind::MissingFile, None))),
+                Err(e) => return Ok(Some((IssueKind::ProbeFailed, Some(e.to_string())))),
+                // Disc folders are directories
+                Ok(metadata) if metadata.is_file() && metadata.len() == 0 => {
+                    return Ok(Some((IssueKind::EmptyFile, None)))
+                }
+                Ok(_) => self.analyzer.analyze_file(path).await,
+            }
+        } else {
+            let Some(key) = storage.key(path) else {
+                return Ok(Some((IssueKind::MissingFile, None)));
+            };
+            match storage.stat(&key).await? {
+                None => return Ok(Some((IssueKind::MissingFile, None))),
+                Some(entry) if entry.size == 0 => return Ok(Some((IssueKind::EmptyFile, None))),
+                Some(entry) => self.analyzer.analyze_entry(storage.clone(), &entry).await,
+            }
+        };
+
+        let issue = match info {
+            Err(e) => Some((IssueKind::ProbeFailed, Some(e.to_string()))),
+            Ok(info) => {
+                // Stored format names double as extensions
+                let format = MediaFormat::from_extension(&item.format);
+                direct_play_problem(format, &info).map(|detail| (IssueKind::UnsupportedCodec, Some(detail)))
+            }
+        };
+        Ok(issue)
+    }
+
+    /// Apply the cleanup actions to the unresolved issues of an audit
+    ///
+    /// Missing files and items in no library are marked removed, as a scan
+    /// would, so they come back when their file does. Stacks and audiobooks
+    /// are deleted if they are still empty, and orphaned images with their
+    /// files.
+    pub async fn clean_up(&self, audit_id: Uuid) -> Result<AuditCleanup> {
+        let audit = self
+            .repository
+            .get_audit(audit_id)
+            .await?
+            .ok_or_else(|| RustFlixError::not_found("library audit", &audit_id.to_string()))?;
+        if audit.status != "completed" {
+            return Err(RustFlixError::validation("audit", "only completed audits can be cleaned up"));
+        }
+
+        let issues = self.repository.get_audit_issues(audit_id, None).await?;
+        let pending = issues
+            .iter()
+            .filter(|issue| issue.resolved_at.is_none())
+            .filter_map(|issue| Some((IssueKind::from_name(&issue.kind)?, issue)))
+            .collect::<Vec<_>>();
+        let of_kind = |kinds: &[IssueKind]| {
+            pending
+                .iter()
+                .filter(|(kind, _)| kinds.contains(kind))
+                .map(|(_, issue)| *issue)
+                .collect::<Vec<_>>()
+        };
+        let mut cleanup = AuditCleanup::default();
+        let mut resolved = Vec::new();
+
+        let removed = of_kind(&[IssueKind::MissingFile, IssueKind::OrphanedItem])
+            .into_iter()
+            .filter(|issue| issue.media_id.is_some())
+            .collect::<Vec<_>>();
+        if !removed.is_empty() {
+            let ids = removed.iter().filter_map(|issue| issue.media_id).collect::<Vec<_>>();
+            cleanup.items_removed = self.repository.mark_media_items_removed(&ids, Utc::now()).await?;
+            resolved.extend(removed.iter().map(|issue| issue.id));
+        }
+
+        let stacks = of_kind(&[IssueKind::OrphanedStack]);
+        if !stacks.is_empty() {
+            let empty = self.repository.get_empty_stacks().await?.into_iter().map(|stack| stack.id).collect::<HashSet<_>>();
+            let stacks = stacks
+                .into_iter()
+                .filter(|issue| issue.row_id.is_some_and(|id| empty.contains(&id)))
+                .collect::<Vec<_>>();
+            let ids = stacks.iter().filter_map(|issue| issue.row_id).collect::<Vec<_>>();
+            if !ids.is_empty() {
+                self.repository.delete_stacks(&ids).await?;
+            }
+            cleanup.stacks_deleted = ids.len();
+            resolved.extend(stacks.iter().map(|issue| issue.id));
+        }
+
+        let books = of_kind(&[IssueKind::OrphanedAudiobook]);
+        if !books.is_empty() {
+            let empty = self.repository.get_empty_audiobooks().await?.into_iter().map(|book| book.id).collect::<HashSet<_>>();
+            let books = books
+                .into_iter()
+                .filter(|issue| issue.row_id.is_some_and(|id| empty.contains(&id)))
+                .collect::<Vec<_>>();
+            let ids = books.iter().filter_map(|issue| issue.row_id).collect::<Vec<_>>();
+            if !ids.is_empty() {
+                cleanup.audiobooks_deleted = self.repository.delete_audiobooks(&ids).await?;
+            }
+            resolved.extend(books.iter().map(|issue| issue.id));
+        }
+
+        // Images are deleted as a whole: any image unlinked now is garbage
+        let images = of_kind(&[IssueKind::OrphanedImage]);
+        if !images.is_empty() {
+            cleanup.image_files_removed = self.scanner.remove_orphaned_images(&self.repository).await?;
+            resolved.extend(images.iter().map(|issue| issue.id));
+        }
+
+        cleanup.issues_resolved = resolved.len();
+        self.repository.resolve_audit_issues(audit_id, &resolved, Utc::now()).await?;
+        info!("Cleaned up library audit {}: {:?}", audit_id, cleanup);
+        Ok(cleanup)
+    }
+}
+
+/// Exclusive right to run an audit, released on drop
+#[derive(Debug)]
+struct RunningAudit(Arc<AtomicBool>);
+
+impl RunningAudit {
+    fn acquire(running: &Arc<AtomicBool>) -> Option<Self> {
+        (!running.swap(true, Ordering::SeqCst)).then(|| Self(running.clone()))
+    }
+}
+
+impl Drop for RunningAudit {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+fn new_issue(
+    audit_id: Uuid,
+    kind: IssueKind,
+    library_id: Option<Uuid>,
+    media_id: Option<Uuid>,
+    path: Option<&str>,
+) -> AuditIssueModel {
+    AuditIssueModel {
+        id: Uuid::new_v4(),
+        audit_id,
+        kind: kind.as_str().to_string(),
+        library_id,
+        media_id,
+        row_id: None,
+        path: path.map(str::to_string),
+        detail: None,
+        resolved_at: None,
+    }
+}
+
+/// Library a path belongs to: the deepest library folder holding it
+pub fn library_of(libraries: &[LibraryModel], path: &str) -> Option<Uuid> {
+    libraries
+        .iter()
+        .filter(|library| Path::new(path).starts_with(&library.path))
+        .max_by_key(|library| library.path.len())
+        .map(|library| library.id)
+}
+
+/// Duplicates of items ordered by hash and age, each with the oldest item
+/// of the same content
+fn duplicate_pairs(items: &[MediaItemModel]) -> Vec<(&MediaItemModel, &MediaItemModel)> {
+    items
+        .chunk_by(|a, b| a.file_hash == b.file_hash)
+        .filter(|group| group[0].file_hash.is_some())
+        .flat_map(|group| group[1..].iter().map(move |item| (item, &group[0])))
+        .collect()
+}
+
+/// Reason a video or audio file needs transcoding to play in a browser
+pub fn direct_play_problem(format: MediaFormat, info: &MediaInfo) -> Option<String> {
+    if !format.is_video() && !format.is_audio() {
+        return None;
+    }
+    if !DIRECT_PLAY_FORMATS.contains(&format) {
+        return Some(format!("{} container", format.as_str()));
+    }
+
+    // The stream lists are empty for formats read without a probe
+    let video = if info.streams.video.is_empty() {
+        info.video_codec.iter().cloned().collect::<Vec<_>>()
+    } else {
+        info.streams.video.iter().map(|stream| stream.name.clone()).collect()
+    };
+    let audio = if info.streams.audio.is_empty() {
+        info.audio_codec.iter().cloned().collect::<Vec<_>>()
+    } else {
+        info.streams.audio.iter().map(|stream| stream.name.clone()).collect()
+    };
+    let mut problems = video
+        .iter()
+        .filter(|codec| !DIRECT_PLAY_VIDEO_CODECS.contains(&codec.as_str()))
+        .map(|codec| format!("{} video", codec))
+        .collect::<Vec<_>>();
+    // A file plays when any of its audio tracks does
+    if !audio.is_empty() && !audio.iter().any(|codec| is_direct_play_audio(codec)) {
+        problems.extend(audio.iter().map(|codec| format!("{} audio", codec)));
+    }
+    problems.dedup();
+    (!problems.is_empty()).then(|| problems.join(", "))
+}
+
+fn is_direct_play_audio(codec: &str) -> bool {
+    DIRECT_PLAY_AUDIO_CODECS.contains(&codec) || codec.starts_with("pcm_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use rustflix_core::media::{AudioCodec, VideoCodec};
+
+    fn library(path: &str) -> LibraryModel {
+        LibraryModel {
+            id: Uuid::new_v4(),
+            name: path.to_string(),
+            path: path.to_string(),
+            library_type: "movies".to_string(),
+            scan_interval: None,
+            last_scan: None,
+            is_enabled: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn item(path: &str, hash: Option<&str>, age: i64) -> MediaItemModel {
+        let created_at = Utc::now() - Duration::days(age);
+        MediaItemModel {
+            id: Uuid::new_v4(),
+            path: path.to_string(),
+            file_size: 1,
+            file_hash: hash.map(str::to_string),
+            media_type: "movie".to_string(),
+            format: "mkv".to_string(),
+            duration: None,
+            width: None,
+            height: None,
+            bitrate: None,
+            file_modified: None,
+            removed_at: None,
+            full_hash: None,
+            created_at,
+            updated_at: created_at,
+            stack_id: None,
+            stack_version: None,
+            stack_part: None,
+            extra_type: None,
+            extra_owner: None,
+        }
+    }
+
+    fn streams(video: &[&str], audio: &[&str]) -> MediaInfo {
+        let mut info = MediaInfo::default();
+        info.streams.video = video
+            .iter()
+            .map(|name| VideoCodec {
+                name: name.to_string(),
+                profile: None,
+                level: None,
+                width: 1920,
+                height: 1080,
+                frame_rate: None,
+                bit_depth: None,
+                color_space: None,
+            })
+            .collect();
+        info.streams.audio = audio
+            .iter()
+            .map(|name| AudioCodec {
+                name: name.to_string(),
+                channels: 2,
+                sample_rate: 48000,
+                bit_depth: None,
+                bitrate: None,
+                language: None,
+            })
+            .collect();
+        info
+    }
+
+    #[test]
+    fn test_issue_kind_names() {
+        for kind in IssueKind::ALL {
+            assert_eq!(IssueKind::from_name(kind.as_str()), Some(kind));
+        }
+        assert_eq!(IssueKind::from_name("unknown"), None);
+        assert!(IssueKind::MissingFile.can_clean_up());
+        assert!(!IssueKind::ProbeFailed.can_clean_up());
+    }
+
+    #[test]
+    fn test_library_of() {
+        let movies = library("/media/movies");
+        let kids = library("/media/movies/kids");
+        let libraries = vec![movies.clone(), kids.clone()];
+
+        assert_eq!(library_of(&libraries, "/media/movies/Heat (1995)/Heat.mkv"), Some(movies.id));
+        assert_eq!(library_of(&libraries, "/media/movies/kids/Up (2009)/Up.mkv"), Some(kids.id));
+        assert_eq!(library_of(&libraries, "/media/movies-old/Heat.mkv"), None);
+    }
+
+    #[test]
+    fn test_duplicate_pairs() {
+        let items = vec![
+            item("/a/first.mkv", Some("aa"), 3),
+            item("/a/second.mkv", Some("aa"), 2),
+            item("/a/third.mkv", Some("aa"), 1),
+            item("/b/other.mkv", Some("bb"), 2),
+            item("/b/copy.mkv", Some("bb"), 1),
+        ];
+        let pairs = duplicate_pairs(&items)
+            .into_iter()
+            .map(|(item, original)| (item.path.as_str(), original.path.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            pairs,
+            vec![
+                ("/a/second.mkv", "/a/first.mkv"),
+                ("/a/third.mkv", "/a/first.mkv"),
+                ("/b/copy.mkv", "/b/other.mkv"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_direct_play_problem() {
+        assert_eq!(direct_play_problem(MediaFormat::Mp4, &streams(&["h264"], &["aac"])), None);
+        assert_eq!(direct_play_problem(MediaFormat::Mkv, &streams(&["h264"], &["aac"])).as_deref(), Some("mkv container"));
+        assert_eq!(
+            direct_play_problem(MediaFormat::Mp4, &streams(&["hevc"], &["ac3", "eac3"])).as_deref(),
+            Some("hevc video, ac3 audio, eac3 audio")
+        );
+        // A second, playable audio track is enough
+        assert_eq!(direct_play_problem(MediaFormat::Mp4, &streams(&["av1"], &["ac3", "aac"])), None);
+        assert_eq!(direct_play_problem(MediaFormat::Flac, &streams(&[], &["flac"])), None);
+        // Unknown codecs are not reported
+        assert_eq!(direct_play_problem(MediaFormat::Mp4, &MediaInfo::default()), None);
+        assert_eq!(direct_play_problem(MediaFormat::Epub, &MediaInfo::default()), None);
+    }
+}
@@ -0,0 +1,303 @@
+//! EPUB package metadata
+//!
+//! The package document (OPF) named by `META-INF/container.xml` holds the
+//! Dublin Core metadata and the manifest the cover image is found in.
+//! Series are read from Calibre's `calibre:series` meta elements and from
+//! EPUB 3 collections.
+
+use super::{image_mime, Archive, BookInfo};
+use crate::tags::{parse_year, CoverArt};
+use roxmltree::{Document, Node};
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek};
+use tracing::warn;
+
+const CONTAINER: &str = "META-INF/container.xml";
+
+/// Read the metadata and cover of an EPUB package
+pub(crate) fn read<R: Read + Seek>(reader: &mut R, archive: &Archive) -> Result<BookInfo> {
+    let container = read_text(reader, archive, CONTAINER)?;
+    let container = Document::parse(&container).map_err(invalid)?;
+    let package_path = container
+        .descendants()
+        .find(|node| is_element(node, "rootfile"))
+        .and_then(|node| node.attribute("full-path"))
+        .ok_or_else(|| RustFlixError::media_processing("EPUB container names no package document"))?
+        .to_string();
+
+    let package = read_text(reader, archive, &package_path)?;
+    let package = Document::parse(&package).map_err(invalid)?;
+    let mut info = parse_package(&package);
+
+    if let Some(href) = cover_href(&package) {
+        let path = resolve(&package_path, &href);
+        match archive.find(&path).map(|entry| archive.read(reader, entry)) {
+            Some(Ok(data)) => {
+                info.cover = image_mime(&path).map(|mime_type| CoverArt { mime_type: mime_type.to_string(), data })
+            }
+            Some(Err(e)) => warn!("Failed to read EPUB cover {}: {}", path, e),
+            None => warn!("EPUB cover {} is missing", path),
+        }
+    }
+
+    Ok(info)
+}
+
+/// Metadata of a package document, without the cover
+fn parse_package(package: &Document<'_>) -> BookInfo {
+    let mut info = BookInfo::default();
+    let Some(metadata) = package.descendants().find(|node| is_element(node, "metadata")) else {
+        return info;
+    };
+
+    let text = |name: &str| {
+        metadata
+            .children()
+            .filter(|node| is_element(node, name))
+            .find_map(|node| node.text().map(str::trim).filter(|text| !text.is_empty()))
+            .map(str::to_string)
+    };
+    info.title = text("title");
+    info.publisher = text("publisher");
+    info.language = text("language");
+    info.description = text("description").map(|description| strip_markup(&description));
+    info.year = text("date").and_then(|date| parse_year(&date));
+
+    // Authors are the creators without a role, or with the "aut" role given
+    // as an EPUB 2 attribute or an EPUB 3 refinement
+    let creators = metadata.children().filter(|node| is_element(node, "creator")).collect::<Vec<_>>();
+    let role = |node: &Node<'_, '_>| {
+        node.attributes()
+            .find(|attr| attr.name() == "role")
+            .map(|attr| attr.value().to_string())
+            .or_else(|| refinement(&metadata, node.attribute("id")?, "role"))
+    };
+    let authors = creators
+        .iter()
+        .filter(|node| role(node).is_none_or(|role| role == "aut"))
+        .filter_map(|node| node.text().map(str::trim).filter(|name| !name.is_empty()))
+        .collect::<Vec<_>>();
+    info.author = (!authors.is_empty()).then(|| authors.join("; "));
+
+    let meta = |name: &str| {
+        metadata
+            .children()
+            .filter(|node| is_element(node, "meta"))
+            .find(|node| node.attribute("name") == Some(name))
+            .and_then(|node| node.attribute("content"))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    info.series = meta("calibre:series");
+    info.series_index = meta("calibre:series_index");
+
+    if info.series.is_none() {
+        let collection = metadata
+            .children()
+            .filter(|node| is_element(node, "meta"))
+            .find(|node| node.attribute("property") == Some("belongs-to-collection"));
+        if let Some(collection) = collection {
+            info.series = collection.text().map(str::trim).map(str::to_string);
+            info.series_index = collection
+                .attribute("id")
+                .and_then(|id| refinement(&metadata, id, "group-position"));
+        }
+    }
+
+    // Calibre writes whole positions as "3.0"
+    if let Some(index) = &mut info.series_index {
+        if let Some(whole) = index.strip_suffix(".0") {
+            *index = whole.to_string();
+        }
+    }
+
+    info
+}
+
+/// Value of an EPUB 3 `meta` element refining the element with an id
+fn refinement(metadata: &Node<'_, '_>, id: &str, property: &str) -> Option<String> {
+    let target = format!("#{}", id);
+    metadata
+        .children()
+        .filter(|node| is_element(node, "meta"))
+        .find(|node| node.attribute("refines") == Some(target.as_str()) && node.attribute("property") == Some(property))
+        .and_then(|node| node.text())
+        .map(|value| value.trim().to_string())
+}
+
+/// Location of the cover image relative to the package document
+///
+/// EPUB 3 marks the manifest item with the `cover-image` property; EPUB 2
+/// names it in a `cover` meta element.
+fn cover_href(package: &Document<'_>) -> Option<String> {
+    let items = package
+        .descendants()
+        .filter(|node| is_element(node, "item"))
+        .collect::<Vec<_>>();
+    let by_property = items.iter().find(|item| {
+        item.attribute("properties")
+            .is_some_and(|properties| properties.split_whitespace().any(|property| property == "cover-image"))
+    });
+    let by_meta = || {
+        let id = package
+            .descendants()
+            .filter(|node| is_element(node, "meta"))
+            .find(|node| node.attribute("name") == Some("cover"))?
+            .attribute("content")?;
+        items.iter().find(|item| item.attribute("id") == Some(id))
+    };
+    by_property
+        .or_else(by_meta)
+        .and_then(|item| item.attribute("href"))
+        .map(str::to_string)
+}
+
+/// Path within the archive of a reference relative to the package document
+fn resolve(package_path: &str, href: &str) -> String {
+    let href = percent_decode(href.split('#').next().unwrap_or_default());
+    let mut parts = match package_path.rsplit_once('/') {
+        Some((dir, _)) => dir.split('/').collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Decode the `%XX` escapes of a URL path
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Plain text of a description, which publishers often write as HTML
+fn strip_markup(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut in_tag = false;
+    for c in value.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn read_text<R: Read + Seek>(reader: &mut R, archive: &Archive, name: &str) -> Result<String> {
+    let entry = archive
+        .find(name)
+        .ok_or_else(|| RustFlixError::media_processing(format!("EPUB is missing {}", name)))?;
+    let data = archive.read(reader, entry)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn invalid(e: roxmltree::Error) -> RustFlixError {
+    RustFlixError::media_processing(format!("Invalid EPUB XML: {}", e))
+}
+
+/// Check whether a node is an element with a name, ignoring its namespace,
+/// as packages differ in the prefixes and namespaces they declare
+fn is_element(node: &Node<'_, '_>, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPUB2: &str = r#"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>The Colour of Magic</dc:title>
+    <dc:creator opf:role="aut">Terry Pratchett</dc:creator>
+    <dc:creator opf:role="ill">Josh Kirby</dc:creator>
+    <dc:date>1983-11-24T00:00:00+00:00</dc:date>
+    <dc:description>&lt;p&gt;The &lt;b&gt;first&lt;/b&gt; Discworld novel.&lt;/p&gt;</dc:description>
+    <meta name="calibre:series" content="Discworld"/>
+    <meta name="calibre:series_index" content="1.0"/>
+    <meta name="cover" content="cover-img"/>
+  </metadata>
+  <manifest>
+    <item id="cover-img" href="Images/Cover%20Art.jpg" media-type="image/jpeg"/>
+  </manifest>
+</package>"#;
+
+    const EPUB3: &str = r##"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Leviathan Wakes</dc:title>
+    <dc:creator id="c1">James S. A. Corey</dc:creator>
+    <meta refines="#c1" property="role">aut</meta>
+    <dc:language>en</dc:language>
+    <meta property="belongs-to-collection" id="s1">The Expanse</meta>
+    <meta refines="#s1" property="group-position">1</meta>
+  </metadata>
+  <manifest>
+    <item id="c" href="../cover.png" properties="cover-image" media-type="image/png"/>
+  </manifest>
+</package>"##;
+
+    #[test]
+    fn test_parse_epub2_package() {
+        let package = Document::parse(EPUB2).unwrap();
+        let info = parse_package(&package);
+
+        assert_eq!(info.title.as_deref(), Some("The Colour of Magic"));
+        assert_eq!(info.author.as_deref(), Some("Terry Pratchett"));
+        assert_eq!(info.year, Some(1983));
+        assert_eq!(info.series.as_deref(), Some("Discworld"));
+        assert_eq!(info.series_index.as_deref(), Some("1"));
+        assert_eq!(info.description.as_deref(), Some("The first Discworld novel."));
+        assert_eq!(resolve("OEBPS/content.opf", &cover_href(&package).unwrap()), "OEBPS/Images/Cover Art.jpg");
+    }
+
+    #[test]
+    fn test_parse_epub3_package() {
+        let package = Document::parse(EPUB3).unwrap();
+        let info = parse_package(&package);
+
+        assert_eq!(info.author.as_deref(), Some("James S. A. Corey"));
+        assert_eq!(info.language.as_deref(), Some("en"));
+        assert_eq!(info.series.as_deref(), Some("The Expanse"));
+        assert_eq!(info.series_index.as_deref(), Some("1"));
+        assert_eq!(resolve("OPS/book/content.opf", &cover_href(&package).unwrap()), "OPS/cover.png");
+    }
+}
@@ -0,0 +1,358 @@
+//! Comic archives and e-books
+//!
+//! CBZ and CBR comics are archives of page images, listed in natural order
+//! and described by an optional `ComicInfo.xml`. EPUB packages are ZIP
+//! archives too, described by their OPF package document; PDF documents
+//! only get a best-effort scan for their page count, title and author. Like
+//! `crate::probe`, the readers work on the file directly, with blocking
+//! I/O.
+
+pub(crate) mod epub;
+pub(crate) mod pdf;
+pub(crate) mod rar;
+pub(crate) mod zip;
+
+use crate::audiobooks::natural_key;
+use crate::photo;
+use crate::tags::{parse_year, CoverArt};
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use tracing::warn;
+
+/// Largest archive entry read into memory (64 MiB)
+pub(crate) const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+const COMIC_INFO: &str = "ComicInfo.xml";
+
+/// Details read from a comic or e-book
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BookInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    /// Position in the series, e.g. "3" or "2.5"
+    pub series_index: Option<String>,
+    pub publisher: Option<String>,
+    pub language: Option<String>,
+    pub description: Option<String>,
+    pub year: Option<i32>,
+    /// Pages of comics and PDF documents
+    pub page_count: Option<u32>,
+    pub cover: Option<CoverArt>,
+}
+
+/// Page image of a comic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    /// Path of the image within the archive
+    pub name: String,
+    pub mime_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// File within an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Entry {
+    pub name: String,
+    /// Offset of the local header of ZIP entries, of the data of RAR entries
+    pub offset: u64,
+    pub compressed_size: u64,
+    pub size: u64,
+    pub method: Method,
+}
+
+/// Compression of an archive entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Method {
+    Stored,
+    Deflate,
+    /// Compressed some other way, or encrypted
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Rar,
+}
+
+/// Listing of a ZIP or RAR archive
+#[derive(Debug, Clone)]
+pub(crate) struct Archive {
+    kind: ArchiveKind,
+    pub entries: Vec<Entry>,
+}
+
+impl Archive {
+    /// List an archive, recognised by its signature rather than its
+    /// extension, as comics renamed from one to the other are common
+    pub fn open<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Self> {
+        let mut signature = Vec::with_capacity(8);
+        reader.seek(SeekFrom::Start(0))?;
+        reader.take(8).read_to_end(&mut signature)?;
+        if signature.starts_with(rar::RAR4_SIGNATURE) || signature.starts_with(rar::RAR5_SIGNATURE) {
+            let entries = rar::entries(reader, file_size)?;
+            Ok(Self { kind: ArchiveKind::Rar, entries })
+        } else {
+            let entries = zip::entries(reader, file_size)?;
+            Ok(Self { kind: ArchiveKind::Zip, entries })
+        }
+    }
+
+    /// Entry with a path, compared ignoring ASCII case
+    pub fn find(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Content of an entry
+    pub fn read<R: Read + Seek>(&self, reader: &mut R, entry: &Entry) -> Result<Vec<u8>> {
+        if entry.size > MAX_ENTRY_SIZE {
+            return Err(RustFlixError::media_processing(format!(
+                "Archive entry {} is too large ({} bytes)",
+                entry.name, entry.size
+            )));
+        }
+        match self.kind {
+            ArchiveKind::Zip => zip::read_entry(reader, entry),
+            ArchiveKind::Rar => rar::read_entry(reader, entry),
+        }
+    }
+
+    /// Page images in reading order, leaving out hidden files and the
+    /// resource forks macOS adds to archives
+    pub fn pages(&self) -> Vec<&Entry> {
+        let mut pages = self
+            .entries
+            .iter()
+            .filter(|entry| image_mime(&entry.name).is_some())
+            .filter(|entry| {
+                !entry
+                    .name
+                    .split('/')
+                    .any(|part| part.starts_with('.') || part.eq_ignore_ascii_case("__MACOSX"))
+            })
+            .collect::<Vec<_>>();
+        pages.sort_by_cached_key(|entry| natural_key(Path::new(&entry.name)));
+        pages
+    }
+}
+
+/// Check whether a format is a comic or e-book format with a reader
+pub fn supports_format(format: MediaFormat) -> bool {
+    format.is_book()
+}
+
+/// Read the details and cover of a comic or e-book
+pub fn read_file(path: &Path, format: MediaFormat) -> Result<BookInfo> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    read_from(&mut BufReader::new(file), file_size, format)
+}
+
+/// Read the details and cover of a comic or e-book read through `reader`
+pub fn read_from<R: Read + Seek>(reader: &mut R, file_size: u64, format: MediaFormat) -> Result<BookInfo> {
+    match format {
+        MediaFormat::Cbz | MediaFormat::Cbr => read_comic(reader, file_size),
+        MediaFormat::Epub => {
+            let archive = Archive::open(reader, file_size)?;
+            epub::read(reader, &archive)
+        }
+        MediaFormat::Pdf => pdf::read(reader, file_size),
+        _ => Err(RustFlixError::media_processing(format!("No book reader for {:?}", format))),
+    }
+}
+
+/// Paths within the archive of the pages of a comic, in reading order
+pub fn list_pages(path: &Path) -> Result<Vec<String>> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let archive = Archive::open(&mut BufReader::new(file), file_size)?;
+    Ok(archive.pages().into_iter().map(|entry| entry.name.clone()).collect())
+}
+
+/// Read a page of a comic, `index` counting from 0
+pub fn read_page(path: &Path, index: usize) -> Result<Page> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    read_page_from(&mut BufReader::new(file), file_size, index)
+}
+
+/// Read a page of a comic read through `reader`, `index` counting from 0
+pub fn read_page_from<R: Read + Seek>(reader: &mut R, file_size: u64, index: usize) -> Result<Page> {
+    let archive = Archive::open(reader, file_size)?;
+    let pages = archive.pages();
+    let entry = pages
+        .get(index)
+        .ok_or_else(|| RustFlixError::not_found("page", &(index + 1).to_string()))?;
+    Ok(Page {
+        name: entry.name.clone(),
+        mime_type: image_mime(&entry.name).unwrap_or("application/octet-stream"),
+        data: archive.read(reader, entry)?,
+    })
+}
+
+/// Read the `ComicInfo.xml` and cover of a comic archive
+fn read_comic<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<BookInfo> {
+    let archive = Archive::open(reader, file_size)?;
+    let pages = archive.pages();
+
+    let mut info = BookInfo::default();
+    let mut cover_index = None;
+    let comic_info = archive
+        .entries
+        .iter()
+        .find(|entry| entry.name.rsplit('/').next().is_some_and(|name| name.eq_ignore_ascii_case(COMIC_INFO)));
+    if let Some(entry) = comic_info {
+        match archive.read(reader, entry).and_then(|data| parse_comic_info(&String::from_utf8_lossy(&data))) {
+            Ok((comic_info, front_cover)) => {
+                info = comic_info;
+                cover_index = front_cover;
+            }
+            Err(e) => warn!("Failed to read {}: {}", entry.name, e),
+        }
+    }
+    info.page_count = Some(pages.len() as u32);
+
+    let cover = cover_index.and_then(|index| pages.get(index)).or(pages.first());
+    if let Some(entry) = cover {
+        match archive.read(reader, entry) {
+            Ok(data) => {
+                let mime_type = image_mime(&entry.name).unwrap_or_default().to_string();
+                info.cover = Some(CoverArt { mime_type, data });
+            }
+            Err(e) => warn!("Failed to read cover page {}: {}", entry.name, e),
+        }
+    }
+
+    Ok(info)
+}
+
+/// Details of a `ComicInfo.xml` document, with the index of the page marked
+/// as the front cover
+fn parse_comic_info(xml: &str) -> Result<(BookInfo, Option<usize>)> {
+    let document = roxmltree::Document::parse(xml)
+        .map_err(|e| RustFlixError::media_processing(format!("Invalid ComicInfo.xml: {}", e)))?;
+    let root = document.root_element();
+    let text = |name: &str| {
+        root.children()
+            .find(|node| node.has_tag_name(name))
+            .and_then(|node| node.text())
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .map(str::to_string)
+    };
+
+    let info = BookInfo {
+        title: text("Title"),
+        author: text("Writer"),
+        series: text("Series"),
+        series_index: text("Number"),
+        publisher: text("Publisher"),
+        language: text("LanguageISO"),
+        description: text("Summary"),
+        year: text("Year").and_then(|year| parse_year(&year)),
+        page_count: None,
+        cover: None,
+    };
+    let front_cover = root
+        .descendants()
+        .filter(|node| node.has_tag_name("Page"))
+        .find(|node| node.attribute("Type") == Some("FrontCover"))
+        .and_then(|node| node.attribute("Image"))
+        .and_then(|image| image.trim().parse().ok());
+
+    Ok((info, front_cover))
+}
+
+/// MIME type of a page or cover image decodable by the image pipeline,
+/// from its file name
+pub(crate) fn image_mime(name: &str) -> Option<&'static str> {
+    let ext = Path::new(name).extension()?.to_str()?;
+    let format = MediaFormat::from_extension(ext);
+    photo::supports_format(format).then(|| format.mime_type())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const COMIC_INFO_XML: &str = r#"<?xml version="1.0"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Title>The Long Night</Title>
+  <Series>Night Watch</Series>
+  <Number>3</Number>
+  <Writer>A. Writer</Writer>
+  <Year>2019</Year>
+  <LanguageISO>en</LanguageISO>
+  <Pages>
+    <Page Image="0" Type="InnerCover"/>
+    <Page Image="1" Type="FrontCover"/>
+  </Pages>
+</ComicInfo>"#;
+
+    fn comic() -> Vec<u8> {
+        zip::tests::build(&[
+            ("Issue 3/10.jpg", b"page ten"),
+            ("Issue 3/2.jpg", b"page two"),
+            ("__MACOSX/Issue 3/._2.jpg", b"resource fork"),
+            ("Issue 3/notes.txt", b"not a page"),
+            ("ComicInfo.xml", COMIC_INFO_XML.as_bytes()),
+        ])
+    }
+
+    #[test]
+    fn test_read_comic() {
+        let archive = comic();
+        let info = read_from(&mut Cursor::new(&archive), archive.len() as u64, MediaFormat::Cbz).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("The Long Night"));
+        assert_eq!(info.series.as_deref(), Some("Night Watch"));
+        assert_eq!(info.series_index.as_deref(), Some("3"));
+        assert_eq!(info.author.as_deref(), Some("A. Writer"));
+        assert_eq!(info.year, Some(2019));
+        assert_eq!(info.page_count, Some(2));
+        let cover = info.cover.unwrap();
+        assert_eq!(cover.mime_type, "image/jpeg");
+        assert_eq!(cover.data, b"page ten");
+    }
+
+    #[test]
+    fn test_read_pages() {
+        let archive = comic();
+        let page = read_page_from(&mut Cursor::new(&archive), archive.len() as u64, 0).unwrap();
+        assert_eq!(page.name, "Issue 3/2.jpg");
+        assert_eq!(page.data, b"page two");
+        assert!(read_page_from(&mut Cursor::new(&archive), archive.len() as u64, 2).is_err());
+    }
+
+    #[test]
+    fn test_read_epub() {
+        let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#;
+        let package = r#"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>A Book</dc:title>
+    <dc:creator>An Author</dc:creator>
+  </metadata>
+  <manifest><item id="cover" href="images/cover.png" properties="cover-image" media-type="image/png"/></manifest>
+</package>"#;
+        let archive = zip::tests::build(&[
+            ("mimetype", b"application/epub+zip"),
+            ("META-INF/container.xml", container.as_bytes()),
+            ("OEBPS/content.opf", package.as_bytes()),
+            ("OEBPS/images/cover.png", b"cover image"),
+        ]);
+        let info = read_from(&mut Cursor::new(&archive), archive.len() as u64, MediaFormat::Epub).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("A Book"));
+        assert_eq!(info.author.as_deref(), Some("An Author"));
+        assert_eq!(info.page_count, None);
+        assert_eq!(info.cover.unwrap().data, b"cover image");
+    }
+}
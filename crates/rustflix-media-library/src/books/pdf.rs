@@ -0,0 +1,258 @@
+//! PDF document details
+//!
+//! A best-effort scan rather than a PDF parser: the page count is the
+//! `/Count` of the root page tree and the title and author come from the
+//! document information dictionary named by the trailer. Objects kept in
+//! compressed object streams are not seen, so those fields stay empty for
+//! documents storing them that way.
+
+use super::BookInfo;
+use crate::probe::MAX_HEADER_SIZE;
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Read the page count, title and author of a PDF document
+///
+/// Only the first `MAX_HEADER_SIZE` bytes are searched for objects.
+pub(crate) fn read<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<BookInfo> {
+    let mut data = Vec::with_capacity(file_size.min(MAX_HEADER_SIZE) as usize);
+    reader.seek(SeekFrom::Start(0))?;
+    reader.take(MAX_HEADER_SIZE).read_to_end(&mut data)?;
+    if !data.starts_with(b"%PDF-") {
+        return Err(RustFlixError::media_processing("Not a PDF document"));
+    }
+
+    // The trailer, or the cross-reference stream replacing it, ends the file
+    let mut tail = data.clone();
+    if file_size > data.len() as u64 {
+        let tail_size = file_size.min(64 * 1024);
+        tail = vec![0u8; tail_size as usize];
+        reader.seek(SeekFrom::Start(file_size - tail_size))?;
+        reader.read_exact(&mut tail)?;
+    }
+
+    let mut info = BookInfo {
+        page_count: page_count(&data),
+        ..BookInfo::default()
+    };
+    let document_info = rfind(&tail, b"/Info")
+        .and_then(|pos| reference(&tail[pos + 5..]))
+        .and_then(|(number, generation)| object(&data, number, generation));
+    if let Some(document_info) = document_info {
+        info.title = string_value(document_info, b"/Title");
+        info.author = string_value(document_info, b"/Author");
+    }
+
+    Ok(info)
+}
+
+/// Largest `/Count` of the page tree nodes, which is that of the root
+fn page_count(data: &[u8]) -> Option<u32> {
+    let mut count = None;
+    let mut pos = 0;
+    while let Some(found) = find(&data[pos..], b"/Type") {
+        pos += found + 5;
+        let rest = skip_whitespace(&data[pos..]);
+        if !rest.starts_with(b"/Pages") || rest.get(6).is_some_and(u8::is_ascii_alphanumeric) {
+            continue;
+        }
+        // The node dictionary ends at the first ">>", unless it nests
+        // another; a few hundred bytes either way hold its keys
+        let start = rfind(&data[pos.saturating_sub(512)..pos], b"<<").map_or(pos, |start| pos.saturating_sub(512) + start);
+        let end = find(&data[pos..], b">>").map_or(data.len(), |end| pos + end);
+        let node_count = find(&data[start..end], b"/Count")
+            .and_then(|at| integer(&data[start + at + 6..end]))
+            .and_then(|value| u32::try_from(value).ok());
+        count = count.max(node_count);
+    }
+    count
+}
+
+/// Body of the dictionary of an indirect object, searched for as
+/// "<number> <generation> obj"
+fn object(data: &[u8], number: u64, generation: u64) -> Option<&[u8]> {
+    let header = format!("{} {} obj", number, generation);
+    let mut pos = 0;
+    while let Some(found) = find(&data[pos..], header.as_bytes()) {
+        let start = pos + found;
+        pos = start + header.len();
+        // Skip matches of a longer object number, e.g. "12 0 obj" for "2 0 obj"
+        if start > 0 && data[start - 1].is_ascii_digit() {
+            continue;
+        }
+        let end = find(&data[pos..], b"endobj").map_or(data.len(), |end| pos + end);
+        return Some(&data[pos..end]);
+    }
+    None
+}
+
+/// Object number and generation of a reference such as "12 0 R"
+fn reference(data: &[u8]) -> Option<(u64, u64)> {
+    let data = skip_whitespace(data);
+    let number_len = data.iter().take_while(|b| b.is_ascii_digit()).count();
+    let number = std::str::from_utf8(&data[..number_len]).ok()?.parse().ok()?;
+    let rest = skip_whitespace(&data[number_len..]);
+    let generation_len = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    let generation = std::str::from_utf8(&rest[..generation_len]).ok()?.parse().ok()?;
+    skip_whitespace(&rest[generation_len..]).starts_with(b"R").then_some((number, generation))
+}
+
+/// Integer at the start of `data`, after whitespace
+fn integer(data: &[u8]) -> Option<i64> {
+    let data = skip_whitespace(data);
+    let len = data
+        .iter()
+        .enumerate()
+        .take_while(|(i, b)| b.is_ascii_digit() || (*i == 0 && **b == b'-'))
+        .count();
+    std::str::from_utf8(&data[..len]).ok()?.parse().ok()
+}
+
+/// Text value of a dictionary key, a literal "(...)" or hexadecimal "<...>"
+/// string
+fn string_value(dictionary: &[u8], key: &[u8]) -> Option<String> {
+    let pos = find(dictionary, key)? + key.len();
+    let value = skip_whitespace(&dictionary[pos..]);
+    let bytes = match value.first()? {
+        b'(' => literal_string(&value[1..]),
+        b'<' => hex_string(&value[1..]),
+        _ => return None,
+    };
+    let text = decode_text(&bytes);
+    let text = text.trim_matches(char::from(0)).trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Bytes of a literal string up to its closing parenthesis, with escapes
+/// resolved and balanced parentheses kept
+fn literal_string(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let b = data[i];
+        i += 1;
+        match b {
+            b'\\' => {
+                let Some(&escaped) = data.get(i) else { break };
+                i += 1;
+                match escaped {
+                    b'n' => bytes.push(b'\n'),
+                    b'r' => bytes.push(b'\r'),
+                    b't' => bytes.push(b'\t'),
+                    b'b' => bytes.push(0x08),
+                    b'f' => bytes.push(0x0c),
+                    b'0'..=b'7' => {
+                        let mut value = (escaped - b'0') as u32;
+                        for _ in 0..2 {
+                            match data.get(i) {
+                                Some(digit @ b'0'..=b'7') => {
+                                    value = value * 8 + (digit - b'0') as u32;
+                                    i += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        bytes.push(value as u8);
+                    }
+                    // Line continuation
+                    b'\r' | b'\n' => {}
+                    other => bytes.push(other),
+                }
+            }
+            b'(' => {
+                depth += 1;
+                bytes.push(b);
+            }
+            b')' if depth == 0 => break,
+            b')' => {
+                depth -= 1;
+                bytes.push(b);
+            }
+            _ => bytes.push(b),
+        }
+    }
+    bytes
+}
+
+/// Bytes of a hexadecimal string up to its closing angle bracket
+fn hex_string(data: &[u8]) -> Vec<u8> {
+    let digits = data
+        .iter()
+        .take_while(|b| **b != b'>')
+        .filter_map(|b| (*b as char).to_digit(16))
+        .map(|digit| digit as u8)
+        .collect::<Vec<_>>();
+    // An odd final digit is followed by an implied 0
+    digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair.get(1).copied().unwrap_or(0))
+        .collect()
+}
+
+/// Text of a string: UTF-16 after a byte order mark, UTF-8 after its own
+/// mark, and otherwise PDFDocEncoding, read as Latin-1
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        let units = utf16.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]]));
+        char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+    } else if let Some(utf8) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        String::from_utf8_lossy(utf8).into_owned()
+    } else {
+        bytes.iter().map(|b| *b as char).collect()
+    }
+}
+
+fn skip_whitespace(data: &[u8]) -> &[u8] {
+    let len = data.iter().take_while(|b| b.is_ascii_whitespace() || **b == 0).count();
+    &data[len..]
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|window| window == needle)
+}
+
+fn rfind(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).rposition(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PDF: &[u8] = b"%PDF-1.4
+1 0 obj << /Type /Catalog /Pages 3 0 R >> endobj
+3 0 obj << /Type /Pages /Kids [4 0 R] /Count 12 >> endobj
+4 0 obj << /Type /Pages /Parent 3 0 R /Count 5 >> endobj
+5 0 obj << /Type /Page /Parent 4 0 R >> endobj
+12 0 obj << /Title (Wrong object) >> endobj
+2 0 obj << /Title <FEFF0054006F00740061006C> /Author (Jane \\(J.\\) Doe) >> endobj
+xref
+0 1
+0000000000 65535 f
+trailer
+<< /Size 13 /Root 1 0 R /Info 2 0 R >>
+startxref
+0
+%%EOF";
+
+    #[test]
+    fn test_read_pdf() {
+        let info = read(&mut Cursor::new(PDF), PDF.len() as u64).unwrap();
+        assert_eq!(info.page_count, Some(12));
+        assert_eq!(info.title.as_deref(), Some("Total"));
+        assert_eq!(info.author.as_deref(), Some("Jane (J.) Doe"));
+    }
+
+    #[test]
+    fn test_literal_string_escapes() {
+        assert_eq!(literal_string(b"a\\(b\\) (c) \\101\\n)rest"), b"a(b) (c) A\n");
+        assert_eq!(decode_text(&hex_string(b"48 6 >")), "H`");
+    }
+
+    #[test]
+    fn test_not_a_pdf() {
+        assert!(read(&mut Cursor::new(b"plain text"), 10).is_err());
+    }
+}
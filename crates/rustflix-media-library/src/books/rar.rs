@@ -0,0 +1,297 @@
+//! RAR archive listing, for CBR comics
+//!
+//! Both RAR 4 and RAR 5 headers are read. RAR compression is proprietary
+//! and not implemented, so only files stored without compression can be
+//! read; comic pages are already compressed images and are often stored
+//! that way.
+
+use super::{Entry, Method};
+use crate::probe::{le16, le32, truncated, MAX_HEADER_SIZE};
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+pub(crate) const RAR4_SIGNATURE: &[u8] = b"Rar!\x1a\x07\x00";
+pub(crate) const RAR5_SIGNATURE: &[u8] = b"Rar!\x1a\x07\x01\x00";
+
+/// Most headers read before giving up on an archive
+const MAX_HEADERS: usize = 100_000;
+
+/// List the files of an archive
+pub(crate) fn entries<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Vec<Entry>> {
+    let mut signature = [0u8; 8];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut signature)?;
+    if signature.starts_with(RAR5_SIGNATURE) {
+        rar5_entries(reader, file_size)
+    } else if signature.starts_with(RAR4_SIGNATURE) {
+        rar4_entries(reader, file_size)
+    } else {
+        Err(RustFlixError::media_processing("Not a RAR archive"))
+    }
+}
+
+/// Read an entry stored without compression
+pub(crate) fn read_entry<R: Read + Seek>(reader: &mut R, entry: &Entry) -> Result<Vec<u8>> {
+    if entry.method != Method::Stored {
+        return Err(RustFlixError::media_processing(format!(
+            "RAR entry {} is compressed or encrypted; only uncompressed RAR archives can be read",
+            entry.name
+        )));
+    }
+    let mut data = Vec::with_capacity(entry.size as usize);
+    reader.seek(SeekFrom::Start(entry.offset))?;
+    reader.take(entry.size).read_to_end(&mut data)?;
+    if (data.len() as u64) < entry.size {
+        return Err(truncated());
+    }
+    Ok(data)
+}
+
+fn rar4_entries<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut pos = RAR4_SIGNATURE.len() as u64;
+    for _ in 0..MAX_HEADERS {
+        if pos + 7 > file_size {
+            break;
+        }
+        let mut base = [0u8; 7];
+        reader.seek(SeekFrom::Start(pos))?;
+        reader.read_exact(&mut base)?;
+        let kind = base[2];
+        let flags = le16(&base, 3)?;
+        let header_size = le16(&base, 5)? as u64;
+        if header_size < 7 {
+            return Err(RustFlixError::media_processing("Invalid RAR header"));
+        }
+        let mut header = vec![0u8; header_size as usize];
+        reader.seek(SeekFrom::Start(pos))?;
+        reader.read_exact(&mut header)?;
+
+        let data_size = match kind {
+            // Archive header, with encrypted headers past it
+            0x73 if flags & 0x0080 != 0 => {
+                return Err(RustFlixError::media_processing("RAR archive headers are encrypted"))
+            }
+            // End of archive
+            0x7b => break,
+            // File header, its packed size extended by a high part for
+            // files over 4 GiB
+            0x74 => {
+                let high = if flags & 0x0100 != 0 { le32(&header, 32)? as u64 } else { 0 };
+                let compressed_size = (high << 32) | le32(&header, 7)? as u64;
+                let high = if flags & 0x0100 != 0 { le32(&header, 36)? as u64 } else { 0 };
+                let size = (high << 32) | le32(&header, 11)? as u64;
+                let method = header.get(25).copied().ok_or_else(truncated)?;
+                let name_len = le16(&header, 26)? as usize;
+                let name_start = if flags & 0x0100 != 0 { 40 } else { 32 };
+                let name = header.get(name_start..name_start + name_len).ok_or_else(truncated)?;
+                // Unicode names follow the plain name after a NUL byte
+                let name = name.split(|b| *b == 0).next().unwrap_or_default();
+                let name = String::from_utf8_lossy(name).replace('\\', "/");
+
+                let directory = flags & 0x00e0 == 0x00e0;
+                let continued = flags & 0x0003 != 0;
+                if !directory {
+                    let method = if method == 0x30 && flags & 0x0004 == 0 && !continued {
+                        Method::Stored
+                    } else {
+                        Method::Unsupported
+                    };
+                    entries.push(Entry { name, offset: pos + header_size, compressed_size, size, method });
+                }
+                compressed_size
+            }
+            _ if flags & 0x8000 != 0 => le32(&header, 7)? as u64,
+            _ => 0,
+        };
+        pos += header_size + data_size;
+    }
+    Ok(entries)
+}
+
+fn rar5_entries<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut pos = RAR5_SIGNATURE.len() as u64;
+    for _ in 0..MAX_HEADERS {
+        if pos + 7 > file_size {
+            break;
+        }
+        // CRC32 and the header size, a variable-length integer of up to 3
+        // bytes for the 2 MiB headers allow
+        let mut start = [0u8; 7];
+        reader.seek(SeekFrom::Start(pos))?;
+        reader.read_exact(&mut start)?;
+        let mut cursor = VintReader { data: &start[4..], pos: 0 };
+        let header_size = cursor.next()?;
+        let header_start = pos + 4 + cursor.pos as u64;
+        if header_size == 0 || header_size > MAX_HEADER_SIZE {
+            return Err(RustFlixError::media_processing("Invalid RAR header"));
+        }
+        let mut header = vec![0u8; header_size as usize];
+        reader.seek(SeekFrom::Start(header_start))?;
+        reader.read_exact(&mut header)?;
+
+        let mut fields = VintReader { data: &header, pos: 0 };
+        let kind = fields.next()?;
+        let flags = fields.next()?;
+        let extra_size = if flags & 0x0001 != 0 { fields.next()? } else { 0 };
+        let data_size = if flags & 0x0002 != 0 { fields.next()? } else { 0 };
+        let data_offset = header_start + header_size;
+
+        match kind {
+            // Archive encryption header
+            4 => return Err(RustFlixError::media_processing("RAR archive headers are encrypted")),
+            // End of archive
+            5 => break,
+            // File header
+            2 => {
+                let file_flags = fields.next()?;
+                let size = fields.next()?;
+                fields.next()?;
+                if file_flags & 0x0002 != 0 {
+                    fields.skip(4)?;
+                }
+                if file_flags & 0x0004 != 0 {
+                    fields.skip(4)?;
+                }
+                let compression = fields.next()?;
+                fields.next()?;
+                let name_len = fields.next()? as usize;
+                let name = String::from_utf8_lossy(fields.bytes(name_len)?).into_owned();
+
+                let extra = header.get(header.len().saturating_sub(extra_size as usize)..).unwrap_or_default();
+                let encrypted = has_record(extra, 0x01);
+                // Solid files and files split across volumes depend on data
+                // elsewhere
+                let stored = compression & 0x0380 == 0 && compression & 0x0040 == 0;
+                let split = flags & 0x0018 != 0;
+                if file_flags & 0x0001 == 0 {
+                    let method = if stored && !encrypted && !split { Method::Stored } else { Method::Unsupported };
+                    entries.push(Entry { name, offset: data_offset, compressed_size: data_size, size, method });
+                }
+            }
+            _ => {}
+        }
+        pos = data_offset + data_size;
+    }
+    Ok(entries)
+}
+
+/// Check whether the extra area of a RAR 5 header holds a record of a type
+fn has_record(extra: &[u8], record_type: u64) -> bool {
+    let mut records = VintReader { data: extra, pos: 0 };
+    while records.pos < extra.len() {
+        let Ok(size) = records.next() else { return false };
+        let start = records.pos;
+        if records.next().ok() == Some(record_type) {
+            return true;
+        }
+        records.pos = start + size as usize;
+    }
+    false
+}
+
+/// Reader of the variable-length integers of RAR 5 headers: 7 bits per
+/// byte, least significant first, the high bit set on all but the last
+struct VintReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> VintReader<'a> {
+    fn next(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or_else(truncated)?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(RustFlixError::media_processing("Invalid RAR header"))
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or_else(truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn rar4(files: &[(&str, &[u8], u8)]) -> Vec<u8> {
+        let mut archive = RAR4_SIGNATURE.to_vec();
+        archive.extend_from_slice(&[0, 0, 0x73, 0, 0, 13, 0, 0, 0, 0, 0, 0, 0]);
+        for (name, content, method) in files {
+            let header_size = 32 + name.len() as u16;
+            archive.extend_from_slice(&[0, 0, 0x74]);
+            archive.extend_from_slice(&0x8000u16.to_le_bytes());
+            archive.extend_from_slice(&header_size.to_le_bytes());
+            archive.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            archive.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            archive.extend_from_slice(&[0; 9]);
+            archive.extend_from_slice(&[29, *method]);
+            archive.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            archive.extend_from_slice(&[0; 4]);
+            archive.extend_from_slice(name.as_bytes());
+            archive.extend_from_slice(content);
+        }
+        archive.extend_from_slice(&[0, 0, 0x7b, 0, 0x40, 7, 0]);
+        archive
+    }
+
+    fn rar5(files: &[(&str, &[u8], &[u8])]) -> Vec<u8> {
+        let mut archive = RAR5_SIGNATURE.to_vec();
+        let block = |archive: &mut Vec<u8>, header: &[u8]| {
+            archive.extend_from_slice(&[0; 4]);
+            archive.push(header.len() as u8);
+            archive.extend_from_slice(header);
+        };
+        block(&mut archive, &[1, 0, 0]);
+        for (name, content, compression) in files {
+            let mut header = vec![2, 0x02, content.len() as u8, 0, content.len() as u8, 0];
+            header.extend_from_slice(compression);
+            header.extend_from_slice(&[0, name.len() as u8]);
+            header.extend_from_slice(name.as_bytes());
+            block(&mut archive, &header);
+            archive.extend_from_slice(content);
+        }
+        block(&mut archive, &[5, 0, 0]);
+        archive
+    }
+
+    #[test]
+    fn test_rar4_entries() {
+        let archive = rar4(&[("Issue\\01.jpg", b"first page", 0x30), ("02.jpg", b"packed", 0x33)]);
+        let mut reader = Cursor::new(&archive);
+        let entries = entries(&mut reader, archive.len() as u64).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "Issue/01.jpg");
+        assert_eq!(read_entry(&mut reader, &entries[0]).unwrap(), b"first page");
+        assert_eq!(entries[1].method, Method::Unsupported);
+        assert!(read_entry(&mut reader, &entries[1]).is_err());
+    }
+
+    #[test]
+    fn test_rar5_entries() {
+        let archive = rar5(&[("01.png", b"stored", &[0]), ("02.png", b"packed", &[0x80, 0x01])]);
+        let mut reader = Cursor::new(&archive);
+        let entries = entries(&mut reader, archive.len() as u64).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "01.png");
+        assert_eq!(entries[0].size, 6);
+        assert_eq!(read_entry(&mut reader, &entries[0]).unwrap(), b"stored");
+        assert_eq!(entries[1].method, Method::Unsupported);
+    }
+}
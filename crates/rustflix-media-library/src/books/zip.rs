@@ -0,0 +1,221 @@
+//! ZIP archive directory reader, for CBZ comics and EPUB packages
+//!
+//! Only the central directory is parsed when listing; entries are located
+//! through their local header when read.
+
+use super::{Entry, Method, MAX_ENTRY_SIZE};
+use crate::probe::{le16, le32, le64, truncated, MAX_HEADER_SIZE};
+use flate2::read::DeflateDecoder;
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+const END_OF_DIRECTORY: &[u8] = b"PK\x05\x06";
+const ZIP64_LOCATOR: &[u8] = b"PK\x06\x07";
+const ZIP64_END_OF_DIRECTORY: &[u8] = b"PK\x06\x06";
+const DIRECTORY_ENTRY: &[u8] = b"PK\x01\x02";
+const LOCAL_HEADER: &[u8] = b"PK\x03\x04";
+
+/// Size of the end of central directory record without its comment
+const END_SIZE: u64 = 22;
+
+/// Longest archive comment following the end of central directory record
+const MAX_COMMENT_SIZE: u64 = 0xFFFF;
+
+/// List the files of an archive
+pub(crate) fn entries<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Vec<Entry>> {
+    let tail_size = file_size.min(END_SIZE + MAX_COMMENT_SIZE);
+    let tail_start = file_size - tail_size;
+    let mut tail = vec![0u8; tail_size as usize];
+    reader.seek(SeekFrom::Start(tail_start))?;
+    reader.read_exact(&mut tail)?;
+
+    let end = tail
+        .windows(4)
+        .rposition(|window| window == END_OF_DIRECTORY)
+        .ok_or_else(|| RustFlixError::media_processing("Not a ZIP archive"))?;
+    let mut count = le16(&tail, end + 10)? as u64;
+    let mut directory_size = le32(&tail, end + 12)? as u64;
+    let mut directory_offset = le32(&tail, end + 16)? as u64;
+
+    // ZIP64 archives keep the real values in a record found through the
+    // locator preceding the end of central directory record
+    if end >= 20 && &tail[end - 20..end - 16] == ZIP64_LOCATOR {
+        let record_offset = le64(&tail, end - 12)?;
+        let mut record = [0u8; 56];
+        reader.seek(SeekFrom::Start(record_offset))?;
+        reader.read_exact(&mut record)?;
+        if &record[..4] != ZIP64_END_OF_DIRECTORY {
+            return Err(RustFlixError::media_processing("Invalid ZIP64 end of central directory"));
+        }
+        count = le64(&record, 32)?;
+        directory_size = le64(&record, 40)?;
+        directory_offset = le64(&record, 48)?;
+    }
+
+    if directory_size > MAX_HEADER_SIZE || directory_offset + directory_size > file_size {
+        return Err(RustFlixError::media_processing("Invalid ZIP central directory"));
+    }
+    let mut directory = vec![0u8; directory_size as usize];
+    reader.seek(SeekFrom::Start(directory_offset))?;
+    reader.read_exact(&mut directory)?;
+
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while entries.len() < count as usize && pos + 46 <= directory.len() {
+        let header = &directory[pos..];
+        if &header[..4] != DIRECTORY_ENTRY {
+            return Err(RustFlixError::media_processing("Invalid ZIP central directory entry"));
+        }
+        let flags = le16(header, 8)?;
+        let method = le16(header, 10)?;
+        let mut compressed_size = le32(header, 20)? as u64;
+        let mut size = le32(header, 24)? as u64;
+        let name_len = le16(header, 28)? as usize;
+        let extra_len = le16(header, 30)? as usize;
+        let comment_len = le16(header, 32)? as usize;
+        let mut offset = le32(header, 42)? as u64;
+        let name = header.get(46..46 + name_len).ok_or_else(truncated)?;
+        let extra = header.get(46 + name_len..46 + name_len + extra_len).ok_or_else(truncated)?;
+        pos += 46 + name_len + extra_len + comment_len;
+
+        // Sizes and offsets too large for the entry are in the ZIP64 extra
+        // field, in this order
+        let mut wide = zip64_field(extra).unwrap_or_default().chunks_exact(8).map(|value| le64(value, 0));
+        for field in [&mut size, &mut compressed_size, &mut offset] {
+            if *field == 0xFFFF_FFFF {
+                *field = wide.next().ok_or_else(truncated)??;
+            }
+        }
+
+        // Names are UTF-8 when flagged, and nearly always plain ASCII otherwise
+        let name = String::from_utf8_lossy(name).into_owned();
+        if name.ends_with('/') {
+            continue;
+        }
+        let method = match method {
+            _ if flags & 0x0001 != 0 => Method::Unsupported,
+            0 => Method::Stored,
+            8 => Method::Deflate,
+            _ => Method::Unsupported,
+        };
+        entries.push(Entry { name, offset, compressed_size, size, method });
+    }
+
+    Ok(entries)
+}
+
+/// Content of the ZIP64 extra field, if present
+fn zip64_field(extra: &[u8]) -> Option<&[u8]> {
+    let mut pos = 0;
+    while pos + 4 <= extra.len() {
+        let id = le16(extra, pos).ok()?;
+        let len = le16(extra, pos + 2).ok()? as usize;
+        let data = extra.get(pos + 4..pos + 4 + len)?;
+        if id == 0x0001 {
+            return Some(data);
+        }
+        pos += 4 + len;
+    }
+    None
+}
+
+/// Read and decompress an entry
+pub(crate) fn read_entry<R: Read + Seek>(reader: &mut R, entry: &Entry) -> Result<Vec<u8>> {
+    let mut header = [0u8; 30];
+    reader.seek(SeekFrom::Start(entry.offset))?;
+    reader.read_exact(&mut header)?;
+    if &header[..4] != LOCAL_HEADER {
+        return Err(RustFlixError::media_processing(format!("Invalid ZIP entry {}", entry.name)));
+    }
+    let data_offset = entry.offset + 30 + le16(&header, 26)? as u64 + le16(&header, 28)? as u64;
+    reader.seek(SeekFrom::Start(data_offset))?;
+
+    let compressed = reader.take(entry.compressed_size.min(MAX_ENTRY_SIZE));
+    let mut data = Vec::with_capacity(entry.size.min(MAX_ENTRY_SIZE) as usize);
+    match entry.method {
+        Method::Stored => compressed.take(entry.size).read_to_end(&mut data)?,
+        Method::Deflate => DeflateDecoder::new(compressed).take(entry.size).read_to_end(&mut data)?,
+        Method::Unsupported => {
+            return Err(RustFlixError::media_processing(format!(
+                "Unsupported compression or encryption of ZIP entry {}",
+                entry.name
+            )))
+        }
+    };
+    if (data.len() as u64) < entry.size {
+        return Err(truncated());
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use std::io::{Cursor, Write};
+
+    /// Build a ZIP archive of deflated files
+    pub(crate) fn build(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for (name, content) in files {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content).unwrap();
+            let compressed = encoder.finish().unwrap();
+            let offset = archive.len() as u32;
+
+            let fields = |out: &mut Vec<u8>| {
+                out.extend_from_slice(&8u16.to_le_bytes());
+                out.extend_from_slice(&[0; 8]);
+                out.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+                out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+                out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                out.extend_from_slice(&0u16.to_le_bytes());
+            };
+            archive.extend_from_slice(LOCAL_HEADER);
+            archive.extend_from_slice(&[20, 0, 0, 0]);
+            fields(&mut archive);
+            archive.extend_from_slice(name.as_bytes());
+            archive.extend_from_slice(&compressed);
+
+            directory.extend_from_slice(DIRECTORY_ENTRY);
+            directory.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+            fields(&mut directory);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = archive.len() as u32;
+        archive.extend_from_slice(&directory);
+        archive.extend_from_slice(END_OF_DIRECTORY);
+        archive.extend_from_slice(&[0; 4]);
+        archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        archive.extend_from_slice(&directory_offset.to_le_bytes());
+        archive.extend_from_slice(b"\x07\x00comment");
+        archive
+    }
+
+    #[test]
+    fn test_read_entries() {
+        let archive = build(&[("b.txt", b"second"), ("dir/a.txt", &[b'a'; 1000])]);
+        let mut reader = Cursor::new(&archive);
+        let entries = entries(&mut reader, archive.len() as u64).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].name, "dir/a.txt");
+        assert_eq!(entries[1].method, Method::Deflate);
+        assert!(entries[1].compressed_size < 1000);
+        assert_eq!(read_entry(&mut reader, &entries[0]).unwrap(), b"second");
+        assert_eq!(read_entry(&mut reader, &entries[1]).unwrap(), vec![b'a'; 1000]);
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        let data = b"plain text, not an archive";
+        assert!(entries(&mut Cursor::new(data), data.len() as u64).is_err());
+    }
+}
@@ -0,0 +1,406 @@
+//! Blu-ray playlists
+//!
+//! Every `BDMV/PLAYLIST/*.mpls` file is a title playing clips stored in
+//! `BDMV/STREAM`. Its play items give the part of each clip played, and its
+//! entry marks the chapters. Times count ticks of a 45 kHz clock.
+
+use super::{DiscFiles, DiscTitle};
+use crate::probe::{finish_chapters, normalize_language, truncated, ByteReader, ChapterMark};
+use rustflix_core::media::{AudioCodec, MediaStreams, SubtitleTrack, VideoCodec};
+use rustflix_core::{Result, RustFlixError};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Ticks per second of playlist times
+const CLOCK: f64 = 45_000.0;
+
+/// Entry mark type, as opposed to link points
+const ENTRY_MARK: u8 = 1;
+
+/// Playlist parsed from an MPLS file
+#[derive(Debug, Default)]
+pub(crate) struct Playlist {
+    pub items: Vec<PlayItem>,
+    /// Streams of the first play item
+    pub streams: MediaStreams,
+    /// Entry marks, as play item index and time
+    pub marks: Vec<(usize, u32)>,
+}
+
+/// Part of a clip played by a playlist
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PlayItem {
+    /// Clip name, the stream file without its `.m2ts` extension
+    pub clip: String,
+    pub in_time: u32,
+    pub out_time: u32,
+}
+
+impl PlayItem {
+    fn duration(&self) -> f64 {
+        self.out_time.saturating_sub(self.in_time) as f64 / CLOCK
+    }
+}
+
+impl Playlist {
+    /// Seconds played
+    pub fn duration(&self) -> f64 {
+        self.items.iter().map(PlayItem::duration).sum()
+    }
+
+    /// Chapter starts from the entry marks
+    pub fn chapters(&self) -> Vec<ChapterMark> {
+        let mut offsets = Vec::with_capacity(self.items.len());
+        let mut offset = 0.0;
+        for item in &self.items {
+            offsets.push(offset);
+            offset += item.duration();
+        }
+
+        self.marks
+            .iter()
+            .filter_map(|&(index, time)| {
+                let item = self.items.get(index)?;
+                Some(ChapterMark {
+                    start: offsets[index] + time.saturating_sub(item.in_time) as f64 / CLOCK,
+                    end: None,
+                    title: None,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Read every playlist of a Blu-ray disc
+///
+/// Unreadable playlists are skipped, as discs often carry damaged decoys.
+pub(crate) fn read_titles(files: &mut dyn DiscFiles) -> Result<Vec<DiscTitle>> {
+    let clip_sizes = files
+        .list("BDMV/STREAM")?
+        .into_iter()
+        .map(|(name, size)| (name.to_ascii_lowercase(), size))
+        .collect::<HashMap<_, _>>();
+
+    let mut names = files
+        .list("BDMV/PLAYLIST")?
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| name.to_ascii_lowercase().ends_with(".mpls"))
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let mut titles = Vec::new();
+    for name in names {
+        let Some(data) = files.read(&format!("BDMV/PLAYLIST/{}", name))? else {
+            continue;
+        };
+        let playlist = match parse_playlist(&data) {
+            Ok(playlist) => playlist,
+            Err(e) => {
+                warn!("Invalid Blu-ray playlist {}: {}", name, e);
+                continue;
+            }
+        };
+
+        let mut clips = Vec::new();
+        for item in &playlist.items {
+            if !clips.contains(&item.clip) {
+                clips.push(item.clip.clone());
+            }
+        }
+        let duration = playlist.duration();
+        titles.push(DiscTitle {
+            name,
+            duration,
+            chapters: finish_chapters(playlist.chapters(), Some(duration)),
+            size: clips
+                .iter()
+                .filter_map(|clip| clip_sizes.get(&format!("{}.m2ts", clip.to_ascii_lowercase())))
+                .sum(),
+            files: clips.iter().map(|clip| format!("BDMV/STREAM/{}.m2ts", clip)).collect(),
+            streams: playlist.streams,
+        });
+    }
+
+    Ok(titles)
+}
+
+/// Parse an MPLS playlist file
+pub(crate) fn parse_playlist(data: &[u8]) -> Result<Playlist> {
+    let mut header = ByteReader::new(data);
+    if &header.fourcc()? != b"MPLS" {
+        return Err(RustFlixError::media_processing("Not a Blu-ray playlist"));
+    }
+    header.skip(4)?; // version
+    let list_start = header.u32()? as usize;
+    let marks_start = header.u32()? as usize;
+
+    let mut playlist = Playlist::default();
+    let mut reader = ByteReader::new(data.get(list_start..).ok_or_else(truncated)?);
+    reader.skip(4 + 2)?; // length, reserved
+    let item_count = reader.u16()?;
+    reader.skip(2)?; // number of sub paths
+    for index in 0..item_count {
+        let length = reader.u16()? as usize;
+        let (item, streams) = parse_play_item(reader.bytes(length)?)?;
+        if index == 0 {
+            playlist.streams = streams;
+        }
+        playlist.items.push(item);
+    }
+
+    let mut reader = ByteReader::new(data.get(marks_start..).ok_or_else(truncated)?);
+    reader.skip(4)?; // length
+    let mark_count = reader.u16()?;
+    for _ in 0..mark_count {
+        reader.skip(1)?; // reserved
+        let mark_type = reader.u8()?;
+        let item = reader.u16()? as usize;
+        let time = reader.u32()?;
+        reader.skip(2 + 4)?; // entry PID, duration
+        if mark_type == ENTRY_MARK {
+            playlist.marks.push((item, time));
+        }
+    }
+
+    Ok(playlist)
+}
+
+fn parse_play_item(data: &[u8]) -> Result<(PlayItem, MediaStreams)> {
+    let mut reader = ByteReader::new(data);
+    let clip = String::from_utf8_lossy(reader.bytes(5)?).into_owned();
+    reader.skip(4)?; // codec identifier, "M2TS"
+    let multi_angle = reader.u16()? & 0x0010 != 0;
+    reader.skip(1)?; // STC id
+    let in_time = reader.u32()?;
+    let out_time = reader.u32()?;
+    reader.skip(8 + 1 + 1 + 2)?; // user operation mask, random access flag, still mode and time
+    if multi_angle {
+        let angles = reader.u8()? as usize;
+        reader.skip(1)?; // flags
+        reader.skip(angles.saturating_sub(1) * 10)?; // clip name, codec identifier and STC id of each angle
+    }
+
+    let streams = parse_stream_table(reader.rest())?;
+    Ok((PlayItem { clip, in_time, out_time }, streams))
+}
+
+/// Parse the STN table listing the streams of a play item
+fn parse_stream_table(data: &[u8]) -> Result<MediaStreams> {
+    let mut reader = ByteReader::new(data);
+    let length = reader.u16()? as usize;
+    let mut reader = ByteReader::new(reader.bytes(length)?);
+    reader.skip(2)?; // reserved
+    let video_count = reader.u8()?;
+    let audio_count = reader.u8()?;
+    let subtitle_count = reader.u8()?;
+    reader.skip(1 + 1 + 1 + 1 + 5)?; // interactive graphics, secondary audio and video, PiP subtitles, reserved
+
+    let mut streams = MediaStreams::default();
+    for _ in 0..video_count {
+        let attributes = stream_attributes(&mut reader)?;
+        let mut attributes = ByteReader::new(attributes);
+        let coding_type = attributes.u8()?;
+        let format = attributes.u8()?;
+        let (width, height) = video_size(format >> 4);
+        streams.video.push(VideoCodec {
+            name: video_codec_name(coding_type).to_string(),
+            profile: None,
+            level: None,
+            width,
+            height,
+            frame_rate: frame_rate(format & 0x0F),
+            bit_depth: None,
+            color_space: None,
+        });
+    }
+    for _ in 0..audio_count {
+        let attributes = stream_attributes(&mut reader)?;
+        let mut attributes = ByteReader::new(attributes);
+        let coding_type = attributes.u8()?;
+        let format = attributes.u8()?;
+        streams.audio.push(AudioCodec {
+            name: audio_codec_name(coding_type).to_string(),
+            channels: channels(format >> 4),
+            sample_rate: sample_rate(format & 0x0F),
+            bit_depth: None,
+            bitrate: None,
+            language: attributes.bytes(3).ok().and_then(language),
+        });
+    }
+    for index in 0..subtitle_count {
+        let attributes = stream_attributes(&mut reader)?;
+        let mut attributes = ByteReader::new(attributes);
+        let coding_type = attributes.u8()?;
+        if coding_type == 0x92 {
+            attributes.skip(1)?; // character code of text subtitles
+        }
+        streams.subtitles.push(SubtitleTrack {
+            index: index as u32,
+            language: attributes.bytes(3).ok().and_then(language),
+            title: None,
+            codec: if coding_type == 0x92 { "hdmv_text_subtitle" } else { "pgssub" }.to_string(),
+            forced: false,
+            default: false,
+            path: None,
+        });
+    }
+
+    Ok(streams)
+}
+
+/// Skip a stream entry and return the attributes following it
+fn stream_attributes<'a>(reader: &mut ByteReader<'a>) -> Result<&'a [u8]> {
+    let entry_length = reader.u8()? as usize;
+    reader.skip(entry_length)?;
+    let attributes_length = reader.u8()? as usize;
+    reader.bytes(attributes_length)
+}
+
+fn language(code: &[u8]) -> Option<String> {
+    normalize_language(&String::from_utf8_lossy(code))
+}
+
+fn video_codec_name(coding_type: u8) -> &'static str {
+    match coding_type {
+        0x01 => "mpeg1video",
+        0x02 => "mpeg2video",
+        0x1B | 0x20 => "h264",
+        0x24 => "hevc",
+        0xEA => "vc1",
+        _ => "unknown",
+    }
+}
+
+fn audio_codec_name(coding_type: u8) -> &'static str {
+    match coding_type {
+        0x03 | 0x04 => "mp2",
+        0x80 => "pcm_bluray",
+        0x81 => "ac3",
+        0x82 | 0x85 | 0x86 | 0xA2 => "dts",
+        0x83 => "truehd",
+        0x84 | 0xA1 => "eac3",
+        _ => "unknown",
+    }
+}
+
+fn video_size(format: u8) -> (u32, u32) {
+    match format {
+        1 | 3 => (720, 480),
+        2 | 7 => (720, 576),
+        4 | 6 => (1920, 1080),
+        5 => (1280, 720),
+        8 => (3840, 2160),
+        _ => (0, 0),
+    }
+}
+
+fn frame_rate(rate: u8) -> Option<f64> {
+    match rate {
+        1 => Some(23.976),
+        2 => Some(24.0),
+        3 => Some(25.0),
+        4 => Some(29.97),
+        6 => Some(50.0),
+        7 => Some(59.94),
+        _ => None,
+    }
+}
+
+fn channels(format: u8) -> u8 {
+    match format {
+        1 => 1,
+        3 => 2,
+        6 | 12 => 6,
+        _ => 0,
+    }
+}
+
+fn sample_rate(rate: u8) -> u32 {
+    match rate {
+        1 => 48_000,
+        4 | 14 => 96_000,
+        5 | 12 => 192_000,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Build an MPLS file playing `items` as clip name, in and out times,
+    /// with an H.264 video, an English TrueHD and a French subtitle stream
+    pub(crate) fn mpls(items: &[(&str, u32, u32)], marks: &[(u16, u32)]) -> Vec<u8> {
+        let mut table = vec![0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        table.extend_from_slice(&[9, 1, 0x10, 0x11, 0, 0, 0, 0, 0, 0]); // video entry
+        table.extend_from_slice(&[5, 0x1B, 0x61, 0, 0, 0]); // H.264, 1080p at 23.976
+        table.extend_from_slice(&[9, 1, 0x11, 0x00, 0, 0, 0, 0, 0, 0]); // audio entry
+        table.extend_from_slice(&[5, 0x83, 0x61]);
+        table.extend_from_slice(b"eng");
+        table.extend_from_slice(&[9, 1, 0x12, 0x00, 0, 0, 0, 0, 0, 0]); // subtitle entry
+        table.extend_from_slice(&[5, 0x90]);
+        table.extend_from_slice(b"fre");
+        table.push(0);
+
+        let mut list = Vec::new();
+        for (clip, in_time, out_time) in items {
+            let mut item = Vec::new();
+            item.extend_from_slice(clip.as_bytes());
+            item.extend_from_slice(b"M2TS");
+            item.extend_from_slice(&[0, 1, 0]);
+            item.extend_from_slice(&in_time.to_be_bytes());
+            item.extend_from_slice(&out_time.to_be_bytes());
+            item.extend_from_slice(&[0; 12]);
+            item.extend_from_slice(&(table.len() as u16).to_be_bytes());
+            item.extend_from_slice(&table);
+            list.extend_from_slice(&(item.len() as u16).to_be_bytes());
+            list.extend_from_slice(&item);
+        }
+
+        let mut data = b"MPLS0200".to_vec();
+        let list_start = 20u32;
+        let marks_start = list_start + 10 + list.len() as u32;
+        data.extend_from_slice(&list_start.to_be_bytes());
+        data.extend_from_slice(&marks_start.to_be_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(6 + list.len() as u32).to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&(items.len() as u16).to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&list);
+
+        data.extend_from_slice(&(2 + marks.len() as u32 * 14).to_be_bytes());
+        data.extend_from_slice(&(marks.len() as u16).to_be_bytes());
+        for (item, time) in marks {
+            data.extend_from_slice(&[0, ENTRY_MARK]);
+            data.extend_from_slice(&item.to_be_bytes());
+            data.extend_from_slice(&time.to_be_bytes());
+            data.extend_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_playlist() {
+        let data = mpls(&[("00001", 45_000, 4_545_000), ("00002", 0, 2_700_000)], &[(0, 45_000), (0, 2_295_000), (1, 900_000)]);
+        let playlist = parse_playlist(&data).unwrap();
+
+        assert_eq!(playlist.items.len(), 2);
+        assert_eq!(playlist.items[1].clip, "00002");
+        assert_eq!(playlist.duration(), 160.0);
+        let starts = playlist.chapters().iter().map(|mark| mark.start).collect::<Vec<_>>();
+        assert_eq!(starts, vec![0.0, 50.0, 120.0]);
+
+        let video = &playlist.streams.video[0];
+        assert_eq!((video.name.as_str(), video.width, video.height, video.frame_rate), ("h264", 1920, 1080, Some(23.976)));
+        let audio = &playlist.streams.audio[0];
+        assert_eq!((audio.name.as_str(), audio.channels, audio.sample_rate), ("truehd", 6, 48_000));
+        assert_eq!(audio.language.as_deref(), Some("eng"));
+        assert_eq!(playlist.streams.subtitles[0].language.as_deref(), Some("fre"));
+        assert_eq!(playlist.streams.subtitles[0].codec, "pgssub");
+
+        assert!(parse_playlist(b"MOBJ0200").is_err());
+        assert!(parse_playlist(&data[..60]).is_err());
+    }
+}
@@ -0,0 +1,432 @@
+//! DVD IFO files
+//!
+//! `VIDEO_TS.IFO` lists the titles of the disc and the title set holding
+//! each. A title set's `VTS_nn_0.IFO` describes its streams, splits titles
+//! into chapters (parts of title) and gives the playback time of each
+//! program chain and cell. Times are BCD coded.
+
+use super::{DiscFiles, DiscTitle};
+use crate::probe::{finish_chapters, normalize_language, truncated, ByteReader, ChapterMark};
+use rustflix_core::media::{AudioCodec, MediaStreams, SubtitleTrack, VideoCodec};
+use rustflix_core::{Result, RustFlixError};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// IFO files address their tables in sectors
+const SECTOR: usize = 2048;
+
+/// Title listed by the video manager
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TitleEntry {
+    title_set: u8,
+    /// Title number within the title set, starting at 1
+    number: u8,
+}
+
+/// Program chain, the sequence of cells played for (part of) a title
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ProgramChain {
+    /// Seconds
+    pub duration: f64,
+    /// First cell of each program, starting at 1
+    pub programs: Vec<u8>,
+    /// Seconds of each cell
+    pub cells: Vec<f64>,
+}
+
+impl ProgramChain {
+    /// Seconds from the start of the chain to a program, starting at 1
+    fn program_start(&self, program: u16) -> Option<f64> {
+        let cell = *self.programs.get(program.checked_sub(1)? as usize)? as usize;
+        Some(self.cells.iter().take(cell.saturating_sub(1)).sum())
+    }
+}
+
+/// Title set parsed from a `VTS_nn_0.IFO` file
+#[derive(Debug, Default)]
+pub(crate) struct TitleSet {
+    pub streams: MediaStreams,
+    /// Chapters of each title, as program chain and program numbers
+    pub parts: Vec<Vec<(u16, u16)>>,
+    pub chains: Vec<ProgramChain>,
+}
+
+impl TitleSet {
+    /// Duration and chapter starts of a title, starting at 1
+    fn title(&self, number: u8) -> Option<(f64, Vec<ChapterMark>)> {
+        let parts = self.parts.get((number as usize).checked_sub(1)?)?;
+
+        // Offset of each program chain within the title, in order of play
+        let mut offsets = Vec::<(u16, f64)>::new();
+        let mut duration = 0.0;
+        for &(chain, _) in parts {
+            if offsets.iter().all(|(played, _)| *played != chain) {
+                offsets.push((chain, duration));
+                duration += self.chain(chain).map(|chain| chain.duration).unwrap_or(0.0);
+            }
+        }
+
+        let chapters = parts
+            .iter()
+            .filter_map(|&(chain, program)| {
+                let (_, offset) = offsets.iter().find(|(played, _)| *played == chain)?;
+                Some(ChapterMark {
+                    start: offset + self.chain(chain)?.program_start(program)?,
+                    end: None,
+                    title: None,
+                })
+            })
+            .collect();
+        Some((duration, chapters))
+    }
+
+    fn chain(&self, number: u16) -> Option<&ProgramChain> {
+        self.chains.get((number as usize).checked_sub(1)?)
+    }
+}
+
+/// Read every title of a DVD from its video manager, `VIDEO_TS.IFO`
+pub(crate) fn read_titles(files: &mut dyn DiscFiles, manager: &[u8]) -> Result<Vec<DiscTitle>> {
+    let entries = parse_manager(manager)?;
+    let vobs = files.list("VIDEO_TS")?;
+
+    let mut title_sets = HashMap::new();
+    let mut titles = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        if let Entry::Vacant(vacant) = title_sets.entry(entry.title_set) {
+            let name = format!("VIDEO_TS/VTS_{:02}_0.IFO", entry.title_set);
+            let title_set = match files.read(&name)?.map(|data| parse_title_set(&data)) {
+                Some(Ok(title_set)) => Some(title_set),
+                Some(Err(e)) => {
+                    warn!("Invalid DVD title set {}: {}", name, e);
+                    None
+                }
+                None => None,
+            };
+            vacant.insert(title_set);
+        }
+        let Some(title_set) = title_sets[&entry.title_set].as_ref() else {
+            continue;
+        };
+        let Some((duration, chapters)) = title_set.title(entry.number) else {
+            continue;
+        };
+
+        let prefix = format!("VTS_{:02}_", entry.title_set);
+        let mut files = vobs
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.to_ascii_uppercase();
+                name.len() == prefix.len() + 5
+                    && name.starts_with(&prefix)
+                    && name.ends_with(".VOB")
+                    && matches!(name.as_bytes()[prefix.len()], b'1'..=b'9')
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+
+        titles.push(DiscTitle {
+            name: (index + 1).to_string(),
+            duration,
+            streams: title_set.streams.clone(),
+            chapters: finish_chapters(chapters, Some(duration)),
+            files: files.iter().map(|(name, _)| format!("VIDEO_TS/{}", name)).collect(),
+            size: files.iter().map(|(_, size)| size).sum(),
+        });
+    }
+
+    Ok(titles)
+}
+
+/// Parse the title search pointer table of the video manager
+fn parse_manager(data: &[u8]) -> Result<Vec<TitleEntry>> {
+    if !data.starts_with(b"DVDVIDEO-VMG") {
+        return Err(RustFlixError::media_processing("Not a DVD video manager"));
+    }
+
+    let mut reader = table(data, 0xC4)?;
+    let count = reader.u16()?;
+    reader.skip(2 + 4)?; // reserved, end address
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        reader.skip(1 + 1 + 2 + 2)?; // playback type, angles, chapters, parental mask
+        let title_set = reader.u8()?;
+        let number = reader.u8()?;
+        reader.skip(4)?; // title set start sector
+        entries.push(TitleEntry { title_set, number });
+    }
+    Ok(entries)
+}
+
+/// Parse a title set IFO file
+pub(crate) fn parse_title_set(data: &[u8]) -> Result<TitleSet> {
+    if !data.starts_with(b"DVDVIDEO-VTS") {
+        return Err(RustFlixError::media_processing("Not a DVD title set"));
+    }
+
+    let mut title_set = TitleSet {
+        streams: parse_attributes(data.get(0x200..).ok_or_else(truncated)?)?,
+        ..TitleSet::default()
+    };
+
+    // Parts of title: offsets of each title's part list, relative to the table
+    let start = table_start(data, 0xC8)?;
+    let mut reader = table(data, 0xC8)?;
+    let count = reader.u16()? as usize;
+    reader.skip(2)?; // reserved
+    let end = start + reader.u32()? as usize + 1;
+    let mut offsets = Vec::with_capacity(count + 1);
+    for _ in 0..count {
+        offsets.push(start + reader.u32()? as usize);
+    }
+    offsets.push(end);
+    for window in offsets.windows(2) {
+        let mut reader = ByteReader::new(data.get(window[0]..window[1]).ok_or_else(truncated)?);
+        let mut parts = Vec::new();
+        while reader.remaining() >= 4 {
+            parts.push((reader.u16()?, reader.u16()?));
+        }
+        title_set.parts.push(parts);
+    }
+
+    // Program chains
+    let start = table_start(data, 0xCC)?;
+    let mut reader = table(data, 0xCC)?;
+    let count = reader.u16()?;
+    reader.skip(2 + 4)?; // reserved, end address
+    for _ in 0..count {
+        reader.skip(4)?; // category
+        let offset = start + reader.u32()? as usize;
+        title_set.chains.push(parse_program_chain(data.get(offset..).ok_or_else(truncated)?)?);
+    }
+
+    Ok(title_set)
+}
+
+fn parse_program_chain(data: &[u8]) -> Result<ProgramChain> {
+    let mut reader = ByteReader::new(data);
+    reader.skip(2)?;
+    let program_count = reader.u8()? as usize;
+    let cell_count = reader.u8()? as usize;
+    let duration = playback_time(reader.bytes(4)?);
+
+    let mut reader = ByteReader::new(data.get(0xE6..).ok_or_else(truncated)?);
+    let programs_offset = reader.u16()? as usize;
+    let cells_offset = reader.u16()? as usize;
+
+    let programs = if program_count > 0 {
+        ByteReader::new(data.get(programs_offset..).ok_or_else(truncated)?).bytes(program_count)?.to_vec()
+    } else {
+        Vec::new()
+    };
+    let mut cells = Vec::with_capacity(cell_count);
+    if cell_count > 0 {
+        let mut reader = ByteReader::new(data.get(cells_offset..).ok_or_else(truncated)?);
+        for _ in 0..cell_count {
+            let cell = reader.bytes(24)?;
+            cells.push(playback_time(&cell[4..8]));
+        }
+    }
+
+    Ok(ProgramChain { duration, programs, cells })
+}
+
+/// Parse the video, audio and subpicture attributes of a title set
+fn parse_attributes(data: &[u8]) -> Result<MediaStreams> {
+    let mut reader = ByteReader::new(data);
+    let video = reader.u16()?;
+    let pal = (video >> 12) & 0x03 == 1;
+    let height = if pal { 576 } else { 480 };
+    let (width, height) = match (video >> 3) & 0x07 {
+        1 => (704, height),
+        2 => (352, height),
+        3 => (352, height / 2),
+        _ => (720, height),
+    };
+
+    let mut streams = MediaStreams::default();
+    streams.video.push(VideoCodec {
+        name: if video >> 14 == 0 { "mpeg1video" } else { "mpeg2video" }.to_string(),
+        profile: None,
+        level: None,
+        width,
+        height,
+        frame_rate: Some(if pal { 25.0 } else { 29.97 }),
+        bit_depth: Some(8),
+        color_space: None,
+    });
+
+    let audio_count = reader.u16()?.min(8);
+    let audio = reader.bytes(64)?;
+    for attributes in audio.chunks(8).take(audio_count as usize) {
+        streams.audio.push(AudioCodec {
+            name: match attributes[0] >> 5 {
+                0 => "ac3",
+                2 | 3 => "mp2",
+                4 => "pcm_dvd",
+                6 => "dts",
+                _ => "unknown",
+            }
+            .to_string(),
+            channels: (attributes[1] & 0x07) + 1,
+            sample_rate: if (attributes[1] >> 4) & 0x03 == 1 { 96_000 } else { 48_000 },
+            bit_depth: None,
+            bitrate: None,
+            language: language(attributes[0] >> 2, &attributes[2..4]),
+        });
+    }
+
+    // Multichannel extension and reserved bytes up to 0x254
+    reader.skip(16)?;
+    let subpicture_count = reader.u16()?.min(32) as usize;
+    for index in 0..subpicture_count {
+        let attributes = reader.bytes(6)?;
+        streams.subtitles.push(SubtitleTrack {
+            index: index as u32,
+            language: language(attributes[0], &attributes[2..4]),
+            title: None,
+            codec: "dvd_subtitle".to_string(),
+            forced: false,
+            default: false,
+            path: None,
+        });
+    }
+
+    Ok(streams)
+}
+
+/// Language of a stream whose type bits say it has one
+fn language(language_type: u8, code: &[u8]) -> Option<String> {
+    if language_type & 0x03 != 1 {
+        return None;
+    }
+    normalize_language(&String::from_utf8_lossy(code).replace('\0', ""))
+}
+
+/// Seconds of a BCD coded playback time, hours, minutes, seconds and frames
+fn playback_time(data: &[u8]) -> f64 {
+    let bcd = |byte: u8| (byte >> 4) as f64 * 10.0 + (byte & 0x0F) as f64;
+    let fps = if data[3] >> 6 == 1 { 25.0 } else { 29.97 };
+    bcd(data[0]) * 3600.0 + bcd(data[1]) * 60.0 + bcd(data[2]) + bcd(data[3] & 0x3F) / fps
+}
+
+/// Offset of a table from the sector pointer at `pointer`
+fn table_start(data: &[u8], pointer: usize) -> Result<usize> {
+    let sector = ByteReader::new(data.get(pointer..).ok_or_else(truncated)?).u32()? as usize;
+    Ok(sector * SECTOR)
+}
+
+fn table(data: &[u8], pointer: usize) -> Result<ByteReader<'_>> {
+    Ok(ByteReader::new(data.get(table_start(data, pointer)?..).ok_or_else(truncated)?))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// BCD playback time at 25 fps
+    fn time(seconds: u32) -> [u8; 4] {
+        let bcd = |value: u32| (((value / 10) << 4) | (value % 10)) as u8;
+        [bcd(seconds / 3600), bcd(seconds / 60 % 60), bcd(seconds % 60), 0x40]
+    }
+
+    /// Build a `VIDEO_TS.IFO` listing `titles` of title set 1
+    pub(crate) fn manager(titles: u8) -> Vec<u8> {
+        let mut data = vec![0u8; SECTOR];
+        data[..12].copy_from_slice(b"DVDVIDEO-VMG");
+        data[0xC4..0xC8].copy_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&(titles as u16).to_be_bytes());
+        data.extend_from_slice(&[0; 6]);
+        for number in 1..=titles {
+            data.extend_from_slice(&[1, 1, 0, 1, 0, 0, 1, number, 0, 0, 0, 0]);
+        }
+        data
+    }
+
+    /// Build a `VTS_01_0.IFO` with one program chain per title, each
+    /// holding one program per cell of the given seconds
+    pub(crate) fn title_set(titles: &[&[u32]]) -> Vec<u8> {
+        let mut data = vec![0u8; SECTOR];
+        data[..12].copy_from_slice(b"DVDVIDEO-VTS");
+        data[0xC8..0xCC].copy_from_slice(&1u32.to_be_bytes());
+        data[0xCC..0xD0].copy_from_slice(&2u32.to_be_bytes());
+        // PAL MPEG-2 video, English AC-3 5.1 and a French subpicture
+        data[0x200..0x202].copy_from_slice(&0x5000u16.to_be_bytes());
+        data[0x202..0x204].copy_from_slice(&1u16.to_be_bytes());
+        data[0x204..0x208].copy_from_slice(&[0x04, 0x05, b'e', b'n']);
+        data[0x254..0x256].copy_from_slice(&1u16.to_be_bytes());
+        data[0x256..0x25A].copy_from_slice(&[0x01, 0x00, b'f', b'r']);
+
+        let mut parts = Vec::new();
+        let mut lists = Vec::new();
+        let header = 8 + 4 * titles.len();
+        for (index, cells) in titles.iter().enumerate() {
+            parts.extend_from_slice(&((header + lists.len()) as u32).to_be_bytes());
+            for program in 1..=cells.len() {
+                lists.extend_from_slice(&(index as u16 + 1).to_be_bytes());
+                lists.extend_from_slice(&(program as u16).to_be_bytes());
+            }
+        }
+        let mut table = (titles.len() as u16).to_be_bytes().to_vec();
+        table.extend_from_slice(&[0, 0]);
+        table.extend_from_slice(&((header + lists.len() - 1) as u32).to_be_bytes());
+        table.extend_from_slice(&parts);
+        table.extend_from_slice(&lists);
+        table.resize(SECTOR, 0);
+        data.extend_from_slice(&table);
+
+        let mut chains = Vec::new();
+        let mut entries = Vec::new();
+        let header = 8 + 8 * titles.len();
+        for cells in titles {
+            let mut chain = vec![0u8; 0xEC];
+            chain[2] = cells.len() as u8;
+            chain[3] = cells.len() as u8;
+            chain[4..8].copy_from_slice(&time(cells.iter().sum()));
+            chain[0xE6..0xE8].copy_from_slice(&0xECu16.to_be_bytes());
+            chain[0xE8..0xEA].copy_from_slice(&(0xEC + cells.len() as u16).to_be_bytes());
+            chain.extend((1..=cells.len()).map(|cell| cell as u8));
+            for seconds in cells.iter() {
+                let mut cell = [0u8; 24];
+                cell[4..8].copy_from_slice(&time(*seconds));
+                chain.extend_from_slice(&cell);
+            }
+            entries.extend_from_slice(&[0x81, 0, 0, 0]);
+            entries.extend_from_slice(&((header + chains.len()) as u32).to_be_bytes());
+            chains.extend_from_slice(&chain);
+        }
+        data.extend_from_slice(&(titles.len() as u16).to_be_bytes());
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&entries);
+        data.extend_from_slice(&chains);
+        data
+    }
+
+    #[test]
+    fn test_parse_title_set() {
+        let data = title_set(&[&[30], &[600, 1200, 1800]]);
+        let title_set = parse_title_set(&data).unwrap();
+
+        assert_eq!(title_set.parts.len(), 2);
+        assert_eq!(title_set.parts[1], vec![(2, 1), (2, 2), (2, 3)]);
+        let (duration, chapters) = title_set.title(2).unwrap();
+        assert_eq!(duration, 3600.0);
+        assert_eq!(chapters.iter().map(|mark| mark.start).collect::<Vec<_>>(), vec![0.0, 600.0, 1800.0]);
+        assert!(title_set.title(3).is_none());
+
+        let video = &title_set.streams.video[0];
+        assert_eq!((video.name.as_str(), video.width, video.height, video.frame_rate), ("mpeg2video", 720, 576, Some(25.0)));
+        let audio = &title_set.streams.audio[0];
+        assert_eq!((audio.name.as_str(), audio.channels, audio.language.as_deref()), ("ac3", 6, Some("en")));
+        assert_eq!(title_set.streams.subtitles[0].language.as_deref(), Some("fr"));
+
+        assert!(parse_title_set(&manager(1)).is_err());
+    }
+
+    #[test]
+    fn test_playback_time() {
+        assert!((playback_time(&[0x01, 0x30, 0x15, 0x40 | 0x12]) - 5415.48).abs() < 1e-9);
+        assert_eq!(playback_time(&[0x00, 0x00, 0x01, 0xC0 | 0x15]), 1.0 + 15.0 / 29.97);
+    }
+}
@@ -0,0 +1,415 @@
+//! Blu-ray and DVD disc structures
+//!
+//! A ripped disc is a folder holding a `BDMV` or `VIDEO_TS` structure, or an
+//! ISO image of one. Each disc is a single media item described by its main
+//! title: the longest Blu-ray playlist, or the longest DVD title found in
+//! the IFO files. Like `crate::probe`, the structures are parsed natively.
+//! Folders and images are read with blocking I/O.
+
+pub(crate) mod bdmv;
+pub(crate) mod dvd;
+pub(crate) mod udf;
+
+use crate::analyzer::{Chapter, MediaInfo};
+use crate::probe::summarize;
+use rustflix_core::media::MediaStreams;
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// Folders making up a disc structure, next to each other at the disc root
+const STRUCTURE_FOLDERS: &[&str] = &["BDMV", "CERTIFICATE", "VIDEO_TS", "AUDIO_TS"];
+
+/// Extensions of the small files describing the disc layout
+const NAVIGATION_EXTENSIONS: &[&str] = &["bdmv", "mpls", "clpi", "ifo"];
+
+/// Kind of disc structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscKind {
+    Bluray,
+    Dvd,
+}
+
+impl DiscKind {
+    pub fn format(self) -> MediaFormat {
+        match self {
+            DiscKind::Bluray => MediaFormat::Bluray,
+            DiscKind::Dvd => MediaFormat::Dvd,
+        }
+    }
+
+    /// File present at the root of every disc of this kind
+    fn index(self) -> &'static str {
+        match self {
+            DiscKind::Bluray => "BDMV/index.bdmv",
+            DiscKind::Dvd => "VIDEO_TS/VIDEO_TS.IFO",
+        }
+    }
+}
+
+/// Title of a disc, as listed by its playlists or IFO files
+#[derive(Debug, Clone, Default)]
+pub struct DiscTitle {
+    /// Playlist file name for Blu-ray discs, title number for DVDs
+    pub name: String,
+    /// Seconds
+    pub duration: f64,
+    pub streams: MediaStreams,
+    pub chapters: Vec<Chapter>,
+    /// Stream files played, relative to the disc root
+    pub files: Vec<String>,
+    /// Total size of the stream files
+    pub size: u64,
+}
+
+/// Blu-ray or DVD disc
+#[derive(Debug, Clone)]
+pub struct Disc {
+    pub kind: DiscKind,
+    pub titles: Vec<DiscTitle>,
+}
+
+impl Disc {
+    /// Title played by default: the longest one, the first listed on ties
+    pub fn main_title(&self) -> Option<&DiscTitle> {
+        self.titles.iter().fold(None, |main: Option<&DiscTitle>, title| match main {
+            Some(main) if main.duration >= title.duration => Some(main),
+            _ => Some(title),
+        })
+    }
+}
+
+/// Read access to the files of a disc, in a folder or an image
+pub(crate) trait DiscFiles {
+    /// Content of a file from its path relative to the disc root, `None`
+    /// when it does not exist
+    fn read(&mut self, path: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Names and sizes of the files in a folder of the disc, empty when the
+    /// folder does not exist
+    fn list(&mut self, folder: &str) -> Result<Vec<(String, u64)>>;
+}
+
+/// Disc files in a folder on disk
+struct FolderFiles<'a> {
+    root: &'a Path,
+}
+
+impl DiscFiles for FolderFiles<'_> {
+    fn read(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.root.join(path)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(RustFlixError::Io(e)),
+        }
+    }
+
+    fn list(&mut self, folder: &str) -> Result<Vec<(String, u64)>> {
+        let entries = match std::fs::read_dir(self.root.join(folder)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(RustFlixError::Io(e)),
+        };
+
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                files.push((entry.file_name().to_string_lossy().into_owned(), metadata.len()));
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Kind of the disc structure at the root of a folder, `None` when it
+/// holds none
+pub fn detect_folder(path: &Path) -> Option<DiscKind> {
+    [DiscKind::Bluray, DiscKind::Dvd]
+        .into_iter()
+        .find(|kind| path.join(kind.index()).is_file())
+}
+
+/// Check whether a folder belongs to the structure of a disc, whose files
+/// are not media items on their own
+pub fn is_structure_folder(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| STRUCTURE_FOLDERS.iter().any(|folder| folder.eq_ignore_ascii_case(name)))
+        && path.parent().is_some_and(|parent| detect_folder(parent).is_some())
+}
+
+/// Root of the disc a path inside a `BDMV`, `VIDEO_TS` or `AUDIO_TS`
+/// folder belongs to
+///
+/// Only the path is looked at, so this also works for removed files.
+pub fn disc_root(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .skip(1)
+        .find(|ancestor| {
+            ancestor.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+                ["BDMV", "VIDEO_TS", "AUDIO_TS"].iter().any(|folder| folder.eq_ignore_ascii_case(name))
+            })
+        })
+        .and_then(Path::parent)
+}
+
+/// Read the titles of a disc folder
+pub fn read_folder(root: &Path) -> Result<Disc> {
+    read(&mut FolderFiles { root })
+}
+
+/// Read the titles of a disc image read through `reader`
+pub fn read_image<R: Read + Seek>(reader: R) -> Result<Disc> {
+    read(&mut udf::UdfImage::open(reader)?)
+}
+
+fn read(files: &mut dyn DiscFiles) -> Result<Disc> {
+    if files.read(DiscKind::Bluray.index())?.is_some() {
+        Ok(Disc {
+            kind: DiscKind::Bluray,
+            titles: bdmv::read_titles(files)?,
+        })
+    } else if let Some(manager) = files.read(DiscKind::Dvd.index())? {
+        Ok(Disc {
+            kind: DiscKind::Dvd,
+            titles: dvd::read_titles(files, &manager)?,
+        })
+    } else {
+        Err(RustFlixError::media_processing("No Blu-ray or DVD structure found"))
+    }
+}
+
+/// Probe the main title of a disc folder
+pub fn probe_folder(root: &Path) -> Result<MediaInfo> {
+    main_title_info(&read_folder(root)?)
+}
+
+/// Probe the main title of a disc image read through `reader`
+pub fn probe_image<R: Read + Seek>(reader: R) -> Result<MediaInfo> {
+    main_title_info(&read_image(reader)?)
+}
+
+fn main_title_info(disc: &Disc) -> Result<MediaInfo> {
+    let title = disc
+        .main_title()
+        .ok_or_else(|| RustFlixError::media_processing("Disc has no playable title"))?;
+
+    let mut info = MediaInfo {
+        duration: Some(title.duration).filter(|duration| *duration > 0.0),
+        streams: title.streams.clone(),
+        chapters: Some(title.chapters.clone()),
+        ..MediaInfo::default()
+    };
+    summarize(&mut info, title.size);
+    Ok(info)
+}
+
+/// Total size and latest modification time of the files of a disc folder
+///
+/// Only the disc structure is counted, not extras stored next to it.
+pub fn folder_stats(root: &Path) -> io::Result<(u64, SystemTime)> {
+    let mut size = 0;
+    let mut modified = UNIX_EPOCH;
+    for (_, _, metadata) in structure_files(root)? {
+        size += metadata.len();
+        modified = modified.max(metadata.modified()?);
+    }
+    Ok((size, modified))
+}
+
+/// Fingerprint a disc folder from its layout and navigation files
+///
+/// Stream files are covered by their path and size only; the playlists,
+/// clip information and IFO files identify the disc.
+pub fn fingerprint_folder(root: &Path) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    for (relative, path, metadata) in structure_files(root)? {
+        hasher.update(relative.as_bytes());
+        hasher.update(&metadata.len().to_le_bytes());
+        if is_navigation_file(&path) {
+            hasher.update(&std::fs::read(&path)?);
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Hash the whole content of a disc folder
+pub fn full_hash_folder(root: &Path) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    for (relative, path, _) in structure_files(root)? {
+        hasher.update(relative.as_bytes());
+        io::copy(&mut File::open(&path)?, &mut hasher)?;
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Files of the disc structure below `root`, as relative path with `/`
+/// separators, path and metadata, in path order
+fn structure_files(root: &Path) -> io::Result<Vec<(String, PathBuf, std::fs::Metadata)>> {
+    let mut files = Vec::new();
+    for folder in STRUCTURE_FOLDERS {
+        let start = root.join(folder);
+        if !start.is_dir() {
+            continue;
+        }
+        for entry in WalkDir::new(&start).follow_links(false) {
+            let entry = entry.map_err(io::Error::from)?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or(entry.path())
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((relative, entry.path().to_path_buf(), entry.metadata().map_err(io::Error::from)?));
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+fn is_navigation_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| NAVIGATION_EXTENSIONS.iter().any(|nav| nav.eq_ignore_ascii_case(ext)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    /// Files of a Blu-ray with a short menu playlist and a two clip feature
+    fn bluray_files() -> Vec<(&'static str, Vec<u8>)> {
+        vec![
+            ("BDMV/index.bdmv", b"INDX0200".to_vec()),
+            ("BDMV/PLAYLIST/00000.mpls", bdmv::tests::mpls(&[("00000", 0, 450_000)], &[])),
+            (
+                "BDMV/PLAYLIST/00001.mpls",
+                bdmv::tests::mpls(&[("00001", 0, 270_000_000), ("00002", 0, 54_000_000)], &[(0, 0), (0, 135_000_000), (1, 0)]),
+            ),
+            ("BDMV/PLAYLIST/00002.mpls", b"damaged".to_vec()),
+            ("BDMV/STREAM/00000.m2ts", vec![0; 100]),
+            ("BDMV/STREAM/00001.m2ts", vec![0; 6000]),
+            ("BDMV/STREAM/00002.m2ts", vec![0; 1200]),
+        ]
+    }
+
+    fn write_files(root: &Path, files: &[(&str, Vec<u8>)]) {
+        for (path, data) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_bluray_folder() {
+        let dir = TempDir::new().unwrap();
+        write_files(dir.path(), &bluray_files());
+
+        assert_eq!(detect_folder(dir.path()), Some(DiscKind::Bluray));
+        let disc = read_folder(dir.path()).unwrap();
+        assert_eq!(disc.titles.len(), 2);
+
+        let main = disc.main_title().unwrap();
+        assert_eq!(main.name, "00001.mpls");
+        assert_eq!(main.duration, 7200.0);
+        assert_eq!(main.files, vec!["BDMV/STREAM/00001.m2ts", "BDMV/STREAM/00002.m2ts"]);
+        assert_eq!(main.size, 7200);
+        let starts = main.chapters.iter().map(|chapter| chapter.start_time).collect::<Vec<_>>();
+        assert_eq!(starts, vec![0.0, 3000.0, 6000.0]);
+
+        let info = probe_folder(dir.path()).unwrap();
+        assert_eq!(info.duration, Some(7200.0));
+        assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+        assert_eq!(info.video_codec.as_deref(), Some("h264"));
+        assert_eq!(info.audio_codec.as_deref(), Some("truehd"));
+        assert_eq!(info.bitrate, Some(8));
+        assert_eq!(info.chapters.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_read_dvd_folder() {
+        let dir = TempDir::new().unwrap();
+        write_files(
+            dir.path(),
+            &[
+                ("VIDEO_TS/VIDEO_TS.IFO", dvd::tests::manager(2)),
+                ("VIDEO_TS/VTS_01_0.IFO", dvd::tests::title_set(&[&[45], &[1200, 1800, 600]])),
+                ("VIDEO_TS/VTS_01_0.VOB", vec![0; 10]),
+                ("VIDEO_TS/VTS_01_1.VOB", vec![0; 300]),
+                ("VIDEO_TS/VTS_01_2.VOB", vec![0; 200]),
+            ],
+        );
+
+        assert_eq!(detect_folder(dir.path()), Some(DiscKind::Dvd));
+        let disc = read_folder(dir.path()).unwrap();
+        assert_eq!(disc.kind.format(), MediaFormat::Dvd);
+        let main = disc.main_title().unwrap();
+        assert_eq!(main.name, "2");
+        assert_eq!(main.duration, 3600.0);
+        assert_eq!(main.files, vec!["VIDEO_TS/VTS_01_1.VOB", "VIDEO_TS/VTS_01_2.VOB"]);
+        assert_eq!(main.size, 500);
+        assert_eq!(main.chapters.len(), 3);
+        assert_eq!(main.chapters[2].start_time, 3000.0);
+        assert_eq!(main.streams.video[0].height, 576);
+    }
+
+    #[test]
+    fn test_read_image() {
+        let files = bluray_files();
+        let image = udf::tests::image(&files.iter().map(|(path, data)| (*path, data.as_slice())).collect::<Vec<_>>());
+
+        let disc = read_image(Cursor::new(&image)).unwrap();
+        assert_eq!(disc.kind, DiscKind::Bluray);
+        assert_eq!(disc.main_title().unwrap().name, "00001.mpls");
+        assert_eq!(disc.main_title().unwrap().size, 7200);
+        assert_eq!(probe_image(Cursor::new(&image)).unwrap().duration, Some(7200.0));
+
+        let empty = udf::tests::image(&[("README.txt", b"")]);
+        assert!(read_image(Cursor::new(empty)).is_err());
+    }
+
+    #[test]
+    fn test_disc_root() {
+        assert_eq!(disc_root(Path::new("/movies/Heat/BDMV/STREAM/00001.m2ts")), Some(Path::new("/movies/Heat")));
+        assert_eq!(disc_root(Path::new("/movies/Alien/video_ts/VTS_01_1.VOB")), Some(Path::new("/movies/Alien")));
+        assert_eq!(disc_root(Path::new("/movies/Heat/Heat.mkv")), None);
+        assert_eq!(disc_root(Path::new("/movies/BDMV")), None);
+    }
+
+    #[test]
+    fn test_folder_hashes() {
+        let dir = TempDir::new().unwrap();
+        write_files(dir.path(), &bluray_files());
+        std::fs::write(dir.path().join("poster.jpg"), b"poster").unwrap();
+
+        assert!(is_structure_folder(&dir.path().join("BDMV")));
+        assert!(!is_structure_folder(&dir.path().join("extras")));
+        let (size, _) = folder_stats(dir.path()).unwrap();
+        assert_eq!(size, bluray_files().iter().map(|(_, data)| data.len() as u64).sum::<u64>());
+
+        let fingerprint = fingerprint_folder(dir.path()).unwrap();
+        let full = full_hash_folder(dir.path()).unwrap();
+        std::fs::write(dir.path().join("poster.jpg"), b"other poster").unwrap();
+        assert_eq!(fingerprint_folder(dir.path()).unwrap(), fingerprint);
+
+        std::fs::write(dir.path().join("BDMV/STREAM/00002.m2ts"), vec![1; 1200]).unwrap();
+        assert_eq!(fingerprint_folder(dir.path()).unwrap(), fingerprint);
+        assert_ne!(full_hash_folder(dir.path()).unwrap(), full);
+
+        std::fs::write(dir.path().join("BDMV/PLAYLIST/00000.mpls"), b"changed").unwrap();
+        assert_ne!(fingerprint_folder(dir.path()).unwrap(), fingerprint);
+    }
+}
@@ -0,0 +1,460 @@
+//! UDF file system of disc images
+//!
+//! Reads just enough of UDF (ECMA-167) to find files in DVD and Blu-ray ISO
+//! images: the volume descriptors, the file set, file entries and
+//! directories. Blu-ray images use UDF 2.50, whose file entries and
+//! directories are kept in a metadata partition. Unlike the containers in
+//! `crate::probe`, UDF structures are little-endian.
+
+use super::DiscFiles;
+use crate::probe::{le16, le32, le64, truncated, MAX_HEADER_SIZE};
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Size of sectors and logical blocks
+const SECTOR: u64 = 2048;
+
+/// Sector of the anchor volume descriptor pointer
+const ANCHOR_SECTOR: u64 = 256;
+
+// Descriptor tag identifiers
+const TAG_ANCHOR: u16 = 2;
+const TAG_PARTITION: u16 = 5;
+const TAG_LOGICAL_VOLUME: u16 = 6;
+const TAG_TERMINATING: u16 = 8;
+const TAG_FILE_SET: u16 = 256;
+const TAG_FILE_IDENTIFIER: u16 = 257;
+const TAG_FILE_ENTRY: u16 = 261;
+const TAG_EXTENDED_FILE_ENTRY: u16 = 266;
+
+/// Identifier of UDF 2.50 metadata partition maps
+const METADATA_PARTITION: &[u8] = b"*UDF Metadata Partition";
+
+/// Byte range of the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Extent {
+    position: u64,
+    length: u64,
+}
+
+/// Logical blocks addressed through a partition map
+#[derive(Debug, Clone, PartialEq)]
+enum Partition {
+    /// Blocks of the physical partition
+    Physical,
+    /// Blocks of the metadata file, itself stored in the physical partition
+    Metadata(Vec<Extent>),
+}
+
+/// Location of a descriptor, as partition reference and logical block
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Location {
+    partition: u16,
+    block: u32,
+}
+
+/// File or directory read from its file entry
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    size: u64,
+    directory: bool,
+    data: EntryData,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum EntryData {
+    Extents(Vec<Extent>),
+    /// Small files stored in the file entry itself
+    Embedded(Vec<u8>),
+}
+
+/// Files of a UDF disc image read through `reader`
+#[derive(Debug)]
+pub(crate) struct UdfImage<R> {
+    reader: R,
+    /// First sector of the physical partition
+    partition_start: u64,
+    partitions: Vec<Partition>,
+    root: Location,
+}
+
+impl<R: Read + Seek> UdfImage<R> {
+    /// Read the volume structure of an image
+    pub fn open(reader: R) -> Result<Self> {
+        let mut image = Self {
+            reader,
+            partition_start: 0,
+            partitions: Vec::new(),
+            root: Location { partition: 0, block: 0 },
+        };
+
+        let anchor = image.read_sector(ANCHOR_SECTOR)?;
+        if tag(&anchor)? != TAG_ANCHOR {
+            return Err(RustFlixError::media_processing("Not a UDF disc image"));
+        }
+        let sequence_length = le32(&anchor, 16)? as u64;
+        let sequence_start = le32(&anchor, 20)? as u64;
+
+        let mut partition_start = None;
+        let mut logical_volume = None;
+        for sector in sequence_start..sequence_start + sequence_length / SECTOR {
+            let descriptor = image.read_sector(sector)?;
+            match tag(&descriptor)? {
+                TAG_PARTITION => partition_start = Some(le32(&descriptor, 188)? as u64),
+                TAG_LOGICAL_VOLUME => logical_volume = Some(descriptor),
+                TAG_TERMINATING => break,
+                _ => {}
+            }
+        }
+        let (Some(partition_start), Some(volume)) = (partition_start, logical_volume) else {
+            return Err(RustFlixError::media_processing("UDF image has no partition"));
+        };
+        image.partition_start = partition_start;
+
+        let block_size = le32(&volume, 212)? as u64;
+        if block_size != SECTOR {
+            return Err(RustFlixError::media_processing(format!("Unsupported UDF block size {}", block_size)));
+        }
+
+        // Partition maps: sparable and virtual maps are read as physical
+        let mut metadata_files = Vec::new();
+        let mut offset = 440;
+        for index in 0..le32(&volume, 268)? {
+            let map_type = *volume.get(offset).ok_or_else(truncated)?;
+            let length = *volume.get(offset + 1).ok_or_else(truncated)? as usize;
+            let identifier = volume.get(offset + 5..offset + 5 + METADATA_PARTITION.len());
+            if map_type == 2 && identifier == Some(METADATA_PARTITION) {
+                metadata_files.push((index as usize, le32(&volume, offset + 40)?));
+            }
+            image.partitions.push(Partition::Physical);
+            offset += length.max(2);
+        }
+        for (index, block) in metadata_files {
+            let entry = image.read_entry(Location { partition: u16::MAX, block })?;
+            if let EntryData::Extents(extents) = entry.data {
+                image.partitions[index] = Partition::Metadata(extents);
+            }
+        }
+
+        let file_set = long_ad(&volume, 248)?;
+        let descriptor = image.read_block(file_set)?;
+        if tag(&descriptor)? != TAG_FILE_SET {
+            return Err(RustFlixError::media_processing("UDF image has no file set"));
+        }
+        image.root = long_ad(&descriptor, 400)?;
+        Ok(image)
+    }
+
+    /// Find a file from its path, comparing names case-insensitively
+    fn find(&mut self, path: &str) -> Result<Option<Entry>> {
+        let mut entry = self.read_entry(self.root)?;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if !entry.directory {
+                return Ok(None);
+            }
+            let children = self.directory(&entry)?;
+            let Some((_, location)) = children.into_iter().find(|(child, _)| child.eq_ignore_ascii_case(name)) else {
+                return Ok(None);
+            };
+            entry = self.read_entry(location)?;
+        }
+        Ok(Some(entry))
+    }
+
+    /// Names and file entry locations of the files in a directory
+    fn directory(&mut self, entry: &Entry) -> Result<Vec<(String, Location)>> {
+        let data = self.read_data(entry)?;
+        let mut children = Vec::new();
+        let mut offset = 0;
+        while offset + 38 <= data.len() {
+            if tag(&data[offset..])? != TAG_FILE_IDENTIFIER {
+                break;
+            }
+            let characteristics = data[offset + 18];
+            let name_length = data[offset + 19] as usize;
+            let location = long_ad(&data, offset + 20)?;
+            let name_start = offset + 38 + le16(&data, offset + 36)? as usize;
+            let name = data.get(name_start..name_start + name_length).ok_or_else(truncated)?;
+            // Skip deleted entries and the parent directory
+            if characteristics & 0x0C == 0 {
+                children.push((decode_name(name), location));
+            }
+            offset = (name_start + name_length + 3) & !3;
+        }
+        Ok(children)
+    }
+
+    /// Read a file entry; partition `u16::MAX` is the physical partition
+    fn read_entry(&mut self, location: Location) -> Result<Entry> {
+        let data = self.read_block(location)?;
+        let (ad_start, extended_length_offset) = match tag(&data)? {
+            TAG_FILE_ENTRY => (176, 168),
+            TAG_EXTENDED_FILE_ENTRY => (216, 208),
+            _ => return Err(RustFlixError::media_processing("Invalid UDF file entry")),
+        };
+        let directory = data[27] == 4;
+        let size = le64(&data, 56)?;
+        let ad_start = ad_start + le32(&data, extended_length_offset)? as usize;
+        let ad_length = le32(&data, extended_length_offset + 4)? as usize;
+        let descriptors = data.get(ad_start..ad_start + ad_length).ok_or_else(truncated)?;
+
+        let data = match le16(&data, 34)? & 0x07 {
+            0 => EntryData::Extents(
+                descriptors
+                    .chunks_exact(8)
+                    .filter(|ad| ad[3] >> 6 == 0)
+                    .map(|ad| self.extent(location.partition, le32(ad, 4)?, le32(ad, 0)?))
+                    .collect::<Result<_>>()?,
+            ),
+            1 => EntryData::Extents(
+                descriptors
+                    .chunks_exact(16)
+                    .filter(|ad| ad[3] >> 6 == 0)
+                    .map(|ad| self.extent(le16(ad, 8)?, le32(ad, 4)?, le32(ad, 0)?))
+                    .collect::<Result<_>>()?,
+            ),
+            3 => EntryData::Embedded(descriptors.to_vec()),
+            _ => return Err(RustFlixError::media_processing("Unsupported UDF allocation descriptors")),
+        };
+        Ok(Entry { size, directory, data })
+    }
+
+    /// Content of a file, up to `MAX_HEADER_SIZE` bytes
+    fn read_data(&mut self, entry: &Entry) -> Result<Vec<u8>> {
+        let size = entry.size.min(MAX_HEADER_SIZE) as usize;
+        let extents = match &entry.data {
+            EntryData::Embedded(data) => return Ok(data[..size.min(data.len())].to_vec()),
+            EntryData::Extents(extents) => extents,
+        };
+
+        let mut data = Vec::with_capacity(size);
+        for extent in extents {
+            let length = (extent.length as usize).min(size - data.len());
+            self.reader.seek(SeekFrom::Start(extent.position))?;
+            (&mut self.reader).take(length as u64).read_to_end(&mut data)?;
+            if data.len() >= size {
+                break;
+            }
+        }
+        data.resize(size, 0);
+        Ok(data)
+    }
+
+    /// Extent of `length` bytes (the low 30 bits) from a logical block
+    fn extent(&self, partition: u16, block: u32, length: u32) -> Result<Extent> {
+        Ok(Extent {
+            position: self.position(Location { partition, block })?,
+            length: (length & 0x3FFF_FFFF) as u64,
+        })
+    }
+
+    /// Image offset of a logical block
+    fn position(&self, location: Location) -> Result<u64> {
+        let offset = location.block as u64 * SECTOR;
+        match self.partitions.get(location.partition as usize) {
+            Some(Partition::Metadata(extents)) => {
+                let mut start = 0;
+                for extent in extents {
+                    if offset < start + extent.length {
+                        return Ok(extent.position + offset - start);
+                    }
+                    start += extent.length;
+                }
+                Err(RustFlixError::media_processing("UDF block outside of the metadata partition"))
+            }
+            Some(Partition::Physical) | None => Ok(self.partition_start * SECTOR + offset),
+        }
+    }
+
+    fn read_block(&mut self, location: Location) -> Result<Vec<u8>> {
+        let position = self.position(location)?;
+        self.read_at(position)
+    }
+
+    fn read_sector(&mut self, sector: u64) -> Result<Vec<u8>> {
+        self.read_at(sector * SECTOR)
+    }
+
+    fn read_at(&mut self, position: u64) -> Result<Vec<u8>> {
+        let mut data = vec![0u8; SECTOR as usize];
+        self.reader.seek(SeekFrom::Start(position))?;
+        self.reader.read_exact(&mut data).map_err(|_| truncated())?;
+        Ok(data)
+    }
+}
+
+impl<R: Read + Seek> DiscFiles for UdfImage<R> {
+    fn read(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        match self.find(path)? {
+            Some(entry) if !entry.directory => Ok(Some(self.read_data(&entry)?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn list(&mut self, folder: &str) -> Result<Vec<(String, u64)>> {
+        let Some(entry) = self.find(folder)?.filter(|entry| entry.directory) else {
+            return Ok(Vec::new());
+        };
+
+        let mut files = Vec::new();
+        for (name, location) in self.directory(&entry)? {
+            let entry = self.read_entry(location)?;
+            if !entry.directory {
+                files.push((name, entry.size));
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Identifier of a descriptor tag
+fn tag(data: &[u8]) -> Result<u16> {
+    le16(data, 0)
+}
+
+/// Location from a long allocation descriptor
+fn long_ad(data: &[u8], offset: usize) -> Result<Location> {
+    Ok(Location {
+        block: le32(data, offset + 4)?,
+        partition: le16(data, offset + 8)?,
+    })
+}
+
+/// Decode an OSTA compressed unicode name
+fn decode_name(data: &[u8]) -> String {
+    match data.split_first() {
+        Some((16, name)) => String::from_utf16_lossy(
+            &name.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect::<Vec<_>>(),
+        ),
+        Some((_, name)) => name.iter().map(|byte| *byte as char).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::io::Cursor;
+
+    /// First sector of the partition of built images
+    const PARTITION_START: usize = 300;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Build a UDF image holding `files`, with one block per file entry and
+    /// directory
+    pub(crate) fn image(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut directories = BTreeSet::from([String::new()]);
+        for (path, _) in files {
+            let mut parent = String::new();
+            for name in path.split('/').rev().skip(1).collect::<Vec<_>>().into_iter().rev() {
+                parent = if parent.is_empty() { name.to_string() } else { format!("{}/{}", parent, name) };
+                directories.insert(parent.clone());
+            }
+        }
+        // Block 0 is the file set, then one file entry per node
+        let nodes = directories
+            .iter()
+            .map(|dir| (dir.clone(), None))
+            .chain(files.iter().map(|(path, data)| (path.to_string(), Some(*data))))
+            .collect::<Vec<_>>();
+        let entry_block = |path: &str| nodes.iter().position(|(node, _)| node == path).unwrap() as u32 + 1;
+        let parent_of = |path: &str| path.rsplit_once('/').map(|(parent, _)| parent.to_string()).unwrap_or_default();
+
+        // Content of each node, directories listing their children
+        let mut contents = Vec::new();
+        for (path, data) in &nodes {
+            let content = match data {
+                Some(data) => data.to_vec(),
+                None => {
+                    let mut content = Vec::new();
+                    for (child, _) in nodes.iter().filter(|(child, _)| !child.is_empty() && parent_of(child) == *path) {
+                        let name = child.rsplit('/').next().unwrap();
+                        let mut fid = vec![0u8; 38];
+                        put(&mut fid, 0, &TAG_FILE_IDENTIFIER.to_le_bytes());
+                        fid[18] = if directories.contains(child) { 0x02 } else { 0 };
+                        fid[19] = name.len() as u8 + 1;
+                        put(&mut fid, 20, &(SECTOR as u32).to_le_bytes());
+                        put(&mut fid, 24, &entry_block(child).to_le_bytes());
+                        fid.push(8);
+                        fid.extend_from_slice(name.as_bytes());
+                        fid.resize((fid.len() + 3) & !3, 0);
+                        content.extend_from_slice(&fid);
+                    }
+                    content
+                }
+            };
+            contents.push(content);
+        }
+
+        let mut block = nodes.len() + 1;
+        let data_blocks = contents
+            .iter()
+            .map(|content| {
+                let start = block;
+                block += content.len().div_ceil(SECTOR as usize).max(1);
+                start
+            })
+            .collect::<Vec<_>>();
+
+        let sector = SECTOR as usize;
+        let mut image = vec![0u8; (PARTITION_START + block) * sector];
+        put(&mut image, 256 * sector, &TAG_ANCHOR.to_le_bytes());
+        put(&mut image, 256 * sector + 16, &(3 * SECTOR as u32).to_le_bytes());
+        put(&mut image, 256 * sector + 20, &257u32.to_le_bytes());
+        put(&mut image, 257 * sector, &TAG_PARTITION.to_le_bytes());
+        put(&mut image, 257 * sector + 188, &(PARTITION_START as u32).to_le_bytes());
+        put(&mut image, 258 * sector, &TAG_LOGICAL_VOLUME.to_le_bytes());
+        put(&mut image, 258 * sector + 212, &(SECTOR as u32).to_le_bytes());
+        put(&mut image, 258 * sector + 248, &(SECTOR as u32).to_le_bytes());
+        put(&mut image, 258 * sector + 268, &1u32.to_le_bytes());
+        put(&mut image, 258 * sector + 440, &[1, 6, 1, 0, 0, 0]);
+        put(&mut image, 259 * sector, &TAG_TERMINATING.to_le_bytes());
+
+        let partition = PARTITION_START * sector;
+        put(&mut image, partition, &TAG_FILE_SET.to_le_bytes());
+        put(&mut image, partition + 400, &(SECTOR as u32).to_le_bytes());
+        put(&mut image, partition + 404, &1u32.to_le_bytes());
+        for (index, ((path, _), content)) in nodes.iter().zip(&contents).enumerate() {
+            let entry = partition + (index + 1) * sector;
+            put(&mut image, entry, &TAG_FILE_ENTRY.to_le_bytes());
+            image[entry + 27] = if directories.contains(path) { 4 } else { 5 };
+            put(&mut image, entry + 56, &(content.len() as u64).to_le_bytes());
+            put(&mut image, entry + 172, &8u32.to_le_bytes());
+            put(&mut image, entry + 176, &(content.len() as u32).to_le_bytes());
+            put(&mut image, entry + 180, &(data_blocks[index] as u32).to_le_bytes());
+            put(&mut image, partition + data_blocks[index] * sector, content);
+        }
+        image
+    }
+
+    #[test]
+    fn test_udf_image() {
+        let large = (0..5000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let data = image(&[("BDMV/index.bdmv", b"INDX0200"), ("BDMV/STREAM/00001.m2ts", &large), ("README.txt", b"")]);
+        let mut udf = UdfImage::open(Cursor::new(data)).unwrap();
+
+        assert_eq!(udf.read("BDMV/index.bdmv").unwrap().as_deref(), Some(&b"INDX0200"[..]));
+        assert_eq!(udf.read("bdmv/INDEX.BDMV").unwrap().as_deref(), Some(&b"INDX0200"[..]));
+        assert_eq!(udf.read("BDMV/STREAM/00001.m2ts").unwrap(), Some(large));
+        assert_eq!(udf.read("README.txt").unwrap(), Some(Vec::new()));
+        assert!(udf.read("BDMV/missing").unwrap().is_none());
+        assert!(udf.read("BDMV").unwrap().is_none());
+
+        assert_eq!(udf.list("BDMV/STREAM").unwrap(), vec![("00001.m2ts".to_string(), 5000)]);
+        assert_eq!(udf.list("BDMV").unwrap(), vec![("index.bdmv".to_string(), 8)]);
+        assert!(udf.list("VIDEO_TS").unwrap().is_empty());
+
+        assert!(UdfImage::open(Cursor::new(vec![0u8; 300 * SECTOR as usize])).is_err());
+    }
+
+    #[test]
+    fn test_decode_name() {
+        assert_eq!(decode_name(b"\x08VIDEO_TS"), "VIDEO_TS");
+        assert_eq!(decode_name(b"\x10\x00B\x00D"), "BD");
+        assert_eq!(decode_name(b""), "");
+    }
+}
@@ -0,0 +1,156 @@
+//! Extras detection
+//!
+//! Trailers, featurettes and similar videos are recognized by the folder
+//! holding them (`Extras/`, `Trailers/`, `Behind The Scenes/`) or by a name
+//! suffix (`Movie (2001)-trailer.mkv`). Extras in a folder belong to the
+//! movie or show folder above it; suffixed extras belong to their own folder.
+
+use rustflix_core::{ExtraType, MediaFormat};
+use std::path::{Path, PathBuf};
+
+/// Folder names holding extras, normalized to lowercase words
+const EXTRA_FOLDERS: &[(&str, ExtraType)] = &[
+    ("extras", ExtraType::Other),
+    ("extra", ExtraType::Other),
+    ("other", ExtraType::Other),
+    ("trailers", ExtraType::Trailer),
+    ("featurettes", ExtraType::Featurette),
+    ("behind the scenes", ExtraType::BehindTheScenes),
+    ("deleted scenes", ExtraType::DeletedScene),
+    ("interviews", ExtraType::Interview),
+    ("scenes", ExtraType::Scene),
+    ("shorts", ExtraType::Short),
+];
+
+/// File name suffixes marking extras, as in `movie-trailer.mkv`
+const EXTRA_SUFFIXES: &[(&str, ExtraType)] = &[
+    ("trailer", ExtraType::Trailer),
+    ("featurette", ExtraType::Featurette),
+    ("behindthescenes", ExtraType::BehindTheScenes),
+    ("deleted", ExtraType::DeletedScene),
+    ("deletedscene", ExtraType::DeletedScene),
+    ("interview", ExtraType::Interview),
+    ("scene", ExtraType::Scene),
+    ("short", ExtraType::Short),
+    ("other", ExtraType::Other),
+    ("extra", ExtraType::Other),
+];
+
+/// Extra video and the folder of the title it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extra {
+    pub extra_type: ExtraType,
+    pub owner: PathBuf,
+}
+
+/// Classify a video file as an extra
+pub fn detect(path: &Path) -> Option<Extra> {
+    let extension = path.extension()?.to_str()?;
+    if !MediaFormat::from_extension(&extension.to_lowercase()).is_video() {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let parent = path.parent()?;
+    let suffix = suffix_type(stem);
+
+    if let Some(folder) = parent.file_name().and_then(|name| name.to_str()).and_then(folder_type) {
+        if let Some(owner) = parent.parent() {
+            return Some(Extra {
+                extra_type: suffix.unwrap_or(folder),
+                owner: owner.to_path_buf(),
+            });
+        }
+    }
+
+    suffix.map(|extra_type| Extra {
+        extra_type,
+        owner: parent.to_path_buf(),
+    })
+}
+
+/// Check whether a video file is an extra
+pub fn is_extra(path: &Path) -> bool {
+    detect(path).is_some()
+}
+
+fn folder_type(name: &str) -> Option<ExtraType> {
+    let name = normalize(name);
+    EXTRA_FOLDERS
+        .iter()
+        .find(|(folder, _)| *folder == name)
+        .map(|(_, extra_type)| *extra_type)
+}
+
+/// Type named by the last dash-separated word of a file stem, or by the
+/// whole stem as in `trailer.mkv`
+fn suffix_type(stem: &str) -> Option<ExtraType> {
+    let suffix = stem.rsplit('-').next()?;
+    let suffix = normalize(suffix).replace(' ', "");
+    EXTRA_SUFFIXES
+        .iter()
+        .find(|(name, _)| *name == suffix)
+        .map(|(_, extra_type)| *extra_type)
+}
+
+/// Lowercase words separated by single spaces
+fn normalize(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || matches!(c, '.' | '_' | '-'))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extra(path: &str) -> Option<(ExtraType, String)> {
+        detect(Path::new(path)).map(|extra| (extra.extra_type, extra.owner.to_string_lossy().into_owned()))
+    }
+
+    #[test]
+    fn test_extra_folders() {
+        let cases = [
+            ("/movies/Movie (2001)/Extras/Making of.mkv", Some((ExtraType::Other, "/movies/Movie (2001)"))),
+            ("/movies/Movie (2001)/Trailers/Teaser.mp4", Some((ExtraType::Trailer, "/movies/Movie (2001)"))),
+            ("/movies/Movie (2001)/behind_the_scenes/Day 1.mkv", Some((ExtraType::BehindTheScenes, "/movies/Movie (2001)"))),
+            ("/tv/Show/Featurettes/Cast.mkv", Some((ExtraType::Featurette, "/tv/Show"))),
+            ("/tv/Show/Season 1/Deleted Scenes/Cut.mkv", Some((ExtraType::DeletedScene, "/tv/Show/Season 1"))),
+            // A suffix refines the type given by an extras folder
+            ("/movies/Movie (2001)/Extras/Teaser-trailer.mkv", Some((ExtraType::Trailer, "/movies/Movie (2001)"))),
+            ("/movies/Movie (2001)/Extras/poster.jpg", None),
+        ];
+
+        for (path, expected) in cases {
+            let expected = expected.map(|(extra_type, owner)| (extra_type, owner.to_string()));
+            assert_eq!(extra(path), expected, "extra type of {}", path);
+        }
+    }
+
+    #[test]
+    fn test_extra_suffixes() {
+        let cases = [
+            ("/movies/Movie (2001)/Movie (2001)-trailer.mkv", Some(ExtraType::Trailer)),
+            ("/movies/Movie (2001)/Movie (2001) - Behind The Scenes.mkv", Some(ExtraType::BehindTheScenes)),
+            ("/movies/Movie (2001)/Movie-deleted.mkv", Some(ExtraType::DeletedScene)),
+            ("/movies/Movie (2001)/trailer.mp4", Some(ExtraType::Trailer)),
+            ("/movies/Movie (2001)/Movie (2001).mkv", None),
+            ("/movies/The Other Guys (2010)/The Other Guys (2010).mkv", None),
+            ("/movies/Trailer Park Boys (1999)/Trailer Park Boys (1999).mkv", None),
+        ];
+
+        for (path, expected) in cases {
+            let expected = expected.map(|extra_type| (extra_type, "/movies/".to_string() + path.split('/').nth(2).unwrap()));
+            assert_eq!(extra(path), expected, "extra type of {}", path);
+        }
+    }
+
+    #[test]
+    fn test_extra_type_names() {
+        for extra_type in [ExtraType::Trailer, ExtraType::BehindTheScenes, ExtraType::Other] {
+            assert_eq!(ExtraType::from_name(extra_type.as_str()), Some(extra_type));
+        }
+        assert_eq!(ExtraType::from_name("movie"), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! Ignore rules for library scans and watch events
+//!
+//! Paths are excluded by the global patterns from the configuration and by
+//! `.rustflixignore` files. Both use `.gitignore` syntax; an ignore file
+//! applies to its own directory and everything below it, deeper files take
+//! precedence, and an ignored directory excludes everything inside it.
+//! Files below the configured minimum size are excluded as well.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use rustflix_core::config::MediaConfig;
+use rustflix_core::{Result, RustFlixError};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Name of the per-directory ignore file
+pub const IGNORE_FILE: &str = ".rustflixignore";
+
+/// Global ignore rules shared by every library
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    global: Gitignore,
+    min_file_size: u64,
+}
+
+impl IgnoreRules {
+    /// Create rules from gitignore-style patterns, matched case-insensitively
+    /// against paths relative to the library root
+    pub fn new(patterns: &[String], min_file_size: u64) -> Result<Self> {
+        let invalid = |e: ignore::Error| RustFlixError::config(format!("Invalid ignore pattern: {}", e));
+
+        let mut builder = GitignoreBuilder::new("/");
+        builder.case_insensitive(true).map_err(invalid)?;
+        for pattern in patterns {
+            builder.add_line(None, pattern).map_err(invalid)?;
+        }
+
+        Ok(Self {
+            global: builder.build().map_err(invalid)?,
+            min_file_size,
+        })
+    }
+
+    /// Create rules from the media configuration
+    pub fn from_config(config: &MediaConfig) -> Result<Self> {
+        Self::new(&config.ignore_patterns, config.min_file_size)
+    }
+
+    /// Check whether a file is below the minimum size
+    pub fn is_too_small(&self, file_size: u64) -> bool {
+        file_size < self.min_file_size
+    }
+
+    /// Check whether a path below a library root is ignored
+    ///
+    /// Ignore files are read from disk on every call; use an `IgnoreMatcher`
+    /// to check many paths.
+    pub fn is_ignored(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        IgnoreMatcher::new(self, root).is_ignored(path, is_dir)
+    }
+}
+
+impl Default for IgnoreRules {
+    fn default() -> Self {
+        Self {
+            global: Gitignore::empty(),
+            min_file_size: 0,
+        }
+    }
+}
+
+/// Ignore rules of one library, caching the ignore files it reads
+#[derive(Debug)]
+pub struct IgnoreMatcher<'a> {
+    rules: &'a IgnoreRules,
+    root: PathBuf,
+    files: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl<'a> IgnoreMatcher<'a> {
+    /// Create a matcher for the library at `root`
+    pub fn new(rules: &'a IgnoreRules, root: &Path) -> Self {
+        Self {
+            rules,
+            root: root.to_path_buf(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Check a path, including the directories between it and the root
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return self.rules.global.matched(path, is_dir).is_ignore();
+        };
+
+        let mut current = self.root.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            current.push(component);
+            let last = components.peek().is_none();
+            if self.is_entry_ignored(&current, !last || is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Check a path whose parent directories are known not to be ignored
+    pub fn is_entry_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let mut ignored = self.rules.global.matched(relative, is_dir).is_ignore();
+
+        let mut directories = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        directories.reverse();
+
+        for dir in directories {
+            if let Some(file) = self.ignore_file(&dir) {
+                match file.matched(path, is_dir) {
+                    Match::Ignore(_) => ignored = true,
+                    Match::Whitelist(_) => ignored = false,
+                    Match::None => {}
+                }
+            }
+        }
+        ignored
+    }
+
+    fn ignore_file(&mut self, dir: &Path) -> Option<&Gitignore> {
+        self.files
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let path = dir.join(IGNORE_FILE);
+                if !path.is_file() {
+                    return None;
+                }
+                let (file, error) = Gitignore::new(&path);
+                if let Some(e) = error {
+                    warn!("Invalid rules in {}: {}", path.display(), e);
+                }
+                Some(file)
+            })
+            .as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn rules(patterns: &[&str]) -> IgnoreRules {
+        let patterns = patterns.iter().map(|pattern| pattern.to_string()).collect::<Vec<_>>();
+        IgnoreRules::new(&patterns, 0).unwrap()
+    }
+
+    #[test]
+    fn test_global_patterns() {
+        let root = Path::new("/library");
+        let rules = rules(&["@eaDir", "sample.*", "*.part", ".trash*"]);
+
+        assert!(rules.is_ignored(root, Path::new("/library/Movie/@eaDir/thumb.jpg"), false));
+        assert!(rules.is_ignored(root, Path::new("/library/Movie/Sample.mkv"), false));
+        assert!(rules.is_ignored(root, Path::new("/library/Movie/movie.mkv.part"), false));
+        assert!(rules.is_ignored(root, Path::new("/library/.Trashes/movie.mkv"), false));
+        assert!(!rules.is_ignored(root, Path::new("/library/Movie/movie.mkv"), false));
+        assert!(!rules.is_ignored(root, Path::new("/library/Samples of Life/movie.mkv"), false));
+
+        // The root itself never matches, even if it looks like an ignored folder
+        assert!(!rules.is_ignored(Path::new("/@eaDir"), Path::new("/@eaDir/movie.mkv"), false));
+    }
+
+    #[test]
+    fn test_ignore_files() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("Movies/Extras")).unwrap();
+        std::fs::write(root.join(IGNORE_FILE), "*.avi\nExtras/\n").unwrap();
+        std::fs::write(root.join("Movies").join(IGNORE_FILE), "!keep.avi\n").unwrap();
+
+        let rules = rules(&[]);
+        let mut matcher = IgnoreMatcher::new(&rules, root);
+        assert!(matcher.is_ignored(&root.join("old.avi"), false));
+        assert!(matcher.is_ignored(&root.join("Movies/other.avi"), false));
+        assert!(!matcher.is_ignored(&root.join("Movies/keep.avi"), false));
+        assert!(matcher.is_ignored(&root.join("Movies/Extras/trailer.mkv"), false));
+        assert!(!matcher.is_ignored(&root.join("Movies/movie.mkv"), false));
+    }
+
+    #[test]
+    fn test_min_file_size() {
+        assert!(!IgnoreRules::default().is_too_small(0));
+
+        let rules = IgnoreRules::new(&[], 1024).unwrap();
+        assert!(rules.is_too_small(1023));
+        assert!(!rules.is_too_small(1024));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(IgnoreRules::new(&["{unclosed".to_string()], 0).is_err());
+    }
+}
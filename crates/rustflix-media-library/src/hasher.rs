@@ -0,0 +1,242 @@
+//! Content hashing for duplicate and move detection
+//!
+//! The fingerprint covers the file size plus fixed-size chunks from the start,
+//! middle and end of the file, so it costs three small reads regardless of the
+//! file size. Hashing the whole file can be enabled for stronger confirmation
+//! of duplicates.
+
+use crate::disc;
+use crate::storage::{LibraryStorage, StorageEntry, StorageReader};
+use rustflix_core::{Result, RustFlixError};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Size of each sampled chunk (64 KiB)
+pub const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Content hashes of a media file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHash {
+    /// Hash of the file size and sampled chunks
+    pub fingerprint: String,
+    /// Hash of the whole file, when enabled
+    pub full: Option<String>,
+}
+
+impl FileHash {
+    /// Check whether two hashes may describe the same content
+    ///
+    /// Fingerprints must match; full hashes are only compared when both exist.
+    pub fn matches(&self, fingerprint: Option<&str>, full: Option<&str>) -> bool {
+        if fingerprint != Some(self.fingerprint.as_str()) {
+            return false;
+        }
+        match (self.full.as_deref(), full) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        }
+    }
+}
+
+/// Hasher computing content fingerprints of media files
+#[derive(Debug, Clone, Default)]
+pub struct MediaHasher {
+    full_hash: bool,
+}
+
+impl MediaHasher {
+    /// Create a new hasher; `full_hash` also hashes the whole file
+    pub fn new(full_hash: bool) -> Self {
+        Self { full_hash }
+    }
+
+    /// Check whether whole files are hashed
+    pub fn full_hash(&self) -> bool {
+        self.full_hash
+    }
+
+    /// Hash a file on the blocking thread pool
+    ///
+    /// Blu-ray and DVD folders are hashed as a whole, see `crate::disc`.
+    pub async fn hash_file(&self, path: &Path) -> Result<FileHash> {
+        let path = path.to_path_buf();
+        let full_hash = self.full_hash;
+
+        tokio::task::spawn_blocking(move || {
+            if path.is_dir() {
+                return Ok(FileHash {
+                    fingerprint: disc::fingerprint_folder(&path)?,
+                    full: if full_hash { Some(disc::full_hash_folder(&path)?) } else { None },
+                });
+            }
+            Ok(FileHash {
+                fingerprint: fingerprint(&path)?,
+                full: if full_hash { Some(full_hash_file(&path)?) } else { None },
+            })
+        })
+        .await
+        .map_err(|e| RustFlixError::internal(format!("Hashing task failed: {}", e)))?
+    }
+
+    /// Hash a file kept in a storage backend through ranged reads
+    pub async fn hash_entry(&self, storage: Arc<dyn LibraryStorage>, entry: &StorageEntry) -> Result<FileHash> {
+        let mut reader = StorageReader::new(storage, entry)?;
+        let (size, full_hash) = (entry.size, self.full_hash);
+
+        tokio::task::spawn_blocking(move || {
+            let fingerprint = fingerprint_from(&mut reader, size)?;
+            let full = if full_hash {
+                reader.rewind()?;
+                Some(full_hash_from(&mut reader)?)
+            } else {
+                None
+            };
+            Ok(FileHash { fingerprint, full })
+        })
+        .await
+        .map_err(|e| RustFlixError::internal(format!("Hashing task failed: {}", e)))?
+    }
+}
+
+/// Fingerprint a file from its size and head, middle and tail chunks
+pub fn fingerprint(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    fingerprint_from(&mut file, size)
+}
+
+/// Fingerprint a file of `size` bytes read through `reader`
+pub fn fingerprint_from<R: Read + Seek>(reader: &mut R, size: u64) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&size.to_le_bytes());
+
+    for (offset, len) in chunk_ranges(size) {
+        let mut chunk = vec![0u8; len as usize];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut chunk)?;
+        hasher.update(&chunk);
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Hash the whole content of a file
+pub fn full_hash_file(path: &Path) -> Result<String> {
+    full_hash_from(&mut File::open(path)?)
+}
+
+/// Hash everything left to read from `reader`
+pub fn full_hash_from<R: Read>(reader: &mut R) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Byte ranges sampled for the fingerprint; small files are read whole
+fn chunk_ranges(size: u64) -> Vec<(u64, u64)> {
+    if size <= CHUNK_SIZE * 3 {
+        return vec![(0, size)];
+    }
+
+    vec![
+        (0, CHUNK_SIZE),
+        ((size - CHUNK_SIZE) / 2, CHUNK_SIZE),
+        (size - CHUNK_SIZE, CHUNK_SIZE),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_chunk_ranges() {
+        assert_eq!(chunk_ranges(100), vec![(0, 100)]);
+        assert_eq!(chunk_ranges(CHUNK_SIZE * 3), vec![(0, CHUNK_SIZE * 3)]);
+
+        let size = CHUNK_SIZE * 10;
+        assert_eq!(
+            chunk_ranges(size),
+            vec![(0, CHUNK_SIZE), (CHUNK_SIZE * 9 / 2, CHUNK_SIZE), (CHUNK_SIZE * 9, CHUNK_SIZE)]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = TempDir::new().unwrap();
+        let data = (0..CHUNK_SIZE * 8).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+        let original = write(&dir, "original.mkv", &data);
+        let copy = write(&dir, "copy.mkv", &data);
+        assert_eq!(fingerprint(&original).unwrap(), fingerprint(&copy).unwrap());
+        assert_eq!(fingerprint(&original).unwrap().len(), 64);
+
+        // Changes in a sampled chunk are detected
+        let mut tail_changed = data.clone();
+        *tail_changed.last_mut().unwrap() ^= 0xff;
+        let tail_changed = write(&dir, "tail.mkv", &tail_changed);
+        assert_ne!(fingerprint(&original).unwrap(), fingerprint(&tail_changed).unwrap());
+
+        // Changes outside the sampled chunks are only caught by the full hash
+        let mut unsampled = data.clone();
+        unsampled[CHUNK_SIZE as usize * 2] ^= 0xff;
+        let unsampled = write(&dir, "unsampled.mkv", &unsampled);
+        assert_eq!(fingerprint(&original).unwrap(), fingerprint(&unsampled).unwrap());
+        assert_ne!(full_hash_file(&original).unwrap(), full_hash_file(&unsampled).unwrap());
+
+        // The size is part of the fingerprint
+        let truncated = write(&dir, "truncated.mkv", &data[..data.len() - 1]);
+        assert_ne!(fingerprint(&original).unwrap(), fingerprint(&truncated).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_hash_file() {
+        let dir = TempDir::new().unwrap();
+        let path = write(&dir, "movie.mkv", b"small file");
+
+        let hash = MediaHasher::default().hash_file(&path).await.unwrap();
+        assert_eq!(hash.full, None);
+
+        let full = MediaHasher::new(true).hash_file(&path).await.unwrap();
+        assert_eq!(full.fingerprint, hash.fingerprint);
+        assert!(full.full.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_hash_entry() {
+        let dir = TempDir::new().unwrap();
+        let data = (0..CHUNK_SIZE * 4).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let path = write(&dir, "movie.mkv", &data);
+
+        let storage: Arc<dyn LibraryStorage> = Arc::new(LocalStorage::new(dir.path()));
+        let entry = storage.stat("movie.mkv").await.unwrap().unwrap();
+        let hasher = MediaHasher::new(true);
+        assert_eq!(
+            hasher.hash_entry(storage, &entry).await.unwrap(),
+            hasher.hash_file(&path).await.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let hash = FileHash {
+            fingerprint: "a".to_string(),
+            full: Some("full".to_string()),
+        };
+        assert!(hash.matches(Some("a"), None));
+        assert!(hash.matches(Some("a"), Some("full")));
+        assert!(!hash.matches(Some("a"), Some("other")));
+        assert!(!hash.matches(Some("b"), None));
+        assert!(!hash.matches(None, None));
+    }
+}
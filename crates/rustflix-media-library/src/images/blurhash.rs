@@ -0,0 +1,137 @@
+//! BlurHash encoding
+//!
+//! Encodes a compact placeholder that clients decode into a blurred preview
+//! while the real image loads, following https://blurha.sh.
+
+use image::RgbImage;
+use std::f64::consts::PI;
+
+const CHARACTERS: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Encode an image with `x_components` by `y_components` cosine components
+/// (each from 1 to 9)
+///
+/// The image should already be small; every pixel is visited once per
+/// component.
+pub fn encode(image: &RgbImage, x_components: u32, y_components: u32) -> String {
+    let (x_components, y_components) = (x_components.clamp(1, 9), y_components.clamp(1, 9));
+    let (width, height) = (image.width().max(1) as f64, image.height().max(1) as f64);
+
+    let linear = image
+        .pixels()
+        .map(|pixel| pixel.0.map(srgb_to_linear))
+        .collect::<Vec<_>>();
+
+    let mut factors = Vec::with_capacity((x_components * y_components) as usize);
+    for j in 0..y_components {
+        for i in 0..x_components {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0f64; 3];
+            for (index, pixel) in linear.iter().enumerate() {
+                let x = (index as u32 % image.width()) as f64;
+                let y = (index as u32 / image.width()) as f64;
+                let basis = (PI * i as f64 * x / width).cos() * (PI * j as f64 * y / height).cos();
+                for (sum, value) in factor.iter_mut().zip(pixel) {
+                    *sum += basis * value;
+                }
+            }
+            factors.push(factor.map(|value| value * normalisation / (width * height)));
+        }
+    }
+
+    let mut hash = String::with_capacity(4 + 2 * factors.len());
+    encode83((x_components - 1) + (y_components - 1) * 9, 1, &mut hash);
+
+    let (dc, ac) = factors.split_first().expect("at least one component");
+    let maximum = if ac.is_empty() {
+        encode83(0, 1, &mut hash);
+        1.0
+    } else {
+        let actual = ac.iter().flatten().fold(0.0f64, |max, value| max.max(value.abs()));
+        let quantised = (actual * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+        encode83(quantised, 1, &mut hash);
+        (quantised + 1) as f64 / 166.0
+    };
+
+    encode83(
+        (linear_to_srgb(dc[0]) << 16) + (linear_to_srgb(dc[1]) << 8) + linear_to_srgb(dc[2]),
+        4,
+        &mut hash,
+    );
+    for factor in ac {
+        let [r, g, b] = factor.map(|value| {
+            (sign_pow(value / maximum, 0.5) * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+        });
+        encode83(r * 19 * 19 + g * 19 + b, 2, &mut hash);
+    }
+    hash
+}
+
+/// Components to use for an image, more along its longer side
+pub fn components(width: u32, height: u32) -> (u32, u32) {
+    if width >= height {
+        (4, 3)
+    } else {
+        (3, 4)
+    }
+}
+
+fn encode83(value: u32, length: u32, out: &mut String) {
+    for i in 1..=length {
+        let digit = (value / 83u32.pow(length - i)) % 83;
+        out.push(CHARACTERS[digit as usize] as char);
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let value = value as f64 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u32 {
+    let value = value.clamp(0.0, 1.0);
+    let srgb = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0 + 0.5) as u32
+}
+
+fn sign_pow(value: f64, exponent: f64) -> f64 {
+    value.abs().powf(exponent).copysign(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_solid_colour() {
+        let image = RgbImage::from_pixel(8, 8, Rgb([255, 0, 0]));
+        assert_eq!(encode(&image, 1, 1), "00TI:j");
+        // Components of a flat image are zero, so only the DC value varies
+        assert_eq!(&encode(&image, 4, 3)[2..6], "TI:j");
+    }
+
+    #[test]
+    fn test_gradient() {
+        let image = RgbImage::from_fn(32, 16, |x, _| Rgb([(x * 8) as u8, 64, 128]));
+        let hash = encode(&image, 4, 3);
+        assert_eq!(hash.len(), 4 + 2 * 12);
+        assert!(hash.starts_with('L'));
+        assert_ne!(&hash[1..2], "0");
+    }
+
+    #[test]
+    fn test_components() {
+        assert_eq!(components(1920, 1080), (4, 3));
+        assert_eq!(components(1000, 1500), (3, 4));
+    }
+}
@@ -0,0 +1,286 @@
+//! Image pipeline for posters, backdrops and photos
+//!
+//! Source images are stored content-addressed: every resized variant of an
+//! image lives under `<root>/<first two hash characters>/<hash>/`, so items
+//! sharing artwork share its variants and an image is only resized once.
+//! Decoding and writing variants block, so run them on a blocking thread.
+
+pub mod blurhash;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageEncoder, RgbImage};
+use rustflix_core::{Result, RustFlixError};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Quality of JPEG variants
+const JPEG_QUALITY: u8 = 85;
+
+/// Size the image is reduced to before computing its blurhash
+const BLURHASH_SIZE: u32 = 32;
+
+/// Encoding of a resized variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariantFormat {
+    Jpeg,
+    /// Lossless WebP, for clients that prefer it
+    Webp,
+}
+
+impl VariantFormat {
+    pub const ALL: [VariantFormat; 2] = [VariantFormat::Jpeg, VariantFormat::Webp];
+
+    /// Name of the format as stored in the database
+    pub fn name(self) -> &'static str {
+        match self {
+            VariantFormat::Jpeg => "jpeg",
+            VariantFormat::Webp => "webp",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            VariantFormat::Jpeg => "jpg",
+            VariantFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            VariantFormat::Jpeg => "image/jpeg",
+            VariantFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// Resized copy of an image written to disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageVariant {
+    pub width: u32,
+    pub height: u32,
+    pub format: VariantFormat,
+    pub path: PathBuf,
+}
+
+/// Image processed by the pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessedImage {
+    /// BLAKE3 hash of the source file
+    pub hash: String,
+    /// Dimensions after applying the orientation
+    pub width: u32,
+    pub height: u32,
+    pub blurhash: String,
+    pub variants: Vec<ImageVariant>,
+}
+
+/// Content hash of a source image
+pub fn content_hash(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+/// Folder holding the variants of an image
+pub fn image_dir(root: &Path, hash: &str) -> PathBuf {
+    root.join(&hash[..2.min(hash.len())]).join(hash)
+}
+
+/// Decode an image and write JPEG and WebP variants fitting each size
+///
+/// Images are turned upright first and never scaled up, so sizes larger
+/// than the image share one variant.
+pub fn process_image(data: &[u8], orientation: Option<u16>, root: &Path, sizes: &[(u32, u32)]) -> Result<ProcessedImage> {
+    let hash = content_hash(data);
+    let image = image::load_from_memory(data).map_err(|e| RustFlixError::media_processing(format!("Invalid image: {}", e)))?;
+    let image = orient(image, orientation);
+
+    let small = image.thumbnail(BLURHASH_SIZE, BLURHASH_SIZE).to_rgb8();
+    let (x_components, y_components) = blurhash::components(image.width(), image.height());
+    let blurhash = blurhash::encode(&small, x_components, y_components);
+
+    let output_dir = image_dir(root, &hash);
+    std::fs::create_dir_all(&output_dir)?;
+
+    let mut variants: Vec<ImageVariant> = Vec::new();
+    for &(max_width, max_height) in sizes {
+        let resized = resize(&image, max_width, max_height);
+        let (width, height) = resized.dimensions();
+        if variants.iter().any(|variant| (variant.width, variant.height) == (width, height)) {
+            continue;
+        }
+
+        for format in VariantFormat::ALL {
+            let path = output_dir.join(format!("{}x{}.{}", width, height, format.extension()));
+            write_variant(&resized, format, &path)?;
+            variants.push(ImageVariant { width, height, format, path });
+        }
+    }
+
+    Ok(ProcessedImage {
+        hash,
+        width: image.width(),
+        height: image.height(),
+        blurhash,
+        variants,
+    })
+}
+
+/// Write a single JPEG thumbnail of an image fitting the given size
+pub fn generate_thumbnail(source: &Path, output: &Path, (max_width, max_height): (u32, u32)) -> Result<()> {
+    let image = image::open(source).map_err(|e| RustFlixError::media_processing(format!("Invalid image: {}", e)))?;
+    write_variant(&resize(&image, max_width, max_height), VariantFormat::Jpeg, output)
+}
+
+/// Encode an image scaled down to a width, in memory
+///
+/// For images served on demand rather than stored as variants, such as the
+/// pages of comics. Images are never scaled up.
+pub fn resize_image(data: &[u8], max_width: u32, format: VariantFormat) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(data).map_err(|e| RustFlixError::media_processing(format!("Invalid image: {}", e)))?;
+    let mut output = Vec::new();
+    encode(&resize(&image, max_width, u32::MAX), format, &mut output)
+        .map_err(|e| RustFlixError::media_processing(format!("Failed to encode image: {}", e)))?;
+    Ok(output)
+}
+
+/// Delete the files of variants and the folders left empty
+pub fn remove_variants(paths: &[PathBuf]) -> Result<()> {
+    for path in paths {
+        match std::fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(RustFlixError::Io(e)),
+        }
+        // Fails while other variants remain, which is fine
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::remove_dir(dir);
+            if let Some(shard) = dir.parent() {
+                let _ = std::fs::remove_dir(shard);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Turn an image upright according to its EXIF orientation
+fn orient(image: DynamicImage, orientation: Option<u16>) -> DynamicImage {
+    match orientation {
+        Some(2) => image.fliph(),
+        Some(3) => image.rotate180(),
+        Some(4) => image.flipv(),
+        Some(5) => image.rotate90().fliph(),
+        Some(6) => image.rotate90(),
+        Some(7) => image.rotate270().fliph(),
+        Some(8) => image.rotate270(),
+        _ => image,
+    }
+}
+
+fn resize(image: &DynamicImage, max_width: u32, max_height: u32) -> RgbImage {
+    if image.width() <= max_width && image.height() <= max_height {
+        image.to_rgb8()
+    } else {
+        image.thumbnail(max_width, max_height).to_rgb8()
+    }
+}
+
+fn write_variant(image: &RgbImage, format: VariantFormat, path: &Path) -> Result<()> {
+    encode(image, format, BufWriter::new(File::create(path)?))
+        .map_err(|e| RustFlixError::media_processing(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+fn encode<W: Write>(image: &RgbImage, format: VariantFormat, writer: W) -> image::ImageResult<()> {
+    let (width, height) = image.dimensions();
+    match format {
+        VariantFormat::Jpeg => JpegEncoder::new_with_quality(writer, JPEG_QUALITY)
+            .write_image(image.as_raw(), width, height, image::ColorType::Rgb8),
+        VariantFormat::Webp => WebPEncoder::new_lossless(writer)
+            .write_image(image.as_raw(), width, height, image::ColorType::Rgb8),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_process_image() {
+        let dir = TempDir::new().unwrap();
+        let data = png(400, 300);
+
+        let image = process_image(&data, Some(6), dir.path(), &[(100, 100), (200, 200), (800, 800), (1600, 1600)]).unwrap();
+        assert_eq!(image.hash, content_hash(&data));
+        // Orientation 6 turns the image upright as 300x400
+        assert_eq!((image.width, image.height), (300, 400));
+        assert!(image.blurhash.starts_with('T'));
+
+        let sizes = image
+            .variants
+            .iter()
+            .filter(|variant| variant.format == VariantFormat::Jpeg)
+            .map(|variant| (variant.width, variant.height))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(75, 100), (150, 200), (300, 400)]);
+        assert_eq!(image.variants.len(), 6);
+        assert!(image.variants.iter().all(|variant| variant.path.starts_with(image_dir(dir.path(), &image.hash))));
+        assert_eq!(image::image_dimensions(&image.variants[1].path).unwrap(), (75, 100));
+        assert_eq!(image::ImageFormat::from_path(&image.variants[1].path).unwrap(), ImageFormat::WebP);
+
+        let paths = image.variants.iter().map(|variant| variant.path.clone()).collect::<Vec<_>>();
+        remove_variants(&paths).unwrap();
+        assert!(!image_dir(dir.path(), &image.hash).exists());
+        assert!(dir.path().exists());
+    }
+
+    #[test]
+    fn test_generate_thumbnail() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("poster.png");
+        std::fs::write(&source, png(200, 300)).unwrap();
+        let output = dir.path().join("thumb.jpg");
+
+        generate_thumbnail(&source, &output, (320, 180)).unwrap();
+        assert_eq!(image::image_dimensions(&output).unwrap(), (120, 180));
+    }
+
+    #[test]
+    fn test_resize_image() {
+        let resized = resize_image(&png(400, 600), 200, VariantFormat::Jpeg).unwrap();
+        let image = image::load_from_memory_with_format(&resized, ImageFormat::Jpeg).unwrap();
+        assert_eq!((image.width(), image.height()), (200, 300));
+
+        let small = resize_image(&png(100, 150), 200, VariantFormat::Webp).unwrap();
+        let image = image::load_from_memory_with_format(&small, ImageFormat::WebP).unwrap();
+        assert_eq!((image.width(), image.height()), (100, 150));
+    }
+
+    #[test]
+    fn test_invalid_image() {
+        let dir = TempDir::new().unwrap();
+        assert!(process_image(b"not an image", None, dir.path(), &[(100, 100)]).is_err());
+    }
+
+    #[test]
+    fn test_orient() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(4, 2));
+        assert_eq!(orient(image.clone(), Some(8)).width(), 2);
+        assert_eq!(orient(image.clone(), Some(3)).width(), 4);
+        assert_eq!(orient(image, None).height(), 2);
+    }
+}
@@ -5,50 +5,770 @@
 pub mod scanner;
 pub mod watcher;
 pub mod analyzer;
+pub mod probe;
+pub mod parser;
+pub mod hasher;
+pub mod sidecar;
+pub mod filter;
+pub mod stacking;
+pub mod extras;
+pub mod tags;
+pub mod music;
+pub mod audiobooks;
+pub mod books;
+pub mod photo;
+pub mod images;
+pub mod storage;
+pub mod disc;
+pub mod audit;
 
 // Re-export commonly used types
-pub use scanner::{MediaScanner, ScanResult};
+pub use scanner::{MediaScanner, ScanEvent, ScanResult};
 pub use watcher::{FileWatcher, WatchEvent};
 pub use analyzer::{MediaAnalyzer, MediaInfo};
+pub use parser::{ParsedName, SearchQuery};
+pub use hasher::{FileHash, MediaHasher};
+pub use sidecar::{Sidecar, SidecarKind};
+pub use filter::IgnoreRules;
+pub use stacking::{Stack, StackVersion};
+pub use extras::Extra;
+pub use tags::MusicTags;
+pub use music::MusicLibrary;
+pub use audiobooks::AudiobookLibrary;
+pub use books::BookInfo;
+pub use photo::Exif;
+pub use images::{ImageVariant, ProcessedImage, VariantFormat};
+pub use storage::{LibraryStorage, LibraryStorages, StorageEntry};
+pub use audit::{AuditCleanup, IssueKind, LibraryAuditor};
 
-use rustflix_core::{Result, RustFlixError};
+use rustflix_core::config::{MediaConfig, ObjectStorageConfig};
+use rustflix_core::{Event, EventType, Result, RustFlixError};
+use rustflix_database::{LibraryModel, MediaRepository};
+use storage::StorageSnapshot;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Source name of published events
+const EVENT_SOURCE: &str = "media_library";
+
+/// Capacity of the event channel before slow subscribers lag
+const EVENT_CAPACITY: usize = 1024;
+
+/// How often the libraries table is read for added, changed and removed
+/// libraries
+const LIBRARY_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Media library service
-#[derive(Debug)]
+///
+/// Runs every enabled library: scans on its schedule and applies file system
+/// changes as they happen, publishing core events along the way. Clones
+/// share the same libraries.
+#[derive(Debug, Clone)]
 pub struct MediaLibraryService {
+    inner: Arc<LibraryRunner>,
+    /// Task of each running library, by library
+    libraries: Arc<Mutex<HashMap<Uuid, LibraryTask>>>,
+    /// Task refreshing the libraries from the repository
+    refresh: Arc<Mutex<Option<JoinHandle<()>>>>,
+    /// Held while the running libraries are brought in line
+    reloading: Arc<tokio::sync::Mutex<()>>,
+    shutdown: Arc<Mutex<CancellationToken>>,
+}
+
+/// Library being run, with the settings it was started with
+#[derive(Debug)]
+struct LibraryTask {
+    library: LibraryModel,
+    cancel: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+/// State shared by the service and its library tasks
+#[derive(Debug)]
+struct LibraryRunner {
     scanner: MediaScanner,
     analyzer: MediaAnalyzer,
+    repository: MediaRepository,
+    events: broadcast::Sender<Event>,
+    default_scan_interval: Option<u64>,
+    /// Object storage of bucket libraries, when configured
+    object_storage: Option<ObjectStorageConfig>,
+    /// Cancellation tokens of the running scans, by library
+    scans: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+    auditor: LibraryAuditor,
 }
 
 impl MediaLibraryService {
     /// Create a new media library service
-    pub fn new() -> Result<Self> {
+    pub fn new(repository: MediaRepository, config: &MediaConfig) -> Result<Self> {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let scanner = MediaScanner::new()?
+            .with_full_hash(config.full_hash)
+            .with_ignore_rules(IgnoreRules::from_config(config)?)
+            .with_thumbnails(config.thumbnail_path.clone(), config.thumbnail_sizes.clone());
+        let analyzer = MediaAnalyzer::new()?.with_chapters(config.extract_chapters);
+        // Audits only probe streams, so they skip the chapters
+        let auditor = LibraryAuditor::new(
+            repository.clone(),
+            scanner.clone(),
+            analyzer.clone().with_chapters(false),
+            config.object_storage.clone(),
+        );
+
         Ok(Self {
-            scanner: MediaScanner::new()?,
-            analyzer: MediaAnalyzer::new()?,
+            inner: Arc::new(LibraryRunner {
+                scanner,
+                analyzer,
+                repository,
+                events,
+                default_scan_interval: config.scan_interval,
+                object_storage: config.object_storage.clone(),
+                scans: Arc::new(Mutex::new(HashMap::new())),
+                auditor,
+            }),
+            libraries: Arc::new(Mutex::new(HashMap::new())),
+            refresh: Arc::new(Mutex::new(None)),
+            reloading: Arc::new(tokio::sync::Mutex::new(())),
+            shutdown: Arc::new(Mutex::new(CancellationToken::new())),
         })
     }
 
+    /// Subscribe to library and media item events
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.inner.events.subscribe()
+    }
+
     /// Start the media library service
+    ///
+    /// The libraries are read again every minute, so libraries added,
+    /// disabled or changed while running are picked up.
     pub async fn start(&self) -> Result<()> {
-        // Implementation will be added
+        if lock(&self.refresh).is_some() {
+            return Ok(());
+        }
+
+        // Audits run in the background and do not survive a restart
+        let interrupted = self.inner.repository.fail_running_audits("interrupted by a restart").await?;
+        if interrupted > 0 {
+            warn!("Marked {} interrupted library audits as failed", interrupted);
+        }
+
+        let shutdown = CancellationToken::new();
+        *lock(&self.shutdown) = shutdown.clone();
+        self.reload_libraries().await?;
+
+        let service = self.clone();
+        *lock(&self.refresh) = Some(tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(LIBRARY_REFRESH_INTERVAL) => {
+                        if let Err(e) = service.reload_libraries().await {
+                            warn!("Failed to refresh libraries: {}", e);
+                        }
+                    }
+                }
+            }
+        }));
+
+        Ok(())
+    }
+
+    /// Read the libraries from the repository and bring the running ones in
+    /// line: new and enabled libraries are started, removed and disabled
+    /// ones stopped, and changed ones restarted
+    pub async fn reload_libraries(&self) -> Result<()> {
+        let libraries = self.inner.repository.list_libraries().await?;
+        let shutdown = lock(&self.shutdown).clone();
+        if shutdown.is_cancelled() {
+            return Ok(());
+        }
+
+        let _reloading = self.reloading.lock().await;
+        let (stopping, start) = {
+            let mut running = lock(&self.libraries);
+            let started = running.iter().map(|(id, task)| (*id, &task.library)).collect();
+            let (stop, start) = plan_libraries(&started, libraries);
+
+            let stopping = stop.iter().filter_map(|id| running.remove(id)).collect::<Vec<_>>();
+            for task in &stopping {
+                info!("Stopping library {}", task.library.name);
+                task.cancel.cancel();
+            }
+            (stopping, start)
+        };
+        // Restarted libraries wait for their previous task to let go of them
+        for task in stopping {
+            if let Err(e) = task.task.await {
+                warn!("Library task failed: {}", e);
+            }
+        }
+
+        let mut running = lock(&self.libraries);
+        for library in start {
+            info!("Starting library {} at {}", library.name, library.path);
+            let cancel = shutdown.child_token();
+            let task = tokio::spawn(self.inner.clone().run(library.clone(), cancel.clone()));
+            running.insert(library.id, LibraryTask { library, cancel, task });
+        }
+
         Ok(())
     }
 
     /// Stop the media library service
+    ///
+    /// Running scans are cancelled and library tasks are awaited.
     pub async fn stop(&self) -> Result<()> {
-        // Implementation will be added
+        lock(&self.shutdown).cancel();
+
+        let refresh = lock(&self.refresh).take();
+        let tasks = std::mem::take(&mut *lock(&self.libraries));
+        for task in refresh.into_iter().chain(tasks.into_values().map(|task| task.task)) {
+            if let Err(e) = task.await {
+                warn!("Library task failed: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scan a library now
+    ///
+    /// Fails if a scan of the same library is already running.
+    pub async fn scan_library(&self, library_id: Uuid) -> Result<ScanResult> {
+        let library = self.library(library_id).await?;
+        let shutdown = lock(&self.shutdown).child_token();
+
+        self.inner.scan(&library, shutdown).await
+    }
+
+    /// Start a scan of a library in the background
+    ///
+    /// Fails if the library does not exist or is already being scanned.
+    pub async fn start_scan(&self, library_id: Uuid) -> Result<()> {
+        let library = self.library(library_id).await?;
+        let cancel = lock(&self.shutdown).child_token();
+        let slot = self.inner.claim_scan(library_id, &cancel)?;
+
+        let runner = self.inner.clone();
+        tokio::spawn(async move {
+            if let Err(e) = runner.scan_claimed(&library, cancel, slot).await {
+                warn!("Scan of library {} failed: {}", library.name, e);
+            }
+        });
         Ok(())
     }
+
+    /// Cancel the running scan of a library, returning whether one was running
+    pub fn cancel_scan(&self, library_id: Uuid) -> bool {
+        match lock(&self.inner.scans).get(&library_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Check whether a library is being scanned
+    pub fn is_scanning(&self, library_id: Uuid) -> bool {
+        lock(&self.inner.scans).contains_key(&library_id)
+    }
+
+    async fn library(&self, library_id: Uuid) -> Result<LibraryModel> {
+        self.inner
+            .repository
+            .get_library(library_id)
+            .await?
+            .ok_or_else(|| RustFlixError::not_found("library", &library_id.to_string()))
+    }
+
+    /// Auditor reporting broken and inconsistent library content
+    pub fn auditor(&self) -> LibraryAuditor {
+        self.inner.auditor.clone()
+    }
+
+    /// Storage backends of the libraries, to read their files
+    pub fn storages(&self) -> LibraryStorages {
+        LibraryStorages::new(self.inner.repository.clone(), self.inner.object_storage.clone())
+    }
+}
+
+impl LibraryRunner {
+    /// Run one library until shutdown
+    ///
+    /// Local libraries are watched for changes, and scanned when the watcher
+    /// dropped events; bucket libraries are listed at the polling interval
+    /// and scanned when their files changed.
+    async fn run(self: Arc<Self>, library: LibraryModel, shutdown: CancellationToken) {
+        let storage = match storage::open(&library.path, self.object_storage.as_ref()) {
+            Ok(storage) => storage,
+            Err(e) => {
+                error!("Cannot open library {}: {}", library.name, e);
+                return;
+            }
+        };
+        // Watching lists the files of the library
+        let watching = storage.clone();
+        let ignore = self.scanner.ignore_rules().clone();
+        let watched = tokio::task::spawn_blocking(move || watching.watch(ignore))
+            .await
+            .map_err(|e| RustFlixError::internal(format!("Watch task failed: {}", e)))
+            .and_then(|result| result);
+        let mut watcher = match watched {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                warn!("Not watching library {}: {}", library.name, e);
+                None
+            }
+        };
+        let poll_interval = self
+            .object_storage
+            .as_ref()
+            .filter(|_| storage::is_remote(&library.path))
+            .map(|config| Duration::from_secs(config.poll_interval.max(1)));
+        let mut next_poll = poll_interval.map(|interval| tokio::time::Instant::now() + interval);
+        let mut snapshot = None;
+
+        let interval = library
+            .scan_interval
+            .map(|seconds| seconds.max(0) as u64)
+            .or(self.default_scan_interval)
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs);
+        let mut next_scan = first_scan_delay(library.last_scan, interval, Utc::now())
+            .map(|delay| tokio::time::Instant::now() + delay);
+
+        loop {
+            let scan_at = next_scan.unwrap_or_else(tokio::time::Instant::now);
+            let poll_at = next_poll.unwrap_or_else(tokio::time::Instant::now);
+
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep_until(scan_at), if next_scan.is_some() => {
+                    if let Err(e) = self.scan(&library, shutdown.child_token()).await {
+                        warn!("Scan of library {} failed: {}", library.name, e);
+                    }
+                    next_scan = interval.map(|interval| tokio::time::Instant::now() + interval);
+                }
+                _ = tokio::time::sleep_until(poll_at), if next_poll.is_some() => {
+                    if self.poll(&library, storage.as_ref(), &mut snapshot).await {
+                        if let Err(e) = self.scan(&library, shutdown.child_token()).await {
+                            warn!("Scan of library {} failed: {}", library.name, e);
+                        }
+                    }
+                    next_poll = poll_interval.map(|interval| tokio::time::Instant::now() + interval);
+                }
+                event = next_watch_event(&mut watcher) => match event {
+                    Some(WatchEvent::Rescan) => next_scan = Some(tokio::time::Instant::now()),
+                    Some(event) => self.apply_watch_event(&library, &event).await,
+                    None => watcher = None,
+                },
+            }
+        }
+
+        info!("Stopped library {}", library.name);
+    }
+
+    /// List a library for changes, returning whether a scan is needed
+    ///
+    /// The first listing only records the files, as libraries are scanned
+    /// on their own schedule.
+    async fn poll(&self, library: &LibraryModel, storage: &dyn LibraryStorage, snapshot: &mut Option<StorageSnapshot>) -> bool {
+        let previous = snapshot.get_or_insert_with(StorageSnapshot::new);
+        let first = previous.is_empty();
+        match storage.poll(previous).await {
+            Ok(changes) if first || changes.is_empty() => false,
+            Ok(changes) => {
+                info!("Found {} changed files in library {}", changes.len(), library.name);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to list library {}: {}", library.name, e);
+                false
+            }
+        }
+    }
+
+    /// Scanner for a library, reading bucket libraries through their storage
+    fn scanner(&self, library: &LibraryModel) -> Result<MediaScanner> {
+        let scanner = self.scanner.for_library_type(&library.library_type);
+        if !storage::is_remote(&library.path) {
+            return Ok(scanner);
+        }
+        Ok(scanner.with_storage(storage::open(&library.path, self.object_storage.as_ref())?))
+    }
+
+    /// Scan a library, holding its scan slot for the duration
+    async fn scan(&self, library: &LibraryModel, cancel: CancellationToken) -> Result<ScanResult> {
+        let slot = self.claim_scan(library.id, &cancel)?;
+        self.scan_claimed(library, cancel, slot).await
+    }
+
+    /// Take the scan slot of a library, failing if it is being scanned
+    fn claim_scan(&self, library_id: Uuid, cancel: &CancellationToken) -> Result<ScanSlot> {
+        ScanSlot::acquire(&self.scans, library_id, cancel.clone()).ok_or_else(|| {
+            RustFlixError::service_unavailable("media_library", "library is already being scanned")
+        })
+    }
+
+    /// Scan a library whose scan slot is held, releasing it when done
+    ///
+    /// Every started scan publishes either a completed or a failed event.
+    async fn scan_claimed(&self, library: &LibraryModel, cancel: CancellationToken, _slot: ScanSlot) -> Result<ScanResult> {
+        self.publish(EventType::LibraryScanStarted { library_id: library.id });
+
+        let result = self.scan_files(library, &cancel).await;
+        match &result {
+            Ok(result) => {
+                if !result.cancelled {
+                    if let Err(e) = self.record_scan(library.id).await {
+                        error!("Failed to record scan of library {}: {}", library.name, e);
+                    }
+                }
+                self.publish(EventType::LibraryScanCompleted {
+                    library_id: library.id,
+                    items_added: result.items_added,
+                    items_updated: result.items_updated,
+                });
+            }
+            Err(e) => self.publish(EventType::LibraryScanFailed {
+                library_id: library.id,
+                error: e.to_string(),
+            }),
+        }
+
+        result
+    }
+
+    async fn scan_files(&self, library: &LibraryModel, cancel: &CancellationToken) -> Result<ScanResult> {
+        let root = PathBuf::from(&library.path);
+        self.scanner(library)?
+            .scan_library_with(&root, &self.repository, &self.analyzer, cancel, |event| {
+                self.publish_scan_event(library.id, event)
+            })
+            .await
+    }
+
+    async fn apply_watch_event(&self, library: &LibraryModel, event: &WatchEvent) {
+        let result = self
+            .scanner
+            .for_library_type(&library.library_type)
+            .apply_watch_event(event, &self.repository, &self.analyzer, |event| {
+                self.publish_scan_event(Uuid::nil(), event)
+            })
+            .await;
+
+        match result {
+            Ok(result) => {
+                for error in result.errors {
+                    warn!("Failed to apply {:?}: {}", event, error);
+                }
+            }
+            Err(e) => warn!("Failed to apply {:?}: {}", event, e),
+        }
+    }
+
+    async fn record_scan(&self, library_id: Uuid) -> Result<()> {
+        if let Some(mut library) = self.repository.get_library(library_id).await? {
+            let now = Utc::now();
+            library.last_scan = Some(now);
+            library.updated_at = now;
+            self.repository.update_library(&library).await?;
+        }
+        Ok(())
+    }
+
+    fn publish_scan_event(&self, library_id: Uuid, event: ScanEvent) {
+        let event_type = match event {
+            ScanEvent::Progress { processed, total, path } => EventType::LibraryScanProgress {
+                library_id,
+                progress: if total == 0 { 1.0 } else { processed as f32 / total as f32 },
+                current_path: path.to_string_lossy().into_owned(),
+            },
+            ScanEvent::Added { id, path } => EventType::MediaItemAdded {
+                media_id: id,
+                path: path.to_string_lossy().into_owned(),
+            },
+            ScanEvent::Updated { id } => EventType::MediaItemUpdated { media_id: id },
+            ScanEvent::Removed { id } => EventType::MediaItemRemoved { media_id: id },
+        };
+
+        // Watch events are not part of a scan, so they carry no progress
+        if library_id.is_nil() && matches!(event_type, EventType::LibraryScanProgress { .. }) {
+            return;
+        }
+        self.publish(event_type);
+    }
+
+    fn publish(&self, event_type: EventType) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(Event::new(event_type, EVENT_SOURCE.to_string()));
+    }
+}
+
+/// Exclusive right to scan a library, released on drop
+#[derive(Debug)]
+struct ScanSlot {
+    scans: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+    library_id: Uuid,
+}
+
+impl ScanSlot {
+    fn acquire(
+        scans: &Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+        library_id: Uuid,
+        cancel: CancellationToken,
+    ) -> Option<Self> {
+        let mut running = lock(scans);
+        if running.contains_key(&library_id) {
+            return None;
+        }
+        running.insert(library_id, cancel);
+
+        Some(Self {
+            scans: scans.clone(),
+            library_id,
+        })
+    }
+}
+
+impl Drop for ScanSlot {
+    fn drop(&mut self) {
+        lock(&self.scans).remove(&self.library_id);
+    }
+}
+
+/// Libraries to stop and to start so the running ones match the enabled
+/// libraries of the repository
+///
+/// Libraries whose path, type or scan interval changed are restarted.
+fn plan_libraries(running: &HashMap<Uuid, &LibraryModel>, libraries: Vec<LibraryModel>) -> (Vec<Uuid>, Vec<LibraryModel>) {
+    let enabled = libraries
+        .into_iter()
+        .filter(|library| library.is_enabled)
+        .map(|library| (library.id, library))
+        .collect::<HashMap<_, _>>();
+
+    let stop = running
+        .iter()
+        .filter(|(id, started)| {
+            enabled.get(id).is_none_or(|library| {
+                library.path != started.path
+                    || library.library_type != started.library_type
+                    || library.scan_interval != started.scan_interval
+            })
+        })
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    let start = enabled
+        .into_values()
+        .filter(|library| !running.contains_key(&library.id) || stop.contains(&library.id))
+        .collect();
+    (stop, start)
+}
+
+/// Delay before the first scheduled scan of a library
+///
+/// Libraries never scanned are scanned right away; others wait for the rest
+/// of their interval. Without an interval only never-scanned libraries scan.
+fn first_scan_delay(
+    last_scan: Option<DateTime<Utc>>,
+    interval: Option<Duration>,
+    now: DateTime<Utc>,
+) -> Option<Duration> {
+    let Some(last_scan) = last_scan else {
+        return Some(Duration::ZERO);
+    };
+    let interval = interval?;
+    let elapsed = (now - last_scan).to_std().unwrap_or(Duration::ZERO);
+    Some(interval.saturating_sub(elapsed))
+}
+
+async fn next_watch_event(watcher: &mut Option<FileWatcher>) -> Option<WatchEvent> {
+    match watcher {
+        Some(watcher) => watcher.next_event().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rustflix_core::RustFlixConfig;
+    use sqlx::postgres::PgPoolOptions;
+
+    fn service() -> MediaLibraryService {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/rustflix")
+            .unwrap();
+        MediaLibraryService::new(MediaRepository::new(pool), &RustFlixConfig::default().media).unwrap()
+    }
 
     #[tokio::test]
     async fn test_service_creation() {
-        let service = MediaLibraryService::new();
-        assert!(service.is_ok());
+        let service = service();
+        assert!(!service.is_scanning(Uuid::new_v4()));
+        assert!(service.stop().await.is_ok());
+    }
+
+    #[test]
+    fn test_one_scan_per_library() {
+        let scans = Arc::new(Mutex::new(HashMap::new()));
+        let library_id = Uuid::new_v4();
+
+        let cancel = CancellationToken::new();
+        let slot = ScanSlot::acquire(&scans, library_id, cancel.clone()).unwrap();
+        assert!(ScanSlot::acquire(&scans, library_id, CancellationToken::new()).is_none());
+        assert!(ScanSlot::acquire(&scans, Uuid::new_v4(), CancellationToken::new()).is_some());
+
+        drop(slot);
+        assert!(ScanSlot::acquire(&scans, library_id, CancellationToken::new()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_scan() {
+        let service = service();
+        let library_id = Uuid::new_v4();
+        assert!(!service.cancel_scan(library_id));
+
+        let cancel = CancellationToken::new();
+        let _slot = ScanSlot::acquire(&service.inner.scans, library_id, cancel.clone()).unwrap();
+        assert!(service.is_scanning(library_id));
+        assert!(service.cancel_scan(library_id));
+        assert!(cancel.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_publish_scan_events() {
+        let service = service();
+        let mut events = service.subscribe();
+        let library_id = Uuid::new_v4();
+        let media_id = Uuid::new_v4();
+
+        service.inner.publish_scan_event(
+            library_id,
+            ScanEvent::Progress { processed: 1, total: 4, path: PathBuf::from("/lib/a.mkv") },
+        );
+        service.inner.publish_scan_event(Uuid::nil(), ScanEvent::Progress {
+            processed: 1,
+            total: 1,
+            path: PathBuf::from("/lib/b.mkv"),
+        });
+        service.inner.publish_scan_event(Uuid::nil(), ScanEvent::Removed { id: media_id });
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.source, EVENT_SOURCE);
+        match event.event_type {
+            EventType::LibraryScanProgress { library_id: id, progress, current_path } => {
+                assert_eq!(id, library_id);
+                assert_eq!(progress, 0.25);
+                assert_eq!(current_path, "/lib/a.mkv");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(events.recv().await.unwrap().type_name(), "media_item_removed");
+    }
+
+    #[tokio::test]
+    async fn test_failed_scan_ends() {
+        let service = service();
+        let mut events = service.subscribe();
+        let now = Utc::now();
+        // Bucket libraries cannot be opened without object storage
+        let library = LibraryModel {
+            id: Uuid::new_v4(),
+            name: "Movies".to_string(),
+            path: "s3://media/movies".to_string(),
+            library_type: "movies".to_string(),
+            scan_interval: None,
+            last_scan: None,
+            is_enabled: true,
+            created_at: now,
+            updated_at: now,
+        };
+
+        assert!(service.inner.scan(&library, CancellationToken::new()).await.is_err());
+        assert!(!service.is_scanning(library.id));
+        assert_eq!(events.recv().await.unwrap().type_name(), "library_scan_started");
+        match events.recv().await.unwrap().event_type {
+            EventType::LibraryScanFailed { library_id, error } => {
+                assert_eq!(library_id, library.id);
+                assert!(error.contains("object storage"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_plan_libraries() {
+        let now = Utc::now();
+        let library = |name: &str| LibraryModel {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            path: format!("/media/{}", name),
+            library_type: "movies".to_string(),
+            scan_interval: None,
+            last_scan: None,
+            is_enabled: true,
+            created_at: now,
+            updated_at: now,
+        };
+        let (kept, changed, disabled, removed) = (library("kept"), library("changed"), library("disabled"), library("removed"));
+        let running = [&kept, &changed, &disabled, &removed]
+            .into_iter()
+            .map(|library| (library.id, library))
+            .collect::<HashMap<_, _>>();
+
+        let added = library("added");
+        let libraries = vec![
+            // Scanning again is not a change
+            LibraryModel { last_scan: Some(now), ..kept.clone() },
+            LibraryModel { scan_interval: Some(3600), ..changed.clone() },
+            LibraryModel { is_enabled: false, ..disabled.clone() },
+            added.clone(),
+            LibraryModel { is_enabled: false, ..library("off") },
+        ];
+
+        let (mut stop, start) = plan_libraries(&running, libraries);
+        stop.sort();
+        let mut expected = vec![changed.id, disabled.id, removed.id];
+        expected.sort();
+        assert_eq!(stop, expected);
+
+        let mut started = start.iter().map(|library| library.id).collect::<Vec<_>>();
+        started.sort();
+        let mut expected = vec![changed.id, added.id];
+        expected.sort();
+        assert_eq!(started, expected);
+        assert_eq!(start.iter().find(|library| library.id == changed.id).unwrap().scan_interval, Some(3600));
+    }
+
+    #[test]
+    fn test_first_scan_delay() {
+        let now = Utc::now();
+        let hour = Duration::from_secs(3600);
+
+        assert_eq!(first_scan_delay(None, None, now), Some(Duration::ZERO));
+        assert_eq!(first_scan_delay(None, Some(hour), now), Some(Duration::ZERO));
+        assert_eq!(first_scan_delay(Some(now), None, now), None);
+
+        let last_scan = now - chrono::Duration::minutes(15);
+        assert_eq!(first_scan_delay(Some(last_scan), Some(hour), now), Some(Duration::from_secs(45 * 60)));
+
+        let overdue = now - chrono::Duration::hours(2);
+        assert_eq!(first_scan_delay(Some(overdue), Some(hour), now), Some(Duration::ZERO));
     }
 }
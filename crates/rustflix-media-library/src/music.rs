@@ -0,0 +1,498 @@
+//! Grouping of tagged music tracks into albums and artists
+//!
+//! Tracks are grouped by MusicBrainz release when tagged with one, otherwise
+//! by album title and album artist. Compilations are grouped by the folder
+//! holding them, since their tracks have different artists, and the disc
+//! folders of multi-disc albums ("CD1", "Disc 2") count as one folder.
+
+use crate::tags::{CoverArt, MusicTags};
+use chrono::Utc;
+use rustflix_core::AlbumType;
+use rustflix_database::{MusicAlbumModel, MusicArtistModel, MusicTrackLinkModel, MusicTrackModel};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Album artist of compilations
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// Albums and artists built from the tracks of the music libraries
+#[derive(Debug, Clone, Default)]
+pub struct MusicLibrary {
+    pub artists: Vec<MusicArtistModel>,
+    pub albums: Vec<MusicAlbumModel>,
+    /// Album and artist of every track
+    pub links: Vec<MusicTrackLinkModel>,
+}
+
+/// Build the track row of a music file from its tags
+pub fn track_model(media_id: Uuid, tags: &MusicTags, cover_hash: Option<String>) -> MusicTrackModel {
+    MusicTrackModel {
+        media_id,
+        title: tags.title.clone(),
+        artist: tags.artist.clone(),
+        artist_sort: tags.artist_sort.clone(),
+        album_artist: tags.album_artist.clone(),
+        album_artist_sort: tags.album_artist_sort.clone(),
+        album: tags.album.clone(),
+        track_number: tags.track_number.map(|number| number as i32),
+        track_total: tags.track_total.map(|number| number as i32),
+        disc_number: tags.disc_number.map(|number| number as i32),
+        disc_total: tags.disc_total.map(|number| number as i32),
+        year: tags.year,
+        genre: tags.genre.clone(),
+        compilation: tags.compilation,
+        release_type: tags.release_type.clone(),
+        musicbrainz_recording_id: tags.musicbrainz.recording.clone(),
+        musicbrainz_release_track_id: tags.musicbrainz.release_track.clone(),
+        musicbrainz_release_id: tags.musicbrainz.release.clone(),
+        musicbrainz_artist_id: tags.musicbrainz.artist.clone(),
+        musicbrainz_album_artist_id: tags.musicbrainz.album_artist.clone(),
+        cover_hash,
+        album_id: None,
+        artist_id: None,
+        updated_at: Utc::now(),
+    }
+}
+
+/// Content hash under which cover art is stored
+pub fn cover_hash(cover: &CoverArt) -> String {
+    blake3::hash(&cover.data).to_hex().to_string()
+}
+
+/// Group tracks, given with their paths, into albums and artists
+///
+/// Albums and artists keep the ids of the existing ones with the same group
+/// key, so that regrouping a library does not change them. Artists named
+/// without a MusicBrainz id take the one of an existing artist of that name,
+/// so that regrouping some of the tracks finds the same artists.
+pub fn group(
+    tracks: &[(PathBuf, MusicTrackModel)],
+    existing_artists: &[MusicArtistModel],
+    existing_albums: &[MusicAlbumModel],
+) -> MusicLibrary {
+    let mut tracks = tracks.iter().collect::<Vec<_>>();
+    tracks.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut artists = ArtistSet::new(&tracks, existing_artists);
+
+    // Tracks of the same album title in the same folder decide together
+    // whether they form a compilation
+    let mut buckets: HashMap<(PathBuf, String), Vec<&MusicTrackModel>> = HashMap::new();
+    let mut bucket_order = Vec::new();
+    for (path, track) in &tracks {
+        let Some(title) = album_title(track) else {
+            continue;
+        };
+        let key = (album_dir(path).to_path_buf(), title.to_lowercase());
+        if !buckets.contains_key(&key) {
+            bucket_order.push(key.clone());
+        }
+        buckets.entry(key).or_default().push(track);
+    }
+
+    let mut groups: HashMap<String, AlbumGroup> = HashMap::new();
+    let mut group_order = Vec::new();
+    for key in bucket_order {
+        let members = buckets.remove(&key).unwrap_or_default();
+        let (dir, title) = key;
+        let compilation = is_compilation(&members);
+        let album_artist = if compilation {
+            VARIOUS_ARTISTS.to_string()
+        } else {
+            most_common(members.iter().filter_map(|track| track.album_artist.as_deref().or(track.artist.as_deref())))
+                .unwrap_or_default()
+        };
+
+        let group_key = match most_common(members.iter().filter_map(|track| track.musicbrainz_release_id.as_deref())) {
+            Some(release) => format!("mb:{}", release.to_lowercase()),
+            None if compilation => format!("compilation:{}\u{1f}{}", title, dir.to_string_lossy()),
+            None => format!("album:{}\u{1f}{}", title, album_artist.to_lowercase()),
+        };
+
+        let group = groups.entry(group_key.clone()).or_insert_with(|| {
+            group_order.push(group_key.clone());
+            AlbumGroup {
+                album_artist: album_artist.clone(),
+                compilation,
+                tracks: Vec::new(),
+            }
+        });
+        group.compilation |= compilation;
+        group.tracks.extend(members);
+    }
+
+    let existing_albums = existing_albums
+        .iter()
+        .map(|album| (album.group_key.as_str(), album))
+        .collect::<HashMap<_, _>>();
+    let now = Utc::now();
+    let mut albums = Vec::new();
+    let mut album_ids: HashMap<Uuid, Uuid> = HashMap::new();
+    for group_key in group_order {
+        let Some(mut group) = groups.remove(&group_key) else {
+            continue;
+        };
+        group.tracks.sort_by_key(|track| (track.disc_number.unwrap_or(1), track.track_number.unwrap_or(0)));
+
+        let album_artist = if group.compilation { VARIOUS_ARTISTS } else { group.album_artist.as_str() };
+        let artist_id = (!album_artist.is_empty()).then(|| {
+            let sort_name = group.tracks.iter().find_map(|track| track.album_artist_sort.clone());
+            let musicbrainz_id = group.tracks.iter().find_map(|track| track.musicbrainz_album_artist_id.clone());
+            artists.get(album_artist, sort_name, musicbrainz_id)
+        });
+
+        let existing = existing_albums.get(group_key.as_str());
+        let id = existing.map_or_else(Uuid::new_v4, |album| album.id);
+        let disc_count = group
+            .tracks
+            .iter()
+            .filter_map(|track| track.disc_total.max(track.disc_number))
+            .max()
+            .unwrap_or(1)
+            .max(1);
+        let album_type = if group.compilation {
+            AlbumType::Compilation
+        } else {
+            group
+                .tracks
+                .iter()
+                .find_map(|track| track.release_type.as_deref())
+                .map_or(AlbumType::Album, AlbumType::from_release_type)
+        };
+
+        for track in &group.tracks {
+            album_ids.insert(track.media_id, id);
+        }
+        albums.push(MusicAlbumModel {
+            id,
+            group_key,
+            title: most_common(group.tracks.iter().filter_map(|track| album_title(track))).unwrap_or_default(),
+            artist_id,
+            album_type: album_type.as_str().to_string(),
+            is_compilation: group.compilation,
+            year: group.tracks.iter().filter_map(|track| track.year).min(),
+            genre: most_common(group.tracks.iter().filter_map(|track| track.genre.as_deref())),
+            disc_count,
+            track_count: group.tracks.len() as i32,
+            musicbrainz_id: group.tracks.iter().find_map(|track| track.musicbrainz_release_id.clone()),
+            cover_hash: group.tracks.iter().find_map(|track| track.cover_hash.clone()),
+            created_at: existing.map_or(now, |album| album.created_at),
+            updated_at: now,
+        });
+    }
+
+    let links = tracks
+        .iter()
+        .map(|(_, track)| MusicTrackLinkModel {
+            media_id: track.media_id,
+            album_id: album_ids.get(&track.media_id).copied(),
+            artist_id: track.artist.as_deref().map(|artist| {
+                artists.get(artist, track.artist_sort.clone(), track.musicbrainz_artist_id.clone())
+            }),
+        })
+        .collect();
+
+    MusicLibrary {
+        artists: artists.into_models(),
+        albums,
+        links,
+    }
+}
+
+/// Tracks grouped into one album
+struct AlbumGroup<'a> {
+    album_artist: String,
+    compilation: bool,
+    tracks: Vec<&'a MusicTrackModel>,
+}
+
+/// Artists found while grouping, by group key
+struct ArtistSet<'a> {
+    /// MusicBrainz id of each artist name, where any track gives one
+    musicbrainz_ids: HashMap<String, String>,
+    existing: HashMap<&'a str, &'a MusicArtistModel>,
+    artists: Vec<MusicArtistModel>,
+    index: HashMap<String, usize>,
+}
+
+impl<'a> ArtistSet<'a> {
+    fn new(tracks: &[&(PathBuf, MusicTrackModel)], existing: &'a [MusicArtistModel]) -> Self {
+        let mut musicbrainz_ids = HashMap::new();
+        for (_, track) in tracks {
+            for (name, id) in [
+                (&track.artist, &track.musicbrainz_artist_id),
+                (&track.album_artist, &track.musicbrainz_album_artist_id),
+            ] {
+                if let (Some(name), Some(id)) = (name, id) {
+                    musicbrainz_ids.entry(name.to_lowercase()).or_insert_with(|| id.to_lowercase());
+                }
+            }
+        }
+
+        for artist in existing {
+            if let Some(id) = &artist.musicbrainz_id {
+                musicbrainz_ids.entry(artist.name.to_lowercase()).or_insert_with(|| id.to_lowercase());
+            }
+        }
+
+        Self {
+            musicbrainz_ids,
+            existing: existing.iter().map(|artist| (artist.group_key.as_str(), artist)).collect(),
+            artists: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    /// Id of an artist, adding it on first use
+    fn get(&mut self, name: &str, sort_name: Option<String>, musicbrainz_id: Option<String>) -> Uuid {
+        let musicbrainz_id = musicbrainz_id
+            .map(|id| id.to_lowercase())
+            .or_else(|| self.musicbrainz_ids.get(&name.to_lowercase()).cloned());
+        let group_key = match &musicbrainz_id {
+            Some(id) => format!("mb:{}", id),
+            None => format!("name:{}", name.to_lowercase()),
+        };
+
+        if let Some(&index) = self.index.get(&group_key) {
+            let artist = &mut self.artists[index];
+            if artist.sort_name.is_none() {
+                artist.sort_name = sort_name;
+            }
+            return artist.id;
+        }
+
+        let now = Utc::now();
+        let existing = self.existing.get(group_key.as_str());
+        let artist = MusicArtistModel {
+            id: existing.map_or_else(Uuid::new_v4, |artist| artist.id),
+            group_key: group_key.clone(),
+            name: name.to_string(),
+            sort_name,
+            musicbrainz_id,
+            created_at: existing.map_or(now, |artist| artist.created_at),
+            updated_at: now,
+        };
+        let id = artist.id;
+        self.index.insert(group_key, self.artists.len());
+        self.artists.push(artist);
+        id
+    }
+
+    fn into_models(self) -> Vec<MusicArtistModel> {
+        self.artists
+    }
+}
+
+/// Lowercased album titles and MusicBrainz releases of tracks, under which
+/// the tracks they may be grouped with are found
+pub fn album_keys(tracks: &[MusicTrackModel]) -> (Vec<String>, Vec<String>) {
+    let mut titles = tracks.iter().filter_map(album_title).map(str::to_lowercase).collect::<Vec<_>>();
+    let mut releases = tracks
+        .iter()
+        .filter_map(|track| track.musicbrainz_release_id.as_deref())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    titles.sort();
+    titles.dedup();
+    releases.sort();
+    releases.dedup();
+    (titles, releases)
+}
+
+/// Album title of a track, if it has one
+fn album_title(track: &MusicTrackModel) -> Option<&str> {
+    track.album.as_deref().map(str::trim).filter(|title| !title.is_empty())
+}
+
+/// Folder holding an album, looking past disc folders such as "CD 2"
+pub(crate) fn album_dir(path: &Path) -> &Path {
+    let Some(parent) = path.parent() else {
+        return Path::new("");
+    };
+    match (parent.file_name().and_then(|name| name.to_str()), parent.parent()) {
+        (Some(name), Some(grandparent)) if is_disc_folder(name) => grandparent,
+        _ => parent,
+    }
+}
+
+/// Check whether a folder name is that of one disc of an album
+fn is_disc_folder(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["disc", "disk", "cd"].iter().any(|prefix| {
+        name.strip_prefix(prefix).is_some_and(|rest| {
+            let number = rest.trim_start_matches([' ', '_', '-', '.']);
+            !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit())
+        })
+    })
+}
+
+/// Check whether tracks of one album form a various-artists compilation
+fn is_compilation(tracks: &[&MusicTrackModel]) -> bool {
+    if tracks.iter().any(|track| track.compilation) {
+        return true;
+    }
+    let album_artists = tracks.iter().filter_map(|track| track.album_artist.as_deref()).collect::<Vec<_>>();
+    if album_artists.iter().any(|artist| is_various(artist)) {
+        return true;
+    }
+    if !album_artists.is_empty() {
+        return false;
+    }
+
+    let mut artists = tracks.iter().filter_map(|track| track.artist.as_deref()).map(str::to_lowercase);
+    let first = artists.next();
+    artists.any(|artist| Some(&artist) != first.as_ref())
+}
+
+fn is_various(artist: &str) -> bool {
+    matches!(artist.trim().to_lowercase().as_str(), "various artists" | "various" | "va")
+}
+
+/// Most frequent value, the first seen winning ties
+pub(crate) fn most_common<'a>(values: impl Iterator<Item = &'a str>) -> Option<String> {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for value in values.map(str::trim).filter(|value| !value.is_empty()) {
+        match counts.iter_mut().find(|(seen, _)| *seen == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    let max = counts.iter().map(|(_, count)| *count).max()?;
+    counts.into_iter().find(|(_, count)| *count == max).map(|(value, _)| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(path: &str, artist: &str, album_artist: Option<&str>, album: &str) -> (PathBuf, MusicTrackModel) {
+        let tags = MusicTags {
+            title: Some("Song".to_string()),
+            artist: Some(artist.to_string()),
+            album_artist: album_artist.map(str::to_string),
+            album: Some(album.to_string()),
+            ..Default::default()
+        };
+        (PathBuf::from(path), track_model(Uuid::new_v4(), &tags, None))
+    }
+
+    fn album(library: &MusicLibrary, media_id: Uuid) -> &MusicAlbumModel {
+        let link = library.links.iter().find(|link| link.media_id == media_id).unwrap();
+        library.albums.iter().find(|album| Some(album.id) == link.album_id).unwrap()
+    }
+
+    #[test]
+    fn test_group_compilation() {
+        let tracks = vec![
+            track("/music/Hits/01.mp3", "First", None, "Hits"),
+            track("/music/Hits/02.mp3", "Second", None, "Hits"),
+            track("/music/Other Hits/01.mp3", "First", None, "Hits"),
+            track("/music/Other Hits/02.mp3", "Second", None, "Hits"),
+            track("/music/Band/Album/01.mp3", "Band feat. Guest", Some("Band"), "Album"),
+            track("/music/Band/Album/02.mp3", "Band", Some("Band"), "Album"),
+        ];
+        let library = group(&tracks, &[], &[]);
+
+        assert_eq!(library.albums.len(), 3);
+        let hits = album(&library, tracks[0].1.media_id);
+        assert!(hits.is_compilation);
+        assert_eq!(hits.album_type, "compilation");
+        assert_eq!(hits.track_count, 2);
+        assert_eq!(album(&library, tracks[1].1.media_id).id, hits.id);
+        assert_ne!(album(&library, tracks[2].1.media_id).id, hits.id);
+        let various = library.artists.iter().find(|artist| Some(artist.id) == hits.artist_id).unwrap();
+        assert_eq!(various.name, VARIOUS_ARTISTS);
+
+        let band = album(&library, tracks[4].1.media_id);
+        assert!(!band.is_compilation);
+        assert_eq!(album(&library, tracks[5].1.media_id).id, band.id);
+        let band_artist = library.artists.iter().find(|artist| Some(artist.id) == band.artist_id).unwrap();
+        assert_eq!(band_artist.name, "Band");
+        assert!(library.artists.iter().any(|artist| artist.name == "Band feat. Guest"));
+    }
+
+    #[test]
+    fn test_group_multi_disc() {
+        let mut tracks = vec![
+            track("/music/Band/Album/CD1/01.flac", "Band", None, "Album"),
+            track("/music/Band/Album/CD1/02.flac", "Band", None, "Album"),
+            track("/music/Band/Album/Disc 2/01.flac", "Band", None, "Album"),
+            track("/music/Band/Album (Bonus)/01.flac", "Band", Some("Band"), "Album"),
+        ];
+        tracks[0].1.disc_number = Some(1);
+        tracks[2].1.disc_number = Some(2);
+        tracks[2].1.year = Some(2001);
+        tracks[3].1.disc_number = Some(3);
+        tracks[3].1.disc_total = Some(3);
+        tracks[0].1.year = Some(1999);
+        tracks[1].1.cover_hash = Some("cover".to_string());
+
+        let library = group(&tracks, &[], &[]);
+        assert_eq!(library.albums.len(), 1);
+        let album = &library.albums[0];
+        assert_eq!(album.track_count, 4);
+        assert_eq!(album.disc_count, 3);
+        assert_eq!(album.year, Some(1999));
+        assert_eq!(album.cover_hash.as_deref(), Some("cover"));
+        assert!(!album.is_compilation);
+        assert_eq!(library.artists.len(), 1);
+    }
+
+    #[test]
+    fn test_group_musicbrainz_and_existing_ids() {
+        let mut tracks = vec![
+            track("/music/a/01.mp3", "Band", None, "Album"),
+            track("/music/b/01.mp3", "Band", None, "Album (Remaster)"),
+            track("/music/c/01.mp3", "Untagged", None, ""),
+        ];
+        for (_, track) in &mut tracks[..2] {
+            track.musicbrainz_release_id = Some("RELEASE".to_string());
+            track.musicbrainz_artist_id = Some("artist".to_string());
+        }
+
+        let first = group(&tracks, &[], &[]);
+        assert_eq!(first.albums.len(), 1);
+        assert_eq!(first.albums[0].group_key, "mb:release");
+        assert_eq!(first.albums[0].musicbrainz_id.as_deref(), Some("RELEASE"));
+        assert!(first.artists.iter().any(|artist| artist.group_key == "mb:artist"));
+        assert_eq!(first.links[2].album_id, None);
+
+        let second = group(&tracks, &first.artists, &first.albums);
+        assert_eq!(second.albums[0].id, first.albums[0].id);
+        assert_eq!(second.artists[0].id, first.artists[0].id);
+    }
+
+    #[test]
+    fn test_group_changed_tracks() {
+        let mut tracks = vec![
+            track("/music/a/01.mp3", "Band", None, "Album"),
+            track("/music/a/02.mp3", "Band", None, "Album"),
+            track("/music/b/01.mp3", "Band", None, " album "),
+        ];
+        tracks[0].1.musicbrainz_artist_id = Some("artist".to_string());
+        let full = group(&tracks, &[], &[]);
+
+        // The track whose tags changed is found with the album it now shares a
+        // title with, and keeps the artist known from the rest of the library
+        tracks[2].1.musicbrainz_release_id = Some("Release".to_string());
+        let (titles, releases) = album_keys(&[tracks[2].1.clone()]);
+        assert_eq!((titles, releases), (vec!["album".to_string()], vec!["release".to_string()]));
+
+        let changed = group(&tracks[1..], &full.artists, &full.albums);
+        assert_eq!(changed.artists.len(), 1);
+        assert_eq!(changed.artists[0].id, full.artists[0].id);
+        assert_eq!(changed.links[0].artist_id, Some(full.artists[0].id));
+        assert_eq!(album(&changed, tracks[1].1.media_id).id, album(&full, tracks[1].1.media_id).id);
+        assert_eq!(album(&changed, tracks[2].1.media_id).group_key, "mb:release");
+    }
+
+    #[test]
+    fn test_is_disc_folder() {
+        assert!(is_disc_folder("CD1"));
+        assert!(is_disc_folder("Disc 2"));
+        assert!(is_disc_folder("disk_03"));
+        assert!(!is_disc_folder("Discography"));
+        assert!(!is_disc_folder("CD"));
+    }
+}
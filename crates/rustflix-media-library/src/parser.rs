@@ -0,0 +1,930 @@
+//! Filename and folder-structure parsing for movies and TV episodes
+//!
+//! Release names are split into tokens and scanned for the markers that end
+//! the title: episode numbers (`S01E02`, `1x02`, `- 012`), air dates, years
+//! and release tags such as resolution, source and edition.
+
+use chrono::NaiveDate;
+use rustflix_core::MediaType;
+use std::path::Path;
+
+/// Information extracted from a media file name and its parent folders
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedName {
+    /// Movie or show title
+    pub title: String,
+    /// Release year of the movie or show
+    pub year: Option<i32>,
+    /// Season number
+    pub season: Option<u32>,
+    /// Episode numbers within the season (several for multi-episode files)
+    pub episodes: Vec<u32>,
+    /// Absolute episode number for shows without seasons (e.g. anime)
+    pub absolute_episode: Option<u32>,
+    /// Air date for date-based shows
+    pub air_date: Option<NaiveDate>,
+    /// Episode title following the episode marker
+    pub episode_title: Option<String>,
+    /// Normalized resolution tag, e.g. "1080p"
+    pub resolution: Option<String>,
+    /// Normalized source tag, e.g. "BluRay" or "WEB-DL"
+    pub source: Option<String>,
+    /// Edition, e.g. "Director's Cut"
+    pub edition: Option<String>,
+}
+
+/// Query used to look up parsed media with metadata providers
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub title: String,
+    pub year: Option<i32>,
+    pub media_type: MediaType,
+}
+
+impl ParsedName {
+    /// Check whether the name describes a TV episode
+    pub fn is_episode(&self) -> bool {
+        self.season.is_some()
+            || !self.episodes.is_empty()
+            || self.absolute_episode.is_some()
+            || self.air_date.is_some()
+    }
+
+    /// Media type implied by the name
+    pub fn media_type(&self) -> MediaType {
+        if self.is_episode() {
+            MediaType::Episode
+        } else {
+            MediaType::Movie
+        }
+    }
+
+    /// Build the metadata provider query; episodes search for their show
+    pub fn search_query(&self) -> SearchQuery {
+        SearchQuery {
+            title: self.title.clone(),
+            year: self.year,
+            media_type: if self.is_episode() {
+                MediaType::TvShow
+            } else {
+                MediaType::Movie
+            },
+        }
+    }
+
+    fn merge_tags(&mut self, other: &ParsedName) {
+        if self.resolution.is_none() {
+            self.resolution = other.resolution.clone();
+        }
+        if self.source.is_none() {
+            self.source = other.source.clone();
+        }
+        if self.edition.is_none() {
+            self.edition = other.edition.clone();
+        }
+    }
+}
+
+/// Parse a media file path, using parent folders to fill in missing details
+///
+/// Handles the common `Show (Year)/Season 01/...` and `Movie (Year)/...`
+/// layouts as well as bare scene release names.
+pub fn parse_path(path: &Path) -> ParsedName {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let mut parsed = parse_name(stem);
+
+    let parent = path.parent().and_then(folder_name);
+    let folder_season = parent.and_then(season_folder);
+
+    if let Some(season) = folder_season {
+        if parsed.season.is_none() {
+            parsed.season = Some(season);
+            // Absolute numbers inside a season folder are regular episode numbers
+            if parsed.episodes.is_empty() {
+                if let Some(episode) = parsed.absolute_episode.take() {
+                    parsed.episodes.push(episode);
+                }
+            }
+        }
+        if parsed.episodes.is_empty() && parsed.air_date.is_none() {
+            if let Some((episode, episode_title)) = leading_episode(stem) {
+                parsed.episodes.push(episode);
+                parsed.episode_title = episode_title;
+            }
+        }
+
+        let show = path
+            .parent()
+            .and_then(Path::parent)
+            .and_then(folder_name)
+            .map(parse_name)
+            .filter(|show| !show.title.is_empty());
+        if let Some(show) = show {
+            parsed.title = show.title;
+            parsed.year = show.year.or(parsed.year);
+        }
+    } else if let Some(folder) = parent.map(parse_name) {
+        let use_folder = if parsed.title.is_empty() {
+            true
+        } else {
+            // Scene folders often carry the year while the file inside does not
+            !parsed.is_episode()
+                && parsed.year.is_none()
+                && folder.year.is_some()
+                && !folder.is_episode()
+        };
+        if use_folder && !folder.title.is_empty() {
+            parsed.title = folder.title.clone();
+            parsed.year = parsed.year.or(folder.year);
+            parsed.merge_tags(&folder);
+        }
+    }
+
+    parsed
+}
+
+/// Parse a single file or folder name (without extension)
+pub fn parse_name(name: &str) -> ParsedName {
+    let (name, tagged_edition) = take_edition_tag(name);
+    let tokens = tokenize(&name);
+    let mut parsed = ParsedName::default();
+
+    let strong = tokens
+        .iter()
+        .position(|token| is_strong_tag(&token.lower))
+        .unwrap_or(tokens.len());
+
+    let mut episode_at = None;
+    for index in 0..strong {
+        if tokens[index].bracketed {
+            continue;
+        }
+        if let Some((marker, len)) = match_episode(&tokens, index) {
+            marker.apply(&mut parsed);
+            episode_at = Some((index, len));
+            break;
+        }
+    }
+
+    let boundary = episode_at.map(|(index, _)| index).unwrap_or(strong);
+    let year_at = (1..boundary)
+        .rev()
+        .find(|&index| !tokens[index].bracketed && parse_year(&tokens[index].lower).is_some());
+    let title_end = match year_at {
+        Some(index) => index,
+        None => (1..boundary)
+            .find(|&index| ends_title(&tokens, index))
+            .unwrap_or(boundary),
+    };
+
+    parsed.year = year_at.and_then(|index| parse_year(&tokens[index].lower));
+    parsed.title = join_tokens(&tokens[..title_end]);
+
+    if let Some((index, len)) = episode_at {
+        let start = index + len;
+        let end = (start..tokens.len())
+            .find(|&index| is_strong_tag(&tokens[index].lower) || ends_title(&tokens, index))
+            .unwrap_or(tokens.len());
+        let episode_title = join_tokens(&tokens[start..end]);
+        if !episode_title.is_empty() {
+            parsed.episode_title = Some(episode_title);
+        }
+    }
+
+    let mut index = title_end;
+    while index < tokens.len() {
+        let token = &tokens[index].lower;
+        if parsed.resolution.is_none() {
+            parsed.resolution = parse_resolution(token);
+        }
+        if parsed.source.is_none() {
+            parsed.source = parse_source(token).map(|(source, _)| source.to_string());
+        }
+        if parsed.edition.is_none() {
+            if let Some((edition, len)) = match_edition(&tokens, index) {
+                parsed.edition = Some(edition.name.to_string());
+                index += len;
+                continue;
+            }
+        }
+        index += 1;
+    }
+
+    if tagged_edition.is_some() {
+        parsed.edition = tagged_edition;
+    }
+
+    parsed
+}
+
+/// Parse a season folder name such as "Season 01", "S2" or "Specials"
+pub fn season_folder(name: &str) -> Option<u32> {
+    let tokens = tokenize(name);
+    let first = &tokens.first()?.lower;
+
+    if first == "specials" {
+        return Some(0);
+    }
+    if let Some(season) = first.strip_prefix('s').and_then(|rest| parse_number(rest, 4)) {
+        return (tokens.len() == 1).then_some(season);
+    }
+    if SEASON_WORDS.contains(&first.as_str()) {
+        return tokens.get(1).and_then(|token| parse_number(&token.lower, 4));
+    }
+    None
+}
+
+const SEASON_WORDS: &[&str] = &["season", "series", "saison", "staffel", "temporada"];
+
+/// A token of a release name
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    lower: String,
+    /// Inside `[...]` or `{...}`
+    bracketed: bool,
+}
+
+impl Token {
+    fn is_dash(&self) -> bool {
+        self.text == "-"
+    }
+}
+
+/// Split a name into tokens on spaces, dots, underscores and brackets
+///
+/// Leading bracketed groups (release groups) are dropped. When the name uses
+/// spaces, a dot directly before a space is kept as part of the word so that
+/// titles like "Mr. Robot" survive.
+fn tokenize(name: &str) -> Vec<Token> {
+    let spaced = name.contains(' ');
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+
+    let flush = |current: &mut String, tokens: &mut Vec<Token>, bracketed: bool| {
+        if current.is_empty() {
+            return;
+        }
+        let trimmed = current.trim_matches('-');
+        let text = if trimmed.is_empty() { "-" } else { trimmed };
+        tokens.push(Token {
+            text: text.to_string(),
+            lower: text.to_lowercase(),
+            bracketed,
+        });
+        current.clear();
+    };
+
+    for (index, c) in chars.iter().enumerate() {
+        match c {
+            '[' | '{' => {
+                flush(&mut current, &mut tokens, depth > 0);
+                depth += 1;
+            }
+            ']' | '}' => {
+                flush(&mut current, &mut tokens, depth > 0);
+                depth = depth.saturating_sub(1);
+            }
+            '(' | ')' | ' ' | '_' | ',' => flush(&mut current, &mut tokens, depth > 0),
+            '.' => {
+                let before_space = chars.get(index + 1) == Some(&' ');
+                if spaced && before_space && current.chars().all(char::is_alphabetic) && !current.is_empty() {
+                    current.push('.');
+                }
+                flush(&mut current, &mut tokens, depth > 0);
+            }
+            _ => current.push(*c),
+        }
+    }
+    flush(&mut current, &mut tokens, depth > 0);
+
+    let leading = tokens.iter().take_while(|token| token.bracketed).count();
+    if leading < tokens.len() {
+        tokens.drain(..leading);
+    }
+    tokens
+}
+
+/// Extract a Plex-style `{edition-Name}` tag
+fn take_edition_tag(name: &str) -> (String, Option<String>) {
+    let lower = name.to_ascii_lowercase();
+    let Some(start) = lower.find("{edition-") else {
+        return (name.to_string(), None);
+    };
+    let Some(len) = name[start..].find('}') else {
+        return (name.to_string(), None);
+    };
+
+    let edition = name[start + "{edition-".len()..start + len].trim().to_string();
+    let rest = format!("{}{}", &name[..start], &name[start + len + 1..]);
+    (rest, (!edition.is_empty()).then_some(edition))
+}
+
+/// Join title tokens, dropping separators and bracketed tokens
+fn join_tokens(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .filter(|token| !token.is_dash() && !token.bracketed)
+        .map(|token| token.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse a number made of at most `max_digits` ASCII digits
+fn parse_number(value: &str, max_digits: usize) -> Option<u32> {
+    if value.is_empty() || value.len() > max_digits || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Split leading digits off a string
+fn split_number(value: &str, max_digits: usize) -> Option<(u32, &str)> {
+    let len = value.bytes().take_while(u8::is_ascii_digit).count();
+    Some((parse_number(&value[..len], max_digits)?, &value[len..]))
+}
+
+fn parse_year(value: &str) -> Option<i32> {
+    let year = parse_number(value, 4)? as i32;
+    (value.len() == 4 && (1900..=2099).contains(&year)).then_some(year)
+}
+
+/// An episode or date marker found in a name
+#[derive(Debug, Clone, PartialEq)]
+enum Marker {
+    Episode { season: Option<u32>, episodes: Vec<u32> },
+    Absolute(u32),
+    Date(NaiveDate),
+}
+
+impl Marker {
+    fn apply(self, parsed: &mut ParsedName) {
+        match self {
+            Marker::Episode { season, episodes } => {
+                parsed.season = season;
+                if season.is_none() && episodes.len() == 1 {
+                    parsed.absolute_episode = episodes.first().copied();
+                } else {
+                    parsed.episodes = episodes;
+                }
+            }
+            Marker::Absolute(episode) => parsed.absolute_episode = Some(episode),
+            Marker::Date(date) => parsed.air_date = Some(date),
+        }
+    }
+}
+
+/// Match an episode or date marker starting at `index`, returning its token length
+fn match_episode(tokens: &[Token], index: usize) -> Option<(Marker, usize)> {
+    let token = &tokens[index].lower;
+    let next = tokens.get(index + 1).map(|token| token.lower.as_str());
+
+    // S01E02, S01E02E03, S01E02-E03, S01 E02
+    if let Some((season, episodes)) = parse_season_episode(token) {
+        if episodes.is_empty() {
+            if let Some(episode) = next.and_then(parse_episode_token) {
+                return Some((episode_marker(Some(season), vec![episode]), 2));
+            }
+        }
+        return Some((episode_marker(Some(season), episodes), 1));
+    }
+
+    // 1x02, 1x02-03
+    if let Some((season, episodes)) = parse_cross(token) {
+        return Some((episode_marker(Some(season), episodes), 1));
+    }
+
+    // Season 1, Season 1 Episode 2
+    if SEASON_WORDS.contains(&token.as_str()) {
+        let season = next.and_then(|next| parse_number(next, 4))?;
+        let episode = match tokens.get(index + 2).map(|token| token.lower.as_str()) {
+            Some("episode" | "ep" | "e") => tokens
+                .get(index + 3)
+                .and_then(|token| parse_number(&token.lower, 4))
+                .map(|episode| (episode, 4)),
+            Some(token) => parse_episode_token(token).map(|episode| (episode, 3)),
+            None => None,
+        };
+        return Some(match episode {
+            Some((episode, len)) => (episode_marker(Some(season), vec![episode]), len),
+            None => (episode_marker(Some(season), Vec::new()), 2),
+        });
+    }
+
+    // Episode 12, E12, EP12
+    if token == "episode" || token == "ep" {
+        let episode = next.and_then(|next| parse_number(next, 4))?;
+        return Some((Marker::Absolute(episode), 2));
+    }
+    if let Some(episode) = parse_episode_token(token) {
+        return Some((Marker::Absolute(episode), 1));
+    }
+
+    // Air dates: 2024-03-15 or 2024.03.15
+    if let Some(date) = parse_date_token(token) {
+        return Some((Marker::Date(date), 1));
+    }
+    if parse_year(token).is_some() {
+        let month = next.filter(|value| value.len() == 2).and_then(|value| parse_number(value, 2));
+        let day = tokens
+            .get(index + 2)
+            .map(|token| token.lower.as_str())
+            .filter(|value| value.len() == 2)
+            .and_then(|value| parse_number(value, 2));
+        if let (Some(month), Some(day)) = (month, day) {
+            if let Some(date) = NaiveDate::from_ymd_opt(token.parse().ok()?, month, day) {
+                return Some((Marker::Date(date), 3));
+            }
+        }
+    }
+
+    // Absolute numbering after a separator: "Show - 012", "Show - 12v2"
+    if index > 0 && tokens[index].is_dash() {
+        let next = next?;
+        let number = next.split_once('v').map(|(number, _)| number).unwrap_or(next);
+        if parse_year(number).is_none() {
+            if let Some(episode) = parse_number(number, 4) {
+                return Some((Marker::Absolute(episode), 2));
+            }
+        }
+    }
+
+    None
+}
+
+fn episode_marker(season: Option<u32>, episodes: Vec<u32>) -> Marker {
+    Marker::Episode { season, episodes }
+}
+
+/// Parse `s01e02`-style tokens, returning the season and episodes
+fn parse_season_episode(token: &str) -> Option<(u32, Vec<u32>)> {
+    let (season, rest) = split_number(token.strip_prefix('s')?, 4)?;
+    let episodes = parse_episode_list(rest, &["ep", "e"])?;
+    Some((season, episodes))
+}
+
+/// Parse `1x02`-style tokens, returning the season and episodes
+fn parse_cross(token: &str) -> Option<(u32, Vec<u32>)> {
+    let (season, rest) = split_number(token, 2)?;
+    if !rest.starts_with('x') {
+        return None;
+    }
+    let episodes = parse_episode_list(rest, &["x"])?;
+    Some((season, episodes))
+}
+
+/// Parse a run of episode numbers such as `e02e03`, `e02-e05` or `-05`
+///
+/// A dash denotes a range, which is expanded.
+fn parse_episode_list(mut rest: &str, prefixes: &[&str]) -> Option<Vec<u32>> {
+    let mut episodes: Vec<u32> = Vec::new();
+
+    while !rest.is_empty() {
+        let (is_range, after_dash) = match rest.strip_prefix('-') {
+            Some(after) => (true, after),
+            None => (false, rest),
+        };
+        let unprefixed = prefixes.iter().find_map(|prefix| after_dash.strip_prefix(prefix));
+        let digits = match unprefixed {
+            Some(digits) => digits,
+            None if is_range && !episodes.is_empty() => after_dash,
+            None => return None,
+        };
+        let (episode, tail) = split_number(digits, 4)?;
+
+        match episodes.last() {
+            Some(&last) if is_range && episode > last && episode - last <= 50 => {
+                episodes.extend(last + 1..=episode);
+            }
+            _ => episodes.push(episode),
+        }
+        rest = tail;
+    }
+
+    Some(episodes)
+}
+
+/// Parse a standalone episode token such as `e12` or `ep12`
+fn parse_episode_token(token: &str) -> Option<u32> {
+    let digits = token
+        .strip_prefix("ep")
+        .or_else(|| token.strip_prefix('e'))?;
+    parse_number(digits, 4)
+}
+
+fn parse_date_token(token: &str) -> Option<NaiveDate> {
+    let mut parts = token.split('-');
+    let year = parse_year(parts.next()?)?;
+    let month = parts.next().filter(|part| part.len() == 2)?;
+    let day = parts.next().filter(|part| part.len() == 2)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, parse_number(month, 2)?, parse_number(day, 2)?)
+}
+
+/// Leading episode number of a file inside a season folder, e.g. "05 - Pilot"
+fn leading_episode(stem: &str) -> Option<(u32, Option<String>)> {
+    let tokens = tokenize(stem);
+    let episode = parse_number(&tokens.first()?.lower, 3)?;
+
+    let end = (1..tokens.len())
+        .find(|&index| is_strong_tag(&tokens[index].lower) || ends_title(&tokens, index))
+        .unwrap_or(tokens.len());
+    let title = join_tokens(&tokens[1..end]);
+    Some((episode, (!title.is_empty()).then_some(title)))
+}
+
+fn folder_name(path: &Path) -> Option<&str> {
+    path.file_name().and_then(|name| name.to_str())
+}
+
+/// Tokens that never appear in titles and always end one
+fn is_strong_tag(token: &str) -> bool {
+    if parse_resolution(token).is_some() {
+        return true;
+    }
+    if let Some((_, strong)) = parse_source(token) {
+        return strong;
+    }
+
+    // Codecs often carry the release group, e.g. "x264-GROUP"
+    let token = token.split('-').next().unwrap_or(token);
+    if CODEC_TAGS.contains(&token) {
+        return true;
+    }
+    AUDIO_TAGS.iter().any(|prefix| {
+        token
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit() || c == '+'))
+    })
+}
+
+/// Tokens that end a title when no year marks the boundary
+fn ends_title(tokens: &[Token], index: usize) -> bool {
+    let token = &tokens[index];
+    token.bracketed
+        || WEAK_TAGS.contains(&token.lower.as_str())
+        || parse_source(&token.lower).is_some()
+        || match_edition(tokens, index).is_some_and(|(edition, _)| edition.ends_title)
+}
+
+const CODEC_TAGS: &[&str] = &[
+    "x264", "x265", "h264", "h265", "hevc", "avc", "xvid", "divx", "av1", "vp9", "8bit",
+    "10bit", "12bit", "hdr", "hdr10", "hdr10+", "dovi", "remux",
+];
+
+const AUDIO_TAGS: &[&str] = &[
+    "aac", "ac3", "eac3", "ddp", "dd", "dts", "truehd", "atmos", "flac", "opus", "mp3",
+];
+
+const WEAK_TAGS: &[&str] = &[
+    "proper", "repack", "rerip", "internal", "limited", "multi", "dubbed", "subbed",
+    "complete", "nf", "amzn", "dsnp", "hmax", "atvp", "hulu",
+];
+
+fn parse_resolution(token: &str) -> Option<String> {
+    const HEIGHTS: &[u32] = &[240, 360, 480, 540, 576, 720, 1080, 1440, 2160, 4320];
+
+    match token {
+        "4k" | "uhd" => return Some("2160p".to_string()),
+        "8k" => return Some("4320p".to_string()),
+        _ => {}
+    }
+
+    if let Some(height) = token.strip_suffix('p').and_then(|value| parse_number(value, 4)) {
+        return HEIGHTS.contains(&height).then(|| format!("{}p", height));
+    }
+    if let Some(height) = token.strip_suffix('i').and_then(|value| parse_number(value, 4)) {
+        return HEIGHTS.contains(&height).then(|| format!("{}i", height));
+    }
+
+    // Dimensions such as 1920x1080; classified by width to cope with cropping
+    let (width, height) = token.split_once('x')?;
+    let width = parse_number(width, 4).filter(|width| *width >= 320)?;
+    parse_number(height, 4)?;
+    let height = match width {
+        3800.. => 2160,
+        1900.. => 1080,
+        1200.. => 720,
+        700.. => 480,
+        _ => 360,
+    };
+    Some(format!("{}p", height))
+}
+
+/// Normalized source name and whether the token is unambiguous
+fn parse_source(token: &str) -> Option<(&'static str, bool)> {
+    let source = match token {
+        "bluray" | "blu-ray" | "bdrip" | "brrip" | "bdremux" | "bd25" | "bd50" => ("BluRay", true),
+        "web-dl" | "webdl" => ("WEB-DL", true),
+        "webrip" | "web-rip" => ("WEBRip", true),
+        "web" => ("WEB", false),
+        "hdtv" => ("HDTV", true),
+        "pdtv" | "sdtv" | "dsr" | "dsrip" => ("SDTV", true),
+        "dvdrip" | "dvd5" | "dvd9" | "dvdr" | "dvd-r" => ("DVD", true),
+        "dvd" => ("DVD", false),
+        "hdrip" => ("HDRip", true),
+        "hdcam" | "camrip" => ("CAM", true),
+        "cam" => ("CAM", false),
+        "hdts" | "telesync" => ("Telesync", true),
+        "hdtc" | "telecine" => ("Telecine", true),
+        "dvdscr" | "bdscr" | "screener" => ("Screener", true),
+        "vhsrip" => ("VHS", true),
+        _ => return None,
+    };
+    Some(source)
+}
+
+/// A known edition phrase
+#[derive(Debug)]
+struct Edition {
+    words: &'static [&'static str],
+    name: &'static str,
+    /// Whether the phrase is unlikely to be part of a title
+    ends_title: bool,
+}
+
+const EDITIONS: &[Edition] = &[
+    Edition { words: &["directors", "cut"], name: "Director's Cut", ends_title: true },
+    Edition { words: &["director's", "cut"], name: "Director's Cut", ends_title: true },
+    Edition { words: &["extended", "cut"], name: "Extended", ends_title: true },
+    Edition { words: &["extended", "edition"], name: "Extended", ends_title: true },
+    Edition { words: &["extended"], name: "Extended", ends_title: true },
+    Edition { words: &["theatrical", "cut"], name: "Theatrical", ends_title: true },
+    Edition { words: &["theatrical"], name: "Theatrical", ends_title: true },
+    Edition { words: &["special", "edition"], name: "Special Edition", ends_title: true },
+    Edition { words: &["ultimate", "edition"], name: "Ultimate Edition", ends_title: true },
+    Edition { words: &["ultimate", "cut"], name: "Ultimate Cut", ends_title: true },
+    Edition { words: &["collectors", "edition"], name: "Collector's Edition", ends_title: true },
+    Edition { words: &["collector's", "edition"], name: "Collector's Edition", ends_title: true },
+    Edition { words: &["anniversary", "edition"], name: "Anniversary Edition", ends_title: true },
+    Edition { words: &["unrated"], name: "Unrated", ends_title: true },
+    Edition { words: &["uncut"], name: "Uncut", ends_title: true },
+    Edition { words: &["remastered"], name: "Remastered", ends_title: true },
+    Edition { words: &["imax"], name: "IMAX", ends_title: true },
+    Edition { words: &["criterion"], name: "Criterion", ends_title: true },
+    Edition { words: &["final", "cut"], name: "Final Cut", ends_title: false },
+    Edition { words: &["open", "matte"], name: "Open Matte", ends_title: false },
+];
+
+fn match_edition(tokens: &[Token], index: usize) -> Option<(&'static Edition, usize)> {
+    EDITIONS
+        .iter()
+        .find(|edition| {
+            edition.words.iter().enumerate().all(|(offset, word)| {
+                tokens
+                    .get(index + offset)
+                    .is_some_and(|token| token.lower == *word)
+            })
+        })
+        .map(|edition| (edition, edition.words.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Name, resolution, source and edition
+    type TagCase = (&'static str, Option<&'static str>, Option<&'static str>, Option<&'static str>);
+    /// Path, title, year, season and episodes
+    type PathCase = (&'static str, &'static str, Option<i32>, Option<u32>, &'static [u32]);
+
+    fn date(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+
+    #[test]
+    fn test_movie_names() {
+        let cases: &[(&str, &str, Option<i32>)] = &[
+            ("The.Matrix.1999.1080p.BluRay.x264-SPARKS", "The Matrix", Some(1999)),
+            ("The Matrix (1999)", "The Matrix", Some(1999)),
+            ("Inception.2010.720p.BrRip.x264.YIFY", "Inception", Some(2010)),
+            ("Blade.Runner.2049.2017.2160p.UHD.BluRay.x265-TERMiNAL", "Blade Runner 2049", Some(2017)),
+            ("2001.A.Space.Odyssey.1968.1080p.BluRay.x264", "2001 A Space Odyssey", Some(1968)),
+            ("1917.2019.1080p.WEB-DL.DD5.1.H264-FGT", "1917", Some(2019)),
+            ("1917 (2019)", "1917", Some(2019)),
+            ("2012", "2012", None),
+            ("Alien", "Alien", None),
+            ("Mad.Max.Fury.Road.2015.1080p.BluRay.DTS.x264-HDMaNiAcS", "Mad Max Fury Road", Some(2015)),
+            ("Spider-Man.No.Way.Home.2021.1080p.WEBRip.x265-RARBG", "Spider-Man No Way Home", Some(2021)),
+            ("Mr. Nobody (2009) [1080p]", "Mr. Nobody", Some(2009)),
+            ("Amélie (2001)", "Amélie", Some(2001)),
+            ("The_Godfather_1972_DVDRip_XviD", "The Godfather", Some(1972)),
+            ("[YTS.MX] Parasite (2019) [1080p]", "Parasite", Some(2019)),
+            ("Parasite (2019) {tmdb-496243}", "Parasite", Some(2019)),
+            ("Charlotte's Web 2006 720p", "Charlotte's Web", Some(2006)),
+            ("Ocean's Eleven 2001", "Ocean's Eleven", Some(2001)),
+            ("Dune Part Two 2024 2160p WEB-DL DDP5 1 Atmos DV HDR H 265-FLUX", "Dune Part Two", Some(2024)),
+            ("Oppenheimer.2023.IMAX.2160p.WEB-DL", "Oppenheimer", Some(2023)),
+            ("Aliens.Special.Edition.1080p.BluRay", "Aliens", None),
+            ("The.Final.Cut.2004.DVDRip", "The Final Cut", Some(2004)),
+            ("Se7en.1995.REMASTERED.1080p.BluRay", "Se7en", Some(1995)),
+            ("WALL-E.2008.720p.BluRay.x264", "WALL-E", Some(2008)),
+            ("Avengers Endgame 2019 HDCAM", "Avengers Endgame", Some(2019)),
+            ("Movie.Title.PROPER.720p.HDTV", "Movie Title", None),
+            ("Star Wars - Episode IV - A New Hope (1977)", "Star Wars Episode IV A New Hope", Some(1977)),
+        ];
+
+        for (name, title, year) in cases {
+            let parsed = parse_name(name);
+            assert_eq!(parsed.title, *title, "title of {}", name);
+            assert_eq!(parsed.year, *year, "year of {}", name);
+            assert_eq!(parsed.media_type(), MediaType::Movie, "type of {}", name);
+        }
+    }
+
+    #[test]
+    fn test_episode_names() {
+        let cases: &[(&str, &str, Option<u32>, &[u32])] = &[
+            ("Breaking.Bad.S01E02.720p.HDTV.x264", "Breaking Bad", Some(1), &[2]),
+            ("breaking.bad.s05e16.1080p.web.h264", "breaking bad", Some(5), &[16]),
+            ("Game of Thrones - S08E03 - The Long Night", "Game of Thrones", Some(8), &[3]),
+            ("The.Office.US.S02E01E02.DVDRip", "The Office US", Some(2), &[1, 2]),
+            ("Friends.S01E01-E03.1080p.BluRay", "Friends", Some(1), &[1, 2, 3]),
+            ("Friends.S01E16-17.720p", "Friends", Some(1), &[16, 17]),
+            ("Doctor.Who.2005.S13E01.1080p.HDTV", "Doctor Who", Some(13), &[1]),
+            ("Lost.1x02.Pilot.Part.2", "Lost", Some(1), &[2]),
+            ("lost.4x12x13.hdtv", "lost", Some(4), &[12, 13]),
+            ("Seinfeld 3x05-06", "Seinfeld", Some(3), &[5, 6]),
+            ("Show Name S01 E05", "Show Name", Some(1), &[5]),
+            ("Show.Name.Season.2.Episode.4", "Show Name", Some(2), &[4]),
+            ("Show Name Season 3 E07", "Show Name", Some(3), &[7]),
+            ("Mr. Robot S01E01 eps1.0_hellofriend.mov", "Mr. Robot", Some(1), &[1]),
+            ("S01E02", "", Some(1), &[2]),
+            ("The.Expanse.S06.1080p.AMZN.WEB-DL", "The Expanse", Some(6), &[]),
+            ("Top.Gear.S22E01.720p.HDTV.x264-FTP", "Top Gear", Some(22), &[1]),
+            ("The Simpsons S1989E01", "The Simpsons", Some(1989), &[1]),
+            ("Shogun.2024.S01E01.Anjin.2160p.DSNP.WEB-DL", "Shogun", Some(1), &[1]),
+        ];
+
+        for (name, title, season, episodes) in cases {
+            let parsed = parse_name(name);
+            assert_eq!(parsed.title, *title, "title of {}", name);
+            assert_eq!(parsed.season, *season, "season of {}", name);
+            assert_eq!(parsed.episodes, *episodes, "episodes of {}", name);
+            assert_eq!(parsed.media_type(), MediaType::Episode, "type of {}", name);
+        }
+    }
+
+    #[test]
+    fn test_absolute_episode_names() {
+        let cases: &[(&str, &str, u32)] = &[
+            ("[SubsPlease] Jujutsu Kaisen - 24 (1080p) [ABCD1234]", "Jujutsu Kaisen", 24),
+            ("[HorribleSubs] One Piece - 1071 [720p]", "One Piece", 1071),
+            ("[Erai-raws] Frieren - 05v2 [1080p][Multiple Subtitle]", "Frieren", 5),
+            ("Naruto Shippuden - 500", "Naruto Shippuden", 500),
+            ("Bleach.E366.720p", "Bleach", 366),
+            ("Cowboy Bebop Episode 05", "Cowboy Bebop", 5),
+            ("Dragon Ball Z - EP012", "Dragon Ball Z", 12),
+        ];
+
+        for (name, title, episode) in cases {
+            let parsed = parse_name(name);
+            assert_eq!(parsed.title, *title, "title of {}", name);
+            assert_eq!(parsed.absolute_episode, Some(*episode), "episode of {}", name);
+            assert_eq!(parsed.season, None, "season of {}", name);
+            assert!(parsed.is_episode(), "type of {}", name);
+        }
+    }
+
+    #[test]
+    fn test_date_based_names() {
+        let cases: &[(&str, &str, Option<NaiveDate>)] = &[
+            ("The.Daily.Show.2024.03.15.Jon.Stewart.720p.WEB.h264", "The Daily Show", date(2024, 3, 15)),
+            ("Last Week Tonight with John Oliver 2023-10-01", "Last Week Tonight with John Oliver", date(2023, 10, 1)),
+            ("The.Tonight.Show.Starring.Jimmy.Fallon.2019.12.31.1080p", "The Tonight Show Starring Jimmy Fallon", date(2019, 12, 31)),
+            ("Jeopardy 2022 02 30", "Jeopardy", None),
+        ];
+
+        for (name, title, air_date) in cases {
+            let parsed = parse_name(name);
+            assert_eq!(parsed.title, *title, "title of {}", name);
+            assert_eq!(parsed.air_date, *air_date, "date of {}", name);
+        }
+    }
+
+    #[test]
+    fn test_release_tags() {
+        let cases: &[TagCase] = &[
+            ("The.Matrix.1999.1080p.BluRay.x264", Some("1080p"), Some("BluRay"), None),
+            ("Movie.2020.2160p.UHD.BluRay.REMUX", Some("2160p"), Some("BluRay"), None),
+            ("Movie 2020 4K WEB-DL", Some("2160p"), Some("WEB-DL"), None),
+            ("Movie.2020.720p.WEBRip", Some("720p"), Some("WEBRip"), None),
+            ("Show.S01E01.HDTV.x264", None, Some("HDTV"), None),
+            ("Movie.1994.1080i.HDTV", Some("1080i"), Some("HDTV"), None),
+            ("Movie (1999) 1920x1080", Some("1080p"), None, None),
+            ("Movie (1999) 1920x800 BDRip", Some("1080p"), Some("BluRay"), None),
+            ("Old.Movie.1985.DVDRip.XviD", None, Some("DVD"), None),
+            ("Blade.Runner.1982.Final.Cut.1080p.BluRay", Some("1080p"), Some("BluRay"), Some("Final Cut")),
+            ("Apocalypse.Now.1979.Directors.Cut.720p", Some("720p"), None, Some("Director's Cut")),
+            ("Aliens 1986 Special Edition 1080p", Some("1080p"), None, Some("Special Edition")),
+            ("Movie 2010 EXTENDED 1080p", Some("1080p"), None, Some("Extended")),
+            ("Movie.2010.UNRATED.DVDRip", None, Some("DVD"), Some("Unrated")),
+            ("Movie.2010.IMAX.WEB", None, Some("WEB"), Some("IMAX")),
+            ("Kingdom of Heaven (2005) {edition-Director's Cut}", None, None, Some("Director's Cut")),
+            ("Avengers Endgame 2019 HDCAM", None, Some("CAM"), None),
+            ("Plain Movie (2015)", None, None, None),
+        ];
+
+        for (name, resolution, source, edition) in cases {
+            let parsed = parse_name(name);
+            assert_eq!(parsed.resolution.as_deref(), *resolution, "resolution of {}", name);
+            assert_eq!(parsed.source.as_deref(), *source, "source of {}", name);
+            assert_eq!(parsed.edition.as_deref(), *edition, "edition of {}", name);
+        }
+    }
+
+    #[test]
+    fn test_episode_titles() {
+        let cases: &[(&str, Option<&str>)] = &[
+            ("Game of Thrones - S08E03 - The Long Night", Some("The Long Night")),
+            ("Breaking.Bad.S01E02.Cats.in.the.Bag.720p.HDTV", Some("Cats in the Bag")),
+            ("The.Daily.Show.2024.03.15.Jon.Stewart.720p.WEB.h264", Some("Jon Stewart")),
+            ("Breaking.Bad.S01E02.720p.HDTV.x264", None),
+            ("[SubsPlease] Jujutsu Kaisen - 24 (1080p) [ABCD1234]", None),
+        ];
+
+        for (name, episode_title) in cases {
+            let parsed = parse_name(name);
+            assert_eq!(parsed.episode_title.as_deref(), *episode_title, "episode title of {}", name);
+        }
+    }
+
+    #[test]
+    fn test_paths() {
+        let cases: &[PathCase] = &[
+            ("/tv/Breaking Bad (2008)/Season 01/Breaking.Bad.S01E02.720p.mkv", "Breaking Bad", Some(2008), Some(1), &[2]),
+            ("/tv/The Office (US)/Season 2/05 - Halloween.mkv", "The Office US", None, Some(2), &[5]),
+            ("/tv/Doctor Who (2005)/Specials/Doctor.Who.The.Day.of.the.Doctor.mkv", "Doctor Who", Some(2005), Some(0), &[]),
+            ("/tv/Lost/S03/lost.3x07.hdtv.avi", "Lost", None, Some(3), &[7]),
+            ("/tv/Frieren/Season 1/[SubsPlease] Frieren - 05 (1080p).mkv", "Frieren", None, Some(1), &[5]),
+            ("/tv/Seinfeld/S05E01.mkv", "Seinfeld", None, Some(5), &[1]),
+            ("/tv/Dark/Staffel 2/Dark.S02E03.mkv", "Dark", None, Some(2), &[3]),
+            ("/movies/The Matrix (1999)/The Matrix (1999).mkv", "The Matrix", Some(1999), None, &[]),
+            ("/movies/Heat.1995.1080p.BluRay.x264-AMIABLE/amiable-heat-1080p.mkv", "Heat", Some(1995), None, &[]),
+            ("/movies/Alien (1979)/1080p.mkv", "Alien", Some(1979), None, &[]),
+            ("/downloads/Inception.2010.720p.mkv", "Inception", Some(2010), None, &[]),
+            ("/downloads/Some.Movie.mkv", "Some Movie", None, None, &[]),
+        ];
+
+        for (path, title, year, season, episodes) in cases {
+            let parsed = parse_path(Path::new(path));
+            assert_eq!(parsed.title, *title, "title of {}", path);
+            assert_eq!(parsed.year, *year, "year of {}", path);
+            assert_eq!(parsed.season, *season, "season of {}", path);
+            assert_eq!(parsed.episodes, *episodes, "episodes of {}", path);
+        }
+    }
+
+    #[test]
+    fn test_folder_tags_are_merged() {
+        let parsed = parse_path(Path::new(
+            "/movies/Heat.1995.1080p.BluRay.x264-AMIABLE/amiable-heat.mkv",
+        ));
+        assert_eq!(parsed.resolution.as_deref(), Some("1080p"));
+        assert_eq!(parsed.source.as_deref(), Some("BluRay"));
+    }
+
+    #[test]
+    fn test_season_folder() {
+        assert_eq!(season_folder("Season 01"), Some(1));
+        assert_eq!(season_folder("Season.2"), Some(2));
+        assert_eq!(season_folder("season_10"), Some(10));
+        assert_eq!(season_folder("S03"), Some(3));
+        assert_eq!(season_folder("Series 4"), Some(4));
+        assert_eq!(season_folder("Specials"), Some(0));
+        assert_eq!(season_folder("Saison 5"), Some(5));
+        assert_eq!(season_folder("Season Finale"), None);
+        assert_eq!(season_folder("Breaking Bad"), None);
+        assert_eq!(season_folder("Extras"), None);
+    }
+
+    #[test]
+    fn test_search_query() {
+        let movie = parse_name("The.Matrix.1999.1080p.BluRay.x264");
+        assert_eq!(
+            movie.search_query(),
+            SearchQuery {
+                title: "The Matrix".to_string(),
+                year: Some(1999),
+                media_type: MediaType::Movie,
+            }
+        );
+
+        let episode = parse_path(Path::new("/tv/Breaking Bad (2008)/Season 01/S01E02.mkv"));
+        assert_eq!(
+            episode.search_query(),
+            SearchQuery {
+                title: "Breaking Bad".to_string(),
+                year: Some(2008),
+                media_type: MediaType::TvShow,
+            }
+        );
+    }
+}
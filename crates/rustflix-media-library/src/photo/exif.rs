@@ -0,0 +1,475 @@
+//! EXIF parsing for JPEG, PNG and WebP files
+
+use super::Exif;
+use crate::probe::{decode_string, truncated, MAX_HEADER_SIZE};
+use chrono::NaiveDateTime;
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+const MAKE: u16 = 0x010F;
+const MODEL: u16 = 0x0110;
+const ORIENTATION: u16 = 0x0112;
+const DATE_TIME: u16 = 0x0132;
+const EXIF_IFD: u16 = 0x8769;
+const GPS_IFD: u16 = 0x8825;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const DATE_TIME_DIGITIZED: u16 = 0x9004;
+const LENS_MODEL: u16 = 0xA434;
+
+const GPS_LATITUDE_REF: u16 = 1;
+const GPS_LATITUDE: u16 = 2;
+const GPS_LONGITUDE_REF: u16 = 3;
+const GPS_LONGITUDE: u16 = 4;
+const GPS_ALTITUDE_REF: u16 = 5;
+const GPS_ALTITUDE: u16 = 6;
+
+/// Most entries read from one IFD, guarding against corrupt counts
+const MAX_ENTRIES: usize = 1024;
+
+/// Read the EXIF block of an image file, if it has one
+pub(crate) fn read<R: Read + Seek>(reader: &mut R, format: MediaFormat) -> Result<Option<Exif>> {
+    reader.seek(SeekFrom::Start(0))?;
+    let tiff = match format {
+        MediaFormat::Jpeg => jpeg_exif(reader)?,
+        MediaFormat::Png => png_exif(reader)?,
+        MediaFormat::Webp => webp_exif(reader)?,
+        _ => None,
+    };
+
+    tiff.map(|tiff| parse_tiff(&tiff)).transpose()
+}
+
+/// Find the `Exif` APP1 segment of a JPEG file
+fn jpeg_exif<R: Read + Seek>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut soi = [0u8; 2];
+    reader.read_exact(&mut soi)?;
+    if soi != [0xFF, 0xD8] {
+        return Err(RustFlixError::media_processing("Not a JPEG file"));
+    }
+
+    loop {
+        let mut marker = [0u8; 2];
+        reader.read_exact(&mut marker)?;
+        if marker[0] != 0xFF {
+            return Err(RustFlixError::media_processing("Invalid JPEG marker"));
+        }
+        // Start of scan or end of image: no more metadata segments
+        if marker[1] == 0xDA || marker[1] == 0xD9 {
+            return Ok(None);
+        }
+        // Fill bytes and markers without a payload
+        if marker[1] == 0xFF {
+            reader.seek(SeekFrom::Current(-1))?;
+            continue;
+        }
+        if (0xD0..=0xD7).contains(&marker[1]) || marker[1] == 0x01 {
+            continue;
+        }
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let length = (u16::from_be_bytes(length) as u64).checked_sub(2).ok_or_else(truncated)?;
+        if marker[1] == 0xE1 {
+            let mut segment = vec![0u8; length as usize];
+            reader.read_exact(&mut segment)?;
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return Ok(Some(tiff.to_vec()));
+            }
+        } else {
+            reader.seek(SeekFrom::Current(length as i64))?;
+        }
+    }
+}
+
+/// Find the `eXIf` chunk of a PNG file
+fn png_exif<R: Read + Seek>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut signature = [0u8; 8];
+    reader.read_exact(&mut signature)?;
+    if &signature != b"\x89PNG\r\n\x1a\n" {
+        return Err(RustFlixError::media_processing("Not a PNG file"));
+    }
+
+    loop {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        match &header[4..] {
+            b"eXIf" => return read_chunk(reader, length).map(Some),
+            // The EXIF chunk must come before the image data
+            b"IDAT" | b"IEND" => return Ok(None),
+            // Skip the data and CRC
+            _ => {
+                reader.seek(SeekFrom::Current(length as i64 + 4))?;
+            }
+        }
+    }
+}
+
+/// Find the `EXIF` chunk of a WebP file
+fn webp_exif<R: Read + Seek>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        return Err(RustFlixError::media_processing("Not a WebP file"));
+    }
+
+    loop {
+        let mut chunk = [0u8; 8];
+        match reader.read_exact(&mut chunk) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let length = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+        if &chunk[..4] == b"EXIF" {
+            let data = read_chunk(reader, length)?;
+            // Some writers keep the JPEG "Exif" prefix
+            return Ok(Some(match data.strip_prefix(b"Exif\0\0") {
+                Some(tiff) => tiff.to_vec(),
+                None => data,
+            }));
+        }
+        // Chunks are padded to an even size
+        reader.seek(SeekFrom::Current((length + (length & 1)) as i64))?;
+    }
+}
+
+fn read_chunk<R: Read>(reader: &mut R, length: u64) -> Result<Vec<u8>> {
+    if length > MAX_HEADER_SIZE {
+        return Err(RustFlixError::media_processing("EXIF block too large"));
+    }
+    let mut data = vec![0u8; length as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Parse the IFDs of a TIFF-structured EXIF block
+pub(crate) fn parse_tiff(data: &[u8]) -> Result<Exif> {
+    let tiff = Tiff {
+        data,
+        little_endian: match data.get(..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err(RustFlixError::media_processing("Invalid EXIF byte order")),
+        },
+    };
+    if tiff.u16(2)? != 42 {
+        return Err(RustFlixError::media_processing("Invalid EXIF header"));
+    }
+
+    let mut exif = Exif::default();
+    let mut date_time = None;
+    for entry in tiff.entries(tiff.u32(4)? as usize)? {
+        match entry.tag {
+            MAKE => exif.camera_make = tiff.ascii(&entry),
+            MODEL => exif.camera_model = tiff.ascii(&entry),
+            ORIENTATION => exif.orientation = tiff.unsigned(&entry).filter(|value| (1..=8).contains(value)).map(|value| value as u16),
+            DATE_TIME => date_time = tiff.ascii(&entry).and_then(|value| parse_date(&value)),
+            EXIF_IFD => {
+                if let Some(offset) = tiff.unsigned(&entry) {
+                    parse_exif_ifd(&tiff, offset as usize, &mut exif)?;
+                }
+            }
+            GPS_IFD => {
+                if let Some(offset) = tiff.unsigned(&entry) {
+                    parse_gps_ifd(&tiff, offset as usize, &mut exif)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    exif.date_taken = exif.date_taken.or(date_time);
+
+    Ok(exif)
+}
+
+fn parse_exif_ifd(tiff: &Tiff, offset: usize, exif: &mut Exif) -> Result<()> {
+    let mut digitized = None;
+    for entry in tiff.entries(offset)? {
+        match entry.tag {
+            DATE_TIME_ORIGINAL => exif.date_taken = tiff.ascii(&entry).and_then(|value| parse_date(&value)),
+            DATE_TIME_DIGITIZED => digitized = tiff.ascii(&entry).and_then(|value| parse_date(&value)),
+            LENS_MODEL => exif.lens_model = tiff.ascii(&entry),
+            _ => {}
+        }
+    }
+    exif.date_taken = exif.date_taken.or(digitized);
+    Ok(())
+}
+
+fn parse_gps_ifd(tiff: &Tiff, offset: usize, exif: &mut Exif) -> Result<()> {
+    let (mut latitude, mut longitude, mut altitude) = (None, None, None);
+    let (mut south, mut west, mut below) = (false, false, false);
+    for entry in tiff.entries(offset)? {
+        match entry.tag {
+            GPS_LATITUDE_REF => south = tiff.ascii(&entry).is_some_and(|value| value.eq_ignore_ascii_case("S")),
+            GPS_LATITUDE => latitude = tiff.degrees(&entry),
+            GPS_LONGITUDE_REF => west = tiff.ascii(&entry).is_some_and(|value| value.eq_ignore_ascii_case("W")),
+            GPS_LONGITUDE => longitude = tiff.degrees(&entry),
+            GPS_ALTITUDE_REF => below = tiff.unsigned(&entry) == Some(1),
+            GPS_ALTITUDE => altitude = tiff.rationals(&entry).first().copied(),
+            _ => {}
+        }
+    }
+
+    // A position needs both coordinates
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        if latitude.abs() <= 90.0 && longitude.abs() <= 180.0 {
+            exif.latitude = Some(if south { -latitude } else { latitude });
+            exif.longitude = Some(if west { -longitude } else { longitude });
+            exif.altitude = altitude.map(|altitude| if below { -altitude } else { altitude });
+        }
+    }
+    Ok(())
+}
+
+/// Parse an EXIF date such as "2021:07:14 18:30:05"; blank dates written
+/// as zeros give `None`
+fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim(), "%Y:%m:%d %H:%M:%S").ok()
+}
+
+/// One entry of an image file directory
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    /// Offset of the value within the TIFF block
+    value_offset: usize,
+}
+
+/// TIFF block in either byte order
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(truncated)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        let b = self.bytes(offset, 2)?;
+        Ok(if self.little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        let b = self.bytes(offset, 4)?;
+        let b = [b[0], b[1], b[2], b[3]];
+        Ok(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    /// Entries of the IFD at `offset`
+    fn entries(&self, offset: usize) -> Result<Vec<Entry>> {
+        let count = (self.u16(offset)? as usize).min(MAX_ENTRIES);
+        let mut entries = Vec::with_capacity(count);
+        for index in 0..count {
+            let at = offset + 2 + index * 12;
+            let kind = self.u16(at + 2)?;
+            let count = self.u32(at + 4)?;
+            let size = type_size(kind).saturating_mul(count as usize);
+            let value_offset = if size <= 4 { at + 8 } else { self.u32(at + 8)? as usize };
+            entries.push(Entry {
+                tag: self.u16(at)?,
+                kind,
+                count,
+                value_offset,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn ascii(&self, entry: &Entry) -> Option<String> {
+        if entry.kind != 2 {
+            return None;
+        }
+        decode_string(self.bytes(entry.value_offset, entry.count as usize).ok()?)
+    }
+
+    /// First value of a BYTE, SHORT or LONG entry
+    fn unsigned(&self, entry: &Entry) -> Option<u32> {
+        match entry.kind {
+            1 | 7 => self.bytes(entry.value_offset, 1).ok().map(|b| b[0] as u32),
+            3 => self.u16(entry.value_offset).ok().map(u32::from),
+            4 => self.u32(entry.value_offset).ok(),
+            _ => None,
+        }
+    }
+
+    /// Values of a RATIONAL entry
+    fn rationals(&self, entry: &Entry) -> Vec<f64> {
+        if entry.kind != 5 {
+            return Vec::new();
+        }
+        (0..entry.count.min(16) as usize)
+            .map_while(|index| {
+                let at = entry.value_offset + index * 8;
+                let numerator = self.u32(at).ok()?;
+                let denominator = self.u32(at + 4).ok()?;
+                Some(if denominator == 0 { 0.0 } else { numerator as f64 / denominator as f64 })
+            })
+            .collect()
+    }
+
+    /// Degrees of a GPS coordinate given as degrees, minutes and seconds
+    fn degrees(&self, entry: &Entry) -> Option<f64> {
+        match self.rationals(entry).as_slice() {
+            [degrees, minutes, seconds, ..] => Some(degrees + minutes / 60.0 + seconds / 3600.0),
+            [degrees, minutes] => Some(degrees + minutes / 60.0),
+            [degrees] => Some(*degrees),
+            [] => None,
+        }
+    }
+}
+
+/// Size in bytes of one value of a TIFF field type
+fn type_size(kind: u16) -> usize {
+    match kind {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    enum Value<'a> {
+        Ascii(&'a str),
+        Short(u16),
+        Long(u32),
+        Rationals(&'a [(u32, u32)]),
+    }
+
+    /// Build a little-endian IFD at `offset`, with out-of-line values after it
+    fn ifd(offset: usize, entries: &[(u16, Value)]) -> Vec<u8> {
+        let mut head = (entries.len() as u16).to_le_bytes().to_vec();
+        let mut tail = Vec::new();
+        let tail_start = offset + 2 + entries.len() * 12 + 4;
+        for (tag, value) in entries {
+            let (kind, count, data) = match value {
+                Value::Ascii(text) => (2u16, text.len() as u32 + 1, [text.as_bytes(), b"\0"].concat()),
+                Value::Short(value) => (3, 1, value.to_le_bytes().to_vec()),
+                Value::Long(value) => (4, 1, value.to_le_bytes().to_vec()),
+                Value::Rationals(values) => (
+                    5,
+                    values.len() as u32,
+                    values
+                        .iter()
+                        .flat_map(|(n, d)| [n.to_le_bytes(), d.to_le_bytes()].concat())
+                        .collect(),
+                ),
+            };
+            head.extend(tag.to_le_bytes());
+            head.extend(kind.to_le_bytes());
+            head.extend(count.to_le_bytes());
+            if data.len() <= 4 {
+                let mut inline = data.clone();
+                inline.resize(4, 0);
+                head.extend(inline);
+            } else {
+                head.extend(((tail_start + tail.len()) as u32).to_le_bytes());
+                tail.extend(data);
+            }
+        }
+        head.extend([0u8; 4]);
+        head.extend(tail);
+        head
+    }
+
+    /// TIFF block with a camera, a date taken and a position
+    pub(crate) fn sample_tiff() -> Vec<u8> {
+        let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        let ifd0_len = ifd(8, &[
+            (MAKE, Value::Ascii("Canon")),
+            (MODEL, Value::Ascii("EOS R5")),
+            (ORIENTATION, Value::Short(6)),
+            (EXIF_IFD, Value::Long(0)),
+            (GPS_IFD, Value::Long(0)),
+        ])
+        .len();
+        let exif_offset = 8 + ifd0_len;
+        let exif = ifd(exif_offset, &[
+            (DATE_TIME_ORIGINAL, Value::Ascii("2021:07:14 18:30:05")),
+            (LENS_MODEL, Value::Ascii("RF24-105mm")),
+        ]);
+        let gps_offset = exif_offset + exif.len();
+        let gps = ifd(gps_offset, &[
+            (GPS_LATITUDE_REF, Value::Ascii("N")),
+            (GPS_LATITUDE, Value::Rationals(&[(48, 1), (51, 1), (2400, 100)])),
+            (GPS_LONGITUDE_REF, Value::Ascii("W")),
+            (GPS_LONGITUDE, Value::Rationals(&[(2, 1), (21, 1), (0, 1)])),
+            (GPS_ALTITUDE, Value::Rationals(&[(355, 10)])),
+        ]);
+
+        tiff.extend(ifd(8, &[
+            (MAKE, Value::Ascii("Canon")),
+            (MODEL, Value::Ascii("EOS R5")),
+            (ORIENTATION, Value::Short(6)),
+            (EXIF_IFD, Value::Long(exif_offset as u32)),
+            (GPS_IFD, Value::Long(gps_offset as u32)),
+        ]));
+        tiff.extend(exif);
+        tiff.extend(gps);
+        tiff
+    }
+
+    /// JPEG header with an EXIF segment, followed by the start of scan
+    pub(crate) fn sample_jpeg() -> Vec<u8> {
+        let payload = [b"Exif\0\0".as_slice(), &sample_tiff()].concat();
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xE1];
+        jpeg.extend(((payload.len() + 2) as u16).to_be_bytes());
+        jpeg.extend(payload);
+        jpeg.extend([0xFF, 0xDA]);
+        jpeg
+    }
+
+    #[test]
+    fn test_parse_tiff() {
+        let exif = parse_tiff(&sample_tiff()).unwrap();
+        assert_eq!(exif.camera_make.as_deref(), Some("Canon"));
+        assert_eq!(exif.camera_model.as_deref(), Some("EOS R5"));
+        assert_eq!(exif.lens_model.as_deref(), Some("RF24-105mm"));
+        assert_eq!(exif.orientation, Some(6));
+        assert_eq!(exif.date_taken.unwrap().to_string(), "2021-07-14 18:30:05");
+        assert!((exif.latitude.unwrap() - 48.8566).abs() < 1e-4);
+        assert!((exif.longitude.unwrap() + 2.35).abs() < 1e-9);
+        assert_eq!(exif.altitude, Some(35.5));
+    }
+
+    #[test]
+    fn test_read_containers() {
+        let exif = read(&mut Cursor::new(sample_jpeg()), MediaFormat::Jpeg).unwrap().unwrap();
+        assert_eq!(exif.camera_model.as_deref(), Some("EOS R5"));
+
+        let tiff = sample_tiff();
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend((tiff.len() as u32).to_be_bytes());
+        png.extend(b"eXIf");
+        png.extend(&tiff);
+        let exif = read(&mut Cursor::new(png), MediaFormat::Png).unwrap().unwrap();
+        assert_eq!(exif.orientation, Some(6));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8 \x02\0\0\0\0\0EXIF".to_vec();
+        webp.extend((tiff.len() as u32).to_le_bytes());
+        webp.extend(&tiff);
+        let exif = read(&mut Cursor::new(webp), MediaFormat::Webp).unwrap().unwrap();
+        assert_eq!(exif.camera_make.as_deref(), Some("Canon"));
+    }
+
+    #[test]
+    fn test_no_exif() {
+        let jpeg = vec![0xFF, 0xD8, 0xFF, 0xDA];
+        assert!(read(&mut Cursor::new(jpeg), MediaFormat::Jpeg).unwrap().is_none());
+        assert!(parse_date("0000:00:00 00:00:00").is_none());
+        assert!(parse_tiff(b"XX\x2a\x00").is_err());
+    }
+}
@@ -0,0 +1,104 @@
+//! Photo analysis and thumbnails
+//!
+//! EXIF blocks are found and parsed natively, like `crate::probe` does for
+//! containers. Thumbnails are written by `crate::images`. Photos are read
+//! with blocking I/O, so probe them from a blocking thread.
+
+pub(crate) mod exif;
+
+use crate::analyzer::MediaInfo;
+use chrono::NaiveDateTime;
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek};
+use std::path::Path;
+use tracing::warn;
+
+/// Camera details read from a photo's EXIF block
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exif {
+    /// Local time the photo was taken, as recorded by the camera
+    pub date_taken: Option<NaiveDateTime>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    /// EXIF orientation, 1 (upright) to 8
+    pub orientation: Option<u16>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Metres above sea level
+    pub altitude: Option<f64>,
+}
+
+impl Exif {
+    /// Check whether the orientation turns the image by 90 degrees, so that
+    /// its displayed width and height are swapped
+    pub fn is_transposed(&self) -> bool {
+        matches!(self.orientation, Some(5..=8))
+    }
+}
+
+/// Check whether a format is a photo format that can be decoded
+pub fn supports_format(format: MediaFormat) -> bool {
+    matches!(
+        format,
+        MediaFormat::Jpeg | MediaFormat::Png | MediaFormat::Gif | MediaFormat::Bmp | MediaFormat::Webp
+    )
+}
+
+/// Read the dimensions and EXIF block of a photo
+///
+/// Dimensions are those displayed, after applying the EXIF orientation.
+pub fn probe_file(path: &Path, format: MediaFormat) -> Result<MediaInfo> {
+    let mut reader = BufReader::new(File::open(path)?);
+    probe_from(&mut reader, format)
+}
+
+/// Read the dimensions and EXIF block of a photo read through `reader`
+pub fn probe_from<R: BufRead + Seek>(reader: &mut R, format: MediaFormat) -> Result<MediaInfo> {
+    let (width, height) = image::io::Reader::new(&mut *reader)
+        .with_guessed_format()?
+        .into_dimensions()
+        .map_err(|e| RustFlixError::media_processing(format!("Invalid image: {}", e)))?;
+
+    reader.rewind()?;
+    let exif = match exif::read(reader, format) {
+        Ok(exif) => exif,
+        Err(e) => {
+            warn!("Failed to read EXIF block: {}", e);
+            None
+        }
+    };
+
+    let transposed = exif.as_ref().is_some_and(Exif::is_transposed);
+    Ok(MediaInfo {
+        width: Some(if transposed { height } else { width }),
+        height: Some(if transposed { width } else { height }),
+        exif,
+        ..MediaInfo::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn write_image(dir: &Path, name: &str, width: u32, height: u32) -> PathBuf {
+        let path = dir.join(name);
+        RgbImage::new(width, height).save_with_format(&path, ImageFormat::Png).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_probe_file() {
+        let dir = TempDir::new().unwrap();
+        let path = write_image(dir.path(), "photo.png", 40, 30);
+
+        let info = probe_file(&path, MediaFormat::Png).unwrap();
+        assert_eq!((info.width, info.height), (Some(40), Some(30)));
+        assert!(info.exif.is_none());
+    }
+}
@@ -0,0 +1,700 @@
+//! Audio stream parsing (MP3, AAC, FLAC, Ogg and WAV)
+//!
+//! These formats have no container index, so the duration comes from the
+//! stream headers: a Xing/VBRI header or a frame scan for MPEG audio, the
+//! frame scan for ADTS, STREAMINFO for FLAC, the last granule position for
+//! Ogg and the `fmt `/`data` chunks for WAV.
+
+use super::{le16, le32, le64};
+use crate::analyzer::MediaInfo;
+use rustflix_core::media::AudioCodec;
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// How far into the stream we look for the first frame
+const SYNC_SCAN_SIZE: u64 = 64 * 1024;
+
+/// How much of the end of an Ogg file we search for the last page
+const OGG_TAIL_SIZE: u64 = 64 * 1024;
+
+/// Frames compared to tell constant from variable bitrate MP3 files
+const CBR_CHECK_FRAMES: usize = 8;
+
+/// Largest WAV `fmt ` chunk we accept
+const MAX_WAV_FORMAT_SIZE: u64 = 1024;
+
+/// Bitrates in kbit/s by table and index; index 0 is the free format
+const MPEG_BITRATES: [[u32; 15]; 5] = [
+    // MPEG-1 layer I, II and III
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    // MPEG-2 and 2.5 layer I, then layers II and III
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Sample rates of MPEG-1, MPEG-2 and MPEG-2.5
+const MPEG_SAMPLE_RATES: [[u32; 3]; 3] = [[44100, 48000, 32000], [22050, 24000, 16000], [11025, 12000, 8000]];
+
+const ADTS_SAMPLE_RATES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+
+/// Probe an audio file of one of the formats without a container index
+pub(crate) fn probe<R: Read + Seek>(reader: &mut R, file_size: u64, format: MediaFormat) -> Result<MediaInfo> {
+    match format {
+        MediaFormat::Mp3 => probe_mpeg(reader, file_size),
+        MediaFormat::Aac => probe_adts(reader, file_size),
+        MediaFormat::Flac => probe_flac(reader),
+        MediaFormat::Ogg => probe_ogg(reader, file_size),
+        MediaFormat::Wav => probe_wav(reader, file_size),
+        _ => Err(RustFlixError::media_processing(format!("No audio parser for {:?}", format))),
+    }
+}
+
+/// Audio stream summary shared by the parsers
+struct Stream {
+    codec: String,
+    channels: u8,
+    sample_rate: u32,
+    bit_depth: Option<u8>,
+    /// Bitrate of the audio stream in bits per second
+    bitrate: Option<u64>,
+    duration: Option<f64>,
+}
+
+impl Stream {
+    fn into_info(self) -> MediaInfo {
+        let mut info = MediaInfo {
+            duration: self.duration.filter(|duration| duration.is_finite() && *duration > 0.0),
+            bitrate: self.bitrate,
+            ..MediaInfo::default()
+        };
+        info.streams.audio.push(AudioCodec {
+            name: self.codec,
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bit_depth: self.bit_depth,
+            bitrate: self.bitrate,
+            language: None,
+        });
+        info
+    }
+}
+
+/// MPEG audio frame header
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MpegFrame {
+    /// 0 for MPEG-1, 1 for MPEG-2 and 2 for MPEG-2.5
+    version: usize,
+    layer: u8,
+    /// Bits per second
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    mono: bool,
+}
+
+impl MpegFrame {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let header = data.get(..4)?;
+        if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+
+        let version = match (header[1] >> 3) & 0x03 {
+            3 => 0,
+            2 => 1,
+            0 => 2,
+            _ => return None,
+        };
+        let layer = match (header[1] >> 1) & 0x03 {
+            3 => 1,
+            2 => 2,
+            1 => 3,
+            _ => return None,
+        };
+        let table = match (version, layer) {
+            (0, layer) => layer as usize - 1,
+            (_, 1) => 3,
+            _ => 4,
+        };
+        let bitrate = *MPEG_BITRATES[table].get((header[2] >> 4) as usize)?;
+        let sample_rate = *MPEG_SAMPLE_RATES[version].get(((header[2] >> 2) & 0x03) as usize)?;
+        if bitrate == 0 {
+            return None;
+        }
+
+        Some(Self {
+            version,
+            layer,
+            bitrate: bitrate * 1000,
+            sample_rate,
+            padding: header[2] & 0x02 != 0,
+            mono: header[3] >> 6 == 3,
+        })
+    }
+
+    fn samples(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (3, 1 | 2) => 576,
+            _ => 1152,
+        }
+    }
+
+    fn len(&self) -> usize {
+        let padding = self.padding as usize;
+        if self.layer == 1 {
+            (12 * self.bitrate as usize / self.sample_rate as usize + padding) * 4
+        } else {
+            self.samples() as usize / 8 * self.bitrate as usize / self.sample_rate as usize + padding
+        }
+    }
+
+    /// Size of the layer III side information, after which a Xing header sits
+    fn side_info_len(&self) -> usize {
+        match (self.version, self.mono) {
+            (0, true) => 17,
+            (0, false) => 32,
+            (_, true) => 9,
+            (_, false) => 17,
+        }
+    }
+
+    fn codec(&self) -> &'static str {
+        match self.layer {
+            1 => "mp1",
+            2 => "mp2",
+            _ => "mp3",
+        }
+    }
+
+    /// Whether another frame belongs to the same stream
+    fn matches(&self, other: &Self) -> bool {
+        self.version == other.version && self.layer == other.layer && self.sample_rate == other.sample_rate
+    }
+}
+
+fn probe_mpeg<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<MediaInfo> {
+    let start = id3v2_end(reader)?;
+    reader.seek(SeekFrom::Start(start))?;
+    let head = read_up_to(reader, SYNC_SCAN_SIZE)?;
+
+    let (offset, first) = (0..head.len())
+        .filter_map(|pos| MpegFrame::parse(&head[pos..]).map(|frame| (pos, frame)))
+        .find(|(pos, frame)| match head.get(pos + frame.len()..) {
+            // A sync word followed by another frame is not a false match
+            Some(next) if next.len() >= 4 => MpegFrame::parse(next).is_some_and(|next| frame.matches(&next)),
+            _ => true,
+        })
+        .ok_or_else(|| RustFlixError::media_processing("No MPEG audio frame found"))?;
+
+    let audio_start = start + offset as u64;
+    let audio_end = if has_id3v1(reader, file_size)? { file_size - 128 } else { file_size };
+    let audio_size = audio_end.saturating_sub(audio_start);
+    let frame = &head[offset..];
+
+    // VBR encoders put the frame count in a Xing (or Info) or VBRI header
+    let xing = 4 + first.side_info_len();
+    let (frames, bytes) = match frame.get(xing..xing + 4) {
+        Some(b"Xing" | b"Info") => {
+            let flags = be32(frame, xing + 4);
+            let mut pos = xing + 8;
+            let frames = flags.filter(|flags| flags & 0x01 != 0).and_then(|_| {
+                let frames = be32(frame, pos);
+                pos += 4;
+                frames
+            });
+            let bytes = flags.filter(|flags| flags & 0x02 != 0).and_then(|_| be32(frame, pos));
+            (frames, bytes)
+        }
+        _ if frame.get(36..40) == Some(&b"VBRI"[..]) => (be32(frame, 50), be32(frame, 46)),
+        _ => (None, None),
+    };
+
+    let (duration, bitrate) = match frames.filter(|frames| *frames > 0) {
+        Some(frames) => {
+            let duration = frames as f64 * first.samples() as f64 / first.sample_rate as f64;
+            let bytes = bytes.map(u64::from).unwrap_or(audio_size);
+            (duration, (bytes as f64 * 8.0 / duration) as u64)
+        }
+        None if is_constant_bitrate(frame, &first) => {
+            (audio_size as f64 * 8.0 / first.bitrate as f64, first.bitrate as u64)
+        }
+        None => {
+            reader.seek(SeekFrom::Start(audio_start))?;
+            let (samples, bytes) = scan_frames(reader, audio_size, 4, |header| {
+                MpegFrame::parse(header)
+                    .filter(|frame| first.matches(frame))
+                    .map(|frame| (frame.len(), frame.samples()))
+            })?;
+            let duration = samples as f64 / first.sample_rate as f64;
+            (duration, (bytes as f64 * 8.0 / duration) as u64)
+        }
+    };
+
+    Ok(Stream {
+        codec: first.codec().to_string(),
+        channels: if first.mono { 1 } else { 2 },
+        sample_rate: first.sample_rate,
+        bit_depth: None,
+        bitrate: Some(bitrate).filter(|_| duration > 0.0),
+        duration: Some(duration),
+    }
+    .into_info())
+}
+
+/// Check whether the first frames in `data` all share the bitrate of `first`
+fn is_constant_bitrate(data: &[u8], first: &MpegFrame) -> bool {
+    let mut pos = 0;
+    for _ in 0..CBR_CHECK_FRAMES {
+        match MpegFrame::parse(&data[pos..]) {
+            Some(frame) if frame.bitrate == first.bitrate => pos += frame.len(),
+            Some(_) => return false,
+            None => return pos + 4 > data.len(),
+        }
+        if pos >= data.len() {
+            break;
+        }
+    }
+    true
+}
+
+/// ADTS frame header: frame length and samples
+fn adts_frame(data: &[u8]) -> Option<(usize, u32)> {
+    let header = data.get(..7)?;
+    if header[0] != 0xff || header[1] & 0xf6 != 0xf0 {
+        return None;
+    }
+    let len = ((header[3] as usize & 0x03) << 11) | ((header[4] as usize) << 3) | (header[5] as usize >> 5);
+    let blocks = (header[6] & 0x03) as u32 + 1;
+    (len >= 7).then_some((len, 1024 * blocks))
+}
+
+fn probe_adts<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<MediaInfo> {
+    let start = id3v2_end(reader)?;
+    reader.seek(SeekFrom::Start(start))?;
+    let mut header = [0u8; 7];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| RustFlixError::media_processing("Not an ADTS stream"))?;
+    adts_frame(&header).ok_or_else(|| RustFlixError::media_processing("Not an ADTS stream"))?;
+
+    let sample_rate = *ADTS_SAMPLE_RATES
+        .get(((header[2] >> 2) & 0x0f) as usize)
+        .ok_or_else(|| RustFlixError::media_processing("Invalid ADTS sample rate"))?;
+    let channels = ((header[2] & 0x01) << 2) | (header[3] >> 6);
+
+    reader.seek(SeekFrom::Start(start))?;
+    let (samples, bytes) = scan_frames(reader, file_size.saturating_sub(start), 7, adts_frame)?;
+    let duration = samples as f64 / sample_rate as f64;
+
+    Ok(Stream {
+        codec: "aac".to_string(),
+        channels,
+        sample_rate,
+        bit_depth: None,
+        bitrate: Some((bytes as f64 * 8.0 / duration) as u64).filter(|_| duration > 0.0),
+        duration: Some(duration),
+    }
+    .into_info())
+}
+
+/// Walk consecutive frames, returning the samples and bytes they hold
+///
+/// `frame` parses a header of `header_len` bytes into the frame length and
+/// its sample count. The walk stops at the first byte that does not start a
+/// frame, such as a trailing tag. Frames are skipped by reading rather than
+/// seeking, so remote readers fetch the stream in order.
+fn scan_frames<R: Read>(
+    reader: &mut R,
+    limit: u64,
+    header_len: usize,
+    frame: impl Fn(&[u8]) -> Option<(usize, u32)>,
+) -> Result<(u64, u64)> {
+    let (mut samples, mut bytes) = (0u64, 0u64);
+    let mut header = vec![0u8; header_len];
+
+    while bytes + header_len as u64 <= limit {
+        if reader.read_exact(&mut header).is_err() {
+            break;
+        }
+        let Some((len, frame_samples)) = frame(&header) else {
+            break;
+        };
+        let rest = (len - header_len) as u64;
+        if io::copy(&mut reader.by_ref().take(rest), &mut io::sink())? < rest {
+            break;
+        }
+        samples += frame_samples as u64;
+        bytes += len as u64;
+    }
+
+    Ok((samples, bytes))
+}
+
+fn probe_flac<R: Read + Seek>(reader: &mut R) -> Result<MediaInfo> {
+    let start = id3v2_end(reader)?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    // STREAMINFO is always the first metadata block
+    let mut header = [0u8; 8 + 34];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| RustFlixError::media_processing("Not a FLAC stream"))?;
+    if &header[..4] != b"fLaC" || header[4] & 0x7f != 0 {
+        return Err(RustFlixError::media_processing("Not a FLAC stream"));
+    }
+
+    let info = &header[8..];
+    let packed = u64::from_be_bytes([info[10], info[11], info[12], info[13], info[14], info[15], info[16], info[17]]);
+    let sample_rate = (packed >> 44) as u32;
+    let total_samples = packed & 0x0f_ffff_ffff;
+    if sample_rate == 0 {
+        return Err(RustFlixError::media_processing("Invalid FLAC stream info"));
+    }
+
+    Ok(Stream {
+        codec: "flac".to_string(),
+        channels: ((packed >> 41) & 0x07) as u8 + 1,
+        sample_rate,
+        bit_depth: Some(((packed >> 36) & 0x1f) as u8 + 1),
+        bitrate: None,
+        // Zero samples means the encoder did not know the length
+        duration: Some(total_samples as f64 / sample_rate as f64),
+    }
+    .into_info())
+}
+
+fn probe_ogg<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<MediaInfo> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 27];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| RustFlixError::media_processing("Not an Ogg stream"))?;
+    if &header[..4] != b"OggS" {
+        return Err(RustFlixError::media_processing("Not an Ogg stream"));
+    }
+    let serial = le32(&header, 14)?;
+
+    // The identification header is the first packet of the first page
+    let mut lacing = vec![0u8; header[26] as usize];
+    reader.read_exact(&mut lacing)?;
+    let segments = lacing.iter().position(|len| *len < 255).map_or(lacing.len(), |end| end + 1);
+    let mut packet = vec![0u8; lacing[..segments].iter().map(|len| *len as usize).sum()];
+    reader.read_exact(&mut packet)?;
+
+    let (codec, channels, sample_rate, pre_skip, bitrate) = if packet.starts_with(b"\x01vorbis") {
+        let nominal = le32(&packet, 20)? as i32;
+        ("vorbis", packet.get(11).copied(), le32(&packet, 12)?, 0, (nominal > 0).then_some(nominal as u64))
+    } else if packet.starts_with(b"OpusHead") {
+        // Opus granule positions always count 48 kHz samples
+        ("opus", packet.get(9).copied(), 48000, le16(&packet, 10)? as u64, None)
+    } else {
+        return Err(RustFlixError::media_processing("Unsupported Ogg codec"));
+    };
+    if sample_rate == 0 {
+        return Err(RustFlixError::media_processing("Invalid Ogg sample rate"));
+    }
+
+    let duration = last_granule(reader, file_size, serial)?
+        .map(|granule| granule.saturating_sub(pre_skip) as f64 / sample_rate as f64);
+
+    Ok(Stream {
+        codec: codec.to_string(),
+        channels: channels.unwrap_or(0),
+        sample_rate,
+        bit_depth: None,
+        bitrate,
+        duration,
+    }
+    .into_info())
+}
+
+/// Granule position of the last page of the logical stream `serial`
+fn last_granule<R: Read + Seek>(reader: &mut R, file_size: u64, serial: u32) -> Result<Option<u64>> {
+    reader.seek(SeekFrom::Start(file_size.saturating_sub(OGG_TAIL_SIZE)))?;
+    let tail = read_up_to(reader, OGG_TAIL_SIZE)?;
+    if tail.len() < 27 {
+        return Ok(None);
+    }
+
+    for pos in (0..=tail.len() - 27).rev() {
+        if &tail[pos..pos + 4] == b"OggS" && le32(&tail, pos + 14)? == serial {
+            // Pages on which no packet ends carry a granule position of -1
+            let granule = le64(&tail, pos + 6)?;
+            if granule != u64::MAX {
+                return Ok(Some(granule));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn probe_wav<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<MediaInfo> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut riff = [0u8; 12];
+    reader
+        .read_exact(&mut riff)
+        .map_err(|_| RustFlixError::media_processing("Not a WAV file"))?;
+    if &riff[..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(RustFlixError::media_processing("Not a WAV file"));
+    }
+
+    let mut fmt = None;
+    let mut pos = 12u64;
+    let data_size = loop {
+        let mut chunk = [0u8; 8];
+        reader
+            .read_exact(&mut chunk)
+            .map_err(|_| RustFlixError::media_processing("Missing WAV data chunk"))?;
+        let size = le32(&chunk, 4)? as u64;
+        pos += 8;
+
+        match &chunk[..4] {
+            b"data" => break size.min(file_size.saturating_sub(pos)),
+            b"fmt " if size <= MAX_WAV_FORMAT_SIZE => {
+                let mut data = vec![0u8; size as usize];
+                reader.read_exact(&mut data)?;
+                if size & 1 != 0 {
+                    reader.seek(SeekFrom::Current(1))?;
+                }
+                fmt = Some(data);
+            }
+            _ => {
+                reader.seek(SeekFrom::Current((size + (size & 1)) as i64))?;
+            }
+        }
+        pos += size + (size & 1);
+    };
+
+    let fmt = fmt.ok_or_else(|| RustFlixError::media_processing("Missing WAV format chunk"))?;
+    let mut format_tag = le16(&fmt, 0)?;
+    // WAVE_FORMAT_EXTENSIBLE keeps the real tag at the start of its subformat GUID
+    if format_tag == 0xfffe {
+        format_tag = le16(&fmt, 24).unwrap_or(format_tag);
+    }
+    let byte_rate = le32(&fmt, 8)? as u64;
+    let bits = le16(&fmt, 14)?;
+
+    Ok(Stream {
+        codec: wav_codec_name(format_tag, bits),
+        channels: le16(&fmt, 2)?.min(u8::MAX as u16) as u8,
+        sample_rate: le32(&fmt, 4)?,
+        bit_depth: (bits > 0).then_some(bits.min(u8::MAX as u16) as u8),
+        bitrate: (byte_rate > 0).then_some(byte_rate * 8),
+        duration: (byte_rate > 0).then(|| data_size as f64 / byte_rate as f64),
+    }
+    .into_info())
+}
+
+fn wav_codec_name(format_tag: u16, bits: u16) -> String {
+    match (format_tag, bits) {
+        (0x0001, 8) => "pcm_u8".to_string(),
+        (0x0001, bits) => format!("pcm_s{}le", bits),
+        (0x0003, 64) => "pcm_f64le".to_string(),
+        (0x0003, _) => "pcm_f32le".to_string(),
+        (0x0006, _) => "pcm_alaw".to_string(),
+        (0x0007, _) => "pcm_mulaw".to_string(),
+        _ => super::avi::audio_codec_name(format_tag),
+    }
+}
+
+/// Offset of the audio stream, past an ID3v2 tag at the start of the file
+fn id3v2_end<R: Read + Seek>(reader: &mut R) -> Result<u64> {
+    reader.seek(SeekFrom::Start(0))?;
+    let header = read_up_to(reader, 10)?;
+    if header.len() < 10 || &header[..3] != b"ID3" {
+        return Ok(0);
+    }
+    let size = header[6..10].iter().fold(0u64, |value, byte| (value << 7) | (*byte as u64 & 0x7f));
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Ok(10 + size + footer)
+}
+
+fn has_id3v1<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<bool> {
+    if file_size < 128 {
+        return Ok(false);
+    }
+    reader.seek(SeekFrom::Start(file_size - 128))?;
+    let mut magic = [0u8; 3];
+    reader.read_exact(&mut magic)?;
+    Ok(&magic == b"TAG")
+}
+
+fn read_up_to<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// MPEG-1 layer III, 128 kbit/s, 44.1 kHz, joint stereo
+    const MP3_HEADER: [u8; 4] = [0xff, 0xfb, 0x90, 0x40];
+    const MP3_FRAME_LEN: usize = 417;
+
+    /// Build a constant bitrate MP3 file of `frames` frames behind an ID3v2 tag
+    pub(crate) fn sample_mp3(frames: usize) -> Vec<u8> {
+        let mut file = b"ID3\x03\x00\x00\x00\x00\x00\x10".to_vec();
+        file.extend([0u8; 16]);
+        for _ in 0..frames {
+            file.extend(MP3_HEADER);
+            file.extend(vec![0u8; MP3_FRAME_LEN - 4]);
+        }
+        file
+    }
+
+    /// Seconds of audio in `sample_mp3(frames)`
+    pub(crate) fn mp3_duration(frames: usize) -> f64 {
+        (frames * MP3_FRAME_LEN * 8) as f64 / 128_000.0
+    }
+
+    fn probe_bytes(data: Vec<u8>, format: MediaFormat) -> Result<MediaInfo> {
+        let size = data.len() as u64;
+        probe(&mut Cursor::new(data), size, format)
+    }
+
+    #[test]
+    fn test_mp3_constant_bitrate() {
+        let info = probe_bytes(sample_mp3(100), MediaFormat::Mp3).unwrap();
+        assert!((info.duration.unwrap() - mp3_duration(100)).abs() < 1e-9);
+        assert_eq!(info.bitrate, Some(128_000));
+        let audio = &info.streams.audio[0];
+        assert_eq!((audio.name.as_str(), audio.channels, audio.sample_rate), ("mp3", 2, 44100));
+
+        assert!(probe_bytes(b"fake audio data".to_vec(), MediaFormat::Mp3).is_err());
+    }
+
+    #[test]
+    fn test_mp3_xing_header() {
+        // The first frame is a Xing header counting the frames that follow
+        let mut first = vec![0u8; MP3_FRAME_LEN];
+        first[..4].copy_from_slice(&MP3_HEADER);
+        first[36..40].copy_from_slice(b"Xing");
+        first[40..44].copy_from_slice(&3u32.to_be_bytes());
+        first[44..48].copy_from_slice(&1000u32.to_be_bytes());
+        first[48..52].copy_from_slice(&(1000 * MP3_FRAME_LEN as u32).to_be_bytes());
+
+        let info = probe_bytes(first, MediaFormat::Mp3).unwrap();
+        let duration = 1000.0 * 1152.0 / 44100.0;
+        assert!((info.duration.unwrap() - duration).abs() < 1e-9);
+        assert_eq!(info.bitrate, Some((1000.0 * MP3_FRAME_LEN as f64 * 8.0 / duration) as u64));
+    }
+
+    #[test]
+    fn test_mp3_frame_scan() {
+        // Mixed bitrates without a VBR header are counted frame by frame
+        let mut data = Vec::new();
+        for index in 0..20 {
+            let (bitrate, len) = if index % 2 == 0 { (0x90, 417) } else { (0x50, 208) };
+            data.extend([0xff, 0xfb, bitrate, 0x40]);
+            data.extend(vec![0u8; len - 4]);
+        }
+        data.extend(b"TAG");
+        data.extend([0u8; 125]);
+
+        let info = probe_bytes(data, MediaFormat::Mp3).unwrap();
+        assert!((info.duration.unwrap() - 20.0 * 1152.0 / 44100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_adts() {
+        // AAC LC, 48 kHz, stereo, 256 byte frames
+        let mut data = Vec::new();
+        for _ in 0..50 {
+            let len = 256usize;
+            data.extend([0xff, 0xf1, 0x4c, 0x80 | (len >> 11) as u8, (len >> 3) as u8, ((len & 7) << 5) as u8 | 0x1f, 0xfc]);
+            data.extend(vec![0u8; len - 7]);
+        }
+
+        let info = probe_bytes(data, MediaFormat::Aac).unwrap();
+        assert!((info.duration.unwrap() - 50.0 * 1024.0 / 48000.0).abs() < 1e-9);
+        assert_eq!((info.streams.audio[0].channels, info.streams.audio[0].sample_rate), (2, 48000));
+    }
+
+    #[test]
+    fn test_flac_stream_info() {
+        let data = crate::tags::vorbis::tests::sample_flac(&["TITLE=Song"]);
+        let info = probe_bytes(data, MediaFormat::Flac).unwrap();
+        assert_eq!(info.duration, Some(180.0));
+        let audio = &info.streams.audio[0];
+        assert_eq!((audio.name.as_str(), audio.channels, audio.sample_rate, audio.bit_depth), ("flac", 2, 44100, Some(16)));
+    }
+
+    fn ogg_page(serial: u32, granule: u64, packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend(granule.to_le_bytes());
+        page.extend(serial.to_le_bytes());
+        page.extend([0u8; 8]);
+        page.push(1);
+        page.push(packet.len() as u8);
+        page.extend(packet);
+        page
+    }
+
+    #[test]
+    fn test_ogg_last_granule() {
+        let mut head = b"\x01vorbis".to_vec();
+        head.extend(0u32.to_le_bytes());
+        head.push(2);
+        head.extend(44100u32.to_le_bytes());
+        head.extend([0u8; 4]);
+        head.extend(160_000u32.to_le_bytes());
+        head.extend([0u8; 6]);
+
+        let mut data = ogg_page(5, 0, &head);
+        data.extend(ogg_page(5, 441_000, b"audio"));
+        data.extend(ogg_page(6, 9_999_999, b"other stream"));
+        data.extend(ogg_page(5, u64::MAX, b"continued"));
+
+        let info = probe_bytes(data, MediaFormat::Ogg).unwrap();
+        assert_eq!(info.duration, Some(10.0));
+        assert_eq!(info.bitrate, Some(160_000));
+
+        let mut opus = b"OpusHead\x01\x02".to_vec();
+        opus.extend(312u16.to_le_bytes());
+        opus.extend(44100u32.to_le_bytes());
+        opus.extend([0u8; 3]);
+        let mut data = ogg_page(1, 0, &opus);
+        data.extend(ogg_page(1, 48_312, b"audio"));
+
+        let info = probe_bytes(data, MediaFormat::Ogg).unwrap();
+        assert_eq!(info.duration, Some(1.0));
+        assert_eq!(info.streams.audio[0].name, "opus");
+    }
+
+    #[test]
+    fn test_wav() {
+        let mut fmt = Vec::new();
+        fmt.extend(1u16.to_le_bytes());
+        fmt.extend(2u16.to_le_bytes());
+        fmt.extend(44100u32.to_le_bytes());
+        fmt.extend(176_400u32.to_le_bytes());
+        fmt.extend(4u16.to_le_bytes());
+        fmt.extend(16u16.to_le_bytes());
+
+        let mut data = b"RIFF\0\0\0\0WAVE".to_vec();
+        data.extend(b"LIST\x03\0\0\0abc\0");
+        data.extend(b"fmt ");
+        data.extend((fmt.len() as u32).to_le_bytes());
+        data.extend(fmt);
+        data.extend(b"data");
+        // Streaming writers leave the size at its maximum
+        data.extend(u32::MAX.to_le_bytes());
+        data.extend(vec![0u8; 176_400 * 2]);
+
+        let info = probe_bytes(data, MediaFormat::Wav).unwrap();
+        assert_eq!(info.duration, Some(2.0));
+        assert_eq!(info.bitrate, Some(1_411_200));
+        assert_eq!(info.streams.audio[0].name, "pcm_s16le");
+    }
+}
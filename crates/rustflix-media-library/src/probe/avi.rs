@@ -0,0 +1,297 @@
+//! AVI (RIFF) parsing
+
+use super::{decode_string, round_frame_rate, MAX_HEADER_SIZE};
+use crate::analyzer::MediaInfo;
+use rustflix_core::media::{AudioCodec, VideoCodec};
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+/// A RIFF chunk inside an in-memory buffer
+#[derive(Debug, Clone, Copy)]
+struct Chunk<'a> {
+    id: [u8; 4],
+    /// List type for `LIST` chunks
+    list_type: Option<[u8; 4]>,
+    data: &'a [u8],
+}
+
+/// Split a buffer into RIFF chunks (little-endian sizes, word aligned)
+fn chunks(data: &[u8]) -> Vec<Chunk<'_>> {
+    let mut out = Vec::new();
+    let mut pos = 0usize;
+
+    while pos + 8 <= data.len() {
+        let id = [data[pos], data[pos + 1], data[pos + 2], data[pos + 3]];
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        let start = pos + 8;
+        let end = (start + size).min(data.len());
+
+        let chunk = if &id == b"LIST" && end - start >= 4 {
+            Chunk {
+                id,
+                list_type: Some([data[start], data[start + 1], data[start + 2], data[start + 3]]),
+                data: &data[start + 4..end],
+            }
+        } else {
+            Chunk {
+                id,
+                list_type: None,
+                data: &data[start..end],
+            }
+        };
+        out.push(chunk);
+        pos = start + size + (size & 1);
+    }
+
+    out
+}
+
+fn le_u16(data: &[u8], offset: usize) -> u16 {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .unwrap_or(0)
+}
+
+fn le_u32(data: &[u8], offset: usize) -> u32 {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or(0)
+}
+
+/// Probe an AVI file
+pub(crate) fn probe<R: Read + Seek>(reader: &mut R, _file_size: u64) -> Result<MediaInfo> {
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut riff = [0u8; 12];
+    reader
+        .read_exact(&mut riff)
+        .map_err(|_| RustFlixError::media_processing("Not an AVI file"))?;
+    if &riff[..4] != b"RIFF" || &riff[8..12] != b"AVI " {
+        return Err(RustFlixError::media_processing("Not an AVI file"));
+    }
+
+    // The header list is the first chunk of a well-formed AVI file
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+    let size = le_u32(&header, 4) as u64;
+    if &header[..4] != b"LIST" || &header[8..12] != b"hdrl" || size < 4 {
+        return Err(RustFlixError::media_processing("Missing AVI header list"));
+    }
+    if size > MAX_HEADER_SIZE {
+        return Err(RustFlixError::media_processing("AVI header list is too large"));
+    }
+
+    let mut hdrl = vec![0u8; size as usize - 4];
+    reader.read_exact(&mut hdrl)?;
+    Ok(parse_hdrl(&hdrl))
+}
+
+fn parse_hdrl(hdrl: &[u8]) -> MediaInfo {
+    let mut info = MediaInfo::default();
+    let mut micros_per_frame = 0u32;
+    let mut total_frames = 0u32;
+    let mut video_duration = None;
+
+    for chunk in chunks(hdrl) {
+        match (&chunk.id, chunk.list_type.as_ref()) {
+            (b"avih", _) => {
+                micros_per_frame = le_u32(chunk.data, 0);
+                total_frames = le_u32(chunk.data, 16);
+            }
+            (b"LIST", Some(b"odml")) => {
+                // OpenDML files larger than 1 GiB carry the real frame count here
+                if let Some(dmlh) = chunks(chunk.data).into_iter().find(|c| &c.id == b"dmlh") {
+                    total_frames = total_frames.max(le_u32(dmlh.data, 0));
+                }
+            }
+            (b"LIST", Some(b"strl")) => {
+                parse_strl(chunk.data, &mut info, &mut video_duration);
+            }
+            _ => {}
+        }
+    }
+
+    info.duration = if total_frames > 0 && micros_per_frame > 0 {
+        Some(total_frames as f64 * micros_per_frame as f64 / 1_000_000.0)
+    } else {
+        video_duration
+    };
+
+    if let Some(video) = info.streams.video.first_mut() {
+        if video.frame_rate.is_none() && micros_per_frame > 0 {
+            video.frame_rate = round_frame_rate(1_000_000.0 / micros_per_frame as f64);
+        }
+    }
+
+    info
+}
+
+fn parse_strl(strl: &[u8], info: &mut MediaInfo, video_duration: &mut Option<f64>) {
+    let parts = chunks(strl);
+    let Some(strh) = parts.iter().find(|c| &c.id == b"strh") else {
+        return;
+    };
+    let strf = parts.iter().find(|c| &c.id == b"strf").map(|c| c.data).unwrap_or_default();
+
+    let stream_type = &strh.data[..4.min(strh.data.len())];
+    let handler = strh.data.get(4..8).unwrap_or_default();
+    let scale = le_u32(strh.data, 20);
+    let rate = le_u32(strh.data, 24);
+    let length = le_u32(strh.data, 32);
+
+    match stream_type {
+        b"vids" => {
+            // BITMAPINFOHEADER: size, width, height, planes, bit count, compression
+            let compression = strf.get(16..20).unwrap_or(handler);
+            let frame_rate = if scale > 0 {
+                round_frame_rate(rate as f64 / scale as f64)
+            } else {
+                None
+            };
+            if scale > 0 && rate > 0 && length > 0 {
+                video_duration.get_or_insert(length as f64 * scale as f64 / rate as f64);
+            }
+
+            info.streams.video.push(VideoCodec {
+                name: video_codec_name(compression),
+                profile: None,
+                level: None,
+                width: le_u32(strf, 4),
+                height: (le_u32(strf, 8) as i32).unsigned_abs(),
+                frame_rate,
+                bit_depth: None,
+                color_space: None,
+            });
+        }
+        b"auds" => {
+            // WAVEFORMATEX: format tag, channels, sample rate, byte rate, block align, bits
+            let bits_per_sample = le_u16(strf, 14);
+            let byte_rate = le_u32(strf, 8);
+
+            info.streams.audio.push(AudioCodec {
+                name: audio_codec_name(le_u16(strf, 0)),
+                channels: le_u16(strf, 2).min(u8::MAX as u16) as u8,
+                sample_rate: le_u32(strf, 4),
+                bit_depth: (bits_per_sample > 0).then_some(bits_per_sample as u8),
+                bitrate: (byte_rate > 0).then_some(byte_rate as u64 * 8),
+                language: None,
+            });
+        }
+        _ => {}
+    }
+}
+
+fn video_codec_name(fourcc: &[u8]) -> String {
+    let fourcc = String::from_utf8_lossy(fourcc).to_uppercase();
+    let name = match fourcc.as_str() {
+        "XVID" | "DIVX" | "DX50" | "FMP4" | "MP4V" | "3IV2" => "mpeg4",
+        "H264" | "X264" | "AVC1" => "h264",
+        "HEVC" | "H265" | "X265" | "HVC1" => "hevc",
+        "DIV3" | "MP43" => "msmpeg4v3",
+        "MJPG" => "mjpeg",
+        "WMV3" => "wmv3",
+        "MPG2" => "mpeg2video",
+        _ => return decode_string(fourcc.to_lowercase().as_bytes()).unwrap_or_default(),
+    };
+    name.to_string()
+}
+
+pub(super) fn audio_codec_name(format_tag: u16) -> String {
+    let name = match format_tag {
+        0x0001 => "pcm",
+        0x0003 => "pcm_float",
+        0x0050 => "mp2",
+        0x0055 => "mp3",
+        0x00ff | 0x1610 | 0x706d => "aac",
+        0x0161 => "wmav2",
+        0x2000 => "ac3",
+        0x2001 => "dts",
+        0xf1ac => "flac",
+        _ => "unknown",
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn chunk(id: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        out.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            out.push(0);
+        }
+        out
+    }
+
+    fn list(list_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = list_type.to_vec();
+        data.extend_from_slice(payload);
+        chunk(b"LIST", &data)
+    }
+
+    fn words(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn sample_file() -> Vec<u8> {
+        // 25 fps, 2500 frames, 720x304
+        let avih = chunk(b"avih", &words(&[40_000, 0, 0, 0, 2500, 0, 2, 0, 720, 304, 0, 0, 0, 0]));
+
+        let mut video_strh = b"vidsXVID".to_vec();
+        video_strh.extend(words(&[0, 0, 0, 1, 25, 0, 2500, 0, 0, 0, 0, 0]));
+        let mut video_strf = words(&[40, 720, 304]);
+        video_strf.extend_from_slice(&[1, 0, 24, 0]);
+        video_strf.extend_from_slice(b"XVID");
+        video_strf.extend(words(&[0, 0, 0, 0, 0]));
+        let mut video = chunk(b"strh", &video_strh);
+        video.extend(chunk(b"strf", &video_strf));
+
+        let mut audio_strh = b"auds\0\0\0\0".to_vec();
+        audio_strh.extend(words(&[0, 0, 0, 1, 44100, 0, 0, 0, 0, 0, 0, 0]));
+        let mut audio_strf = Vec::new();
+        audio_strf.extend_from_slice(&0x0055u16.to_le_bytes());
+        audio_strf.extend_from_slice(&2u16.to_le_bytes());
+        audio_strf.extend(words(&[44100, 16000]));
+        audio_strf.extend_from_slice(&[1, 0, 0, 0]);
+        let mut audio = chunk(b"strh", &audio_strh);
+        audio.extend(chunk(b"strf", &audio_strf));
+
+        let mut hdrl = avih;
+        hdrl.extend(list(b"strl", &video));
+        hdrl.extend(list(b"strl", &audio));
+
+        let mut body = b"AVI ".to_vec();
+        body.extend(list(b"hdrl", &hdrl));
+        body.extend(list(b"movi", &[0u8; 16]));
+        chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn test_probe_sample_file() {
+        let data = sample_file();
+        let info = probe(&mut Cursor::new(&data), data.len() as u64).unwrap();
+
+        assert_eq!(info.duration, Some(100.0));
+
+        let video = &info.streams.video[0];
+        assert_eq!(video.name, "mpeg4");
+        assert_eq!((video.width, video.height), (720, 304));
+        assert_eq!(video.frame_rate, Some(25.0));
+
+        let audio = &info.streams.audio[0];
+        assert_eq!(audio.name, "mp3");
+        assert_eq!(audio.channels, 2);
+        assert_eq!(audio.sample_rate, 44100);
+        assert_eq!(audio.bitrate, Some(128_000));
+    }
+
+    #[test]
+    fn test_not_avi() {
+        let data = b"fake video data".to_vec();
+        assert!(probe(&mut Cursor::new(&data), data.len() as u64).is_err());
+    }
+}
@@ -0,0 +1,796 @@
+//! Matroska and WebM (EBML) parsing
+
+use super::{
+    color_space_name, decode_string, normalize_language, parse_av1_config, parse_avc_config,
+    parse_hevc_config, round_frame_rate, truncated, ChapterMark, CodecDetails, MAX_HEADER_SIZE,
+};
+use crate::analyzer::MediaInfo;
+use rustflix_core::media::{AudioCodec, SubtitleTrack, VideoCodec};
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+pub(crate) const EBML_HEADER: u32 = 0x1A45_DFA3;
+pub(crate) const DOC_TYPE: u32 = 0x4282;
+pub(crate) const SEGMENT: u32 = 0x1853_8067;
+pub(crate) const SEEK_HEAD: u32 = 0x114D_9B74;
+pub(crate) const SEEK: u32 = 0x4DBB;
+pub(crate) const SEEK_ID: u32 = 0x53AB;
+pub(crate) const SEEK_POSITION: u32 = 0x53AC;
+pub(crate) const INFO: u32 = 0x1549_A966;
+pub(crate) const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+pub(crate) const DURATION: u32 = 0x4489;
+pub(crate) const TRACKS: u32 = 0x1654_AE6B;
+pub(crate) const TRACK_ENTRY: u32 = 0xAE;
+pub(crate) const TRACK_NUMBER: u32 = 0xD7;
+pub(crate) const TRACK_TYPE: u32 = 0x83;
+pub(crate) const FLAG_DEFAULT: u32 = 0x88;
+pub(crate) const FLAG_FORCED: u32 = 0x55AA;
+pub(crate) const DEFAULT_DURATION: u32 = 0x23_E383;
+pub(crate) const NAME: u32 = 0x536E;
+pub(crate) const LANGUAGE: u32 = 0x22_B59C;
+pub(crate) const LANGUAGE_BCP47: u32 = 0x22_B59D;
+pub(crate) const CODEC_ID: u32 = 0x86;
+pub(crate) const CODEC_PRIVATE: u32 = 0x63A2;
+pub(crate) const VIDEO: u32 = 0xE0;
+pub(crate) const PIXEL_WIDTH: u32 = 0xB0;
+pub(crate) const PIXEL_HEIGHT: u32 = 0xBA;
+pub(crate) const COLOUR: u32 = 0x55B0;
+pub(crate) const MATRIX_COEFFICIENTS: u32 = 0x55B1;
+pub(crate) const BITS_PER_CHANNEL: u32 = 0x55B2;
+pub(crate) const AUDIO: u32 = 0xE1;
+pub(crate) const SAMPLING_FREQUENCY: u32 = 0xB5;
+pub(crate) const CHANNELS: u32 = 0x9F;
+pub(crate) const BIT_DEPTH: u32 = 0x6264;
+pub(crate) const CLUSTER: u32 = 0x1F43_B675;
+pub(crate) const CHAPTERS: u32 = 0x1043_A770;
+pub(crate) const EDITION_ENTRY: u32 = 0x45B9;
+pub(crate) const EDITION_FLAG_HIDDEN: u32 = 0x45BD;
+pub(crate) const EDITION_FLAG_DEFAULT: u32 = 0x45DB;
+pub(crate) const CHAPTER_ATOM: u32 = 0xB6;
+pub(crate) const CHAPTER_TIME_START: u32 = 0x91;
+pub(crate) const CHAPTER_TIME_END: u32 = 0x92;
+pub(crate) const CHAPTER_FLAG_HIDDEN: u32 = 0x98;
+pub(crate) const CHAPTER_FLAG_ENABLED: u32 = 0x4598;
+pub(crate) const CHAPTER_DISPLAY: u32 = 0x80;
+pub(crate) const CHAP_STRING: u32 = 0x85;
+pub(crate) const CUES: u32 = 0x1C53_BB6B;
+pub(crate) const CUE_POINT: u32 = 0xBB;
+pub(crate) const CUE_TIME: u32 = 0xB3;
+pub(crate) const CUE_TRACK_POSITIONS: u32 = 0xB7;
+pub(crate) const CUE_TRACK: u32 = 0xF7;
+
+const TRACK_TYPE_VIDEO: u64 = 1;
+const TRACK_TYPE_AUDIO: u64 = 2;
+const TRACK_TYPE_SUBTITLE: u64 = 17;
+
+/// An EBML element inside an in-memory buffer
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Element<'a> {
+    pub id: u32,
+    pub data: &'a [u8],
+}
+
+impl<'a> Element<'a> {
+    pub fn uint(&self) -> u64 {
+        read_uint(self.data)
+    }
+
+    pub fn float(&self) -> Option<f64> {
+        match self.data.len() {
+            4 => Some(f32::from_be_bytes([self.data[0], self.data[1], self.data[2], self.data[3]]) as f64),
+            8 => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(self.data);
+                Some(f64::from_be_bytes(buf))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn string(&self) -> Option<String> {
+        decode_string(self.data)
+    }
+
+    pub fn children(&self) -> Result<Vec<Element<'a>>> {
+        elements(self.data)
+    }
+}
+
+/// Read a big-endian unsigned integer of up to eight bytes
+pub(crate) fn read_uint(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0u64, |acc, b| (acc << 8) | *b as u64)
+}
+
+/// Length of a variable-size integer from its first byte
+fn vint_length(first: u8) -> Result<usize> {
+    if first == 0 {
+        return Err(RustFlixError::media_processing("Invalid EBML variable-size integer"));
+    }
+    Ok(first.leading_zeros() as usize + 1)
+}
+
+/// Decode an element ID (marker bits kept) from a buffer
+fn parse_id(data: &[u8]) -> Result<(u32, usize)> {
+    let first = *data.first().ok_or_else(truncated)?;
+    let len = vint_length(first)?;
+    if len > 4 || data.len() < len {
+        return Err(truncated());
+    }
+    Ok((read_uint(&data[..len]) as u32, len))
+}
+
+/// Decode an element data size from a buffer; `None` means unknown size
+fn parse_size(data: &[u8]) -> Result<(Option<u64>, usize)> {
+    let first = *data.first().ok_or_else(truncated)?;
+    let len = vint_length(first)?;
+    if data.len() < len {
+        return Err(truncated());
+    }
+    let mask = if len == 8 { 0 } else { 0xffu8 >> len };
+    let mut value = (first & mask) as u64;
+    let mut all_ones = first & mask == mask;
+    for b in &data[1..len] {
+        value = (value << 8) | *b as u64;
+        all_ones &= *b == 0xff;
+    }
+    Ok((if all_ones { None } else { Some(value) }, len))
+}
+
+/// Split a buffer into its child elements
+pub(crate) fn elements(data: &[u8]) -> Result<Vec<Element<'_>>> {
+    let mut out = Vec::new();
+    let mut pos = 0usize;
+
+    while pos < data.len() {
+        let (id, id_len) = parse_id(&data[pos..])?;
+        let (size, size_len) = parse_size(&data[pos + id_len..])?;
+        let start = pos + id_len + size_len;
+        let end = match size {
+            Some(size) => start
+                .checked_add(size as usize)
+                .filter(|end| *end <= data.len())
+                .ok_or_else(truncated)?,
+            None => data.len(),
+        };
+        out.push(Element {
+            id,
+            data: &data[start..end],
+        });
+        pos = end;
+    }
+
+    Ok(out)
+}
+
+/// Find the first child element with the given ID
+pub(crate) fn find<'a>(elements: &[Element<'a>], id: u32) -> Option<Element<'a>> {
+    elements.iter().find(|e| e.id == id).copied()
+}
+
+/// Element header read from a stream
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    id: u32,
+    size: Option<u64>,
+    header_len: u64,
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<Header> {
+    let mut buf = [0u8; 12];
+    reader.read_exact(&mut buf[..1])?;
+    let id_len = vint_length(buf[0])?;
+    if id_len > 4 {
+        return Err(RustFlixError::media_processing("Invalid EBML element ID"));
+    }
+    reader.read_exact(&mut buf[1..id_len])?;
+    let id = read_uint(&buf[..id_len]) as u32;
+
+    reader.read_exact(&mut buf[id_len..id_len + 1])?;
+    let size_len = vint_length(buf[id_len])?;
+    reader.read_exact(&mut buf[id_len + 1..id_len + size_len])?;
+    let (size, _) = parse_size(&buf[id_len..id_len + size_len])?;
+
+    Ok(Header {
+        id,
+        size,
+        header_len: (id_len + size_len) as u64,
+    })
+}
+
+fn read_payload<R: Read>(reader: &mut R, size: u64) -> Result<Vec<u8>> {
+    if size > MAX_HEADER_SIZE {
+        return Err(RustFlixError::media_processing("Matroska element is too large"));
+    }
+    let mut payload = vec![0u8; size as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Top-level segment children needed for analysis, read into memory
+#[derive(Debug, Default)]
+pub(crate) struct SegmentElements {
+    /// Segment-level elements keyed by ID, in the order they were read
+    pub elements: Vec<(u32, Vec<u8>)>,
+}
+
+impl SegmentElements {
+    pub fn get(&self, id: u32) -> Option<&[u8]> {
+        self.elements
+            .iter()
+            .find(|(element_id, _)| *element_id == id)
+            .map(|(_, data)| data.as_slice())
+    }
+}
+
+/// Walk the EBML header and Segment, reading the wanted top-level elements
+///
+/// Elements located after the first Cluster are reached through the SeekHead.
+pub(crate) fn read_segment<R: Read + Seek>(
+    reader: &mut R,
+    file_size: u64,
+    wanted: &[u32],
+) -> Result<SegmentElements> {
+    reader.seek(SeekFrom::Start(0))?;
+
+    let header = read_header(reader)
+        .map_err(|_| RustFlixError::media_processing("Not a Matroska file"))?;
+    if header.id != EBML_HEADER {
+        return Err(RustFlixError::media_processing("Not a Matroska file"));
+    }
+    let ebml = read_payload(reader, header.size.ok_or_else(truncated)?)?;
+    let doc_type = find(&elements(&ebml)?, DOC_TYPE).and_then(|e| e.string());
+    if !matches!(doc_type.as_deref(), Some("matroska") | Some("webm")) {
+        return Err(RustFlixError::media_processing("Unsupported EBML document type"));
+    }
+
+    let segment = read_header(reader)?;
+    if segment.id != SEGMENT {
+        return Err(RustFlixError::media_processing("Missing Matroska segment"));
+    }
+    let segment_start = reader.stream_position()?;
+    let segment_end = segment
+        .size
+        .map(|size| (segment_start + size).min(file_size))
+        .unwrap_or(file_size);
+
+    let mut found = SegmentElements::default();
+    let mut seek_positions: Vec<(u32, u64)> = Vec::new();
+    let mut offset = segment_start;
+
+    while offset < segment_end {
+        // A truncated tail (e.g. an interrupted copy) still leaves usable headers
+        let Ok(header) = read_header(reader) else {
+            break;
+        };
+        if header.id == CLUSTER {
+            break;
+        }
+        let Some(size) = header.size else {
+            break;
+        };
+
+        if header.id == SEEK_HEAD {
+            let payload = read_payload(reader, size)?;
+            seek_positions.extend(parse_seek_head(&payload)?);
+        } else if wanted.contains(&header.id) && found.get(header.id).is_none() {
+            found.elements.push((header.id, read_payload(reader, size)?));
+        }
+
+        offset += header.header_len + size;
+        reader.seek(SeekFrom::Start(offset))?;
+    }
+
+    for id in wanted {
+        if found.get(*id).is_some() {
+            continue;
+        }
+        let Some((_, position)) = seek_positions.iter().find(|(seek_id, _)| seek_id == id) else {
+            continue;
+        };
+        let target = segment_start + position;
+        if target >= segment_end {
+            continue;
+        }
+        reader.seek(SeekFrom::Start(target))?;
+        let header = read_header(reader)?;
+        if header.id == *id {
+            if let Some(size) = header.size {
+                found.elements.push((*id, read_payload(reader, size)?));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+fn parse_seek_head(data: &[u8]) -> Result<Vec<(u32, u64)>> {
+    let mut positions = Vec::new();
+    for seek in elements(data)?.into_iter().filter(|e| e.id == SEEK) {
+        let children = seek.children()?;
+        let id = find(&children, SEEK_ID).map(|e| e.uint() as u32);
+        let position = find(&children, SEEK_POSITION).map(|e| e.uint());
+        if let (Some(id), Some(position)) = (id, position) {
+            positions.push((id, position));
+        }
+    }
+    Ok(positions)
+}
+
+/// Probe a Matroska/WebM file
+pub(crate) fn probe<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<MediaInfo> {
+    let segment = read_segment(reader, file_size, &[INFO, TRACKS])?;
+    let mut info = MediaInfo::default();
+
+    if let Some(data) = segment.get(INFO) {
+        info.duration = parse_info(data)?;
+    }
+
+    match segment.get(TRACKS) {
+        Some(data) => parse_tracks(data, &mut info)?,
+        None => return Err(RustFlixError::media_processing("No Matroska Tracks element found")),
+    }
+
+    Ok(info)
+}
+
+/// Read the timestamp scale (nanoseconds per tick) from an Info element
+pub(crate) fn timestamp_scale(info: &[Element<'_>]) -> u64 {
+    find(info, TIMESTAMP_SCALE)
+        .map(|e| e.uint())
+        .filter(|scale| *scale > 0)
+        .unwrap_or(1_000_000)
+}
+
+fn parse_info(data: &[u8]) -> Result<Option<f64>> {
+    let info = elements(data)?;
+    let scale = timestamp_scale(&info);
+    Ok(find(&info, DURATION)
+        .and_then(|e| e.float())
+        .filter(|duration| *duration > 0.0)
+        .map(|duration| duration * scale as f64 / 1_000_000_000.0))
+}
+
+fn parse_tracks(data: &[u8], info: &mut MediaInfo) -> Result<()> {
+    let entries = elements(data)?
+        .into_iter()
+        .filter(|e| e.id == TRACK_ENTRY)
+        .collect::<Vec<_>>();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let track = entry.children()?;
+        let codec_id = find(&track, CODEC_ID).and_then(|e| e.string()).unwrap_or_default();
+        let codec_private = find(&track, CODEC_PRIVATE).map(|e| e.data).unwrap_or_default();
+        let language = find(&track, LANGUAGE_BCP47)
+            .or_else(|| find(&track, LANGUAGE))
+            .and_then(|e| e.string())
+            .unwrap_or_else(|| "eng".to_string());
+        let language = normalize_language(&language);
+
+        match find(&track, TRACK_TYPE).map(|e| e.uint()) {
+            Some(TRACK_TYPE_VIDEO) => {
+                let video = find(&track, VIDEO).map(|e| e.children()).transpose()?.unwrap_or_default();
+                let colour = find(&video, COLOUR).map(|e| e.children()).transpose()?.unwrap_or_default();
+                let details = codec_details(&codec_id, codec_private);
+
+                info.streams.video.push(VideoCodec {
+                    name: video_codec_name(&codec_id),
+                    profile: details.profile,
+                    level: details.level,
+                    width: find(&video, PIXEL_WIDTH).map(|e| e.uint() as u32).unwrap_or(0),
+                    height: find(&video, PIXEL_HEIGHT).map(|e| e.uint() as u32).unwrap_or(0),
+                    frame_rate: find(&track, DEFAULT_DURATION)
+                        .map(|e| e.uint())
+                        .filter(|ns| *ns > 0)
+                        .and_then(|ns| round_frame_rate(1_000_000_000.0 / ns as f64)),
+                    bit_depth: find(&colour, BITS_PER_CHANNEL)
+                        .map(|e| e.uint() as u8)
+                        .filter(|depth| *depth > 0)
+                        .or(details.bit_depth),
+                    color_space: find(&colour, MATRIX_COEFFICIENTS)
+                        .and_then(|e| color_space_name(e.uint())),
+                });
+            }
+            Some(TRACK_TYPE_AUDIO) => {
+                let audio = find(&track, AUDIO).map(|e| e.children()).transpose()?.unwrap_or_default();
+
+                info.streams.audio.push(AudioCodec {
+                    name: audio_codec_name(&codec_id),
+                    channels: find(&audio, CHANNELS).map(|e| e.uint().min(255) as u8).unwrap_or(1),
+                    sample_rate: find(&audio, SAMPLING_FREQUENCY)
+                        .and_then(|e| e.float())
+                        .map(|rate| rate as u32)
+                        .unwrap_or(8000),
+                    bit_depth: find(&audio, BIT_DEPTH).map(|e| e.uint() as u8),
+                    bitrate: None,
+                    language,
+                });
+            }
+            Some(TRACK_TYPE_SUBTITLE) => {
+                info.streams.subtitles.push(SubtitleTrack {
+                    index: index as u32,
+                    language,
+                    title: find(&track, NAME).and_then(|e| e.string()),
+                    codec: subtitle_codec_name(&codec_id),
+                    forced: find(&track, FLAG_FORCED).map(|e| e.uint() != 0).unwrap_or(false),
+                    default: find(&track, FLAG_DEFAULT).map(|e| e.uint() != 0).unwrap_or(true),
+                    path: None,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn codec_details(codec_id: &str, codec_private: &[u8]) -> CodecDetails {
+    match codec_id {
+        "V_MPEG4/ISO/AVC" => parse_avc_config(codec_private),
+        "V_MPEGH/ISO/HEVC" => parse_hevc_config(codec_private),
+        "V_AV1" => parse_av1_config(codec_private),
+        _ => CodecDetails::default(),
+    }
+}
+
+fn video_codec_name(codec_id: &str) -> String {
+    let name = match codec_id {
+        "V_MPEG4/ISO/AVC" => "h264",
+        "V_MPEGH/ISO/HEVC" => "hevc",
+        "V_AV1" => "av1",
+        "V_VP8" => "vp8",
+        "V_VP9" => "vp9",
+        "V_MPEG1" => "mpeg1video",
+        "V_MPEG2" => "mpeg2video",
+        "V_THEORA" => "theora",
+        "V_MJPEG" => "mjpeg",
+        "V_PRORES" => "prores",
+        id if id.starts_with("V_MPEG4/ISO/") => "mpeg4",
+        id if id.starts_with("V_MS/VFW") => "vfw",
+        id => return id.trim_start_matches("V_").to_lowercase(),
+    };
+    name.to_string()
+}
+
+fn audio_codec_name(codec_id: &str) -> String {
+    let name = match codec_id {
+        "A_AC3" => "ac3",
+        "A_EAC3" => "eac3",
+        "A_TRUEHD" => "truehd",
+        "A_OPUS" => "opus",
+        "A_VORBIS" => "vorbis",
+        "A_FLAC" => "flac",
+        "A_ALAC" => "alac",
+        "A_MPEG/L3" => "mp3",
+        "A_MPEG/L2" => "mp2",
+        id if id.starts_with("A_AAC") => "aac",
+        id if id.starts_with("A_DTS") => "dts",
+        id if id.starts_with("A_PCM") => "pcm",
+        id => return id.trim_start_matches("A_").to_lowercase(),
+    };
+    name.to_string()
+}
+
+fn subtitle_codec_name(codec_id: &str) -> String {
+    let name = match codec_id {
+        "S_TEXT/UTF8" | "S_TEXT/ASCII" => "subrip",
+        "S_TEXT/ASS" | "S_ASS" => "ass",
+        "S_TEXT/SSA" | "S_SSA" => "ssa",
+        "S_TEXT/WEBVTT" => "webvtt",
+        "S_HDMV/PGS" => "hdmv_pgs_subtitle",
+        "S_HDMV/TEXTST" => "hdmv_text_subtitle",
+        "S_VOBSUB" => "dvd_subtitle",
+        "S_DVBSUB" => "dvb_subtitle",
+        id => return id.trim_start_matches("S_").to_lowercase(),
+    };
+    name.to_string()
+}
+
+/// Read the chapters of the default edition, with the segment duration
+pub(crate) fn read_chapters<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<(Vec<ChapterMark>, Option<f64>)> {
+    let segment = read_segment(reader, file_size, &[INFO, CHAPTERS])?;
+    let duration = match segment.get(INFO) {
+        Some(data) => parse_info(data)?,
+        None => None,
+    };
+    let chapters = match segment.get(CHAPTERS) {
+        Some(data) => parse_chapters(data)?,
+        None => Vec::new(),
+    };
+    Ok((chapters, duration))
+}
+
+/// Parse the top-level chapter atoms of the default edition
+///
+/// The edition flagged as default wins, otherwise the first one that is not
+/// hidden. Hidden and disabled chapters are skipped; nested chapters are not
+/// shown by players and are ignored.
+pub(crate) fn parse_chapters(data: &[u8]) -> Result<Vec<ChapterMark>> {
+    let editions = elements(data)?
+        .into_iter()
+        .filter(|e| e.id == EDITION_ENTRY)
+        .map(|e| e.children())
+        .collect::<Result<Vec<_>>>()?;
+    let flag = |children: &[Element<'_>], id: u32| find(children, id).is_some_and(|e| e.uint() == 1);
+    let Some(edition) = editions
+        .iter()
+        .find(|edition| flag(edition, EDITION_FLAG_DEFAULT))
+        .or_else(|| editions.iter().find(|edition| !flag(edition, EDITION_FLAG_HIDDEN)))
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut chapters = Vec::new();
+    for atom in edition.iter().filter(|e| e.id == CHAPTER_ATOM) {
+        let children = atom.children()?;
+        let enabled = find(&children, CHAPTER_FLAG_ENABLED).is_none_or(|e| e.uint() == 1);
+        if flag(&children, CHAPTER_FLAG_HIDDEN) || !enabled {
+            continue;
+        }
+        let Some(start) = find(&children, CHAPTER_TIME_START).map(|e| e.uint()) else {
+            continue;
+        };
+
+        let title = children
+            .iter()
+            .filter(|e| e.id == CHAPTER_DISPLAY)
+            .find_map(|display| find(&display.children().ok()?, CHAP_STRING)?.string());
+        chapters.push(ChapterMark {
+            start: start as f64 / 1_000_000_000.0,
+            end: find(&children, CHAPTER_TIME_END).map(|e| e.uint() as f64 / 1_000_000_000.0),
+            title,
+        });
+    }
+    Ok(chapters)
+}
+
+/// Read the times of the cue points of the first video track
+///
+/// Muxers place cue points on keyframes, so the Cues element doubles as the
+/// keyframe index; files without one yield no keyframes.
+pub(crate) fn read_keyframes<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Vec<f64>> {
+    let segment = read_segment(reader, file_size, &[INFO, TRACKS, CUES])?;
+    let scale = match segment.get(INFO) {
+        Some(data) => timestamp_scale(&elements(data)?),
+        None => 1_000_000,
+    };
+
+    let mut video_track = None;
+    if let Some(data) = segment.get(TRACKS) {
+        for entry in elements(data)?.into_iter().filter(|e| e.id == TRACK_ENTRY) {
+            let track = entry.children()?;
+            if find(&track, TRACK_TYPE).map(|e| e.uint()) == Some(TRACK_TYPE_VIDEO) {
+                video_track = find(&track, TRACK_NUMBER).map(|e| e.uint());
+                break;
+            }
+        }
+    }
+    let (Some(video_track), Some(cues)) = (video_track, segment.get(CUES)) else {
+        return Ok(Vec::new());
+    };
+
+    let mut keyframes = Vec::new();
+    for point in elements(cues)?.into_iter().filter(|e| e.id == CUE_POINT) {
+        let children = point.children()?;
+        let on_video = children
+            .iter()
+            .filter(|e| e.id == CUE_TRACK_POSITIONS)
+            .any(|positions| {
+                positions
+                    .children()
+                    .is_ok_and(|positions| find(&positions, CUE_TRACK).is_some_and(|e| e.uint() == video_track))
+            });
+        if let (true, Some(time)) = (on_video, find(&children, CUE_TIME)) {
+            keyframes.push(time.uint() as f64 * scale as f64 / 1_000_000_000.0);
+        }
+    }
+    Ok(keyframes)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    pub(crate) fn encode_id(id: u32) -> Vec<u8> {
+        let bytes = id.to_be_bytes();
+        let skip = bytes.iter().position(|b| *b != 0).unwrap_or(3);
+        bytes[skip..].to_vec()
+    }
+
+    pub(crate) fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = encode_id(id);
+        // Always use an 8-byte size to keep the helper simple
+        out.push(0x01);
+        out.extend_from_slice(&(payload.len() as u64).to_be_bytes()[1..]);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    pub(crate) fn uint_element(id: u32, value: u64) -> Vec<u8> {
+        element(id, &value.to_be_bytes())
+    }
+
+    pub(crate) fn ebml_header(doc_type: &str) -> Vec<u8> {
+        element(EBML_HEADER, &element(DOC_TYPE, doc_type.as_bytes()))
+    }
+
+    fn track(number: u64, track_type: u64, codec_id: &str, extra: Vec<u8>) -> Vec<u8> {
+        let mut entry = uint_element(TRACK_NUMBER, number);
+        entry.extend(uint_element(TRACK_TYPE, track_type));
+        entry.extend(element(CODEC_ID, codec_id.as_bytes()));
+        entry.extend(extra);
+        element(TRACK_ENTRY, &entry)
+    }
+
+    /// Build a small Matroska file with HEVC video, two audio tracks and subtitles
+    pub(crate) fn sample_file() -> Vec<u8> {
+        let mut info = uint_element(TIMESTAMP_SCALE, 1_000_000);
+        info.extend(element(DURATION, &5_400_000.0f64.to_be_bytes()));
+
+        let mut hvcc = vec![0u8; 23];
+        hvcc[1] = 2;
+        hvcc[12] = 150;
+        hvcc[17] = 0xfa;
+        let mut colour = uint_element(MATRIX_COEFFICIENTS, 9);
+        colour.extend(uint_element(BITS_PER_CHANNEL, 10));
+        let mut video = uint_element(PIXEL_WIDTH, 3840);
+        video.extend(uint_element(PIXEL_HEIGHT, 2160));
+        video.extend(element(COLOUR, &colour));
+        let mut video_extra = element(VIDEO, &video);
+        video_extra.extend(uint_element(DEFAULT_DURATION, 41_708_333));
+        video_extra.extend(element(CODEC_PRIVATE, &hvcc));
+
+        let mut audio = uint_element(CHANNELS, 8);
+        audio.extend(element(SAMPLING_FREQUENCY, &48000.0f32.to_be_bytes()));
+        let mut truehd_extra = element(AUDIO, &audio);
+        truehd_extra.extend(element(LANGUAGE, b"eng"));
+
+        let mut commentary_audio = uint_element(CHANNELS, 2);
+        commentary_audio.extend(element(SAMPLING_FREQUENCY, &48000.0f64.to_be_bytes()));
+        let mut commentary_extra = element(AUDIO, &commentary_audio);
+        commentary_extra.extend(element(LANGUAGE, b"ger"));
+        commentary_extra.extend(uint_element(FLAG_DEFAULT, 0));
+
+        let mut forced_extra = element(LANGUAGE, b"fre");
+        forced_extra.extend(uint_element(FLAG_FORCED, 1));
+        forced_extra.extend(element(NAME, b"Forced"));
+
+        let mut tracks = track(1, TRACK_TYPE_VIDEO, "V_MPEGH/ISO/HEVC", video_extra);
+        tracks.extend(track(2, TRACK_TYPE_AUDIO, "A_TRUEHD", truehd_extra));
+        tracks.extend(track(3, TRACK_TYPE_AUDIO, "A_AC3", commentary_extra));
+        tracks.extend(track(4, TRACK_TYPE_SUBTITLE, "S_HDMV/PGS", forced_extra));
+
+        let mut segment = element(INFO, &info);
+        segment.extend(element(TRACKS, &tracks));
+        segment.extend(element(CLUSTER, &[0u8; 64]));
+
+        let mut file = ebml_header("matroska");
+        file.extend(element(SEGMENT, &segment));
+        file
+    }
+
+    fn chapter_atom(start_ms: u64, title: Option<&str>, extra: Vec<u8>) -> Vec<u8> {
+        let mut atom = uint_element(CHAPTER_TIME_START, start_ms * 1_000_000);
+        if let Some(title) = title {
+            atom.extend(element(CHAPTER_DISPLAY, &element(CHAP_STRING, title.as_bytes())));
+        }
+        atom.extend(extra);
+        element(CHAPTER_ATOM, &atom)
+    }
+
+    #[test]
+    fn test_read_chapters() {
+        let mut hidden_edition = uint_element(EDITION_FLAG_HIDDEN, 1);
+        hidden_edition.extend(chapter_atom(0, Some("Hidden"), Vec::new()));
+
+        let mut edition = uint_element(EDITION_FLAG_DEFAULT, 1);
+        edition.extend(chapter_atom(0, Some("Opening"), uint_element(CHAPTER_TIME_END, 90_000_000_000)));
+        edition.extend(chapter_atom(60_000, Some("Skipped"), uint_element(CHAPTER_FLAG_HIDDEN, 1)));
+        edition.extend(chapter_atom(90_000, None, Vec::new()));
+        edition.extend(chapter_atom(120_000, Some("Disabled"), uint_element(CHAPTER_FLAG_ENABLED, 0)));
+
+        let mut chapters = element(EDITION_ENTRY, &hidden_edition);
+        chapters.extend(element(EDITION_ENTRY, &edition));
+        let mut info = uint_element(TIMESTAMP_SCALE, 1_000_000);
+        info.extend(element(DURATION, &5_400_000.0f64.to_be_bytes()));
+        let mut segment = element(INFO, &info);
+        segment.extend(element(CHAPTERS, &chapters));
+        let mut file = ebml_header("matroska");
+        file.extend(element(SEGMENT, &segment));
+
+        let (chapters, duration) = read_chapters(&mut Cursor::new(&file), file.len() as u64).unwrap();
+        assert_eq!(duration, Some(5400.0));
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title.as_deref(), Some("Opening"));
+        assert_eq!(chapters[0].end, Some(90.0));
+        assert_eq!((chapters[1].start, chapters[1].end), (90.0, None));
+        assert!(chapters[1].title.is_none());
+    }
+
+    #[test]
+    fn test_read_keyframes() {
+        let cue_point = |time: u64, track: u64| {
+            let mut point = uint_element(CUE_TIME, time);
+            point.extend(element(CUE_TRACK_POSITIONS, &uint_element(CUE_TRACK, track)));
+            element(CUE_POINT, &point)
+        };
+        let mut cues = cue_point(0, 1);
+        cues.extend(cue_point(2500, 2));
+        cues.extend(cue_point(4000, 1));
+
+        let mut tracks = track(1, TRACK_TYPE_AUDIO, "A_OPUS", Vec::new());
+        tracks.extend(track(2, TRACK_TYPE_VIDEO, "V_VP9", Vec::new()));
+        let mut segment = element(INFO, &uint_element(TIMESTAMP_SCALE, 1_000_000));
+        segment.extend(element(TRACKS, &tracks));
+        segment.extend(element(CUES, &cues));
+        let mut file = ebml_header("webm");
+        file.extend(element(SEGMENT, &segment));
+
+        let keyframes = read_keyframes(&mut Cursor::new(&file), file.len() as u64).unwrap();
+        assert_eq!(keyframes, vec![2.5]);
+    }
+
+    #[test]
+    fn test_probe_sample_file() {
+        let data = sample_file();
+        let info = probe(&mut Cursor::new(&data), data.len() as u64).unwrap();
+
+        assert_eq!(info.duration, Some(5400.0));
+
+        let video = &info.streams.video[0];
+        assert_eq!(video.name, "hevc");
+        assert_eq!(video.profile.as_deref(), Some("Main 10"));
+        assert_eq!(video.level.as_deref(), Some("5"));
+        assert_eq!((video.width, video.height), (3840, 2160));
+        assert_eq!(video.frame_rate, Some(23.976));
+        assert_eq!(video.bit_depth, Some(10));
+        assert_eq!(video.color_space.as_deref(), Some("bt2020nc"));
+
+        assert_eq!(info.streams.audio.len(), 2);
+        assert_eq!(info.streams.audio[0].name, "truehd");
+        assert_eq!(info.streams.audio[0].channels, 8);
+        assert_eq!(info.streams.audio[0].sample_rate, 48000);
+        assert_eq!(info.streams.audio[0].language.as_deref(), Some("eng"));
+        assert_eq!(info.streams.audio[1].name, "ac3");
+        assert_eq!(info.streams.audio[1].language.as_deref(), Some("ger"));
+
+        let subtitle = &info.streams.subtitles[0];
+        assert_eq!(subtitle.index, 3);
+        assert_eq!(subtitle.codec, "hdmv_pgs_subtitle");
+        assert_eq!(subtitle.language.as_deref(), Some("fre"));
+        assert_eq!(subtitle.title.as_deref(), Some("Forced"));
+        assert!(subtitle.forced);
+        assert!(subtitle.default);
+    }
+
+    #[test]
+    fn test_tracks_after_cluster_via_seek_head() {
+        let tracks = track(1, TRACK_TYPE_AUDIO, "A_OPUS", Vec::new());
+        let tracks = element(TRACKS, &tracks);
+
+        let cluster = element(CLUSTER, &[0u8; 32]);
+        let seek_head = |position: u64| {
+            let mut seek = element(SEEK_ID, &encode_id(TRACKS));
+            seek.extend(uint_element(SEEK_POSITION, position));
+            element(SEEK_HEAD, &element(SEEK, &seek))
+        };
+        let position = (seek_head(0).len() + cluster.len()) as u64;
+
+        let mut segment = seek_head(position);
+        segment.extend(cluster);
+        segment.extend(tracks);
+
+        let mut data = ebml_header("webm");
+        data.extend(element(SEGMENT, &segment));
+
+        let info = probe(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert_eq!(info.streams.audio[0].name, "opus");
+        assert_eq!(info.streams.audio[0].language.as_deref(), Some("eng"));
+    }
+
+    #[test]
+    fn test_not_matroska() {
+        let data = b"fake video data".to_vec();
+        assert!(probe(&mut Cursor::new(&data), data.len() as u64).is_err());
+    }
+}
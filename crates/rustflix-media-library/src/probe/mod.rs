@@ -0,0 +1,498 @@
+//! Pure-Rust container probing
+//!
+//! Reads container headers directly (ISO BMFF, Matroska/WebM and AVI) and
+//! the stream headers of plain audio files so media analysis works on
+//! minimal deployments without FFmpeg. Only header structures are read;
+//! sample data is skipped.
+//!
+//! Reading is blocking I/O, so these functions belong on a blocking thread
+//! such as `tokio::task::spawn_blocking`.
+
+pub(crate) mod audio;
+pub(crate) mod avi;
+pub(crate) mod matroska;
+pub(crate) mod mp4;
+
+use crate::analyzer::{Chapter, MediaInfo};
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+/// Largest header structure we are willing to buffer in memory (64 MiB)
+pub(crate) const MAX_HEADER_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Check whether a format has a native container parser
+pub fn supports_format(format: MediaFormat) -> bool {
+    matches!(
+        format,
+        MediaFormat::Mp4
+            | MediaFormat::M4v
+            | MediaFormat::Mov
+            | MediaFormat::M4a
+            | MediaFormat::M4b
+            | MediaFormat::Mkv
+            | MediaFormat::Webm
+            | MediaFormat::Avi
+            | MediaFormat::Mp3
+            | MediaFormat::Aac
+            | MediaFormat::Flac
+            | MediaFormat::Ogg
+            | MediaFormat::Wav
+    )
+}
+
+/// Probe a file on disk using the parser for its container format
+pub fn probe_file(path: &Path, format: MediaFormat) -> Result<MediaInfo> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    probe_from(&mut BufReader::new(file), file_size, format)
+}
+
+/// Probe a file read through `reader` using the parser for its container format
+pub fn probe_from<R: Read + Seek>(reader: &mut R, file_size: u64, format: MediaFormat) -> Result<MediaInfo> {
+    let mut info = match format {
+        MediaFormat::Mp4 | MediaFormat::M4v | MediaFormat::Mov | MediaFormat::M4a | MediaFormat::M4b => {
+            mp4::probe(reader, file_size)?
+        }
+        MediaFormat::Mkv | MediaFormat::Webm => matroska::probe(reader, file_size)?,
+        MediaFormat::Avi => avi::probe(reader, file_size)?,
+        MediaFormat::Mp3 | MediaFormat::Aac | MediaFormat::Flac | MediaFormat::Ogg | MediaFormat::Wav => {
+            audio::probe(reader, file_size, format)?
+        }
+        _ => {
+            return Err(RustFlixError::media_processing(format!(
+                "No container parser for {:?}",
+                format
+            )))
+        }
+    };
+
+    summarize(&mut info, file_size);
+    Ok(info)
+}
+
+/// Check whether a format can carry chapters we know how to read
+pub fn supports_chapters(format: MediaFormat) -> bool {
+    matches!(
+        format,
+        MediaFormat::Mp4
+            | MediaFormat::M4v
+            | MediaFormat::Mov
+            | MediaFormat::M4a
+            | MediaFormat::M4b
+            | MediaFormat::Mkv
+            | MediaFormat::Webm
+    )
+}
+
+/// Read the chapters of a file using the parser for its container format
+pub fn read_chapters(path: &Path, format: MediaFormat) -> Result<Vec<Chapter>> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    read_chapters_from(&mut BufReader::new(file), file_size, format)
+}
+
+/// Read the chapters of a file read through `reader`
+pub fn read_chapters_from<R: Read + Seek>(reader: &mut R, file_size: u64, format: MediaFormat) -> Result<Vec<Chapter>> {
+    let (marks, duration) = match format {
+        MediaFormat::Mp4 | MediaFormat::M4v | MediaFormat::Mov | MediaFormat::M4a | MediaFormat::M4b => {
+            mp4::read_chapters(reader, file_size)?
+        }
+        MediaFormat::Mkv | MediaFormat::Webm => matroska::read_chapters(reader, file_size)?,
+        _ => {
+            return Err(RustFlixError::media_processing(format!(
+                "No chapter parser for {:?}",
+                format
+            )))
+        }
+    };
+
+    Ok(finish_chapters(marks, duration))
+}
+
+/// Check whether a format has a keyframe index we know how to read
+pub fn supports_keyframes(format: MediaFormat) -> bool {
+    matches!(
+        format,
+        MediaFormat::Mp4 | MediaFormat::M4v | MediaFormat::Mov | MediaFormat::Mkv | MediaFormat::Webm
+    )
+}
+
+/// Read the presentation times of the video keyframes of a file, in seconds
+pub fn read_keyframes(path: &Path, format: MediaFormat) -> Result<Vec<f64>> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    read_keyframes_from(&mut BufReader::new(file), file_size, format)
+}
+
+/// Read the video keyframe times of a file read through `reader`
+///
+/// Times are sorted; an empty list means the container has no keyframe
+/// index.
+pub fn read_keyframes_from<R: Read + Seek>(reader: &mut R, file_size: u64, format: MediaFormat) -> Result<Vec<f64>> {
+    let mut keyframes = match format {
+        MediaFormat::Mp4 | MediaFormat::M4v | MediaFormat::Mov => mp4::read_keyframes(reader, file_size)?,
+        MediaFormat::Mkv | MediaFormat::Webm => matroska::read_keyframes(reader, file_size)?,
+        _ => {
+            return Err(RustFlixError::media_processing(format!(
+                "No keyframe parser for {:?}",
+                format
+            )))
+        }
+    };
+
+    keyframes.retain(|time| time.is_finite() && *time >= 0.0);
+    keyframes.sort_by(f64::total_cmp);
+    keyframes.dedup();
+    Ok(keyframes)
+}
+
+/// Chapter start as read from a container, before end times are resolved
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ChapterMark {
+    /// Seconds from the start of the file
+    pub start: f64,
+    pub end: Option<f64>,
+    pub title: Option<String>,
+}
+
+/// Order chapters and resolve missing end times and titles
+///
+/// A chapter without an end runs until the next one starts, or until the
+/// end of the file for the last one.
+pub(crate) fn finish_chapters(mut marks: Vec<ChapterMark>, duration: Option<f64>) -> Vec<Chapter> {
+    marks.retain(|mark| mark.start.is_finite() && mark.start >= 0.0);
+    marks.sort_by(|a, b| a.start.total_cmp(&b.start));
+    marks.dedup_by(|b, a| a.start == b.start);
+
+    let starts = marks.iter().map(|mark| mark.start).skip(1).collect::<Vec<_>>();
+    marks
+        .into_iter()
+        .enumerate()
+        .map(|(index, mark)| {
+            let next = starts.get(index).copied().or(duration);
+            let end = mark.end.filter(|end| *end > mark.start).or(next).unwrap_or(mark.start).max(mark.start);
+            Chapter {
+                id: index as u32 + 1,
+                start_time: mark.start,
+                end_time: end,
+                title: mark.title.unwrap_or_else(|| format!("Chapter {}", index + 1)),
+            }
+        })
+        .collect()
+}
+
+/// Fill the summary fields of `MediaInfo` from the parsed streams
+pub(crate) fn summarize(info: &mut MediaInfo, file_size: u64) {
+    if let Some(video) = info.streams.video.first() {
+        info.width = Some(video.width);
+        info.height = Some(video.height);
+        info.video_codec = Some(video.name.clone());
+        info.frame_rate = video.frame_rate;
+    }
+
+    if let Some(audio) = info.streams.audio.first() {
+        info.audio_codec = Some(audio.name.clone());
+    }
+
+    if info.bitrate.is_none() {
+        info.bitrate = info
+            .duration
+            .filter(|duration| *duration > 0.0)
+            .map(|duration| (file_size as f64 * 8.0 / duration) as u64);
+    }
+}
+
+/// Big-endian cursor over an in-memory header structure
+#[derive(Debug, Clone)]
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    /// Create a new reader over a byte slice
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Number of unread bytes
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Unread bytes
+    pub fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    /// Skip `len` bytes
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    /// Read `len` raw bytes
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(truncated());
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn u24(&mut self) -> Result<u32> {
+        let b = self.bytes(3)?;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        let b = self.bytes(8)?;
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(b);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    pub fn fourcc(&mut self) -> Result<[u8; 4]> {
+        let b = self.bytes(4)?;
+        Ok([b[0], b[1], b[2], b[3]])
+    }
+}
+
+/// Error for a header structure that ends early
+pub(crate) fn truncated() -> RustFlixError {
+    RustFlixError::media_processing("Unexpected end of container header")
+}
+
+/// Little-endian integers at an offset of a buffer, for the formats not
+/// read through `ByteReader`
+pub(crate) fn le16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..offset + 2).ok_or_else(truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub(crate) fn le32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or_else(truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub(crate) fn le64(data: &[u8], offset: usize) -> Result<u64> {
+    let bytes = data.get(offset..offset + 8).ok_or_else(truncated)?;
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Decode a NUL-padded UTF-8 string
+pub(crate) fn decode_string(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    let value = String::from_utf8_lossy(&data[..end]).trim().to_string();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Normalize a container language code, dropping "undetermined"
+pub(crate) fn normalize_language(language: &str) -> Option<String> {
+    let language = language.trim();
+    if language.is_empty() || language.eq_ignore_ascii_case("und") {
+        None
+    } else {
+        Some(language.to_lowercase())
+    }
+}
+
+/// Map ISO/IEC 23091-4 matrix coefficients to a color space name
+pub(crate) fn color_space_name(matrix_coefficients: u64) -> Option<String> {
+    let name = match matrix_coefficients {
+        0 => "gbr",
+        1 => "bt709",
+        4 => "fcc",
+        5 => "bt470bg",
+        6 => "smpte170m",
+        7 => "smpte240m",
+        8 => "ycgco",
+        9 => "bt2020nc",
+        10 => "bt2020c",
+        14 => "ictcp",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// Codec profile details decoded from a codec configuration record
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct CodecDetails {
+    pub profile: Option<String>,
+    pub level: Option<String>,
+    pub bit_depth: Option<u8>,
+}
+
+/// Decode an AVCDecoderConfigurationRecord (`avcC`)
+pub(crate) fn parse_avc_config(data: &[u8]) -> CodecDetails {
+    if data.len() < 4 {
+        return CodecDetails::default();
+    }
+
+    let profile = match data[1] {
+        66 => "Baseline",
+        77 => "Main",
+        88 => "Extended",
+        100 => "High",
+        110 => "High 10",
+        122 => "High 4:2:2",
+        244 => "High 4:4:4 Predictive",
+        _ => "",
+    };
+    let bit_depth = match data[1] {
+        110 | 122 | 244 => None,
+        _ => Some(8),
+    };
+
+    CodecDetails {
+        profile: (!profile.is_empty()).then(|| profile.to_string()),
+        level: Some(format_level(data[3] as f64 / 10.0)),
+        bit_depth,
+    }
+}
+
+/// Decode an HEVCDecoderConfigurationRecord (`hvcC`)
+pub(crate) fn parse_hevc_config(data: &[u8]) -> CodecDetails {
+    if data.len() < 23 {
+        return CodecDetails::default();
+    }
+
+    let profile = match data[1] & 0x1f {
+        1 => Some("Main"),
+        2 => Some("Main 10"),
+        3 => Some("Main Still Picture"),
+        4 => Some("Rext"),
+        _ => None,
+    };
+
+    CodecDetails {
+        profile: profile.map(str::to_string),
+        level: Some(format_level(data[12] as f64 / 30.0)),
+        bit_depth: Some((data[17] & 0x07) + 8),
+    }
+}
+
+/// Decode an AV1CodecConfigurationRecord (`av1C`)
+pub(crate) fn parse_av1_config(data: &[u8]) -> CodecDetails {
+    if data.len() < 3 {
+        return CodecDetails::default();
+    }
+
+    let profile = match data[1] >> 5 {
+        0 => Some("Main"),
+        1 => Some("High"),
+        2 => Some("Professional"),
+        _ => None,
+    };
+    let high_bitdepth = data[2] & 0x40 != 0;
+    let twelve_bit = data[2] & 0x20 != 0;
+    let bit_depth = match (high_bitdepth, twelve_bit) {
+        (true, true) => 12,
+        (true, false) => 10,
+        _ => 8,
+    };
+
+    CodecDetails {
+        profile: profile.map(str::to_string),
+        level: Some((data[1] & 0x1f).to_string()),
+        bit_depth: Some(bit_depth),
+    }
+}
+
+fn format_level(level: f64) -> String {
+    let formatted = format!("{:.1}", level);
+    formatted.strip_suffix(".0").map(str::to_string).unwrap_or(formatted)
+}
+
+/// Round a frame rate to three decimals (e.g. 23.976)
+pub(crate) fn round_frame_rate(rate: f64) -> Option<f64> {
+    if rate.is_finite() && rate > 0.0 {
+        Some((rate * 1000.0).round() / 1000.0)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_reader() {
+        let data = [0x00, 0x01, 0x00, 0x00, 0x00, 0x02, b'a', b'b', b'c', b'd'];
+        let mut reader = ByteReader::new(&data);
+
+        assert_eq!(reader.u16().unwrap(), 1);
+        assert_eq!(reader.u32().unwrap(), 2);
+        assert_eq!(&reader.fourcc().unwrap(), b"abcd");
+        assert!(reader.u8().is_err());
+    }
+
+    #[test]
+    fn test_normalize_language() {
+        assert_eq!(normalize_language("eng"), Some("eng".to_string()));
+        assert_eq!(normalize_language("und"), None);
+        assert_eq!(normalize_language(""), None);
+    }
+
+    #[test]
+    fn test_codec_configs() {
+        let avc = parse_avc_config(&[1, 100, 0, 41]);
+        assert_eq!(avc.profile.as_deref(), Some("High"));
+        assert_eq!(avc.level.as_deref(), Some("4.1"));
+
+        let mut hvcc = vec![0u8; 23];
+        hvcc[1] = 2;
+        hvcc[12] = 153;
+        hvcc[17] = 0xfa;
+        let hevc = parse_hevc_config(&hvcc);
+        assert_eq!(hevc.profile.as_deref(), Some("Main 10"));
+        assert_eq!(hevc.level.as_deref(), Some("5.1"));
+        assert_eq!(hevc.bit_depth, Some(10));
+
+        let av1 = parse_av1_config(&[0x81, 0x08, 0x40, 0x00]);
+        assert_eq!(av1.profile.as_deref(), Some("Main"));
+        assert_eq!(av1.bit_depth, Some(10));
+    }
+
+    #[test]
+    fn test_finish_chapters() {
+        let mark = |start: f64, end: Option<f64>, title: Option<&str>| ChapterMark {
+            start,
+            end,
+            title: title.map(str::to_string),
+        };
+        let chapters = finish_chapters(
+            vec![mark(300.0, None, Some("Middle")), mark(0.0, Some(120.0), None), mark(300.0, None, None)],
+            Some(900.0),
+        );
+
+        assert_eq!(chapters.len(), 2);
+        assert_eq!((chapters[0].id, chapters[0].start_time, chapters[0].end_time), (1, 0.0, 120.0));
+        assert_eq!(chapters[0].title, "Chapter 1");
+        assert_eq!((chapters[1].start_time, chapters[1].end_time), (300.0, 900.0));
+        assert_eq!(chapters[1].title, "Middle");
+    }
+}
@@ -0,0 +1,1191 @@
+//! ISO base media file format (MP4/MOV/M4A) parsing
+
+use super::{
+    color_space_name, decode_string, normalize_language, parse_av1_config, parse_avc_config,
+    parse_hevc_config, round_frame_rate, truncated, ByteReader, ChapterMark, CodecDetails, MAX_HEADER_SIZE,
+};
+use crate::analyzer::MediaInfo;
+use rustflix_core::media::{AudioCodec, SubtitleTrack, VideoCodec};
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+/// A single box inside an in-memory buffer
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Mp4Box<'a> {
+    pub kind: [u8; 4],
+    pub data: &'a [u8],
+}
+
+/// Split a buffer into its child boxes
+pub(crate) fn children(data: &[u8]) -> Result<Vec<Mp4Box<'_>>> {
+    let mut reader = ByteReader::new(data);
+    let mut boxes = Vec::new();
+
+    while reader.remaining() >= 8 {
+        let size = reader.u32()? as u64;
+        let kind = reader.fourcc()?;
+        let payload_len = match size {
+            0 => reader.remaining() as u64,
+            1 => reader.u64()?.checked_sub(16).ok_or_else(invalid_box)?,
+            2..=7 => return Err(invalid_box()),
+            _ => size - 8,
+        };
+        if payload_len > reader.remaining() as u64 {
+            return Err(truncated());
+        }
+        boxes.push(Mp4Box {
+            kind,
+            data: reader.bytes(payload_len as usize)?,
+        });
+    }
+
+    Ok(boxes)
+}
+
+/// Find the first child box of the given type
+pub(crate) fn find<'a>(data: &'a [u8], kind: &[u8; 4]) -> Result<Option<&'a [u8]>> {
+    Ok(children(data)?
+        .into_iter()
+        .find(|b| &b.kind == kind)
+        .map(|b| b.data))
+}
+
+/// Follow a path of nested boxes, e.g. `[b"mdia", b"minf", b"stbl"]`
+pub(crate) fn find_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Result<Option<&'a [u8]>> {
+    let mut current = data;
+    for kind in path {
+        match find(current, kind)? {
+            Some(child) => current = child,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Locate the top-level `moov` box and read it into memory
+pub(crate) fn read_moov<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Vec<u8>> {
+    let mut offset = reader.seek(SeekFrom::Start(0))?;
+
+    while offset + 8 <= file_size {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let kind = [header[4], header[5], header[6], header[7]];
+
+        let (header_len, box_size) = match size {
+            0 => (8, file_size - offset),
+            1 => {
+                let mut large = [0u8; 8];
+                reader.read_exact(&mut large)?;
+                (16, u64::from_be_bytes(large))
+            }
+            _ => (8, size),
+        };
+        if box_size < header_len || offset + box_size > file_size {
+            return Err(invalid_box());
+        }
+
+        if &kind == b"moov" {
+            let payload_len = box_size - header_len;
+            if payload_len > MAX_HEADER_SIZE {
+                return Err(RustFlixError::media_processing("moov box is too large"));
+            }
+            let mut payload = vec![0u8; payload_len as usize];
+            reader.read_exact(&mut payload)?;
+            return Ok(payload);
+        }
+
+        offset = reader.seek(SeekFrom::Start(offset + box_size))?;
+    }
+
+    Err(RustFlixError::media_processing("No moov box found"))
+}
+
+/// Probe an ISO BMFF file
+pub(crate) fn probe<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<MediaInfo> {
+    let moov = read_moov(reader, file_size)?;
+    parse_moov(&moov)
+}
+
+/// Parse the contents of a `moov` box
+pub(crate) fn parse_moov(moov: &[u8]) -> Result<MediaInfo> {
+    let mut info = MediaInfo::default();
+
+    if let Some(mvhd) = find(moov, b"mvhd")? {
+        info.duration = parse_mvhd(mvhd)?;
+    }
+
+    let tracks = children(moov)?
+        .into_iter()
+        .filter(|b| &b.kind == b"trak")
+        .map(|trak| parse_trak(trak.data))
+        .collect::<Result<Vec<_>>>()?;
+    // Chapter titles are stored as text tracks, which are not subtitles
+    let chapter_ids = tracks.iter().flat_map(|track| track.chapter_ids.clone()).collect::<Vec<_>>();
+
+    let mut longest_track = 0.0f64;
+    for (index, track) in tracks.into_iter().enumerate() {
+        if let Some(duration) = track.duration_seconds() {
+            longest_track = longest_track.max(duration);
+        }
+        if !chapter_ids.contains(&track.id) {
+            track.push_stream(index as u32, &mut info);
+        }
+    }
+
+    if info.duration.is_none() && longest_track > 0.0 {
+        info.duration = Some(longest_track);
+    }
+
+    Ok(info)
+}
+
+/// Read chapters from a QuickTime chapter track, or else a Nero `chpl` box
+///
+/// Returns the chapters together with the movie duration.
+pub(crate) fn read_chapters<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<(Vec<ChapterMark>, Option<f64>)> {
+    let moov = read_moov(reader, file_size)?;
+    let duration = match find(&moov, b"mvhd")? {
+        Some(mvhd) => parse_mvhd(mvhd)?,
+        None => None,
+    };
+
+    let traks = children(&moov)?
+        .into_iter()
+        .filter(|b| &b.kind == b"trak")
+        .map(|trak| Ok((parse_trak(trak.data)?, trak.data)))
+        .collect::<Result<Vec<_>>>()?;
+    let chapter_ids = traks.iter().flat_map(|(track, _)| track.chapter_ids.clone()).collect::<Vec<_>>();
+
+    if let Some((track, trak)) = traks.iter().find(|(track, _)| chapter_ids.contains(&track.id)) {
+        let samples = match find_path(trak, &[b"mdia", b"minf", b"stbl"])? {
+            Some(stbl) => parse_sample_table(stbl)?,
+            None => Vec::new(),
+        };
+        if track.timescale > 0 && !samples.is_empty() {
+            let mut chapters = Vec::with_capacity(samples.len());
+            for sample in samples {
+                chapters.push(ChapterMark {
+                    start: sample.time as f64 / track.timescale as f64,
+                    end: Some((sample.time + sample.duration) as f64 / track.timescale as f64),
+                    title: read_text_sample(reader, &sample, file_size)?,
+                });
+            }
+            return Ok((chapters, duration));
+        }
+    }
+
+    let chapters = match find_path(&moov, &[b"udta", b"chpl"])? {
+        Some(chpl) => parse_chpl(chpl)?,
+        None => Vec::new(),
+    };
+    Ok((chapters, duration))
+}
+
+/// Parse a Nero chapter list, whose start times are in 100ns units
+fn parse_chpl(data: &[u8]) -> Result<Vec<ChapterMark>> {
+    let mut reader = ByteReader::new(data);
+    let (version, _) = full_box_header(&mut reader)?;
+    if version > 0 {
+        reader.skip(4)?;
+    }
+
+    let count = reader.u8()?;
+    let mut chapters = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let start = reader.u64()?;
+        let len = reader.u8()? as usize;
+        chapters.push(ChapterMark {
+            start: start as f64 / 10_000_000.0,
+            end: None,
+            title: decode_string(reader.bytes(len)?),
+        });
+    }
+    Ok(chapters)
+}
+
+/// Most samples we accept in a chapter track
+const MAX_CHAPTER_SAMPLES: usize = 10_000;
+
+/// Timing and location of one sample in the file
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    time: u64,
+    duration: u64,
+    offset: u64,
+    size: u32,
+}
+
+/// Resolve the timing and file location of every sample of a track
+fn parse_sample_table(stbl: &[u8]) -> Result<Vec<Sample>> {
+    let (Some(stts), Some(stsz), Some(stsc)) = (find(stbl, b"stts")?, find(stbl, b"stsz")?, find(stbl, b"stsc")?) else {
+        return Ok(Vec::new());
+    };
+
+    let mut reader = ByteReader::new(stts);
+    full_box_header(&mut reader)?;
+    let mut durations = Vec::new();
+    for _ in 0..reader.u32()? {
+        let count = reader.u32()? as usize;
+        let delta = reader.u32()? as u64;
+        if durations.len() + count > MAX_CHAPTER_SAMPLES {
+            return Err(RustFlixError::media_processing("Too many samples in chapter track"));
+        }
+        durations.extend(std::iter::repeat_n(delta, count));
+    }
+
+    let mut reader = ByteReader::new(stsz);
+    full_box_header(&mut reader)?;
+    let sample_size = reader.u32()?;
+    let sample_count = reader.u32()? as usize;
+    let sizes = if sample_size == 0 {
+        (0..sample_count).map(|_| reader.u32()).collect::<Result<Vec<_>>>()?
+    } else {
+        vec![sample_size; sample_count.min(durations.len())]
+    };
+
+    let mut reader = ByteReader::new(stsc);
+    full_box_header(&mut reader)?;
+    let mut runs = Vec::new();
+    for _ in 0..reader.u32()? {
+        let first_chunk = reader.u32()?;
+        let samples_per_chunk = reader.u32()?;
+        reader.skip(4)?;
+        runs.push((first_chunk, samples_per_chunk));
+    }
+
+    let offsets = if let Some(stco) = find(stbl, b"stco")? {
+        let mut reader = ByteReader::new(stco);
+        full_box_header(&mut reader)?;
+        (0..reader.u32()?).map(|_| reader.u32().map(u64::from)).collect::<Result<Vec<_>>>()?
+    } else if let Some(co64) = find(stbl, b"co64")? {
+        let mut reader = ByteReader::new(co64);
+        full_box_header(&mut reader)?;
+        (0..reader.u32()?).map(|_| reader.u64()).collect::<Result<Vec<_>>>()?
+    } else {
+        return Ok(Vec::new());
+    };
+
+    let mut samples = Vec::new();
+    let mut time = 0;
+    for (chunk, &chunk_offset) in offsets.iter().enumerate() {
+        let chunk_number = chunk as u32 + 1;
+        let per_chunk = runs
+            .iter()
+            .rev()
+            .find(|(first_chunk, _)| *first_chunk <= chunk_number)
+            .map_or(0, |(_, samples_per_chunk)| *samples_per_chunk);
+
+        let mut offset = chunk_offset;
+        for _ in 0..per_chunk {
+            let index = samples.len();
+            let (Some(&size), Some(&duration)) = (sizes.get(index), durations.get(index)) else {
+                return Ok(samples);
+            };
+            samples.push(Sample { time, duration, offset, size });
+            time += duration;
+            offset += size as u64;
+        }
+    }
+    Ok(samples)
+}
+
+/// Read the title stored in a text sample: a 16-bit length and the text
+fn read_text_sample<R: Read + Seek>(reader: &mut R, sample: &Sample, file_size: u64) -> Result<Option<String>> {
+    if sample.size < 2 || sample.offset + sample.size as u64 > file_size {
+        return Ok(None);
+    }
+    // The length prefix limits the text to 64 KiB
+    let mut data = vec![0u8; (sample.size as usize).min(2 + u16::MAX as usize)];
+    reader.seek(SeekFrom::Start(sample.offset))?;
+    reader.read_exact(&mut data)?;
+
+    let len = u16::from_be_bytes([data[0], data[1]]) as usize;
+    let Some(text) = data.get(2..2 + len) else {
+        return Ok(None);
+    };
+    match text {
+        [0xfe, 0xff, utf16 @ ..] => {
+            let units = utf16.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+            let title = char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect::<String>();
+            Ok(Some(title.trim().to_string()).filter(|title| !title.is_empty()))
+        }
+        _ => Ok(decode_string(text)),
+    }
+}
+
+/// Most keyframes we accept in a video track
+const MAX_KEYFRAMES: u64 = 1_000_000;
+
+/// Read the presentation times of the keyframes of the first video track
+///
+/// Times are in seconds, shifted by the track's edit list so the first
+/// frame shown is at zero.
+pub(crate) fn read_keyframes<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Vec<f64>> {
+    let moov = read_moov(reader, file_size)?;
+    for trak in children(&moov)?.into_iter().filter(|b| &b.kind == b"trak") {
+        let track = parse_trak(trak.data)?;
+        if &track.handler != b"vide" || track.timescale == 0 {
+            continue;
+        }
+        let Some(stbl) = find_path(trak.data, &[b"mdia", b"minf", b"stbl"])? else {
+            continue;
+        };
+        let media_start = match find_path(trak.data, &[b"edts", b"elst"])? {
+            Some(elst) => parse_elst(elst)?.unwrap_or(0),
+            None => 0,
+        };
+        return Ok(parse_sync_times(stbl)?
+            .into_iter()
+            .map(|time| (time - media_start).max(0) as f64 / track.timescale as f64)
+            .collect());
+    }
+    Ok(Vec::new())
+}
+
+/// Presentation times, in media timescale units, of the sync samples of a track
+///
+/// Without an `stss` box every sample is a sync sample.
+fn parse_sync_times(stbl: &[u8]) -> Result<Vec<i64>> {
+    let Some(stts) = find(stbl, b"stts")? else {
+        return Ok(Vec::new());
+    };
+    let durations = parse_runs(stts)?;
+    let offsets = match find(stbl, b"ctts")? {
+        Some(ctts) => parse_runs(ctts)?,
+        None => Vec::new(),
+    };
+
+    let sync_samples = match find(stbl, b"stss")? {
+        Some(stss) => {
+            let mut reader = ByteReader::new(stss);
+            full_box_header(&mut reader)?;
+            let count = reader.u32()? as u64;
+            if count > MAX_KEYFRAMES {
+                return Err(RustFlixError::media_processing("Too many keyframes in video track"));
+            }
+            // Sample numbers are 1-based
+            let mut samples = (0..count)
+                .map(|_| reader.u32().map(|number| (number as u64).saturating_sub(1)))
+                .collect::<Result<Vec<_>>>()?;
+            samples.sort_unstable();
+            samples.dedup();
+            samples
+        }
+        None => {
+            let count = durations.iter().map(|(count, _)| count).sum::<u64>();
+            if count > MAX_KEYFRAMES {
+                return Err(RustFlixError::media_processing("Too many keyframes in video track"));
+            }
+            (0..count).collect()
+        }
+    };
+
+    let mut times = Vec::with_capacity(sync_samples.len());
+    let (mut run, mut run_first, mut run_time) = (0, 0u64, 0i64);
+    let (mut offset_run, mut offset_first) = (0, 0u64);
+    for sample in sync_samples {
+        while run < durations.len() && sample >= run_first + durations[run].0 {
+            run_time += durations[run].0 as i64 * durations[run].1;
+            run_first += durations[run].0;
+            run += 1;
+        }
+        let Some(&(_, delta)) = durations.get(run) else {
+            break;
+        };
+        while offset_run < offsets.len() && sample >= offset_first + offsets[offset_run].0 {
+            offset_first += offsets[offset_run].0;
+            offset_run += 1;
+        }
+        let offset = offsets.get(offset_run).map_or(0, |(_, offset)| *offset);
+        times.push(run_time + (sample - run_first) as i64 * delta + offset);
+    }
+    Ok(times)
+}
+
+/// Decode the `(sample count, value)` runs of an `stts` or `ctts` box
+fn parse_runs(data: &[u8]) -> Result<Vec<(u64, i64)>> {
+    let mut reader = ByteReader::new(data);
+    let (version, _) = full_box_header(&mut reader)?;
+    (0..reader.u32()?)
+        .map(|_| {
+            let count = reader.u32()? as u64;
+            let value = reader.u32()?;
+            // Version 1 composition offsets are signed
+            let value = if version == 1 { value as i32 as i64 } else { value as i64 };
+            Ok((count, value))
+        })
+        .collect()
+}
+
+/// Media time the presentation starts at, from the first edit that is not empty
+fn parse_elst(data: &[u8]) -> Result<Option<i64>> {
+    let mut reader = ByteReader::new(data);
+    let (version, _) = full_box_header(&mut reader)?;
+    for _ in 0..reader.u32()? {
+        let media_time = if version == 1 {
+            reader.skip(8)?;
+            reader.u64()? as i64
+        } else {
+            reader.skip(4)?;
+            reader.u32()? as i32 as i64
+        };
+        // media rate
+        reader.skip(4)?;
+        if media_time >= 0 {
+            return Ok(Some(media_time));
+        }
+    }
+    Ok(None)
+}
+
+fn invalid_box() -> RustFlixError {
+    RustFlixError::media_processing("Invalid MP4 box size")
+}
+
+/// Read version and flags from a full box header
+fn full_box_header(reader: &mut ByteReader<'_>) -> Result<(u8, u32)> {
+    let version = reader.u8()?;
+    let flags = reader.u24()?;
+    Ok((version, flags))
+}
+
+fn parse_mvhd(data: &[u8]) -> Result<Option<f64>> {
+    let mut reader = ByteReader::new(data);
+    let (version, _) = full_box_header(&mut reader)?;
+
+    let (timescale, duration) = if version == 1 {
+        reader.skip(16)?;
+        (reader.u32()?, reader.u64()?)
+    } else {
+        reader.skip(8)?;
+        let timescale = reader.u32()?;
+        let duration = reader.u32()?;
+        let duration = if duration == u32::MAX { u64::MAX } else { duration as u64 };
+        (timescale, duration)
+    };
+
+    if timescale == 0 || duration == 0 || duration == u64::MAX {
+        return Ok(None);
+    }
+    Ok(Some(duration as f64 / timescale as f64))
+}
+
+/// Track-level information gathered from a `trak` box
+#[derive(Debug, Default)]
+struct Track {
+    id: u32,
+    handler: [u8; 4],
+    enabled: bool,
+    width: u32,
+    height: u32,
+    timescale: u32,
+    duration: u64,
+    language: Option<String>,
+    title: Option<String>,
+    sample_count: u64,
+    entry: Option<SampleEntry>,
+    /// IDs of the tracks holding this track's chapter titles (`tref/chap`)
+    chapter_ids: Vec<u32>,
+}
+
+/// Decoded sample description
+#[derive(Debug, Default)]
+struct SampleEntry {
+    codec: String,
+    width: u32,
+    height: u32,
+    channels: u8,
+    sample_rate: u32,
+    sample_size: Option<u8>,
+    bitrate: Option<u64>,
+    details: CodecDetails,
+    color_space: Option<String>,
+}
+
+impl Track {
+    fn duration_seconds(&self) -> Option<f64> {
+        if self.timescale == 0 || self.duration == 0 {
+            None
+        } else {
+            Some(self.duration as f64 / self.timescale as f64)
+        }
+    }
+
+    fn push_stream(self, index: u32, info: &mut MediaInfo) {
+        let duration = self.duration_seconds();
+        let Some(entry) = self.entry else {
+            return;
+        };
+
+        match &self.handler {
+            b"vide" => {
+                let frame_rate = duration
+                    .and_then(|duration| round_frame_rate(self.sample_count as f64 / duration));
+                info.streams.video.push(VideoCodec {
+                    name: entry.codec,
+                    profile: entry.details.profile,
+                    level: entry.details.level,
+                    width: if entry.width > 0 { entry.width } else { self.width },
+                    height: if entry.height > 0 { entry.height } else { self.height },
+                    frame_rate,
+                    bit_depth: entry.details.bit_depth,
+                    color_space: entry.color_space,
+                });
+            }
+            b"soun" => {
+                info.streams.audio.push(AudioCodec {
+                    name: entry.codec,
+                    channels: entry.channels,
+                    sample_rate: entry.sample_rate,
+                    bit_depth: entry.sample_size,
+                    bitrate: entry.bitrate,
+                    language: self.language,
+                });
+            }
+            b"sbtl" | b"subt" | b"text" | b"clcp" => {
+                info.streams.subtitles.push(SubtitleTrack {
+                    index,
+                    language: self.language,
+                    title: self.title,
+                    codec: entry.codec,
+                    forced: false,
+                    default: self.enabled,
+                    path: None,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_trak(trak: &[u8]) -> Result<Track> {
+    let mut track = Track::default();
+
+    if let Some(tkhd) = find(trak, b"tkhd")? {
+        parse_tkhd(tkhd, &mut track)?;
+    }
+
+    if let Some(chap) = find_path(trak, &[b"tref", b"chap"])? {
+        track.chapter_ids = chap
+            .chunks_exact(4)
+            .map(|id| u32::from_be_bytes([id[0], id[1], id[2], id[3]]))
+            .collect();
+    }
+
+    if let Some(name) = find_path(trak, &[b"udta", b"name"])? {
+        track.title = decode_string(name);
+    }
+
+    let Some(mdia) = find(trak, b"mdia")? else {
+        return Ok(track);
+    };
+
+    if let Some(mdhd) = find(mdia, b"mdhd")? {
+        parse_mdhd(mdhd, &mut track)?;
+    }
+    if let Some(elng) = find(mdia, b"elng")? {
+        if elng.len() > 4 {
+            if let Some(language) = decode_string(&elng[4..]) {
+                track.language = normalize_language(&language);
+            }
+        }
+    }
+    if let Some(hdlr) = find(mdia, b"hdlr")? {
+        let mut reader = ByteReader::new(hdlr);
+        reader.skip(8)?;
+        track.handler = reader.fourcc()?;
+    }
+
+    if let Some(stbl) = find_path(mdia, &[b"minf", b"stbl"])? {
+        if let Some(stts) = find(stbl, b"stts")? {
+            track.sample_count = parse_stts(stts)?;
+        }
+        if let Some(stsd) = find(stbl, b"stsd")? {
+            track.entry = parse_stsd(stsd, &track.handler)?;
+        }
+    }
+
+    Ok(track)
+}
+
+fn parse_tkhd(data: &[u8], track: &mut Track) -> Result<()> {
+    let mut reader = ByteReader::new(data);
+    let (version, flags) = full_box_header(&mut reader)?;
+    track.enabled = flags & 0x1 != 0;
+
+    // creation/modification time
+    reader.skip(if version == 1 { 16 } else { 8 })?;
+    track.id = reader.u32()?;
+    // reserved, duration
+    reader.skip(if version == 1 { 12 } else { 8 })?;
+    // reserved, layer, alternate group, volume, reserved, matrix
+    reader.skip(8 + 2 + 2 + 2 + 2 + 36)?;
+    track.width = reader.u32()? >> 16;
+    track.height = reader.u32()? >> 16;
+    Ok(())
+}
+
+fn parse_mdhd(data: &[u8], track: &mut Track) -> Result<()> {
+    let mut reader = ByteReader::new(data);
+    let (version, _) = full_box_header(&mut reader)?;
+
+    if version == 1 {
+        reader.skip(16)?;
+        track.timescale = reader.u32()?;
+        track.duration = reader.u64()?;
+    } else {
+        reader.skip(8)?;
+        track.timescale = reader.u32()?;
+        track.duration = reader.u32()? as u64;
+    }
+
+    let packed = reader.u16()?;
+    let language: String = [10u16, 5, 0]
+        .iter()
+        .map(|shift| (((packed >> shift) & 0x1f) as u8 + 0x60) as char)
+        .collect();
+    if language.chars().all(|c| c.is_ascii_lowercase()) {
+        track.language = normalize_language(&language);
+    }
+    Ok(())
+}
+
+fn parse_stts(data: &[u8]) -> Result<u64> {
+    let mut reader = ByteReader::new(data);
+    full_box_header(&mut reader)?;
+    let entries = reader.u32()?;
+
+    let mut total = 0u64;
+    for _ in 0..entries {
+        total += reader.u32()? as u64;
+        reader.skip(4)?;
+    }
+    Ok(total)
+}
+
+fn parse_stsd(data: &[u8], handler: &[u8; 4]) -> Result<Option<SampleEntry>> {
+    let mut reader = ByteReader::new(data);
+    full_box_header(&mut reader)?;
+    reader.skip(4)?;
+
+    let Some(entry) = children(reader.rest())?.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let entry = match handler {
+        b"vide" => parse_visual_entry(entry)?,
+        b"soun" => parse_audio_entry(entry)?,
+        _ => SampleEntry {
+            codec: subtitle_codec_name(&entry.kind),
+            ..SampleEntry::default()
+        },
+    };
+    Ok(Some(entry))
+}
+
+/// Resolve the original format of an encrypted (`encv`/`enca`) sample entry
+fn original_format(kind: [u8; 4], extensions: &[u8]) -> Result<[u8; 4]> {
+    if &kind != b"encv" && &kind != b"enca" {
+        return Ok(kind);
+    }
+    match find_path(extensions, &[b"sinf", b"frma"])? {
+        Some(frma) if frma.len() >= 4 => Ok([frma[0], frma[1], frma[2], frma[3]]),
+        _ => Ok(kind),
+    }
+}
+
+fn parse_visual_entry(entry: Mp4Box<'_>) -> Result<SampleEntry> {
+    let mut reader = ByteReader::new(entry.data);
+    // reserved, data reference index, pre-defined and reserved fields
+    reader.skip(6 + 2 + 2 + 2 + 12)?;
+    let width = reader.u16()? as u32;
+    let height = reader.u16()? as u32;
+    // resolution, reserved, frame count, compressor name, depth, pre-defined
+    reader.skip(4 + 4 + 4 + 2 + 32 + 2 + 2)?;
+    let extensions = reader.rest();
+
+    let kind = original_format(entry.kind, extensions)?;
+    let details = match &kind {
+        b"avc1" | b"avc3" => find(extensions, b"avcC")?.map(parse_avc_config),
+        b"hvc1" | b"hev1" | b"dvh1" | b"dvhe" => find(extensions, b"hvcC")?.map(parse_hevc_config),
+        b"av01" => find(extensions, b"av1C")?.map(parse_av1_config),
+        _ => None,
+    };
+
+    let color_space = match find(extensions, b"colr")? {
+        Some(colr) if colr.len() >= 10 && &colr[..4] == b"nclx" => {
+            color_space_name(u16::from_be_bytes([colr[8], colr[9]]) as u64)
+        }
+        _ => None,
+    };
+
+    Ok(SampleEntry {
+        codec: video_codec_name(&kind),
+        width,
+        height,
+        bitrate: parse_btrt(extensions)?,
+        details: details.unwrap_or_default(),
+        color_space,
+        ..SampleEntry::default()
+    })
+}
+
+fn parse_audio_entry(entry: Mp4Box<'_>) -> Result<SampleEntry> {
+    let mut reader = ByteReader::new(entry.data);
+    reader.skip(6 + 2)?;
+    let version = reader.u16()?;
+    reader.skip(2 + 4)?;
+    let mut channels = reader.u16()? as u32;
+    let sample_size = reader.u16()?;
+    reader.skip(2 + 2)?;
+    let mut sample_rate = reader.u32()? >> 16;
+
+    match version {
+        1 => reader.skip(16)?,
+        2 => {
+            // QuickTime sound description v2 stores rate and channels separately
+            reader.skip(4)?;
+            sample_rate = f64::from_bits(reader.u64()?) as u32;
+            channels = reader.u32()?;
+            reader.skip(20)?;
+        }
+        _ => {}
+    }
+    let extensions = reader.rest();
+
+    let kind = original_format(entry.kind, extensions)?;
+    let mut codec = audio_codec_name(&kind);
+    let mut bitrate = parse_btrt(extensions)?;
+
+    if &kind == b"mp4a" {
+        if let Some(esds) = find(extensions, b"esds")? {
+            if let Some((object_type, avg_bitrate)) = parse_esds(esds) {
+                if let Some(name) = object_type_name(object_type) {
+                    codec = name.to_string();
+                }
+                if avg_bitrate > 0 {
+                    bitrate = Some(avg_bitrate as u64);
+                }
+            }
+        }
+    }
+
+    Ok(SampleEntry {
+        codec,
+        channels: channels.min(u8::MAX as u32) as u8,
+        sample_rate,
+        sample_size: (sample_size > 0 && sample_size <= 64 && !is_lossy(&kind))
+            .then_some(sample_size as u8),
+        bitrate,
+        ..SampleEntry::default()
+    })
+}
+
+fn is_lossy(kind: &[u8; 4]) -> bool {
+    matches!(kind, b"mp4a" | b"ac-3" | b"ec-3" | b"Opus" | b".mp3" | b"dtsc" | b"dtsh")
+}
+
+fn parse_btrt(extensions: &[u8]) -> Result<Option<u64>> {
+    Ok(find(extensions, b"btrt")?
+        .filter(|btrt| btrt.len() >= 12)
+        .map(|btrt| u32::from_be_bytes([btrt[8], btrt[9], btrt[10], btrt[11]]) as u64)
+        .filter(|bitrate| *bitrate > 0))
+}
+
+/// Extract the object type and average bitrate from an `esds` box
+fn parse_esds(data: &[u8]) -> Option<(u8, u32)> {
+    let mut reader = ByteReader::new(data);
+    reader.skip(4).ok()?;
+
+    loop {
+        let tag = reader.u8().ok()?;
+        let len = read_descriptor_length(&mut reader)?;
+        match tag {
+            // ES_Descriptor
+            0x03 => {
+                reader.skip(2).ok()?;
+                let flags = reader.u8().ok()?;
+                if flags & 0x80 != 0 {
+                    reader.skip(2).ok()?;
+                }
+                if flags & 0x40 != 0 {
+                    let url_len = reader.u8().ok()? as usize;
+                    reader.skip(url_len).ok()?;
+                }
+                if flags & 0x20 != 0 {
+                    reader.skip(2).ok()?;
+                }
+            }
+            // DecoderConfigDescriptor
+            0x04 => {
+                let object_type = reader.u8().ok()?;
+                reader.skip(1 + 3 + 4).ok()?;
+                let avg_bitrate = reader.u32().ok()?;
+                return Some((object_type, avg_bitrate));
+            }
+            _ => reader.skip(len).ok()?,
+        }
+    }
+}
+
+fn read_descriptor_length(reader: &mut ByteReader<'_>) -> Option<usize> {
+    let mut len = 0usize;
+    for _ in 0..4 {
+        let byte = reader.u8().ok()?;
+        len = (len << 7) | (byte & 0x7f) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Some(len)
+}
+
+fn object_type_name(object_type: u8) -> Option<&'static str> {
+    match object_type {
+        0x40 | 0x66 | 0x67 | 0x68 => Some("aac"),
+        0x69 | 0x6b => Some("mp3"),
+        0xa5 => Some("ac3"),
+        0xa6 => Some("eac3"),
+        0xa9 => Some("dts"),
+        0xad => Some("opus"),
+        _ => None,
+    }
+}
+
+fn video_codec_name(kind: &[u8; 4]) -> String {
+    let name = match kind {
+        b"avc1" | b"avc3" => "h264",
+        b"hvc1" | b"hev1" | b"dvh1" | b"dvhe" => "hevc",
+        b"av01" => "av1",
+        b"vp08" => "vp8",
+        b"vp09" => "vp9",
+        b"mp4v" => "mpeg4",
+        b"s263" | b"h263" => "h263",
+        b"jpeg" | b"mjpa" => "mjpeg",
+        b"apch" | b"apcn" | b"apcs" | b"apco" | b"ap4h" => "prores",
+        _ => return fourcc_name(kind),
+    };
+    name.to_string()
+}
+
+fn audio_codec_name(kind: &[u8; 4]) -> String {
+    let name = match kind {
+        b"mp4a" => "aac",
+        b".mp3" => "mp3",
+        b"ac-3" => "ac3",
+        b"ec-3" => "eac3",
+        b"Opus" => "opus",
+        b"fLaC" => "flac",
+        b"alac" => "alac",
+        b"dtsc" | b"dtsh" | b"dtsl" => "dts",
+        b"samr" => "amr_nb",
+        b"sowt" | b"twos" | b"lpcm" | b"in24" | b"in32" | b"fl32" | b"fl64" => "pcm",
+        _ => return fourcc_name(kind),
+    };
+    name.to_string()
+}
+
+fn subtitle_codec_name(kind: &[u8; 4]) -> String {
+    let name = match kind {
+        b"tx3g" | b"text" => "mov_text",
+        b"wvtt" => "webvtt",
+        b"stpp" => "ttml",
+        b"c608" => "eia_608",
+        b"c708" => "eia_708",
+        _ => return fourcc_name(kind),
+    };
+    name.to_string()
+}
+
+fn fourcc_name(kind: &[u8; 4]) -> String {
+    String::from_utf8_lossy(kind).trim().to_lowercase()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    pub(crate) fn make_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn full_box(kind: &[u8; 4], version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![version];
+        data.extend_from_slice(&flags.to_be_bytes()[1..]);
+        data.extend_from_slice(payload);
+        make_box(kind, &data)
+    }
+
+    fn mvhd(timescale: u32, duration: u32) -> Vec<u8> {
+        let mut payload = vec![0u8; 8];
+        payload.extend_from_slice(&timescale.to_be_bytes());
+        payload.extend_from_slice(&duration.to_be_bytes());
+        payload.extend_from_slice(&[0u8; 80]);
+        full_box(b"mvhd", 0, 0, &payload)
+    }
+
+    fn tkhd(id: u32, enabled: bool, width: u32, height: u32) -> Vec<u8> {
+        let mut payload = vec![0u8; 8];
+        payload.extend_from_slice(&id.to_be_bytes());
+        payload.extend_from_slice(&[0u8; 8 + 8 + 8 + 36]);
+        payload.extend_from_slice(&(width << 16).to_be_bytes());
+        payload.extend_from_slice(&(height << 16).to_be_bytes());
+        full_box(b"tkhd", 0, enabled as u32, &payload)
+    }
+
+    fn mdhd(timescale: u32, duration: u32, language: &str) -> Vec<u8> {
+        let mut payload = vec![0u8; 8];
+        payload.extend_from_slice(&timescale.to_be_bytes());
+        payload.extend_from_slice(&duration.to_be_bytes());
+        let packed = language
+            .bytes()
+            .fold(0u16, |acc, c| (acc << 5) | (c - 0x60) as u16);
+        payload.extend_from_slice(&packed.to_be_bytes());
+        payload.extend_from_slice(&[0, 0]);
+        full_box(b"mdhd", 0, 0, &payload)
+    }
+
+    fn hdlr(handler: &[u8; 4]) -> Vec<u8> {
+        let mut payload = vec![0u8; 4];
+        payload.extend_from_slice(handler);
+        payload.extend_from_slice(&[0u8; 13]);
+        full_box(b"hdlr", 0, 0, &payload)
+    }
+
+    fn stts(samples: u32, delta: u32) -> Vec<u8> {
+        let mut payload = 1u32.to_be_bytes().to_vec();
+        payload.extend_from_slice(&samples.to_be_bytes());
+        payload.extend_from_slice(&delta.to_be_bytes());
+        full_box(b"stts", 0, 0, &payload)
+    }
+
+    fn stsd(entry: Vec<u8>) -> Vec<u8> {
+        let mut payload = 1u32.to_be_bytes().to_vec();
+        payload.extend(entry);
+        full_box(b"stsd", 0, 0, &payload)
+    }
+
+    fn avc1(width: u16, height: u16) -> Vec<u8> {
+        let mut payload = vec![0u8; 24];
+        payload.extend_from_slice(&width.to_be_bytes());
+        payload.extend_from_slice(&height.to_be_bytes());
+        payload.extend_from_slice(&[0u8; 50]);
+        payload.extend(make_box(b"avcC", &[1, 100, 0, 40, 0xff]));
+        make_box(b"avc1", &payload)
+    }
+
+    fn mp4a(channels: u16, sample_rate: u32) -> Vec<u8> {
+        let mut payload = vec![0u8; 16];
+        payload.extend_from_slice(&channels.to_be_bytes());
+        payload.extend_from_slice(&16u16.to_be_bytes());
+        payload.extend_from_slice(&[0u8; 4]);
+        payload.extend_from_slice(&(sample_rate << 16).to_be_bytes());
+
+        let mut decoder_config = vec![0x40, 0x15, 0, 0, 0];
+        decoder_config.extend_from_slice(&256_000u32.to_be_bytes());
+        decoder_config.extend_from_slice(&192_000u32.to_be_bytes());
+        let mut es = vec![0, 1, 0, 0x04, decoder_config.len() as u8];
+        es.extend(decoder_config);
+        let mut esds = vec![0x03, es.len() as u8];
+        esds.extend(es);
+        payload.extend(full_box(b"esds", 0, 0, &esds));
+        make_box(b"mp4a", &payload)
+    }
+
+    fn trak(header: Vec<u8>, mdhd: Vec<u8>, handler: &[u8; 4], stbl: Vec<u8>) -> Vec<u8> {
+        let minf = make_box(b"minf", &make_box(b"stbl", &stbl));
+        let mut mdia = mdhd;
+        mdia.extend(hdlr(handler));
+        mdia.extend(minf);
+        let mut trak = header;
+        trak.extend(make_box(b"mdia", &mdia));
+        make_box(b"trak", &trak)
+    }
+
+    /// Build a small MP4 with H.264 video, AAC audio and a text subtitle track
+    pub(crate) fn sample_file() -> Vec<u8> {
+        sample_file_with_chapters(&[])
+    }
+
+    /// Build the sample MP4 with a Nero chapter list of `(start seconds, title)`
+    pub(crate) fn sample_file_with_chapters(chapters: &[(u64, &str)]) -> Vec<u8> {
+        let mut video_stbl = stsd(avc1(1920, 800));
+        video_stbl.extend(stts(2400, 1001));
+        let video = trak(tkhd(1, true, 1920, 800), mdhd(24000, 2_402_400, "und"), b"vide", video_stbl);
+
+        let mut audio_stbl = stsd(mp4a(6, 48000));
+        audio_stbl.extend(stts(4690, 1024));
+        let audio = trak(tkhd(2, true, 0, 0), mdhd(48000, 4_802_560, "eng"), b"soun", audio_stbl);
+
+        let subtitle = trak(
+            tkhd(3, false, 0, 0),
+            mdhd(1000, 100_000, "fre"),
+            b"sbtl",
+            stsd(make_box(b"tx3g", &[0u8; 38])),
+        );
+
+        let mut moov = mvhd(1000, 100_100);
+        moov.extend(video);
+        moov.extend(audio);
+        moov.extend(subtitle);
+        if !chapters.is_empty() {
+            moov.extend(make_box(b"udta", &full_box(b"chpl", 0, 0, &chpl(chapters))));
+        }
+
+        let mut file = make_box(b"ftyp", b"isom\0\0\x02\0isomavc1");
+        file.extend(make_box(b"mdat", &[0u8; 1024]));
+        file.extend(make_box(b"moov", &moov));
+        file
+    }
+
+    #[test]
+    fn test_probe_sample_file() {
+        let data = sample_file();
+        let info = probe(&mut Cursor::new(&data), data.len() as u64).unwrap();
+
+        assert!((info.duration.unwrap() - 100.1).abs() < 1e-6);
+
+        assert_eq!(info.streams.video.len(), 1);
+        let video = &info.streams.video[0];
+        assert_eq!(video.name, "h264");
+        assert_eq!(video.profile.as_deref(), Some("High"));
+        assert_eq!(video.level.as_deref(), Some("4"));
+        assert_eq!((video.width, video.height), (1920, 800));
+        assert_eq!(video.frame_rate, Some(23.976));
+
+        assert_eq!(info.streams.audio.len(), 1);
+        let audio = &info.streams.audio[0];
+        assert_eq!(audio.name, "aac");
+        assert_eq!(audio.channels, 6);
+        assert_eq!(audio.sample_rate, 48000);
+        assert_eq!(audio.bitrate, Some(192_000));
+        assert_eq!(audio.language.as_deref(), Some("eng"));
+
+        assert_eq!(info.streams.subtitles.len(), 1);
+        let subtitle = &info.streams.subtitles[0];
+        assert_eq!(subtitle.index, 2);
+        assert_eq!(subtitle.codec, "mov_text");
+        assert_eq!(subtitle.language.as_deref(), Some("fre"));
+        assert!(!subtitle.default);
+    }
+
+    fn chpl(chapters: &[(u64, &str)]) -> Vec<u8> {
+        let mut payload = vec![chapters.len() as u8];
+        for (start, title) in chapters {
+            payload.extend_from_slice(&(start * 10_000_000).to_be_bytes());
+            payload.push(title.len() as u8);
+            payload.extend_from_slice(title.as_bytes());
+        }
+        payload
+    }
+
+    #[test]
+    fn test_nero_chapters() {
+        let mut moov = mvhd(1000, 100_100);
+        let payload = [&[0u8; 4][..], &chpl(&[(0, "Opening"), (60, "Finale")])].concat();
+        moov.extend(make_box(b"udta", &full_box(b"chpl", 1, 0, &payload)));
+        let data = make_box(b"moov", &moov);
+
+        let (chapters, duration) = read_chapters(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert_eq!(duration, Some(100.1));
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[1].start, 60.0);
+        assert_eq!(chapters[1].title.as_deref(), Some("Finale"));
+    }
+
+    #[test]
+    fn test_chapter_track() {
+        let ftyp = make_box(b"ftyp", b"isom\0\0\x02\0isom");
+        let samples = [&b"\0\x05Intro"[..], b"\0\x0aThe Heist\0"];
+        let mdat = make_box(b"mdat", &samples.concat());
+
+        let mut stsz_payload = vec![0u8; 4];
+        stsz_payload.extend_from_slice(&2u32.to_be_bytes());
+        for sample in samples {
+            stsz_payload.extend_from_slice(&(sample.len() as u32).to_be_bytes());
+        }
+        let mut stsc_payload = 1u32.to_be_bytes().to_vec();
+        for value in [1u32, 2, 1] {
+            stsc_payload.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut stco_payload = 1u32.to_be_bytes().to_vec();
+        stco_payload.extend_from_slice(&(ftyp.len() as u32 + 8).to_be_bytes());
+
+        let mut text_stbl = stsd(make_box(b"text", &[0u8; 8]));
+        text_stbl.extend(stts(2, 60_000));
+        text_stbl.extend(full_box(b"stsz", 0, 0, &stsz_payload));
+        text_stbl.extend(full_box(b"stsc", 0, 0, &stsc_payload));
+        text_stbl.extend(full_box(b"stco", 0, 0, &stco_payload));
+        let text = trak(tkhd(2, false, 0, 0), mdhd(1000, 120_000, "eng"), b"text", text_stbl);
+
+        let mut video = tkhd(1, true, 1920, 800);
+        video.extend(make_box(b"tref", &make_box(b"chap", &2u32.to_be_bytes())));
+        let video = trak(video, mdhd(24000, 2_880_000, "und"), b"vide", [stsd(avc1(1920, 800)), stts(2880, 1000)].concat());
+
+        let mut moov = mvhd(1000, 120_000);
+        moov.extend(video);
+        moov.extend(text);
+        let data = [ftyp, mdat, make_box(b"moov", &moov)].concat();
+
+        let (chapters, _) = read_chapters(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!((chapters[0].start, chapters[0].end), (0.0, Some(60.0)));
+        assert_eq!(chapters[0].title.as_deref(), Some("Intro"));
+        assert_eq!(chapters[1].start, 60.0);
+        assert_eq!(chapters[1].title.as_deref(), Some("The Heist"));
+
+        // The chapter track is not listed as a subtitle
+        let info = probe(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert_eq!(info.streams.video.len(), 1);
+        assert!(info.streams.subtitles.is_empty());
+    }
+
+    #[test]
+    fn test_read_keyframes() {
+        let mut stss_payload = 3u32.to_be_bytes().to_vec();
+        for number in [1u32, 145, 49] {
+            stss_payload.extend_from_slice(&number.to_be_bytes());
+        }
+        // Two frames of reordering delay, removed again by the edit list
+        let mut ctts_payload = 1u32.to_be_bytes().to_vec();
+        ctts_payload.extend_from_slice(&240u32.to_be_bytes());
+        ctts_payload.extend_from_slice(&2002u32.to_be_bytes());
+        let mut elst_payload = 2u32.to_be_bytes().to_vec();
+        elst_payload.extend_from_slice(&[0, 0, 0, 10, 0xff, 0xff, 0xff, 0xff, 0, 1, 0, 0]);
+        elst_payload.extend_from_slice(&[0, 0, 0x27, 0x10, 0, 0, 0x07, 0xd2, 0, 1, 0, 0]);
+
+        let mut video_stbl = stsd(avc1(1920, 800));
+        video_stbl.extend(stts(240, 1001));
+        video_stbl.extend(full_box(b"stss", 0, 0, &stss_payload));
+        video_stbl.extend(full_box(b"ctts", 0, 0, &ctts_payload));
+        let mut header = tkhd(1, true, 1920, 800);
+        header.extend(make_box(b"edts", &full_box(b"elst", 0, 0, &elst_payload)));
+        let video = trak(header, mdhd(24000, 240_240, "und"), b"vide", video_stbl);
+
+        let audio = trak(tkhd(2, true, 0, 0), mdhd(48000, 480_000, "eng"), b"soun", stsd(mp4a(2, 48000)));
+        let mut moov = mvhd(1000, 10_010);
+        moov.extend(audio);
+        moov.extend(video);
+        let data = make_box(b"moov", &moov);
+
+        let keyframes = read_keyframes(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert_eq!(keyframes, vec![0.0, 2.002, 6.006]);
+    }
+
+    #[test]
+    fn test_missing_moov() {
+        let data = make_box(b"ftyp", b"isom");
+        assert!(probe(&mut Cursor::new(&data), data.len() as u64).is_err());
+    }
+
+    #[test]
+    fn test_garbage_data() {
+        let data = b"fake video data".to_vec();
+        assert!(probe(&mut Cursor::new(&data), data.len() as u64).is_err());
+    }
+}
@@ -1,25 +1,70 @@
 //! Media file scanning functionality
 
-use rustflix_core::{Result, RustFlixError, MediaItem, MediaFormat};
-use std::path::{Path, PathBuf};
+use crate::analyzer::{MediaAnalyzer, MediaInfo};
+use crate::audiobooks;
+use crate::books::{self, BookInfo};
+use crate::disc;
+use crate::extras;
+use crate::filter::{IgnoreMatcher, IgnoreRules};
+use crate::hasher::{FileHash, MediaHasher};
+use crate::images;
+use crate::music;
+use crate::parser;
+use crate::sidecar::{self, ArtworkKind, Sidecar, SidecarKind};
+use crate::stacking;
+use crate::storage::{LibraryStorage, StorageEntry, StorageReader};
+use crate::watcher::WatchEvent;
+use chrono::{DateTime, SubsecRound, Utc};
+use futures::{stream, StreamExt};
+use rustflix_core::{Result, RustFlixError, MediaFormat, MediaItem, MediaType};
+use rustflix_database::{
+    AudiobookFileModel, BookModel, ImageModel, ImageVariantModel, MediaChapterModel, MediaFileStateModel, MediaImageModel, MediaItemModel, MediaRepository, MediaStackModel, MusicTrackModel, PhotoModel, StackMemberModel,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::Arc;
+use uuid::Uuid;
 use walkdir::WalkDir;
 use tokio::fs;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, warn, debug};
 
+/// Number of files analyzed concurrently during a library scan
+const ANALYSIS_CONCURRENCY: usize = 4;
+
 /// Media scanner for discovering files in library paths
 #[derive(Debug, Clone)]
 pub struct MediaScanner {
     supported_extensions: Vec<String>,
+    hasher: MediaHasher,
+    ignore: IgnoreRules,
+    /// Folder and sizes of the resized variants of artwork and photos,
+    /// when they are generated
+    thumbnails: Option<(PathBuf, Vec<(u32, u32)>)>,
+    /// Storage files are read through, for libraries not on the local disk
+    storage: Option<Arc<dyn LibraryStorage>>,
+    /// Whether audio files are parts of audiobooks rather than music tracks
+    audiobooks: bool,
 }
 
+/// Extensions scanned in photo libraries
+const PHOTO_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "bmp", "webp"];
+
+/// Extensions scanned in audiobook libraries
+const AUDIOBOOK_EXTENSIONS: &[&str] = &["m4b", "m4a", "mp3", "flac", "ogg", "aac", "wav"];
+
+/// Extensions scanned in book libraries
+const BOOK_EXTENSIONS: &[&str] = &["cbz", "cbr", "epub", "pdf"];
+
 /// Result of a media scan operation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ScanResult {
     pub items_found: u32,
     pub items_added: u32,
     pub items_updated: u32,
     pub items_removed: u32,
     pub errors: Vec<String>,
+    pub cancelled: bool,
 }
 
 impl MediaScanner {
@@ -31,11 +76,72 @@ impl MediaScanner {
                 "mov".to_string(), "wmv".to_string(), "flv".to_string(),
                 "webm".to_string(), "m4v".to_string(), "mp3".to_string(),
                 "flac".to_string(), "aac".to_string(), "ogg".to_string(),
-                "wav".to_string(), "m4a".to_string(),
+                "wav".to_string(), "m4a".to_string(), "iso".to_string(),
             ],
+            hasher: MediaHasher::default(),
+            ignore: IgnoreRules::default(),
+            thumbnails: None,
+            storage: None,
+            audiobooks: false,
         })
     }
 
+    /// Get a scanner for a library of the given type
+    ///
+    /// Photo libraries are scanned for images only; images elsewhere are
+    /// artwork handled as sidecar files. Audiobook libraries are scanned for
+    /// audio files, which are grouped into books instead of albums, and book
+    /// libraries for comic archives and e-books.
+    pub fn for_library_type(&self, library_type: &str) -> Self {
+        let mut scanner = self.clone();
+        match library_type {
+            "photos" => {
+                scanner.supported_extensions = PHOTO_EXTENSIONS.iter().map(|ext| ext.to_string()).collect();
+            }
+            "audiobooks" => {
+                scanner.supported_extensions = AUDIOBOOK_EXTENSIONS.iter().map(|ext| ext.to_string()).collect();
+                scanner.audiobooks = true;
+            }
+            "books" => {
+                scanner.supported_extensions = BOOK_EXTENSIONS.iter().map(|ext| ext.to_string()).collect();
+            }
+            _ => {}
+        }
+        scanner
+    }
+
+    /// Generate variants of artwork and photos fitting each size below the
+    /// given folder
+    pub fn with_thumbnails(mut self, path: PathBuf, sizes: Vec<(u32, u32)>) -> Self {
+        self.thumbnails = Some((path, sizes));
+        self
+    }
+
+    /// Read the library through a storage backend
+    ///
+    /// Local storage is read from disk directly, as without a backend.
+    pub fn with_storage(mut self, storage: Arc<dyn LibraryStorage>) -> Self {
+        self.storage = Some(storage).filter(|storage| storage.local_root().is_none());
+        self
+    }
+
+    /// Enable or disable hashing whole files in addition to the fingerprint
+    pub fn with_full_hash(mut self, enabled: bool) -> Self {
+        self.hasher = MediaHasher::new(enabled);
+        self
+    }
+
+    /// Set the rules excluding paths and small files from scans
+    pub fn with_ignore_rules(mut self, rules: IgnoreRules) -> Self {
+        self.ignore = rules;
+        self
+    }
+
+    /// Rules excluding paths and small files from scans
+    pub fn ignore_rules(&self) -> &IgnoreRules {
+        &self.ignore
+    }
+
     /// Scan a directory for media files
     pub async fn scan_directory(&self, path: &Path) -> Result<Vec<PathBuf>> {
         info!("Scanning directory: {}", path.display());
@@ -45,12 +151,18 @@ impl MediaScanner {
         }
 
         let mut media_files = Vec::new();
-        
-        for entry in WalkDir::new(path).follow_links(false) {
+        let mut ignore = IgnoreMatcher::new(&self.ignore, path);
+
+        let walker = WalkDir::new(path).follow_links(false).into_iter().filter_entry(|entry| {
+            entry.depth() == 0
+                || !(ignore.is_entry_ignored(entry.path(), entry.file_type().is_dir())
+                    || entry.file_type().is_dir() && disc::is_structure_folder(entry.path()))
+        });
+        for entry in walker {
             match entry {
                 Ok(entry) => {
                     let path = entry.path();
-                    if self.is_media_file(path) {
+                    if self.is_media_file(path) || self.is_disc_folder(path) {
                         media_files.push(path.to_path_buf());
                         debug!("Found media file: {}", path.display());
                     }
@@ -67,7 +179,7 @@ impl MediaScanner {
 
     /// Check if a file is a supported media file
     pub fn is_media_file(&self, path: &Path) -> bool {
-        if !path.is_file() {
+        if path.is_dir() {
             return false;
         }
 
@@ -80,31 +192,1489 @@ impl MediaScanner {
         false
     }
 
+    /// Check if a folder holds a Blu-ray or DVD structure scanned as a
+    /// single item
+    ///
+    /// Discs are scanned in the libraries scanning ISO images.
+    pub fn is_disc_folder(&self, path: &Path) -> bool {
+        self.supported_extensions.iter().any(|ext| ext == "iso") && disc::detect_folder(path).is_some()
+    }
+
     /// Get file metadata
+    ///
+    /// The size and modification time of a disc folder are those of its
+    /// whole structure.
     pub async fn get_file_info(&self, path: &Path) -> Result<FileInfo> {
+        if self.is_disc_folder(path) {
+            let root = path.to_path_buf();
+            let (file_size, modified) = tokio::task::spawn_blocking(move || disc::folder_stats(&root))
+                .await
+                .map_err(|e| RustFlixError::internal(format!("Directory walk failed: {}", e)))??;
+            return Ok(FileInfo {
+                path: path.to_path_buf(),
+                file_size,
+                modified: modification_time(modified),
+            });
+        }
+
         let metadata = fs::metadata(path).await
-            .map_err(|e| RustFlixError::Io(e))?;
+            .map_err(RustFlixError::Io)?;
 
         let file_size = metadata.len();
         let modified = metadata.modified()
-            .map_err(|e| RustFlixError::Io(e))?;
+            .map_err(RustFlixError::Io)?;
 
         Ok(FileInfo {
             path: path.to_path_buf(),
             file_size,
-            modified: modified.into(),
+            modified: modification_time(modified),
         })
     }
 
     /// Create MediaItem from file path
     pub async fn create_media_item(&self, path: &Path) -> Result<MediaItem> {
         let file_info = self.get_file_info(path).await?;
-        let mut item = MediaItem::new(path.to_path_buf(), file_info.file_size);
-        
+        Ok(self.media_item_from_file(&file_info))
+    }
+
+    /// Create MediaItem from already collected file information
+    pub fn media_item_from_file(&self, file_info: &FileInfo) -> MediaItem {
+        let mut item = MediaItem::new(file_info.path.clone(), file_info.file_size);
+
         // Set additional metadata if available
         item.updated_at = file_info.modified;
-        
-        Ok(item)
+
+        if let Some(kind) = disc::detect_folder(&file_info.path) {
+            item.format = kind.format();
+            item.media_type = MediaType::from_format(item.format);
+        }
+
+        // Refine the media type from the file and folder names
+        if item.format.is_video() {
+            item.media_type = parser::parse_path(&file_info.path).media_type();
+        } else if self.audiobooks && item.format.is_audio() {
+            item.media_type = MediaType::Audiobook;
+        }
+
+        item
+    }
+
+    /// Walk a directory and collect file information for every media file
+    pub async fn collect_files(&self, path: &Path) -> Result<Vec<FileInfo>> {
+        if !path.exists() {
+            return Err(RustFlixError::not_found("directory", &path.to_string_lossy()));
+        }
+
+        let scanner = self.clone();
+        let root = path.to_path_buf();
+        tokio::task::spawn_blocking(move || scanner.collect_files_blocking(&root))
+            .await
+            .map_err(|e| RustFlixError::internal(format!("Directory walk failed: {}", e)))
+    }
+
+    fn collect_files_blocking(&self, root: &Path) -> Vec<FileInfo> {
+        let mut files = Vec::new();
+        let mut ignore = IgnoreMatcher::new(&self.ignore, root);
+
+        // The files of disc structures are not scanned one by one; the folder
+        // holding the structure is the item instead
+        let walker = WalkDir::new(root).follow_links(false).into_iter().filter_entry(|entry| {
+            entry.depth() == 0
+                || !(ignore.is_entry_ignored(entry.path(), entry.file_type().is_dir())
+                    || entry.file_type().is_dir() && disc::is_structure_folder(entry.path()))
+        });
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Error scanning entry: {}", e);
+                    continue;
+                }
+            };
+            let stats = if entry.file_type().is_dir() && self.is_disc_folder(entry.path()) {
+                disc::folder_stats(entry.path())
+            } else if entry.file_type().is_file() && self.is_media_file(entry.path()) {
+                entry.metadata().map_err(std::io::Error::from).and_then(|m| Ok((m.len(), m.modified()?)))
+            } else {
+                continue;
+            };
+
+            match stats {
+                Ok((file_size, _)) if self.ignore.is_too_small(file_size) => {
+                    debug!("Skipping small file: {}", entry.path().display());
+                }
+                Ok((file_size, modified)) => files.push(FileInfo {
+                    path: entry.path().to_path_buf(),
+                    file_size,
+                    modified: modification_time(modified),
+                }),
+                Err(e) => warn!("Failed to read metadata of {}: {}", entry.path().display(), e),
+            }
+        }
+
+        files
+    }
+
+    /// List the storage backend and collect file information for every
+    /// media file
+    async fn collect_entries(&self, storage: &dyn LibraryStorage) -> Result<Vec<FileInfo>> {
+        let root = PathBuf::from(storage.location());
+        let mut ignore = IgnoreMatcher::new(&self.ignore, &root);
+
+        let files = storage
+            .list("")
+            .await?
+            .into_iter()
+            .map(|entry| (storage.path(&entry.key), entry))
+            .filter(|(path, _)| self.is_media_file(path) && !ignore.is_ignored(path, false))
+            .filter(|(path, entry)| {
+                let too_small = self.ignore.is_too_small(entry.size);
+                if too_small {
+                    debug!("Skipping small file: {}", path.display());
+                }
+                !too_small
+            })
+            .map(|(path, entry)| FileInfo {
+                path,
+                file_size: entry.size,
+                modified: entry.modified.trunc_subsecs(6),
+            })
+            .collect();
+        Ok(files)
+    }
+
+    /// Scan a library and reconcile it with the media repository
+    ///
+    /// Files are matched to stored items by path. Only new files and files
+    /// whose size or modification time changed are analyzed; items whose
+    /// files have disappeared are marked removed. New files whose content
+    /// hash matches a removed item are treated as moves, so the existing item
+    /// keeps its metadata and watch history.
+    pub async fn scan_library(
+        &self,
+        root: &Path,
+        repository: &MediaRepository,
+        analyzer: &MediaAnalyzer,
+    ) -> Result<ScanResult> {
+        self.scan_library_with(root, repository, analyzer, &CancellationToken::new(), |_| {})
+            .await
+    }
+
+    /// Scan a library, reporting changes as they happen
+    ///
+    /// The scan stops between files once `cancel` is triggered; work already
+    /// stored is kept and the result is flagged as cancelled. With a storage
+    /// backend, `root` is its location and files are listed and read through
+    /// it.
+    pub async fn scan_library_with<F>(
+        &self,
+        root: &Path,
+        repository: &MediaRepository,
+        analyzer: &MediaAnalyzer,
+        cancel: &CancellationToken,
+        mut on_event: F,
+    ) -> Result<ScanResult>
+    where
+        F: FnMut(ScanEvent),
+    {
+        info!("Scanning library: {}", root.display());
+
+        let files = match &self.storage {
+            Some(storage) => self.collect_entries(storage.as_ref()).await?,
+            None => self.collect_files(root).await?,
+        };
+        let existing = repository.get_file_states_by_prefix(&path_prefix(root)).await?;
+        let directories = files
+            .iter()
+            .filter_map(|file| file.path.parent().map(Path::to_path_buf))
+            .collect::<HashSet<_>>();
+
+        let items_found = files.len() as u32;
+        let plan = plan_scan(existing, files);
+        let unchanged = plan.unchanged;
+
+        let mut result = self.apply_plan(plan, repository, analyzer, cancel, &mut on_event).await?;
+        result.items_found = items_found;
+
+        // Sidecars are found by reading folders, which is only done for
+        // libraries on the local disk
+        if !result.cancelled && self.storage.is_none() {
+            if let Err(e) = self.associate_sidecars(root, &directories, &HashSet::new(), repository, &mut on_event).await {
+                warn!("Failed to associate sidecar files in {}: {}", root.display(), e);
+                result.errors.push(format!("{}: {}", root.display(), e));
+            }
+        }
+        if !result.cancelled {
+            if let Err(e) = self.stack_items(root, &directories, repository, &mut on_event).await {
+                warn!("Failed to stack media items in {}: {}", root.display(), e);
+                result.errors.push(format!("{}: {}", root.display(), e));
+            }
+            if result.items_added + result.items_updated + result.items_removed > 0 {
+                let grouped = if self.audiobooks {
+                    self.group_audiobooks(repository, &mut on_event).await
+                } else {
+                    self.group_music(repository, &mut on_event).await
+                };
+                if let Err(e) = grouped {
+                    warn!("Failed to group audio files in {}: {}", root.display(), e);
+                    result.errors.push(format!("{}: {}", root.display(), e));
+                }
+            }
+            if let Err(e) = self.remove_orphaned_images(repository).await {
+                warn!("Failed to remove orphaned images: {}", e);
+                result.errors.push(format!("{}: {}", root.display(), e));
+            }
+        }
+
+        info!(
+            "Scanned {}: {} found, {} added, {} updated, {} removed, {} unchanged, {} errors{}",
+            root.display(),
+            result.items_found,
+            result.items_added,
+            result.items_updated,
+            result.items_removed,
+            unchanged,
+            result.errors.len(),
+            if result.cancelled { " (cancelled)" } else { "" }
+        );
+        Ok(result)
+    }
+
+    /// Apply a debounced file system change to the media repository
+    ///
+    /// Changes to media or sidecar files also re-associate the sidecars and
+    /// stacks of the directories involved, and changes to music files regroup
+    /// the albums and artists, or the books of audiobook libraries. `Rescan`
+    /// events change nothing, as they call for a full scan instead.
+    pub async fn apply_watch_event<F>(
+        &self,
+        event: &WatchEvent,
+        repository: &MediaRepository,
+        analyzer: &MediaAnalyzer,
+        mut on_event: F,
+    ) -> Result<ScanResult>
+    where
+        F: FnMut(ScanEvent),
+    {
+        debug!("Applying watch event: {:?}", event);
+
+        // Changes inside a disc structure update the disc as a whole
+        let event = &disc_event(event).unwrap_or_else(|| event.clone());
+        let paths = match event {
+            WatchEvent::Created(path) | WatchEvent::Modified(path) | WatchEvent::Removed(path) => vec![path],
+            WatchEvent::Renamed { from, to } => vec![from, to],
+            WatchEvent::Rescan => return Ok(ScanResult::default()),
+        };
+        let audio = paths
+            .iter()
+            .filter(|path| {
+                self.is_media_file(path)
+                    && path
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .is_some_and(|ext| MediaFormat::from_extension(ext).is_audio())
+            })
+            .map(|path| path.to_path_buf())
+            .collect::<Vec<_>>();
+        // Albums and books the files were grouped into before the change are
+        // regrouped as well, in case they lose their last file
+        let previous = if audio.is_empty() {
+            Vec::new()
+        } else if self.audiobooks {
+            repository
+                .get_audiobook_files_by_paths(&path_strings(&audio))
+                .await?
+                .into_iter()
+                .filter_map(|file| file.book_id)
+                .collect::<Vec<_>>()
+        } else {
+            repository
+                .get_music_tracks_by_paths(&path_strings(&audio))
+                .await?
+                .into_iter()
+                .filter_map(|track| track.album_id)
+                .collect::<Vec<_>>()
+        };
+
+        let result = self.apply_file_change(event, repository, analyzer, &mut on_event).await?;
+        let changed = paths
+            .iter()
+            .filter(|path| sidecar::is_sidecar_file(path))
+            .map(|path| path.to_path_buf())
+            .collect::<HashSet<_>>();
+        let directories = paths
+            .into_iter()
+            .filter(|path| self.is_media_file(path) || sidecar::is_sidecar_file(path))
+            .filter_map(|path| path.parent().map(Path::to_path_buf))
+            .collect::<HashSet<_>>();
+        for directory in &directories {
+            let single = HashSet::from([directory.clone()]);
+            self.associate_sidecars(directory, &single, &changed, repository, &mut on_event).await?;
+            self.stack_items(directory, &single, repository, &mut on_event).await?;
+        }
+        if !audio.is_empty() && self.audiobooks {
+            self.group_audiobook_changes(&audio, &previous, repository, &mut on_event).await?;
+        } else if !audio.is_empty() {
+            self.group_music_changes(&audio, &previous, repository, &mut on_event).await?;
+        }
+        self.remove_orphaned_images(repository).await?;
+
+        Ok(result)
+    }
+
+    /// Re-associate the sidecar files of videos in the given directories
+    ///
+    /// `root` bounds the stored items considered. Items whose sidecars
+    /// changed are reported as updated. Artwork is run through the image
+    /// pipeline when it is new, missing its images or listed in `changed`.
+    pub async fn associate_sidecars<F>(
+        &self,
+        root: &Path,
+        directories: &HashSet<PathBuf>,
+        changed: &HashSet<PathBuf>,
+        repository: &MediaRepository,
+        on_event: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let prefix = path_prefix(root);
+        let items = repository
+            .get_file_states_by_prefix(&prefix)
+            .await?
+            .into_iter()
+            .filter(|state| state.removed_at.is_none())
+            .map(|state| (PathBuf::from(state.path), state.id))
+            .collect::<HashMap<_, _>>();
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        let mut stored: HashMap<Uuid, Vec<Sidecar>> = HashMap::new();
+        for model in repository.get_sidecars_by_prefix(&prefix).await? {
+            if let Some(sidecar) = Sidecar::from_model(&model) {
+                stored.entry(model.media_id).or_default().push(sidecar);
+            }
+        }
+        let mut linked: HashMap<Uuid, HashSet<String>> = HashMap::new();
+        if self.thumbnails.is_some() {
+            for image in repository.get_media_images_by_prefix(&prefix).await? {
+                linked.entry(image.media_id).or_default().insert(image.kind);
+            }
+        }
+
+        for directory in directories {
+            let dir = directory.clone();
+            let found = match tokio::task::spawn_blocking(move || sidecar::discover(&dir))
+                .await
+                .map_err(|e| RustFlixError::internal(format!("Sidecar discovery failed: {}", e)))?
+            {
+                Ok(found) => found,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(RustFlixError::Io(e)),
+            };
+
+            for (video, sidecars) in found {
+                let Some(&id) = items.get(&video) else {
+                    continue;
+                };
+                let unchanged = stored.get(&id).map_or(&[][..], Vec::as_slice) == sidecars.as_slice();
+                if !unchanged {
+                    let models = sidecars.iter().map(|sidecar| sidecar.to_model(id)).collect::<Vec<_>>();
+                    repository.set_sidecars(id, &models).await?;
+                    debug!("Associated {} sidecar files with {}", models.len(), video.display());
+                    on_event(ScanEvent::Updated { id });
+                }
+
+                let kinds = sidecars.iter().filter_map(artwork_image_kind).map(str::to_string).collect::<HashSet<_>>();
+                let artwork_changed = sidecars
+                    .iter()
+                    .any(|sidecar| artwork_image_kind(sidecar).is_some() && changed.contains(&sidecar.path));
+                if self.thumbnails.is_some()
+                    && (!unchanged || artwork_changed || linked.get(&id).unwrap_or(&HashSet::new()) != &kinds)
+                {
+                    self.store_artwork(repository, id, &sidecars).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Regroup the multi-part and multi-version movies in the given directories
+    ///
+    /// `root` bounds the stored items considered. Stacks keep their id while
+    /// any of their files remain; items whose stack changed are reported as
+    /// updated.
+    pub async fn stack_items<F>(
+        &self,
+        root: &Path,
+        directories: &HashSet<PathBuf>,
+        repository: &MediaRepository,
+        on_event: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let prefix = path_prefix(root);
+        let mut files: HashMap<&Path, Vec<PathBuf>> = HashMap::new();
+        let states = repository.get_file_states_by_prefix(&prefix).await?;
+        let ids = states
+            .into_iter()
+            .filter(|state| state.removed_at.is_none())
+            .map(|state| (PathBuf::from(state.path), state.id))
+            .collect::<HashMap<_, _>>();
+        for path in ids.keys() {
+            if let Some(directory) = path.parent().and_then(|parent| directories.get(parent)) {
+                files.entry(directory.as_path()).or_default().push(path.clone());
+            }
+        }
+
+        // Current stacks of the directories, and the members of each
+        let mut current: HashMap<Uuid, StackMemberModel> = HashMap::new();
+        let mut stack_members: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+        let mut stale: HashSet<Uuid> = HashSet::new();
+        for item in repository.get_stacked_items_by_prefix(&prefix).await? {
+            let Some(stack_id) = item.stack_id else {
+                continue;
+            };
+            if !Path::new(&item.path).parent().is_some_and(|parent| directories.contains(parent)) {
+                continue;
+            }
+            stale.insert(stack_id);
+            stack_members.entry(stack_id).or_default().insert(item.id);
+            current.insert(
+                item.id,
+                StackMemberModel {
+                    media_id: item.id,
+                    version: item.stack_version.unwrap_or_default(),
+                    part: item.stack_part.unwrap_or_default(),
+                },
+            );
+        }
+        let stack_of = |id: &Uuid| {
+            stack_members
+                .iter()
+                .find_map(|(stack_id, members)| members.contains(id).then_some(*stack_id))
+        };
+
+        for (directory, paths) in files {
+            for stack in stacking::stack_files(&paths) {
+                let members = stack
+                    .versions
+                    .iter()
+                    .flat_map(|version| {
+                        version.parts.iter().enumerate().map(|(index, path)| StackMemberModel {
+                            media_id: ids[path],
+                            version: version.name.clone(),
+                            part: index as i32 + 1,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                // Reuse the stack of an existing member unless another stack claimed it
+                let existing = members
+                    .iter()
+                    .filter_map(|member| stack_of(&member.media_id))
+                    .find(|stack_id| stale.contains(stack_id));
+                let stack_id = existing.unwrap_or_else(Uuid::new_v4);
+                stale.remove(&stack_id);
+
+                let previous = stack_members.get(&stack_id).cloned().unwrap_or_default();
+                let unchanged = existing.is_some()
+                    && previous.len() == members.len()
+                    && members.iter().all(|member| current.get(&member.media_id) == Some(member));
+                if unchanged {
+                    continue;
+                }
+
+                let now = Utc::now();
+                let model = MediaStackModel {
+                    id: stack_id,
+                    directory: directory.to_string_lossy().into_owned(),
+                    title: stack.title.clone(),
+                    year: stack.year,
+                    created_at: now,
+                    updated_at: now,
+                };
+                repository.save_stack(&model, &members).await?;
+                debug!("Stacked {} files as {}", members.len(), stack.title);
+
+                let changed = members.iter().map(|member| member.media_id).collect::<HashSet<_>>();
+                for id in changed.union(&previous) {
+                    on_event(ScanEvent::Updated { id: *id });
+                }
+            }
+        }
+
+        if !stale.is_empty() {
+            let stale = stale.into_iter().collect::<Vec<_>>();
+            repository.delete_stacks(&stale).await?;
+            for id in stale.iter().filter_map(|stack_id| stack_members.get(stack_id)).flatten() {
+                on_event(ScanEvent::Updated { id: *id });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the media file part of a watch event
+    async fn apply_file_change<F>(
+        &self,
+        event: &WatchEvent,
+        repository: &MediaRepository,
+        analyzer: &MediaAnalyzer,
+        on_event: &mut F,
+    ) -> Result<ScanResult>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let plan = match event {
+            WatchEvent::Created(path) | WatchEvent::Modified(path) => {
+                self.plan_file(path, repository).await?
+            }
+            WatchEvent::Removed(path) => ScanPlan {
+                removed: stored_item(repository, path).await?.into_iter().collect(),
+                ..Default::default()
+            },
+            WatchEvent::Renamed { from, to } => {
+                if let Some(result) = self.move_item(from, to, repository, on_event).await? {
+                    return Ok(result);
+                }
+
+                // Not a plain move, so handle it as a removal and a new file
+                let mut plan = self.plan_file(to, repository).await?;
+                plan.removed = stored_item(repository, from).await?.into_iter().collect();
+                plan
+            }
+            WatchEvent::Rescan => ScanPlan::default(),
+        };
+
+        self.apply_plan(plan, repository, analyzer, &CancellationToken::new(), on_event).await
+    }
+
+    /// Plan the work for a single file
+    async fn plan_file(&self, path: &Path, repository: &MediaRepository) -> Result<ScanPlan> {
+        if !(path.is_file() && self.is_media_file(path) || self.is_disc_folder(path)) {
+            return Ok(ScanPlan::default());
+        }
+
+        let file = self.get_file_info(path).await?;
+        let existing = repository
+            .get_media_item_by_path(&path.to_string_lossy())
+            .await?
+            .map(|item| file_state(&item));
+
+        Ok(plan_scan(existing.into_iter().collect(), vec![file]))
+    }
+
+    /// Point the stored item of a renamed file at its new path
+    async fn move_item<F>(
+        &self,
+        from: &Path,
+        to: &Path,
+        repository: &MediaRepository,
+        on_event: &mut F,
+    ) -> Result<Option<ScanResult>>
+    where
+        F: FnMut(ScanEvent),
+    {
+        if !to.is_file() || !self.is_media_file(to) {
+            return Ok(None);
+        }
+        if repository.get_media_item_by_path(&to.to_string_lossy()).await?.is_some() {
+            return Ok(None);
+        }
+        let item = match repository.get_media_item_by_path(&from.to_string_lossy()).await? {
+            Some(item) if item.removed_at.is_none() => item,
+            _ => return Ok(None),
+        };
+
+        let id = item.id;
+        let file = self.get_file_info(to).await?;
+        self.store_moved(repository, item, &file, None).await?;
+        info!("Detected move of {} to {}", from.display(), to.display());
+        on_event(ScanEvent::Updated { id });
+
+        Ok(Some(ScanResult {
+            items_found: 1,
+            items_updated: 1,
+            ..Default::default()
+        }))
+    }
+
+    /// Regroup the tagged music tracks of all libraries into albums and artists
+    ///
+    /// Albums span libraries, so every track is considered. Tracks whose
+    /// album or artist changed are reported as updated.
+    pub async fn group_music<F>(&self, repository: &MediaRepository, on_event: &mut F) -> Result<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let tracks = repository
+            .get_music_tracks()
+            .await?
+            .into_iter()
+            .map(|(path, track)| (PathBuf::from(path), track))
+            .collect::<Vec<_>>();
+        self.save_music_groups(tracks, None, repository, on_event).await
+    }
+
+    /// Regroup the albums and artists affected by changes to the given music files
+    ///
+    /// These are the albums in `previous`, which the files' tracks were
+    /// grouped into before the change, and the albums sharing a title or
+    /// MusicBrainz release with the tracks now. Each is regrouped with all
+    /// of its tracks. Tracks whose album or artist changed are reported as
+    /// updated.
+    pub async fn group_music_changes<F>(
+        &self,
+        paths: &[PathBuf],
+        previous: &[Uuid],
+        repository: &MediaRepository,
+        on_event: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let changed = repository.get_music_tracks_by_paths(&path_strings(paths)).await?;
+        let mut album_ids = previous
+            .iter()
+            .copied()
+            .chain(changed.iter().filter_map(|track| track.album_id))
+            .collect::<HashSet<_>>();
+        let (titles, releases) = music::album_keys(&changed);
+        if album_ids.is_empty() && titles.is_empty() && releases.is_empty() {
+            return Ok(());
+        }
+
+        let ids = album_ids.iter().copied().collect::<Vec<_>>();
+        let mut tracks = repository.get_music_tracks_by_albums(&ids, &titles, &releases).await?;
+        // Tracks sharing a title may be in an album grouped by release, whose
+        // other tracks have to be regrouped with them
+        let more = tracks
+            .iter()
+            .filter_map(|(_, track)| track.album_id)
+            .filter(|id| !album_ids.contains(id))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        if !more.is_empty() {
+            let known = tracks.iter().map(|(_, track)| track.media_id).collect::<HashSet<_>>();
+            let others = repository.get_music_tracks_by_albums(&more, &[], &[]).await?;
+            tracks.extend(others.into_iter().filter(|(_, track)| !known.contains(&track.media_id)));
+            album_ids.extend(more);
+        }
+
+        let tracks = tracks.into_iter().map(|(path, track)| (PathBuf::from(path), track)).collect();
+        let replaced = album_ids.into_iter().collect::<Vec<_>>();
+        self.save_music_groups(tracks, Some(&replaced), repository, on_event).await
+    }
+
+    /// Group tracks into albums and artists and store them
+    ///
+    /// With `replaced`, only those albums are replaced by the new ones;
+    /// otherwise the albums and artists of all libraries are.
+    async fn save_music_groups<F>(
+        &self,
+        tracks: Vec<(PathBuf, MusicTrackModel)>,
+        replaced: Option<&[Uuid]>,
+        repository: &MediaRepository,
+        on_event: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let artists = repository.get_music_artists().await?;
+        let albums = repository.get_music_albums().await?;
+
+        let library = music::group(&tracks, &artists, &albums);
+        match replaced {
+            Some(replaced) => {
+                repository
+                    .save_music_albums(&library.artists, &library.albums, &library.links, replaced)
+                    .await?
+            }
+            None => {
+                repository
+                    .save_music_library(&library.artists, &library.albums, &library.links)
+                    .await?
+            }
+        }
+        debug!("Grouped {} music tracks into {} albums", tracks.len(), library.albums.len());
+
+        let current = tracks
+            .iter()
+            .map(|(_, track)| (track.media_id, (track.album_id, track.artist_id)))
+            .collect::<HashMap<_, _>>();
+        for link in &library.links {
+            if current.get(&link.media_id) != Some(&(link.album_id, link.artist_id)) {
+                on_event(ScanEvent::Updated { id: link.media_id });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Regroup the files of all audiobook libraries into books
+    ///
+    /// Files whose book or position in it changed are reported as updated.
+    pub async fn group_audiobooks<F>(&self, repository: &MediaRepository, on_event: &mut F) -> Result<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let files = repository
+            .get_audiobook_files()
+            .await?
+            .into_iter()
+            .map(|(path, file)| (PathBuf::from(path), file))
+            .collect::<Vec<_>>();
+        self.save_audiobook_groups(files, None, repository, on_event).await
+    }
+
+    /// Regroup the books affected by changes to the given audiobook files
+    ///
+    /// These are the books in `previous`, which the files were grouped into
+    /// before the change, and the books of the folders holding the files.
+    /// Files whose book or position in it changed are reported as updated.
+    pub async fn group_audiobook_changes<F>(
+        &self,
+        paths: &[PathBuf],
+        previous: &[Uuid],
+        repository: &MediaRepository,
+        on_event: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let mut prefixes = paths
+            .iter()
+            .map(|path| path_prefix(audiobooks::book_dir(path)))
+            .collect::<Vec<_>>();
+        prefixes.sort();
+        prefixes.dedup();
+
+        let files = repository
+            .get_audiobook_files_by_prefixes(&prefixes)
+            .await?
+            .into_iter()
+            .map(|(path, file)| (PathBuf::from(path), file))
+            .collect::<Vec<_>>();
+        let replaced = previous
+            .iter()
+            .copied()
+            .chain(files.iter().filter_map(|(_, file)| file.book_id))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        self.save_audiobook_groups(files, Some(&replaced), repository, on_event).await
+    }
+
+    /// Group files into books and store them
+    ///
+    /// With `replaced`, only those books are replaced by the new ones;
+    /// otherwise the books of all libraries are.
+    async fn save_audiobook_groups<F>(
+        &self,
+        files: Vec<(PathBuf, AudiobookFileModel)>,
+        replaced: Option<&[Uuid]>,
+        repository: &MediaRepository,
+        on_event: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let books = repository.get_audiobooks().await?;
+
+        let library = audiobooks::group(&files, &books);
+        match replaced {
+            Some(replaced) => repository.save_audiobooks(&library.books, &library.links, replaced).await?,
+            None => repository.save_audiobook_library(&library.books, &library.links).await?,
+        }
+        debug!("Grouped {} audiobook files into {} books", files.len(), library.books.len());
+
+        let current = files
+            .iter()
+            .map(|(_, file)| (file.media_id, (file.book_id, file.part_number)))
+            .collect::<HashMap<_, _>>();
+        for link in &library.links {
+            if current.get(&link.media_id) != Some(&(link.book_id, link.part_number)) {
+                on_event(ScanEvent::Updated { id: link.media_id });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bring the repository in line with a scan plan
+    async fn apply_plan<F>(
+        &self,
+        plan: ScanPlan,
+        repository: &MediaRepository,
+        analyzer: &MediaAnalyzer,
+        cancel: &CancellationToken,
+        mut on_event: F,
+    ) -> Result<ScanResult>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let mut result = ScanResult::default();
+        let total = (plan.added.len() + plan.changed.len() + plan.restored.len()) as u32;
+        let mut processed = 0;
+        let mut progress = |path: &Path, on_event: &mut F| {
+            processed += 1;
+            on_event(ScanEvent::Progress { processed, total, path: path.to_path_buf() });
+        };
+
+        // Mark missing files first so that moves within the library are found
+        if !plan.removed.is_empty() {
+            result.items_removed = repository
+                .mark_media_items_removed(&plan.removed, Utc::now())
+                .await? as u32;
+        }
+        let mut removed = plan.removed.into_iter().collect::<HashSet<_>>();
+
+        let mut hashed = Vec::with_capacity(plan.added.len());
+        let mut hashes = stream::iter(plan.added)
+            .map(|file| async move {
+                let hash = self.hash_file(&file).await;
+                (file, hash)
+            })
+            .buffer_unordered(ANALYSIS_CONCURRENCY);
+
+        while let Some((file, hash)) = hashes.next().await {
+            match hash {
+                Ok(hash) => hashed.push((file, hash)),
+                Err(e) => {
+                    warn!("Failed to hash {}: {}", file.path.display(), e);
+                    result.errors.push(format!("{}: {}", file.path.display(), e));
+                    progress(&file.path, &mut on_event);
+                }
+            }
+            if cancel.is_cancelled() {
+                result.cancelled = true;
+                break;
+            }
+        }
+        drop(hashes);
+
+        let fingerprints = hashed
+            .iter()
+            .map(|(_, hash)| hash.fingerprint.clone())
+            .collect::<Vec<_>>();
+        let candidates = if fingerprints.is_empty() || result.cancelled {
+            Vec::new()
+        } else {
+            repository.get_removed_media_items_by_hashes(&fingerprints).await?
+        };
+        let (moved, added) = match_moved(hashed, candidates);
+
+        for MovedFile { item, file, hash } in moved {
+            if cancel.is_cancelled() {
+                result.cancelled = true;
+                break;
+            }
+
+            let id = item.id;
+            let from = item.path.clone();
+            match self.store_moved(repository, item, &file, Some(&hash)).await {
+                Ok(()) => {
+                    info!("Detected move of {} to {}", from, file.path.display());
+                    result.items_updated += 1;
+                    if removed.remove(&id) {
+                        result.items_removed = result.items_removed.saturating_sub(1);
+                    }
+                    on_event(ScanEvent::Updated { id });
+                }
+                Err(e) => {
+                    warn!("Failed to process {}: {}", file.path.display(), e);
+                    result.errors.push(format!("{}: {}", file.path.display(), e));
+                }
+            }
+            progress(&file.path, &mut on_event);
+        }
+
+        let jobs = added
+            .into_iter()
+            .map(|(file, hash)| (ScanAction::Add, file, Some(hash)))
+            .chain(plan.changed.into_iter().map(|(id, file)| (ScanAction::Update(id), file, None)))
+            .chain(plan.restored.into_iter().map(|(id, file)| (ScanAction::Restore(id), file, None)))
+            .take_while(|_| !cancel.is_cancelled());
+
+        let mut analyzed = stream::iter(jobs)
+            .map(|(action, file, hash)| async move {
+                let processed = async {
+                    let hash = match hash {
+                        Some(hash) => hash,
+                        None => self.hash_file(&file).await?,
+                    };
+                    let info = match &self.storage {
+                        Some(storage) => analyzer.analyze_entry(storage.clone(), &storage_entry(storage.as_ref(), &file)?).await?,
+                        None => analyzer.analyze_file(&file.path).await?,
+                    };
+                    Ok::<_, RustFlixError>((hash, info))
+                }
+                .await;
+                (action, file, processed)
+            })
+            .buffer_unordered(ANALYSIS_CONCURRENCY);
+
+        while let Some((action, file, processed)) = analyzed.next().await {
+            let stored = match processed {
+                Ok((hash, info)) => self.store_item(repository, action, &file, &hash, &info).await,
+                Err(e) => Err(e),
+            };
+
+            match (stored, action) {
+                (Ok(id), ScanAction::Update(_)) => {
+                    result.items_updated += 1;
+                    on_event(ScanEvent::Updated { id });
+                }
+                (Ok(id), _) => {
+                    result.items_added += 1;
+                    on_event(ScanEvent::Added { id, path: file.path.clone() });
+                }
+                (Err(e), _) => {
+                    warn!("Failed to process {}: {}", file.path.display(), e);
+                    result.errors.push(format!("{}: {}", file.path.display(), e));
+                }
+            }
+            progress(&file.path, &mut on_event);
+        }
+        if cancel.is_cancelled() {
+            result.cancelled = true;
+        }
+
+        // Images of removed items become orphans, cleaned up after the scan
+        let removed = removed.into_iter().collect::<Vec<_>>();
+        if !removed.is_empty() {
+            repository.delete_media_images(&removed).await?;
+        }
+        for id in removed {
+            on_event(ScanEvent::Removed { id });
+        }
+        Ok(result)
+    }
+
+    async fn store_item(
+        &self,
+        repository: &MediaRepository,
+        action: ScanAction,
+        file: &FileInfo,
+        hash: &FileHash,
+        info: &MediaInfo,
+    ) -> Result<Uuid> {
+        let item = self.media_item_from_file(file);
+        let mut model = media_item_model(&item, file, hash, info);
+
+        match action {
+            ScanAction::Add => repository.create_media_item(&model).await?,
+            ScanAction::Update(id) | ScanAction::Restore(id) => {
+                model.id = id;
+                repository.update_media_item(&model).await?
+            }
+        }
+
+        let cover_hash = match info.tags.as_ref().and_then(|tags| tags.cover.as_ref()) {
+            Some(cover) => {
+                let hash = music::cover_hash(cover);
+                repository.save_cover_art(&hash, &cover.mime_type, &cover.data).await?;
+                Some(hash)
+            }
+            None => None,
+        };
+        if item.media_type == MediaType::Audiobook {
+            // Untagged audiobook files are still grouped, by their folder
+            let tags = info.tags.clone().unwrap_or_default();
+            repository.save_audiobook_file(&audiobooks::file_model(model.id, &tags, cover_hash)).await?;
+        } else {
+            match &info.tags {
+                Some(tags) => repository.save_music_track(&music::track_model(model.id, tags, cover_hash)).await?,
+                None if item.media_type == MediaType::Music => repository.delete_music_track(model.id).await?,
+                None => {}
+            }
+        }
+        if item.media_type == MediaType::Photo {
+            self.store_photo(repository, model.id, file, info).await?;
+        }
+        if item.media_type == MediaType::Book {
+            self.store_book(repository, model.id, file, info.book.clone().unwrap_or_default()).await?;
+        }
+        if let Some(chapters) = &info.chapters {
+            let chapters = chapters
+                .iter()
+                .map(|chapter| MediaChapterModel {
+                    media_id: model.id,
+                    chapter_index: chapter.id as i32,
+                    start_time: chapter.start_time,
+                    end_time: chapter.end_time,
+                    title: chapter.title.clone(),
+                })
+                .collect::<Vec<_>>();
+            repository.set_chapters(model.id, &chapters).await?;
+        }
+        Ok(model.id)
+    }
+
+    /// Store the EXIF details of a photo and write its thumbnails
+    ///
+    /// Photos without an EXIF date are dated by their modification time.
+    /// Failing to write thumbnails does not fail the photo.
+    async fn store_photo(&self, repository: &MediaRepository, id: Uuid, file: &FileInfo, info: &MediaInfo) -> Result<()> {
+        let exif = info.exif.clone().unwrap_or_default();
+        let photo = PhotoModel {
+            media_id: id,
+            taken_at: exif.date_taken.unwrap_or_else(|| file.modified.naive_utc()),
+            has_exif_date: exif.date_taken.is_some(),
+            width: info.width.map(|width| width as i32),
+            height: info.height.map(|height| height as i32),
+            camera_make: exif.camera_make,
+            camera_model: exif.camera_model,
+            lens_model: exif.lens_model,
+            orientation: exif.orientation.map(i32::from),
+            latitude: exif.latitude,
+            longitude: exif.longitude,
+            altitude: exif.altitude,
+            updated_at: Utc::now(),
+        };
+
+        repository.save_photo(&photo).await?;
+
+        let images = match self.store_image(repository, &file.path, exif.orientation).await {
+            Ok(Some(hash)) => vec![MediaImageModel { media_id: id, kind: "photo".to_string(), hash }],
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                warn!("Failed to write thumbnails of {}: {}", file.path.display(), e);
+                Vec::new()
+            }
+        };
+        repository.set_media_images(id, &images).await
+    }
+
+    /// Store the details of a comic or e-book and link its cover as its
+    /// poster
+    ///
+    /// Failing to write the variants of the cover does not fail the book.
+    async fn store_book(&self, repository: &MediaRepository, id: Uuid, file: &FileInfo, book: BookInfo) -> Result<()> {
+        let model = BookModel {
+            media_id: id,
+            title: book.title,
+            author: book.author,
+            series: book.series,
+            series_index: book.series_index,
+            publisher: book.publisher,
+            language: book.language,
+            description: book.description,
+            year: book.year,
+            page_count: book.page_count.map(|count| count as i32),
+            updated_at: Utc::now(),
+        };
+        repository.save_book(&model).await?;
+
+        let Some(cover) = book.cover else {
+            return repository.set_media_images(id, &[]).await;
+        };
+        let images = match self.store_image_data(repository, cover.data, None, &file.path).await {
+            Ok(Some(hash)) => vec![MediaImageModel { media_id: id, kind: "poster".to_string(), hash }],
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                warn!("Failed to process the cover of {}: {}", file.path.display(), e);
+                Vec::new()
+            }
+        };
+        repository.set_media_images(id, &images).await
+    }
+
+    /// Read the details and cover of a comic or e-book, through the storage
+    /// backend when there is one
+    async fn read_book(&self, file: &FileInfo) -> Result<BookInfo> {
+        let format = MediaFormat::from_extension(file.path.extension().and_then(|ext| ext.to_str()).unwrap_or(""));
+        let task = match &self.storage {
+            Some(storage) => {
+                let entry = storage_entry(storage.as_ref(), file)?;
+                let mut reader = std::io::BufReader::new(StorageReader::new(storage.clone(), &entry)?);
+                tokio::task::spawn_blocking(move || books::read_from(&mut reader, entry.size, format))
+            }
+            None => {
+                let path = file.path.clone();
+                tokio::task::spawn_blocking(move || books::read_file(&path, format))
+            }
+        };
+        task.await.map_err(|e| RustFlixError::internal(format!("Book task failed: {}", e)))?
+    }
+
+    /// Run an image file through the pipeline unless it was processed
+    /// before, returning its content hash
+    ///
+    /// Returns `None` when variants are not generated.
+    async fn store_image(&self, repository: &MediaRepository, source: &Path, orientation: Option<u16>) -> Result<Option<String>> {
+        if self.thumbnails.is_none() {
+            return Ok(None);
+        }
+
+        let data = match &self.storage {
+            Some(storage) => {
+                let key = storage_key(storage.as_ref(), source)?;
+                storage.read_range(&key, 0..u64::MAX).await?.to_vec()
+            }
+            None => fs::read(source).await?,
+        };
+        self.store_image_data(repository, data, orientation, source).await
+    }
+
+    /// Run image data, read from `source`, through the pipeline unless it
+    /// was processed before, returning its content hash
+    ///
+    /// Returns `None` when variants are not generated.
+    async fn store_image_data(
+        &self,
+        repository: &MediaRepository,
+        data: Vec<u8>,
+        orientation: Option<u16>,
+        source: &Path,
+    ) -> Result<Option<String>> {
+        let Some((root, sizes)) = &self.thumbnails else {
+            return Ok(None);
+        };
+
+        let hash = images::content_hash(&data);
+        if repository.get_image(&hash).await?.is_some() {
+            return Ok(Some(hash));
+        }
+
+        let (root, sizes) = (root.clone(), sizes.clone());
+        let image = tokio::task::spawn_blocking(move || images::process_image(&data, orientation, &root, &sizes))
+            .await
+            .map_err(|e| RustFlixError::internal(format!("Image task failed: {}", e)))??;
+        let variants = image
+            .variants
+            .iter()
+            .map(|variant| ImageVariantModel {
+                hash: image.hash.clone(),
+                width: variant.width as i32,
+                height: variant.height as i32,
+                format: variant.format.name().to_string(),
+                path: variant.path.to_string_lossy().into_owned(),
+            })
+            .collect::<Vec<_>>();
+        let model = ImageModel {
+            hash: image.hash,
+            width: image.width as i32,
+            height: image.height as i32,
+            blurhash: image.blurhash,
+            created_at: Utc::now(),
+        };
+        repository.save_image(&model, &variants).await?;
+        debug!("Processed image {} into {} variants", source.display(), variants.len());
+        Ok(Some(model.hash))
+    }
+
+    /// Link a video to the images of its poster, backdrop and thumbnail
+    /// artwork
+    ///
+    /// Artwork that cannot be decoded is skipped.
+    async fn store_artwork(&self, repository: &MediaRepository, id: Uuid, sidecars: &[Sidecar]) -> Result<()> {
+        let mut images: Vec<MediaImageModel> = Vec::new();
+        for sidecar in sidecars {
+            let Some(kind) = artwork_image_kind(sidecar) else {
+                continue;
+            };
+            if images.iter().any(|image| image.kind == kind) {
+                continue;
+            }
+            match self.store_image(repository, &sidecar.path, None).await {
+                Ok(Some(hash)) => images.push(MediaImageModel { media_id: id, kind: kind.to_string(), hash }),
+                Ok(None) => return Ok(()),
+                Err(e) => warn!("Failed to process artwork {}: {}", sidecar.path.display(), e),
+            }
+        }
+        repository.set_media_images(id, &images).await
+    }
+
+    /// Delete images no media item uses anymore, with their files
+    ///
+    /// Returns the number of variant files removed.
+    pub async fn remove_orphaned_images(&self, repository: &MediaRepository) -> Result<usize> {
+        let variants = repository.delete_orphaned_images().await?;
+        if variants.is_empty() {
+            return Ok(0);
+        }
+
+        let paths = variants.iter().map(|variant| PathBuf::from(&variant.path)).collect::<Vec<_>>();
+        let count = paths.len();
+        tokio::task::spawn_blocking(move || images::remove_variants(&paths))
+            .await
+            .map_err(|e| RustFlixError::internal(format!("Image cleanup failed: {}", e)))??;
+        debug!("Removed {} orphaned image variants", count);
+        Ok(count)
+    }
+
+    /// Hash a file, through the storage backend when there is one
+    async fn hash_file(&self, file: &FileInfo) -> Result<FileHash> {
+        match &self.storage {
+            Some(storage) => self.hasher.hash_entry(storage.clone(), &storage_entry(storage.as_ref(), file)?).await,
+            None => self.hasher.hash_file(&file.path).await,
+        }
+    }
+
+    /// Point an item at the file it was moved to
+    ///
+    /// Without a new hash the stored hashes are kept, as the content is unchanged.
+    async fn store_moved(
+        &self,
+        repository: &MediaRepository,
+        mut model: MediaItemModel,
+        file: &FileInfo,
+        hash: Option<&FileHash>,
+    ) -> Result<()> {
+        let item = self.media_item_from_file(file);
+
+        model.path = file.path.to_string_lossy().into_owned();
+        model.file_size = file.file_size as i64;
+        model.media_type = item.media_type.as_str().to_string();
+        let extra = extras::detect(&file.path);
+        model.extra_type = extra.as_ref().map(|extra| extra.extra_type.as_str().to_string());
+        model.extra_owner = extra.map(|extra| extra.owner.to_string_lossy().into_owned());
+        if let Some(hash) = hash {
+            model.file_hash = Some(hash.fingerprint.clone());
+            model.full_hash = hash.full.clone().or(model.full_hash);
+        }
+        model.file_modified = Some(file.modified);
+        model.removed_at = None;
+        model.updated_at = Utc::now();
+        repository.update_media_item(&model).await?;
+
+        // Photos removed by an earlier scan lost their thumbnails
+        if item.media_type == MediaType::Photo && repository.get_media_images(model.id).await?.is_empty() {
+            let orientation = repository
+                .get_photo(model.id)
+                .await?
+                .and_then(|photo| photo.orientation)
+                .and_then(|orientation| u16::try_from(orientation).ok());
+            if let Some(hash) = self.store_image(repository, &file.path, orientation).await? {
+                let image = MediaImageModel { media_id: model.id, kind: "photo".to_string(), hash };
+                repository.set_media_images(model.id, &[image]).await?;
+            }
+        }
+        // And so did books their covers
+        if item.media_type == MediaType::Book && self.thumbnails.is_some() && repository.get_media_images(model.id).await?.is_empty() {
+            match self.read_book(file).await {
+                Ok(book) => self.store_book(repository, model.id, file, book).await?,
+                Err(e) => warn!("Failed to read {}: {}", file.path.display(), e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Change reported while a scan or watch event is applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanEvent {
+    /// A file has been processed
+    Progress { processed: u32, total: u32, path: PathBuf },
+    Added { id: Uuid, path: PathBuf },
+    Updated { id: Uuid },
+    Removed { id: Uuid },
+}
+
+/// Work needed to bring the repository in line with the file system
+#[derive(Debug, Default)]
+pub struct ScanPlan {
+    /// Files not yet in the repository
+    pub added: Vec<FileInfo>,
+    /// Known files whose size or modification time changed
+    pub changed: Vec<(Uuid, FileInfo)>,
+    /// Previously removed files that are back on disk
+    pub restored: Vec<(Uuid, FileInfo)>,
+    /// Items whose files are gone
+    pub removed: Vec<Uuid>,
+    /// Number of files that need no work
+    pub unchanged: u32,
+}
+
+/// Compare collected files with stored file states
+pub fn plan_scan(existing: Vec<MediaFileStateModel>, files: Vec<FileInfo>) -> ScanPlan {
+    let mut known = existing
+        .into_iter()
+        .map(|state| (state.path.clone(), state))
+        .collect::<HashMap<_, _>>();
+    let mut plan = ScanPlan::default();
+
+    for file in files {
+        let state = known.remove(file.path.to_string_lossy().as_ref());
+        match state {
+            None => plan.added.push(file),
+            Some(state) if state.removed_at.is_some() => plan.restored.push((state.id, file)),
+            Some(state)
+                if state.file_size == file.file_size as i64
+                    && state.file_modified == Some(file.modified) =>
+            {
+                plan.unchanged += 1
+            }
+            Some(state) => plan.changed.push((state.id, file)),
+        }
+    }
+
+    plan.removed = known
+        .into_values()
+        .filter(|state| state.removed_at.is_none())
+        .map(|state| state.id)
+        .collect();
+    plan
+}
+
+/// Collected file together with its content hash
+pub type HashedFile = (FileInfo, FileHash);
+
+/// Removed item whose content reappeared at a new path
+#[derive(Debug, Clone)]
+pub struct MovedFile {
+    pub item: MediaItemModel,
+    pub file: FileInfo,
+    pub hash: FileHash,
+}
+
+/// Match new files to removed items with the same content
+///
+/// Returns the moved items and the files that are genuinely new.
+pub fn match_moved(files: Vec<HashedFile>, candidates: Vec<MediaItemModel>) -> (Vec<MovedFile>, Vec<HashedFile>) {
+    let mut candidates = candidates.into_iter().map(Some).collect::<Vec<_>>();
+    let mut moved = Vec::new();
+    let mut added = Vec::new();
+
+    for (file, hash) in files {
+        let found = candidates.iter_mut().find(|candidate| {
+            candidate.as_ref().is_some_and(|model| {
+                hash.matches(model.file_hash.as_deref(), model.full_hash.as_deref())
+            })
+        });
+
+        match found.and_then(Option::take) {
+            Some(item) => moved.push(MovedFile { item, file, hash }),
+            None => added.push((file, hash)),
+        }
+    }
+
+    (moved, added)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ScanAction {
+    Add,
+    Update(Uuid),
+    Restore(Uuid),
+}
+
+/// Event on the disc folder holding a file changed inside a Blu-ray or DVD
+/// structure, `None` for files outside of discs
+fn disc_event(event: &WatchEvent) -> Option<WatchEvent> {
+    let root = |path: &Path| disc::disc_root(path).map(Path::to_path_buf);
+    let changed = |root: PathBuf| {
+        if disc::detect_folder(&root).is_some() {
+            WatchEvent::Modified(root)
+        } else {
+            WatchEvent::Removed(root)
+        }
+    };
+
+    match event {
+        WatchEvent::Created(path) | WatchEvent::Modified(path) | WatchEvent::Removed(path) => root(path).map(changed),
+        WatchEvent::Renamed { from, to } => match (root(from), root(to)) {
+            (Some(from), Some(to)) if from != to => Some(WatchEvent::Renamed { from, to }),
+            (_, Some(root)) | (Some(root), None) => Some(changed(root)),
+            (None, None) => None,
+        },
+        WatchEvent::Rescan => None,
+    }
+}
+
+/// Database timestamps keep microseconds, so file times are truncated to match
+fn modification_time(modified: std::time::SystemTime) -> DateTime<Utc> {
+    DateTime::<Utc>::from(modified).trunc_subsecs(6)
+}
+
+/// Key of a file in a storage backend from its path
+fn storage_key(storage: &dyn LibraryStorage, path: &Path) -> Result<String> {
+    storage
+        .key(path)
+        .ok_or_else(|| RustFlixError::not_found("file", &path.to_string_lossy()))
+}
+
+fn storage_entry(storage: &dyn LibraryStorage, file: &FileInfo) -> Result<StorageEntry> {
+    Ok(StorageEntry {
+        key: storage_key(storage, &file.path)?,
+        size: file.file_size,
+        modified: file.modified,
+        etag: None,
+    })
+}
+
+/// Stored, not yet removed item at a path
+async fn stored_item(repository: &MediaRepository, path: &Path) -> Result<Option<Uuid>> {
+    let item = repository.get_media_item_by_path(&path.to_string_lossy()).await?;
+    Ok(item.filter(|item| item.removed_at.is_none()).map(|item| item.id))
+}
+
+fn file_state(item: &MediaItemModel) -> MediaFileStateModel {
+    MediaFileStateModel {
+        id: item.id,
+        path: item.path.clone(),
+        file_size: item.file_size,
+        file_modified: item.file_modified,
+        removed_at: item.removed_at,
+    }
+}
+
+/// Paths as they are stored in the repository
+fn path_strings(paths: &[PathBuf]) -> Vec<String> {
+    paths.iter().map(|path| path.to_string_lossy().into_owned()).collect()
+}
+
+/// Path prefix matching every file below a library root
+fn path_prefix(root: &Path) -> String {
+    let mut prefix = root.to_string_lossy().into_owned();
+    if !prefix.ends_with(MAIN_SEPARATOR) {
+        prefix.push(MAIN_SEPARATOR);
+    }
+    prefix
+}
+
+/// Kind of image stored for artwork that goes through the image pipeline
+///
+/// Logos are left out, as they need their transparency.
+fn artwork_image_kind(sidecar: &Sidecar) -> Option<&'static str> {
+    match sidecar.kind {
+        SidecarKind::Artwork(ArtworkKind::Poster) => Some("poster"),
+        SidecarKind::Artwork(ArtworkKind::Backdrop | ArtworkKind::Fanart) => Some("backdrop"),
+        SidecarKind::Artwork(ArtworkKind::Thumbnail) => Some("thumbnail"),
+        _ => None,
+    }
+}
+
+fn media_item_model(item: &MediaItem, file: &FileInfo, hash: &FileHash, info: &MediaInfo) -> MediaItemModel {
+    let now = Utc::now();
+    let extra = extras::detect(&file.path);
+    MediaItemModel {
+        id: item.id,
+        path: file.path.to_string_lossy().into_owned(),
+        file_size: file.file_size as i64,
+        file_hash: Some(hash.fingerprint.clone()),
+        media_type: item.media_type.as_str().to_string(),
+        format: item.format.as_str().to_string(),
+        duration: info.duration,
+        width: info.width.map(|width| width as i32),
+        height: info.height.map(|height| height as i32),
+        bitrate: info.bitrate.map(|bitrate| bitrate as i64),
+        file_modified: Some(file.modified),
+        removed_at: None,
+        full_hash: hash.full.clone(),
+        created_at: now,
+        updated_at: now,
+        stack_id: None,
+        stack_version: None,
+        stack_part: None,
+        extra_type: extra.as_ref().map(|extra| extra.extra_type.as_str().to_string()),
+        extra_owner: extra.map(|extra| extra.owner.to_string_lossy().into_owned()),
     }
 }
 
@@ -138,6 +1708,44 @@ mod tests {
         assert!(!scanner.is_media_file(Path::new("test")));
     }
 
+    #[test]
+    fn test_photo_library_extensions() {
+        let scanner = MediaScanner::new().unwrap();
+        assert!(!scanner.is_media_file(Path::new("poster.jpg")));
+
+        let photos = scanner.for_library_type("photos");
+        assert!(photos.is_media_file(Path::new("IMG_0001.JPG")));
+        assert!(photos.is_media_file(Path::new("scan.webp")));
+        assert!(!photos.is_media_file(Path::new("movie.mkv")));
+        assert!(!scanner.for_library_type("movies").is_media_file(Path::new("poster.jpg")));
+    }
+
+    #[test]
+    fn test_audiobook_library_items() {
+        let scanner = MediaScanner::new().unwrap();
+        assert!(!scanner.is_media_file(Path::new("book.m4b")));
+
+        let books = scanner.for_library_type("audiobooks");
+        assert!(books.is_media_file(Path::new("book.m4b")));
+        assert!(!books.is_media_file(Path::new("movie.mkv")));
+        let file = FileInfo { path: PathBuf::from("/books/Author/Book/01.mp3"), file_size: 1, modified: Utc::now() };
+        assert_eq!(books.media_item_from_file(&file).media_type, MediaType::Audiobook);
+        assert_eq!(scanner.media_item_from_file(&file).media_type, MediaType::Music);
+    }
+
+    #[test]
+    fn test_book_library_items() {
+        let scanner = MediaScanner::new().unwrap();
+        assert!(!scanner.is_media_file(Path::new("issue.cbz")));
+
+        let books = scanner.for_library_type("books");
+        assert!(books.is_media_file(Path::new("issue.CBR")));
+        assert!(books.is_media_file(Path::new("novel.epub")));
+        assert!(!books.is_media_file(Path::new("song.mp3")));
+        let file = FileInfo { path: PathBuf::from("/books/Novel.epub"), file_size: 1, modified: Utc::now() };
+        assert_eq!(books.media_item_from_file(&file).media_type, MediaType::Book);
+    }
+
     #[tokio::test]
     async fn test_scan_directory() {
         let scanner = MediaScanner::new().unwrap();
@@ -151,4 +1759,263 @@ mod tests {
         let result = scanner.scan_directory(temp_dir.path()).await.unwrap();
         assert_eq!(result.len(), 2); // Only media files
     }
+
+    fn state(path: &str, file_size: i64, modified: DateTime<Utc>) -> MediaFileStateModel {
+        MediaFileStateModel {
+            id: Uuid::new_v4(),
+            path: path.to_string(),
+            file_size,
+            file_modified: Some(modified),
+            removed_at: None,
+        }
+    }
+
+    fn file(path: &str, file_size: u64, modified: DateTime<Utc>) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(path),
+            file_size,
+            modified,
+        }
+    }
+
+    #[test]
+    fn test_plan_scan() {
+        let then = Utc::now().trunc_subsecs(6) - chrono::Duration::hours(1);
+        let now = then + chrono::Duration::minutes(30);
+
+        let unchanged = state("/lib/unchanged.mkv", 100, then);
+        let resized = state("/lib/resized.mkv", 100, then);
+        let touched = state("/lib/touched.mkv", 100, then);
+        let missing = state("/lib/missing.mkv", 100, then);
+        let mut restored = state("/lib/restored.mkv", 100, then);
+        restored.removed_at = Some(then);
+        let mut already_removed = state("/lib/gone.mkv", 100, then);
+        already_removed.removed_at = Some(then);
+        let mut legacy = state("/lib/legacy.mkv", 100, then);
+        legacy.file_modified = None;
+
+        let ids = (resized.id, touched.id, missing.id, restored.id, legacy.id);
+        let existing = vec![unchanged, resized, touched, missing, restored, already_removed, legacy];
+        let files = vec![
+            file("/lib/unchanged.mkv", 100, then),
+            file("/lib/resized.mkv", 200, then),
+            file("/lib/touched.mkv", 100, now),
+            file("/lib/restored.mkv", 100, then),
+            file("/lib/legacy.mkv", 100, then),
+            file("/lib/new.mkv", 100, now),
+        ];
+
+        let plan = plan_scan(existing, files);
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.added.len(), 1);
+        assert_eq!(plan.added[0].path, PathBuf::from("/lib/new.mkv"));
+
+        let mut changed = plan.changed.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let mut expected = vec![ids.0, ids.1, ids.4];
+        changed.sort();
+        expected.sort();
+        assert_eq!(changed, expected);
+
+        assert_eq!(plan.restored.len(), 1);
+        assert_eq!(plan.restored[0].0, ids.3);
+        assert_eq!(plan.removed, vec![ids.2]);
+    }
+
+    fn removed_model(path: &str, file_hash: &str, full_hash: Option<&str>) -> MediaItemModel {
+        let item = MediaItem::new(PathBuf::from(path), 100);
+        let mut model = media_item_model(
+            &item,
+            &file(path, 100, Utc::now()),
+            &FileHash { fingerprint: file_hash.to_string(), full: full_hash.map(str::to_string) },
+            &MediaInfo::default(),
+        );
+        model.removed_at = Some(Utc::now());
+        model
+    }
+
+    #[test]
+    fn test_match_moved() {
+        let now = Utc::now();
+        let hash = |fingerprint: &str, full: Option<&str>| FileHash {
+            fingerprint: fingerprint.to_string(),
+            full: full.map(str::to_string),
+        };
+
+        let moved = removed_model("/old/movie.mkv", "aaa", None);
+        let other = removed_model("/old/other.mkv", "bbb", Some("full-b"));
+        let copy = removed_model("/old/copy.mkv", "aaa", None);
+        let ids = (moved.id, copy.id);
+
+        let files = vec![
+            (file("/new/movie.mkv", 100, now), hash("aaa", Some("full-a"))),
+            (file("/new/again.mkv", 100, now), hash("aaa", None)),
+            (file("/new/third.mkv", 100, now), hash("aaa", None)),
+            (file("/new/other.mkv", 100, now), hash("bbb", Some("changed"))),
+        ];
+
+        let (moved, added) = match_moved(files, vec![moved, other, copy]);
+        let moved = moved
+            .iter()
+            .map(|moved| (moved.item.id, moved.file.path.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            moved,
+            vec![
+                (ids.0, PathBuf::from("/new/movie.mkv")),
+                (ids.1, PathBuf::from("/new/again.mkv")),
+            ]
+        );
+
+        // Each removed item is reused once, and conflicting full hashes never match
+        let added = added.iter().map(|(file, _)| file.path.clone()).collect::<Vec<_>>();
+        assert_eq!(added, vec![PathBuf::from("/new/third.mkv"), PathBuf::from("/new/other.mkv")]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_files() {
+        let scanner = MediaScanner::new().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("nested")).unwrap();
+        std::fs::write(temp_dir.path().join("movie.mkv"), b"12345").unwrap();
+        std::fs::write(temp_dir.path().join("nested").join("song.mp3"), b"123").unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), b"text").unwrap();
+
+        let mut files = scanner.collect_files(temp_dir.path()).await.unwrap();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].file_size, 5);
+        assert_eq!(files[0].modified, files[0].modified.trunc_subsecs(6));
+
+        // An unchanged rescan plans no work
+        let existing = files
+            .iter()
+            .map(|f| state(&f.path.to_string_lossy(), f.file_size as i64, f.modified))
+            .collect();
+        let plan = plan_scan(existing, scanner.collect_files(temp_dir.path()).await.unwrap());
+        assert_eq!(plan.unchanged, 2);
+        assert!(plan.added.is_empty() && plan.changed.is_empty() && plan.removed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_collect_files_detects_discs() {
+        let scanner = MediaScanner::new().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let disc = temp_dir.path().join("Heat (1995)");
+        std::fs::create_dir_all(disc.join("BDMV/STREAM")).unwrap();
+        std::fs::write(disc.join("BDMV/index.bdmv"), b"INDX0200").unwrap();
+        std::fs::write(disc.join("BDMV/STREAM/00001.m2ts"), b"0123456789").unwrap();
+        std::fs::write(disc.join("BDMV/STREAM/00002.mkv"), b"0123").unwrap();
+        std::fs::write(disc.join("Heat.Trailer.mkv"), b"12345").unwrap();
+        std::fs::write(temp_dir.path().join("Alien.iso"), b"123").unwrap();
+
+        let mut files = scanner.collect_files(temp_dir.path()).await.unwrap();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let paths = files.iter().map(|f| f.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths, vec![temp_dir.path().join("Alien.iso"), disc.clone(), disc.join("Heat.Trailer.mkv")]);
+        assert_eq!(files[1].file_size, 22);
+        assert_eq!(scanner.get_file_info(&disc).await.unwrap().file_size, 22);
+
+        let item = scanner.media_item_from_file(&files[1]);
+        assert_eq!(item.format, MediaFormat::Bluray);
+        assert_eq!(item.media_type, MediaType::Movie);
+        assert_eq!(scanner.media_item_from_file(&files[0]).format, MediaFormat::Iso);
+
+        let photos = scanner.for_library_type("photos");
+        assert!(photos.collect_files(temp_dir.path()).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_disc_event() {
+        let temp_dir = TempDir::new().unwrap();
+        let disc = temp_dir.path().join("Heat");
+        std::fs::create_dir_all(disc.join("BDMV")).unwrap();
+        std::fs::write(disc.join("BDMV/index.bdmv"), b"INDX0200").unwrap();
+        let stream = disc.join("BDMV/STREAM/00001.m2ts");
+        let gone = temp_dir.path().join("Alien/VIDEO_TS/VTS_01_1.VOB");
+
+        assert_eq!(disc_event(&WatchEvent::Created(stream.clone())), Some(WatchEvent::Modified(disc.clone())));
+        assert_eq!(disc_event(&WatchEvent::Removed(gone.clone())), Some(WatchEvent::Removed(temp_dir.path().join("Alien"))));
+        assert_eq!(
+            disc_event(&WatchEvent::Renamed { from: gone, to: stream }),
+            Some(WatchEvent::Renamed { from: temp_dir.path().join("Alien"), to: disc })
+        );
+        assert_eq!(disc_event(&WatchEvent::Modified(temp_dir.path().join("movie.mkv"))), None);
+    }
+
+    #[tokio::test]
+    async fn test_collect_files_applies_ignore_rules() {
+        let patterns = vec!["@eaDir".to_string(), "sample.*".to_string()];
+        let scanner = MediaScanner::new()
+            .unwrap()
+            .with_ignore_rules(IgnoreRules::new(&patterns, 4).unwrap());
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("Movie/@eaDir")).unwrap();
+        std::fs::create_dir(root.join("Private")).unwrap();
+        std::fs::write(root.join("Movie/movie.mkv"), b"12345").unwrap();
+        std::fs::write(root.join("Movie/sample.mkv"), b"12345").unwrap();
+        std::fs::write(root.join("Movie/tiny.mkv"), b"123").unwrap();
+        std::fs::write(root.join("Movie/@eaDir/movie.mkv"), b"12345").unwrap();
+        std::fs::write(root.join("Private/home.mkv"), b"12345").unwrap();
+        std::fs::write(root.join(crate::filter::IGNORE_FILE), b"Private/\n").unwrap();
+
+        let files = scanner.collect_files(root).await.unwrap();
+        let paths = files.iter().map(|file| file.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths, vec![root.join("Movie/movie.mkv")]);
+        assert_eq!(scanner.scan_directory(root).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_collect_entries() {
+        let (_server, config) = crate::storage::s3::tests::fake_bucket(&[
+            ("movies/Movie/movie.mkv", b"12345".to_vec()),
+            ("movies/Movie/sample.mkv", b"12345".to_vec()),
+            ("movies/Movie/tiny.mkv", b"123".to_vec()),
+            ("movies/Movie/@eaDir/movie.mkv", b"12345".to_vec()),
+            ("movies/Movie/poster.jpg", b"12345".to_vec()),
+        ])
+        .await;
+        let storage = crate::storage::open("s3://media/movies", Some(&config)).unwrap();
+
+        let patterns = vec!["@eaDir".to_string(), "sample.*".to_string()];
+        let scanner = MediaScanner::new()
+            .unwrap()
+            .with_ignore_rules(IgnoreRules::new(&patterns, 4).unwrap())
+            .with_storage(storage.clone());
+
+        let files = scanner.collect_entries(storage.as_ref()).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path.to_string_lossy(), "s3://media/movies/Movie/movie.mkv");
+        assert_eq!(files[0].file_size, 5);
+
+        // Hashed through ranged reads, like a local copy would be
+        let hash = scanner.hash_file(&files[0]).await.unwrap();
+        let expected = crate::hasher::fingerprint_from(&mut std::io::Cursor::new(b"12345"), 5).unwrap();
+        assert_eq!(hash.fingerprint, expected);
+    }
+
+    #[test]
+    fn test_path_prefix() {
+        assert_eq!(path_prefix(Path::new("/media/movies")), "/media/movies/");
+        assert_eq!(path_prefix(Path::new("/media/movies/")), "/media/movies/");
+    }
+
+    #[tokio::test]
+    async fn test_create_media_item_detects_episodes() {
+        let scanner = MediaScanner::new().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let season_dir = temp_dir.path().join("Breaking Bad").join("Season 01");
+        std::fs::create_dir_all(&season_dir).unwrap();
+
+        let episode = season_dir.join("Breaking.Bad.S01E02.mkv");
+        let movie = temp_dir.path().join("The.Matrix.1999.1080p.mkv");
+        File::create(&episode).unwrap();
+        File::create(&movie).unwrap();
+
+        let item = scanner.create_media_item(&episode).await.unwrap();
+        assert_eq!(item.media_type, rustflix_core::MediaType::Episode);
+
+        let item = scanner.create_media_item(&movie).await.unwrap();
+        assert_eq!(item.media_type, rustflix_core::MediaType::Movie);
+    }
 }
@@ -0,0 +1,565 @@
+//! Sidecar file discovery
+//!
+//! Associates files stored next to a video with it: external subtitles such
+//! as `movie.en.forced.srt`, local artwork such as `poster.jpg` or
+//! `movie-fanart.jpg`, and NFO files. Folder-level names (`poster.jpg`,
+//! `movie.nfo`) only apply when the folder holds a single video.
+
+use crate::{extras, parser};
+use chrono::Utc;
+use rustflix_core::media::{MediaFormat, SubtitleTrack};
+use rustflix_core::metadata::MediaImages;
+use rustflix_database::MediaSidecarModel;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Subtitle file extensions with the codec they hold
+const SUBTITLE_EXTENSIONS: &[(&str, &str)] = &[
+    ("srt", "subrip"),
+    ("ass", "ass"),
+    ("ssa", "ssa"),
+    ("vtt", "webvtt"),
+    ("sub", "microdvd"),
+    ("idx", "dvd_subtitle"),
+    ("sup", "hdmv_pgs_subtitle"),
+    ("smi", "sami"),
+    ("ttml", "ttml"),
+];
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "tbn"];
+
+/// Languages recognized in subtitle file names:
+/// ISO 639-1, ISO 639-2/B, ISO 639-2/T and English name
+const LANGUAGES: &[(&str, &str, &str, &str)] = &[
+    ("en", "eng", "eng", "english"),
+    ("fr", "fre", "fra", "french"),
+    ("de", "ger", "deu", "german"),
+    ("es", "spa", "spa", "spanish"),
+    ("it", "ita", "ita", "italian"),
+    ("pt", "por", "por", "portuguese"),
+    ("nl", "dut", "nld", "dutch"),
+    ("sv", "swe", "swe", "swedish"),
+    ("no", "nor", "nor", "norwegian"),
+    ("da", "dan", "dan", "danish"),
+    ("fi", "fin", "fin", "finnish"),
+    ("pl", "pol", "pol", "polish"),
+    ("cs", "cze", "ces", "czech"),
+    ("hu", "hun", "hun", "hungarian"),
+    ("ro", "rum", "ron", "romanian"),
+    ("el", "gre", "ell", "greek"),
+    ("tr", "tur", "tur", "turkish"),
+    ("ru", "rus", "rus", "russian"),
+    ("uk", "ukr", "ukr", "ukrainian"),
+    ("ar", "ara", "ara", "arabic"),
+    ("he", "heb", "heb", "hebrew"),
+    ("hi", "hin", "hin", "hindi"),
+    ("ja", "jpn", "jpn", "japanese"),
+    ("ko", "kor", "kor", "korean"),
+    ("zh", "chi", "zho", "chinese"),
+    ("th", "tha", "tha", "thai"),
+    ("vi", "vie", "vie", "vietnamese"),
+    ("id", "ind", "ind", "indonesian"),
+    ("fa", "per", "fas", "persian"),
+    ("bg", "bul", "bul", "bulgarian"),
+    ("hr", "hrv", "hrv", "croatian"),
+    ("sr", "srp", "srp", "serbian"),
+    ("sk", "slo", "slk", "slovak"),
+    ("sl", "slv", "slv", "slovenian"),
+];
+
+/// Kind of local artwork
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtworkKind {
+    Poster,
+    Backdrop,
+    Fanart,
+    Logo,
+    Thumbnail,
+}
+
+/// What a sidecar file provides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SidecarKind {
+    Subtitle {
+        language: Option<String>,
+        title: Option<String>,
+        codec: String,
+        forced: bool,
+        default: bool,
+    },
+    Artwork(ArtworkKind),
+    Nfo,
+}
+
+/// File associated with a video
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sidecar {
+    pub path: PathBuf,
+    pub kind: SidecarKind,
+}
+
+impl Sidecar {
+    /// Name of the kind as stored in the database
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            SidecarKind::Subtitle { .. } => "subtitle",
+            SidecarKind::Artwork(ArtworkKind::Poster) => "poster",
+            SidecarKind::Artwork(ArtworkKind::Backdrop) => "backdrop",
+            SidecarKind::Artwork(ArtworkKind::Fanart) => "fanart",
+            SidecarKind::Artwork(ArtworkKind::Logo) => "logo",
+            SidecarKind::Artwork(ArtworkKind::Thumbnail) => "thumbnail",
+            SidecarKind::Nfo => "nfo",
+        }
+    }
+
+    /// Convert to a database model for a media item
+    pub fn to_model(&self, media_id: Uuid) -> MediaSidecarModel {
+        let (language, title, codec, forced, is_default) = match &self.kind {
+            SidecarKind::Subtitle { language, title, codec, forced, default } => {
+                (language.clone(), title.clone(), Some(codec.clone()), *forced, *default)
+            }
+            _ => (None, None, None, false, false),
+        };
+
+        MediaSidecarModel {
+            id: Uuid::new_v4(),
+            media_id,
+            path: self.path.to_string_lossy().into_owned(),
+            kind: self.kind_name().to_string(),
+            language,
+            title,
+            codec,
+            forced,
+            is_default,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Convert from a database model
+    pub fn from_model(model: &MediaSidecarModel) -> Option<Self> {
+        let kind = match model.kind.as_str() {
+            "subtitle" => SidecarKind::Subtitle {
+                language: model.language.clone(),
+                title: model.title.clone(),
+                codec: model.codec.clone().unwrap_or_default(),
+                forced: model.forced,
+                default: model.is_default,
+            },
+            "poster" => SidecarKind::Artwork(ArtworkKind::Poster),
+            "backdrop" => SidecarKind::Artwork(ArtworkKind::Backdrop),
+            "fanart" => SidecarKind::Artwork(ArtworkKind::Fanart),
+            "logo" => SidecarKind::Artwork(ArtworkKind::Logo),
+            "thumbnail" => SidecarKind::Artwork(ArtworkKind::Thumbnail),
+            "nfo" => SidecarKind::Nfo,
+            _ => return None,
+        };
+
+        Some(Self {
+            path: PathBuf::from(&model.path),
+            kind,
+        })
+    }
+}
+
+/// Check whether a file could be a sidecar, judging by its extension
+pub fn is_sidecar_file(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| {
+        subtitle_codec(&ext).is_some() || IMAGE_EXTENSIONS.contains(&ext.as_str()) || ext == "nfo"
+    })
+}
+
+/// Find the sidecar files of every video in a directory
+pub fn discover(dir: &Path) -> std::io::Result<Vec<(PathBuf, Vec<Sidecar>)>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+
+    Ok(associate(&files))
+}
+
+/// Associate the files of one directory with the videos among them
+///
+/// Every video is returned, with an empty list when it has no sidecars.
+pub fn associate(files: &[PathBuf]) -> Vec<(PathBuf, Vec<Sidecar>)> {
+    let mut videos = files.iter().filter(|file| is_video(file)).collect::<Vec<_>>();
+    videos.sort();
+    // Extras next to a movie do not make folder-level names ambiguous
+    let mut main = videos.iter().copied().filter(|video| !extras::is_extra(video));
+    let single = match (main.next(), main.next()) {
+        (Some(video), None) => Some(video),
+        _ => None,
+    };
+
+    let mut found: HashMap<&PathBuf, Vec<Sidecar>> = HashMap::new();
+    for file in files {
+        let (Some(ext), Some(stem)) = (extension(file), file_stem(file)) else {
+            continue;
+        };
+        let owner = owner(&videos, &stem);
+
+        let (video, kind) = if let Some(codec) = subtitle_codec(&ext) {
+            // VobSub pairs are listed through their index file
+            if ext == "sub" && files.contains(&file.with_extension("idx")) {
+                continue;
+            }
+            match owner.or_else(|| single.map(|video| (video, stem.as_str()))) {
+                Some((video, tags)) => (video, subtitle(tags, codec)),
+                None => continue,
+            }
+        } else if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+            let artwork = match owner {
+                Some((video, "")) if parser::parse_path(video).is_episode() => {
+                    Some((video, ArtworkKind::Thumbnail))
+                }
+                Some((video, "")) => Some((video, ArtworkKind::Poster)),
+                Some((video, suffix)) => artwork_kind(suffix).map(|kind| (video, kind)),
+                None => single.and_then(|video| artwork_kind(&stem).map(|kind| (video, kind))),
+            };
+            match artwork {
+                Some((video, kind)) => (video, SidecarKind::Artwork(kind)),
+                None => continue,
+            }
+        } else if ext == "nfo" {
+            match (owner, single) {
+                (Some((video, "")), _) => (video, SidecarKind::Nfo),
+                (None, Some(video)) if stem.eq_ignore_ascii_case("movie") => (video, SidecarKind::Nfo),
+                _ => continue,
+            }
+        } else {
+            continue;
+        };
+
+        found.entry(video).or_default().push(Sidecar {
+            path: file.clone(),
+            kind,
+        });
+    }
+
+    videos
+        .into_iter()
+        .map(|video| {
+            let mut sidecars = found.remove(video).unwrap_or_default();
+            sidecars.sort_by(|a, b| a.path.cmp(&b.path));
+            (video.clone(), sidecars)
+        })
+        .collect()
+}
+
+/// External subtitle tracks, numbered from `first_index`
+pub fn subtitle_tracks(sidecars: &[Sidecar], first_index: u32) -> Vec<SubtitleTrack> {
+    sidecars
+        .iter()
+        .filter_map(|sidecar| match &sidecar.kind {
+            SidecarKind::Subtitle { language, title, codec, forced, default } => Some(SubtitleTrack {
+                index: 0,
+                language: language.clone(),
+                title: title.clone(),
+                codec: codec.clone(),
+                forced: *forced,
+                default: *default,
+                path: Some(sidecar.path.clone()),
+            }),
+            _ => None,
+        })
+        .zip(first_index..)
+        .map(|(track, index)| SubtitleTrack { index, ..track })
+        .collect()
+}
+
+/// Local artwork as media images
+pub fn media_images(sidecars: &[Sidecar]) -> MediaImages {
+    let mut images = MediaImages::default();
+
+    for sidecar in sidecars {
+        let path = sidecar.path.to_string_lossy().into_owned();
+        match sidecar.kind {
+            SidecarKind::Artwork(ArtworkKind::Poster) if images.poster.is_none() => images.poster = Some(path),
+            SidecarKind::Artwork(ArtworkKind::Backdrop) if images.backdrop.is_none() => images.backdrop = Some(path),
+            SidecarKind::Artwork(ArtworkKind::Logo) if images.logo.is_none() => images.logo = Some(path),
+            SidecarKind::Artwork(ArtworkKind::Thumbnail) => images.thumbnails.push(path),
+            SidecarKind::Artwork(ArtworkKind::Backdrop | ArtworkKind::Fanart) => images.fanart.push(path),
+            _ => {}
+        }
+    }
+
+    images
+}
+
+/// Video whose file name the given stem extends, with the rest of the stem
+///
+/// The longest matching video name wins, so `Movie.Extended.en.srt` belongs
+/// to `Movie.Extended.mkv` rather than `Movie.mkv`.
+fn owner<'a, 'b>(videos: &[&'a PathBuf], stem: &'b str) -> Option<(&'a PathBuf, &'b str)> {
+    videos
+        .iter()
+        .filter_map(|video| {
+            let len = file_stem(video)?.len();
+            let prefix = stem.get(..len)?;
+            if !prefix.eq_ignore_ascii_case(&file_stem(video)?) {
+                return None;
+            }
+            match stem[len..].chars().next() {
+                None => Some((*video, len, "")),
+                Some('.' | '-' | '_') => Some((*video, len, &stem[len + 1..])),
+                Some(_) => None,
+            }
+        })
+        .max_by_key(|(_, len, _)| *len)
+        .map(|(video, _, rest)| (video, rest))
+}
+
+fn subtitle(tags: &str, codec: &str) -> SidecarKind {
+    let mut language = None;
+    let mut forced = false;
+    let mut default = false;
+    let mut title = Vec::new();
+
+    for tag in tags.split(['.', '-', '_', ' ']).filter(|tag| !tag.is_empty()) {
+        let lower = tag.to_lowercase();
+        match lower.as_str() {
+            "forced" | "foreign" => forced = true,
+            "default" => default = true,
+            "sdh" | "cc" => title.push("SDH".to_string()),
+            // After a language, "hi" marks hearing impaired rather than Hindi
+            "hi" if language.is_some() => title.push("SDH".to_string()),
+            _ => match language_code(&lower) {
+                Some(code) if language.is_none() => language = Some(code.to_string()),
+                _ => title.push(tag.to_string()),
+            },
+        }
+    }
+
+    SidecarKind::Subtitle {
+        language,
+        title: if title.is_empty() { None } else { Some(title.join(" ")) },
+        codec: codec.to_string(),
+        forced,
+        default,
+    }
+}
+
+fn artwork_kind(name: &str) -> Option<ArtworkKind> {
+    let name = name.to_lowercase();
+    match name.as_str() {
+        "poster" | "cover" | "folder" | "default" | "movie" => Some(ArtworkKind::Poster),
+        "fanart" | "backdrop" | "background" | "art" => Some(ArtworkKind::Backdrop),
+        "logo" | "clearlogo" => Some(ArtworkKind::Logo),
+        "thumb" | "landscape" => Some(ArtworkKind::Thumbnail),
+        _ => {
+            let numbered = ["fanart", "backdrop"].iter().any(|prefix| {
+                name.strip_prefix(prefix)
+                    .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()))
+            });
+            numbered.then_some(ArtworkKind::Fanart)
+        }
+    }
+}
+
+/// ISO 639-2/B code of a language tag
+fn language_code(tag: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(alpha2, bibliographic, terminology, name)| {
+            [*alpha2, *bibliographic, *terminology, *name].contains(&tag)
+        })
+        .map(|(_, bibliographic, _, _)| *bibliographic)
+}
+
+fn subtitle_codec(ext: &str) -> Option<&'static str> {
+    SUBTITLE_EXTENSIONS
+        .iter()
+        .find(|(extension, _)| *extension == ext)
+        .map(|(_, codec)| *codec)
+}
+
+fn is_video(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| MediaFormat::from_extension(&ext).is_video())
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase)
+}
+
+fn file_stem(path: &Path) -> Option<String> {
+    path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subtitle_kind(language: Option<&str>, title: Option<&str>, codec: &str, forced: bool) -> SidecarKind {
+        SidecarKind::Subtitle {
+            language: language.map(str::to_string),
+            title: title.map(str::to_string),
+            codec: codec.to_string(),
+            forced,
+            default: false,
+        }
+    }
+
+    fn kinds(files: &[&str]) -> Vec<(String, Vec<(String, SidecarKind)>)> {
+        let files = files.iter().map(|file| PathBuf::from(format!("/lib/{}", file))).collect::<Vec<_>>();
+        associate(&files)
+            .into_iter()
+            .map(|(video, sidecars)| {
+                let name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+                let sidecars = sidecars.into_iter().map(|sidecar| (name(&sidecar.path), sidecar.kind)).collect();
+                (name(&video), sidecars)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_subtitle_tags() {
+        let cases = [
+            ("", subtitle_kind(None, None, "subrip", false)),
+            ("en", subtitle_kind(Some("eng"), None, "subrip", false)),
+            ("eng.forced", subtitle_kind(Some("eng"), None, "subrip", true)),
+            ("French", subtitle_kind(Some("fre"), None, "subrip", false)),
+            ("de.sdh", subtitle_kind(Some("ger"), Some("SDH"), "subrip", false)),
+            ("en.hi", subtitle_kind(Some("eng"), Some("SDH"), "subrip", false)),
+            ("hi", subtitle_kind(Some("hin"), None, "subrip", false)),
+            ("en.Commentary", subtitle_kind(Some("eng"), Some("Commentary"), "subrip", false)),
+        ];
+
+        for (tags, expected) in cases {
+            assert_eq!(subtitle(tags, "subrip"), expected, "tags: {}", tags);
+        }
+    }
+
+    #[test]
+    fn test_associate_single_movie() {
+        let found = kinds(&[
+            "Movie (2020).mkv",
+            "Movie (2020).en.srt",
+            "Movie (2020).forced.ass",
+            "Movie (2020).nfo",
+            "English.srt",
+            "poster.jpg",
+            "fanart.jpg",
+            "fanart1.jpg",
+            "clearlogo.png",
+            "banner.jpg",
+            "notes.txt",
+        ]);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, "Movie (2020).mkv");
+        assert_eq!(
+            found[0].1,
+            vec![
+                ("English.srt".to_string(), subtitle_kind(Some("eng"), None, "subrip", false)),
+                ("Movie (2020).en.srt".to_string(), subtitle_kind(Some("eng"), None, "subrip", false)),
+                ("Movie (2020).forced.ass".to_string(), subtitle_kind(None, None, "ass", true)),
+                ("Movie (2020).nfo".to_string(), SidecarKind::Nfo),
+                ("clearlogo.png".to_string(), SidecarKind::Artwork(ArtworkKind::Logo)),
+                ("fanart.jpg".to_string(), SidecarKind::Artwork(ArtworkKind::Backdrop)),
+                ("fanart1.jpg".to_string(), SidecarKind::Artwork(ArtworkKind::Fanart)),
+                ("poster.jpg".to_string(), SidecarKind::Artwork(ArtworkKind::Poster)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extras_do_not_share_folder_artwork() {
+        let found = kinds(&["Movie (2020).mkv", "Movie (2020)-trailer.mkv", "poster.jpg"]);
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].0, "Movie (2020)-trailer.mkv");
+        assert!(found[0].1.is_empty());
+        assert_eq!(found[1].1, vec![("poster.jpg".to_string(), SidecarKind::Artwork(ArtworkKind::Poster))]);
+    }
+
+    #[test]
+    fn test_associate_shared_folder() {
+        let found = kinds(&[
+            "Show S01E01.mkv",
+            "Show S01E01.en.srt",
+            "Show S01E01-thumb.jpg",
+            "Show S01E02.mkv",
+            "Show S01E02.jpg",
+            "Show S01E02.idx",
+            "Show S01E02.sub",
+            "poster.jpg",
+            "English.srt",
+            "movie.nfo",
+        ]);
+
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "Show S01E01.mkv".to_string(),
+                    vec![
+                        ("Show S01E01-thumb.jpg".to_string(), SidecarKind::Artwork(ArtworkKind::Thumbnail)),
+                        ("Show S01E01.en.srt".to_string(), subtitle_kind(Some("eng"), None, "subrip", false)),
+                    ]
+                ),
+                (
+                    "Show S01E02.mkv".to_string(),
+                    vec![
+                        ("Show S01E02.idx".to_string(), subtitle_kind(None, None, "dvd_subtitle", false)),
+                        ("Show S01E02.jpg".to_string(), SidecarKind::Artwork(ArtworkKind::Thumbnail)),
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_longest_video_name_wins() {
+        let found = kinds(&["Movie.mkv", "Movie.Extended.mkv", "Movie.Extended.en.srt", "Movie.en.srt"]);
+
+        assert_eq!(found[0].0, "Movie.Extended.mkv");
+        assert_eq!(found[0].1[0].0, "Movie.Extended.en.srt");
+        assert_eq!(found[1].0, "Movie.mkv");
+        assert_eq!(found[1].1[0].0, "Movie.en.srt");
+    }
+
+    #[test]
+    fn test_tracks_and_images() {
+        let sidecar = |path: &str, kind| Sidecar { path: PathBuf::from(path), kind };
+        let sidecars = vec![
+            sidecar("/lib/movie.en.srt", subtitle_kind(Some("eng"), None, "subrip", false)),
+            sidecar("/lib/movie.forced.ass", subtitle_kind(None, None, "ass", true)),
+            sidecar("/lib/poster.jpg", SidecarKind::Artwork(ArtworkKind::Poster)),
+            sidecar("/lib/fanart.jpg", SidecarKind::Artwork(ArtworkKind::Backdrop)),
+            sidecar("/lib/backdrop.jpg", SidecarKind::Artwork(ArtworkKind::Backdrop)),
+            sidecar("/lib/movie.nfo", SidecarKind::Nfo),
+        ];
+
+        let tracks = subtitle_tracks(&sidecars, 2);
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].index, 2);
+        assert_eq!(tracks[0].language.as_deref(), Some("eng"));
+        assert_eq!(tracks[0].path, Some(PathBuf::from("/lib/movie.en.srt")));
+        assert_eq!(tracks[1].index, 3);
+        assert!(tracks[1].forced);
+
+        let images = media_images(&sidecars);
+        assert_eq!(images.poster.as_deref(), Some("/lib/poster.jpg"));
+        assert_eq!(images.backdrop.as_deref(), Some("/lib/fanart.jpg"));
+        assert_eq!(images.fanart, vec!["/lib/backdrop.jpg".to_string()]);
+    }
+
+    #[test]
+    fn test_model_round_trip() {
+        let media_id = Uuid::new_v4();
+        for kind in [
+            subtitle_kind(Some("eng"), Some("SDH"), "subrip", true),
+            SidecarKind::Artwork(ArtworkKind::Fanart),
+            SidecarKind::Nfo,
+        ] {
+            let sidecar = Sidecar { path: PathBuf::from("/lib/file"), kind };
+            let model = sidecar.to_model(media_id);
+            assert_eq!(model.media_id, media_id);
+            assert_eq!(Sidecar::from_model(&model), Some(sidecar));
+        }
+    }
+}
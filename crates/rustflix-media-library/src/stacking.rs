@@ -0,0 +1,310 @@
+//! Multi-part and multi-version stacking
+//!
+//! Movie files in one folder that share a title and year form a stack: parts
+//! marked `cd1`, `part2`, `disc b` and so on play back-to-back as one
+//! version, and differently named files (`Movie - 1080p`, `Movie - Director's
+//! Cut`) become alternate versions of the same title.
+
+use crate::{extras, parser};
+use rustflix_core::MediaFormat;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Words introducing a part number
+const PART_PREFIXES: &[&str] = &["cd", "dvd", "part", "pt", "disc", "disk"];
+
+/// Separators between the words of a file name
+const SEPARATORS: &[char] = &[' ', '.', '_', '-'];
+
+/// Title grouping the parts and versions of one movie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stack {
+    pub title: String,
+    pub year: Option<i32>,
+    /// Versions ordered by name
+    pub versions: Vec<StackVersion>,
+}
+
+/// One version of a stacked title
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackVersion {
+    pub name: String,
+    /// Files in playback order
+    pub parts: Vec<PathBuf>,
+}
+
+/// File of a stack candidate
+#[derive(Debug)]
+struct Candidate<'a> {
+    path: &'a Path,
+    part: u32,
+    /// File stem without the part marker
+    base: String,
+    extension: String,
+    parsed: parser::ParsedName,
+}
+
+/// Split a part marker off a file stem
+///
+/// Returns the part number and the stem without the marker, e.g.
+/// `Movie (2001) - cd2` gives `(2, "Movie (2001)")`. Letters `a` to `d` count
+/// as parts 1 to 4.
+pub fn split_part(stem: &str) -> Option<(u32, String)> {
+    let tokens = tokens(stem);
+
+    for index in (1..tokens.len()).rev() {
+        let (start, end) = tokens[index];
+        let lower = stem[start..end].to_lowercase();
+
+        let marker = PART_PREFIXES.iter().find_map(|prefix| {
+            let rest = lower.strip_prefix(prefix)?;
+            if rest.is_empty() {
+                // The number is the next word, as in "part 2"
+                let &(next_start, next_end) = tokens.get(index + 1)?;
+                part_number(&stem[next_start..next_end]).map(|part| (part, next_end))
+            } else {
+                part_number(rest).map(|part| (part, end))
+            }
+        });
+
+        if let Some((part, end)) = marker {
+            let base = format!("{}{}", stem[..start].trim_end_matches(SEPARATORS), &stem[end..]);
+            return Some((part, base.trim().to_string()));
+        }
+    }
+    None
+}
+
+/// Group the video files of one folder into stacks
+///
+/// Files that do not share their title with another file are left out, as
+/// are TV episodes and extras.
+pub fn stack_files(files: &[PathBuf]) -> Vec<Stack> {
+    let mut titles: BTreeMap<(String, Option<i32>), Vec<Candidate>> = BTreeMap::new();
+
+    for path in files {
+        let Some(candidate) = candidate(path) else {
+            continue;
+        };
+        let key = (candidate.parsed.title.to_lowercase(), candidate.parsed.year);
+        titles.entry(key).or_default().push(candidate);
+    }
+
+    titles.into_values().filter_map(stack).collect()
+}
+
+fn candidate(path: &Path) -> Option<Candidate<'_>> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if !MediaFormat::from_extension(&extension).is_video() || extras::is_extra(path) {
+        return None;
+    }
+
+    let stem = path.file_stem()?.to_str()?;
+    let (part, base) = split_part(stem).unwrap_or((0, stem.to_string()));
+    let parsed = parser::parse_path(&path.with_file_name(format!("{}.{}", base, extension)));
+    if parsed.is_episode() || parsed.title.is_empty() {
+        return None;
+    }
+
+    Some(Candidate { path, part, base, extension, parsed })
+}
+
+fn stack(mut candidates: Vec<Candidate<'_>>) -> Option<Stack> {
+    candidates.sort_by(|a, b| (&a.base, &a.extension, a.part).cmp(&(&b.base, &b.extension, b.part)));
+
+    let mut versions: Vec<Vec<Candidate>> = Vec::new();
+    for candidate in candidates {
+        match versions.last_mut() {
+            Some(version) if version[0].base == candidate.base && version[0].extension == candidate.extension => {
+                version.push(candidate)
+            }
+            _ => versions.push(vec![candidate]),
+        }
+    }
+
+    if versions.len() < 2 && versions.iter().all(|version| version.len() < 2) {
+        return None;
+    }
+
+    let names = version_names(&versions);
+    let title = versions[0][0].parsed.title.clone();
+    let year = versions[0][0].parsed.year;
+
+    let mut versions = versions
+        .into_iter()
+        .zip(names)
+        .map(|(parts, name)| StackVersion {
+            name,
+            parts: parts.into_iter().map(|part| part.path.to_path_buf()).collect(),
+        })
+        .collect::<Vec<_>>();
+    versions.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Some(Stack { title, year, versions })
+}
+
+/// Name each version by its edition and release tags, falling back to the
+/// file name when those do not tell the versions apart
+fn version_names(versions: &[Vec<Candidate<'_>>]) -> Vec<String> {
+    let tagged = versions
+        .iter()
+        .map(|version| {
+            let parsed = &version[0].parsed;
+            let tags = [&parsed.edition, &parsed.resolution, &parsed.source]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>();
+            if tags.is_empty() {
+                version[0].base.clone()
+            } else {
+                tags.join(" ")
+            }
+        })
+        .collect::<Vec<_>>();
+    if is_unique(&tagged) {
+        return tagged;
+    }
+
+    let bases = versions.iter().map(|version| version[0].base.clone()).collect::<Vec<_>>();
+    if is_unique(&bases) {
+        return bases;
+    }
+
+    versions
+        .iter()
+        .map(|version| format!("{}.{}", version[0].base, version[0].extension))
+        .collect()
+}
+
+fn is_unique(names: &[String]) -> bool {
+    names.iter().collect::<HashSet<_>>().len() == names.len()
+}
+
+fn part_number(text: &str) -> Option<u32> {
+    match text.to_lowercase().as_str() {
+        "a" => Some(1),
+        "b" => Some(2),
+        "c" => Some(3),
+        "d" => Some(4),
+        digits if !digits.is_empty() && digits.len() <= 2 && digits.bytes().all(|b| b.is_ascii_digit()) => {
+            digits.parse().ok().filter(|part| *part > 0)
+        }
+        _ => None,
+    }
+}
+
+/// Byte ranges of the words of a file name
+fn tokens(stem: &str) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+
+    for (index, c) in stem.char_indices() {
+        match (SEPARATORS.contains(&c), start) {
+            (true, Some(begin)) => {
+                tokens.push((begin, index));
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push((begin, stem.len()));
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(|name| PathBuf::from(format!("/movies/{}", name))).collect()
+    }
+
+    fn names(parts: &[PathBuf]) -> Vec<String> {
+        parts
+            .iter()
+            .map(|part| part.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_part() {
+        let cases = [
+            ("Movie (2001) - cd1", Some((1, "Movie (2001)"))),
+            ("Movie (2001) - CD2", Some((2, "Movie (2001)"))),
+            ("Movie.2001.part3.XviD", Some((3, "Movie.2001.XviD"))),
+            ("Movie - Part 2", Some((2, "Movie"))),
+            ("Movie_disc-b", Some((2, "Movie"))),
+            ("Movie pt1", Some((1, "Movie"))),
+            ("Movie (2001)", None),
+            ("cd1", None),
+            ("Movie - Partial", None),
+            ("Movie - part0", None),
+        ];
+
+        for (stem, expected) in cases {
+            let expected = expected.map(|(part, base): (u32, &str)| (part, base.to_string()));
+            assert_eq!(split_part(stem), expected, "parts of {}", stem);
+        }
+    }
+
+    #[test]
+    fn test_multi_part_stack() {
+        let stacks = stack_files(&paths(&[
+            "Movie (2001) - cd2.avi",
+            "Movie (2001) - cd1.avi",
+            "Other Movie (2005).mkv",
+        ]));
+
+        assert_eq!(stacks.len(), 1);
+        assert_eq!(stacks[0].title, "Movie");
+        assert_eq!(stacks[0].year, Some(2001));
+        assert_eq!(stacks[0].versions.len(), 1);
+        assert_eq!(names(&stacks[0].versions[0].parts), vec!["Movie (2001) - cd1.avi", "Movie (2001) - cd2.avi"]);
+    }
+
+    #[test]
+    fn test_multi_version_stack() {
+        let stacks = stack_files(&paths(&[
+            "Movie (2001) - 2160p.mkv",
+            "Movie (2001) - 1080p.mkv",
+            "Movie (2001) - Director's Cut - cd1.mkv",
+            "Movie (2001) - Director's Cut - cd2.mkv",
+        ]));
+
+        assert_eq!(stacks.len(), 1);
+        let versions = &stacks[0].versions;
+        let version_names = versions.iter().map(|version| version.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(version_names, vec!["1080p", "2160p", "Director's Cut"]);
+        assert_eq!(versions[2].parts.len(), 2);
+    }
+
+    #[test]
+    fn test_versions_named_by_file_when_tags_match() {
+        let stacks = stack_files(&paths(&["Movie (2001) - Remastered.mkv", "Movie (2001).mkv", "Movie (2001).mp4"]));
+
+        let version_names = stacks[0].versions.iter().map(|version| version.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            version_names,
+            vec!["Movie (2001) - Remastered.mkv", "Movie (2001).mkv", "Movie (2001).mp4"]
+        );
+    }
+
+    #[test]
+    fn test_unrelated_files_are_not_stacked() {
+        let stacks = stack_files(&paths(&[
+            "Harry Potter and the Deathly Hallows Part 1 (2010).mkv",
+            "Harry Potter and the Deathly Hallows Part 2 (2011).mkv",
+            "Show S01E01.mkv",
+            "Show S01E02.mkv",
+            "Movie (2001).mkv",
+            "Movie (2001).srt",
+            "Movie (2001)-trailer.mkv",
+        ]));
+
+        assert!(stacks.is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Libraries in a folder on the local file system
+
+use super::{LibraryStorage, StorageEntry};
+use crate::filter::IgnoreRules;
+use crate::watcher::FileWatcher;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use rustflix_core::{Result, RustFlixError};
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::warn;
+use walkdir::WalkDir;
+
+/// Library stored in a local folder
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+    location: String,
+}
+
+impl LocalStorage {
+    /// Create a storage for the folder at `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            location: root.to_string_lossy().into_owned(),
+            root,
+        }
+    }
+
+    /// Path of a file from its key, refusing keys leaving the root
+    fn resolve(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(RustFlixError::validation("key", &format!("Invalid storage key: {}", key)));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    fn entry(&self, path: &Path, metadata: &std::fs::Metadata) -> Option<StorageEntry> {
+        let key = self.key(path)?;
+        Some(StorageEntry {
+            key,
+            size: metadata.len(),
+            modified: metadata.modified().map(DateTime::<Utc>::from).ok()?,
+            etag: None,
+        })
+    }
+}
+
+#[async_trait]
+impl LibraryStorage for LocalStorage {
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+
+    async fn list(&self, folder: &str) -> Result<Vec<StorageEntry>> {
+        let start = if folder.is_empty() { self.root.clone() } else { self.resolve(folder)? };
+        let storage = self.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut entries = Vec::new();
+            for entry in WalkDir::new(&start).follow_links(false) {
+                let entry = match entry {
+                    Ok(entry) if entry.file_type().is_file() => entry,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        warn!("Error listing entry: {}", e);
+                        continue;
+                    }
+                };
+                match entry.metadata() {
+                    Ok(metadata) => entries.extend(storage.entry(entry.path(), &metadata)),
+                    Err(e) => warn!("Failed to read metadata of {}: {}", entry.path().display(), e),
+                }
+            }
+            entries
+        })
+        .await
+        .map_err(|e| RustFlixError::internal(format!("Directory walk failed: {}", e)))
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<StorageEntry>> {
+        let path = self.resolve(key)?;
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => Ok(self.entry(&path, &metadata)),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(RustFlixError::Io(e)),
+        }
+    }
+
+    async fn read_range(&self, key: &str, range: Range<u64>) -> Result<Bytes> {
+        let mut file = tokio::fs::File::open(self.resolve(key)?).await?;
+        file.seek(SeekFrom::Start(range.start)).await?;
+
+        let mut data = Vec::new();
+        file.take(range.end.saturating_sub(range.start)).read_to_end(&mut data).await?;
+        Ok(Bytes::from(data))
+    }
+
+    fn watch(&self, ignore: IgnoreRules) -> Result<Option<FileWatcher>> {
+        let mut watcher = FileWatcher::new()?.with_ignore_rules(ignore);
+        watcher.watch_directory(&self.root)?;
+        Ok(Some(watcher))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_local_storage() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("Heat (1995)")).unwrap();
+        std::fs::write(dir.path().join("Heat (1995)/Heat.mkv"), b"0123456789").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"notes").unwrap();
+
+        let storage = LocalStorage::new(dir.path());
+        let mut keys = storage.list("").await.unwrap().into_iter().map(|entry| entry.key).collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["Heat (1995)/Heat.mkv", "notes.txt"]);
+        assert_eq!(storage.list("Heat (1995)").await.unwrap().len(), 1);
+
+        let entry = storage.stat("Heat (1995)/Heat.mkv").await.unwrap().unwrap();
+        assert_eq!(entry.size, 10);
+        assert!(storage.stat("missing.mkv").await.unwrap().is_none());
+        assert!(storage.stat("Heat (1995)").await.unwrap().is_none());
+
+        assert_eq!(&storage.read_range("Heat (1995)/Heat.mkv", 2..5).await.unwrap()[..], b"234");
+        assert_eq!(&storage.read_range("Heat (1995)/Heat.mkv", 8..20).await.unwrap()[..], b"89");
+        assert!(storage.read_range("../outside.mkv", 0..1).await.is_err());
+    }
+}
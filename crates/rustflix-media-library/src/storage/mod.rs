@@ -0,0 +1,378 @@
+//! Storage backends holding library files
+//!
+//! A library path is either a local folder or an `s3://bucket/prefix` URL
+//! pointing at S3-compatible object storage. Files are addressed by keys
+//! relative to the library root, using `/` separators on every backend.
+//! Local folders are watched for changes as they happen; object storage is
+//! polled by comparing listings.
+
+pub mod local;
+pub mod s3;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+use crate::filter::IgnoreRules;
+use crate::watcher::FileWatcher;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use rustflix_core::config::ObjectStorageConfig;
+use rustflix_core::{Result, RustFlixError};
+use rustflix_database::MediaRepository;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+
+/// Bytes fetched at once by a `StorageReader` (256 KiB)
+const READ_AHEAD: u64 = 256 * 1024;
+
+/// File found in a storage backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageEntry {
+    /// Path relative to the library root, with `/` separators
+    pub key: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    /// Entity tag, on backends that have them
+    pub etag: Option<String>,
+}
+
+/// Change found by polling a storage backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageChange {
+    Created(String),
+    Modified(String),
+    Removed(String),
+}
+
+/// Files of a library as last listed, by key
+pub type StorageSnapshot = HashMap<String, StorageEntry>;
+
+/// Backend holding the files of a library
+#[async_trait]
+pub trait LibraryStorage: Send + Sync + std::fmt::Debug {
+    /// Library root, a folder path or `s3://bucket/prefix` URL
+    fn location(&self) -> &str;
+
+    /// Folder holding the files, for backends on the local file system
+    fn local_root(&self) -> Option<&Path> {
+        None
+    }
+
+    /// List every file below a folder, `""` being the library root
+    async fn list(&self, folder: &str) -> Result<Vec<StorageEntry>>;
+
+    /// Look up a single file, `None` when it does not exist
+    async fn stat(&self, key: &str) -> Result<Option<StorageEntry>>;
+
+    /// Read a range of bytes of a file
+    ///
+    /// Fewer bytes than asked for are returned when the range runs past the
+    /// end of the file.
+    async fn read_range(&self, key: &str, range: Range<u64>) -> Result<Bytes>;
+
+    /// Watch the library for changes as they happen
+    ///
+    /// Backends without change notifications return `None` and are polled.
+    /// Watching a folder lists the files below it, so this may block.
+    fn watch(&self, _ignore: IgnoreRules) -> Result<Option<FileWatcher>> {
+        Ok(None)
+    }
+
+    /// List the library and compare it with the previous listing
+    ///
+    /// The snapshot is replaced by the new listing.
+    async fn poll(&self, snapshot: &mut StorageSnapshot) -> Result<Vec<StorageChange>> {
+        let entries = self.list("").await?;
+        Ok(diff_snapshot(snapshot, entries))
+    }
+
+    /// Path under which a file is stored in the media repository
+    fn path(&self, key: &str) -> PathBuf {
+        Path::new(self.location()).join(key)
+    }
+
+    /// Key of a file from its repository path, `None` outside the library
+    fn key(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(self.location()).ok()?;
+        let parts = relative
+            .components()
+            .map(|component| match component {
+                Component::Normal(part) => part.to_str(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(parts.join("/")).filter(|key| !key.is_empty())
+    }
+}
+
+/// Check whether a library path points at object storage
+pub fn is_remote(location: &str) -> bool {
+    location.starts_with("s3://")
+}
+
+/// Open the storage of a library from its path
+pub fn open(location: &str, object_storage: Option<&ObjectStorageConfig>) -> Result<Arc<dyn LibraryStorage>> {
+    if !is_remote(location) {
+        return Ok(Arc::new(LocalStorage::new(location)));
+    }
+
+    let config = object_storage.ok_or_else(|| {
+        RustFlixError::config(format!("Library {} needs object storage to be configured", location))
+    })?;
+    Ok(Arc::new(S3Storage::from_location(location, config)?))
+}
+
+/// Opens the storage of the library holding a file of the media repository
+#[derive(Debug, Clone)]
+pub struct LibraryStorages {
+    repository: MediaRepository,
+    /// Object storage of bucket libraries, when configured
+    object_storage: Option<ObjectStorageConfig>,
+}
+
+impl LibraryStorages {
+    pub fn new(repository: MediaRepository, object_storage: Option<ObjectStorageConfig>) -> Self {
+        Self { repository, object_storage }
+    }
+
+    /// Storage and key of a file from its repository path, `None` when no
+    /// library holds it
+    pub async fn locate(&self, path: &str) -> Result<Option<(Arc<dyn LibraryStorage>, String)>> {
+        let libraries = self.repository.list_libraries().await?;
+        let Some(library) = crate::audit::library_of(&libraries, path)
+            .and_then(|id| libraries.into_iter().find(|library| library.id == id))
+        else {
+            return Ok(None);
+        };
+
+        let storage = open(&library.path, self.object_storage.as_ref())?;
+        Ok(storage.key(Path::new(path)).map(|key| (storage, key)))
+    }
+}
+
+/// Replace a snapshot by a new listing, returning what changed
+///
+/// Files are modified when their entity tag changed or, without tags, when
+/// their size or modification time changed.
+pub fn diff_snapshot(snapshot: &mut StorageSnapshot, entries: Vec<StorageEntry>) -> Vec<StorageChange> {
+    let current = entries
+        .into_iter()
+        .map(|entry| (entry.key.clone(), entry))
+        .collect::<StorageSnapshot>();
+
+    let keys = snapshot.keys().chain(current.keys()).collect::<BTreeSet<_>>();
+    let changes = keys
+        .into_iter()
+        .filter_map(|key| match (snapshot.get(key), current.get(key)) {
+            (None, Some(_)) => Some(StorageChange::Created(key.clone())),
+            (Some(_), None) => Some(StorageChange::Removed(key.clone())),
+            (Some(old), Some(new)) if is_modified(old, new) => Some(StorageChange::Modified(key.clone())),
+            _ => None,
+        })
+        .collect();
+
+    *snapshot = current;
+    changes
+}
+
+fn is_modified(old: &StorageEntry, new: &StorageEntry) -> bool {
+    match (&old.etag, &new.etag) {
+        (Some(old_tag), Some(new_tag)) => old_tag != new_tag,
+        _ => old.size != new.size || old.modified != new.modified,
+    }
+}
+
+/// Blocking `Read + Seek` view of a stored file, made of ranged reads
+///
+/// Small reads are served from a block fetched ahead of the position, so
+/// parsers reading one field at a time do not cost a request each. Must be
+/// used from a blocking context, such as `tokio::task::spawn_blocking`.
+#[derive(Debug)]
+pub struct StorageReader {
+    storage: Arc<dyn LibraryStorage>,
+    key: String,
+    size: u64,
+    position: u64,
+    block_start: u64,
+    block: Bytes,
+    runtime: Handle,
+}
+
+impl StorageReader {
+    /// Create a reader of a file; must be called within a Tokio runtime
+    pub fn new(storage: Arc<dyn LibraryStorage>, entry: &StorageEntry) -> Result<Self> {
+        let runtime = Handle::try_current()
+            .map_err(|e| RustFlixError::internal(format!("No runtime for storage reads: {}", e)))?;
+
+        Ok(Self {
+            storage,
+            key: entry.key.clone(),
+            size: entry.size,
+            position: 0,
+            block_start: 0,
+            block: Bytes::new(),
+            runtime,
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn fetch(&self, range: Range<u64>) -> io::Result<Bytes> {
+        self.runtime
+            .block_on(self.storage.read_range(&self.key, range))
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+impl Read for StorageReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.size {
+            return Ok(0);
+        }
+
+        let block_end = self.block_start + self.block.len() as u64;
+        if !(self.block_start..block_end).contains(&self.position) {
+            let wanted = (buf.len() as u64).max(READ_AHEAD);
+            let range = self.position..self.size.min(self.position + wanted);
+            self.block = self.fetch(range)?;
+            self.block_start = self.position;
+            if self.block.is_empty() {
+                return Ok(0);
+            }
+        }
+
+        let offset = (self.position - self.block_start) as usize;
+        let count = buf.len().min(self.block.len() - offset);
+        buf[..count].copy_from_slice(&self.block[offset..offset + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for StorageReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the file"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(key: &str, size: u64, etag: Option<&str>) -> StorageEntry {
+        StorageEntry {
+            key: key.to_string(),
+            size,
+            modified: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            etag: etag.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_diff_snapshot() {
+        let mut snapshot = StorageSnapshot::new();
+        let changes = diff_snapshot(&mut snapshot, vec![entry("a.mkv", 1, Some("1")), entry("b.mkv", 2, None)]);
+        assert_eq!(
+            changes,
+            vec![StorageChange::Created("a.mkv".to_string()), StorageChange::Created("b.mkv".to_string())]
+        );
+
+        let changes = diff_snapshot(
+            &mut snapshot,
+            vec![entry("a.mkv", 1, Some("2")), entry("b.mkv", 2, None), entry("c.mkv", 3, None)],
+        );
+        assert_eq!(
+            changes,
+            vec![StorageChange::Modified("a.mkv".to_string()), StorageChange::Created("c.mkv".to_string())]
+        );
+
+        let changes = diff_snapshot(&mut snapshot, vec![entry("a.mkv", 1, Some("2")), entry("c.mkv", 4, None)]);
+        assert_eq!(
+            changes,
+            vec![StorageChange::Removed("b.mkv".to_string()), StorageChange::Modified("c.mkv".to_string())]
+        );
+        assert_eq!(snapshot.len(), 2);
+    }
+
+    #[test]
+    fn test_paths_and_keys() {
+        let storage = LocalStorage::new("/media/movies");
+        let path = storage.path("Heat (1995)/Heat.mkv");
+        assert_eq!(path, PathBuf::from("/media/movies/Heat (1995)/Heat.mkv"));
+        assert_eq!(storage.key(&path).as_deref(), Some("Heat (1995)/Heat.mkv"));
+        assert_eq!(storage.key(Path::new("/media/shows/a.mkv")), None);
+        assert_eq!(storage.key(Path::new("/media/movies")), None);
+    }
+
+    #[test]
+    fn test_open() {
+        let config = ObjectStorageConfig {
+            endpoint: "http://127.0.0.1:9000".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: "minio".to_string(),
+            secret_access_key: "minio123".to_string(),
+            path_style: true,
+            poll_interval: 300,
+        };
+
+        let local = open("/media/movies", None).unwrap();
+        assert_eq!(local.local_root(), Some(Path::new("/media/movies")));
+
+        let remote = open("s3://media/movies/", Some(&config)).unwrap();
+        assert_eq!(remote.location(), "s3://media/movies");
+        assert!(remote.local_root().is_none());
+        let path = remote.path("Heat.mkv");
+        assert_eq!(path.to_string_lossy(), "s3://media/movies/Heat.mkv");
+        assert_eq!(remote.key(&path).as_deref(), Some("Heat.mkv"));
+
+        assert!(open("s3://media", None).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_storage_reader() {
+        let dir = TempDir::new().unwrap();
+        let data = (0..READ_AHEAD as usize * 2 + 10).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(dir.path().join("file.bin"), &data).unwrap();
+
+        let storage: Arc<dyn LibraryStorage> = Arc::new(LocalStorage::new(dir.path()));
+        let entry = storage.stat("file.bin").await.unwrap().unwrap();
+
+        let read = tokio::task::spawn_blocking(move || {
+            let mut reader = StorageReader::new(storage, &entry).unwrap();
+            let mut head = [0u8; 4];
+            reader.read_exact(&mut head).unwrap();
+
+            reader.seek(SeekFrom::End(-4)).unwrap();
+            let mut tail = Vec::new();
+            reader.read_to_end(&mut tail).unwrap();
+
+            reader.seek(SeekFrom::Start(0)).unwrap();
+            let mut all = Vec::new();
+            reader.read_to_end(&mut all).unwrap();
+
+            assert!(reader.seek(SeekFrom::Current(-(all.len() as i64) - 1)).is_err());
+            (head, tail, all)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(read.0, [0, 1, 2, 3]);
+        assert_eq!(read.1, data[data.len() - 4..]);
+        assert_eq!(read.2, data);
+    }
+}
@@ -0,0 +1,458 @@
+//! ID3v2 tag parsing, with ID3v1 as a fallback
+
+use super::{genre_name, parse_flag, parse_pair, parse_year, CoverArt, MusicTags};
+use crate::probe::{truncated, ByteReader, MAX_HEADER_SIZE};
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Picture type of a front cover
+const FRONT_COVER: u8 = 3;
+
+/// Owner of the UFID frame holding the MusicBrainz recording id
+const MUSICBRAINZ_UFID: &[u8] = b"http://musicbrainz.org";
+
+/// Read the ID3v2 tag at the start of a file, or the ID3v1 tag at its end
+pub(crate) fn read<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<MusicTags> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 10];
+    if file_size >= 10 {
+        reader.read_exact(&mut header)?;
+    }
+
+    if &header[..3] == b"ID3" {
+        let size = syncsafe(&header[6..10]) as u64;
+        if size > MAX_HEADER_SIZE || 10 + size > file_size {
+            return Err(RustFlixError::media_processing("ID3v2 tag is too large"));
+        }
+        let mut tag = vec![0u8; size as usize];
+        reader.read_exact(&mut tag)?;
+        return parse_v2(header[3], header[5], tag);
+    }
+
+    if file_size >= 128 {
+        let mut tag = [0u8; 128];
+        reader.seek(SeekFrom::Start(file_size - 128))?;
+        reader.read_exact(&mut tag)?;
+        if &tag[..3] == b"TAG" {
+            return Ok(parse_v1(&tag));
+        }
+    }
+    Ok(MusicTags::default())
+}
+
+/// Parse the body of an ID3v2 tag
+pub(crate) fn parse_v2(version: u8, flags: u8, mut tag: Vec<u8>) -> Result<MusicTags> {
+    if !(2..=4).contains(&version) {
+        return Err(RustFlixError::media_processing(format!("Unsupported ID3v2.{} tag", version)));
+    }
+    // Before v2.4 unsynchronisation applies to the whole tag
+    if flags & 0x80 != 0 && version < 4 {
+        tag = unsynchronise(&tag);
+    }
+
+    let mut reader = ByteReader::new(&tag);
+    if flags & 0x40 != 0 && version > 2 {
+        let size = reader.u32()?;
+        match version {
+            3 => reader.skip(size as usize)?,
+            _ => reader.skip((syncsafe(&size.to_be_bytes()) as usize).saturating_sub(4))?,
+        }
+    }
+
+    let mut tags = MusicTags::default();
+    let mut cover: Option<(u8, CoverArt)> = None;
+
+    while let Some((id, frame_flags, data)) = next_frame(&mut reader, version)? {
+        let Some(data) = frame_data(version, frame_flags, data) else {
+            continue;
+        };
+        let id = normalize_id(&id);
+
+        match id.as_str() {
+            "TIT2" => tags.title = text(&data),
+            "TPE1" => tags.artist = text(&data),
+            "TPE2" => tags.album_artist = text(&data),
+            "TALB" => tags.album = text(&data),
+            "TSOP" => tags.artist_sort = text(&data),
+            "TSO2" => tags.album_artist_sort = text(&data),
+            "TRCK" => {
+                (tags.track_number, tags.track_total) = text(&data).map(|value| parse_pair(&value)).unwrap_or_default()
+            }
+            "TPOS" => {
+                (tags.disc_number, tags.disc_total) = text(&data).map(|value| parse_pair(&value)).unwrap_or_default()
+            }
+            "TYER" | "TDRC" => tags.year = text(&data).and_then(|value| parse_year(&value)).or(tags.year),
+            "TCON" => tags.genre = text(&data).map(|value| genre(&value)),
+            "MVNM" => tags.series = text(&data),
+            "MVIN" => tags.series_part = text(&data).and_then(|value| parse_pair(&value).0).map(|part| part.to_string()),
+            "TCMP" => tags.compilation = text(&data).is_some_and(|value| parse_flag(&value)),
+            "TXXX" => {
+                if let Some((name, value)) = user_text(&data) {
+                    tags.set_named(&name, &value);
+                }
+            }
+            "UFID" => {
+                if let Some(id) = data.strip_prefix(MUSICBRAINZ_UFID).and_then(|rest| rest.strip_prefix(&[0])) {
+                    tags.musicbrainz.recording = Some(String::from_utf8_lossy(id).into_owned());
+                }
+            }
+            "APIC" | "PIC" => {
+                if let Some((kind, art)) = picture(&data, id == "PIC") {
+                    if cover.as_ref().is_none_or(|(current, _)| *current != FRONT_COVER && kind == FRONT_COVER) {
+                        cover = Some((kind, art));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tags.cover = cover.map(|(_, art)| art);
+    Ok(tags)
+}
+
+/// Read the next frame header and payload; `None` at the end or the padding
+fn next_frame<'a>(reader: &mut ByteReader<'a>, version: u8) -> Result<Option<(String, u16, &'a [u8])>> {
+    let header_len = if version == 2 { 6 } else { 10 };
+    if reader.remaining() < header_len || reader.rest()[0] == 0 {
+        return Ok(None);
+    }
+
+    let (id, size, flags) = if version == 2 {
+        let id = reader.bytes(3)?;
+        (id, reader.u24()? as usize, 0)
+    } else {
+        let id = reader.bytes(4)?;
+        let size = reader.u32()?;
+        let size = if version == 4 { syncsafe(&size.to_be_bytes()) } else { size };
+        (id, size as usize, reader.u16()?)
+    };
+    if !id.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+        return Ok(None);
+    }
+    if size > reader.remaining() {
+        return Err(truncated());
+    }
+
+    Ok(Some((String::from_utf8_lossy(id).into_owned(), flags, reader.bytes(size)?)))
+}
+
+/// Undo the per-frame encodings; `None` for compressed or encrypted frames
+fn frame_data(version: u8, flags: u16, data: &[u8]) -> Option<Vec<u8>> {
+    match version {
+        3 => {
+            if flags & 0x00c0 != 0 {
+                return None;
+            }
+            let skip = if flags & 0x0020 != 0 { 1 } else { 0 };
+            data.get(skip..).map(<[u8]>::to_vec)
+        }
+        4 => {
+            if flags & 0x000c != 0 {
+                return None;
+            }
+            let skip = if flags & 0x0040 != 0 { 1 } else { 0 } + if flags & 0x0001 != 0 { 4 } else { 0 };
+            let data = data.get(skip..)?;
+            Some(if flags & 0x0002 != 0 { unsynchronise(data) } else { data.to_vec() })
+        }
+        _ => Some(data.to_vec()),
+    }
+}
+
+/// Map ID3v2.2 frame ids to their ID3v2.3 names
+fn normalize_id(id: &str) -> String {
+    let renamed = match id {
+        "TT2" => "TIT2",
+        "TP1" => "TPE1",
+        "TP2" => "TPE2",
+        "TAL" => "TALB",
+        "TRK" => "TRCK",
+        "TPA" => "TPOS",
+        "TYE" => "TYER",
+        "TCO" => "TCON",
+        "TCP" => "TCMP",
+        "TXX" => "TXXX",
+        "UFI" => "UFID",
+        "TS2" => "TSO2",
+        "TSP" => "TSOP",
+        other => other,
+    };
+    renamed.to_string()
+}
+
+/// Value of a text frame; several values are joined with "; "
+fn text(data: &[u8]) -> Option<String> {
+    let (&encoding, body) = data.split_first()?;
+    let values = split_strings(encoding, body)
+        .into_iter()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+    (!values.is_empty()).then(|| values.join("; "))
+}
+
+/// Description and value of a TXXX frame
+fn user_text(data: &[u8]) -> Option<(String, String)> {
+    let (&encoding, body) = data.split_first()?;
+    let mut strings = split_strings(encoding, body).into_iter();
+    let name = strings.next()?;
+    let value = strings.collect::<Vec<_>>().join("; ");
+    Some((name, value))
+}
+
+/// Picture type and contents of an APIC (or ID3v2.2 PIC) frame
+fn picture(data: &[u8], legacy: bool) -> Option<(u8, CoverArt)> {
+    let (&encoding, rest) = data.split_first()?;
+    let (mime_type, rest) = if legacy {
+        let format = std::str::from_utf8(rest.get(..3)?).ok()?.to_ascii_lowercase();
+        let mime_type = match format.as_str() {
+            "jpg" => "image/jpeg".to_string(),
+            other => format!("image/{}", other),
+        };
+        (mime_type, &rest[3..])
+    } else {
+        let end = rest.iter().position(|b| *b == 0)?;
+        let mime_type = String::from_utf8_lossy(&rest[..end]).to_ascii_lowercase();
+        (mime_type, &rest[end + 1..])
+    };
+    let (&kind, rest) = rest.split_first()?;
+    let (_, data) = split_terminated(encoding, rest);
+
+    let mime_type = match mime_type.as_str() {
+        "" | "image/jpg" => "image/jpeg".to_string(),
+        _ => mime_type,
+    };
+    (!data.is_empty()).then(|| (kind, CoverArt { mime_type, data: data.to_vec() }))
+}
+
+/// Resolve ID3v1 genre references such as "(17)" or "17"
+fn genre(value: &str) -> String {
+    let reference = value
+        .strip_prefix('(')
+        .and_then(|rest| rest.split_once(')'))
+        .map(|(index, rest)| (index, rest.trim()));
+
+    match reference {
+        // "(17)Rock" already carries the name
+        Some((_, name)) if !name.is_empty() => name.to_string(),
+        Some((index, _)) => index.parse().ok().and_then(genre_name).unwrap_or_else(|| value.to_string()),
+        None => value.parse().ok().and_then(genre_name).unwrap_or_else(|| value.to_string()),
+    }
+}
+
+/// Split NUL-separated strings in the given text encoding
+fn split_strings(encoding: u8, mut data: &[u8]) -> Vec<String> {
+    let mut strings = Vec::new();
+    while !data.is_empty() {
+        let (value, rest) = split_terminated(encoding, data);
+        strings.push(decode(encoding, value));
+        data = rest;
+    }
+    strings
+}
+
+/// Split off one string terminated by the NUL of its encoding
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    match encoding {
+        1 | 2 => {
+            let end = data.chunks_exact(2).position(|pair| pair == [0, 0]).map(|index| index * 2);
+            match end {
+                Some(end) => (&data[..end], &data[end + 2..]),
+                None => (data, &[]),
+            }
+        }
+        _ => match data.iter().position(|b| *b == 0) {
+            Some(end) => (&data[..end], &data[end + 1..]),
+            None => (data, &[]),
+        },
+    }
+}
+
+/// Decode a string: 0 is ISO-8859-1, 1 UTF-16 with BOM, 2 UTF-16BE, 3 UTF-8
+fn decode(encoding: u8, data: &[u8]) -> String {
+    match encoding {
+        1 | 2 => {
+            let (big_endian, data) = match data {
+                [0xfe, 0xff, rest @ ..] => (true, rest),
+                [0xff, 0xfe, rest @ ..] => (false, rest),
+                _ => (encoding == 2, data),
+            };
+            let units = data
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(data).into_owned(),
+        _ => data.iter().map(|b| *b as char).collect(),
+    }
+}
+
+/// Remove the zero bytes inserted after 0xFF by unsynchronisation
+fn unsynchronise(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut previous = 0;
+    for &byte in data {
+        if !(previous == 0xff && byte == 0) {
+            output.push(byte);
+        }
+        previous = byte;
+    }
+    output
+}
+
+/// Decode a 28-bit integer stored in 4 bytes of 7 bits
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, byte| (value << 7) | (*byte as u32 & 0x7f))
+}
+
+/// Parse a 128-byte ID3v1 tag
+fn parse_v1(tag: &[u8]) -> MusicTags {
+    let field = |range: std::ops::Range<usize>| {
+        let value = decode(0, &tag[range]);
+        let value = value.trim_end_matches('\0').trim();
+        (!value.is_empty()).then(|| value.to_string())
+    };
+
+    MusicTags {
+        title: field(3..33),
+        artist: field(33..63),
+        album: field(63..93),
+        year: field(93..97).and_then(|year| parse_year(&year)),
+        // ID3v1.1 keeps the track number in the last byte of the comment
+        track_number: (tag[125] == 0 && tag[126] != 0).then_some(tag[126] as u32),
+        genre: genre_name(tag[127] as usize),
+        ..MusicTags::default()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn frame(id: &str, payload: &[u8]) -> Vec<u8> {
+        let mut frame = id.as_bytes().to_vec();
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn text_frame(id: &str, value: &str) -> Vec<u8> {
+        let mut payload = vec![3];
+        payload.extend_from_slice(value.as_bytes());
+        frame(id, &payload)
+    }
+
+    /// Build an ID3v2.3 tag followed by some audio bytes
+    pub(crate) fn sample_file(frames: &[Vec<u8>]) -> Vec<u8> {
+        let body = frames.concat();
+        let size = body.len() as u32 + 16;
+        let mut file = b"ID3\x03\x00\x00".to_vec();
+        file.extend([(size >> 21) as u8 & 0x7f, (size >> 14) as u8 & 0x7f, (size >> 7) as u8 & 0x7f, size as u8 & 0x7f]);
+        file.extend(body);
+        file.extend([0u8; 16]);
+        file.extend([0xff, 0xfb, 0x90, 0x00]);
+        file
+    }
+
+    #[test]
+    fn test_parse_id3v23() {
+        let mut utf16 = vec![1, 0xff, 0xfe];
+        utf16.extend("Ünïcode".encode_utf16().flat_map(u16::to_le_bytes));
+        let mut picture = vec![0];
+        picture.extend_from_slice(b"image/jpeg\0");
+        picture.push(FRONT_COVER);
+        picture.extend_from_slice(b"cover\0");
+        picture.extend_from_slice(&[0xff, 0xd8, 0xff]);
+        let mut musicbrainz = vec![3];
+        musicbrainz.extend_from_slice(b"MusicBrainz Album Id\0release-id");
+        let mut ufid = MUSICBRAINZ_UFID.to_vec();
+        ufid.extend_from_slice(b"\0recording-id");
+
+        let data = sample_file(&[
+            frame("TIT2", &utf16),
+            text_frame("TPE1", "Artist"),
+            text_frame("TPE2", "Various Artists"),
+            text_frame("TALB", "Album"),
+            text_frame("TRCK", "4/10"),
+            text_frame("TPOS", "1/2"),
+            text_frame("TYER", "2001"),
+            text_frame("TCON", "(17)"),
+            text_frame("TCMP", "1"),
+            frame("TXXX", &musicbrainz),
+            frame("UFID", &ufid),
+            frame("APIC", &picture),
+        ]);
+
+        let tags = read(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Ünïcode"));
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.album_artist.as_deref(), Some("Various Artists"));
+        assert_eq!(tags.album.as_deref(), Some("Album"));
+        assert_eq!((tags.track_number, tags.track_total), (Some(4), Some(10)));
+        assert_eq!((tags.disc_number, tags.disc_total), (Some(1), Some(2)));
+        assert_eq!(tags.year, Some(2001));
+        assert_eq!(tags.genre.as_deref(), Some("Rock"));
+        assert!(tags.compilation);
+        assert_eq!(tags.musicbrainz.release.as_deref(), Some("release-id"));
+        assert_eq!(tags.musicbrainz.recording.as_deref(), Some("recording-id"));
+        let cover = tags.cover.unwrap();
+        assert_eq!(cover.mime_type, "image/jpeg");
+        assert_eq!(cover.data, vec![0xff, 0xd8, 0xff]);
+    }
+
+    #[test]
+    fn test_parse_id3v24_frames() {
+        // v2.4 sizes are syncsafe and text frames may hold several values
+        let mut payload = vec![3];
+        payload.extend_from_slice(b"One\0Two");
+        let mut frame = b"TPE1".to_vec();
+        frame.extend([0, 0, 0, payload.len() as u8, 0, 0]);
+        frame.extend(payload);
+        frame.extend(text_frame("TDRC", "1999-05-01"));
+
+        let tags = parse_v2(4, 0, frame).unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("One; Two"));
+        assert_eq!(tags.year, Some(1999));
+    }
+
+    #[test]
+    fn test_parse_id3v1() {
+        let mut data = vec![0u8; 256];
+        let tag = &mut data[128..];
+        tag[..3].copy_from_slice(b"TAG");
+        tag[3..7].copy_from_slice(b"Song");
+        tag[33..39].copy_from_slice(b"Artist");
+        tag[93..97].copy_from_slice(b"1987");
+        tag[126] = 5;
+        tag[127] = 13;
+
+        let tags = read(&mut Cursor::new(&data), data.len() as u64).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.year, Some(1987));
+        assert_eq!(tags.track_number, Some(5));
+        assert_eq!(tags.genre.as_deref(), Some("Pop"));
+    }
+
+    #[test]
+    fn test_genre_references() {
+        assert_eq!(genre("(17)"), "Rock");
+        assert_eq!(genre("17"), "Rock");
+        assert_eq!(genre("(17)Indie Rock"), "Indie Rock");
+        assert_eq!(genre("Shoegaze"), "Shoegaze");
+    }
+
+    #[test]
+    fn test_unsynchronise() {
+        assert_eq!(unsynchronise(&[0xff, 0x00, 0xe0, 0x00, 0xff]), vec![0xff, 0xe0, 0x00, 0xff]);
+    }
+}
@@ -0,0 +1,257 @@
+//! Embedded music tag reading
+//!
+//! Reads ID3v2 (MP3), Vorbis comments (FLAC and Ogg) and iTunes-style MP4
+//! atoms (M4A and M4B) into one set of fields. Like `crate::probe`, the parsers work
+//! on the file directly and only read the tag structures.
+//! Reading is blocking, so call these from a blocking thread.
+
+pub(crate) mod id3;
+pub(crate) mod mp4;
+pub(crate) mod vorbis;
+
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+/// Tags read from a music file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MusicTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub artist_sort: Option<String>,
+    pub album_artist: Option<String>,
+    pub album_artist_sort: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    /// Narrator of an audiobook
+    pub narrator: Option<String>,
+    /// Series an audiobook belongs to
+    pub series: Option<String>,
+    /// Position of an audiobook in its series, e.g. "3" or "2.5"
+    pub series_part: Option<String>,
+    /// Whether the file is marked as part of a various-artists compilation
+    pub compilation: bool,
+    /// MusicBrainz release type, e.g. "album", "single" or "soundtrack"
+    pub release_type: Option<String>,
+    pub musicbrainz: MusicBrainzIds,
+    pub cover: Option<CoverArt>,
+}
+
+/// MusicBrainz identifiers written by taggers such as Picard
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MusicBrainzIds {
+    pub recording: Option<String>,
+    pub release_track: Option<String>,
+    pub release: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+}
+
+/// Embedded picture
+#[derive(Clone, PartialEq, Eq)]
+pub struct CoverArt {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl std::fmt::Debug for CoverArt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoverArt")
+            .field("mime_type", &self.mime_type)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+impl MusicTags {
+    /// Check whether no tag was found
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Set a field from a tag named the way Vorbis comments and MP4
+    /// freeform atoms name it; unknown names are ignored
+    pub(crate) fn set_named(&mut self, name: &str, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            return;
+        }
+        let text = || Some(value.to_string());
+
+        match name.to_ascii_uppercase().replace([' ', '_'], "").as_str() {
+            "TITLE" => append(&mut self.title, value),
+            "ARTIST" => append(&mut self.artist, value),
+            "ARTISTSORT" => self.artist_sort = text(),
+            "ALBUMARTIST" => append(&mut self.album_artist, value),
+            "ALBUMARTISTSORT" => self.album_artist_sort = text(),
+            "ALBUM" => self.album = text(),
+            "TRACKNUMBER" | "TRACK" => {
+                let (number, total) = parse_pair(value);
+                self.track_number = number.or(self.track_number);
+                self.track_total = total.or(self.track_total);
+            }
+            "TRACKTOTAL" | "TOTALTRACKS" => self.track_total = parse_number(value),
+            "DISCNUMBER" | "DISC" => {
+                let (number, total) = parse_pair(value);
+                self.disc_number = number.or(self.disc_number);
+                self.disc_total = total.or(self.disc_total);
+            }
+            "DISCTOTAL" | "TOTALDISCS" => self.disc_total = parse_number(value),
+            "DATE" | "YEAR" => self.year = parse_year(value).or(self.year),
+            "GENRE" => append(&mut self.genre, value),
+            "NARRATOR" | "NARRATEDBY" => append(&mut self.narrator, value),
+            "SERIES" => self.series = text(),
+            "SERIESPART" | "SERIES-PART" | "SERIESPOSITION" => self.series_part = text(),
+            "COMPILATION" => self.compilation = parse_flag(value),
+            "RELEASETYPE" | "MUSICBRAINZALBUMTYPE" => self.release_type = Some(value.to_lowercase()),
+            "MUSICBRAINZTRACKID" => self.musicbrainz.recording = text(),
+            "MUSICBRAINZRELEASETRACKID" => self.musicbrainz.release_track = text(),
+            "MUSICBRAINZALBUMID" => self.musicbrainz.release = text(),
+            "MUSICBRAINZARTISTID" => self.musicbrainz.artist = first_id(value),
+            "MUSICBRAINZALBUMARTISTID" => self.musicbrainz.album_artist = first_id(value),
+            _ => {}
+        }
+    }
+}
+
+/// Check whether a format has an embedded tag reader
+pub fn supports_format(format: MediaFormat) -> bool {
+    matches!(format, MediaFormat::Mp3 | MediaFormat::Flac | MediaFormat::Ogg | MediaFormat::M4a | MediaFormat::M4b)
+}
+
+/// Read the embedded tags of a music file
+pub fn read_file(path: &Path, format: MediaFormat) -> Result<MusicTags> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    read_from(&mut BufReader::new(file), file_size, format)
+}
+
+/// Read the embedded tags of a music file read through `reader`
+pub fn read_from<R: Read + Seek>(reader: &mut R, file_size: u64, format: MediaFormat) -> Result<MusicTags> {
+    match format {
+        MediaFormat::Mp3 => id3::read(reader, file_size),
+        MediaFormat::Flac => vorbis::read_flac(reader),
+        MediaFormat::Ogg => vorbis::read_ogg(reader),
+        MediaFormat::M4a | MediaFormat::M4b => mp4::read(reader, file_size),
+        _ => Err(RustFlixError::media_processing(format!("No tag reader for {:?}", format))),
+    }
+}
+
+/// Add a value to a field that may be given more than once, such as the
+/// artists of a collaboration
+fn append(field: &mut Option<String>, value: &str) {
+    match field {
+        Some(existing) if !existing.split("; ").any(|part| part == value) => {
+            existing.push_str("; ");
+            existing.push_str(value);
+        }
+        Some(_) => {}
+        None => *field = Some(value.to_string()),
+    }
+}
+
+/// Parse a number such as "3" or "03"
+pub(crate) fn parse_number(value: &str) -> Option<u32> {
+    value.trim().parse().ok().filter(|number| *number > 0)
+}
+
+/// Parse a position such as "3/12" into the number and the total
+pub(crate) fn parse_pair(value: &str) -> (Option<u32>, Option<u32>) {
+    match value.split_once('/') {
+        Some((number, total)) => (parse_number(number), parse_number(total)),
+        None => (parse_number(value), None),
+    }
+}
+
+/// Parse the year of a date such as "1999", "1999-05-01" or "1999-05-01T00:00:00Z"
+pub(crate) fn parse_year(value: &str) -> Option<i32> {
+    let value = value.trim();
+    let digits = value.get(..4)?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if value.len() > 4 && value.as_bytes()[4].is_ascii_digit() {
+        return None;
+    }
+    digits.parse().ok().filter(|year| *year > 0)
+}
+
+pub(crate) fn parse_flag(value: &str) -> bool {
+    matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes")
+}
+
+/// First of several MusicBrainz ids separated the way taggers join them
+fn first_id(value: &str) -> Option<String> {
+    value
+        .split(['/', ';'])
+        .map(str::trim)
+        .find(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+/// Genre names of ID3v1 and of the MP4 `gnre` atom, by index
+pub(crate) const GENRES: &[&str] = &[
+    "Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop", "Jazz", "Metal",
+    "New Age", "Oldies", "Other", "Pop", "R&B", "Rap", "Reggae", "Rock", "Techno", "Industrial",
+    "Alternative", "Ska", "Death Metal", "Pranks", "Soundtrack", "Euro-Techno", "Ambient", "Trip-Hop", "Vocal",
+    "Jazz+Funk", "Fusion", "Trance", "Classical", "Instrumental", "Acid", "House", "Game", "Sound Clip",
+    "Gospel", "Noise", "AlternRock", "Bass", "Soul", "Punk", "Space", "Meditative", "Instrumental Pop",
+    "Instrumental Rock", "Ethnic", "Gothic", "Darkwave", "Techno-Industrial", "Electronic", "Pop-Folk",
+    "Eurodance", "Dream", "Southern Rock", "Comedy", "Cult", "Gangsta", "Top 40", "Christian Rap", "Pop/Funk",
+    "Jungle", "Native American", "Cabaret", "New Wave", "Psychadelic", "Rave", "Showtunes", "Trailer", "Lo-Fi",
+    "Tribal", "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock",
+];
+
+/// Genre name of an ID3v1 genre index
+pub(crate) fn genre_name(index: usize) -> Option<String> {
+    GENRES.get(index).map(|genre| genre.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_named() {
+        let mut tags = MusicTags::default();
+        tags.set_named("title", "Song");
+        tags.set_named("ARTIST", "First");
+        tags.set_named("ARTIST", "Second");
+        tags.set_named("Album Artist", "Band");
+        tags.set_named("TRACKNUMBER", "03/12");
+        tags.set_named("DISCNUMBER", "2");
+        tags.set_named("DISCTOTAL", "2");
+        tags.set_named("DATE", "1999-05-01");
+        tags.set_named("COMPILATION", "1");
+        tags.set_named("MUSICBRAINZ_ALBUMID", "0b1ff3c4-0000-4000-8000-000000000001");
+        tags.set_named("MusicBrainz Artist Id", "a/b");
+        tags.set_named("COMMENT", "ignored");
+
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.artist.as_deref(), Some("First; Second"));
+        assert_eq!(tags.album_artist.as_deref(), Some("Band"));
+        assert_eq!((tags.track_number, tags.track_total), (Some(3), Some(12)));
+        assert_eq!((tags.disc_number, tags.disc_total), (Some(2), Some(2)));
+        assert_eq!(tags.year, Some(1999));
+        assert!(tags.compilation);
+        assert_eq!(tags.musicbrainz.release.as_deref(), Some("0b1ff3c4-0000-4000-8000-000000000001"));
+        assert_eq!(tags.musicbrainz.artist.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(parse_pair("3/12"), (Some(3), Some(12)));
+        assert_eq!(parse_pair("0"), (None, None));
+        assert_eq!(parse_year("2004"), Some(2004));
+        assert_eq!(parse_year("2004-01-02T00:00:00Z"), Some(2004));
+        assert_eq!(parse_year("20041"), None);
+        assert_eq!(parse_year("n/a"), None);
+        assert_eq!(genre_name(17).as_deref(), Some("Rock"));
+    }
+}
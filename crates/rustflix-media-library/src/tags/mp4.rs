@@ -0,0 +1,213 @@
+//! iTunes-style metadata atoms of MP4/M4A files
+
+use super::{genre_name, CoverArt, MusicTags};
+use crate::probe::mp4::{children, find, find_path, read_moov};
+use crate::probe::ByteReader;
+use rustflix_core::Result;
+use std::io::{Read, Seek};
+
+/// Data type of a UTF-8 value
+const TYPE_UTF8: u32 = 1;
+const TYPE_JPEG: u32 = 13;
+const TYPE_PNG: u32 = 14;
+const TYPE_BMP: u32 = 27;
+
+/// Read the `ilst` atoms of an MP4 file
+pub(crate) fn read<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<MusicTags> {
+    let moov = read_moov(reader, file_size)?;
+    parse_moov(&moov)
+}
+
+/// Parse the metadata below `moov/udta/meta/ilst`
+pub(crate) fn parse_moov(moov: &[u8]) -> Result<MusicTags> {
+    let mut tags = MusicTags::default();
+    let Some(meta) = find_path(moov, &[b"udta", b"meta"])? else {
+        return Ok(tags);
+    };
+    // `meta` is a full box in MP4 files but a plain one in QuickTime files
+    let ilst = match meta.get(4..).map(|rest| find(rest, b"ilst")) {
+        Some(Ok(Some(ilst))) => ilst,
+        _ => match find(meta, b"ilst")? {
+            Some(ilst) => ilst,
+            None => return Ok(tags),
+        },
+    };
+
+    for item in children(ilst)? {
+        let values = data_values(item.data)?;
+        let Some(&(kind, value)) = values.first() else {
+            continue;
+        };
+        let text = || (kind == TYPE_UTF8).then(|| String::from_utf8_lossy(value).trim().to_string());
+
+        match &item.kind {
+            b"\xa9nam" => tags.title = text(),
+            b"\xa9ART" => tags.artist = text(),
+            b"aART" => tags.album_artist = text(),
+            b"\xa9alb" => tags.album = text(),
+            b"soar" => tags.artist_sort = text(),
+            b"soaa" => tags.album_artist_sort = text(),
+            b"\xa9day" => tags.year = text().and_then(|value| super::parse_year(&value)),
+            b"\xa9gen" => tags.genre = text(),
+            b"\xa9nrt" => tags.narrator = text(),
+            // Audiobook taggers store the series as the movement name and
+            // number
+            b"\xa9mvn" => tags.series = text(),
+            b"\xa9mvi" => tags.series_part = integer(value).filter(|part| *part > 0).map(|part| part.to_string()),
+            b"gnre" if value.len() >= 2 => {
+                let index = u16::from_be_bytes([value[0], value[1]]) as usize;
+                tags.genre = index.checked_sub(1).and_then(genre_name);
+            }
+            b"trkn" => (tags.track_number, tags.track_total) = position(value),
+            b"disk" => (tags.disc_number, tags.disc_total) = position(value),
+            b"cpil" => tags.compilation = value.first().is_some_and(|flag| *flag != 0),
+            b"covr" => {
+                tags.cover = values.iter().find_map(|&(kind, data)| {
+                    let mime_type = match kind {
+                        TYPE_JPEG => "image/jpeg",
+                        TYPE_PNG => "image/png",
+                        TYPE_BMP => "image/bmp",
+                        _ => return None,
+                    };
+                    Some(CoverArt { mime_type: mime_type.to_string(), data: data.to_vec() })
+                });
+            }
+            b"----" => {
+                if let (Some(name), Some(value)) = (freeform_name(item.data)?, text()) {
+                    tags.set_named(&name, &value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(tags)
+}
+
+/// Type and payload of every `data` atom of an item
+fn data_values(item: &[u8]) -> Result<Vec<(u32, &[u8])>> {
+    let mut values = Vec::new();
+    for data in children(item)?.into_iter().filter(|child| &child.kind == b"data") {
+        let mut reader = ByteReader::new(data.data);
+        let kind = reader.u32()? & 0x00ff_ffff;
+        // Locale
+        reader.skip(4)?;
+        values.push((kind, reader.rest()));
+    }
+    Ok(values)
+}
+
+/// Name of a `----` atom, e.g. "MusicBrainz Album Id"
+fn freeform_name(item: &[u8]) -> Result<Option<String>> {
+    Ok(children(item)?
+        .into_iter()
+        .find(|child| &child.kind == b"name")
+        .and_then(|name| name.data.get(4..))
+        .map(|name| String::from_utf8_lossy(name).into_owned()))
+}
+
+/// Big-endian integer value of up to eight bytes
+fn integer(value: &[u8]) -> Option<u64> {
+    (!value.is_empty() && value.len() <= 8).then(|| value.iter().fold(0, |number, b| number << 8 | *b as u64))
+}
+
+/// Number and total of a `trkn` or `disk` value
+fn position(value: &[u8]) -> (Option<u32>, Option<u32>) {
+    let number = |offset: usize| {
+        value
+            .get(offset..offset + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+            .filter(|number| *number > 0)
+    };
+    (number(2), number(4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut atom = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(kind);
+        atom.extend_from_slice(payload);
+        atom
+    }
+
+    fn data(kind: u32, value: &[u8]) -> Vec<u8> {
+        let mut payload = kind.to_be_bytes().to_vec();
+        payload.extend_from_slice(&[0, 0, 0, 0]);
+        payload.extend_from_slice(value);
+        atom(b"data", &payload)
+    }
+
+    fn text_item(kind: &[u8; 4], value: &str) -> Vec<u8> {
+        atom(kind, &data(TYPE_UTF8, value.as_bytes()))
+    }
+
+    fn freeform(name: &str, value: &str) -> Vec<u8> {
+        let mut payload = atom(b"mean", b"\0\0\0\0com.apple.iTunes");
+        payload.extend(atom(b"name", &[b"\0\0\0\0".as_slice(), name.as_bytes()].concat()));
+        payload.extend(data(TYPE_UTF8, value.as_bytes()));
+        atom(b"----", &payload)
+    }
+
+    #[test]
+    fn test_parse_ilst() {
+        let ilst = [
+            text_item(b"\xa9nam", "Song"),
+            text_item(b"\xa9ART", "Artist"),
+            text_item(b"aART", "Band"),
+            text_item(b"\xa9alb", "Album"),
+            text_item(b"\xa9day", "2010-03-01T08:00:00Z"),
+            atom(b"gnre", &data(0, &[0, 18])),
+            atom(b"trkn", &data(0, &[0, 0, 0, 3, 0, 11, 0, 0])),
+            atom(b"disk", &data(0, &[0, 0, 0, 2, 0, 2])),
+            atom(b"cpil", &data(21, &[1])),
+            atom(b"covr", &data(TYPE_PNG, b"\x89PNG")),
+            freeform("MusicBrainz Album Id", "release-id"),
+            freeform("MusicBrainz Album Artist Id", "artist-id"),
+        ]
+        .concat();
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend(atom(b"hdlr", &[0u8; 25]));
+        meta.extend(atom(b"ilst", &ilst));
+        let moov = atom(b"udta", &atom(b"meta", &meta));
+
+        let tags = parse_moov(&moov).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.album_artist.as_deref(), Some("Band"));
+        assert_eq!(tags.album.as_deref(), Some("Album"));
+        assert_eq!(tags.year, Some(2010));
+        assert_eq!(tags.genre.as_deref(), Some("Rock"));
+        assert_eq!((tags.track_number, tags.track_total), (Some(3), Some(11)));
+        assert_eq!((tags.disc_number, tags.disc_total), (Some(2), Some(2)));
+        assert!(tags.compilation);
+        assert_eq!(tags.cover.unwrap().mime_type, "image/png");
+        assert_eq!(tags.musicbrainz.release.as_deref(), Some("release-id"));
+        assert_eq!(tags.musicbrainz.album_artist.as_deref(), Some("artist-id"));
+    }
+
+    #[test]
+    fn test_parse_audiobook_atoms() {
+        let ilst = [
+            text_item(b"\xa9alb", "Book"),
+            text_item(b"\xa9nrt", "Reader"),
+            text_item(b"\xa9mvn", "Saga"),
+            atom(b"\xa9mvi", &data(21, &[0, 3])),
+        ]
+        .concat();
+        let moov = atom(b"udta", &atom(b"meta", &[vec![0, 0, 0, 0], atom(b"ilst", &ilst)].concat()));
+
+        let tags = parse_moov(&moov).unwrap();
+        assert_eq!(tags.album.as_deref(), Some("Book"));
+        assert_eq!(tags.narrator.as_deref(), Some("Reader"));
+        assert_eq!(tags.series.as_deref(), Some("Saga"));
+        assert_eq!(tags.series_part.as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_no_metadata() {
+        assert!(parse_moov(&atom(b"mvhd", &[0u8; 100])).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+//! Vorbis comment parsing for FLAC and Ogg (Vorbis and Opus) files
+
+use super::{CoverArt, MusicTags};
+use crate::probe::{truncated, ByteReader, MAX_HEADER_SIZE};
+use rustflix_core::{Result, RustFlixError};
+use std::io::{Read, Seek, SeekFrom};
+
+const FLAC_VORBIS_COMMENT: u8 = 4;
+const FLAC_PICTURE: u8 = 6;
+
+/// Picture type of a front cover
+const FRONT_COVER: u32 = 3;
+
+/// Read the comment and picture blocks of a FLAC file
+pub(crate) fn read_flac<R: Read + Seek>(reader: &mut R) -> Result<MusicTags> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    // Some files carry an ID3v2 tag before the FLAC stream
+    if &magic[..3] == b"ID3" {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header)?;
+        let size = header[2..].iter().fold(0u64, |value, byte| (value << 7) | (*byte as u64 & 0x7f));
+        reader.seek(SeekFrom::Start(10 + size))?;
+        reader.read_exact(&mut magic)?;
+    }
+    if &magic != b"fLaC" {
+        return Err(RustFlixError::media_processing("Not a FLAC stream"));
+    }
+
+    let mut tags = MusicTags::default();
+    let mut cover: Option<(u32, CoverArt)> = None;
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+
+        if kind == FLAC_VORBIS_COMMENT || kind == FLAC_PICTURE {
+            let mut block = vec![0u8; size as usize];
+            reader.read_exact(&mut block)?;
+            if kind == FLAC_VORBIS_COMMENT {
+                let picture = parse_comments(&block, &mut tags)?;
+                cover = better_cover(cover, picture);
+            } else {
+                cover = better_cover(cover, parse_picture(&block).ok());
+            }
+        } else {
+            reader.seek(SeekFrom::Current(size as i64))?;
+        }
+        if last {
+            break;
+        }
+    }
+
+    tags.cover = cover.map(|(_, art)| art);
+    Ok(tags)
+}
+
+/// Read the comment header of the first logical stream of an Ogg file
+pub(crate) fn read_ogg<R: Read + Seek>(reader: &mut R) -> Result<MusicTags> {
+    reader.seek(SeekFrom::Start(0))?;
+    let packet = second_packet(reader)?;
+
+    let comments = if let Some(rest) = packet.strip_prefix(b"\x03vorbis") {
+        rest
+    } else if let Some(rest) = packet.strip_prefix(b"OpusTags") {
+        rest
+    } else {
+        return Err(RustFlixError::media_processing("No Vorbis comment header in Ogg stream"));
+    };
+
+    let mut tags = MusicTags::default();
+    tags.cover = parse_comments(comments, &mut tags)?.map(|(_, art)| art);
+    Ok(tags)
+}
+
+/// Assemble the second packet of the first logical stream, which holds the
+/// comments for both Vorbis and Opus
+fn second_packet<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut serial = None;
+    let mut packets = 0;
+    let mut packet = Vec::new();
+
+    loop {
+        let mut header = [0u8; 27];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"OggS" {
+            return Err(RustFlixError::media_processing("Invalid Ogg page"));
+        }
+        let page_serial = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+        let mut lacing = vec![0u8; header[26] as usize];
+        reader.read_exact(&mut lacing)?;
+        let mut body = vec![0u8; lacing.iter().map(|len| *len as usize).sum()];
+        reader.read_exact(&mut body)?;
+
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+
+        let mut offset = 0;
+        for len in lacing {
+            let len = len as usize;
+            if packets == 1 {
+                packet.extend_from_slice(&body[offset..offset + len]);
+                if packet.len() as u64 > MAX_HEADER_SIZE {
+                    return Err(RustFlixError::media_processing("Ogg comment header is too large"));
+                }
+            }
+            offset += len;
+            // A segment shorter than 255 bytes ends the packet
+            if len < 255 {
+                if packets == 1 {
+                    return Ok(packet);
+                }
+                packets += 1;
+            }
+        }
+    }
+}
+
+/// Parse a Vorbis comment structure into `tags`, returning the best
+/// embedded picture
+pub(crate) fn parse_comments(data: &[u8], tags: &mut MusicTags) -> Result<Option<(u32, CoverArt)>> {
+    let mut reader = ByteReader::new(data);
+    let vendor_len = le_u32(&mut reader)? as usize;
+    reader.skip(vendor_len)?;
+    let count = le_u32(&mut reader)?;
+
+    let mut cover = None;
+    for _ in 0..count {
+        let len = le_u32(&mut reader)? as usize;
+        let comment = String::from_utf8_lossy(reader.bytes(len)?);
+        let Some((name, value)) = comment.split_once('=') else {
+            continue;
+        };
+
+        if name.eq_ignore_ascii_case("METADATA_BLOCK_PICTURE") {
+            let picture = base64_decode(value).and_then(|block| parse_picture(&block).ok());
+            cover = better_cover(cover, picture);
+        } else {
+            tags.set_named(name, value);
+        }
+    }
+    Ok(cover)
+}
+
+/// Parse a FLAC picture block into its picture type and contents
+fn parse_picture(data: &[u8]) -> Result<(u32, CoverArt)> {
+    let mut reader = ByteReader::new(data);
+    let kind = reader.u32()?;
+    let mime_len = reader.u32()? as usize;
+    let mime_type = String::from_utf8_lossy(reader.bytes(mime_len)?).to_ascii_lowercase();
+    let description_len = reader.u32()? as usize;
+    reader.skip(description_len)?;
+    // Width, height, colour depth and palette size
+    reader.skip(16)?;
+    let len = reader.u32()? as usize;
+    let data = reader.bytes(len)?.to_vec();
+
+    if data.is_empty() {
+        return Err(truncated());
+    }
+    Ok((kind, CoverArt { mime_type, data }))
+}
+
+/// Keep the first picture unless a later one is the front cover
+fn better_cover(current: Option<(u32, CoverArt)>, candidate: Option<(u32, CoverArt)>) -> Option<(u32, CoverArt)> {
+    match (current, candidate) {
+        (Some(current), Some(candidate)) if current.0 != FRONT_COVER && candidate.0 == FRONT_COVER => Some(candidate),
+        (Some(current), _) => Some(current),
+        (None, candidate) => candidate,
+    }
+}
+
+fn le_u32(reader: &mut ByteReader<'_>) -> Result<u32> {
+    let b = reader.bytes(4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Decode standard base64, ignoring padding and whitespace
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' | b'\r' | b'\n' | b' ' => continue,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    pub(crate) fn comments(entries: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&6u32.to_le_bytes());
+        data.extend_from_slice(b"vendor");
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for entry in entries {
+            data.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            data.extend_from_slice(entry.as_bytes());
+        }
+        data
+    }
+
+    fn picture_block(kind: u32, data: &[u8]) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.extend_from_slice(&kind.to_be_bytes());
+        block.extend_from_slice(&9u32.to_be_bytes());
+        block.extend_from_slice(b"image/png");
+        block.extend_from_slice(&0u32.to_be_bytes());
+        block.extend_from_slice(&[0u8; 16]);
+        block.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        block.extend_from_slice(data);
+        block
+    }
+
+    fn flac_block(kind: u8, last: bool, data: &[u8]) -> Vec<u8> {
+        let mut block = vec![kind | if last { 0x80 } else { 0 }];
+        block.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        block.extend_from_slice(data);
+        block
+    }
+
+    /// Build a FLAC file with a stream info block, comments and a picture
+    pub(crate) fn sample_flac(entries: &[&str]) -> Vec<u8> {
+        let mut file = b"fLaC".to_vec();
+        // 44.1 kHz, two channels, 16 bits and 180 seconds of samples
+        let mut stream_info = [0u8; 34];
+        let packed = (44100u64 << 44) | (1 << 41) | (15 << 36) | (180 * 44100);
+        stream_info[10..18].copy_from_slice(&packed.to_be_bytes());
+        file.extend(flac_block(0, false, &stream_info));
+        file.extend(flac_block(FLAC_VORBIS_COMMENT, false, &comments(entries)));
+        file.extend(flac_block(FLAC_PICTURE, true, &picture_block(FRONT_COVER, &[0x89, b'P', b'N', b'G'])));
+        file.extend([0xff, 0xf8]);
+        file
+    }
+
+    fn ogg_page(serial: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut lacing = Vec::new();
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255u8, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend_from_slice(&[0u8; 8]);
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&[0u8; 8]);
+        page.push(lacing.len() as u8);
+        page.extend(lacing);
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+        page
+    }
+
+    #[test]
+    fn test_read_flac() {
+        let data = sample_flac(&["TITLE=Song", "ALBUMARTIST=Band", "TRACKNUMBER=2", "TRACKTOTAL=9"]);
+
+        let tags = read_flac(&mut Cursor::new(data)).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.album_artist.as_deref(), Some("Band"));
+        assert_eq!((tags.track_number, tags.track_total), (Some(2), Some(9)));
+        let cover = tags.cover.unwrap();
+        assert_eq!(cover.mime_type, "image/png");
+        assert_eq!(cover.data, b"\x89PNG");
+    }
+
+    #[test]
+    fn test_read_ogg_opus() {
+        let mut tags_packet = b"OpusTags".to_vec();
+        // A long comment spans several lacing segments
+        let long_album = format!("ALBUM={}", "A".repeat(600));
+        tags_packet.extend(comments(&["ARTIST=Someone", &long_album]));
+
+        let mut data = ogg_page(7, &[b"OpusHead\x01\x02"]);
+        data.extend(ogg_page(9, &[b"other stream"]));
+        data.extend(ogg_page(7, &[&tags_packet]));
+
+        let tags = read_ogg(&mut Cursor::new(data)).unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Someone"));
+        assert_eq!(tags.album.map(|album| album.len()), Some(600));
+    }
+
+    #[test]
+    fn test_picture_comment() {
+        let encoded = "AAAAAwAAAAlpbWFnZS9wbmcAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAARhYmNk";
+        let mut tags = MusicTags::default();
+        let cover = parse_comments(&comments(&[&format!("METADATA_BLOCK_PICTURE={}", encoded)]), &mut tags)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cover.0, FRONT_COVER);
+        assert_eq!(cover.1.data, b"abcd");
+    }
+
+    #[test]
+    fn test_base64_decode() {
+        assert_eq!(base64_decode("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64_decode("aGk=\n").unwrap(), b"hi");
+        assert!(base64_decode("a*b").is_none());
+    }
+}
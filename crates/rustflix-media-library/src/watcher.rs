@@ -1,39 +1,59 @@
 //! File system watching functionality
+//!
+//! Raw notify events are debounced per path before they are handed out:
+//! bursts of events for one file collapse into a single event, rename halves
+//! are paired into `Renamed`, new and modified files are only reported once
+//! their size has stopped changing, and directory events are expanded into
+//! events for the files below them. Paths excluded by the ignore rules never
+//! produce events. When the platform drops events, a `Rescan` is handed out
+//! so the library can be scanned in full.
 
+use crate::filter::IgnoreRules;
 use rustflix_core::{Result, RustFlixError};
+use notify::event::{ModifyKind, RenameMode};
 use notify::{Watcher, RecursiveMode, Event, EventKind};
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tracing::{info, warn, debug};
+use walkdir::WalkDir;
+
+/// Quiet period before events for a path are emitted
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(1);
 
 /// File system watcher for monitoring library changes
 #[derive(Debug)]
 pub struct FileWatcher {
     _watcher: notify::RecommendedWatcher,
-    receiver: mpsc::UnboundedReceiver<WatchEvent>,
+    receiver: mpsc::UnboundedReceiver<Event>,
+    debouncer: Debouncer,
+    ready: VecDeque<WatchEvent>,
+    roots: Vec<PathBuf>,
+    ignore: IgnoreRules,
 }
 
 /// Watch event types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum WatchEvent {
     Created(PathBuf),
     Modified(PathBuf),
     Removed(PathBuf),
     Renamed { from: PathBuf, to: PathBuf },
+    /// Events were dropped, so the watched directories need a full scan
+    Rescan,
 }
 
 impl FileWatcher {
     /// Create a new file watcher
     pub fn new() -> Result<Self> {
         let (sender, receiver) = mpsc::unbounded_channel();
-        
+
         let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
             match res {
                 Ok(event) => {
-                    if let Some(watch_event) = Self::convert_event(event) {
-                        if let Err(e) = sender.send(watch_event) {
-                            warn!("Failed to send watch event: {}", e);
-                        }
+                    if let Err(e) = sender.send(event) {
+                        warn!("Failed to send watch event: {}", e);
                     }
                 }
                 Err(e) => warn!("Watch error: {}", e),
@@ -43,72 +63,518 @@ impl FileWatcher {
         Ok(Self {
             _watcher: watcher,
             receiver,
+            debouncer: Debouncer::new(DEFAULT_DEBOUNCE),
+            ready: VecDeque::new(),
+            roots: Vec::new(),
+            ignore: IgnoreRules::default(),
         })
     }
 
+    /// Set the rules excluding paths and small files from events
+    pub fn with_ignore_rules(mut self, rules: IgnoreRules) -> Self {
+        self.ignore = rules;
+        self
+    }
+
+    /// Set the quiet period a path needs before its events are emitted
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debouncer.debounce = debounce;
+        self
+    }
+
     /// Watch a directory for changes
+    ///
+    /// The files below the directory are listed, so this blocks; call it
+    /// from a blocking context.
     pub fn watch_directory(&mut self, path: &Path) -> Result<()> {
         info!("Watching directory: {}", path.display());
-        
+
         self._watcher.watch(path, RecursiveMode::Recursive)
             .map_err(|e| RustFlixError::internal(format!("Failed to watch directory: {}", e)))?;
-        
+
+        // Existing files are needed to expand directory removals and renames
+        self.debouncer.seed(path);
+        self.roots.push(path.to_path_buf());
+
         Ok(())
     }
 
     /// Stop watching a directory
     pub fn unwatch_directory(&mut self, path: &Path) -> Result<()> {
         info!("Stopped watching directory: {}", path.display());
-        
+
         self._watcher.unwatch(path)
             .map_err(|e| RustFlixError::internal(format!("Failed to unwatch directory: {}", e)))?;
-        
+
+        self.debouncer.forget(path);
+        self.roots.retain(|root| root != path);
+
         Ok(())
     }
 
     /// Receive the next watch event
     pub async fn next_event(&mut self) -> Option<WatchEvent> {
-        self.receiver.recv().await
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Some(event);
+            }
+
+            let due = self.debouncer.next_due();
+            let deadline = tokio::time::Instant::from_std(
+                due.unwrap_or_else(|| Instant::now() + self.debouncer.debounce),
+            );
+
+            tokio::select! {
+                event = self.receiver.recv() => match event {
+                    Some(event) => {
+                        self.debouncer.push(event, Instant::now());
+                        if self.debouncer.take_rescan() && !self.ready.contains(&WatchEvent::Rescan) {
+                            self.ready.push_back(WatchEvent::Rescan);
+                        }
+                    }
+                    None => {
+                        let events = self.debouncer.flush();
+                        self.release(events);
+                        if self.ready.is_empty() {
+                            return None;
+                        }
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline), if due.is_some() => {
+                    let events = self.debouncer.poll(Instant::now());
+                    self.release(events);
+                }
+            }
+        }
+    }
+
+    /// Queue debounced events that pass the ignore rules
+    fn release(&mut self, events: Vec<WatchEvent>) {
+        for event in events {
+            if let Some(event) = self.filter(event) {
+                self.ready.push_back(event);
+            }
+        }
+    }
+
+    /// Apply the ignore rules to an event
+    ///
+    /// Renames across the rules become a creation or removal, so that a
+    /// finished `movie.mkv.part` renamed to `movie.mkv` shows up as new.
+    fn filter(&self, event: WatchEvent) -> Option<WatchEvent> {
+        match event {
+            WatchEvent::Created(path) => self.is_wanted(&path, true).then_some(WatchEvent::Created(path)),
+            WatchEvent::Modified(path) => self.is_wanted(&path, true).then_some(WatchEvent::Modified(path)),
+            WatchEvent::Removed(path) => self.is_wanted(&path, false).then_some(WatchEvent::Removed(path)),
+            WatchEvent::Renamed { from, to } => match (self.is_wanted(&from, false), self.is_wanted(&to, true)) {
+                (true, true) => Some(WatchEvent::Renamed { from, to }),
+                (true, false) => Some(WatchEvent::Removed(from)),
+                (false, true) => Some(WatchEvent::Created(to)),
+                (false, false) => None,
+            },
+            WatchEvent::Rescan => Some(WatchEvent::Rescan),
+        }
     }
 
-    /// Convert notify event to our watch event
-    fn convert_event(event: Event) -> Option<WatchEvent> {
+    /// Check a file against the ignore rules of its library, and against the
+    /// minimum size when `check_size` is set
+    fn is_wanted(&self, path: &Path, check_size: bool) -> bool {
+        let root = self
+            .roots
+            .iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.as_os_str().len());
+
+        if root.is_some_and(|root| self.ignore.is_ignored(root, path, false)) {
+            debug!("Ignoring change to {}", path.display());
+            return false;
+        }
+        !(check_size && file_size(path).is_some_and(|size| self.ignore.is_too_small(size)))
+    }
+}
+
+/// Change waiting for its path to go quiet
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    Created,
+    Modified,
+    Removed,
+    Renamed(PathBuf),
+}
+
+#[derive(Debug)]
+struct Pending {
+    change: Change,
+    /// Order in which the path first changed
+    sequence: u64,
+    due: Instant,
+    /// File size seen at the last event or settle check
+    size: Option<u64>,
+}
+
+/// First half of a rename waiting for its destination
+#[derive(Debug)]
+struct RenameFrom {
+    path: PathBuf,
+    tracker: Option<usize>,
+    at: Instant,
+}
+
+/// Coalesces raw notify events into watch events
+#[derive(Debug)]
+struct Debouncer {
+    debounce: Duration,
+    /// Files known to exist below the watched directories
+    known: BTreeSet<PathBuf>,
+    pending: HashMap<PathBuf, Pending>,
+    renames: Vec<RenameFrom>,
+    /// Rename trackers already paired from separate halves
+    paired: HashMap<usize, Instant>,
+    sequence: u64,
+    /// Whether events were dropped since the last `take_rescan`
+    rescan: bool,
+}
+
+impl Debouncer {
+    fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            known: BTreeSet::new(),
+            pending: HashMap::new(),
+            renames: Vec::new(),
+            paired: HashMap::new(),
+            sequence: 0,
+            rescan: false,
+        }
+    }
+
+    /// Record the files already present below a directory
+    fn seed(&mut self, root: &Path) {
+        self.known.extend(files_below(root));
+    }
+
+    /// Drop all state for paths below a directory
+    fn forget(&mut self, root: &Path) {
+        self.known.retain(|path| !path.starts_with(root));
+        self.pending.retain(|path, _| !path.starts_with(root));
+        self.renames.retain(|rename| !rename.path.starts_with(root));
+    }
+
+    /// Feed a raw notify event
+    fn push(&mut self, event: Event, now: Instant) {
+        if event.need_rescan() {
+            warn!("File watcher dropped events; a library rescan is needed");
+            self.rescan = true;
+        }
+
+        let tracker = event.tracker();
+        let mut paths = event.paths.into_iter();
+
         match event.kind {
-            EventKind::Create(_) => {
-                if let Some(path) = event.paths.first() {
-                    debug!("File created: {}", path.display());
-                    Some(WatchEvent::Created(path.clone()))
-                } else {
-                    None
+            EventKind::Create(_) => paths.for_each(|path| self.created(path, now)),
+            EventKind::Remove(_) => paths.for_each(|path| self.removed(&path, now)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                for path in paths {
+                    self.renames.push(RenameFrom { path, tracker, at: now });
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                for path in paths {
+                    match self.take_rename(tracker) {
+                        Some(from) => {
+                            if let Some(tracker) = tracker {
+                                self.paired.insert(tracker, now);
+                            }
+                            self.renamed(&from, &path, now);
+                        }
+                        None => self.created(path, now),
+                    }
                 }
             }
-            EventKind::Modify(_) => {
-                if let Some(path) = event.paths.first() {
-                    debug!("File modified: {}", path.display());
-                    Some(WatchEvent::Modified(path.clone()))
-                } else {
-                    None
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if tracker.is_some_and(|tracker| self.paired.remove(&tracker).is_some()) {
+                    return;
+                }
+                if let (Some(from), Some(to)) = (paths.next(), paths.next()) {
+                    self.renames.retain(|rename| rename.path != from);
+                    self.renamed(&from, &to, now);
                 }
             }
-            EventKind::Remove(_) => {
-                if let Some(path) = event.paths.first() {
-                    debug!("File removed: {}", path.display());
-                    Some(WatchEvent::Removed(path.clone()))
-                } else {
-                    None
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                // Without a mode, the path's existence tells which half this is
+                for path in paths {
+                    if path.exists() {
+                        self.created(path, now);
+                    } else {
+                        self.removed(&path, now);
+                    }
                 }
             }
-            _ => None,
+            EventKind::Modify(_) => paths.for_each(|path| self.modified(path, now)),
+            _ => {}
+        }
+    }
+
+    /// Check whether events were dropped, clearing the flag
+    fn take_rescan(&mut self) -> bool {
+        std::mem::take(&mut self.rescan)
+    }
+
+    /// Emit the events whose paths have gone quiet
+    fn poll(&mut self, now: Instant) -> Vec<WatchEvent> {
+        let debounce = self.debounce;
+        let (expired, waiting) = std::mem::take(&mut self.renames)
+            .into_iter()
+            .partition::<Vec<_>, _>(|rename| rename.at + debounce <= now);
+        self.renames = waiting;
+        for rename in expired {
+            // The file was moved out of the watched directories
+            self.removed(&rename.path, now);
+        }
+        self.paired.retain(|_, at| *at + debounce > now);
+
+        let due = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.due <= now)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        let mut ready = Vec::new();
+        for path in due {
+            let Some(pending) = self.pending.get_mut(&path) else {
+                continue;
+            };
+
+            match pending.change {
+                Change::Created | Change::Modified => match file_size(&path) {
+                    Some(size) if pending.size == Some(size) => {}
+                    Some(size) => {
+                        debug!("Waiting for {} to settle", path.display());
+                        pending.size = Some(size);
+                        pending.due = now + debounce;
+                        continue;
+                    }
+                    None if pending.change == Change::Created => {
+                        self.pending.remove(&path);
+                        continue;
+                    }
+                    None => pending.change = Change::Removed,
+                },
+                Change::Removed | Change::Renamed(_) => {}
+            }
+
+            if let Some(pending) = self.pending.remove(&path) {
+                ready.push((pending.sequence, watch_event(path, pending.change)));
+            }
+        }
+
+        ready.sort_by_key(|(sequence, _)| *sequence);
+        ready.into_iter().map(|(_, event)| event).collect()
+    }
+
+    /// Emit everything still pending without waiting
+    fn flush(&mut self) -> Vec<WatchEvent> {
+        for rename in std::mem::take(&mut self.renames) {
+            let path = rename.path;
+            self.removed(&path, rename.at);
+        }
+
+        let mut ready = self
+            .pending
+            .drain()
+            .map(|(path, pending)| (pending.sequence, watch_event(path, pending.change)))
+            .collect::<Vec<_>>();
+        ready.sort_by_key(|(sequence, _)| *sequence);
+        ready.into_iter().map(|(_, event)| event).collect()
+    }
+
+    /// Earliest time at which `poll` has work to do
+    fn next_due(&self) -> Option<Instant> {
+        let pending = self.pending.values().map(|pending| pending.due);
+        let renames = self.renames.iter().map(|rename| rename.at + self.debounce);
+        pending.chain(renames).min()
+    }
+
+    fn take_rename(&mut self, tracker: Option<usize>) -> Option<PathBuf> {
+        let index = match tracker {
+            Some(_) => self.renames.iter().position(|rename| rename.tracker == tracker),
+            None => self.renames.iter().rposition(|rename| rename.tracker.is_none()),
+        }?;
+        Some(self.renames.remove(index).path)
+    }
+
+    fn created(&mut self, path: PathBuf, now: Instant) {
+        if path.is_dir() {
+            for file in files_below(&path) {
+                self.created_file(file, now);
+            }
+        } else {
+            self.created_file(path, now);
+        }
+    }
+
+    fn created_file(&mut self, path: PathBuf, now: Instant) {
+        let change = match self.pending.get(&path).map(|pending| &pending.change) {
+            None => Change::Created,
+            // A file replaced within the quiet period has changed, not appeared
+            Some(Change::Removed) => Change::Modified,
+            Some(change) => change.clone(),
+        };
+        self.known.insert(path.clone());
+        self.touch(path, change, now);
+    }
+
+    fn modified(&mut self, path: PathBuf, now: Instant) {
+        if path.is_dir() {
+            return;
+        }
+
+        let change = match self.pending.get(&path).map(|pending| &pending.change) {
+            None | Some(Change::Removed) => Change::Modified,
+            Some(change) => change.clone(),
+        };
+        self.known.insert(path.clone());
+        self.touch(path, change, now);
+    }
+
+    fn removed(&mut self, path: &Path, now: Instant) {
+        let mut files = self.known_below(path);
+        if files.is_empty() {
+            files.push(path.to_path_buf());
+        }
+
+        for file in files {
+            self.known.remove(&file);
+            match self.pending.remove(&file).map(|pending| pending.change) {
+                // Created and removed within the quiet period
+                Some(Change::Created) => {}
+                Some(Change::Renamed(from)) => self.touch(from, Change::Removed, now),
+                _ => self.touch(file, Change::Removed, now),
+            }
+        }
+    }
+
+    fn renamed(&mut self, from: &Path, to: &Path, now: Instant) {
+        if from == to {
+            return;
+        }
+
+        let files = self.known_below(from);
+        if files.is_empty() {
+            // Nothing known about the source, so fall back to remove and create
+            self.removed(from, now);
+            self.created(to.to_path_buf(), now);
+            return;
+        }
+
+        for file in files {
+            let target = match file.strip_prefix(from) {
+                Ok(relative) if !relative.as_os_str().is_empty() => to.join(relative),
+                _ => to.to_path_buf(),
+            };
+            self.renamed_file(file, target, now);
+        }
+    }
+
+    fn renamed_file(&mut self, from: PathBuf, to: PathBuf, now: Instant) {
+        self.known.remove(&from);
+        self.known.insert(to.clone());
+
+        let change = match self.pending.remove(&from).map(|pending| pending.change) {
+            Some(Change::Created) => Change::Created,
+            // Moved back to where it started
+            Some(Change::Renamed(original)) if original == to => return,
+            Some(Change::Renamed(original)) => Change::Renamed(original),
+            _ => Change::Renamed(from),
+        };
+        self.touch(to, change, now);
+    }
+
+    fn touch(&mut self, path: PathBuf, change: Change, now: Instant) {
+        let size = match change {
+            Change::Created | Change::Modified => file_size(&path),
+            Change::Removed | Change::Renamed(_) => None,
+        };
+        let sequence = match self.pending.get(&path) {
+            Some(pending) => pending.sequence,
+            None => {
+                self.sequence += 1;
+                self.sequence
+            }
+        };
+
+        self.pending.insert(path, Pending {
+            change,
+            sequence,
+            due: now + self.debounce,
+            size,
+        });
+    }
+
+    /// Known files at or below a path
+    fn known_below(&self, path: &Path) -> Vec<PathBuf> {
+        self.known
+            .range(path.to_path_buf()..)
+            .take_while(|known| known.starts_with(path))
+            .cloned()
+            .collect()
+    }
+}
+
+fn watch_event(path: PathBuf, change: Change) -> WatchEvent {
+    match change {
+        Change::Created => {
+            debug!("File created: {}", path.display());
+            WatchEvent::Created(path)
+        }
+        Change::Modified => {
+            debug!("File modified: {}", path.display());
+            WatchEvent::Modified(path)
+        }
+        Change::Removed => {
+            debug!("File removed: {}", path.display());
+            WatchEvent::Removed(path)
+        }
+        Change::Renamed(from) => {
+            debug!("File renamed: {} -> {}", from.display(), path.display());
+            WatchEvent::Renamed { from, to: path }
         }
     }
 }
 
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().filter(|metadata| metadata.is_file()).map(|metadata| metadata.len())
+}
+
+fn files_below(root: &Path) -> Vec<PathBuf> {
+    WalkDir::new(root)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
     use tempfile::TempDir;
-    use tokio::time::{timeout, Duration};
+    use tokio::time::timeout;
     use std::fs::File;
+    use std::io::Write;
+
+    const DEBOUNCE: Duration = Duration::from_millis(100);
+
+    fn event(kind: EventKind, paths: &[&Path]) -> Event {
+        paths.iter().fold(Event::new(kind), |event, path| event.add_path(path.to_path_buf()))
+    }
+
+    fn write(path: &Path, data: &[u8]) {
+        File::create(path).unwrap().write_all(data).unwrap();
+    }
 
     #[tokio::test]
     async fn test_watcher_creation() {
@@ -120,28 +586,256 @@ mod tests {
     async fn test_watch_directory() {
         let mut watcher = FileWatcher::new().unwrap();
         let temp_dir = TempDir::new().unwrap();
-        
+
         let result = watcher.watch_directory(temp_dir.path());
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_file_creation_event() {
-        let mut watcher = FileWatcher::new().unwrap();
+        let mut watcher = FileWatcher::new().unwrap().with_debounce(DEBOUNCE);
         let temp_dir = TempDir::new().unwrap();
-        
+
         watcher.watch_directory(temp_dir.path()).unwrap();
-        
-        // Create a file and wait for event
+
+        // Create a file in several writes; only one event should come out
         let file_path = temp_dir.path().join("test.txt");
-        File::create(&file_path).unwrap();
-        
-        // Wait for event with timeout
-        let event = timeout(Duration::from_secs(1), watcher.next_event()).await;
-        
-        if let Ok(Some(WatchEvent::Created(path))) = event {
-            assert_eq!(path, file_path);
-        }
-        // Note: This test might be flaky depending on the file system
+        let mut file = File::create(&file_path).unwrap();
+        for _ in 0..10 {
+            file.write_all(&[0; 1024]).unwrap();
+        }
+        drop(file);
+
+        let event = timeout(Duration::from_secs(5), watcher.next_event()).await.unwrap();
+        assert_eq!(event, Some(WatchEvent::Created(file_path)));
+
+        let next = timeout(DEBOUNCE * 5, watcher.next_event()).await;
+        assert!(next.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_rename_event() {
+        let temp_dir = TempDir::new().unwrap();
+        let from = temp_dir.path().join("movie.part");
+        let to = temp_dir.path().join("movie.mkv");
+        write(&from, b"movie");
+
+        let mut watcher = FileWatcher::new().unwrap().with_debounce(DEBOUNCE);
+        watcher.watch_directory(temp_dir.path()).unwrap();
+        std::fs::rename(&from, &to).unwrap();
+
+        let event = timeout(Duration::from_secs(5), watcher.next_event()).await.unwrap();
+        assert_eq!(event, Some(WatchEvent::Renamed { from, to }));
+    }
+
+    #[tokio::test]
+    async fn test_filters_ignored_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let partial = root.join("movie.mkv.part");
+        let movie = root.join("movie.mkv");
+        let tiny = root.join("tiny.mkv");
+        write(&movie, b"movie");
+        write(&tiny, b"x");
+
+        let patterns = vec!["*.part".to_string()];
+        let mut watcher = FileWatcher::new()
+            .unwrap()
+            .with_ignore_rules(IgnoreRules::new(&patterns, 2).unwrap());
+        watcher.watch_directory(root).unwrap();
+
+        assert_eq!(watcher.filter(WatchEvent::Created(partial.clone())), None);
+        assert_eq!(watcher.filter(WatchEvent::Created(tiny.clone())), None);
+        assert_eq!(watcher.filter(WatchEvent::Removed(tiny.clone())), Some(WatchEvent::Removed(tiny)));
+        assert_eq!(
+            watcher.filter(WatchEvent::Renamed { from: partial.clone(), to: movie.clone() }),
+            Some(WatchEvent::Created(movie.clone()))
+        );
+        assert_eq!(
+            watcher.filter(WatchEvent::Renamed { from: movie.clone(), to: partial }),
+            Some(WatchEvent::Removed(movie))
+        );
+
+        // Paths outside the watched directories only face the size check
+        let outside = TempDir::new().unwrap();
+        let other = outside.path().join("other.mkv.part");
+        write(&other, b"movie");
+        assert_eq!(watcher.filter(WatchEvent::Created(other.clone())), Some(WatchEvent::Created(other)));
+    }
+
+    #[test]
+    fn test_coalesces_modifications() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("movie.mkv");
+        write(&path, b"movie");
+
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        let start = Instant::now();
+        debouncer.push(event(EventKind::Create(CreateKind::File), &[&path]), start);
+        for i in 1..5 {
+            let modify = EventKind::Modify(ModifyKind::Data(DataChange::Any));
+            debouncer.push(event(modify, &[&path]), start + DEBOUNCE / 2 * i);
+        }
+
+        // Every event restarts the quiet period
+        assert!(debouncer.poll(start + DEBOUNCE).is_empty());
+        assert_eq!(debouncer.next_due(), Some(start + DEBOUNCE / 2 * 4 + DEBOUNCE));
+        assert_eq!(
+            debouncer.poll(start + DEBOUNCE * 3),
+            vec![WatchEvent::Created(path.clone())]
+        );
+        assert_eq!(debouncer.next_due(), None);
+
+        // Created and removed within the quiet period leaves nothing behind
+        let later = start + DEBOUNCE * 10;
+        debouncer.push(event(EventKind::Create(CreateKind::File), &[&path]), later);
+        std::fs::remove_file(&path).unwrap();
+        debouncer.push(event(EventKind::Remove(RemoveKind::File), &[&path]), later);
+        assert!(debouncer.poll(later + DEBOUNCE).is_empty());
+    }
+
+    #[test]
+    fn test_flags_dropped_events() {
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        debouncer.push(Event::new(EventKind::Other), Instant::now());
+        assert!(!debouncer.take_rescan());
+
+        debouncer.push(Event::new(EventKind::Other).set_flag(notify::event::Flag::Rescan), Instant::now());
+        assert!(debouncer.take_rescan());
+        assert!(!debouncer.take_rescan());
+    }
+
+    #[test]
+    fn test_waits_for_file_to_settle() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("movie.mkv");
+        write(&path, b"first chunk");
+
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        let start = Instant::now();
+        debouncer.push(event(EventKind::Create(CreateKind::File), &[&path]), start);
+
+        // The file keeps growing without events reaching the watcher
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b" more").unwrap();
+        assert!(debouncer.poll(start + DEBOUNCE).is_empty());
+        assert_eq!(
+            debouncer.poll(start + DEBOUNCE * 2),
+            vec![WatchEvent::Created(path)]
+        );
+    }
+
+    #[test]
+    fn test_pairs_renames() {
+        let temp_dir = TempDir::new().unwrap();
+        let from = temp_dir.path().join("a.mkv");
+        let to = temp_dir.path().join("b.mkv");
+        let outside = temp_dir.path().join("c.mkv");
+        write(&to, b"movie");
+        write(&from, b"other");
+
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        debouncer.seed(temp_dir.path());
+        let start = Instant::now();
+
+        // inotify reports both halves and then the paired event
+        let rename = |mode| EventKind::Modify(ModifyKind::Name(mode));
+        debouncer.push(event(rename(RenameMode::From), &[&from]).set_tracker(7), start);
+        debouncer.push(event(rename(RenameMode::To), &[&to]).set_tracker(7), start);
+        debouncer.push(event(rename(RenameMode::Both), &[&from, &to]).set_tracker(7), start);
+
+        // A rename without a destination is a removal
+        debouncer.push(event(rename(RenameMode::From), &[&outside]).set_tracker(8), start);
+
+        assert!(debouncer.poll(start + DEBOUNCE / 2).is_empty());
+        assert_eq!(
+            debouncer.poll(start + DEBOUNCE),
+            vec![WatchEvent::Renamed { from: from.clone(), to: to.clone() }]
+        );
+        assert_eq!(
+            debouncer.poll(start + DEBOUNCE * 2),
+            vec![WatchEvent::Removed(outside)]
+        );
+
+        // Backends reporting only the paired event
+        let again = temp_dir.path().join("d.mkv");
+        let later = start + DEBOUNCE * 10;
+        debouncer.push(event(rename(RenameMode::Both), &[&to, &again]), later);
+        assert_eq!(
+            debouncer.poll(later + DEBOUNCE),
+            vec![WatchEvent::Renamed { from: to, to: again }]
+        );
+    }
+
+    #[test]
+    fn test_rename_of_new_file_is_creation() {
+        let temp_dir = TempDir::new().unwrap();
+        let partial = temp_dir.path().join("movie.mkv.part");
+        let complete = temp_dir.path().join("movie.mkv");
+        write(&complete, b"movie");
+
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        let start = Instant::now();
+        debouncer.push(event(EventKind::Create(CreateKind::File), &[&partial]), start);
+        debouncer.push(
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&partial, &complete]),
+            start,
+        );
+
+        assert_eq!(debouncer.poll(start + DEBOUNCE), vec![WatchEvent::Created(complete)]);
+    }
+
+    #[test]
+    fn test_expands_directory_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let season = temp_dir.path().join("Season 01");
+        std::fs::create_dir(&season).unwrap();
+        let episodes = [season.join("S01E01.mkv"), season.join("S01E02.mkv")];
+        for episode in &episodes {
+            write(episode, b"episode");
+        }
+
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        let start = Instant::now();
+        debouncer.push(event(EventKind::Create(CreateKind::Folder), &[&season]), start);
+
+        let mut created = debouncer.poll(start + DEBOUNCE);
+        created.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(
+            created,
+            episodes.iter().cloned().map(WatchEvent::Created).collect::<Vec<_>>()
+        );
+
+        // Renaming the directory renames every file below it
+        let renamed = temp_dir.path().join("Season 1");
+        std::fs::rename(&season, &renamed).unwrap();
+        let later = start + DEBOUNCE * 10;
+        debouncer.push(
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&season, &renamed]),
+            later,
+        );
+        let mut moved = debouncer.poll(later + DEBOUNCE);
+        moved.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(
+            moved,
+            vec![
+                WatchEvent::Renamed { from: episodes[0].clone(), to: renamed.join("S01E01.mkv") },
+                WatchEvent::Renamed { from: episodes[1].clone(), to: renamed.join("S01E02.mkv") },
+            ]
+        );
+
+        // Removing the directory removes every file below it
+        std::fs::remove_dir_all(&renamed).unwrap();
+        let last = later + DEBOUNCE * 10;
+        debouncer.push(event(EventKind::Remove(RemoveKind::Folder), &[&renamed]), last);
+        let mut removed = debouncer.poll(last + DEBOUNCE);
+        removed.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(
+            removed,
+            vec![
+                WatchEvent::Removed(renamed.join("S01E01.mkv")),
+                WatchEvent::Removed(renamed.join("S01E02.mkv")),
+            ]
+        );
+        assert!(debouncer.known.is_empty());
     }
 }
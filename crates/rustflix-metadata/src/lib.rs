@@ -6,14 +6,16 @@ pub mod providers;
 pub mod tmdb;
 pub mod omdb;
 pub mod cache;
+pub mod nfo;
 
 // Re-export commonly used types
 pub use providers::{MetadataProvider, ProviderResult};
 pub use tmdb::TmdbProvider;
 pub use omdb::OmdbProvider;
 pub use cache::MetadataCache;
+pub use nfo::NfoProvider;
 
-use rustflix_core::{Result, RustFlixError};
+use rustflix_core::Result;
 
 /// Metadata service for managing multiple providers
 #[derive(Debug)]
@@ -32,8 +34,18 @@ impl MetadataService {
     }
 
     /// Add a metadata provider
+    ///
+    /// Providers are kept in priority order, lowest value first; providers
+    /// of equal priority keep the order they were added in.
     pub fn add_provider(&mut self, provider: Box<dyn MetadataProvider>) {
-        self.providers.push(provider);
+        let priority = provider.config().priority;
+        let index = self.providers.partition_point(|existing| existing.config().priority <= priority);
+        self.providers.insert(index, provider);
+    }
+
+    /// Registered providers in priority order
+    pub fn providers(&self) -> &[Box<dyn MetadataProvider>] {
+        &self.providers
     }
 
     /// Start the metadata service
@@ -58,4 +70,15 @@ mod tests {
         let service = MetadataService::new();
         assert!(service.is_ok());
     }
+
+    #[test]
+    fn test_providers_in_priority_order() {
+        let mut service = MetadataService::new().unwrap();
+        service.add_provider(Box::new(OmdbProvider::new("key".to_string()).unwrap()));
+        service.add_provider(Box::new(TmdbProvider::new("key".to_string()).unwrap()));
+        service.add_provider(Box::new(NfoProvider::new()));
+
+        let names = service.providers().iter().map(|provider| provider.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["NFO", "TMDb", "OMDb"]);
+    }
 }
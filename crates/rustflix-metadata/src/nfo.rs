@@ -0,0 +1,682 @@
+//! Local NFO metadata provider
+//!
+//! Reads Kodi-style `.nfo` XML files kept next to media files: `movie`,
+//! `tvshow` and `episodedetails` documents. Everything is read from disk, so
+//! the provider works offline, and its default priority places it ahead of
+//! the remote providers. Through the provider interface, only files inside
+//! the configured library folders are read.
+
+use crate::providers::{MetadataProvider, ProviderConfig, SearchResult};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use roxmltree::{Document, Node};
+use rustflix_core::metadata::{
+    CrewMember, EpisodeMetadata, MediaImages, Person, SeasonMetadata, TvShowMetadata, TvShowStatus,
+};
+use rustflix_core::{MediaMetadata, MediaType, Result, RustFlixError};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use tracing::debug;
+use uuid::Uuid;
+
+/// Contents of an NFO file
+#[derive(Debug, Clone)]
+pub enum Nfo {
+    Movie(MediaMetadata),
+    TvShow(TvShowMetadata),
+    /// Multi-episode files hold one entry per episode
+    Episodes(Vec<EpisodeMetadata>),
+}
+
+impl Nfo {
+    /// Convert to generic metadata; episode files yield their first episode
+    pub fn into_metadata(self) -> MediaMetadata {
+        match self {
+            Nfo::Movie(metadata) => metadata,
+            Nfo::TvShow(show) => show.base,
+            Nfo::Episodes(episodes) => episodes
+                .into_iter()
+                .next()
+                .map(episode_metadata)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// NFO metadata provider
+#[derive(Debug, Clone)]
+pub struct NfoProvider {
+    config: ProviderConfig,
+    /// Library folders that searches and lookups may read from
+    libraries: Vec<PathBuf>,
+}
+
+impl NfoProvider {
+    /// Create a new NFO provider
+    pub fn new() -> Self {
+        Self {
+            config: ProviderConfig {
+                name: "NFO".to_string(),
+                base_url: String::new(),
+                api_key: None,
+                rate_limit: None,
+                priority: 0,
+                enabled: true,
+            },
+            libraries: Vec::new(),
+        }
+    }
+
+    /// Set the provider priority
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.config.priority = priority;
+        self
+    }
+
+    /// Set the library folders that `search` and `get_metadata` may read
+    pub fn with_libraries(mut self, libraries: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.libraries = libraries.into_iter().collect();
+        self
+    }
+
+    /// Read the NFO file describing a media file or folder, if it has one
+    pub async fn for_media(&self, path: &Path, media_type: MediaType) -> Result<Option<Nfo>> {
+        match Self::find_nfo(path, media_type) {
+            Some(nfo) => Ok(Some(self.read(&nfo).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Find the NFO file describing a media file or folder
+    ///
+    /// Files use the NFO sharing their name, falling back to `movie.nfo` for
+    /// movies; folders use `tvshow.nfo` or `movie.nfo`.
+    pub fn find_nfo(path: &Path, media_type: MediaType) -> Option<PathBuf> {
+        let folder_nfo = match media_type {
+            MediaType::TvShow => "tvshow.nfo",
+            _ => "movie.nfo",
+        };
+
+        let candidates = if path.is_dir() {
+            vec![path.join(folder_nfo)]
+        } else {
+            let mut candidates = vec![path.with_extension("nfo")];
+            if media_type == MediaType::Movie {
+                candidates.extend(path.parent().map(|dir| dir.join(folder_nfo)));
+            }
+            candidates
+        };
+
+        candidates.into_iter().find(|candidate| candidate.is_file())
+    }
+
+    /// Read and parse an NFO file
+    pub async fn read(&self, path: &Path) -> Result<Nfo> {
+        debug!("Reading NFO file: {}", path.display());
+        let xml = tokio::fs::read_to_string(path).await?;
+        parse(&xml)
+    }
+
+    /// Check whether a path is absolute and inside one of the libraries
+    fn in_library(&self, path: &Path) -> bool {
+        path.is_absolute()
+            && !path.components().any(|component| component == Component::ParentDir)
+            && self.libraries.iter().any(|library| path.starts_with(library))
+    }
+}
+
+impl Default for NfoProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for NfoProvider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Search by path: `query` is the absolute path of a media file or
+    /// folder inside a library; other queries find nothing
+    async fn search(&self, query: &str, media_type: MediaType) -> Result<Vec<SearchResult>> {
+        let path = Path::new(query);
+        if !self.in_library(path) {
+            return Ok(vec![]);
+        }
+        let Some(nfo) = Self::find_nfo(path, media_type) else {
+            return Ok(vec![]);
+        };
+
+        let metadata = self.read(&nfo).await?.into_metadata();
+        Ok(vec![SearchResult {
+            external_id: nfo.to_string_lossy().into_owned(),
+            title: metadata.title,
+            original_title: metadata.original_title,
+            release_date: metadata.release_date,
+            overview: metadata.description,
+            poster_path: metadata.images.poster,
+            media_type,
+            popularity: None,
+        }])
+    }
+
+    /// Get metadata by the absolute path of an NFO file inside a library
+    async fn get_metadata(&self, external_id: &str) -> Result<MediaMetadata> {
+        let path = Path::new(external_id);
+        let is_nfo = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("nfo"));
+        if !is_nfo || !self.in_library(path) {
+            return Err(RustFlixError::not_found("NFO file", external_id));
+        }
+        Ok(self.read(path).await?.into_metadata())
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+}
+
+/// Parse the contents of an NFO file
+///
+/// Text after the root element, such as the scraper URL Kodi allows at the
+/// end of the file, is ignored.
+pub fn parse(xml: &str) -> Result<Nfo> {
+    // Wrapping the body allows several roots (multi-episode files) and
+    // trailing text
+    let body = strip_declaration(xml.trim_start_matches('\u{feff}'));
+    let wrapped = format!("<nfo>{}</nfo>", body);
+    let document = Document::parse(&wrapped).map_err(|e| invalid(format!("Invalid XML: {}", e)))?;
+    let roots = document.root_element().children().filter(Node::is_element).collect::<Vec<_>>();
+
+    match roots.first().map(|root| root.tag_name().name()) {
+        Some("movie") => Ok(Nfo::Movie(base_metadata(roots[0]))),
+        Some("tvshow") => Ok(Nfo::TvShow(tv_show(roots[0]))),
+        Some("episodedetails") => Ok(Nfo::Episodes(
+            roots
+                .into_iter()
+                .filter(|root| root.has_tag_name("episodedetails"))
+                .map(episode)
+                .collect(),
+        )),
+        Some(other) => Err(invalid(format!("Unsupported NFO root element <{}>", other))),
+        None => Err(invalid("Empty NFO file".to_string())),
+    }
+}
+
+fn base_metadata(node: Node) -> MediaMetadata {
+    let mut metadata = MediaMetadata::new(text(node, "title").unwrap_or_default());
+    let (rating, vote_count) = rating(node);
+
+    metadata.original_title = text(node, "originaltitle");
+    metadata.description = text(node, "plot").or_else(|| text(node, "outline"));
+    metadata.tagline = text(node, "tagline");
+    metadata.release_date = date(node, "premiered")
+        .or_else(|| date(node, "releasedate"))
+        .or_else(|| number(node, "year").and_then(|year| NaiveDate::from_ymd_opt(year, 1, 1)));
+    metadata.runtime = number(node, "runtime");
+    metadata.genres = texts(node, "genre");
+    metadata.countries = texts(node, "country");
+    metadata.rating = rating;
+    metadata.vote_count = vote_count;
+    metadata.cast = actors(node);
+    metadata.crew = texts(node, "director")
+        .into_iter()
+        .map(|name| crew_member(name, "Director", "Directing"))
+        .chain(texts(node, "credits").into_iter().map(|name| crew_member(name, "Writer", "Writing")))
+        .collect();
+    metadata.external_ids = external_ids(node);
+    metadata.images = images(node);
+    metadata
+}
+
+fn tv_show(node: Node) -> TvShowMetadata {
+    let mut seasons = BTreeMap::new();
+    for named in children(node, "namedseason") {
+        if let (Some(number), Some(name)) = (attribute(named, "number"), named.text()) {
+            season(&mut seasons, number).name = name.trim().to_string();
+        }
+    }
+    for thumb in children(node, "thumb").filter(|thumb| thumb.attribute("aspect") == Some("poster")) {
+        if let (Some(number), Some(url)) = (attribute(thumb, "season"), thumb.text()) {
+            season(&mut seasons, number).poster_path = Some(url.trim().to_string());
+        }
+    }
+
+    TvShowMetadata {
+        base: base_metadata(node),
+        status: text(node, "status").map_or(TvShowStatus::Returning, |status| tv_show_status(&status)),
+        episode_count: number(node, "episode").unwrap_or(0),
+        season_count: number(node, "season").unwrap_or(seasons.len() as u32),
+        first_air_date: date(node, "premiered"),
+        last_air_date: None,
+        networks: texts(node, "studio"),
+        seasons: seasons.into_values().collect(),
+    }
+}
+
+fn episode(node: Node) -> EpisodeMetadata {
+    let (rating, vote_count) = rating(node);
+
+    EpisodeMetadata {
+        id: Uuid::new_v4(),
+        episode_number: number(node, "episode").unwrap_or(0),
+        season_number: number(node, "season").unwrap_or(0),
+        name: text(node, "title").unwrap_or_default(),
+        description: text(node, "plot"),
+        air_date: date(node, "aired").or_else(|| date(node, "premiered")),
+        runtime: number(node, "runtime"),
+        rating,
+        vote_count,
+        still_path: text(node, "thumb"),
+        guest_stars: actors(node),
+    }
+}
+
+fn episode_metadata(episode: EpisodeMetadata) -> MediaMetadata {
+    let mut metadata = MediaMetadata::new(episode.name);
+    metadata.description = episode.description;
+    metadata.release_date = episode.air_date;
+    metadata.runtime = episode.runtime;
+    metadata.rating = episode.rating;
+    metadata.vote_count = episode.vote_count;
+    metadata.cast = episode.guest_stars;
+    metadata.images.thumbnails.extend(episode.still_path);
+    metadata
+}
+
+fn season(seasons: &mut BTreeMap<u32, SeasonMetadata>, number: u32) -> &mut SeasonMetadata {
+    seasons.entry(number).or_insert_with(|| SeasonMetadata {
+        id: Uuid::new_v4(),
+        season_number: number,
+        name: format!("Season {}", number),
+        description: None,
+        air_date: None,
+        episode_count: 0,
+        poster_path: None,
+        episodes: Vec::new(),
+    })
+}
+
+fn tv_show_status(status: &str) -> TvShowStatus {
+    match status.to_lowercase().as_str() {
+        "ended" => TvShowStatus::Ended,
+        "canceled" | "cancelled" => TvShowStatus::Cancelled,
+        "in production" => TvShowStatus::InProduction,
+        "planned" => TvShowStatus::Planned,
+        "pilot" => TvShowStatus::Pilot,
+        _ => TvShowStatus::Returning,
+    }
+}
+
+/// Rating on a 0-10 scale with its vote count
+///
+/// The default entry of `<ratings>` wins, then the first one; the legacy
+/// `<rating>` and `<votes>` elements are used when there is no `<ratings>`.
+fn rating(node: Node) -> (Option<f32>, Option<u32>) {
+    let entries = children(node, "ratings").flat_map(|ratings| children(ratings, "rating")).collect::<Vec<_>>();
+    let entry = entries
+        .iter()
+        .find(|entry| entry.attribute("default") == Some("true"))
+        .or_else(|| entries.first());
+
+    match entry {
+        Some(entry) => {
+            let max = entry.attribute("max").and_then(|max| max.parse::<f32>().ok()).filter(|max| *max > 0.0);
+            let value = number::<f32>(*entry, "value").map(|value| value * 10.0 / max.unwrap_or(10.0));
+            (value, votes(*entry))
+        }
+        None => (number(node, "rating"), votes(node)),
+    }
+}
+
+/// Vote counts are often written with thousands separators
+fn votes(node: Node) -> Option<u32> {
+    text(node, "votes").and_then(|votes| votes.replace([',', '.'], "").parse().ok())
+}
+
+fn actors(node: Node) -> Vec<Person> {
+    let mut actors = children(node, "actor")
+        .filter_map(|actor| {
+            let person = Person {
+                id: Uuid::new_v4(),
+                name: text(actor, "name")?,
+                character: text(actor, "role"),
+                profile_path: text(actor, "thumb"),
+                external_ids: external_ids(actor),
+            };
+            Some((number::<u32>(actor, "order"), person))
+        })
+        .collect::<Vec<_>>();
+
+    // Unordered actors keep their place after the ordered ones
+    actors.sort_by_key(|(order, _)| order.unwrap_or(u32::MAX));
+    actors.into_iter().map(|(_, person)| person).collect()
+}
+
+fn crew_member(name: String, job: &str, department: &str) -> CrewMember {
+    CrewMember {
+        person: Person {
+            id: Uuid::new_v4(),
+            name,
+            character: None,
+            profile_path: None,
+            external_ids: HashMap::new(),
+        },
+        job: job.to_string(),
+        department: department.to_string(),
+    }
+}
+
+/// External IDs from `<uniqueid>` and the legacy ID elements
+fn external_ids(node: Node) -> HashMap<String, String> {
+    let mut ids = HashMap::new();
+
+    for unique_id in children(node, "uniqueid") {
+        if let Some(id) = unique_id.text().map(str::trim).filter(|id| !id.is_empty()) {
+            let provider = unique_id.attribute("type").unwrap_or("unknown").to_lowercase();
+            ids.insert(provider, id.to_string());
+        }
+    }
+
+    for (element, provider) in [("imdbid", "imdb"), ("tmdbid", "tmdb"), ("tvdbid", "tvdb")] {
+        if let Some(id) = text(node, element) {
+            ids.entry(provider.to_string()).or_insert(id);
+        }
+    }
+    if let Some(id) = text(node, "id").filter(|id| id.starts_with("tt")) {
+        ids.entry("imdb".to_string()).or_insert(id);
+    }
+
+    ids
+}
+
+fn images(node: Node) -> MediaImages {
+    let mut images = MediaImages::default();
+
+    for thumb in children(node, "thumb").filter(|thumb| thumb.attribute("season").is_none()) {
+        let Some(url) = thumb.text().map(str::trim).filter(|url| !url.is_empty()) else {
+            continue;
+        };
+        match thumb.attribute("aspect").unwrap_or("poster") {
+            "poster" if images.poster.is_none() => images.poster = Some(url.to_string()),
+            "clearlogo" | "logo" if images.logo.is_none() => images.logo = Some(url.to_string()),
+            "landscape" | "thumb" => images.thumbnails.push(url.to_string()),
+            _ => {}
+        }
+    }
+
+    for url in children(node, "fanart").flat_map(|fanart| children(fanart, "thumb")).filter_map(|thumb| thumb.text()) {
+        let url = url.trim().to_string();
+        if images.backdrop.is_none() {
+            images.backdrop = Some(url);
+        } else {
+            images.fanart.push(url);
+        }
+    }
+
+    images
+}
+
+fn children<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(name))
+}
+
+/// Trimmed text of the first child element with a name, if not empty
+fn text(node: Node, name: &'static str) -> Option<String> {
+    children(node, name)
+        .filter_map(|child| child.text())
+        .map(str::trim)
+        .find(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Texts of every child element with a name; Kodi also joins values with " / "
+fn texts(node: Node, name: &'static str) -> Vec<String> {
+    children(node, name)
+        .filter_map(|child| child.text())
+        .flat_map(|text| text.split(" / "))
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn number<T: FromStr>(node: Node, name: &'static str) -> Option<T> {
+    text(node, name).and_then(|text| text.parse().ok())
+}
+
+fn date(node: Node, name: &'static str) -> Option<NaiveDate> {
+    text(node, name).and_then(|text| NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok())
+}
+
+fn attribute(node: Node, name: &str) -> Option<u32> {
+    node.attribute(name).and_then(|value| value.trim().parse().ok())
+}
+
+fn strip_declaration(xml: &str) -> &str {
+    let xml = xml.trim_start();
+    match xml.strip_prefix("<?xml").and_then(|rest| rest.find("?>").map(|end| &rest[end + 2..])) {
+        Some(rest) => rest,
+        None => xml,
+    }
+}
+
+fn invalid(message: String) -> RustFlixError {
+    RustFlixError::metadata_provider("NFO".to_string(), message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const MOVIE: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes" ?>
+<movie>
+    <title>The Matrix</title>
+    <originaltitle>The Matrix</originaltitle>
+    <ratings>
+        <rating name="imdb" max="10"><value>8.7</value><votes>1,900,000</votes></rating>
+        <rating name="themoviedb" max="100" default="true"><value>82</value><votes>24000</votes></rating>
+    </ratings>
+    <plot>A hacker learns the truth about his reality.</plot>
+    <tagline>Welcome to the Real World.</tagline>
+    <runtime>136</runtime>
+    <thumb aspect="poster">https://example.com/poster.jpg</thumb>
+    <thumb aspect="clearlogo">https://example.com/logo.png</thumb>
+    <fanart>
+        <thumb>https://example.com/fanart1.jpg</thumb>
+        <thumb>https://example.com/fanart2.jpg</thumb>
+    </fanart>
+    <uniqueid type="imdb" default="true">tt0133093</uniqueid>
+    <uniqueid type="tmdb">603</uniqueid>
+    <genre>Action</genre>
+    <genre>Science Fiction / Thriller</genre>
+    <country>United States of America</country>
+    <credits>Lilly Wachowski</credits>
+    <director>Lana Wachowski</director>
+    <premiered>1999-03-30</premiered>
+    <actor><name>Carrie-Anne Moss</name><role>Trinity</role><order>2</order></actor>
+    <actor><name>Keanu Reeves</name><role>Neo</role><order>0</order><thumb>https://example.com/keanu.jpg</thumb></actor>
+</movie>
+https://www.themoviedb.org/movie/603
+"#;
+
+    #[test]
+    fn test_parse_movie() {
+        let Nfo::Movie(movie) = parse(MOVIE).unwrap() else {
+            panic!("expected a movie");
+        };
+
+        assert_eq!(movie.title, "The Matrix");
+        assert_eq!(movie.tagline.as_deref(), Some("Welcome to the Real World."));
+        assert_eq!(movie.release_date, NaiveDate::from_ymd_opt(1999, 3, 30));
+        assert_eq!(movie.runtime, Some(136));
+        assert_eq!(movie.genres, vec!["Action", "Science Fiction", "Thriller"]);
+        assert_eq!(movie.countries, vec!["United States of America"]);
+
+        // The default rating wins and is scaled to 0-10
+        assert_eq!(movie.rating, Some(8.2));
+        assert_eq!(movie.vote_count, Some(24000));
+
+        assert_eq!(movie.external_ids.get("imdb").map(String::as_str), Some("tt0133093"));
+        assert_eq!(movie.external_ids.get("tmdb").map(String::as_str), Some("603"));
+
+        let cast = movie.cast.iter().map(|person| person.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(cast, vec!["Keanu Reeves", "Carrie-Anne Moss"]);
+        assert_eq!(movie.cast[0].character.as_deref(), Some("Neo"));
+        assert_eq!(movie.cast[0].profile_path.as_deref(), Some("https://example.com/keanu.jpg"));
+
+        let crew = movie
+            .crew
+            .iter()
+            .map(|member| (member.person.name.as_str(), member.job.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(crew, vec![("Lana Wachowski", "Director"), ("Lilly Wachowski", "Writer")]);
+
+        assert_eq!(movie.images.poster.as_deref(), Some("https://example.com/poster.jpg"));
+        assert_eq!(movie.images.logo.as_deref(), Some("https://example.com/logo.png"));
+        assert_eq!(movie.images.backdrop.as_deref(), Some("https://example.com/fanart1.jpg"));
+        assert_eq!(movie.images.fanart, vec!["https://example.com/fanart2.jpg"]);
+    }
+
+    #[test]
+    fn test_parse_legacy_fields() {
+        let nfo = "<movie><title>Old</title><rating>7.5</rating><votes>1.234</votes>\
+                   <year>1984</year><id>tt0087332</id></movie>";
+        let movie = parse(nfo).unwrap().into_metadata();
+
+        assert_eq!(movie.rating, Some(7.5));
+        assert_eq!(movie.vote_count, Some(1234));
+        assert_eq!(movie.release_date, NaiveDate::from_ymd_opt(1984, 1, 1));
+        assert_eq!(movie.external_ids.get("imdb").map(String::as_str), Some("tt0087332"));
+    }
+
+    #[test]
+    fn test_parse_tv_show() {
+        let nfo = r#"<tvshow>
+            <title>Breaking Bad</title>
+            <season>5</season>
+            <episode>62</episode>
+            <status>Ended</status>
+            <studio>AMC</studio>
+            <premiered>2008-01-20</premiered>
+            <uniqueid type="tvdb" default="true">81189</uniqueid>
+            <namedseason number="1">The First Season</namedseason>
+            <thumb aspect="poster" season="2">https://example.com/season2.jpg</thumb>
+            <thumb aspect="poster">https://example.com/show.jpg</thumb>
+        </tvshow>"#;
+        let Nfo::TvShow(show) = parse(nfo).unwrap() else {
+            panic!("expected a TV show");
+        };
+
+        assert_eq!(show.base.title, "Breaking Bad");
+        assert!(matches!(show.status, TvShowStatus::Ended));
+        assert_eq!(show.season_count, 5);
+        assert_eq!(show.episode_count, 62);
+        assert_eq!(show.networks, vec!["AMC"]);
+        assert_eq!(show.first_air_date, NaiveDate::from_ymd_opt(2008, 1, 20));
+        assert_eq!(show.base.external_ids.get("tvdb").map(String::as_str), Some("81189"));
+        assert_eq!(show.base.images.poster.as_deref(), Some("https://example.com/show.jpg"));
+
+        assert_eq!(show.seasons.len(), 2);
+        assert_eq!(show.seasons[0].name, "The First Season");
+        assert_eq!(show.seasons[1].season_number, 2);
+        assert_eq!(show.seasons[1].poster_path.as_deref(), Some("https://example.com/season2.jpg"));
+    }
+
+    #[test]
+    fn test_parse_multi_episode() {
+        let nfo = r#"<?xml version="1.0" encoding="UTF-8"?>
+<episodedetails>
+    <title>Pilot</title><season>1</season><episode>1</episode>
+    <aired>2008-01-20</aired><rating>9.0</rating>
+    <thumb>https://example.com/still.jpg</thumb>
+    <actor><name>Guest Star</name><role>Someone</role></actor>
+</episodedetails>
+<episodedetails>
+    <title>Cat's in the Bag...</title><season>1</season><episode>2</episode>
+</episodedetails>"#;
+        let Nfo::Episodes(episodes) = parse(nfo).unwrap() else {
+            panic!("expected episodes");
+        };
+
+        assert_eq!(episodes.len(), 2);
+        assert_eq!(episodes[0].name, "Pilot");
+        assert_eq!((episodes[0].season_number, episodes[0].episode_number), (1, 1));
+        assert_eq!(episodes[0].air_date, NaiveDate::from_ymd_opt(2008, 1, 20));
+        assert_eq!(episodes[0].rating, Some(9.0));
+        assert_eq!(episodes[0].still_path.as_deref(), Some("https://example.com/still.jpg"));
+        assert_eq!(episodes[0].guest_stars[0].name, "Guest Star");
+        assert_eq!(episodes[1].episode_number, 2);
+
+        let metadata = Nfo::Episodes(episodes).into_metadata();
+        assert_eq!(metadata.title, "Pilot");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("").is_err());
+        assert!(parse("<musicvideo><title>Song</title></musicvideo>").is_err());
+        assert!(parse("<movie><title>Broken</movie>").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_provider_reads_local_files() {
+        let dir = TempDir::new().unwrap();
+        let video = dir.path().join("The Matrix (1999).mkv");
+        std::fs::write(&video, b"").unwrap();
+
+        let provider = NfoProvider::new().with_libraries([dir.path().to_path_buf()]);
+        assert_eq!(provider.config().priority, 0);
+        let query = video.to_string_lossy();
+        assert!(provider.search(&query, MediaType::Movie).await.unwrap().is_empty());
+        assert!(provider.for_media(&video, MediaType::Movie).await.unwrap().is_none());
+
+        // Folder-level movie.nfo is used when there is no NFO named after the file
+        std::fs::write(dir.path().join("movie.nfo"), MOVIE).unwrap();
+        let results = provider.search(&query, MediaType::Movie).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "The Matrix");
+        assert!(results[0].external_id.ends_with("movie.nfo"));
+
+        let metadata = provider.get_metadata(&results[0].external_id).await.unwrap();
+        assert_eq!(metadata.external_ids.get("tmdb").map(String::as_str), Some("603"));
+
+        std::fs::write(dir.path().join("The Matrix (1999).nfo"), "<movie><title>Own</title></movie>").unwrap();
+        let results = provider.search(&query, MediaType::Movie).await.unwrap();
+        assert_eq!(results[0].title, "Own");
+        let nfo = provider.for_media(&video, MediaType::Movie).await.unwrap().unwrap();
+        assert_eq!(nfo.into_metadata().title, "Own");
+    }
+
+    #[tokio::test]
+    async fn test_provider_stays_in_libraries() {
+        let dir = TempDir::new().unwrap();
+        let library = dir.path().join("Movies");
+        let outside = dir.path().join("Other");
+        for folder in [&library, &outside] {
+            std::fs::create_dir_all(folder).unwrap();
+            std::fs::write(folder.join("Heat.mkv"), b"").unwrap();
+            std::fs::write(folder.join("Heat.nfo"), MOVIE).unwrap();
+        }
+
+        let provider = NfoProvider::new().with_libraries([library.clone()]);
+        let search = |query: PathBuf| {
+            let provider = provider.clone();
+            async move { provider.search(&query.to_string_lossy(), MediaType::Movie).await.unwrap() }
+        };
+        assert_eq!(search(library.join("Heat.mkv")).await.len(), 1);
+        assert!(search(outside.join("Heat.mkv")).await.is_empty());
+        assert!(search(library.join("../Other/Heat.mkv")).await.is_empty());
+        assert!(search(PathBuf::from("Movies/Heat.mkv")).await.is_empty());
+        // Without libraries, nothing is searched
+        let unconfigured = NfoProvider::new();
+        let query = library.join("Heat.mkv").to_string_lossy().into_owned();
+        assert!(unconfigured.search(&query, MediaType::Movie).await.unwrap().is_empty());
+
+        assert!(provider.get_metadata(&library.join("Heat.nfo").to_string_lossy()).await.is_ok());
+        assert!(provider.get_metadata(&outside.join("Heat.nfo").to_string_lossy()).await.is_err());
+        assert!(provider.get_metadata(&library.join("Heat.mkv").to_string_lossy()).await.is_err());
+    }
+}
@@ -71,6 +71,8 @@ impl MetadataProvider for OmdbProvider {
             MediaType::Person => return Ok(vec![]), // OMDb doesn't support person search
             MediaType::Music => return Ok(vec![]), // OMDb doesn't support music
             MediaType::Photo => return Ok(vec![]), // OMDb doesn't support photos
+            MediaType::Audiobook => return Ok(vec![]), // OMDb doesn't support audiobooks
+            MediaType::Book => return Ok(vec![]), // OMDb doesn't support books
             MediaType::Other => "movie", // Default to movie for other types
         };
 
@@ -41,6 +41,7 @@ pub struct ProviderConfig {
     pub base_url: String,
     pub api_key: Option<String>,
     pub rate_limit: Option<u32>,
+    /// Lower values are consulted first
     pub priority: u8,
     pub enabled: bool,
 }
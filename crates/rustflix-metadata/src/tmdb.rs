@@ -68,6 +68,8 @@ impl MetadataProvider for TmdbProvider {
             MediaType::Episode => "search/tv", // Episodes are part of TV shows
             MediaType::Music => return Ok(vec![]), // TMDb doesn't support music
             MediaType::Photo => return Ok(vec![]), // TMDb doesn't support photos
+            MediaType::Audiobook => return Ok(vec![]), // TMDb doesn't support audiobooks
+            MediaType::Book => return Ok(vec![]), // TMDb doesn't support books
             MediaType::Other => "search/multi",
         };
 
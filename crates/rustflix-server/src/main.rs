@@ -49,11 +49,15 @@ impl RustFlixServer {
             connection_timeout: std::time::Duration::from_secs(30),
         };
         let database = DatabaseService::new(db_config).await?;
-        let media_library = MediaLibraryService::new()?;
+        let media_library = MediaLibraryService::new(database.media_repo.clone(), &config.media)?;
         let metadata = MetadataService::new()?;
-        let streaming = StreamingService::new()?;
+        let streaming = StreamingService::from_config(&config.streaming)?;
         let auth = AuthService::new("secret_key")?; // TODO: Use config
-        let api = ApiService::new()?;
+        let api = ApiService::with_database(&database)?
+            .with_auditor(media_library.auditor())
+            .with_storages(media_library.storages())
+            .with_media_library(media_library.clone())
+            .with_streaming(streaming.clone());
         let plugins = PluginService::new()?;
         let monitoring = MonitoringService::new()?;
 
@@ -76,7 +80,7 @@ impl RustFlixServer {
 
         // Start all services
         // Database service doesn't have start method - it's initialized in new()
-        // Services don't have start methods - they're initialized in new()
+        self.media_library.start().await?;
         info!("All services initialized successfully");
 
         // Create HTTP server
@@ -94,14 +98,15 @@ impl RustFlixServer {
             .await
             .map_err(|e| RustFlixError::internal(format!("Server error: {}", e)))?;
 
-        Ok(())
+        self.stop().await
     }
 
     /// Stop the server
     pub async fn stop(&self) -> Result<()> {
         info!("Stopping RustFlix server");
 
-        // Services don't have stop methods - they're cleaned up automatically
+        self.media_library.stop().await?;
+        self.streaming.stop().await?;
         info!("All services stopped successfully");
 
         info!("RustFlix server stopped");
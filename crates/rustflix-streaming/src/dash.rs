@@ -1,21 +1,55 @@
 //! DASH (Dynamic Adaptive Streaming over HTTP) generation
+//!
+//! Manifests are static MPDs of the ISO base media file format live
+//! profile: one adaptation set for video, one per audio language and one
+//! per text subtitle track. Video and audio representations share a
+//! `SegmentTemplate` whose `SegmentTimeline` holds the same cuts as the HLS
+//! playlists, keyframe-aligned for video and even for audio, so both
+//! reference the same fMP4 segments, written once by the `Segmenter`.
 
-use rustflix_core::{Result, RustFlixError};
+use crate::renditions::{audio_renditions, find_profile, init_segment_name, renditions};
+use crate::segmenter::Segmenter;
+use crate::timeline::{even_segments, probe_timeline, MediaSegment, MediaTimeline};
+use rustflix_core::config::{QualityProfile, StreamingConfig};
+use rustflix_core::streaming::{DashManifest, DashRepresentation};
+use rustflix_core::{Result, RustFlixError, SegmentFormat, StreamId};
+use std::fmt::Write;
 use std::path::Path;
-use tracing::{info, debug};
+use tracing::{debug, info};
+
+/// File name of the manifest in an output directory
+pub const MANIFEST: &str = "manifest.mpd";
+
+/// Units per second of segment times
+const TIMESCALE: f64 = 1000.0;
+
+/// Subtitle codecs that convert to WebVTT
+const TEXT_SUBTITLE_CODECS: &[&str] = &["subrip", "ass", "ssa", "webvtt", "mov_text"];
+
+/// Declared bandwidth of a WebVTT subtitle track
+const TEXT_BANDWIDTH: u64 = 256;
+
+/// `schemeIdUri` of the audio channel count
+const AUDIO_CHANNEL_SCHEME: &str = "urn:mpeg:dash:23003:3:audio_channel_configuration:2011";
 
 /// DASH manifest and segment generator
 #[derive(Debug, Clone)]
 pub struct DashGenerator {
     segment_duration: f64,
+    profiles: Vec<QualityProfile>,
     adaptation_sets: Vec<AdaptationSet>,
+    segmenter: Segmenter,
 }
 
 /// DASH adaptation set configuration
 #[derive(Debug, Clone)]
 pub struct AdaptationSet {
     pub id: u32,
+    /// `video`, `audio` or `text`
     pub content_type: String,
+    pub mime_type: String,
+    /// ISO 639 language code
+    pub language: Option<String>,
     pub representations: Vec<Representation>,
 }
 
@@ -26,79 +60,685 @@ pub struct Representation {
     pub bandwidth: u64,
     pub width: Option<u32>,
     pub height: Option<u32>,
+    pub frame_rate: Option<f64>,
+    /// Codec string, empty for text tracks
     pub codec: String,
+    pub audio_channels: Option<u8>,
+    pub audio_sampling_rate: Option<u32>,
+    /// URL of a representation served as a single file; representations
+    /// without one are segmented
+    pub base_url: Option<String>,
+}
+
+impl AdaptationSet {
+    /// Check whether the representations of this set are segmented
+    fn is_segmented(&self) -> bool {
+        self.representations.iter().any(|representation| representation.base_url.is_none())
+    }
 }
 
 impl DashGenerator {
-    /// Create a new DASH generator
+    /// Create a new DASH generator for the default quality profiles
     pub fn new(segment_duration: f64) -> Self {
         Self {
             segment_duration,
+            profiles: StreamingConfig::default().quality_profiles,
             adaptation_sets: Vec::new(),
+            segmenter: Segmenter::new(segment_duration),
         }
     }
 
+    /// Create a generator with the segment settings and quality profiles of
+    /// a streaming configuration
+    pub fn from_config(config: &StreamingConfig) -> Self {
+        Self {
+            segment_duration: config.segment_duration,
+            profiles: config.quality_profiles.clone(),
+            adaptation_sets: Vec::new(),
+            segmenter: Segmenter::from_config(config),
+        }
+    }
+
+    /// Write segments with another segmenter
+    pub fn with_segmenter(mut self, segmenter: Segmenter) -> Self {
+        self.segmenter = segmenter;
+        self
+    }
+
+    /// Target segment duration in seconds
+    pub fn segment_duration(&self) -> f64 {
+        self.segment_duration
+    }
+
     /// Add adaptation set
+    ///
+    /// Generators with adaptation sets use them instead of deriving them
+    /// from the probed media.
     pub fn add_adaptation_set(&mut self, adaptation_set: AdaptationSet) {
         self.adaptation_sets.push(adaptation_set);
     }
 
     /// Generate DASH manifest (MPD)
+    ///
+    /// The manifest is written to `manifest.mpd` in `output_dir` and returned.
     pub async fn generate_manifest(&self, media_path: &Path, output_dir: &Path) -> Result<String> {
-        info!("Generating DASH manifest for: {}", media_path.display());
-        
-        let manifest = format!(
-            r#"<?xml version="1.0" encoding="UTF-8"?>
-<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT3600S" minBufferTime="PT{:.1}S">
-  <Period>
-    <!-- Adaptation sets will be added here -->
-  </Period>
-</MPD>"#,
-            self.segment_duration
-        );
-        
-        debug!("DASH manifest generated");
+        let (manifest, _) = self.write_manifest(media_path, output_dir).await?;
         Ok(manifest)
     }
 
+    /// Generate the manifest of a media file and describe it
+    pub async fn generate_stream_manifest(
+        &self,
+        stream_id: StreamId,
+        media_path: &Path,
+        output_dir: &Path,
+    ) -> Result<DashManifest> {
+        let (_, adaptation_sets) = self.write_manifest(media_path, output_dir).await?;
+        let representations = |content_type: &str| {
+            adaptation_sets
+                .iter()
+                .filter(|set| set.content_type == content_type)
+                .flat_map(|set| &set.representations)
+                .map(dash_representation)
+                .collect::<Vec<_>>()
+        };
+
+        Ok(DashManifest {
+            stream_id,
+            manifest_url: MANIFEST.to_string(),
+            video_representations: representations("video"),
+            audio_representations: representations("audio"),
+            segment_duration: self.segment_duration,
+        })
+    }
+
     /// Generate DASH segments
+    ///
+    /// Video and audio representations are encoded into `output_dir` as
+    /// the renditions of the quality profiles they are named after; ones
+    /// already segmented for HLS are reused. Text representations are
+    /// converted to WebVTT. Returns the names of the files the manifest
+    /// references, set by set.
     pub async fn generate_segments(&self, media_path: &Path, output_dir: &Path) -> Result<Vec<String>> {
         info!("Generating DASH segments for: {}", media_path.display());
-        
-        // Placeholder implementation
-        let segments = vec![
-            "init.mp4".to_string(),
-            "segment_1.m4s".to_string(),
-            "segment_2.m4s".to_string(),
-        ];
-        
-        debug!("Generated {} DASH segments", segments.len());
-        Ok(segments)
+
+        let timeline = probe_timeline(media_path).await?;
+        let video = renditions(&self.profiles, timeline.video.as_ref(), false)?;
+        let audio = audio_renditions(&self.profiles, &timeline.audio)?;
+        let mut written = Vec::new();
+        for set in self.adaptation_sets(&timeline)? {
+            for representation in &set.representations {
+                let missing = || RustFlixError::config(format!("No rendition {} to segment", representation.id));
+                match set.content_type.as_str() {
+                    "video" => {
+                        let rendition = video
+                            .iter()
+                            .find(|rendition| rendition.name == representation.id)
+                            .ok_or_else(missing)?;
+                        let profile = find_profile(&self.profiles, &rendition.name)?;
+                        written.extend(
+                            self.segmenter
+                                .segment_rendition(media_path, output_dir, &timeline, rendition, profile)
+                                .await?,
+                        );
+                    }
+                    "audio" => {
+                        let rendition = audio
+                            .iter()
+                            .find(|rendition| rendition.name == representation.id)
+                            .ok_or_else(missing)?;
+                        written.extend(
+                            self.segmenter
+                                .segment_audio(media_path, output_dir, &timeline, rendition, &self.profiles[0])
+                                .await?,
+                        );
+                    }
+                    "text" => {
+                        let track = timeline
+                            .subtitles
+                            .iter()
+                            .position(|subtitle| format!("subtitle_{}", subtitle.index) == representation.id)
+                            .ok_or_else(missing)?;
+                        let file_name = representation.base_url.clone().ok_or_else(missing)?;
+                        written.push(
+                            self.segmenter
+                                .extract_subtitle(media_path, output_dir, track, &file_name)
+                                .await?,
+                        );
+                    }
+                    _ => written.extend(representation.base_url.clone()),
+                }
+            }
+        }
+
+        debug!("Generated {} DASH segments", written.len());
+        Ok(written)
+    }
+
+    /// Adaptation sets of a media file
+    ///
+    /// Without configured sets, video renditions of the quality profiles
+    /// form one set, each audio language another, and each text subtitle
+    /// track a WebVTT set.
+    pub fn adaptation_sets(&self, timeline: &MediaTimeline) -> Result<Vec<AdaptationSet>> {
+        if !self.adaptation_sets.is_empty() {
+            return Ok(self.adaptation_sets.clone());
+        }
+
+        let mut sets = Vec::new();
+        let video = renditions(&self.profiles, timeline.video.as_ref(), false)?
+            .into_iter()
+            .filter(|rendition| rendition.video_codec.is_some())
+            .collect::<Vec<_>>();
+        if let Some(rendition) = video.iter().find(|rendition| rendition.segment_format != SegmentFormat::Fmp4) {
+            return Err(RustFlixError::config(format!(
+                "DASH needs fMP4 segments, quality profile {} uses {}",
+                rendition.name,
+                rendition.segment_format.as_str()
+            )));
+        }
+        if !video.is_empty() {
+            sets.push(AdaptationSet {
+                id: 0,
+                content_type: "video".to_string(),
+                mime_type: SegmentFormat::Fmp4.mime_type().to_string(),
+                language: None,
+                representations: video
+                    .into_iter()
+                    .map(|rendition| Representation {
+                        id: rendition.name,
+                        bandwidth: rendition.video_bitrate,
+                        width: rendition.resolution.map(|(width, _)| width),
+                        height: rendition.resolution.map(|(_, height)| height),
+                        frame_rate: rendition.frame_rate,
+                        codec: rendition.video_codec.unwrap_or_default(),
+                        audio_channels: None,
+                        audio_sampling_rate: None,
+                        base_url: None,
+                    })
+                    .collect(),
+            });
+        }
+
+        for rendition in audio_renditions(&self.profiles, &timeline.audio)? {
+            sets.push(AdaptationSet {
+                id: sets.len() as u32,
+                content_type: "audio".to_string(),
+                mime_type: "audio/mp4".to_string(),
+                language: rendition.language,
+                representations: vec![Representation {
+                    id: rendition.name,
+                    bandwidth: rendition.bitrate,
+                    width: None,
+                    height: None,
+                    frame_rate: None,
+                    codec: rendition.codec,
+                    audio_channels: Some(rendition.channels),
+                    audio_sampling_rate: Some(rendition.sample_rate),
+                    base_url: None,
+                }],
+            });
+        }
+
+        for subtitle in &timeline.subtitles {
+            if !TEXT_SUBTITLE_CODECS.contains(&subtitle.codec.as_str()) {
+                continue;
+            }
+            let id = format!("subtitle_{}", subtitle.index);
+            sets.push(AdaptationSet {
+                id: sets.len() as u32,
+                content_type: "text".to_string(),
+                mime_type: "text/vtt".to_string(),
+                language: subtitle.language.clone(),
+                representations: vec![Representation {
+                    base_url: Some(format!("{}.vtt", id)),
+                    id,
+                    bandwidth: TEXT_BANDWIDTH,
+                    width: None,
+                    height: None,
+                    frame_rate: None,
+                    codec: String::new(),
+                    audio_channels: None,
+                    audio_sampling_rate: None,
+                }],
+            });
+        }
+        Ok(sets)
+    }
+
+    async fn write_manifest(&self, media_path: &Path, output_dir: &Path) -> Result<(String, Vec<AdaptationSet>)> {
+        info!("Generating DASH manifest for: {}", media_path.display());
+
+        let timeline = probe_timeline(media_path).await?;
+        let segments = timeline.segments(self.segment_duration);
+        let adaptation_sets = self.adaptation_sets(&timeline)?;
+        if adaptation_sets.is_empty() {
+            return Err(RustFlixError::media_processing(format!(
+                "No streams to package in {}",
+                media_path.display()
+            )));
+        }
+        let manifest = self.manifest(timeline.duration, &segments, &adaptation_sets);
+
+        tokio::fs::create_dir_all(output_dir).await?;
+        tokio::fs::write(output_dir.join(MANIFEST), &manifest).await?;
+
+        debug!("DASH manifest generated with {} adaptation sets", adaptation_sets.len());
+        Ok((manifest, adaptation_sets))
+    }
+
+    /// Render a static MPD
+    ///
+    /// Video sets follow the segments given; audio sets are cut evenly.
+    pub fn manifest(&self, duration: f64, segments: &[MediaSegment], adaptation_sets: &[AdaptationSet]) -> String {
+        let audio_segments = even_segments(duration, self.segment_duration);
+        let max_segment_duration = segments
+            .iter()
+            .chain(&audio_segments)
+            .map(|segment| segment.duration)
+            .fold(0.0, f64::max);
+
+        let mut mpd = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            mpd,
+            "<MPD xmlns=\"urn:mpeg:dash:schema:mpd:2011\" profiles=\"urn:mpeg:dash:profile:isoff-live:2011\" \
+             type=\"static\" mediaPresentationDuration=\"{}\" maxSegmentDuration=\"{}\" minBufferTime=\"{}\">",
+            iso_duration(duration),
+            iso_duration(max_segment_duration),
+            iso_duration(self.segment_duration)
+        );
+        mpd.push_str("  <Period id=\"0\" start=\"PT0S\">\n");
+        let mut main_audio = true;
+        for set in adaptation_sets {
+            let role = match set.content_type.as_str() {
+                "audio" if std::mem::take(&mut main_audio) => Some("main"),
+                "audio" => Some("alternate"),
+                "text" => Some("subtitle"),
+                _ => None,
+            };
+            let segments = if set.content_type == "audio" { &audio_segments } else { segments };
+            adaptation_set(&mut mpd, set, role, segments);
+        }
+        mpd.push_str("  </Period>\n</MPD>\n");
+        mpd
+    }
+}
+
+/// Render an adaptation set, with a `Role` of the DASH role scheme if given
+fn adaptation_set(mpd: &mut String, set: &AdaptationSet, role: Option<&str>, segments: &[MediaSegment]) {
+    let _ = write!(
+        mpd,
+        "    <AdaptationSet id=\"{}\" contentType=\"{}\" mimeType=\"{}\"",
+        set.id,
+        escape(&set.content_type),
+        escape(&set.mime_type)
+    );
+    if let Some(language) = &set.language {
+        let _ = write!(mpd, " lang=\"{}\"", escape(language));
+    }
+    if set.is_segmented() {
+        mpd.push_str(" segmentAlignment=\"true\" startWithSAP=\"1\"");
+    }
+    mpd.push_str(">\n");
+
+    if let Some(role) = role {
+        let _ = writeln!(mpd, "      <Role schemeIdUri=\"urn:mpeg:dash:role:2011\" value=\"{}\"/>", role);
+    }
+
+    if set.is_segmented() {
+        mpd.push_str(
+            "      <SegmentTemplate timescale=\"1000\" startNumber=\"0\" \
+             initialization=\"$RepresentationID$_init.mp4\" media=\"$RepresentationID$_$Number%04d$.m4s\">\n",
+        );
+        mpd.push_str("        <SegmentTimeline>\n");
+        for (index, (start, duration, repeat)) in timeline_runs(segments).into_iter().enumerate() {
+            mpd.push_str("          <S");
+            if index == 0 {
+                let _ = write!(mpd, " t=\"{}\"", start);
+            }
+            let _ = write!(mpd, " d=\"{}\"", duration);
+            if repeat > 0 {
+                let _ = write!(mpd, " r=\"{}\"", repeat);
+            }
+            mpd.push_str("/>\n");
+        }
+        mpd.push_str("        </SegmentTimeline>\n      </SegmentTemplate>\n");
+    }
+
+    for representation in &set.representations {
+        let _ = write!(
+            mpd,
+            "      <Representation id=\"{}\" bandwidth=\"{}\"",
+            escape(&representation.id),
+            representation.bandwidth
+        );
+        if !representation.codec.is_empty() {
+            let _ = write!(mpd, " codecs=\"{}\"", escape(&representation.codec));
+        }
+        if let (Some(width), Some(height)) = (representation.width, representation.height) {
+            let _ = write!(mpd, " width=\"{}\" height=\"{}\" sar=\"1:1\"", width, height);
+        }
+        if let Some(frame_rate) = representation.frame_rate {
+            let _ = write!(mpd, " frameRate=\"{}\"", frame_rate_value(frame_rate));
+        }
+        if let Some(sampling_rate) = representation.audio_sampling_rate {
+            let _ = write!(mpd, " audioSamplingRate=\"{}\"", sampling_rate);
+        }
+
+        if representation.audio_channels.is_none() && representation.base_url.is_none() {
+            mpd.push_str("/>\n");
+            continue;
+        }
+        mpd.push_str(">\n");
+        if let Some(channels) = representation.audio_channels {
+            let _ = writeln!(
+                mpd,
+                "        <AudioChannelConfiguration schemeIdUri=\"{}\" value=\"{}\"/>",
+                AUDIO_CHANNEL_SCHEME, channels
+            );
+        }
+        if let Some(base_url) = &representation.base_url {
+            let _ = writeln!(mpd, "        <BaseURL>{}</BaseURL>", escape(base_url));
+        }
+        mpd.push_str("      </Representation>\n");
+    }
+    mpd.push_str("    </AdaptationSet>\n");
+}
+
+/// `SegmentTimeline` entries of segments as (start, duration, repeat) in
+/// timescale units, with equal consecutive durations run together
+fn timeline_runs(segments: &[MediaSegment]) -> Vec<(u64, u64, u32)> {
+    let mut runs: Vec<(u64, u64, u32)> = Vec::new();
+    for segment in segments {
+        let start = (segment.start * TIMESCALE).round() as u64;
+        let end = ((segment.start + segment.duration) * TIMESCALE).round() as u64;
+        let duration = end.saturating_sub(start);
+        match runs.last_mut() {
+            Some((_, last, repeat)) if *last == duration => *repeat += 1,
+            _ => runs.push((start, duration, 0)),
+        }
+    }
+    runs
+}
+
+/// `xs:duration` of seconds, to the millisecond
+pub fn iso_duration(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let (hours, minutes) = (millis / 3_600_000, millis / 60_000 % 60);
+    let (whole, fraction) = (millis / 1000 % 60, millis % 1000);
+
+    let mut duration = String::from("PT");
+    if hours > 0 {
+        let _ = write!(duration, "{}H", hours);
+    }
+    if minutes > 0 {
+        let _ = write!(duration, "{}M", minutes);
+    }
+    if fraction > 0 {
+        let fraction = format!("{:03}", fraction);
+        let _ = write!(duration, "{}.{}S", whole, fraction.trim_end_matches('0'));
+    } else if whole > 0 || duration.len() == 2 {
+        let _ = write!(duration, "{}S", whole);
+    }
+    duration
+}
+
+/// `frameRate` of a frame rate: whole rates as integers, NTSC rates such as
+/// 23.976 as `24000/1001`
+fn frame_rate_value(frame_rate: f64) -> String {
+    if (frame_rate - frame_rate.round()).abs() < 0.001 {
+        return format!("{}", frame_rate.round() as u64);
+    }
+    let ntsc = (frame_rate * 1.001).round();
+    if (ntsc * 1000.0 / 1001.0 - frame_rate).abs() < 0.01 {
+        return format!("{}/1001", ntsc as u64 * 1000);
     }
+    format!("{}/1000", (frame_rate * 1000.0).round() as u64)
+}
+
+fn dash_representation(representation: &Representation) -> DashRepresentation {
+    DashRepresentation {
+        id: representation.id.clone(),
+        bandwidth: representation.bandwidth,
+        width: representation.width,
+        height: representation.height,
+        frame_rate: representation.frame_rate,
+        codecs: representation.codec.clone(),
+        initialization_url: init_segment_name(&representation.id),
+        media_template: format!("{}_$Number%04d$.m4s", representation.id),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hls::HlsGenerator;
+    use crate::segmenter::tests::FAKE_SEGMENTER;
+    use crate::timeline::tests::sample_dir;
+    use crate::transcoder::tests::fake_ffmpeg;
+    use roxmltree::Document;
+    use std::io::ErrorKind;
+    use std::process::Command;
     use tempfile::TempDir;
 
+    /// Validate a manifest against the vendored MPD schema with xmllint,
+    /// skipped where xmllint is not installed
+    fn validate(manifest: &str) -> bool {
+        let schema = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema/DASH-MPD.xsd");
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(MANIFEST);
+        std::fs::write(&path, manifest).unwrap();
+
+        let output = match Command::new("xmllint").arg("--noout").arg("--schema").arg(&schema).arg(&path).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                eprintln!("xmllint not found, skipping MPD schema validation");
+                return true;
+            }
+            Err(e) => panic!("xmllint: {}", e),
+        };
+        if !output.status.success() {
+            eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        }
+        output.status.success()
+    }
+
+    #[tokio::test]
+    async fn test_schema_validation() {
+        let (temp_dir, media_path) = sample_dir();
+        let manifest = DashGenerator::new(4.0).generate_manifest(&media_path, temp_dir.path()).await.unwrap();
+        assert!(validate(&manifest));
+
+        if Command::new("xmllint").arg("--version").output().is_ok() {
+            assert!(!validate(&manifest.replacen(" minBufferTime=", " minimumBufferTime=", 1)));
+            assert!(!validate(&manifest.replacen("frameRate=\"24000/1001\"", "frameRate=\"23.976\"", 1)));
+            assert!(!validate(&manifest.replacen(" bandwidth=", " rate=", 1)));
+        }
+    }
+
+    #[test]
+    fn test_iso_duration() {
+        assert_eq!(iso_duration(0.0), "PT0S");
+        assert_eq!(iso_duration(20.0), "PT20S");
+        assert_eq!(iso_duration(3725.5), "PT1H2M5.5S");
+        assert_eq!(iso_duration(3600.0), "PT1H");
+        assert_eq!(iso_duration(61.042), "PT1M1.042S");
+        assert_eq!(frame_rate_value(23.976), "24000/1001");
+        assert_eq!(frame_rate_value(59.94), "60000/1001");
+        assert_eq!(frame_rate_value(25.0), "25");
+        assert_eq!(frame_rate_value(12.5), "12500/1000");
+    }
+
     #[tokio::test]
     async fn test_dash_generator_creation() {
         let generator = DashGenerator::new(4.0);
         assert_eq!(generator.segment_duration, 4.0);
+        assert_eq!(generator.profiles.len(), 2);
     }
 
     #[tokio::test]
     async fn test_generate_manifest() {
         let generator = DashGenerator::new(4.0);
-        let temp_dir = TempDir::new().unwrap();
-        let media_path = temp_dir.path().join("test.mp4");
-        
+        let (temp_dir, media_path) = sample_dir();
+
         let result = generator.generate_manifest(&media_path, temp_dir.path()).await;
         assert!(result.is_ok());
-        
+
         let manifest = result.unwrap();
         assert!(manifest.contains("<?xml version=\"1.0\""));
         assert!(manifest.contains("MPD"));
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join(MANIFEST)).unwrap(), manifest);
+
+        let document = Document::parse(&manifest).unwrap();
+        let mpd = document.root_element();
+        assert_eq!(mpd.tag_name().namespace(), Some("urn:mpeg:dash:schema:mpd:2011"));
+        assert!(validate(&manifest));
+        assert_eq!(mpd.attribute("type"), Some("static"));
+        assert_eq!(mpd.attribute("mediaPresentationDuration"), Some("PT20S"));
+        assert_eq!(mpd.attribute("maxSegmentDuration"), Some("PT4S"));
+
+        let sets = mpd.descendants().filter(|node| node.has_tag_name("AdaptationSet")).collect::<Vec<_>>();
+        let kinds = sets
+            .iter()
+            .map(|set| (set.attribute("contentType").unwrap(), set.attribute("lang")))
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, [("video", None), ("audio", Some("eng")), ("audio", Some("ger")), ("text", Some("fre"))]);
+
+        let video = sets[0].children().filter(|node| node.has_tag_name("Representation")).collect::<Vec<_>>();
+        assert_eq!(video.len(), 2);
+        assert_eq!(video[0].attribute("id"), Some("1080p"));
+        assert_eq!(video[0].attribute("bandwidth"), Some("8000000"));
+        assert_eq!(video[0].attribute("codecs"), Some("avc1.640028"));
+        assert_eq!(video[0].attribute("width"), Some("1920"));
+        assert_eq!(video[0].attribute("frameRate"), Some("24000/1001"));
+        assert_eq!(video[1].attribute("height"), Some("720"));
+
+        let timeline = sets[0].descendants().filter(|node| node.has_tag_name("S")).collect::<Vec<_>>();
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline[0].attribute("t"), Some("0"));
+        assert_eq!(timeline[0].attribute("d"), Some("4000"));
+        assert_eq!(timeline[0].attribute("r"), Some("4"));
+
+        let roles = sets.iter().map(|set| {
+            set.children().find(|node| node.has_tag_name("Role")).and_then(|role| role.attribute("value"))
+        });
+        assert_eq!(roles.collect::<Vec<_>>(), [None, Some("main"), Some("alternate"), Some("subtitle")]);
+
+        let english = sets[1].children().find(|node| node.has_tag_name("Representation")).unwrap();
+        assert_eq!(english.attribute("id"), Some("audio_eng"));
+        assert_eq!(english.attribute("codecs"), Some("mp4a.40.2"));
+        assert_eq!(english.attribute("audioSamplingRate"), Some("48000"));
+        let channels = english.children().find(|node| node.has_tag_name("AudioChannelConfiguration")).unwrap();
+        assert_eq!(channels.attribute("value"), Some("2"));
+
+        assert_eq!(sets[3].attribute("mimeType"), Some("text/vtt"));
+        assert!(sets[3].descendants().all(|node| !node.has_tag_name("SegmentTemplate")));
+        let base_url = sets[3].descendants().find(|node| node.has_tag_name("BaseURL")).unwrap();
+        assert!(base_url.text().unwrap().ends_with(".vtt"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_manifest() {
+        let generator = DashGenerator::from_config(&StreamingConfig::default());
+        let (temp_dir, media_path) = sample_dir();
+
+        let manifest = generator
+            .generate_stream_manifest(StreamId::new_v4(), &media_path, temp_dir.path())
+            .await
+            .unwrap();
+        assert_eq!(manifest.manifest_url, MANIFEST);
+        assert_eq!(manifest.video_representations.len(), 2);
+        assert_eq!(manifest.video_representations[1].initialization_url, "720p_init.mp4");
+        assert_eq!(manifest.video_representations[1].media_template, "720p_$Number%04d$.m4s");
+        let languages = manifest.audio_representations.iter().map(|r| r.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(languages, ["audio_eng", "audio_ger"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_generate_segments() {
+        let (temp_dir, media_path) = sample_dir();
+        let segmenter = Segmenter::new(4.0).with_ffmpeg_path(fake_ffmpeg(temp_dir.path(), FAKE_SEGMENTER));
+        let generator = DashGenerator::new(4.0).with_segmenter(segmenter);
+        let output_dir = temp_dir.path().join("dash");
+
+        let segments = generator.generate_segments(&media_path, &output_dir).await.unwrap();
+        assert_eq!(segments.len(), 4 * 6 + 1);
+        assert_eq!(segments[0], "1080p_init.mp4");
+        assert_eq!(segments[5], "1080p_0004.m4s");
+        assert!(segments.contains(&"audio_ger_0004.m4s".to_string()));
+        assert_eq!(segments[24], "subtitle_4.vtt");
+        for segment in &segments {
+            assert!(output_dir.join(segment).exists(), "{} not written", segment);
+        }
+        let log = std::fs::read_to_string(output_dir.join("ffmpeg.log")).unwrap();
+        assert!(log.contains("-map 0:s:0 -c:s webvtt -f webvtt"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_segments_shared_with_hls() {
+        let (temp_dir, media_path) = sample_dir();
+        let segmenter = Segmenter::new(6.0).with_ffmpeg_path(fake_ffmpeg(temp_dir.path(), FAKE_SEGMENTER));
+        let profiles = StreamingConfig::default().quality_profiles;
+        let output_dir = temp_dir.path().join("stream");
+
+        let hls = HlsGenerator::new(6.0, 5).with_segmenter(segmenter.clone());
+        let written = hls.generate_segments(&media_path, &output_dir, &profiles).await.unwrap();
+        let runs = std::fs::read_to_string(output_dir.join("ffmpeg.log")).unwrap().lines().count();
+        assert_eq!(runs, 4);
+
+        let dash = DashGenerator::new(6.0).with_segmenter(segmenter);
+        let segments = dash.generate_segments(&media_path, &output_dir).await.unwrap();
+        // Only the subtitle is converted again
+        assert_eq!(std::fs::read_to_string(output_dir.join("ffmpeg.log")).unwrap().lines().count(), runs + 1);
+        for segment in segments.iter().filter(|segment| !segment.ends_with(".vtt")) {
+            assert!(written.contains(segment), "{} not shared", segment);
+        }
+
+        // The audio timeline of the manifest matches the shared audio segments
+        let manifest = dash.generate_manifest(&media_path, &output_dir).await.unwrap();
+        assert!(manifest.contains("<S t=\"0\" d=\"6000\" r=\"2\"/>\n          <S d=\"2000\"/>"));
+    }
+
+    #[tokio::test]
+    async fn test_configured_adaptation_sets() {
+        let mut generator = DashGenerator::new(6.0);
+        generator.add_adaptation_set(AdaptationSet {
+            id: 7,
+            content_type: "video".to_string(),
+            mime_type: "video/mp4".to_string(),
+            language: None,
+            representations: vec![Representation {
+                id: "main".to_string(),
+                bandwidth: 2_000_000,
+                width: Some(640),
+                height: Some(360),
+                frame_rate: Some(25.0),
+                codec: "avc1.64001e".to_string(),
+                audio_channels: None,
+                audio_sampling_rate: None,
+                base_url: None,
+            }],
+        });
+        let (temp_dir, media_path) = sample_dir();
+
+        let manifest = generator.generate_manifest(&media_path, temp_dir.path()).await.unwrap();
+        assert!(validate(&manifest));
+        assert_eq!(manifest.matches("<AdaptationSet ").count(), 1);
+        assert!(manifest.contains("<AdaptationSet id=\"7\""));
+        assert!(manifest.contains("frameRate=\"25\""));
+        assert!(manifest.contains("<S t=\"0\" d=\"6000\" r=\"2\"/>\n          <S d=\"2000\"/>"));
+
+        let missing = TempDir::new().unwrap();
+        assert!(generator.generate_manifest(&missing.path().join("missing.mp4"), missing.path()).await.is_err());
     }
 }
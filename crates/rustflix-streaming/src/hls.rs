@@ -1,55 +1,307 @@
 //! HLS (HTTP Live Streaming) generation
+//!
+//! Media playlists are VOD playlists whose segments start on keyframes:
+//! each segment ends at the first keyframe at or after the next multiple of
+//! the target segment duration. Master playlists list one variant per
+//! rendition of the configured quality profiles.
+//!
+//! Segments are MPEG-TS or fragmented MP4 (CMAF); fMP4 playlists name their
+//! init segment with `EXT-X-MAP` and need protocol version 7. fMP4 variants
+//! carry video only and share an `EXT-X-MEDIA` audio group with one
+//! playlist per language, whose segments are cut evenly.
 
-use rustflix_core::{Result, RustFlixError};
+use crate::renditions::{
+    audio_renditions, find_profile, init_segment_name, renditions, segment_name, AudioRendition, Rendition,
+};
+use crate::segmenter::Segmenter;
+use crate::timeline::{probe_timeline, MediaSegment, MediaTimeline};
+use rustflix_core::config::{QualityProfile, StreamingConfig};
+use rustflix_core::streaming::{HlsPlaylist, HlsVariant};
+use rustflix_core::{Result, RustFlixError, SegmentFormat, StreamId};
+use std::fmt::Write;
 use std::path::Path;
-use tracing::{info, debug};
+use tracing::{debug, info};
+
+/// File name of the master playlist in an output directory
+pub const MASTER_PLAYLIST: &str = "master.m3u8";
+
+/// File name of the media playlist written by `generate_playlist`
+pub const MEDIA_PLAYLIST: &str = "playlist.m3u8";
+
+/// Segment file name prefix of the media playlist written by `generate_playlist`
+const SEGMENT_PREFIX: &str = "segment";
+
+/// `GROUP-ID` of the audio renditions of demuxed variants
+const AUDIO_GROUP: &str = "audio";
 
 /// HLS playlist and segment generator
 #[derive(Debug, Clone)]
 pub struct HlsGenerator {
     segment_duration: f64,
     segment_count: u32,
+    segment_format: SegmentFormat,
+    segmenter: Segmenter,
 }
 
 impl HlsGenerator {
-    /// Create a new HLS generator
+    /// Create a new HLS generator writing fMP4 segments
     pub fn new(segment_duration: f64, segment_count: u32) -> Self {
         Self {
             segment_duration,
             segment_count,
+            segment_format: SegmentFormat::Fmp4,
+            segmenter: Segmenter::new(segment_duration),
+        }
+    }
+
+    /// Use another segment format for `generate_playlist`
+    ///
+    /// Variants of a master playlist use the container of their quality profile.
+    pub fn with_segment_format(mut self, segment_format: SegmentFormat) -> Self {
+        self.segment_format = segment_format;
+        self
+    }
+
+    /// Create a generator with the segment settings of a streaming configuration
+    pub fn from_config(config: &StreamingConfig) -> Self {
+        Self {
+            segmenter: Segmenter::from_config(config),
+            ..Self::new(config.segment_duration, config.segment_count)
         }
     }
 
-    /// Generate HLS playlist for media file
+    /// Write segments with another segmenter
+    pub fn with_segmenter(mut self, segmenter: Segmenter) -> Self {
+        self.segmenter = segmenter;
+        self
+    }
+
+    /// Target segment duration in seconds
+    pub fn segment_duration(&self) -> f64 {
+        self.segment_duration
+    }
+
+    /// Segments a stream buffers ahead of the playback position
+    pub fn segment_count(&self) -> u32 {
+        self.segment_count
+    }
+
+    /// Generate the media playlist of a media file
+    ///
+    /// The playlist is written to `playlist.m3u8` in `output_dir` and returned.
     pub async fn generate_playlist(&self, media_path: &Path, output_dir: &Path) -> Result<String> {
         info!("Generating HLS playlist for: {}", media_path.display());
-        
-        let playlist_content = format!(
-            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n",
-            self.segment_duration as u32
-        );
-        
-        debug!("HLS playlist generated");
-        Ok(playlist_content)
+
+        let timeline = probe_timeline(media_path).await?;
+        let segments = timeline.segments(self.segment_duration);
+        let playlist = self.media_playlist(&segments, SEGMENT_PREFIX, self.segment_format);
+
+        tokio::fs::create_dir_all(output_dir).await?;
+        tokio::fs::write(output_dir.join(MEDIA_PLAYLIST), &playlist).await?;
+
+        debug!("HLS playlist generated with {} segments", segments.len());
+        Ok(playlist)
     }
 
-    /// Generate video segments
-    pub async fn generate_segments(&self, media_path: &Path, output_dir: &Path) -> Result<Vec<String>> {
+    /// Encode the segments of the variants and audio renditions of the
+    /// master playlist of a media file into `output_dir`
+    ///
+    /// Returns the names of the files written, rendition by rendition.
+    pub async fn generate_segments(
+        &self,
+        media_path: &Path,
+        output_dir: &Path,
+        profiles: &[QualityProfile],
+    ) -> Result<Vec<String>> {
         info!("Generating HLS segments for: {}", media_path.display());
-        
-        // Placeholder implementation
-        let segments = (0..self.segment_count)
-            .map(|i| format!("segment_{:04}.ts", i))
-            .collect();
-        
-        debug!("Generated {} HLS segments", self.segment_count);
-        Ok(segments)
+
+        let timeline = probe_timeline(media_path).await?;
+        let (renditions, audio) = master_renditions(&timeline, profiles)?;
+        let mut written = Vec::new();
+        for rendition in &renditions {
+            let profile = find_profile(profiles, &rendition.name)?;
+            written.extend(
+                self.segmenter
+                    .segment_rendition(media_path, output_dir, &timeline, rendition, profile)
+                    .await?,
+            );
+        }
+        for rendition in &audio {
+            written.extend(
+                self.segmenter
+                    .segment_audio(media_path, output_dir, &timeline, rendition, &profiles[0])
+                    .await?,
+            );
+        }
+
+        debug!("Generated {} HLS segments", written.len());
+        Ok(written)
+    }
+
+    /// Generate the master playlist of a media file and the media playlist
+    /// of each of its variants and audio renditions
+    ///
+    /// `master.m3u8`, one `<profile>.m3u8` per variant and, for demuxed
+    /// variants, one `audio_<language>.m3u8` per audio language are written
+    /// to `output_dir`. Segments are named after their rendition, as
+    /// `Rendition::segment_name` gives them.
+    pub async fn generate_master_playlist(
+        &self,
+        stream_id: StreamId,
+        media_path: &Path,
+        output_dir: &Path,
+        profiles: &[QualityProfile],
+    ) -> Result<HlsPlaylist> {
+        info!("Generating HLS master playlist for: {}", media_path.display());
+
+        let timeline = probe_timeline(media_path).await?;
+        let segments = timeline.segments(self.segment_duration);
+        let (renditions, audio) = master_renditions(&timeline, profiles)?;
+
+        tokio::fs::create_dir_all(output_dir).await?;
+        let audio_segments = timeline.audio_segments(self.segment_duration);
+        for rendition in &renditions {
+            // Audio-only sources have no keyframes to cut at
+            let segments = if rendition.video_codec.is_some() { &segments } else { &audio_segments };
+            let media_playlist = self.media_playlist(segments, &rendition.name, rendition.segment_format);
+            tokio::fs::write(output_dir.join(playlist_name(&rendition.name)), media_playlist).await?;
+        }
+        for rendition in &audio {
+            let media_playlist = self.media_playlist(&audio_segments, &rendition.name, SegmentFormat::Fmp4);
+            tokio::fs::write(output_dir.join(playlist_name(&rendition.name)), media_playlist).await?;
+        }
+
+        let playlist = HlsPlaylist {
+            stream_id,
+            master_playlist_url: MASTER_PLAYLIST.to_string(),
+            variant_playlists: renditions.iter().map(variant).collect(),
+            segment_duration: self.segment_duration,
+            total_segments: Some(segments.len() as u32),
+        };
+        tokio::fs::write(output_dir.join(MASTER_PLAYLIST), master_playlist(&renditions, &audio)).await?;
+
+        debug!(
+            "HLS master playlist generated with {} variants and {} audio renditions",
+            playlist.variant_playlists.len(),
+            audio.len()
+        );
+        Ok(playlist)
+    }
+
+    /// Render a VOD media playlist whose segments are named with a prefix
+    pub fn media_playlist(&self, segments: &[MediaSegment], prefix: &str, format: SegmentFormat) -> String {
+        let mut playlist = String::from("#EXTM3U\n");
+        let _ = writeln!(playlist, "#EXT-X-VERSION:{}", version(format));
+        let _ = writeln!(playlist, "#EXT-X-TARGETDURATION:{}", self.target_duration(segments));
+        playlist.push_str("#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+        if format.has_init_segment() {
+            let _ = writeln!(playlist, "#EXT-X-MAP:URI=\"{}\"", init_segment_name(prefix));
+        }
+        for segment in segments {
+            let _ = writeln!(playlist, "#EXTINF:{:.3},", segment.duration);
+            let _ = writeln!(playlist, "{}", segment_name(prefix, segment.index, format));
+        }
+        playlist.push_str("#EXT-X-ENDLIST\n");
+        playlist
+    }
+
+    /// Value of `EXT-X-TARGETDURATION`: no segment duration may exceed it
+    /// once rounded to the nearest second
+    fn target_duration(&self, segments: &[MediaSegment]) -> u64 {
+        segments
+            .iter()
+            .map(|segment| segment.duration)
+            .fold(self.segment_duration, f64::max)
+            .round()
+            .max(1.0) as u64
+    }
+}
+
+/// Variants and audio renditions of the master playlist of a media file
+fn master_renditions(timeline: &MediaTimeline, profiles: &[QualityProfile]) -> Result<(Vec<Rendition>, Vec<AudioRendition>)> {
+    let renditions = renditions(profiles, timeline.video.as_ref(), !timeline.audio.is_empty())?;
+    if renditions.is_empty() {
+        return Err(RustFlixError::config("No quality profiles configured for HLS"));
+    }
+    let audio = if renditions.iter().any(Rendition::is_demuxed) {
+        audio_renditions(profiles, &timeline.audio)?
+    } else {
+        Vec::new()
+    };
+    Ok((renditions, audio))
+}
+
+/// Render the master playlist of renditions
+///
+/// Demuxed variants reference the audio group of the audio renditions.
+pub fn master_playlist(renditions: &[Rendition], audio: &[AudioRendition]) -> String {
+    let version = renditions.iter().map(|rendition| version(rendition.segment_format)).max().unwrap_or(3);
+    let mut master = String::from("#EXTM3U\n");
+    let _ = writeln!(master, "#EXT-X-VERSION:{}", version);
+    master.push_str("#EXT-X-INDEPENDENT-SEGMENTS\n");
+    for rendition in audio {
+        let name = rendition.language.as_deref().unwrap_or("und");
+        let _ = write!(master, "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"{}\",NAME=\"{}\"", AUDIO_GROUP, name);
+        if let Some(language) = &rendition.language {
+            let _ = write!(master, ",LANGUAGE=\"{}\"", language);
+        }
+        let _ = writeln!(
+            master,
+            ",DEFAULT={},AUTOSELECT=YES,CHANNELS=\"{}\",URI=\"{}\"",
+            if rendition.default { "YES" } else { "NO" },
+            rendition.channels,
+            playlist_name(&rendition.name)
+        );
+    }
+    for rendition in renditions {
+        let variant = variant(rendition);
+        let _ = write!(master, "#EXT-X-STREAM-INF:BANDWIDTH={}", variant.bandwidth);
+        if let Some((width, height)) = variant.resolution {
+            let _ = write!(master, ",RESOLUTION={}x{}", width, height);
+        }
+        let _ = write!(master, ",CODECS=\"{}\"", variant.codecs);
+        if let Some(frame_rate) = variant.frame_rate {
+            let _ = write!(master, ",FRAME-RATE={:.3}", frame_rate);
+        }
+        if rendition.is_demuxed() && !audio.is_empty() {
+            let _ = write!(master, ",AUDIO=\"{}\"", AUDIO_GROUP);
+        }
+        let _ = writeln!(master, "\n{}", variant.playlist_url);
+    }
+    master
+}
+
+/// Protocol version of playlists with segments of a format
+///
+/// `EXT-X-MAP` for segments that are not I-frames only needs version 6, and
+/// fMP4 segments version 7.
+fn version(format: SegmentFormat) -> u8 {
+    match format {
+        SegmentFormat::MpegTs => 3,
+        SegmentFormat::Fmp4 => 7,
+    }
+}
+
+fn playlist_name(name: &str) -> String {
+    format!("{}.m3u8", name)
+}
+
+fn variant(rendition: &Rendition) -> HlsVariant {
+    HlsVariant {
+        playlist_url: playlist_name(&rendition.name),
+        bandwidth: rendition.bandwidth(),
+        resolution: rendition.resolution,
+        codecs: rendition.codecs(),
+        frame_rate: rendition.frame_rate,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::segmenter::tests::FAKE_SEGMENTER;
+    use crate::timeline::tests::sample_dir;
+    use crate::transcoder::tests::fake_ffmpeg;
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -61,15 +313,129 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_playlist() {
-        let generator = HlsGenerator::new(6.0, 5);
-        let temp_dir = TempDir::new().unwrap();
-        let media_path = temp_dir.path().join("test.mp4");
-        
+        let generator = HlsGenerator::new(6.0, 5).with_segment_format(SegmentFormat::MpegTs);
+        let (temp_dir, media_path) = sample_dir();
+
         let result = generator.generate_playlist(&media_path, temp_dir.path()).await;
         assert!(result.is_ok());
-        
+
         let playlist = result.unwrap();
-        assert!(playlist.contains("#EXTM3U"));
+        assert!(playlist.starts_with("#EXTM3U\n#EXT-X-VERSION:3\n"));
+        assert!(!playlist.contains("#EXT-X-MAP"));
         assert!(playlist.contains("#EXT-X-TARGETDURATION:6"));
+        assert!(playlist.contains("#EXT-X-PLAYLIST-TYPE:VOD"));
+        assert_eq!(playlist.matches("#EXTINF:6.000,").count(), 3);
+        assert!(playlist.contains("#EXTINF:2.000,\nsegment_0003.ts\n"));
+        assert!(playlist.ends_with("#EXT-X-ENDLIST\n"));
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join(MEDIA_PLAYLIST)).unwrap(), playlist);
+    }
+
+    #[tokio::test]
+    async fn test_generate_playlist_missing_file() {
+        let generator = HlsGenerator::new(6.0, 5);
+        let temp_dir = TempDir::new().unwrap();
+        let media_path = temp_dir.path().join("missing.mp4");
+        assert!(generator.generate_playlist(&media_path, temp_dir.path()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_master_playlist() {
+        let generator = HlsGenerator::new(6.0, 5);
+        let (temp_dir, media_path) = sample_dir();
+        let profiles = StreamingConfig::default().quality_profiles;
+
+        let playlist = generator
+            .generate_master_playlist(StreamId::new_v4(), &media_path, temp_dir.path(), &profiles)
+            .await
+            .unwrap();
+        assert_eq!(playlist.total_segments, Some(4));
+        assert_eq!(playlist.variant_playlists.len(), 2);
+        assert_eq!(playlist.variant_playlists[1].playlist_url, "720p.m3u8");
+        assert_eq!(playlist.variant_playlists[1].resolution, Some((1280, 720)));
+
+        let master = std::fs::read_to_string(temp_dir.path().join(MASTER_PLAYLIST)).unwrap();
+        assert_eq!(
+            master,
+            "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-INDEPENDENT-SEGMENTS\n\
+             #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",NAME=\"eng\",LANGUAGE=\"eng\",DEFAULT=YES,AUTOSELECT=YES,CHANNELS=\"2\",URI=\"audio_eng.m3u8\"\n\
+             #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",NAME=\"ger\",LANGUAGE=\"ger\",DEFAULT=NO,AUTOSELECT=YES,CHANNELS=\"2\",URI=\"audio_ger.m3u8\"\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=8128000,RESOLUTION=1920x1080,CODECS=\"avc1.640028,mp4a.40.2\",FRAME-RATE=23.976,AUDIO=\"audio\"\n\
+             1080p.m3u8\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=4128000,RESOLUTION=1280x720,CODECS=\"avc1.64001f,mp4a.40.2\",FRAME-RATE=23.976,AUDIO=\"audio\"\n\
+             720p.m3u8\n"
+        );
+        let variant = std::fs::read_to_string(temp_dir.path().join("720p.m3u8")).unwrap();
+        assert!(variant.contains("#EXT-X-VERSION:7\n"));
+        assert!(variant.contains("#EXT-X-MAP:URI=\"720p_init.mp4\"\n#EXTINF:6.000,\n720p_0000.m4s\n"));
+        let audio = std::fs::read_to_string(temp_dir.path().join("audio_ger.m3u8")).unwrap();
+        assert!(audio.contains("#EXT-X-MAP:URI=\"audio_ger_init.mp4\"\n#EXTINF:6.000,\naudio_ger_0000.m4s\n"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_generate_segments() {
+        let (temp_dir, media_path) = sample_dir();
+        let segmenter = Segmenter::new(6.0).with_ffmpeg_path(fake_ffmpeg(temp_dir.path(), FAKE_SEGMENTER));
+        let generator = HlsGenerator::new(6.0, 5).with_segmenter(segmenter);
+        let output_dir = temp_dir.path().join("hls");
+        let profiles = StreamingConfig::default().quality_profiles;
+
+        generator
+            .generate_master_playlist(StreamId::new_v4(), &media_path, &output_dir, &profiles)
+            .await
+            .unwrap();
+        let written = generator.generate_segments(&media_path, &output_dir, &profiles).await.unwrap();
+        assert_eq!(written.len(), 4 * 5);
+        assert_eq!(written[0], "1080p_init.mp4");
+        assert_eq!(written[19], "audio_ger_0003.m4s");
+
+        // Every file the playlists reference was written
+        let master = std::fs::read_to_string(output_dir.join(MASTER_PLAYLIST)).unwrap();
+        for playlist in master.lines().filter(|line| line.ends_with(".m3u8")) {
+            let media = std::fs::read_to_string(output_dir.join(playlist)).unwrap();
+            let uris = media.lines().filter(|line| !line.starts_with('#')).map(str::to_string);
+            let init = media
+                .lines()
+                .filter_map(|line| line.strip_prefix("#EXT-X-MAP:URI=\""))
+                .map(|uri| uri.trim_end_matches('"').to_string());
+            for uri in init.chain(uris) {
+                assert!(written.contains(&uri), "{} of {} not written", uri, playlist);
+            }
+        }
+        for line in master.lines().filter_map(|line| line.split("URI=\"").nth(1)) {
+            assert!(output_dir.join(line.trim_end_matches('"')).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_master_playlist_muxed_ts() {
+        let generator = HlsGenerator::new(6.0, 5);
+        let (temp_dir, media_path) = sample_dir();
+        let mut profiles = StreamingConfig::default().quality_profiles;
+        profiles.iter_mut().for_each(|profile| profile.container = "ts".to_string());
+
+        generator
+            .generate_master_playlist(StreamId::new_v4(), &media_path, temp_dir.path(), &profiles)
+            .await
+            .unwrap();
+        let master = std::fs::read_to_string(temp_dir.path().join(MASTER_PLAYLIST)).unwrap();
+        assert!(master.starts_with("#EXTM3U\n#EXT-X-VERSION:3\n"));
+        assert!(!master.contains("#EXT-X-MEDIA"));
+        assert!(!master.contains("AUDIO="));
+        assert!(!temp_dir.path().join("audio_eng.m3u8").exists());
+    }
+
+    #[test]
+    fn test_long_segments_raise_target_duration() {
+        let generator = HlsGenerator::new(6.0, 5);
+        let timeline = MediaTimeline {
+            duration: 31.25,
+            keyframes: vec![0.0, 4.0, 8.2, 11.9996, 13.0, 30.5, 31.0],
+            ..Default::default()
+        };
+
+        let playlist = generator.media_playlist(&timeline.segments(6.0), "segment", SegmentFormat::MpegTs);
+        assert!(playlist.contains("#EXT-X-TARGETDURATION:19\n"));
+        assert!(playlist.contains("#EXTINF:18.500,\nsegment_0002.ts\n"));
     }
 }
@@ -6,49 +6,272 @@ pub mod transcoder;
 pub mod streamer;
 pub mod hls;
 pub mod dash;
+pub mod renditions;
+pub mod segmenter;
+pub mod timeline;
 
 // Re-export commonly used types
 pub use transcoder::{Transcoder, TranscodingProfile};
 pub use streamer::{MediaStreamer, StreamSession};
 pub use hls::HlsGenerator;
 pub use dash::DashGenerator;
+pub use renditions::{AudioRendition, Rendition};
+pub use segmenter::Segmenter;
+pub use timeline::{MediaSegment, MediaTimeline};
 
-use rustflix_core::{Result, RustFlixError};
+use chrono::Utc;
+use rustflix_core::config::{QualityProfile, StreamingConfig};
+use rustflix_core::streaming::{TranscodingJob, TranscodingStatus};
+use rustflix_core::{EventType, Result, RustFlixError, StreamId};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use uuid::Uuid;
+
+/// Seconds finished transcoding jobs are kept for status queries
+const FINISHED_JOB_RETENTION: i64 = 3600;
 
 /// Streaming service for managing media streams
 #[derive(Debug, Clone)]
 pub struct StreamingService {
     transcoder: Transcoder,
     streamer: MediaStreamer,
+    profiles: Vec<QualityProfile>,
+    transcode_path: PathBuf,
+    jobs: Arc<Mutex<HashMap<Uuid, TranscodeTask>>>,
+}
+
+/// A transcoding job and the token that cancels it
+#[derive(Debug)]
+struct TranscodeTask {
+    job: TranscodingJob,
+    cancel: CancellationToken,
 }
 
 impl StreamingService {
     /// Create a new streaming service
     pub fn new() -> Result<Self> {
+        Self::from_config(&StreamingConfig::default())
+    }
+
+    /// Create a streaming service with the quality profiles and transcode
+    /// folder of a streaming configuration
+    pub fn from_config(config: &StreamingConfig) -> Result<Self> {
         Ok(Self {
-            transcoder: Transcoder::new()?,
+            transcoder: Transcoder::from_config(config)?,
             streamer: MediaStreamer::new()?,
+            profiles: config.quality_profiles.clone(),
+            transcode_path: config.transcode_path.clone(),
+            jobs: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
+    /// Transcode with another transcoder
+    pub fn with_transcoder(mut self, transcoder: Transcoder) -> Self {
+        self.transcoder = transcoder;
+        self
+    }
+
     /// Start the streaming service
     pub async fn start(&self) -> Result<()> {
         Ok(())
     }
 
-    /// Stop the streaming service
+    /// Stop the streaming service, cancelling running transcodes
     pub async fn stop(&self) -> Result<()> {
+        for task in lock(&self.jobs).values() {
+            task.cancel.cancel();
+        }
         Ok(())
     }
+
+    /// Start transcoding a media file with a quality profile
+    ///
+    /// The output is written to the transcode folder, named after the job.
+    /// The transcode runs in the background; `transcode_job` follows it.
+    pub async fn start_transcode(&self, media_id: Uuid, input_path: PathBuf, profile: &str) -> Result<TranscodingJob> {
+        let profile = self
+            .profiles
+            .iter()
+            .find(|quality| quality.name == profile)
+            .map(transcoding_profile)
+            .ok_or_else(|| RustFlixError::validation("profile".to_string(), format!("Unknown quality profile {}", profile)))?;
+        tokio::fs::create_dir_all(&self.transcode_path).await?;
+
+        let job = TranscodingJob::new(StreamId::new_v4(), media_id, profile);
+        let output_path = self
+            .transcode_path
+            .join(format!("{}.{}", job.id, output_extension(&job.profile.container)));
+        let cancel = CancellationToken::new();
+        {
+            let mut jobs = lock(&self.jobs);
+            let now = Utc::now();
+            jobs.retain(|_, task| {
+                !is_finished(task.job.status) || (now - task.job.updated_at).num_seconds() < FINISHED_JOB_RETENTION
+            });
+            jobs.insert(job.id, TranscodeTask { job: job.clone(), cancel: cancel.clone() });
+        }
+        info!("Transcoding job {} started for media {}", job.id, media_id);
+
+        let service = self.clone();
+        let mut running = job.clone();
+        tokio::spawn(async move {
+            service.run_transcode(&mut running, &input_path, &output_path, &cancel).await;
+        });
+        Ok(job)
+    }
+
+    /// Current state of a transcoding job
+    pub fn transcode_job(&self, id: Uuid) -> Option<TranscodingJob> {
+        lock(&self.jobs).get(&id).map(|task| task.job.clone())
+    }
+
+    /// Cancel a transcoding job, returning whether it was still running
+    pub fn cancel_transcode(&self, id: Uuid) -> bool {
+        match lock(&self.jobs).get(&id) {
+            Some(task) if !is_finished(task.job.status) => {
+                task.cancel.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Run a transcode, recording its progress events and final state
+    async fn run_transcode(&self, job: &mut TranscodingJob, input_path: &Path, output_path: &Path, cancel: &CancellationToken) {
+        let id = job.id;
+        let mut events = self.transcoder.subscribe();
+        {
+            let transcode = self.transcoder.transcode(job, input_path, output_path, cancel);
+            tokio::pin!(transcode);
+            loop {
+                tokio::select! {
+                    // Failures are logged and recorded on the job
+                    _ = &mut transcode => break,
+                    Ok(event) = events.recv() => self.follow(id, event.event_type),
+                }
+            }
+        }
+        if let Some(task) = lock(&self.jobs).get_mut(&id) {
+            task.job = job.clone();
+        }
+    }
+
+    /// Apply a transcoder event of a job to its recorded state
+    fn follow(&self, id: Uuid, event: EventType) {
+        let mut jobs = lock(&self.jobs);
+        let Some(task) = jobs.get_mut(&id) else {
+            return;
+        };
+        match event {
+            EventType::TranscodingStarted { job_id, .. } if job_id == id => task.job.status = TranscodingStatus::Running,
+            EventType::TranscodingProgress { job_id, progress } if job_id == id => task.job.progress = progress,
+            _ => return,
+        }
+        task.job.updated_at = Utc::now();
+    }
+}
+
+/// Transcoding profile of a quality profile
+pub fn transcoding_profile(profile: &QualityProfile) -> TranscodingProfile {
+    TranscodingProfile {
+        name: profile.name.clone(),
+        container: profile.container.clone(),
+        video_codec: Some(profile.video_codec.clone()),
+        audio_codec: profile.audio_codec.clone(),
+        max_width: profile.max_width,
+        max_height: profile.max_height,
+        max_bitrate: Some(profile.max_bitrate),
+        max_frame_rate: None,
+        audio_channels: None,
+        audio_sample_rate: None,
+    }
+}
+
+/// File extension of transcoded files of a container
+fn output_extension(container: &str) -> String {
+    match container.to_ascii_lowercase().as_str() {
+        "fmp4" | "cmaf" | "m4v" => "mp4".to_string(),
+        "mpegts" => "ts".to_string(),
+        "matroska" => "mkv".to_string(),
+        container => container.to_string(),
+    }
+}
+
+fn is_finished(status: TranscodingStatus) -> bool {
+    matches!(status, TranscodingStatus::Completed | TranscodingStatus::Failed | TranscodingStatus::Cancelled)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::timeline::tests::sample_dir;
+    use crate::transcoder::tests::fake_ffmpeg;
+    use std::time::Duration;
+
     #[tokio::test]
     async fn test_service_creation() {
         let service = StreamingService::new();
         assert!(service.is_ok());
     }
+
+    /// Wait for a transcoding job to finish
+    async fn finished(service: &StreamingService, id: Uuid) -> TranscodingJob {
+        for _ in 0..100 {
+            let job = service.transcode_job(id).unwrap();
+            if is_finished(job.status) {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("transcoding job {} did not finish", id);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_transcode_jobs() {
+        let (temp_dir, media_path) = sample_dir();
+        let ffmpeg = fake_ffmpeg(
+            temp_dir.path(),
+            "for output; do :; done\n\
+             case \"$output\" in *slow*) exec sleep 30;; esac\n\
+             printf 'out_time_us=20000000\\nprogress=end\\n'\n\
+             echo transcoded > \"$output\"",
+        );
+        let config = StreamingConfig {
+            transcode_path: temp_dir.path().join("transcodes"),
+            ..StreamingConfig::default()
+        };
+        let service = StreamingService::from_config(&config)
+            .unwrap()
+            .with_transcoder(Transcoder::new().unwrap().with_ffmpeg_path(ffmpeg));
+        let media_id = Uuid::new_v4();
+
+        let job = service.start_transcode(media_id, media_path.clone(), "720p").await.unwrap();
+        assert_eq!(job.profile.max_height, Some(720));
+        let job = finished(&service, job.id).await;
+        assert_eq!(job.status, TranscodingStatus::Completed);
+        assert_eq!(job.progress, 100.0);
+        let output = config.transcode_path.join(format!("{}.mp4", job.id));
+        assert_eq!(std::fs::read_to_string(output).unwrap(), "transcoded\n");
+        assert!(!service.cancel_transcode(job.id));
+
+        let slow = temp_dir.path().join("slow.mkv");
+        std::fs::copy(&media_path, &slow).unwrap();
+        let job = service.start_transcode(media_id, slow, "1080p").await.unwrap();
+        assert!(service.cancel_transcode(job.id));
+        assert_eq!(finished(&service, job.id).await.status, TranscodingStatus::Cancelled);
+
+        assert!(!service.cancel_transcode(Uuid::new_v4()));
+        assert!(service.transcode_job(Uuid::new_v4()).is_none());
+        let unknown = service.start_transcode(media_id, media_path, "4k").await;
+        assert!(matches!(unknown, Err(RustFlixError::Validation { .. })));
+    }
 }
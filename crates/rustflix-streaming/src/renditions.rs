@@ -0,0 +1,473 @@
+//! Output renditions of quality profiles and their RFC 6381 codec strings
+//!
+//! Segments are named after their rendition, so the fMP4 segments written
+//! for HLS are the same files a DASH `SegmentTemplate` points at. fMP4 video
+//! segments carry no audio; each audio language is segmented separately.
+
+use rustflix_core::config::QualityProfile;
+use rustflix_core::media::{AudioCodec, VideoCodec};
+use rustflix_core::{Result, RustFlixError, SegmentFormat};
+
+/// Bitrate of the audio of a rendition
+pub const AUDIO_BITRATE: u64 = 128_000;
+
+/// Frame rate assumed for sources whose container does not declare one
+pub(crate) const DEFAULT_FRAME_RATE: f64 = 30.0;
+
+/// Most audio channels of a rendition
+const MAX_AUDIO_CHANNELS: u8 = 2;
+
+/// Highest audio sample rate of a rendition
+const MAX_SAMPLE_RATE: u32 = 48_000;
+
+/// One quality profile applied to a source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendition {
+    /// Name of the quality profile
+    pub name: String,
+    /// Output size, none for audio-only sources
+    pub resolution: Option<(u32, u32)>,
+    pub frame_rate: Option<f64>,
+    /// Peak video bitrate in bits per second
+    pub video_bitrate: u64,
+    pub audio_bitrate: u64,
+    /// Codec string of the video, none for audio-only sources
+    pub video_codec: Option<String>,
+    /// Codec string of the audio, none for sources without audio
+    pub audio_codec: Option<String>,
+    pub segment_format: SegmentFormat,
+}
+
+/// One language of the audio of a source
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioRendition {
+    /// Name of its segments and playlist, `audio_<language>`
+    pub name: String,
+    pub language: Option<String>,
+    pub channels: u8,
+    pub sample_rate: u32,
+    pub bitrate: u64,
+    /// Codec string
+    pub codec: String,
+    /// Set on the first language of the source
+    pub default: bool,
+}
+
+impl Rendition {
+    /// Peak bitrate of video and audio together
+    pub fn bandwidth(&self) -> u64 {
+        self.video_bitrate + self.audio_bitrate
+    }
+
+    /// Codec strings of the streams, comma separated as in a `CODECS` attribute
+    pub fn codecs(&self) -> String {
+        [&self.video_codec, &self.audio_codec]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Check whether segments of this rendition carry video only, with the
+    /// audio in the separate audio renditions
+    pub fn is_demuxed(&self) -> bool {
+        self.segment_format == SegmentFormat::Fmp4 && self.video_codec.is_some()
+    }
+
+    /// File name of a media segment of this rendition
+    pub fn segment_name(&self, index: u32) -> String {
+        segment_name(&self.name, index, self.segment_format)
+    }
+
+    /// File name of the init segment of this rendition, if its format has one
+    pub fn init_segment_name(&self) -> Option<String> {
+        self.segment_format.has_init_segment().then(|| init_segment_name(&self.name))
+    }
+}
+
+impl AudioRendition {
+    /// File name of a media segment of this rendition
+    pub fn segment_name(&self, index: u32) -> String {
+        segment_name(&self.name, index, SegmentFormat::Fmp4)
+    }
+
+    /// File name of the init segment of this rendition
+    pub fn init_segment_name(&self) -> String {
+        init_segment_name(&self.name)
+    }
+}
+
+/// File name of a media segment
+pub fn segment_name(prefix: &str, index: u32, format: SegmentFormat) -> String {
+    format!("{}_{:04}.{}", prefix, index, format.segment_extension())
+}
+
+/// File name of the init segment of fMP4 segments
+pub fn init_segment_name(prefix: &str) -> String {
+    format!("{}_init.mp4", prefix)
+}
+
+/// Renditions of quality profiles for a source
+///
+/// Video is scaled down to fit each profile, never up; profiles that would
+/// give the same size keep only the one with the lowest bitrate. HEVC and
+/// AV1 keep the bit depth of 10-bit (HDR) sources. Audio-only sources get a
+/// single rendition of the first profile's audio codec.
+pub fn renditions(profiles: &[QualityProfile], source: Option<&VideoCodec>, has_audio: bool) -> Result<Vec<Rendition>> {
+    let audio_codec = |profile: &QualityProfile, format: SegmentFormat| {
+        has_audio.then(|| audio_codec_string(&profile.audio_codec, format)).transpose()
+    };
+    let audio_bitrate = if has_audio { AUDIO_BITRATE } else { 0 };
+
+    let Some(source) = source.filter(|video| video.width > 0 && video.height > 0) else {
+        let Some(profile) = profiles.first().filter(|_| has_audio) else {
+            return Ok(Vec::new());
+        };
+        let segment_format = segment_format(profile)?;
+        return Ok(vec![Rendition {
+            name: profile.name.clone(),
+            resolution: None,
+            frame_rate: None,
+            video_bitrate: 0,
+            audio_bitrate,
+            video_codec: None,
+            audio_codec: audio_codec(profile, segment_format)?,
+            segment_format,
+        }]);
+    };
+
+    let mut renditions: Vec<Rendition> = Vec::new();
+    for profile in profiles {
+        let (width, height) = scaled_resolution((source.width, source.height), profile.max_width, profile.max_height);
+        let frame_rate = source.frame_rate.unwrap_or(DEFAULT_FRAME_RATE);
+        let segment_format = segment_format(profile)?;
+        let video_codec = video_codec_string(
+            &profile.video_codec,
+            (width, height),
+            frame_rate,
+            profile.max_bitrate,
+            source.bit_depth.unwrap_or(8),
+            segment_format,
+        )?;
+        let rendition = Rendition {
+            name: profile.name.clone(),
+            resolution: Some((width, height)),
+            frame_rate: source.frame_rate,
+            video_bitrate: profile.max_bitrate,
+            audio_bitrate,
+            video_codec: Some(video_codec),
+            audio_codec: audio_codec(profile, segment_format)?,
+            segment_format,
+        };
+
+        match renditions.iter_mut().find(|existing| existing.resolution == rendition.resolution) {
+            Some(existing) if existing.video_bitrate > rendition.video_bitrate => *existing = rendition,
+            Some(_) => {}
+            None => renditions.push(rendition),
+        }
+    }
+    Ok(renditions)
+}
+
+/// Audio renditions of the audio tracks of a source, one per language
+///
+/// The first track of each language is used, encoded with the audio codec
+/// of the first quality profile.
+pub fn audio_renditions(profiles: &[QualityProfile], tracks: &[AudioCodec]) -> Result<Vec<AudioRendition>> {
+    let Some(profile) = profiles.first() else {
+        return Ok(Vec::new());
+    };
+
+    let mut renditions: Vec<AudioRendition> = Vec::new();
+    for track in tracks {
+        if renditions.iter().any(|rendition| rendition.language == track.language) {
+            continue;
+        }
+        renditions.push(AudioRendition {
+            name: format!("audio_{}", track.language.as_deref().unwrap_or("und")),
+            language: track.language.clone(),
+            channels: track.channels.clamp(1, MAX_AUDIO_CHANNELS),
+            sample_rate: if track.sample_rate > 0 { track.sample_rate.min(MAX_SAMPLE_RATE) } else { MAX_SAMPLE_RATE },
+            bitrate: AUDIO_BITRATE,
+            codec: audio_codec_string(&profile.audio_codec, SegmentFormat::Fmp4)?,
+            default: renditions.is_empty(),
+        });
+    }
+    Ok(renditions)
+}
+
+/// Quality profile a rendition was made of
+pub fn find_profile<'a>(profiles: &'a [QualityProfile], name: &str) -> Result<&'a QualityProfile> {
+    profiles
+        .iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| RustFlixError::config(format!("No quality profile {}", name)))
+}
+
+fn segment_format(profile: &QualityProfile) -> Result<SegmentFormat> {
+    SegmentFormat::from_container(&profile.container).ok_or_else(|| {
+        RustFlixError::config(format!(
+            "Unsupported streaming container {} in quality profile {}",
+            profile.container, profile.name
+        ))
+    })
+}
+
+/// Size of a source scaled down to fit within bounds, keeping its aspect
+/// ratio, with even dimensions as chroma subsampling requires
+pub fn scaled_resolution(source: (u32, u32), max_width: Option<u32>, max_height: Option<u32>) -> (u32, u32) {
+    let (width, height) = source;
+    let scale = [
+        max_width.map(|max| max as f64 / width as f64),
+        max_height.map(|max| max as f64 / height as f64),
+    ]
+    .into_iter()
+    .flatten()
+    .fold(1.0, f64::min);
+    let even = |value: f64| ((value / 2.0).round() as u32 * 2).max(2);
+    (even(width as f64 * scale), even(height as f64 * scale))
+}
+
+/// Levels as `(level, max luma samples per picture, max luma samples per
+/// second, max bitrate)`, from lowest to highest
+type LevelTable = [(u8, u64, u64, u64)];
+
+/// H.264 levels, with the High profile bitrate limits
+const H264_LEVELS: &LevelTable = &[
+    (30, 414_720, 10_368_000, 12_500_000),
+    (31, 921_600, 27_648_000, 17_500_000),
+    (32, 1_310_720, 55_296_000, 25_000_000),
+    (40, 2_097_152, 62_914_560, 25_000_000),
+    (41, 2_097_152, 62_914_560, 62_500_000),
+    (42, 2_228_224, 133_693_440, 62_500_000),
+    (50, 5_652_480, 150_994_944, 168_750_000),
+    (51, 9_437_184, 251_658_240, 300_000_000),
+    (52, 9_437_184, 530_841_600, 300_000_000),
+];
+
+/// HEVC levels, with the Main tier bitrate limits; the level is 30 times
+/// the level number
+const HEVC_LEVELS: &LevelTable = &[
+    (90, 552_960, 16_588_800, 6_000_000),
+    (93, 983_040, 33_177_600, 10_000_000),
+    (120, 2_228_224, 66_846_720, 12_000_000),
+    (123, 2_228_224, 133_693_440, 20_000_000),
+    (150, 8_912_896, 267_386_880, 25_000_000),
+    (153, 8_912_896, 534_773_760, 40_000_000),
+    (156, 8_912_896, 1_069_547_520, 60_000_000),
+    (180, 35_651_584, 1_069_547_520, 60_000_000),
+];
+
+/// AV1 levels as `seq_level_idx`, with the Main tier bitrate limits
+const AV1_LEVELS: &LevelTable = &[
+    (0, 147_456, 4_423_680, 1_500_000),
+    (1, 278_784, 8_363_520, 3_000_000),
+    (4, 665_856, 19_975_680, 6_000_000),
+    (5, 1_065_024, 31_950_720, 10_000_000),
+    (8, 2_359_296, 70_778_880, 12_000_000),
+    (9, 2_359_296, 141_557_760, 20_000_000),
+    (12, 8_912_896, 267_386_880, 30_000_000),
+    (13, 8_912_896, 534_773_760, 40_000_000),
+    (14, 8_912_896, 1_069_547_520, 60_000_000),
+    (16, 35_651_584, 1_069_547_520, 60_000_000),
+];
+
+/// Lowest level of a table that fits a picture size, frame rate and bitrate
+fn level(table: &LevelTable, picture_size: u64, frame_rate: f64, bitrate: u64) -> u8 {
+    let sample_rate = (picture_size as f64 * frame_rate).ceil() as u64;
+    table
+        .iter()
+        .find(|(_, max_picture, max_rate, max_bitrate)| {
+            picture_size <= *max_picture && sample_rate <= *max_rate && bitrate <= *max_bitrate
+        })
+        .or(table.last())
+        .map_or(0, |(level, ..)| *level)
+}
+
+/// RFC 6381 codec string of a video codec encoded at a size, frame rate and
+/// bitrate, as the transcoder encodes it
+///
+/// H.264 is encoded in the High profile at 8 bits. HEVC and AV1 are encoded
+/// in their Main (HEVC Main 10 for 10-bit) profiles at the source bit depth,
+/// and only into fMP4 segments, which HLS requires for them.
+pub fn video_codec_string(
+    codec: &str,
+    (width, height): (u32, u32),
+    frame_rate: f64,
+    bitrate: u64,
+    bit_depth: u8,
+    format: SegmentFormat,
+) -> Result<String> {
+    let codec = codec.to_lowercase();
+    let ten_bit = bit_depth >= 10;
+    match codec.as_str() {
+        "h264" | "avc" | "avc1" => {
+            // Pictures are coded in whole macroblocks
+            let picture_size = (width.div_ceil(16) * 16) as u64 * (height.div_ceil(16) * 16) as u64;
+            Ok(format!("avc1.6400{:02x}", level(H264_LEVELS, picture_size, frame_rate, bitrate)))
+        }
+        "hevc" | "h265" | "hvc1" if format == SegmentFormat::Fmp4 => {
+            let level = level(HEVC_LEVELS, width as u64 * height as u64, frame_rate, bitrate);
+            if ten_bit {
+                Ok(format!("hvc1.2.4.L{}.B0", level))
+            } else {
+                Ok(format!("hvc1.1.6.L{}.B0", level))
+            }
+        }
+        "av1" | "av01" if format == SegmentFormat::Fmp4 => {
+            let level = level(AV1_LEVELS, width as u64 * height as u64, frame_rate, bitrate);
+            Ok(format!("av01.0.{:02}M.{}", level, if ten_bit { "10" } else { "08" }))
+        }
+        "hevc" | "h265" | "hvc1" | "av1" | "av01" => Err(RustFlixError::config(format!(
+            "Streaming video codec {} requires fMP4 segments",
+            codec
+        ))),
+        _ => Err(RustFlixError::config(format!("Unsupported streaming video codec {}", codec))),
+    }
+}
+
+/// RFC 6381 codec string of an audio codec; AAC is encoded as AAC-LC
+///
+/// Opus and FLAC can only be carried in fMP4 segments.
+pub fn audio_codec_string(codec: &str, format: SegmentFormat) -> Result<String> {
+    let codec = codec.to_lowercase();
+    let codec_string = match codec.as_str() {
+        "aac" => "mp4a.40.2",
+        "mp3" => "mp4a.40.34",
+        "ac3" => "ac-3",
+        "eac3" => "ec-3",
+        "opus" if format == SegmentFormat::Fmp4 => "Opus",
+        "flac" if format == SegmentFormat::Fmp4 => "fLaC",
+        "opus" | "flac" => {
+            return Err(RustFlixError::config(format!(
+                "Streaming audio codec {} requires fMP4 segments",
+                codec
+            )))
+        }
+        _ => return Err(RustFlixError::config(format!("Unsupported streaming audio codec {}", codec))),
+    };
+    Ok(codec_string.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustflix_core::config::StreamingConfig;
+
+    fn source(width: u32, height: u32, frame_rate: Option<f64>) -> VideoCodec {
+        VideoCodec {
+            name: "hevc".to_string(),
+            profile: None,
+            level: None,
+            width,
+            height,
+            frame_rate,
+            bit_depth: None,
+            color_space: None,
+        }
+    }
+
+    #[test]
+    fn test_codec_strings() {
+        let video = |codec: &str, size: (u32, u32), frame_rate: f64, bitrate: u64, bit_depth: u8| {
+            video_codec_string(codec, size, frame_rate, bitrate, bit_depth, SegmentFormat::Fmp4)
+        };
+        assert_eq!(video("h264", (1920, 1080), 23.976, 8_000_000, 8).unwrap(), "avc1.640028");
+        assert_eq!(video("h264", (1280, 720), 30.0, 4_000_000, 8).unwrap(), "avc1.64001f");
+        assert_eq!(video("h264", (1920, 1080), 60.0, 8_000_000, 8).unwrap(), "avc1.64002a");
+        assert_eq!(video("h264", (1920, 1080), 24.0, 40_000_000, 10).unwrap(), "avc1.640029");
+        assert_eq!(video("hevc", (3840, 2160), 24.0, 20_000_000, 8).unwrap(), "hvc1.1.6.L150.B0");
+        assert_eq!(video("hevc", (3840, 2160), 24.0, 20_000_000, 10).unwrap(), "hvc1.2.4.L150.B0");
+        assert_eq!(video("av1", (1920, 1080), 24.0, 8_000_000, 8).unwrap(), "av01.0.08M.08");
+        assert_eq!(video("av1", (3840, 2160), 24.0, 20_000_000, 10).unwrap(), "av01.0.12M.10");
+        assert!(video("mpeg2video", (720, 576), 25.0, 8_000_000, 8).is_err());
+        assert!(video_codec_string("hevc", (1920, 1080), 24.0, 8_000_000, 10, SegmentFormat::MpegTs).is_err());
+
+        assert_eq!(audio_codec_string("aac", SegmentFormat::MpegTs).unwrap(), "mp4a.40.2");
+        assert_eq!(audio_codec_string("EAC3", SegmentFormat::Fmp4).unwrap(), "ec-3");
+        assert_eq!(audio_codec_string("opus", SegmentFormat::Fmp4).unwrap(), "Opus");
+        assert!(audio_codec_string("opus", SegmentFormat::MpegTs).is_err());
+        assert!(audio_codec_string("truehd", SegmentFormat::Fmp4).is_err());
+    }
+
+    #[test]
+    fn test_scaled_resolution() {
+        assert_eq!(scaled_resolution((1920, 800), Some(1280), Some(720)), (1280, 534));
+        assert_eq!(scaled_resolution((1440, 1080), Some(1280), Some(720)), (960, 720));
+        assert_eq!(scaled_resolution((1280, 720), Some(1920), Some(1080)), (1280, 720));
+        assert_eq!(scaled_resolution((853, 480), None, None), (854, 480));
+    }
+
+    #[test]
+    fn test_renditions() {
+        let profiles = StreamingConfig::default().quality_profiles;
+
+        let scope = renditions(&profiles, Some(&source(3840, 1600, Some(23.976))), true).unwrap();
+        assert_eq!(scope.len(), 2);
+        assert_eq!(scope[0].resolution, Some((1920, 800)));
+        assert_eq!(scope[0].bandwidth(), 8_128_000);
+        assert_eq!(scope[0].codecs(), "avc1.640028,mp4a.40.2");
+        assert_eq!(scope[1].resolution, Some((1280, 534)));
+        assert_eq!(scope[1].codecs(), "avc1.64001f,mp4a.40.2");
+        assert_eq!(scope[1].segment_name(7), "720p_0007.m4s");
+        assert_eq!(scope[1].init_segment_name().as_deref(), Some("720p_init.mp4"));
+
+        // A 720p source is not scaled up for the 1080p profile
+        let hd = renditions(&profiles, Some(&source(1280, 720, None)), false).unwrap();
+        assert_eq!(hd.len(), 1);
+        assert_eq!(hd[0].name, "720p");
+        assert_eq!(hd[0].video_bitrate, 4_000_000);
+        assert_eq!(hd[0].bandwidth(), 4_000_000);
+        assert_eq!(hd[0].codecs(), "avc1.64001f");
+
+        let audio = renditions(&profiles, None, true).unwrap();
+        assert_eq!(audio.len(), 1);
+        assert_eq!(audio[0].resolution, None);
+        assert_eq!(audio[0].codecs(), "mp4a.40.2");
+        assert!(!audio[0].is_demuxed());
+        assert!(renditions(&profiles, None, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_audio_renditions() {
+        let profiles = StreamingConfig::default().quality_profiles;
+        let track = |channels: u8, sample_rate: u32, language: Option<&str>| AudioCodec {
+            name: "ac3".to_string(),
+            channels,
+            sample_rate,
+            bit_depth: None,
+            bitrate: None,
+            language: language.map(str::to_string),
+        };
+        let tracks = [track(6, 48000, Some("eng")), track(2, 48000, Some("eng")), track(1, 96000, None)];
+
+        let audio = audio_renditions(&profiles, &tracks).unwrap();
+        assert_eq!(audio.len(), 2);
+        assert_eq!(audio[0].name, "audio_eng");
+        assert_eq!((audio[0].channels, audio[0].codec.as_str()), (2, "mp4a.40.2"));
+        assert!(audio[0].default);
+        assert_eq!(audio[0].segment_name(3), "audio_eng_0003.m4s");
+        assert_eq!(audio[1].name, "audio_und");
+        assert_eq!((audio[1].channels, audio[1].sample_rate), (1, 48000));
+        assert!(!audio[1].default);
+    }
+
+    #[test]
+    fn test_hdr_renditions() {
+        let mut hdr = source(3840, 2160, Some(23.976));
+        hdr.bit_depth = Some(10);
+        let mut profile = StreamingConfig::default().quality_profiles.remove(0);
+        profile.video_codec = "hevc".to_string();
+
+        let uhd = renditions(std::slice::from_ref(&profile), Some(&hdr), true).unwrap();
+        assert_eq!(uhd[0].codecs(), "hvc1.2.4.L120.B0,mp4a.40.2");
+        assert!(uhd[0].is_demuxed());
+
+        // Transport streams cannot carry HEVC
+        profile.container = "ts".to_string();
+        assert!(renditions(std::slice::from_ref(&profile), Some(&hdr), true).is_err());
+        profile.container = "mkv".to_string();
+        assert!(renditions(&[profile], Some(&hdr), true).is_err());
+    }
+}
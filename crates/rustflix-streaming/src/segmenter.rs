@@ -0,0 +1,469 @@
+//! Segmenting of media files into the segments of HLS playlists and DASH
+//! manifests
+//!
+//! Each rendition is encoded by ffmpeg's HLS muxer into `<rendition>_%04d`
+//! segments, with an `<rendition>_init.mp4` init segment for fMP4. Video
+//! keyframes are forced at the cut points of the timeline and nowhere else,
+//! so the muxer cuts exactly where the playlists say. Audio renditions are
+//! cut evenly, as `MediaTimeline::audio_segments` gives them.
+//!
+//! HLS and DASH share the fMP4 segments of a rendition: renditions whose
+//! segments are all in the output directory are not encoded again.
+
+use crate::renditions::{init_segment_name, AudioRendition, Rendition, DEFAULT_FRAME_RATE};
+use crate::timeline::{MediaSegment, MediaTimeline, CUT_TOLERANCE};
+use crate::transcoder::{encoding_args, TranscodingProfile};
+use rustflix_core::config::{QualityProfile, StreamingConfig};
+use rustflix_core::{Result, RustFlixError, SegmentFormat};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::{debug, info};
+
+/// Bytes of ffmpeg's error output kept for the failure message
+const MAX_ERROR_OUTPUT: usize = 4096;
+
+/// Segmenter running ffmpeg
+#[derive(Debug, Clone)]
+pub struct Segmenter {
+    segment_duration: f64,
+    ffmpeg_path: PathBuf,
+    threads: Option<usize>,
+}
+
+impl Segmenter {
+    /// Create a segmenter running `ffmpeg` from the `PATH`
+    pub fn new(segment_duration: f64) -> Self {
+        Self {
+            segment_duration,
+            ffmpeg_path: PathBuf::from("ffmpeg"),
+            threads: None,
+        }
+    }
+
+    /// Create a segmenter with the segment duration and thread count of a
+    /// streaming configuration
+    pub fn from_config(config: &StreamingConfig) -> Self {
+        Self {
+            threads: config.transcoding_threads,
+            ..Self::new(config.segment_duration)
+        }
+    }
+
+    /// Run another ffmpeg binary
+    pub fn with_ffmpeg_path(mut self, ffmpeg_path: impl Into<PathBuf>) -> Self {
+        self.ffmpeg_path = ffmpeg_path.into();
+        self
+    }
+
+    /// Encode a rendition of a media file into its segments in `output_dir`
+    ///
+    /// Demuxed renditions carry video only; the others carry the first
+    /// audio track too. Returns the names of the files written, the init
+    /// segment first.
+    pub async fn segment_rendition(
+        &self,
+        media_path: &Path,
+        output_dir: &Path,
+        timeline: &MediaTimeline,
+        rendition: &Rendition,
+        profile: &QualityProfile,
+    ) -> Result<Vec<String>> {
+        info!("Segmenting rendition {} of {}", rendition.name, media_path.display());
+
+        let segments = if rendition.video_codec.is_some() {
+            timeline.segments(self.segment_duration)
+        } else {
+            timeline.audio_segments(self.segment_duration)
+        };
+        let encoding = TranscodingProfile {
+            name: rendition.name.clone(),
+            container: rendition.segment_format.as_str().to_string(),
+            video_codec: rendition.video_codec.as_ref().map(|_| profile.video_codec.clone()),
+            audio_codec: profile.audio_codec.clone(),
+            max_width: rendition.resolution.map(|(width, _)| width),
+            max_height: rendition.resolution.map(|(_, height)| height),
+            max_bitrate: Some(rendition.video_bitrate).filter(|bitrate| *bitrate > 0),
+            max_frame_rate: None,
+            audio_channels: None,
+            audio_sample_rate: None,
+        };
+
+        let mut args = input_args(media_path);
+        if rendition.video_codec.is_some() {
+            args.extend(["-map", "0:v:0"].map(OsString::from));
+        }
+        if rendition.audio_codec.is_some() && !rendition.is_demuxed() {
+            args.extend(["-map", "0:a:0"].map(OsString::from));
+        }
+        args.extend(encoding_args(&encoding, self.threads)?.into_iter().map(OsString::from));
+        if rendition.is_demuxed() {
+            args.push("-an".into());
+        }
+
+        let hls_time = if rendition.video_codec.is_some() {
+            args.extend(keyframe_args(&segments, rendition.frame_rate).into_iter().map(OsString::from));
+            // Every keyframe is a cut point; the muxer must not pass over
+            // one that the timeline took within its tolerance
+            self.segment_duration - CUT_TOLERANCE
+        } else {
+            self.segment_duration
+        };
+        self.run_hls(args, output_dir, &rendition.name, rendition.segment_format, hls_time, segments.len()).await
+    }
+
+    /// Encode an audio rendition of a media file into its fMP4 segments in
+    /// `output_dir`
+    ///
+    /// The first audio track of the rendition's language is encoded.
+    /// Returns the names of the files written, the init segment first.
+    pub async fn segment_audio(
+        &self,
+        media_path: &Path,
+        output_dir: &Path,
+        timeline: &MediaTimeline,
+        rendition: &AudioRendition,
+        profile: &QualityProfile,
+    ) -> Result<Vec<String>> {
+        info!("Segmenting audio rendition {} of {}", rendition.name, media_path.display());
+
+        let track = timeline
+            .audio
+            .iter()
+            .position(|track| track.language == rendition.language)
+            .ok_or_else(|| RustFlixError::not_found("audio track".to_string(), rendition.name.clone()))?;
+        let encoding = TranscodingProfile {
+            name: rendition.name.clone(),
+            container: SegmentFormat::Fmp4.as_str().to_string(),
+            video_codec: None,
+            audio_codec: profile.audio_codec.clone(),
+            max_width: None,
+            max_height: None,
+            max_bitrate: None,
+            max_frame_rate: None,
+            audio_channels: Some(rendition.channels),
+            audio_sample_rate: Some(rendition.sample_rate),
+        };
+
+        let mut args = input_args(media_path);
+        args.extend(["-map".to_string(), format!("0:a:{}", track)].map(OsString::from));
+        args.extend(encoding_args(&encoding, self.threads)?.into_iter().map(OsString::from));
+        let segments = timeline.audio_segments(self.segment_duration).len();
+        self.run_hls(args, output_dir, &rendition.name, SegmentFormat::Fmp4, self.segment_duration, segments)
+            .await
+    }
+
+    /// Convert the text subtitle track at a position among the subtitle
+    /// tracks of a media file to a WebVTT file in `output_dir`
+    ///
+    /// Returns the name of the file, which is not converted again once written.
+    pub async fn extract_subtitle(
+        &self,
+        media_path: &Path,
+        output_dir: &Path,
+        track: usize,
+        file_name: &str,
+    ) -> Result<String> {
+        let output_path = output_dir.join(file_name);
+        if tokio::fs::try_exists(&output_path).await? {
+            debug!("Subtitle {} already written", file_name);
+            return Ok(file_name.to_string());
+        }
+        info!("Extracting subtitle {} of {}", file_name, media_path.display());
+
+        tokio::fs::create_dir_all(output_dir).await?;
+        let mut args = input_args(media_path);
+        args.extend(["-map".to_string(), format!("0:s:{}", track)].map(OsString::from));
+        args.extend(["-c:s", "webvtt", "-f", "webvtt"].map(OsString::from));
+        args.push(output_path.clone().into_os_string());
+        if let Err(e) = self.run(&args).await {
+            let _ = tokio::fs::remove_file(&output_path).await;
+            return Err(e);
+        }
+        Ok(file_name.to_string())
+    }
+
+    /// Mux encoded streams with the HLS muxer and check that it wrote the
+    /// expected number of segments
+    async fn run_hls(
+        &self,
+        mut args: Vec<OsString>,
+        output_dir: &Path,
+        name: &str,
+        format: SegmentFormat,
+        hls_time: f64,
+        segments: usize,
+    ) -> Result<Vec<String>> {
+        tokio::fs::create_dir_all(output_dir).await?;
+        let written = written_segments(output_dir, name, format).await?;
+        if is_complete(&written, name, format, segments) {
+            debug!("Segments of {} already written", name);
+            return Ok(written);
+        }
+
+        args.extend(
+            ["-f", "hls", "-hls_playlist_type", "vod", "-hls_list_size", "0", "-start_number", "0"].map(OsString::from),
+        );
+        args.extend(["-hls_time".to_string(), format!("{:.3}", hls_time)].map(OsString::from));
+        match format {
+            SegmentFormat::Fmp4 => {
+                args.extend(["-hls_segment_type", "fmp4", "-hls_fmp4_init_filename"].map(OsString::from));
+                args.push(init_segment_name(name).into());
+            }
+            SegmentFormat::MpegTs => args.extend(["-hls_segment_type", "mpegts"].map(OsString::from)),
+        }
+        args.push("-hls_segment_filename".into());
+        args.push(output_dir.join(format!("{}_%04d.{}", name, format.segment_extension())).into_os_string());
+        // ffmpeg's own playlist is replaced by the generated ones
+        let playlist = output_dir.join(format!("{}.segmenter.m3u8", name));
+        args.push(playlist.clone().into_os_string());
+
+        self.run(&args).await?;
+        let _ = tokio::fs::remove_file(&playlist).await;
+
+        let written = written_segments(output_dir, name, format).await?;
+        if !is_complete(&written, name, format, segments) {
+            let media_segments = written.len() - usize::from(written.first() == Some(&init_segment_name(name)));
+            return Err(RustFlixError::media_processing(format!(
+                "ffmpeg wrote {} segments of {}, expected {}",
+                media_segments, name, segments
+            )));
+        }
+        debug!("Wrote {} segments of {}", segments, name);
+        Ok(written)
+    }
+
+    async fn run(&self, args: &[OsString]) -> Result<()> {
+        let output = Command::new(&self.ffmpeg_path)
+            .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| {
+                RustFlixError::media_processing(format!("Failed to start {}: {}", self.ffmpeg_path.display(), e))
+            })?;
+        if output.status.success() {
+            return Ok(());
+        }
+        let stderr = &output.stderr[output.stderr.len().saturating_sub(MAX_ERROR_OUTPUT)..];
+        let stderr = String::from_utf8_lossy(stderr);
+        Err(RustFlixError::media_processing(format!(
+            "ffmpeg exited with {}: {}",
+            output.status,
+            stderr.trim()
+        )))
+    }
+}
+
+fn input_args(media_path: &Path) -> Vec<OsString> {
+    let mut args = ["-hide_banner", "-nostdin", "-nostats", "-loglevel", "error", "-y", "-i"]
+        .map(OsString::from)
+        .to_vec();
+    args.push(media_path.as_os_str().to_owned());
+    args
+}
+
+/// Encoder options placing keyframes at the starts of segments only
+///
+/// Forced keyframes are placed a little early, on the frame at the start.
+/// The GOP is longer than any segment and scene cuts are off, so the
+/// encoder adds no keyframes of its own.
+fn keyframe_args(segments: &[MediaSegment], frame_rate: Option<f64>) -> Vec<String> {
+    let mut args = Vec::new();
+    let cuts = segments
+        .iter()
+        .skip(1)
+        .map(|segment| format!("{:.3}", (segment.start - CUT_TOLERANCE).max(0.0)))
+        .collect::<Vec<_>>();
+    if !cuts.is_empty() {
+        args.extend(["-force_key_frames".to_string(), cuts.join(",")]);
+    }
+    let longest = segments.iter().map(|segment| segment.duration).fold(0.0, f64::max);
+    let gop = (longest * frame_rate.unwrap_or(DEFAULT_FRAME_RATE) * 2.0).ceil().max(1.0) as u64;
+    args.extend(["-g".to_string(), gop.to_string(), "-sc_threshold".to_string(), "0".to_string()]);
+    args
+}
+
+/// Check whether written files are the init segment, if the format has
+/// one, and a number of media segments
+fn is_complete(written: &[String], name: &str, format: SegmentFormat, segments: usize) -> bool {
+    if format.has_init_segment() {
+        written.first() == Some(&init_segment_name(name)) && written.len() == segments + 1
+    } else {
+        written.len() == segments
+    }
+}
+
+/// Names of the segments of a rendition in a directory, the init segment
+/// first and the media segments in order
+async fn written_segments(output_dir: &Path, name: &str, format: SegmentFormat) -> Result<Vec<String>> {
+    let init = init_segment_name(name);
+    let prefix = format!("{}_", name);
+    let extension = format!(".{}", format.segment_extension());
+
+    let mut has_init = false;
+    let mut segments = Vec::new();
+    let mut entries = tokio::fs::read_dir(output_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let Ok(file_name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if format.has_init_segment() && file_name == init {
+            has_init = true;
+            continue;
+        }
+        let number = file_name.strip_prefix(&prefix).and_then(|rest| rest.strip_suffix(&extension));
+        if let Some(index) = number.filter(|n| !n.is_empty()).and_then(|n| n.parse::<u32>().ok()) {
+            segments.push((index, file_name));
+        }
+    }
+    segments.sort();
+
+    let mut names = Vec::new();
+    if has_init {
+        names.push(init);
+    }
+    names.extend(segments.into_iter().map(|(_, file_name)| file_name));
+    Ok(names)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::renditions::{audio_renditions, renditions};
+    use crate::timeline::probe_timeline;
+    use crate::timeline::tests::sample_dir;
+    use crate::transcoder::tests::fake_ffmpeg;
+
+    /// Fake ffmpeg writing one segment per forced keyframe plus one, or
+    /// enough `-hls_time` segments for the 20 second sample file, and
+    /// logging its arguments
+    #[cfg(unix)]
+    pub(crate) const FAKE_SEGMENTER: &str = "all=\"$*\"\n\
+        while [ $# -gt 0 ]; do\n\
+          case \"$1\" in\n\
+            -hls_segment_filename) pattern=\"$2\";;\n\
+            -hls_fmp4_init_filename) init=\"$2\";;\n\
+            -hls_time) time=${2%%.*}; timed=$(((20 + time - 1) / time));;\n\
+            -force_key_frames) forced=$(($(printf '%s' \"$2\" | tr -cd , | wc -c) + 2));;\n\
+          esac\n\
+          last=\"$1\"\n\
+          shift\n\
+        done\n\
+        count=${forced:-$timed}\n\
+        dir=$(dirname \"$last\")\n\
+        echo \"$all\" >> \"$dir/ffmpeg.log\"\n\
+        if [ -n \"$pattern\" ]; then\n\
+          i=0\n\
+          while [ $i -lt $count ]; do touch \"$(printf \"$pattern\" $i)\"; i=$((i + 1)); done\n\
+        fi\n\
+        [ -n \"$init\" ] && touch \"$dir/$init\"\n\
+        touch \"$last\"";
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_segment_renditions() {
+        let (temp_dir, media_path) = sample_dir();
+        let ffmpeg = fake_ffmpeg(temp_dir.path(), FAKE_SEGMENTER);
+        let segmenter = Segmenter::new(6.0).with_ffmpeg_path(ffmpeg);
+        let output_dir = temp_dir.path().join("hls");
+        let timeline = probe_timeline(&media_path).await.unwrap();
+        let mut profiles = StreamingConfig::default().quality_profiles;
+
+        let video = renditions(&profiles, timeline.video.as_ref(), true).unwrap();
+        let written = segmenter
+            .segment_rendition(&media_path, &output_dir, &timeline, &video[1], &profiles[1])
+            .await
+            .unwrap();
+        assert_eq!(written, ["720p_init.mp4", "720p_0000.m4s", "720p_0001.m4s", "720p_0002.m4s", "720p_0003.m4s"]);
+        assert!(output_dir.join("720p_0003.m4s").exists());
+        assert!(!output_dir.join("720p.segmenter.m3u8").exists());
+        let log = std::fs::read_to_string(output_dir.join("ffmpeg.log")).unwrap();
+        assert!(log.contains("-map 0:v:0 -c:v libx264"));
+        assert!(log.contains("-an -force_key_frames 5.999,11.999,17.999 -g 288 -sc_threshold 0"));
+        assert!(log.contains("-hls_time 5.999 -hls_segment_type fmp4 -hls_fmp4_init_filename 720p_init.mp4"));
+
+        // Segmented renditions are not encoded again
+        let runs = log.lines().count();
+        let again = segmenter
+            .segment_rendition(&media_path, &output_dir, &timeline, &video[1], &profiles[1])
+            .await
+            .unwrap();
+        assert_eq!(again.len(), 5);
+        assert_eq!(std::fs::read_to_string(output_dir.join("ffmpeg.log")).unwrap().lines().count(), runs);
+
+        let audio = audio_renditions(&profiles, &timeline.audio).unwrap();
+        let written = segmenter
+            .segment_audio(&media_path, &output_dir, &timeline, &audio[1], &profiles[0])
+            .await
+            .unwrap();
+        assert_eq!(written[0], "audio_ger_init.mp4");
+        assert_eq!(written.len(), 5);
+        let log = std::fs::read_to_string(output_dir.join("ffmpeg.log")).unwrap();
+        assert!(log.contains("-map 0:a:2 -vn -c:a aac -ac 2 -ar 48000 -b:a 128000"));
+
+        // Transport stream renditions carry their audio
+        profiles.iter_mut().for_each(|profile| profile.container = "ts".to_string());
+        let muxed = renditions(&profiles, timeline.video.as_ref(), true).unwrap();
+        let written = segmenter
+            .segment_rendition(&media_path, &output_dir, &timeline, &muxed[0], &profiles[0])
+            .await
+            .unwrap();
+        assert_eq!(written.first().map(String::as_str), Some("1080p_0000.ts"));
+        assert_eq!(written.len(), 4);
+        let log = std::fs::read_to_string(output_dir.join("ffmpeg.log")).unwrap();
+        assert!(log.lines().last().unwrap().contains("-map 0:v:0 -map 0:a:0 -c:v libx264"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_extract_subtitle() {
+        let (temp_dir, media_path) = sample_dir();
+        let ffmpeg = fake_ffmpeg(
+            temp_dir.path(),
+            "for output; do :; done\necho \"$*\" >> \"$output.args\"\nprintf 'WEBVTT\\n' > \"$output\"",
+        );
+        let segmenter = Segmenter::new(6.0).with_ffmpeg_path(ffmpeg);
+        let output_dir = temp_dir.path().join("dash");
+
+        let name = segmenter.extract_subtitle(&media_path, &output_dir, 0, "subtitle_5.vtt").await.unwrap();
+        assert_eq!(name, "subtitle_5.vtt");
+        assert_eq!(std::fs::read_to_string(output_dir.join(&name)).unwrap(), "WEBVTT\n");
+        let args = std::fs::read_to_string(output_dir.join("subtitle_5.vtt.args")).unwrap();
+        assert!(args.contains("-map 0:s:0 -c:s webvtt -f webvtt"));
+
+        segmenter.extract_subtitle(&media_path, &output_dir, 0, "subtitle_5.vtt").await.unwrap();
+        assert_eq!(std::fs::read_to_string(output_dir.join("subtitle_5.vtt.args")).unwrap().lines().count(), 1);
+
+        let failing = Segmenter::new(6.0).with_ffmpeg_path(fake_ffmpeg(temp_dir.path(), "exit 1"));
+        assert!(failing.extract_subtitle(&media_path, &output_dir, 1, "subtitle_6.vtt").await.is_err());
+        assert!(!output_dir.join("subtitle_6.vtt").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_segment_count_mismatch() {
+        let (temp_dir, media_path) = sample_dir();
+        let script = "for output; do :; done\ntouch \"$(dirname \"$output\")/720p_0000.ts\"";
+        let ffmpeg = fake_ffmpeg(temp_dir.path(), script);
+        let segmenter = Segmenter::new(6.0).with_ffmpeg_path(ffmpeg);
+        let timeline = probe_timeline(&media_path).await.unwrap();
+        let mut profiles = StreamingConfig::default().quality_profiles;
+        profiles.iter_mut().for_each(|profile| profile.container = "ts".to_string());
+        let rendition = &renditions(&profiles, timeline.video.as_ref(), true).unwrap()[1];
+
+        let result = segmenter
+            .segment_rendition(&media_path, temp_dir.path(), &timeline, rendition, &profiles[1])
+            .await;
+        assert!(result.unwrap_err().to_string().contains("wrote 1 segments of 720p, expected 4"));
+
+        let ffmpeg = fake_ffmpeg(temp_dir.path(), "echo 'No such filter' >&2\nexit 1");
+        let failing = Segmenter::new(6.0).with_ffmpeg_path(ffmpeg);
+        let result = failing
+            .segment_rendition(&media_path, temp_dir.path(), &timeline, rendition, &profiles[1])
+            .await;
+        assert!(result.unwrap_err().to_string().contains("No such filter"));
+    }
+}
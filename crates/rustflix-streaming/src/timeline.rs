@@ -0,0 +1,262 @@
+//! Probed timing of media files and their split into segments
+//!
+//! HLS playlists and DASH manifests of a file are cut at the same points,
+//! so both can reference the same fMP4 segments.
+
+use rustflix_core::media::{AudioCodec, SubtitleTrack, VideoCodec};
+use rustflix_core::{MediaFormat, Result, RustFlixError};
+use rustflix_media_library::probe;
+use std::path::Path;
+use tracing::warn;
+
+/// Keyframes this close before a cut point still end the segment
+pub(crate) const CUT_TOLERANCE: f64 = 0.001;
+
+/// Timing and streams of a media file, as probed
+#[derive(Debug, Clone, Default)]
+pub struct MediaTimeline {
+    /// Seconds
+    pub duration: f64,
+    /// Presentation times of the video keyframes in seconds, empty when the
+    /// container has no keyframe index
+    pub keyframes: Vec<f64>,
+    pub video: Option<VideoCodec>,
+    pub audio: Vec<AudioCodec>,
+    pub subtitles: Vec<SubtitleTrack>,
+}
+
+/// One media segment
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaSegment {
+    pub index: u32,
+    /// Seconds from the start of the media
+    pub start: f64,
+    pub duration: f64,
+}
+
+impl MediaTimeline {
+    /// Split the timeline into segments of a target duration
+    ///
+    /// A segment ends at the first keyframe at or after the next multiple
+    /// of the target duration, so segments run long when keyframes are far
+    /// apart. Without keyframes the timeline is split evenly.
+    pub fn segments(&self, target_duration: f64) -> Vec<MediaSegment> {
+        let duration = self.duration;
+        if self.keyframes.is_empty() {
+            return even_segments(duration, target_duration);
+        }
+        if !(duration > 0.0 && target_duration > 0.0) {
+            return Vec::new();
+        }
+
+        let mut starts = vec![0.0];
+        let mut boundary = target_duration;
+        for &keyframe in &self.keyframes {
+            if keyframe >= duration - CUT_TOLERANCE {
+                break;
+            }
+            if keyframe >= boundary - CUT_TOLERANCE {
+                starts.push(keyframe);
+                while boundary <= keyframe + CUT_TOLERANCE {
+                    boundary += target_duration;
+                }
+            }
+        }
+        split(&starts, duration)
+    }
+
+    /// Split the timeline into the segments of its audio renditions
+    ///
+    /// Every audio frame can start a segment, so audio is cut evenly at the
+    /// multiples of the target duration rather than at video keyframes.
+    pub fn audio_segments(&self, target_duration: f64) -> Vec<MediaSegment> {
+        even_segments(self.duration, target_duration)
+    }
+}
+
+/// Split a duration evenly into segments of a target duration
+pub fn even_segments(duration: f64, target_duration: f64) -> Vec<MediaSegment> {
+    if !(duration > 0.0 && target_duration > 0.0) {
+        return Vec::new();
+    }
+    let mut starts = vec![0.0];
+    let mut cut = target_duration;
+    while cut < duration - CUT_TOLERANCE {
+        starts.push(cut);
+        cut += target_duration;
+    }
+    split(&starts, duration)
+}
+
+/// Segments starting at each start and ending at the next or at the end
+fn split(starts: &[f64], duration: f64) -> Vec<MediaSegment> {
+    let ends = starts.iter().skip(1).copied().chain(std::iter::once(duration));
+    starts
+        .iter()
+        .zip(ends)
+        .enumerate()
+        .map(|(index, (start, end))| MediaSegment {
+            index: index as u32,
+            start: *start,
+            duration: end - start,
+        })
+        .collect()
+}
+
+/// Probe the duration, keyframes and streams of a media file
+///
+/// Files whose keyframes cannot be read are split evenly.
+pub async fn probe_timeline(media_path: &Path) -> Result<MediaTimeline> {
+    let path = media_path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        let format = MediaFormat::from_extension(extension);
+        let info = probe::probe_file(&path, format)?;
+        let duration = info
+            .duration
+            .filter(|duration| *duration > 0.0)
+            .ok_or_else(|| RustFlixError::media_processing(format!("Unknown duration of {}", path.display())))?;
+
+        let keyframes = if probe::supports_keyframes(format) {
+            probe::read_keyframes(&path, format).unwrap_or_else(|e| {
+                warn!("Failed to read keyframes of {}: {}", path.display(), e);
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+
+        Ok(MediaTimeline {
+            duration,
+            keyframes,
+            video: info.streams.video.into_iter().next(),
+            audio: info.streams.audio,
+            subtitles: info.streams.subtitles,
+        })
+    })
+    .await
+    .map_err(|e| RustFlixError::internal(format!("Probe task failed: {}", e)))?
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+        let bytes = id.to_be_bytes();
+        let skip = bytes.iter().position(|b| *b != 0).unwrap_or(3);
+        let mut out = bytes[skip..].to_vec();
+        out.push(0x01);
+        out.extend_from_slice(&(payload.len() as u64).to_be_bytes()[1..]);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn uint_element(id: u32, value: u64) -> Vec<u8> {
+        element(id, &value.to_be_bytes())
+    }
+
+    fn track(number: u64, track_type: u64, codec_id: &str, extra: Vec<u8>) -> Vec<u8> {
+        let mut entry = uint_element(0xD7, number);
+        entry.extend(uint_element(0x83, track_type));
+        entry.extend(element(0x86, codec_id.as_bytes()));
+        entry.extend(extra);
+        element(0xAE, &entry)
+    }
+
+    fn audio_track(number: u64, channels: u64, language: &str) -> Vec<u8> {
+        let mut audio = uint_element(0x9F, channels);
+        audio.extend(element(0xB5, &48000.0f64.to_be_bytes()));
+        let mut extra = element(0xE1, &audio);
+        extra.extend(element(0x22_B59C, language.as_bytes()));
+        track(number, 2, "A_AC3", extra)
+    }
+
+    /// A 20 second 1080p Matroska file with a keyframe every 2 seconds,
+    /// English 5.1 and stereo audio, German stereo audio, and French text
+    /// and image subtitles
+    pub(crate) fn sample_mkv() -> Vec<u8> {
+        let mut info = uint_element(0x2A_D7B1, 1_000_000);
+        info.extend(element(0x4489, &20_000.0f64.to_be_bytes()));
+
+        let mut video = uint_element(0xB0, 1920);
+        video.extend(uint_element(0xBA, 1080));
+        let mut video_extra = element(0xE0, &video);
+        video_extra.extend(uint_element(0x23_E383, 41_708_333));
+
+        let mut tracks = track(1, 1, "V_MPEG4/ISO/AVC", video_extra);
+        tracks.extend(audio_track(2, 6, "eng"));
+        tracks.extend(audio_track(3, 2, "eng"));
+        tracks.extend(audio_track(4, 2, "ger"));
+        tracks.extend(track(5, 17, "S_TEXT/UTF8", element(0x22_B59C, b"fre")));
+        tracks.extend(track(6, 17, "S_HDMV/PGS", element(0x22_B59C, b"fre")));
+
+        let mut cues = Vec::new();
+        for time in (0..20_000).step_by(2000) {
+            let mut point = uint_element(0xB3, time);
+            point.extend(element(0xB7, &uint_element(0xF7, 1)));
+            cues.extend(element(0xBB, &point));
+        }
+
+        let mut segment = element(0x1549_A966, &info);
+        segment.extend(element(0x1654_AE6B, &tracks));
+        segment.extend(element(0x1C53_BB6B, &cues));
+        let mut file = element(0x1A45_DFA3, &element(0x4282, b"matroska"));
+        file.extend(element(0x1853_8067, &segment));
+        file
+    }
+
+    /// Write the sample file to a temporary directory
+    pub(crate) fn sample_dir() -> (TempDir, std::path::PathBuf) {
+        let temp_dir = TempDir::new().unwrap();
+        let media_path = temp_dir.path().join("test.mkv");
+        std::fs::write(&media_path, sample_mkv()).unwrap();
+        (temp_dir, media_path)
+    }
+
+    fn timeline(duration: f64, keyframes: &[f64]) -> MediaTimeline {
+        MediaTimeline {
+            duration,
+            keyframes: keyframes.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_timeline() {
+        let (_temp_dir, media_path) = sample_dir();
+        let timeline = probe_timeline(&media_path).await.unwrap();
+
+        assert_eq!(timeline.duration, 20.0);
+        assert_eq!(timeline.keyframes.len(), 10);
+        assert_eq!(timeline.video.unwrap().frame_rate, Some(23.976));
+        assert_eq!(timeline.audio.len(), 3);
+        assert_eq!(timeline.subtitles.len(), 2);
+        assert!(probe_timeline(&media_path.with_extension("mp4")).await.is_err());
+    }
+
+    #[test]
+    fn test_segments_follow_keyframes() {
+        let keyframes = [0.0, 4.0, 8.2, 11.9996, 13.0, 30.5, 31.0];
+        let segments = timeline(31.25, &keyframes).segments(6.0);
+
+        let starts = segments.iter().map(|s| s.start).collect::<Vec<_>>();
+        assert_eq!(starts, [0.0, 8.2, 11.9996, 30.5]);
+        assert_eq!(segments[3].index, 3);
+        assert_eq!(segments[3].duration, 0.75);
+    }
+
+    #[test]
+    fn test_segments_without_keyframes() {
+        let durations = timeline(15.0, &[]).segments(6.0).iter().map(|s| s.duration).collect::<Vec<_>>();
+        assert_eq!(durations, [6.0, 6.0, 3.0]);
+
+        assert!(timeline(0.0, &[]).segments(6.0).is_empty());
+        assert_eq!(timeline(12.0, &[]).segments(6.0).len(), 2);
+
+        // Audio ignores the keyframes
+        let audio = timeline(15.0, &[0.0, 8.0]).audio_segments(6.0);
+        assert_eq!(audio.iter().map(|s| s.start).collect::<Vec<_>>(), [0.0, 6.0, 12.0]);
+    }
+}
@@ -1,52 +1,639 @@
 //! Media transcoding functionality
+//!
+//! Transcodes run an ffmpeg process built from a transcoding profile. Its
+//! `-progress` output updates the job and is published as core events;
+//! cancelled and timed out transcodes kill the process.
 
-use rustflix_core::{Result, RustFlixError};
-use std::path::Path;
-use tracing::{info, warn, debug};
+use crate::renditions::AUDIO_BITRATE;
+use chrono::Utc;
+use rustflix_core::config::StreamingConfig;
+use rustflix_core::streaming::{TranscodingJob, TranscodingStatus};
+use rustflix_core::{Event, EventType, MediaFormat, Result, RustFlixError};
+use rustflix_media_library::probe;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+pub use rustflix_core::streaming::TranscodingProfile;
+
+/// Source of published events
+const EVENT_SOURCE: &str = "transcoder";
+
+/// Capacity of the event channel before slow subscribers lag
+const EVENT_CAPACITY: usize = 256;
+
+/// Bytes of ffmpeg's error output kept for the failure message
+const MAX_ERROR_OUTPUT: usize = 4096;
 
 /// Media transcoder for converting between formats
 #[derive(Debug, Clone)]
 pub struct Transcoder {
-    // FFmpeg integration will be added here
+    ffmpeg_path: PathBuf,
+    threads: Option<usize>,
+    timeout: Option<Duration>,
+    events: broadcast::Sender<Event>,
 }
 
-/// Transcoding profile configuration
-#[derive(Debug, Clone)]
-pub struct TranscodingProfile {
-    pub name: String,
-    pub video_codec: String,
-    pub audio_codec: String,
-    pub container: String,
-    pub max_bitrate: u64,
-    pub max_width: Option<u32>,
-    pub max_height: Option<u32>,
+/// Progress of a transcode, from one block of ffmpeg `-progress` output
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressUpdate {
+    /// Seconds of output written
+    pub out_time: Option<f64>,
+    /// Multiple of real time
+    pub speed: Option<f64>,
+    /// Set on the last block
+    pub finished: bool,
+}
+
+/// Parser of ffmpeg `-progress` output, which comes in `key=value` lines
+/// ending each block with a `progress` line
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+    out_time: Option<f64>,
+    speed: Option<f64>,
+}
+
+impl ProgressParser {
+    /// Feed one line, returning the update of a block once it is complete
+    pub fn feed(&mut self, line: &str) -> Option<ProgressUpdate> {
+        let (key, value) = line.trim().split_once('=')?;
+        let value = value.trim();
+        match key.trim() {
+            // `out_time_ms` is in microseconds too
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(micros) = value.parse::<i64>() {
+                    self.out_time = Some(micros.max(0) as f64 / 1_000_000.0);
+                }
+            }
+            "speed" => self.speed = value.trim_end_matches('x').parse().ok().filter(|speed: &f64| *speed > 0.0),
+            "progress" => {
+                return Some(ProgressUpdate {
+                    out_time: self.out_time,
+                    speed: self.speed,
+                    finished: value == "end",
+                })
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// How a transcode process ended
+enum Outcome {
+    Exited(std::io::Result<ExitStatus>),
+    Cancelled,
+    TimedOut,
 }
 
 impl Transcoder {
-    /// Create a new transcoder
+    /// Create a new transcoder running `ffmpeg` from the `PATH`
     pub fn new() -> Result<Self> {
-        Ok(Self {})
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Ok(Self {
+            ffmpeg_path: PathBuf::from("ffmpeg"),
+            threads: None,
+            timeout: None,
+            events,
+        })
+    }
+
+    /// Create a transcoder with the thread count of a streaming configuration
+    pub fn from_config(config: &StreamingConfig) -> Result<Self> {
+        Ok(Self {
+            threads: config.transcoding_threads,
+            ..Self::new()?
+        })
     }
 
-    /// Start transcoding a media file
-    pub async fn transcode(&self, input_path: &Path, output_path: &Path, profile: &TranscodingProfile) -> Result<()> {
+    /// Run another ffmpeg binary
+    pub fn with_ffmpeg_path(mut self, ffmpeg_path: impl Into<PathBuf>) -> Self {
+        self.ffmpeg_path = ffmpeg_path.into();
+        self
+    }
+
+    /// Kill transcodes that run longer than a timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Subscribe to transcoding events
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Transcode a media file with the profile of a job
+    ///
+    /// The job's status and progress follow the process. Failed, cancelled
+    /// and timed out transcodes return an error and remove the partial
+    /// output; all of them publish `TranscodingFailed`.
+    pub async fn transcode(
+        &self,
+        job: &mut TranscodingJob,
+        input_path: &Path,
+        output_path: &Path,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
         info!("Starting transcoding: {} -> {}", input_path.display(), output_path.display());
-        
-        // Placeholder implementation - FFmpeg integration needed
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        
-        debug!("Transcoding completed");
-        Ok(())
+
+        let args = ffmpeg_args(input_path, output_path, &job.profile, self.threads)?;
+        let duration = probe_duration(input_path).await;
+        job.status = TranscodingStatus::Starting;
+        job.updated_at = Utc::now();
+
+        let spawned = Command::new(&self.ffmpeg_path)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                let error = format!("Failed to start {}: {}", self.ffmpeg_path.display(), e);
+                return Err(self.fail(job, error));
+            }
+        };
+        job.status = TranscodingStatus::Running;
+        job.updated_at = Utc::now();
+        self.publish(EventType::TranscodingStarted { job_id: job.id, media_id: job.media_id });
+
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        // Drained to the end so ffmpeg never blocks on a full pipe
+        let error_output = tokio::spawn(async move {
+            let mut output = Vec::new();
+            if let Some(mut stderr) = stderr {
+                let mut chunk = [0u8; 1024];
+                while let Ok(read @ 1..) = stderr.read(&mut chunk).await {
+                    output.extend_from_slice(&chunk[..read]);
+                    let excess = output.len().saturating_sub(MAX_ERROR_OUTPUT);
+                    output.drain(..excess);
+                }
+            }
+            String::from_utf8_lossy(&output).trim().to_string()
+        });
+
+        let outcome = {
+            let run = async {
+                if let Some(stdout) = stdout {
+                    let mut lines = BufReader::new(stdout).lines();
+                    let mut parser = ProgressParser::default();
+                    while let Some(line) = lines.next_line().await? {
+                        if let Some(update) = parser.feed(&line) {
+                            self.apply_progress(job, &update, duration);
+                        }
+                    }
+                }
+                child.wait().await
+            };
+            let timeout = async {
+                match self.timeout {
+                    Some(timeout) => tokio::time::sleep(timeout).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                status = run => Outcome::Exited(status),
+                _ = cancel.cancelled() => Outcome::Cancelled,
+                _ = timeout => Outcome::TimedOut,
+            }
+        };
+
+        let error = match outcome {
+            Outcome::Exited(Ok(status)) if status.success() => {
+                job.complete();
+                self.publish(EventType::TranscodingCompleted { job_id: job.id });
+                debug!("Transcoding completed");
+                return Ok(());
+            }
+            Outcome::Exited(Ok(status)) => {
+                let output = error_output.await.unwrap_or_default();
+                if output.is_empty() {
+                    format!("ffmpeg exited with {}", status)
+                } else {
+                    format!("ffmpeg exited with {}: {}", status, output)
+                }
+            }
+            Outcome::Exited(Err(e)) => format!("Failed to run ffmpeg: {}", e),
+            Outcome::Cancelled => {
+                self.stop(&mut child, output_path, &error_output).await;
+                job.status = TranscodingStatus::Cancelled;
+                job.updated_at = Utc::now();
+                let error = "Transcoding cancelled".to_string();
+                self.publish(EventType::TranscodingFailed { job_id: job.id, error: error.clone() });
+                return Err(RustFlixError::media_processing(error));
+            }
+            Outcome::TimedOut => {
+                self.stop(&mut child, output_path, &error_output).await;
+                format!("Transcoding timed out after {:?}", self.timeout.unwrap_or_default())
+            }
+        };
+
+        let _ = tokio::fs::remove_file(output_path).await;
+        Err(self.fail(job, error))
+    }
+
+    fn apply_progress(&self, job: &mut TranscodingJob, update: &ProgressUpdate, duration: Option<f64>) {
+        let progress = match (update.out_time, duration) {
+            _ if update.finished => 100.0,
+            (Some(out_time), Some(duration)) => (out_time / duration * 100.0) as f32,
+            _ => job.progress,
+        };
+        job.update_progress(progress, update.out_time);
+        job.estimated_completion = match (update.out_time, duration, update.speed) {
+            (Some(out_time), Some(duration), Some(speed)) => {
+                let remaining = ((duration - out_time).max(0.0) / speed * 1000.0) as i64;
+                Some(Utc::now() + chrono::Duration::milliseconds(remaining))
+            }
+            _ => None,
+        };
+        self.publish(EventType::TranscodingProgress { job_id: job.id, progress: job.progress });
+    }
+
+    /// Kill a transcode process and remove its partial output
+    async fn stop(&self, child: &mut tokio::process::Child, output_path: &Path, error_output: &tokio::task::JoinHandle<String>) {
+        if let Err(e) = child.kill().await {
+            warn!("Failed to kill ffmpeg: {}", e);
+        }
+        error_output.abort();
+        let _ = tokio::fs::remove_file(output_path).await;
+    }
+
+    fn fail(&self, job: &mut TranscodingJob, error: String) -> RustFlixError {
+        warn!("Transcoding job {} failed: {}", job.id, error);
+        job.fail(error.clone());
+        self.publish(EventType::TranscodingFailed { job_id: job.id, error: error.clone() });
+        RustFlixError::media_processing(error)
+    }
+
+    fn publish(&self, event_type: EventType) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(Event::new(event_type, EVENT_SOURCE.to_string()));
+    }
+}
+
+/// Arguments of an ffmpeg transcode of a file with a profile
+///
+/// Paths are passed as they are, so file names that are not UTF-8 reach
+/// ffmpeg unchanged.
+pub fn ffmpeg_args(
+    input_path: &Path,
+    output_path: &Path,
+    profile: &TranscodingProfile,
+    threads: Option<usize>,
+) -> Result<Vec<OsString>> {
+    let mut args = ["-hide_banner", "-nostdin", "-nostats", "-loglevel", "error", "-y", "-i"]
+        .map(OsString::from)
+        .to_vec();
+    args.push(input_path.as_os_str().to_owned());
+    args.extend(encoding_args(profile, threads)?.into_iter().map(OsString::from));
+
+    let (muxer, flags) = muxer(&profile.container)?;
+    args.extend(["-f", muxer].map(OsString::from));
+    if let Some(flags) = flags {
+        args.extend(["-movflags", flags].map(OsString::from));
+    }
+    args.extend(["-progress", "pipe:1"].map(OsString::from));
+    args.push(output_path.as_os_str().to_owned());
+    Ok(args)
+}
+
+/// Codec options of a transcode with a profile
+///
+/// Video is scaled down to fit the profile, never up, to even dimensions;
+/// profiles without a video codec drop the video, and copied video is left
+/// as it is.
+pub fn encoding_args(profile: &TranscodingProfile, threads: Option<usize>) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    if let Some(threads) = threads {
+        args.extend(["-threads".to_string(), threads.to_string()]);
+    }
+    match &profile.video_codec {
+        Some(codec) => {
+            let video_encoder = encoder(codec, true)?;
+            args.extend(["-c:v".to_string(), video_encoder.to_string()]);
+            // Copied video keeps its size, frame rate and bitrate
+            if video_encoder != "copy" {
+                if profile.max_width.is_some() || profile.max_height.is_some() {
+                    let bound = |max: Option<u32>, size: &str| match max {
+                        Some(max) => format!("'min({},{})'", max, size),
+                        None => size.to_string(),
+                    };
+                    args.extend([
+                        "-vf".to_string(),
+                        format!(
+                            "scale=w={}:h={}:force_original_aspect_ratio=decrease:force_divisible_by=2",
+                            bound(profile.max_width, "iw"),
+                            bound(profile.max_height, "ih")
+                        ),
+                    ]);
+                }
+                if let Some(frame_rate) = profile.max_frame_rate {
+                    args.extend(["-fpsmax".to_string(), frame_rate.to_string()]);
+                }
+                if let Some(bitrate) = profile.max_bitrate {
+                    args.extend([
+                        "-b:v".to_string(),
+                        bitrate.to_string(),
+                        "-maxrate:v".to_string(),
+                        bitrate.to_string(),
+                        "-bufsize:v".to_string(),
+                        (bitrate * 2).to_string(),
+                    ]);
+                }
+            }
+        }
+        None => args.push("-vn".to_string()),
+    }
+
+    let audio_encoder = encoder(&profile.audio_codec, false)?;
+    args.extend(["-c:a".to_string(), audio_encoder.to_string()]);
+    if let Some(channels) = profile.audio_channels {
+        args.extend(["-ac".to_string(), channels.to_string()]);
     }
+    if let Some(sample_rate) = profile.audio_sample_rate {
+        args.extend(["-ar".to_string(), sample_rate.to_string()]);
+    }
+    if !matches!(audio_encoder, "flac" | "copy") {
+        args.extend(["-b:a".to_string(), AUDIO_BITRATE.to_string()]);
+    }
+    Ok(args)
+}
+
+/// ffmpeg encoder of a codec name
+fn encoder(codec: &str, video: bool) -> Result<&'static str> {
+    let encoder = match (codec.to_ascii_lowercase().as_str(), video) {
+        ("copy", _) => "copy",
+        ("h264" | "avc", true) => "libx264",
+        ("hevc" | "h265", true) => "libx265",
+        ("av1", true) => "libsvtav1",
+        ("vp9", true) => "libvpx-vp9",
+        ("aac", false) => "aac",
+        ("mp3", false) => "libmp3lame",
+        ("opus", false) => "libopus",
+        ("flac", false) => "flac",
+        ("ac3", false) => "ac3",
+        ("eac3", false) => "eac3",
+        _ => {
+            let kind = if video { "video" } else { "audio" };
+            return Err(RustFlixError::config(format!("Unsupported {} codec for transcoding: {}", kind, codec)));
+        }
+    };
+    Ok(encoder)
+}
+
+/// ffmpeg muxer of a container name, with its `-movflags`
+fn muxer(container: &str) -> Result<(&'static str, Option<&'static str>)> {
+    let muxer = match container.to_ascii_lowercase().as_str() {
+        "mp4" | "m4v" => ("mp4", Some("+faststart")),
+        "fmp4" | "cmaf" => ("mp4", Some("+frag_keyframe+empty_moov+default_base_moof")),
+        "ts" | "mpegts" => ("mpegts", None),
+        "mkv" | "matroska" => ("matroska", None),
+        "webm" => ("webm", None),
+        _ => return Err(RustFlixError::config(format!("Unsupported container for transcoding: {}", container))),
+    };
+    Ok(muxer)
+}
+
+/// Duration of a media file in seconds, if it can be probed
+async fn probe_duration(path: &Path) -> Option<f64> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        match probe::probe_file(&path, MediaFormat::from_extension(extension)) {
+            Ok(info) => info.duration.filter(|duration| *duration > 0.0),
+            Err(e) => {
+                debug!("Failed to probe duration of {}: {}", path.display(), e);
+                None
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::timeline::tests::sample_dir;
+    use rustflix_core::StreamId;
+    use uuid::Uuid;
+
+    fn profile() -> TranscodingProfile {
+        TranscodingProfile {
+            name: "720p".to_string(),
+            container: "mp4".to_string(),
+            video_codec: Some("h264".to_string()),
+            audio_codec: "aac".to_string(),
+            max_width: Some(1280),
+            max_height: Some(720),
+            max_bitrate: Some(4_000_000),
+            max_frame_rate: None,
+            audio_channels: Some(2),
+            audio_sample_rate: None,
+        }
+    }
+
+    fn job() -> TranscodingJob {
+        TranscodingJob::new(StreamId::new_v4(), Uuid::new_v4(), profile())
+    }
+
+    /// Write an executable fake ffmpeg running a shell script
+    #[cfg(unix)]
+    pub(crate) fn fake_ffmpeg(dir: &Path, script: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.join("ffmpeg");
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    /// Arguments of a transcode joined into one line
+    fn command_line(input_path: &Path, output_path: &Path, profile: &TranscodingProfile, threads: Option<usize>) -> String {
+        let args = ffmpeg_args(input_path, output_path, profile, threads).unwrap();
+        args.iter().map(|arg| arg.to_string_lossy()).collect::<Vec<_>>().join(" ")
+    }
+
+    fn events(receiver: &mut broadcast::Receiver<Event>) -> Vec<EventType> {
+        std::iter::from_fn(|| receiver.try_recv().ok()).map(|event| event.event_type).collect()
+    }
 
     #[tokio::test]
     async fn test_transcoder_creation() {
         let transcoder = Transcoder::new();
         assert!(transcoder.is_ok());
     }
+
+    #[test]
+    fn test_ffmpeg_args() {
+        let command = command_line(Path::new("in.mkv"), Path::new("out.mp4"), &profile(), Some(4));
+        assert!(command.starts_with("-hide_banner -nostdin -nostats -loglevel error -y -i in.mkv -threads 4 -c:v libx264"));
+        assert!(command.contains(
+            "-vf scale=w='min(1280,iw)':h='min(720,ih)':force_original_aspect_ratio=decrease:force_divisible_by=2"
+        ));
+        assert!(command.contains("-b:v 4000000 -maxrate:v 4000000 -bufsize:v 8000000"));
+        assert!(command.contains("-c:a aac -ac 2 -b:a 128000 -f mp4 -movflags +faststart"));
+        assert!(command.ends_with("-progress pipe:1 out.mp4"));
+
+        let audio_only = TranscodingProfile {
+            video_codec: None,
+            audio_codec: "flac".to_string(),
+            container: "mkv".to_string(),
+            ..profile()
+        };
+        let command = command_line(Path::new("in.mkv"), Path::new("out.mka"), &audio_only, None);
+        assert!(command.contains("-vn -c:a flac -ac 2 -f matroska"));
+        assert!(!command.contains("-b:a"));
+
+        let remux = TranscodingProfile {
+            video_codec: Some("copy".to_string()),
+            max_frame_rate: Some(30.0),
+            ..profile()
+        };
+        let command = command_line(Path::new("in.mkv"), Path::new("out.mp4"), &remux, None);
+        assert!(command.contains("-i in.mkv -c:v copy -c:a aac"));
+        for option in ["-vf", "-fpsmax", "-b:v", "-maxrate:v", "-bufsize:v"] {
+            assert!(!command.contains(option), "{} in {}", option, command);
+        }
+
+        let unsupported = TranscodingProfile {
+            video_codec: Some("mpeg7".to_string()),
+            ..profile()
+        };
+        assert!(ffmpeg_args(Path::new("in.mkv"), Path::new("out.mp4"), &unsupported, None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ffmpeg_args_keep_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let input_path = Path::new(OsStr::from_bytes(b"/media/caf\xe9.mkv"));
+        let output_path = Path::new(OsStr::from_bytes(b"/transcodes/caf\xe9.mp4"));
+        let args = ffmpeg_args(input_path, output_path, &profile(), None).unwrap();
+        assert_eq!(args[7], input_path.as_os_str());
+        assert_eq!(args.last().unwrap(), output_path.as_os_str());
+    }
+
+    #[test]
+    fn test_progress_parser() {
+        let mut parser = ProgressParser::default();
+        assert_eq!(parser.feed("frame=120"), None);
+        assert_eq!(parser.feed("out_time_us=5000000"), None);
+        assert_eq!(parser.feed("speed=2.5x"), None);
+        let update = parser.feed("progress=continue").unwrap();
+        assert_eq!(update, ProgressUpdate { out_time: Some(5.0), speed: Some(2.5), finished: false });
+
+        assert_eq!(parser.feed("out_time_us=N/A"), None);
+        assert_eq!(parser.feed("speed=N/A"), None);
+        let update = parser.feed("progress=end").unwrap();
+        assert_eq!(update, ProgressUpdate { out_time: Some(5.0), speed: None, finished: true });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_transcode_with_fake_ffmpeg() {
+        let (temp_dir, media_path) = sample_dir();
+        let ffmpeg = fake_ffmpeg(
+            temp_dir.path(),
+            "for output; do :; done\n\
+             echo \"$@\" > \"$output.args\"\n\
+             printf 'frame=1\\nout_time_us=5000000\\nspeed=2.0x\\nprogress=continue\\n'\n\
+             printf 'out_time_us=20000000\\nspeed=2.0x\\nprogress=end\\n'\n\
+             echo transcoded > \"$output\"",
+        );
+        let transcoder = Transcoder::new().unwrap().with_ffmpeg_path(ffmpeg);
+        let mut receiver = transcoder.subscribe();
+        let output = temp_dir.path().join("out.mp4");
+        let mut job = job();
+
+        transcoder.transcode(&mut job, &media_path, &output, &CancellationToken::new()).await.unwrap();
+        assert_eq!(job.status, TranscodingStatus::Completed);
+        assert_eq!(job.progress, 100.0);
+        assert_eq!(job.current_time, Some(20.0));
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "transcoded\n");
+        let args = std::fs::read_to_string(temp_dir.path().join("out.mp4.args")).unwrap();
+        assert!(args.contains("-c:v libx264") && args.contains("-progress pipe:1"));
+
+        let events = events(&mut receiver);
+        assert!(matches!(events[0], EventType::TranscodingStarted { job_id, .. } if job_id == job.id));
+        assert!(matches!(events[1], EventType::TranscodingProgress { progress, .. } if progress == 25.0));
+        assert!(matches!(events[2], EventType::TranscodingProgress { progress, .. } if progress == 100.0));
+        assert!(matches!(events[3], EventType::TranscodingCompleted { job_id } if job_id == job.id));
+        assert_eq!(events.len(), 4);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_transcode_failure() {
+        let (temp_dir, media_path) = sample_dir();
+        let ffmpeg = fake_ffmpeg(temp_dir.path(), "echo 'Unknown encoder libx264' >&2\nexit 1");
+        let transcoder = Transcoder::new().unwrap().with_ffmpeg_path(ffmpeg);
+        let mut receiver = transcoder.subscribe();
+        let mut job = job();
+
+        let result = transcoder
+            .transcode(&mut job, &media_path, &temp_dir.path().join("out.mp4"), &CancellationToken::new())
+            .await;
+        assert!(result.is_err());
+        assert_eq!(job.status, TranscodingStatus::Failed);
+        assert!(job.error_message.unwrap().contains("Unknown encoder libx264"));
+        let events = events(&mut receiver);
+        assert!(matches!(&events[1], EventType::TranscodingFailed { error, .. } if error.contains("exit status: 1")));
+
+        let missing = Transcoder::new().unwrap().with_ffmpeg_path(temp_dir.path().join("missing"));
+        let mut job = self::job();
+        assert!(missing
+            .transcode(&mut job, &media_path, &temp_dir.path().join("out.mp4"), &CancellationToken::new())
+            .await
+            .is_err());
+        assert_eq!(job.status, TranscodingStatus::Failed);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_transcode_cancel_and_timeout() {
+        let (temp_dir, media_path) = sample_dir();
+        let ffmpeg = fake_ffmpeg(
+            temp_dir.path(),
+            "for output; do :; done\necho partial > \"$output\"\nprintf 'progress=continue\\n'\nexec sleep 30",
+        );
+        let output = temp_dir.path().join("out.mp4");
+
+        let transcoder = Transcoder::new().unwrap().with_ffmpeg_path(&ffmpeg);
+        let mut receiver = transcoder.subscribe();
+        let cancel = CancellationToken::new();
+        let mut job = job();
+        let started = std::time::Instant::now();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            canceller.cancel();
+        });
+        assert!(transcoder.transcode(&mut job, &media_path, &output, &cancel).await.is_err());
+        assert_eq!(job.status, TranscodingStatus::Cancelled);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(!output.exists());
+        let events = events(&mut receiver);
+        assert!(matches!(events.last(), Some(EventType::TranscodingFailed { error, .. }) if error == "Transcoding cancelled"));
+
+        let transcoder = transcoder.with_timeout(Duration::from_millis(200));
+        let mut job = self::job();
+        let result = transcoder.transcode(&mut job, &media_path, &output, &CancellationToken::new()).await;
+        assert!(result.is_err());
+        assert_eq!(job.status, TranscodingStatus::Failed);
+        assert!(job.error_message.unwrap().contains("timed out"));
+        assert!(!output.exists());
+    }
 }